@@ -49,9 +49,11 @@ public struct Cap<phantom T> has key, store {
     id: UID,
 }
 
-// An intent message, used for wrapping enclave messages.
+// An intent message, used for wrapping enclave messages. The intent scope
+// and app id domain separate signatures of different apps and endpoints.
 public struct IntentMessage<T: drop> has copy, drop {
     intent: u8,
+    app_id: vector<u8>,
     timestamp_ms: u64,
     payload: T,
 }
@@ -102,11 +104,12 @@ public fun register_enclave<T>(
 public fun verify_signature<T, P: drop>(
     enclave: &Enclave<T>,
     intent_scope: u8,
+    app_id: vector<u8>,
     timestamp_ms: u64,
     payload: P,
     signature: &vector<u8>,
 ): bool {
-    let intent_message = create_intent_message(intent_scope, timestamp_ms, payload);
+    let intent_message = create_intent_message(intent_scope, app_id, timestamp_ms, payload);
     let payload = bcs::to_bytes(&intent_message);
     return ed25519::ed25519_verify(signature, &enclave.pk, &payload)
 }
@@ -171,14 +174,25 @@ fun to_pcrs(document: &NitroAttestationDocument): Pcrs {
     Pcrs(*pcrs[0].value(), *pcrs[1].value(), *pcrs[2].value())
 }
 
-fun create_intent_message<P: drop>(intent: u8, timestamp_ms: u64, payload: P): IntentMessage<P> {
+fun create_intent_message<P: drop>(
+    intent: u8,
+    app_id: vector<u8>,
+    timestamp_ms: u64,
+    payload: P,
+): IntentMessage<P> {
     IntentMessage {
         intent,
+        app_id,
         timestamp_ms,
         payload,
     }
 }
 
+#[test_only]
+public fun set_pk_for_testing<T>(enclave: &mut Enclave<T>, pk: vector<u8>) {
+    enclave.pk = pk;
+}
+
 #[test_only]
 public fun destroy<T>(enclave: Enclave<T>) {
     let Enclave { id, .. } = enclave;
@@ -198,6 +212,7 @@ fun test_serde() {
     let timestamp = 1744038900000;
     let signing_payload = create_intent_message(
         scope,
+        b"weather",
         timestamp,
         SigningPayload {
             location: b"San Francisco".to_string(),
@@ -205,5 +220,5 @@ fun test_serde() {
         },
     );
     let bytes = bcs::to_bytes(&signing_payload);
-    assert!(bytes == x"0020b1d110960100000d53616e204672616e636973636f0d00000000000000", 0);
+    assert!(bytes == x"00077765617468657220b1d110960100000d53616e204672616e636973636f0d00000000000000", 0);
 }
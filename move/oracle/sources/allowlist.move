@@ -0,0 +1,64 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+/// Feeds an enclave deployment is authorized to serve. An enclave built with
+/// `feed_allowlist.object_id` set in coeus_config.yaml refuses to run any feed
+/// missing from that FeedAllowlist, so an enclave dedicated to one customer
+/// never signs results for another customer's feeds.
+module oracle::allowlist;
+
+use oracle::config::Config;
+
+#[error]
+const ENotAdmin: vector<u8> = b"Not admin";
+
+#[error]
+const EDuplicateFeed: vector<u8> = b"Feed already allowed";
+
+#[error]
+const EUnknownFeed: vector<u8> = b"Feed not allowed";
+
+public struct FeedAllowlist has key {
+    id: UID,
+    /// OracleFeed ids the enclave may run.
+    feeds: vector<ID>,
+}
+
+public fun create(config: &Config, ctx: &mut TxContext) {
+    assert!(config.admin() == ctx.sender(), ENotAdmin);
+    transfer::share_object(FeedAllowlist {
+        id: object::new(ctx),
+        feeds: vector[],
+    });
+}
+
+public fun add_feed(
+    allowlist: &mut FeedAllowlist,
+    config: &Config,
+    feed_id: ID,
+    ctx: &mut TxContext,
+) {
+    assert!(config.admin() == ctx.sender(), ENotAdmin);
+    assert!(!allowlist.feeds.contains(&feed_id), EDuplicateFeed);
+    allowlist.feeds.push_back(feed_id);
+}
+
+public fun remove_feed(
+    allowlist: &mut FeedAllowlist,
+    config: &Config,
+    feed_id: ID,
+    ctx: &mut TxContext,
+) {
+    assert!(config.admin() == ctx.sender(), ENotAdmin);
+    let (found, index) = allowlist.feeds.index_of(&feed_id);
+    assert!(found, EUnknownFeed);
+    allowlist.feeds.remove(index);
+}
+
+public fun feeds(allowlist: &FeedAllowlist): vector<ID> {
+    allowlist.feeds
+}
+
+public fun contains(allowlist: &FeedAllowlist, feed_id: ID): bool {
+    allowlist.feeds.contains(&feed_id)
+}
@@ -0,0 +1,120 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+/// Set of enclave instances whose BLS12-381 (min_sig) signatures are accepted
+/// in aggregate. A result signed by several instances is verified with a single
+/// `bls12381_min_sig_verify` against the sum of the signers' public keys.
+module oracle::attestors;
+
+use enclave::enclave::Enclave;
+use oracle::config::{Config, CONFIG};
+use sui::bls12381::{Self, G2};
+use sui::group_ops::Element;
+
+#[error]
+const ENotAdmin: vector<u8> = b"Not admin";
+
+#[error]
+const EInvalidBlsKey: vector<u8> = b"Invalid BLS public key";
+
+#[error]
+const EDuplicateAttestor: vector<u8> = b"Attestor already registered";
+
+#[error]
+const EInvalidThreshold: vector<u8> = b"Invalid threshold";
+
+#[error]
+const EInvalidSigners: vector<u8> = b"Invalid signers";
+
+const BLS_PUBLIC_KEY_LENGTH: u64 = 96;
+
+public struct Attestors has key {
+    id: UID,
+    /// BLS public keys of the registered instances.
+    keys: vector<vector<u8>>,
+    /// Minimum number of distinct signers of an aggregated signature.
+    threshold: u64,
+}
+
+public fun create(config: &Config, threshold: u64, ctx: &mut TxContext) {
+    assert!(config.admin() == ctx.sender(), ENotAdmin);
+    assert!(threshold > 0, EInvalidThreshold);
+    transfer::share_object(Attestors {
+        id: object::new(ctx),
+        keys: vector[],
+        threshold,
+    });
+}
+
+/// Register an enclave whose attestation committed to its BLS public key
+/// (served by the enclave at /bls_attestation). Only enclaves registered
+/// against the oracle's EnclaveConfig are accepted.
+public fun add_attestor(
+    attestors: &mut Attestors,
+    config: &Config,
+    enclave: &Enclave<CONFIG>,
+    ctx: &mut TxContext,
+) {
+    assert!(config.admin() == ctx.sender(), ENotAdmin);
+    let pk = *enclave.pk();
+    assert!(pk.length() == BLS_PUBLIC_KEY_LENGTH, EInvalidBlsKey);
+    assert!(!attestors.keys.contains(&pk), EDuplicateAttestor);
+    attestors.keys.push_back(pk);
+}
+
+public fun remove_attestor(
+    attestors: &mut Attestors,
+    config: &Config,
+    pk: vector<u8>,
+    ctx: &mut TxContext,
+) {
+    assert!(config.admin() == ctx.sender(), ENotAdmin);
+    let (found, index) = attestors.keys.index_of(&pk);
+    assert!(found, EInvalidBlsKey);
+    attestors.keys.remove(index);
+}
+
+public fun set_threshold(
+    attestors: &mut Attestors,
+    config: &Config,
+    threshold: u64,
+    ctx: &mut TxContext,
+) {
+    assert!(config.admin() == ctx.sender(), ENotAdmin);
+    assert!(threshold > 0, EInvalidThreshold);
+    attestors.threshold = threshold;
+}
+
+/// Verify an aggregated signature over `message` by the attestors at the
+/// strictly increasing indices `signers`, at least `threshold` of them.
+public fun verify(
+    attestors: &Attestors,
+    signers: vector<u64>,
+    message: &vector<u8>,
+    signature: &vector<u8>,
+): bool {
+    assert!(signers.length() >= attestors.threshold, EInvalidSigners);
+    let mut aggregated: Option<Element<G2>> = option::none();
+    let mut previous: Option<u64> = option::none();
+    signers.do!(|index| {
+        assert!(index < attestors.keys.length(), EInvalidSigners);
+        previous.do!(|prev| assert!(index > prev, EInvalidSigners));
+        previous = option::some(index);
+        let pk = bls12381::g2_from_bytes(&attestors.keys[index]);
+        aggregated = option::some(if (aggregated.is_some()) {
+            bls12381::g2_add(&aggregated.extract(), &pk)
+        } else {
+            pk
+        });
+    });
+    let aggregated = aggregated.destroy_some();
+    bls12381::bls12381_min_sig_verify(signature, aggregated.bytes(), message)
+}
+
+public fun keys(attestors: &Attestors): vector<vector<u8>> {
+    attestors.keys
+}
+
+public fun threshold(attestors: &Attestors): u64 {
+    attestors.threshold
+}
@@ -36,3 +36,7 @@ public fun update_max_update_time_ms(
 public fun get_max_update_time_ms(config: &Config): u64 {
     config.max_update_time_ms
 }
+
+public fun admin(config: &Config): address {
+    config.admin
+}
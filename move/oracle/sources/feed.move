@@ -9,9 +9,12 @@ module oracle::oracle;
 module oracle::feed;
 
 use enclave::enclave::Enclave;
+use oracle::attestors::Attestors;
 use oracle::config::Config;
 use std::string::String;
 use sui::clock::Clock;
+use sui::dynamic_field;
+use sui::event;
 use std::bcs;
 use sui::ed25519;
 use std::debug;
@@ -19,6 +22,9 @@ use std::debug;
 #[error]
 const EInvalidSignature: vector<u8> = b"Invalid signature";
 
+#[error]
+const EInvalidIntentScope: vector<u8> = b"Invalid intent scope";
+
 #[error]
 const EInvalidTimestamp: vector<u8> = b"Invalid timestamp";
 
@@ -37,8 +43,22 @@ const EInvalidResult: vector<u8> = b"Invalid result";
 #[error]
 const EInvalidReceipt: vector<u8> = b"Invalid receipt";
 
+#[error]
+const EInvalidBlobHash: vector<u8> = b"Invalid blob hash";
+
+#[error]
+const EInvalidPublisherKey: vector<u8> = b"Invalid publisher key";
+
+/// Scope of results signed by process_data. Other scopes, e.g. results of
+/// execute_code test runs, are never accepted as feed results.
+const PROCESS_DATA_INTENT: u8 = 0;
+/// Should match APP_ID in the Rust app.
+const APP_ID: vector<u8> = b"coeus-oracle";
+
 public enum CodeExtension has store {
     RHAI,
+    JSON_CONFIG,
+    STARLARK,
 }
 
 public enum ReturnType has copy, drop, store {
@@ -61,10 +81,17 @@ public struct UpdateOracleResponse has copy, drop, store {
 
 public struct Payload has copy, drop, store {
     intent_scope: u8,
+    app_id: vector<u8>,
     timestamp_ms: u64,
     result: UpdateOracleResponse,
 }
 
+/// Emitted by request_update. Enclaves with events enabled run the feed when
+/// they see it.
+public struct UpdateRequested has copy, drop {
+    feed_id: ID,
+}
+
 public struct NewOracleFeedReceipt {
     id: ID,
 }
@@ -78,6 +105,30 @@ public struct OracleFeed has key, store {
     allow_update_timestamp_ms: u64,
 }
 
+/// Key of the settings dynamic field of a feed.
+public struct SettingsKey has copy, drop, store {}
+
+/// Settings added after the first OracleFeed layout. A package upgrade
+/// cannot add fields to OracleFeed, so they are a dynamic field of the feed
+/// under SettingsKey, set once by new_with_settings. Feeds created by new
+/// have none. Later settings come as a new FeedSettingsV2 under the same key.
+public struct FeedSettingsV1 has store {
+    strict_types: bool,
+    /// SHA-256 of the script blob, checked by the enclave before execution.
+    blob_hash: vector<u8>,
+    /// Ed25519 public keys allowed to sign the script. When non-empty the
+    /// enclave refuses to execute code without a valid publisher signature.
+    publisher_keys: vector<vector<u8>>,
+    /// walrus::blob::Blob object holding blob_id. When set, the enclave checks
+    /// that the blob is certified and not expired before executing it.
+    blob_object_id: Option<ID>,
+    /// Sender of the transaction that created the feed. The enclave only
+    /// trusts a Blob object owned by the creator or an allowed publisher.
+    creator: address,
+}
+
+/// Create a feed without settings. The enclave runs its blob without a hash
+/// check, so prefer new_with_settings to pin the code.
 public fun new(
     blob_id: String,
     extension: CodeExtension,
@@ -97,12 +148,43 @@ public fun new(
     (feed, receipt)
 }
 
+/// Create a feed with its FeedSettingsV1.
+public fun new_with_settings(
+    blob_id: String,
+    extension: CodeExtension,
+    return_type: ReturnType,
+    allow_update_timestamp_ms: u64,
+    strict_types: bool,
+    blob_hash: vector<u8>,
+    publisher_keys: vector<vector<u8>>,
+    blob_object_id: Option<ID>,
+    ctx: &mut TxContext,
+): (OracleFeed, NewOracleFeedReceipt) {
+    assert!(blob_hash.length() == 32, EInvalidBlobHash);
+    publisher_keys.do_ref!(|key| assert!(key.length() == 32, EInvalidPublisherKey));
+    let (mut feed, receipt) = new(blob_id, extension, return_type, allow_update_timestamp_ms, ctx);
+    let settings = FeedSettingsV1 {
+        strict_types,
+        blob_hash,
+        publisher_keys,
+        blob_object_id,
+        creator: ctx.sender(),
+    };
+    dynamic_field::add(&mut feed.id, SettingsKey {}, settings);
+    (feed, receipt)
+}
+
 public fun repay(feed: OracleFeed, receipt: NewOracleFeedReceipt) {
     let NewOracleFeedReceipt { id } = receipt;
     assert!(object::id(&feed) == id, EInvalidReceipt);
     transfer::share_object(feed);
 }
 
+/// Ask the enclaves watching for UpdateRequested to run the feed.
+public fun request_update(feed: &OracleFeed) {
+    event::emit(UpdateRequested { feed_id: object::id(feed) });
+}
+
 public fun submit_result<T>(
     config: &Config,
     enclave: &Enclave<T>,
@@ -120,8 +202,10 @@ public fun submit_result<T>(
     assert!(clock.timestamp_ms() >= feed.allow_update_timestamp_ms, EInvalidAllowUpdateTimestamp);
     assert!(result.is_some(), EInvalidResult);
     assert!(feed.result.is_none(), EInvalidResult);
+    assert!(intent_scope == PROCESS_DATA_INTENT, EInvalidIntentScope);
     let verify_result = enclave.verify_signature<T, UpdateOracleResponse>(
         intent_scope,
+        APP_ID,
         timestamp_ms,
         UpdateOracleResponse { result },
         &signature,
@@ -130,6 +214,39 @@ public fun submit_result<T>(
     feed.result = result;
 }
 
+/// Submit a result signed by several enclave instances, with their BLS
+/// signatures aggregated into `signature`. `signers` are the indices of the
+/// signing instances in `attestors`.
+public fun submit_aggregated_result(
+    config: &Config,
+    attestors: &Attestors,
+    result: Option<Result>,
+    intent_scope: u8,
+    timestamp_ms: u64,
+    signers: vector<u64>,
+    signature: vector<u8>,
+    feed: &mut OracleFeed,
+    clock: &Clock,
+) {
+    assert!(
+        clock.timestamp_ms() - timestamp_ms <= config.get_max_update_time_ms(),
+        EInvalidTimestamp,
+    );
+    assert!(clock.timestamp_ms() >= feed.allow_update_timestamp_ms, EInvalidAllowUpdateTimestamp);
+    assert!(result.is_some(), EInvalidResult);
+    assert!(feed.result.is_none(), EInvalidResult);
+    assert!(intent_scope == PROCESS_DATA_INTENT, EInvalidIntentScope);
+    let payload = Payload {
+        intent_scope,
+        app_id: APP_ID,
+        timestamp_ms,
+        result: UpdateOracleResponse { result },
+    };
+    let message = bcs::to_bytes(&payload);
+    assert!(attestors.verify(signers, &message, &signature), EInvalidSignature);
+    feed.result = result;
+}
+
 public fun construct_string_result(result: String): Result {
     Result::STRING(result)
 }
@@ -149,6 +266,8 @@ public fun construct_vector_result(result: vector<u8>): Result {
 public fun construct_code_extension(extension: vector<u8>): CodeExtension {
     match (extension) {
         b"rhai" => CodeExtension::RHAI,
+        b"json_config" => CodeExtension::JSON_CONFIG,
+        b"starlark" => CodeExtension::STARLARK,
         _ => abort EInvalidCodeExtension,
     }
 }
@@ -213,4 +332,70 @@ public fun is_vector_result_type(return_type: ReturnType): bool {
 
 public fun return_type(feed: &OracleFeed): ReturnType {
     feed.return_type
-}
\ No newline at end of file
+}
+
+public fun has_settings(feed: &OracleFeed): bool {
+    dynamic_field::exists_with_type<SettingsKey, FeedSettingsV1>(&feed.id, SettingsKey {})
+}
+
+fun settings(feed: &OracleFeed): &FeedSettingsV1 {
+    dynamic_field::borrow(&feed.id, SettingsKey {})
+}
+
+public fun strict_types(feed: &OracleFeed): bool {
+    feed.has_settings() && feed.settings().strict_types
+}
+
+public fun blob_hash(feed: &OracleFeed): vector<u8> {
+    if (feed.has_settings()) feed.settings().blob_hash else vector[]
+}
+
+public fun publisher_keys(feed: &OracleFeed): vector<vector<u8>> {
+    if (feed.has_settings()) feed.settings().publisher_keys else vector[]
+}
+
+public fun blob_object_id(feed: &OracleFeed): Option<ID> {
+    if (feed.has_settings()) feed.settings().blob_object_id else option::none()
+}
+
+/// Sender of the transaction that created the feed, none for feeds without
+/// settings.
+public fun creator(feed: &OracleFeed): Option<address> {
+    if (feed.has_settings()) option::some(feed.settings().creator) else option::none()
+}
+
+#[test_only]
+fun test_payload(result: Option<Result>): vector<u8> {
+    bcs::to_bytes(&Payload {
+        intent_scope: PROCESS_DATA_INTENT,
+        app_id: APP_ID,
+        timestamp_ms: 1744038900000,
+        result: UpdateOracleResponse { result },
+    })
+}
+
+#[test]
+fun test_payload_vectors() {
+    // Same bytes as GOLDEN_VECTORS in src/nautilus-server/src/apps/coeus-oracle/payload_codec.rs.
+    assert!(test_payload(option::none()) == x"000c636f6575732d6f7261636c6520b1d1109601000000", 0);
+    assert!(
+        test_payload(option::some(Result::STRING(b"BTC/USD".to_string()))) == x"000c636f6575732d6f7261636c6520b1d110960100000100074254432f555344",
+        0,
+    );
+    assert!(
+        test_payload(option::some(Result::BOOLEAN(true))) == x"000c636f6575732d6f7261636c6520b1d11096010000010101",
+        0,
+    );
+    assert!(
+        test_payload(option::some(Result::NUMBER(1))) == x"000c636f6575732d6f7261636c6520b1d1109601000001020100000000000000",
+        0,
+    );
+    assert!(
+        test_payload(option::some(Result::NUMBER(18446744073709551615))) == x"000c636f6575732d6f7261636c6520b1d110960100000102ffffffffffffffff",
+        0,
+    );
+    assert!(
+        test_payload(option::some(Result::VECTOR(vector[1, 2, 3]))) == x"000c636f6575732d6f7261636c6520b1d11096010000010303010203",
+        0,
+    );
+}
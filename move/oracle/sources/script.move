@@ -0,0 +1,48 @@
+// Feed code stored directly on-chain, for small scripts that should not depend
+// on Walrus. A feed references it with blob_id "sui://<script object id>".
+module oracle::script;
+
+use sui::dynamic_field as df;
+
+#[error]
+const EEmptyChunk: vector<u8> = b"Empty chunk";
+
+/// The enclave executes `code` followed by the dynamic field chunks 0..chunk_count,
+/// which allows scripts larger than a single object.
+public struct Script has key, store {
+    id: UID,
+    code: vector<u8>,
+    chunk_count: u64,
+}
+
+public fun new(code: vector<u8>, ctx: &mut TxContext): Script {
+    Script {
+        id: object::new(ctx),
+        code,
+        chunk_count: 0,
+    }
+}
+
+/// Append a chunk stored as a dynamic field keyed by its index.
+public fun append_chunk(script: &mut Script, chunk: vector<u8>) {
+    assert!(chunk.length() > 0, EEmptyChunk);
+    df::add(&mut script.id, script.chunk_count, chunk);
+    script.chunk_count = script.chunk_count + 1;
+}
+
+/// Make the script immutable once all chunks are uploaded.
+public fun freeze_script(script: Script) {
+    transfer::freeze_object(script);
+}
+
+public fun code(script: &Script): vector<u8> {
+    script.code
+}
+
+public fun chunk_count(script: &Script): u64 {
+    script.chunk_count
+}
+
+public fun chunk(script: &Script, index: u64): vector<u8> {
+    *df::borrow(&script.id, index)
+}
@@ -0,0 +1,31 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+/// Seal policy of the feed key master seed: only a registered oracle enclave
+/// can fetch the keys to decrypt it.
+module oracle::seal_policy;
+
+use enclave::enclave::Enclave;
+use oracle::config::CONFIG;
+use sui::hash::blake2b256;
+
+#[error]
+const ENoAccess: vector<u8> = b"Sender is not the enclave";
+
+entry fun seal_approve(_id: vector<u8>, enclave: &Enclave<CONFIG>, ctx: &TxContext) {
+    assert!(ctx.sender().to_bytes() == pk_to_address(enclave.pk()), ENoAccess);
+}
+
+fun pk_to_address(pk: &vector<u8>): vector<u8> {
+    // Ed25519 flag followed by the enclave key, the address is blake2b256(flag || pk).
+    let mut arr = vector[0u8];
+    arr.append(*pk);
+    blake2b256(&arr)
+}
+
+#[test]
+fun test_pk_to_address() {
+    let eph_pk = x"5c38d3668c45ff891766ee99bd3522ae48d9771dc77e8a6ac9f0bde6c3a2ca48";
+    let expected_bytes = x"29287d8584fb5b71b8d62e7224b867207d205fb61d42b7cce0deef95bf4e8202";
+    assert!(pk_to_address(&eph_pk) == expected_bytes);
+}
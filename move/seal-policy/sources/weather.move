@@ -11,6 +11,8 @@ module seal_policy_example::weather {
     ///
 
     const WEATHER_INTENT: u8 = 0;
+    /// Should match APP_ID in the Rust app.
+    const APP_ID: vector<u8> = b"seal-weather";
     const EInvalidSignature: u64 = 1;
 
     public struct WeatherNFT has key, store {
@@ -52,6 +54,7 @@ module seal_policy_example::weather {
     ): WeatherNFT {
         let res = enclave.verify_signature(
             WEATHER_INTENT,
+            APP_ID,
             timestamp_ms,
             WeatherResponse { location, temperature },
             sig,
@@ -105,10 +108,12 @@ module seal_policy_example::weather {
 
         scenario.next_tx(@0x4668aa5963dacfe3e169be3cf824395ab9de3f0a544fc2ca638858a536b5ff4b);
 
-        let enclave = scenario.take_shared<Enclave<WEATHER>>();
+        let mut enclave = scenario.take_shared<Enclave<WEATHER>>();
+        // The attested enclave's responses predate app ids, sign with a test key
+        enclave.set_pk_for_testing(x"17d4989d218d9e5c841cb52f1f23ceae73e452e6aa2bc1207eb923c66b81b663");
 
         let sig =
-            x"77b6d8be225440d00f3d6eb52e91076a8927cebfb520e58c19daf31ecf06b3798ec3d3ce9630a9eceee46d24f057794a60dd781657cb06d952269cfc5ae19500";
+            x"f48235af52c8b00d6a7936bb963cf3b6895f8eb37005d9ac4aac804206228da0de140f4091ee457ce83fc5cf25b53f408909d45a3167372cbc117b19fd6e3f0c";
         let nft = update_weather(
             b"San Francisco".to_string(),
             13,
@@ -8,6 +8,8 @@ use std::string::{Self, String};
 use enclave::enclave::{Self, Enclave};
 
 const TWEET_INTENT: u8 = 0;
+/// Should match APP_ID in the Rust app.
+const APP_ID: vector<u8> = b"twitter";
 const EInvalidSignature: u64 = 1;
 
 public struct Twitter has key, store {
@@ -40,7 +42,7 @@ fun init(otw: TWITTER, ctx: &mut TxContext) {
 public fun mint_nft<T>(twitter_name: vector<u8>, timestamp_ms: u64, sig: &vector<u8>, enclave: &Enclave<T>, ctx: &mut TxContext): Twitter {
     let sui_address = ctx.sender().to_bytes();
 
-    let res = enclave::verify_signature<T, UserData>(enclave, TWEET_INTENT, timestamp_ms, UserData { twitter_name, sui_address }, sig);
+    let res = enclave::verify_signature<T, UserData>(enclave, TWEET_INTENT, APP_ID, timestamp_ms, UserData { twitter_name, sui_address }, sig);
     assert!(res, EInvalidSignature);
 
     Twitter {
@@ -91,12 +93,14 @@ fun test_twitter_flow() {
     config.register_enclave(document, ctx(&mut scenario));
 
     next_tx(&mut scenario, @0x101ce8865558e08408b83f60ee9e78843d03d547c850cbe12cb599e17833dd3e);
-    let enclave = test_scenario::take_shared<Enclave<TWITTER>>(&scenario);
+    let mut enclave = test_scenario::take_shared<Enclave<TWITTER>>(&scenario);
+    // The attested enclave's responses predate app ids, sign with a test key
+    enclave.set_pk_for_testing(x"17d4989d218d9e5c841cb52f1f23ceae73e452e6aa2bc1207eb923c66b81b663");
 
-    // using real enclave response for the current address
+    // enclave response for the current address, as signed with the test key
     // curl -X POST http://<PUBLIC_IP>:3000/process_data -H "Content-Type: application/json" -d '{"payload": {"user_url": "https://x.com/mystenintern/status/1852386957789114394" }}'
-    // {"response":{"intent":0,"timestamp_ms":1744731581697,"data":{"twitter_name":[109,121,115,116,101,110,105,110,116,101,114,110],"sui_address":[16,28,232,134,85,88,224,132,8,184,63,96,238,158,120,132,61,3,213,71,200,80,203,225,44,181,153,225,120,51,221,62]}},"signature":"c1d19cd522f81033f5314623a87c92977e65d3b1efc03d64221e9128acb906fdd8339433408c8feb17653a0b92e4014d80a2a346d9415479efb8c8a38cfc400e"}
-    let sig = x"c1d19cd522f81033f5314623a87c92977e65d3b1efc03d64221e9128acb906fdd8339433408c8feb17653a0b92e4014d80a2a346d9415479efb8c8a38cfc400e";
+    // {"response":{"intent":0,"app_id":"twitter","timestamp_ms":1744731581697,"data":{"twitter_name":[109,121,115,116,101,110,105,110,116,101,114,110],"sui_address":[16,28,232,134,85,88,224,132,8,184,63,96,238,158,120,132,61,3,213,71,200,80,203,225,44,181,153,225,120,51,221,62]}},"signature":"66d0ed2f7ab1cb1f2cea883c0ecd841f33b9adf8df6ec40f3d4773a74cfc12380c4ba0521df83b8fda5ca8e1ca6b6ddc4e95465e39ba255089da6ec5841c7d0b"}
+    let sig = x"66d0ed2f7ab1cb1f2cea883c0ecd841f33b9adf8df6ec40f3d4773a74cfc12380c4ba0521df83b8fda5ca8e1ca6b6ddc4e95465e39ba255089da6ec5841c7d0b";
     let twitter_name = b"mystenintern";
     let nft = mint_nft(twitter_name,  1744731581697, &sig, &enclave, ctx(&mut scenario));
     assert!(string::utf8(twitter_name) == nft.twitter_name, 0);
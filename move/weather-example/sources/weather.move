@@ -12,6 +12,8 @@ use std::string::String;
 ///
 
 const WEATHER_INTENT: u8 = 0;
+/// Should match APP_ID in the Rust app.
+const APP_ID: vector<u8> = b"weather";
 const EInvalidSignature: u64 = 1;
 
 public struct WeatherNFT has key, store {
@@ -53,6 +55,7 @@ public fun update_weather<T>(
 ): WeatherNFT {
     let res = enclave.verify_signature(
         WEATHER_INTENT,
+        APP_ID,
         timestamp_ms,
         WeatherResponse { location, temperature },
         sig,
@@ -106,10 +109,12 @@ fun test_weather_flow() {
 
     scenario.next_tx(@0x4668aa5963dacfe3e169be3cf824395ab9de3f0a544fc2ca638858a536b5ff4b);
 
-    let enclave = scenario.take_shared<Enclave<WEATHER>>();
+    let mut enclave = scenario.take_shared<Enclave<WEATHER>>();
+    // The attested enclave's responses predate app ids, sign with a test key
+    enclave.set_pk_for_testing(x"17d4989d218d9e5c841cb52f1f23ceae73e452e6aa2bc1207eb923c66b81b663");
 
     let sig =
-        x"77b6d8be225440d00f3d6eb52e91076a8927cebfb520e58c19daf31ecf06b3798ec3d3ce9630a9eceee46d24f057794a60dd781657cb06d952269cfc5ae19500";
+        x"03ea76ee6f8feb861d53128452997b129af3596e825513380a6056a5afa33005a97ca8982d23c95ac0b633c52fa15524bde672876749dac7abf1d66240fe6e0f";
     let nft = update_weather(
         b"San Francisco".to_string(),
         13,
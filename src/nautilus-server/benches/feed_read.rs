@@ -0,0 +1,73 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Reading a feed from the BCS of its object, as returned by the fullnode:
+//! decoding a whole `sui_sdk_types::Object` and then the feed from its copied
+//! contents, against reading the feed from the borrowed contents (see
+//! feed_object.rs). Feeds with a large stored result show the cost of the
+//! copy.
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use nautilus_server::app::config::COEUS_CONFIG;
+use nautilus_server::app::{
+    CodeExtension, OracleFeed, ResultValue, ReturnType, decode_oracle_feed, feed_layout,
+};
+use std::hint::black_box;
+use sui_sdk_types::{Address, Digest, MoveStruct, Object, ObjectData, Owner};
+
+/// BCS of a shared feed object storing `result_bytes` of result.
+fn feed_object(result_bytes: usize) -> Vec<u8> {
+    let feed_id = Address::from_hex("0xf1").unwrap();
+    let feed = OracleFeed {
+        id: feed_id,
+        blob_id: "0".repeat(43),
+        extension: CodeExtension::RHAI,
+        result: Some(ResultValue::VECTOR(vec![7; result_bytes])),
+        return_type: ReturnType::VECTOR,
+        allow_update_timestamp_ms: 1744038900000,
+        // Settings are a dynamic field, not part of the object
+        strict_types: false,
+        blob_hash: vec![],
+        publisher_keys: vec![],
+        blob_object_id: None,
+        creator: None,
+    };
+    let feed_type = COEUS_CONFIG.feed_object.feed_type().unwrap();
+    let contents = bcs::to_bytes(&feed).unwrap();
+    let move_struct = MoveStruct::new(feed_type, true, 7, contents).unwrap();
+    let object = Object::new(
+        ObjectData::Struct(move_struct),
+        Owner::Shared(1),
+        Digest::ZERO,
+        0,
+    );
+    bcs::to_bytes(&object).unwrap()
+}
+
+fn feed_read(c: &mut Criterion) {
+    let feed_id = Address::from_hex("0xf1").unwrap();
+    let mut group = c.benchmark_group("feed_read");
+    for result_bytes in [32, 4 * 1024, 256 * 1024] {
+        let bytes = feed_object(result_bytes);
+        group.bench_with_input(
+            BenchmarkId::new("object_then_feed", result_bytes),
+            &bytes,
+            |b, bytes| {
+                b.iter(|| {
+                    let object: Object = bcs::from_bytes(black_box(bytes)).unwrap();
+                    let contents = object.as_struct().unwrap().contents();
+                    feed_layout::decode(&feed_id, contents).unwrap()
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("borrowed_contents", result_bytes),
+            &bytes,
+            |b, bytes| b.iter(|| decode_oracle_feed(&feed_id, black_box(bytes)).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, feed_read);
+criterion_main!(benches);
@@ -0,0 +1,117 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Hot path of a feed run after its code is downloaded: compiling the script,
+//! running a typical price extraction, converting its result and signing the
+//! payload. Engine construction is in script_engine.rs.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use fastcrypto::ed25519::Ed25519KeyPair;
+use fastcrypto::traits::KeyPair;
+use nautilus_server::app::script_engine::{compile, new_engine, script_engine};
+use nautilus_server::app::{
+    APP_ID, ResultMeta, ResultValue, ReturnType, UpdateOracleResponse, convert_rhai_result,
+    encode_payload,
+};
+use nautilus_server::common::{IntentScope, to_signed_response};
+use rhai::{Dynamic, Scope};
+use std::hint::black_box;
+use std::sync::{Arc, Mutex};
+
+/// Median of the prices of an exchange API response, scaled to 8 decimals.
+const PRICE_SCRIPT: &str = r#"
+let data = parse_json(body);
+let prices = [];
+for ticker in data.tickers {
+    prices.push(parse_float(ticker.last));
+}
+prices.sort();
+let median = prices[prices.len() / 2];
+set_source_count(prices.len());
+(median * 100000000.0).to_int()
+"#;
+
+const BODY: &str = r#"{"tickers": [
+    {"exchange": "a", "last": "64010.52"},
+    {"exchange": "b", "last": "64022.10"},
+    {"exchange": "c", "last": "63998.75"},
+    {"exchange": "d", "last": "64005.00"},
+    {"exchange": "e", "last": "64031.40"}
+]}"#;
+
+fn compilation(c: &mut Criterion) {
+    let engine = new_engine();
+    let mut group = c.benchmark_group("compile");
+    group.bench_function("uncached", |b| {
+        b.iter(|| engine.compile(black_box(PRICE_SCRIPT)).unwrap())
+    });
+    group.bench_function("cached", |b| {
+        b.iter(|| compile(&engine, black_box(PRICE_SCRIPT)).unwrap())
+    });
+    group.finish();
+}
+
+fn price_script(c: &mut Criterion) {
+    let meta = Arc::new(Mutex::new(ResultMeta::default()));
+    let engine = script_engine(meta);
+    let ast = compile(&engine, PRICE_SCRIPT).unwrap();
+    c.bench_function("price_script", |b| {
+        b.iter(|| {
+            let mut scope = Scope::new();
+            scope.push_constant("body", BODY.to_string());
+            engine
+                .eval_ast_with_scope::<Dynamic>(&mut scope, &ast)
+                .unwrap()
+        })
+    });
+}
+
+fn conversion(c: &mut Criterion) {
+    let mut group = c.benchmark_group("convert");
+    group.bench_function("number", |b| {
+        b.iter(|| convert_rhai_result(Dynamic::from(6401052000000i64), &ReturnType::NUMBER, true))
+    });
+    group.bench_function("vector", |b| {
+        let bytes: rhai::Array = (0..64).map(|byte: i64| Dynamic::from(byte)).collect();
+        b.iter(|| {
+            convert_rhai_result(
+                Dynamic::from_array(bytes.clone()),
+                &ReturnType::VECTOR,
+                true,
+            )
+        })
+    });
+    group.finish();
+}
+
+fn signing(c: &mut Criterion) {
+    let kp = Ed25519KeyPair::generate(&mut rand::thread_rng());
+    let response = UpdateOracleResponse {
+        result: Some(ResultValue::NUMBER(6401052000000)),
+    };
+    let mut group = c.benchmark_group("payload");
+    group.bench_function("encode", |b| {
+        b.iter(|| {
+            encode_payload(
+                IntentScope::ProcessData,
+                1744038900000,
+                black_box(&response),
+            )
+        })
+    });
+    group.bench_function("sign", |b| {
+        b.iter(|| {
+            to_signed_response(
+                &kp,
+                response.clone(),
+                1744038900000,
+                IntentScope::ProcessData,
+                APP_ID,
+            )
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, compilation, price_script, conversion, signing);
+criterion_main!(benches);
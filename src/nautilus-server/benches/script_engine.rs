@@ -0,0 +1,48 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Cost of the Rhai engine of a script run, with its modules built for the
+//! run as before and shared from the worker's template.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use nautilus_server::app::script_engine::{build_modules, new_engine};
+use rhai::Engine;
+
+const SCRIPT: &str = r#"
+let prices = [64010, 64022, 63998];
+prices.sort();
+prices[1]
+"#;
+
+fn engine(c: &mut Criterion) {
+    let mut group = c.benchmark_group("engine");
+    group.bench_function("built_per_run", |b| {
+        b.iter(|| {
+            let mut engine = Engine::new_raw();
+            for module in build_modules() {
+                engine.register_global_module(module);
+            }
+            engine
+        })
+    });
+    group.bench_function("shared_modules", |b| b.iter(new_engine));
+    group.finish();
+
+    let mut group = c.benchmark_group("run");
+    group.bench_function("built_per_run", |b| {
+        b.iter(|| {
+            let mut engine = Engine::new_raw();
+            for module in build_modules() {
+                engine.register_global_module(module);
+            }
+            engine.eval::<i64>(SCRIPT).unwrap()
+        })
+    });
+    group.bench_function("shared_modules", |b| {
+        b.iter(|| new_engine().eval::<i64>(SCRIPT).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, engine);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Generates the gRPC service of the coeus-oracle app from proto/.
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto");
+    if std::env::var_os("CARGO_FEATURE_COEUS_ORACLE").is_none() {
+        return Ok(());
+    }
+    // Parsed by protox rather than protoc, so the enclave image build needs
+    // no protobuf toolchain
+    let descriptors = protox::compile(["coeus/v1/oracle.proto"], ["proto"])?;
+    tonic_prost_build::configure()
+        .build_client(false)
+        .compile_fds(descriptors)?;
+    Ok(())
+}
@@ -74,5 +74,7 @@ python3 /traffic_forwarder.py 127.0.0.72 443 3 8109 &
 
 # Listens on Local VSOCK Port 3000 and forwards to localhost 3000
 socat VSOCK-LISTEN:3000,reuseaddr,fork TCP:localhost:3000 &
+# Same for the gRPC service when [grpc] is enabled in nautilus.toml
+# socat VSOCK-LISTEN:3001,reuseaddr,fork TCP:localhost:3001 &
 
 /nautilus-server
@@ -0,0 +1,360 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Runtime configuration changes under `/admin`, applied without restarting
+//! the enclave so the attested signing key stays valid.
+//!
+//! - `PUT /admin/rate_limit` replaces the `[rate_limit]` section, `DELETE`
+//!   restores it.
+//! - `PUT /admin/allowlist` restricts the hosts script HTTP calls may reach,
+//!   `DELETE` lifts the restriction. The enclave can still only reach the
+//!   hosts run.sh forwards at boot, so this can only narrow them.
+//! - `POST /admin/feeds/:feed_id/pause` and `/resume` stop and restart runs of
+//!   a feed, both on request and by the scheduler.
+//! - `PUT /admin/flags/:name` turns off a feature flag registered by the app,
+//!   or back on if it is enabled in the configuration. Flags gate what the
+//!   enclave signs, so the host can withdraw a feature but never grant one.
+//! - `PUT /admin/registration` sets the Enclave object the registration check
+//!   reads, once it is created for the running enclave.
+//! - `GET /admin/state` returns the current overrides.
+//! - `GET /admin/profile` captures a CPU profile, in builds with the
+//!   `profiling` feature (see profiling.rs).
+//!
+//! Every route needs an API key with the admin scope. Unlike the other
+//! routes, the admin API is refused when no keys are configured, since the
+//! host would otherwise control it. Overrides are not part of the config
+//! hash and are lost on restart.
+
+use crate::auth::{self, Scope};
+use crate::config::RateLimitConfig;
+use crate::extract::AppJson;
+use crate::{AppState, EnclaveError};
+use axum::Json;
+use axum::Router;
+use axum::extract::{Path, Request, State};
+use axum::middleware::{Next, from_fn_with_state};
+use axum::response::Response;
+use axum::routing::{get, post, put};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, RwLock};
+use sui_sdk_types::Address;
+use tracing::info;
+
+lazy_static::lazy_static! {
+    static ref OVERRIDES: RwLock<RuntimeOverrides> = RwLock::new(RuntimeOverrides::default());
+}
+
+/// Configuration changed through the admin API, returned by GET /admin/state.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RuntimeOverrides {
+    /// Replaces `[rate_limit]` of nautilus.toml when set.
+    pub rate_limit: Option<RateLimitConfig>,
+    /// Hosts script HTTP calls may reach, unrestricted when unset.
+    pub allowlist: Option<BTreeSet<String>>,
+    /// Feeds that are not run until resumed.
+    pub paused_feeds: BTreeSet<String>,
+    /// Feature flags registered by the app, with their current value.
+    pub flags: BTreeMap<String, bool>,
+    /// Configured value of each registered flag, the most it can be set to.
+    #[serde(skip)]
+    configured_flags: BTreeMap<String, bool>,
+    /// Replaces `registration.enclave_object_id` of nautilus.toml when set.
+    pub enclave_object_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AllowlistRequest {
+    /// Host names, as in allowed_endpoints.yaml.
+    pub endpoints: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FlagRequest {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RegistrationRequest {
+    /// Hex id of the Enclave object registered for this enclave.
+    pub enclave_object_id: String,
+}
+
+fn read() -> std::sync::RwLockReadGuard<'static, RuntimeOverrides> {
+    OVERRIDES.read().expect("admin overrides lock poisoned")
+}
+
+fn write() -> std::sync::RwLockWriteGuard<'static, RuntimeOverrides> {
+    OVERRIDES.write().expect("admin overrides lock poisoned")
+}
+
+/// Rate limits in effect: the admin override, or the configured ones.
+pub fn rate_limits(config: &RateLimitConfig) -> RateLimitConfig {
+    read().rate_limit.clone().unwrap_or_else(|| config.clone())
+}
+
+/// Whether a script may call `url`. Unparseable URLs are left to the HTTP
+/// client to reject.
+pub fn host_allowed(url: &str) -> bool {
+    let overrides = read();
+    let Some(allowlist) = &overrides.allowlist else {
+        return true;
+    };
+    match reqwest::Url::parse(url) {
+        Ok(url) => url
+            .host_str()
+            .is_some_and(|host| allowlist.contains(&host.to_ascii_lowercase())),
+        Err(_) => true,
+    }
+}
+
+/// Whether runs of `feed_id` are paused.
+pub fn is_paused(feed_id: &Address) -> bool {
+    read().paused_feeds.contains(&feed_id.to_string())
+}
+
+/// Make a feature flag switchable through the admin API, starting at its
+/// configured value. A flag registered twice keeps its current value.
+pub fn register_flag(name: &str, configured: bool) {
+    let mut overrides = write();
+    overrides
+        .flags
+        .entry(name.to_string())
+        .or_insert(configured);
+    overrides
+        .configured_flags
+        .insert(name.to_string(), configured);
+}
+
+/// Current value of a registered feature flag.
+pub fn flag(name: &str) -> Option<bool> {
+    read().flags.get(name).copied()
+}
+
+/// Whether a feature `configured` on is still on, the admin API may only
+/// have turned it off.
+pub fn flag_enabled(name: &str, configured: bool) -> bool {
+    configured && flag(name).unwrap_or(true)
+}
+
+/// Enclave object set through the admin API.
+pub fn enclave_object_id() -> Option<String> {
+    read().enclave_object_id.clone()
+}
+
+fn parse_feed_id(feed_id: &str) -> Result<Address, EnclaveError> {
+    Address::from_hex(feed_id)
+        .map_err(|e| EnclaveError::InvalidRequest(format!("Invalid feed id {}: {}", feed_id, e)))
+}
+
+async fn get_state() -> Json<RuntimeOverrides> {
+    Json(read().clone())
+}
+
+async fn set_rate_limit(AppJson(limits): AppJson<RateLimitConfig>) -> Json<RuntimeOverrides> {
+    info!("Admin: rate limits set to {:?}", limits);
+    let mut overrides = write();
+    overrides.rate_limit = Some(limits);
+    Json(overrides.clone())
+}
+
+async fn reset_rate_limit() -> Json<RuntimeOverrides> {
+    info!("Admin: rate limits reset to the configured ones");
+    let mut overrides = write();
+    overrides.rate_limit = None;
+    Json(overrides.clone())
+}
+
+async fn set_allowlist(AppJson(request): AppJson<AllowlistRequest>) -> Json<RuntimeOverrides> {
+    let allowlist = request
+        .endpoints
+        .iter()
+        .map(|host| host.trim().to_ascii_lowercase())
+        .filter(|host| !host.is_empty())
+        .collect::<BTreeSet<_>>();
+    info!("Admin: allowlist set to {:?}", allowlist);
+    let mut overrides = write();
+    overrides.allowlist = Some(allowlist);
+    Json(overrides.clone())
+}
+
+async fn reset_allowlist() -> Json<RuntimeOverrides> {
+    info!("Admin: allowlist restriction lifted");
+    let mut overrides = write();
+    overrides.allowlist = None;
+    Json(overrides.clone())
+}
+
+async fn pause_feed(Path(feed_id): Path<String>) -> Result<Json<RuntimeOverrides>, EnclaveError> {
+    let feed_id = parse_feed_id(&feed_id)?;
+    info!("Admin: feed {} paused", feed_id);
+    let mut overrides = write();
+    overrides.paused_feeds.insert(feed_id.to_string());
+    Ok(Json(overrides.clone()))
+}
+
+async fn resume_feed(Path(feed_id): Path<String>) -> Result<Json<RuntimeOverrides>, EnclaveError> {
+    let feed_id = parse_feed_id(&feed_id)?;
+    info!("Admin: feed {} resumed", feed_id);
+    let mut overrides = write();
+    overrides.paused_feeds.remove(&feed_id.to_string());
+    Ok(Json(overrides.clone()))
+}
+
+async fn set_flag(
+    Path(name): Path<String>,
+    AppJson(request): AppJson<FlagRequest>,
+) -> Result<Json<RuntimeOverrides>, EnclaveError> {
+    let mut overrides = write();
+    let Some(&configured) = overrides.configured_flags.get(&name) else {
+        return Err(EnclaveError::InvalidRequest(format!(
+            "Unknown feature flag {}",
+            name
+        )));
+    };
+    if request.enabled && !configured {
+        return Err(EnclaveError::Forbidden(format!(
+            "Feature flag {} is off in the configuration, it can only be turned on there",
+            name
+        )));
+    }
+    overrides.flags.insert(name.clone(), request.enabled);
+    info!("Admin: feature flag {} set to {}", name, request.enabled);
+    Ok(Json(overrides.clone()))
+}
+
+async fn set_registration(
+    AppJson(request): AppJson<RegistrationRequest>,
+) -> Result<Json<RuntimeOverrides>, EnclaveError> {
+    let enclave_object_id = Address::from_hex(&request.enclave_object_id).map_err(|e| {
+        EnclaveError::InvalidRequest(format!(
+            "Invalid Enclave object id {}: {}",
+            request.enclave_object_id, e
+        ))
+    })?;
+    info!("Admin: Enclave object set to {}", enclave_object_id);
+    let mut overrides = write();
+    overrides.enclave_object_id = Some(enclave_object_id.to_string());
+    Ok(Json(overrides.clone()))
+}
+
+/// Middleware requiring an API key with the admin scope. Refuses every call
+/// when no keys are configured.
+pub async fn require_admin(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, EnclaveError> {
+    if state.config.auth.keys.is_empty() {
+        return Err(EnclaveError::Forbidden(
+            "The admin API needs an API key with the admin scope in [auth]".to_string(),
+        ));
+    }
+    auth::authorize(&state.config.auth, request.headers(), Scope::Admin)?;
+    Ok(next.run(request).await)
+}
+
+/// Admin routes, to be nested under `/admin`.
+pub fn router(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    let router = Router::new()
+        .route("/state", get(get_state))
+        .route("/rate_limit", put(set_rate_limit).delete(reset_rate_limit))
+        .route("/allowlist", put(set_allowlist).delete(reset_allowlist))
+        .route("/feeds/:feed_id/pause", post(pause_feed))
+        .route("/feeds/:feed_id/resume", post(resume_feed))
+        .route("/flags/:name", put(set_flag))
+        .route("/registration", put(set_registration));
+    #[cfg(feature = "profiling")]
+    let router = router.route("/profile", get(crate::profiling::get_profile));
+    router.route_layer(from_fn_with_state(state, require_admin))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::RateLimit;
+
+    /// Drop every override, as on a restart.
+    fn reset() {
+        *write() = RuntimeOverrides::default();
+    }
+
+    #[tokio::test]
+    async fn test_overrides() {
+        reset();
+        let configured = RateLimitConfig::default();
+        assert_eq!(rate_limits(&configured), configured);
+        let limits = RateLimitConfig {
+            client: Some(RateLimit {
+                burst: 1,
+                per_second: 0.5,
+            }),
+            feed: None,
+        };
+        set_rate_limit(AppJson(limits.clone())).await;
+        assert_eq!(rate_limits(&configured), limits);
+        reset_rate_limit().await;
+        assert_eq!(rate_limits(&configured), configured);
+
+        assert!(host_allowed("https://api.example.com/price"));
+        set_allowlist(AppJson(AllowlistRequest {
+            endpoints: vec!["API.example.com".to_string()],
+        }))
+        .await;
+        assert!(host_allowed("https://api.example.com/price"));
+        assert!(!host_allowed("https://other.example.com/price"));
+        reset_allowlist().await;
+        assert!(host_allowed("https://other.example.com/price"));
+
+        let feed_id = Address::from_hex("0x1").unwrap();
+        pause_feed(Path("0x1".to_string())).await.unwrap();
+        assert!(is_paused(&feed_id));
+        resume_feed(Path(feed_id.to_string())).await.unwrap();
+        assert!(!is_paused(&feed_id));
+        assert!(pause_feed(Path("feed".to_string())).await.is_err());
+
+        register_flag("test_flag", true);
+        assert!(flag_enabled("test_flag", true));
+        set_flag(
+            Path("test_flag".to_string()),
+            AppJson(FlagRequest { enabled: false }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(flag("test_flag"), Some(false));
+        assert!(!flag_enabled("test_flag", true));
+        // Registering again keeps the value set by the admin
+        register_flag("test_flag", true);
+        assert_eq!(flag("test_flag"), Some(false));
+        // Back on, as configured
+        set_flag(
+            Path("test_flag".to_string()),
+            AppJson(FlagRequest { enabled: true }),
+        )
+        .await
+        .unwrap();
+        assert!(flag_enabled("test_flag", true));
+
+        // A feature off in the configuration can't be turned on
+        register_flag("off_flag", false);
+        assert!(matches!(
+            set_flag(
+                Path("off_flag".to_string()),
+                AppJson(FlagRequest { enabled: true }),
+            )
+            .await,
+            Err(EnclaveError::Forbidden(_))
+        ));
+        assert!(!flag_enabled("off_flag", false));
+        assert!(matches!(
+            set_flag(
+                Path("unknown".to_string()),
+                AppJson(FlagRequest { enabled: true }),
+            )
+            .await,
+            Err(EnclaveError::InvalidRequest(_))
+        ));
+        reset();
+        assert_eq!(*read(), RuntimeOverrides::default());
+    }
+}
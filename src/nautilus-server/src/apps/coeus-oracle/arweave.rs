@@ -0,0 +1,289 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Feed code stored on Arweave, referenced by a `blob_id` of the form `ar://<tx id>`.
+//!
+//! The data is downloaded from a gateway and its chunk Merkle root is recomputed
+//! and compared with the `data_root` and `data_size` of the transaction header
+//! served by the same gateway. The header's signature is not verified, so this
+//! only catches data corrupted in transit or by a faulty gateway: the gateways
+//! are trusted to serve the real header. What keeps a malicious gateway from
+//! swapping the code is the feed's `blob_hash`, checked for every source
+//! before execution.
+
+use super::blob_cache::{BLOB_CACHE, CachedBlob};
+use super::config::COEUS_CONFIG;
+use super::http_body::{read_body_capped, read_json_capped};
+use crate::{EnclaveError, metrics};
+use fastcrypto::hash::{HashFunction, Sha256};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::warn;
+
+/// Blob id prefix selecting Arweave as the code source.
+pub const SCHEME: &str = "ar://";
+
+const MAX_CHUNK_SIZE: usize = 256 * 1024;
+const MIN_CHUNK_SIZE: usize = 32 * 1024;
+
+/// Largest transaction header read. Headers of format 2 transactions carry
+/// tags and the data root, the data is downloaded separately.
+const MAX_HEADER_BYTES: u64 = 64 * 1024;
+
+/// Arweave section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArweaveConfig {
+    /// Gateway base URLs in priority order. Hosts must be in allowed_endpoints.yaml.
+    #[serde(default = "default_gateways")]
+    pub gateways: Vec<String>,
+    /// Per-gateway request timeout.
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl Default for ArweaveConfig {
+    fn default() -> Self {
+        Self {
+            gateways: default_gateways(),
+            timeout_ms: default_timeout_ms(),
+        }
+    }
+}
+
+fn default_gateways() -> Vec<String> {
+    vec!["https://arweave.net".to_string()]
+}
+
+fn default_timeout_ms() -> u64 {
+    10_000
+}
+
+/// Fields of the transaction header needed to verify its data.
+#[derive(Debug, Deserialize)]
+struct TxHeader {
+    data_root: String,
+    data_size: String,
+}
+
+/// Unpadded base64url, the encoding of Arweave ids and hashes.
+pub fn decode_base64url(s: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for c in s.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'-' => 62,
+            b'_' => 63,
+            _ => return Err(format!("Invalid base64url character '{}'", c as char)),
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Ok(out)
+}
+
+fn sha256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::default();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().digest
+}
+
+/// 32 byte big-endian offset, as hashed into the Merkle tree.
+fn note(offset: usize) -> [u8; 32] {
+    let mut note = [0u8; 32];
+    note[24..].copy_from_slice(&(offset as u64).to_be_bytes());
+    note
+}
+
+/// Split data into chunks the way Arweave does: 256 KiB chunks, except that
+/// the last two are balanced if the final one would be smaller than 32 KiB.
+fn chunk_boundaries(len: usize) -> Vec<(usize, usize)> {
+    let mut chunks = Vec::new();
+    let mut cursor = 0;
+    while len - cursor >= MAX_CHUNK_SIZE {
+        let rest = len - cursor;
+        let next = rest - MAX_CHUNK_SIZE;
+        let size = if next > 0 && next < MIN_CHUNK_SIZE {
+            rest.div_ceil(2)
+        } else {
+            MAX_CHUNK_SIZE
+        };
+        chunks.push((cursor, cursor + size));
+        cursor += size;
+    }
+    chunks.push((cursor, len));
+    chunks
+}
+
+/// Merkle root of the data, as found in the `data_root` of a transaction.
+pub fn data_root(data: &[u8]) -> [u8; 32] {
+    let mut nodes: Vec<([u8; 32], usize)> = chunk_boundaries(data.len())
+        .into_iter()
+        .map(|(start, end)| {
+            let data_hash = sha256(&[&data[start..end]]);
+            let id = sha256(&[&sha256(&[&data_hash]), &sha256(&[&note(end)])]);
+            (id, end)
+        })
+        .collect();
+    while nodes.len() > 1 {
+        nodes = nodes
+            .chunks(2)
+            .map(|pair| match pair {
+                [(left, left_end), (right, right_end)] => {
+                    let id = sha256(&[
+                        &sha256(&[left]),
+                        &sha256(&[right]),
+                        &sha256(&[&note(*left_end)]),
+                    ]);
+                    (id, *right_end)
+                }
+                _ => pair[0],
+            })
+            .collect();
+    }
+    nodes[0].0
+}
+
+/// Check downloaded data against the transaction header, as served by the
+/// gateway (see the module docs).
+fn verify_data(header: &TxHeader, data: &[u8]) -> Result<(), String> {
+    let size: usize = header
+        .data_size
+        .parse()
+        .map_err(|e| format!("Invalid data_size: {}", e))?;
+    if size != data.len() {
+        return Err(format!("Expected {} bytes, got {}", size, data.len()));
+    }
+    if decode_base64url(&header.data_root)? != data_root(data) {
+        return Err("Data does not match the transaction data_root".to_string());
+    }
+    Ok(())
+}
+
+async fn fetch_from(
+    client: &reqwest::Client,
+    gateway: &str,
+    tx_id: &str,
+) -> Result<Vec<u8>, String> {
+    let gateway_url = gateway.trim_end_matches('/');
+    let response = client
+        .get(format!("{}/tx/{}", gateway_url, tx_id))
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .map_err(|e| format!("{}: {}", gateway, e))?;
+    let header: TxHeader = read_json_capped(response, MAX_HEADER_BYTES)
+        .await
+        .map_err(|e| format!("{}: invalid transaction header: {}", gateway, e))?;
+    let response = client
+        .get(format!("{}/raw/{}", gateway_url, tx_id))
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .map_err(|e| format!("{}: {}", gateway, e))?;
+    let data = read_body_capped(response, COEUS_CONFIG.walrus.max_blob_bytes)
+        .await
+        .map_err(|e| format!("{}: {}", gateway, e))?;
+    verify_data(&header, &data).map_err(|e| format!("{}: {}", gateway, e))?;
+    Ok(data)
+}
+
+/// Download and verify the data of a transaction, trying gateways in order.
+/// Transaction data is permanent, so a cached copy is always reused.
+pub async fn fetch(tx_id: &str) -> Result<Vec<u8>, EnclaveError> {
+    let cache_key = format!("{}{}", SCHEME, tx_id);
+    let cached = BLOB_CACHE.lock().await.get(&cache_key);
+    metrics::record_cache_lookup("blob", cached.is_some());
+    if let Some(cached) = cached {
+        return Ok(cached.data);
+    }
+
+    if decode_base64url(tx_id).map(|id| id.len()) != Ok(32) {
+        return Err(EnclaveError::InvalidRequest(format!(
+            "Invalid Arweave transaction id {}",
+            tx_id
+        )));
+    }
+    let config = &COEUS_CONFIG.arweave;
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_millis(config.timeout_ms))
+        .build()
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to create HTTP client: {}", e)))?;
+
+    let mut errors = Vec::new();
+    for gateway in &config.gateways {
+        match fetch_from(&client, gateway, tx_id).await {
+            Ok(data) => {
+                BLOB_CACHE.lock().await.insert(
+                    cache_key,
+                    CachedBlob {
+                        data: data.clone(),
+                        etag: None,
+                        fetched_at_ms: 0,
+                    },
+                );
+                return Ok(data);
+            }
+            Err(e) => {
+                warn!("Failed to fetch {} from Arweave: {}", tx_id, e);
+                errors.push(e);
+            }
+        }
+    }
+    Err(EnclaveError::BlobFetchFailed {
+        blob_id: cache_key,
+        message: format!("all Arweave gateways failed: {}", errors.join("; ")),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn root(data: &[u8]) -> Vec<u8> {
+        data_root(data).to_vec()
+    }
+
+    #[test]
+    fn test_data_root() {
+        let b64 = |s: &str| decode_base64url(s).unwrap();
+        assert_eq!(
+            root(b"42"),
+            b64("4QlvMMzwC3T-bqDBjPc19PdQpX9EhTrCNymLpiq2XHo")
+        );
+
+        let data: Vec<u8> = (0..600 * 1024).map(|i| (i % 251) as u8).collect();
+        assert_eq!(
+            root(&data),
+            b64("N_gS_f6fPYsmpog4LZJLEwgKVekMLOLMrdCXsi2Z_oc")
+        );
+
+        // Last chunk below MIN_CHUNK_SIZE, the final two chunks are rebalanced
+        let data: Vec<u8> = (0..256 * 1024 + 1000).map(|i| (i % 251) as u8).collect();
+        assert_eq!(chunk_boundaries(data.len()).len(), 2);
+        assert_eq!(
+            root(&data),
+            b64("bFtxR6l6BRJt4Y7utAH1mGDg4Ps969jw17_NfjGzB_Q")
+        );
+    }
+
+    #[test]
+    fn test_verify_data() {
+        let header = TxHeader {
+            data_root: "4QlvMMzwC3T-bqDBjPc19PdQpX9EhTrCNymLpiq2XHo".to_string(),
+            data_size: "2".to_string(),
+        };
+        assert!(verify_data(&header, b"42").is_ok());
+        assert!(verify_data(&header, b"43").is_err());
+        assert!(verify_data(&header, b"420").is_err());
+    }
+}
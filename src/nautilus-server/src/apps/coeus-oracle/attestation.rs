@@ -0,0 +1,267 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Cached attestation documents for GET /get_attestation and
+//! GET /bls_attestation.
+//!
+//! The document commits to the live signing key as its public key and to
+//! `public key || sha256(coeus_config.yaml) || AppConfig::hash()` as its user
+//! data, the last part covering the runtime server configuration. It is reused
+//! for `ttl_ms`, regenerated when the signing key changes (key rotation) and
+//! on `?fresh=true`. `?key=<name>` attests a per-feed key instead, see
+//! feed_keys.
+//!
+//! Verifiers poll these endpoints, and every document is an NSM round trip,
+//! so documents are served from memory while fresh. The NSM is called on a
+//! blocking thread, and concurrent requests for a stale document wait for the
+//! one being generated rather than each asking the NSM for their own. A
+//! document is tagged with its creation time, so a poll with its ETag is
+//! answered with a 304 until it is replaced (see conditional.rs).
+
+use super::conditional::Validators;
+use super::config::{COEUS_CONFIG, config_hash};
+use super::{feed_keys, rotation};
+use crate::common::attestation_with_user_data;
+use crate::{AppState, EnclaveError, metrics};
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use axum::response::Response;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{KeyPair, ToFromBytes};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::info;
+
+lazy_static::lazy_static! {
+    /// Latest document per attested key.
+    static ref ATTESTATION_CACHE: Mutex<HashMap<AttestedKey, CachedAttestation>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Key an attestation document commits to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AttestedKey {
+    /// The current (possibly rotated) signing key.
+    Signing,
+    /// A per-feed key, by name.
+    Feed(String),
+    /// The BLS key, see bls.rs.
+    Bls,
+}
+
+/// Attestation section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttestationConfig {
+    /// How long a generated document is served before a new one is requested
+    /// from the NSM. 0 generates a document on every request.
+    #[serde(default = "default_ttl_ms")]
+    pub ttl_ms: u64,
+}
+
+impl Default for AttestationConfig {
+    fn default() -> Self {
+        Self {
+            ttl_ms: default_ttl_ms(),
+        }
+    }
+}
+
+fn default_ttl_ms() -> u64 {
+    60_000
+}
+
+#[derive(Debug, Clone)]
+pub struct CachedAttestation {
+    pub public_key: Vec<u8>,
+    pub document: Vec<u8>,
+    pub created_at_ms: u64,
+}
+
+impl CachedAttestation {
+    fn is_valid_for(&self, public_key: &[u8], now_ms: u64, ttl_ms: u64) -> bool {
+        self.public_key == public_key && now_ms.saturating_sub(self.created_at_ms) < ttl_ms
+    }
+}
+
+/// Query parameters for GET /get_attestation.
+#[derive(Debug, Default, Deserialize)]
+pub struct AttestationQuery {
+    /// Skip the cache and request a new document.
+    #[serde(default)]
+    pub fresh: bool,
+    /// Name of a per-feed key to attest instead of the enclave key.
+    pub key: Option<String>,
+}
+
+/// Response for GET /get_attestation.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttestationResponse {
+    /// Attestation document serialized in Hex.
+    pub attestation: String,
+    /// Hex signing public key the document commits to.
+    pub public_key: String,
+    /// Hex SHA-256 of coeus_config.yaml, the second part of the user data.
+    pub config_hash: String,
+    /// Hex hash of the server configuration, the last part of the user data.
+    pub app_config_hash: String,
+    /// When the document was generated.
+    pub created_at_ms: u64,
+}
+
+/// User data bound into the document: public key followed by the config
+/// hashes.
+pub fn user_data(public_key: &[u8], app_config_hash: &[u8; 32]) -> Vec<u8> {
+    [public_key, config_hash().as_slice(), app_config_hash].concat()
+}
+
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Document committing to `public_key` for `key`, from the cache unless stale
+/// or `fresh` is set. Otherwise `generate` is run on a blocking thread and its
+/// document cached.
+pub async fn cached_document<F>(
+    key: AttestedKey,
+    public_key: Vec<u8>,
+    fresh: bool,
+    generate: F,
+) -> Result<CachedAttestation, EnclaveError>
+where
+    F: FnOnce(&[u8]) -> Result<Vec<u8>, EnclaveError> + Send + 'static,
+{
+    let now = now_ms();
+    // Held while generating, so a stale document is only replaced once
+    let mut cache = ATTESTATION_CACHE.lock().await;
+    let cached = cache
+        .get(&key)
+        .filter(|_| !fresh)
+        .filter(|c| c.is_valid_for(&public_key, now, COEUS_CONFIG.attestation.ttl_ms))
+        .cloned();
+    metrics::record_cache_lookup("attestation", cached.is_some());
+    if let Some(cached) = cached {
+        return Ok(cached);
+    }
+
+    info!("Generating attestation document for {:?} key", key);
+    let (public_key, document) = tokio::task::spawn_blocking(move || {
+        let document = generate(&public_key)?;
+        Ok::<_, EnclaveError>((public_key, document))
+    })
+    .await
+    .map_err(|e| EnclaveError::Internal(format!("Attestation task failed: {}", e)))??;
+    let fresh = CachedAttestation {
+        public_key,
+        document,
+        created_at_ms: now,
+    };
+    cache.insert(key, fresh.clone());
+    Ok(fresh)
+}
+
+/// Endpoint that returns an attestation committed to the current signing key,
+/// or the named feed key, and configuration, served from the cache unless
+/// stale or `fresh` is set.
+pub async fn get_attestation(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AttestationQuery>,
+    headers: HeaderMap,
+) -> Result<Response, EnclaveError> {
+    let attestation = current_attestation(state, query).await?;
+    let validators = Validators::new(attestation.created_at_ms, Some(attestation.created_at_ms));
+    if let Some(not_modified) = validators.not_modified(&headers) {
+        return Ok(not_modified);
+    }
+    Ok(validators.respond(Json(attestation)))
+}
+
+/// Attestation served by GET /get_attestation.
+pub async fn current_attestation(
+    state: Arc<AppState>,
+    query: AttestationQuery,
+) -> Result<AttestationResponse, EnclaveError> {
+    let signing_kp = match &query.key {
+        Some(name) => feed_keys::key_by_name(name)?,
+        None => rotation::signing_keys(&state).await.0,
+    };
+    let public_key = signing_kp.public().as_bytes().to_vec();
+    let app_config_hash = state.config.hash();
+    let key = match query.key {
+        Some(name) => AttestedKey::Feed(name),
+        None => AttestedKey::Signing,
+    };
+    let attestation = cached_document(key, public_key, query.fresh, move |public_key| {
+        attestation_with_user_data(public_key, Some(&user_data(public_key, &app_config_hash)))
+    })
+    .await?;
+
+    Ok(AttestationResponse {
+        attestation: Hex::encode(&attestation.document),
+        public_key: Hex::encode(&attestation.public_key),
+        config_hash: Hex::encode(config_hash()),
+        app_config_hash: Hex::encode(app_config_hash),
+        created_at_ms: attestation.created_at_ms,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cache_validity() {
+        let cached = CachedAttestation {
+            public_key: vec![1; 32],
+            document: vec![],
+            created_at_ms: 1_000,
+        };
+        assert!(cached.is_valid_for(&[1; 32], 1_500, 1_000));
+        assert!(!cached.is_valid_for(&[1; 32], 2_000, 1_000));
+        // A rotated key invalidates the document
+        assert!(!cached.is_valid_for(&[2; 32], 1_500, 1_000));
+
+        let data = user_data(&[1; 32], &[2; 32]);
+        assert_eq!(data.len(), 96);
+        assert_eq!(&data[32..64], config_hash().as_slice());
+        assert_eq!(&data[64..], &[2; 32]);
+    }
+
+    #[tokio::test]
+    async fn test_cached_document() {
+        let key = AttestedKey::Feed("test_cached_document".to_string());
+        let generate = |public_key: &[u8]| Ok::<_, EnclaveError>([public_key, &[9u8][..]].concat());
+        let first = cached_document(key.clone(), vec![1; 32], false, generate)
+            .await
+            .unwrap();
+        assert_eq!(first.document, [&[1u8; 32][..], &[9u8][..]].concat());
+
+        // Served from memory, without generating again
+        let cached = cached_document(key.clone(), vec![1; 32], false, |_| {
+            panic!("Document generated again")
+        })
+        .await
+        .unwrap();
+        assert_eq!(cached.document, first.document);
+
+        // A new key, fresh or a failed generation are not served from the cache
+        let rotated = cached_document(key.clone(), vec![2; 32], false, generate)
+            .await
+            .unwrap();
+        assert_eq!(rotated.public_key, vec![2; 32]);
+        let failed = cached_document(key.clone(), vec![2; 32], true, |_| {
+            Err(EnclaveError::GenericError("NSM unavailable".to_string()))
+        })
+        .await;
+        assert!(failed.is_err());
+        let cached = cached_document(key, vec![2; 32], false, |_| panic!("Not cached"))
+            .await
+            .unwrap();
+        assert_eq!(cached.public_key, vec![2; 32]);
+    }
+}
@@ -0,0 +1,306 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Append-only, hash-chained log of every payload the enclave signs.
+//!
+//! Every signature path appends to it: results under each of their keys
+//! (current, previous in grace and BLS), result metadata, feed state,
+//! execute_code, sign_digest and key rotation handoffs. Only the signed head
+//! itself is not logged, it is a signature over the log.
+//!
+//! Each entry commits to the digest of the signed intent message, the signing
+//! public key, the feed and the timestamp, and to the previous head:
+//! `head = sha256(prev_head || bcs(AuditRecord))`, starting from 32 zero
+//! bytes. With `sign_head` enabled, every process_data response carries the
+//! head after its own entry, signed under IntentScope::AuditLog. Two signed
+//! heads with the same `seq` but different hashes, or a signed result missing
+//! from the published log, prove the operator equivocated.
+//!
+//! The log is held in memory: at most `capacity` entries, itself capped at
+//! MAX_CAPACITY, and GET /audit/log returns at most MAX_PAGE entries at a time.
+
+use super::config::COEUS_CONFIG;
+use crate::EnclaveError;
+use crate::admin;
+use crate::common::IntentMessage;
+use axum::Json;
+use axum::extract::Query;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hash::{HashFunction, Sha256};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::mem::size_of;
+use sui_sdk_types::Address;
+use tokio::sync::RwLock;
+use utoipa::ToSchema;
+
+lazy_static::lazy_static! {
+    static ref AUDIT_LOG: RwLock<AuditLog> = RwLock::new(AuditLog::default());
+}
+
+/// Audit section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditConfig {
+    /// Entries kept in memory for GET /audit/log. The head covers all entries
+    /// ever appended, evicted ones included.
+    #[serde(default = "default_capacity")]
+    pub capacity: usize,
+    /// Add the signed log head to every process_data response.
+    #[serde(default)]
+    pub sign_head: bool,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            capacity: default_capacity(),
+            sign_head: false,
+        }
+    }
+}
+
+fn default_capacity() -> usize {
+    10_000
+}
+
+/// Upper bound on `capacity`, whatever coeus_config.yaml says.
+pub const MAX_CAPACITY: usize = 100_000;
+
+/// Most entries returned by one GET /audit/log.
+pub const MAX_PAGE: usize = 1_000;
+
+/// Entries actually retained.
+fn capacity(config: &AuditConfig) -> usize {
+    config.capacity.min(MAX_CAPACITY)
+}
+
+/// The hashed part of an entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AuditRecord {
+    seq: u64,
+    feed_id: Option<Address>,
+    intent: u8,
+    timestamp_ms: u64,
+    digest: [u8; 32],
+    signer: Vec<u8>,
+}
+
+/// Log entry as served by GET /audit/log, hashes in Hex.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub feed_id: Option<Address>,
+    pub intent: u8,
+    pub timestamp_ms: u64,
+    /// SHA-256 of the BCS bytes of the signed intent message.
+    pub digest: String,
+    /// Hex public key the message was signed with.
+    pub signer: String,
+    pub head: String,
+}
+
+/// Position of the log, signed into responses when `sign_head` is enabled.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuditHead {
+    /// Number of entries appended so far.
+    pub seq: u64,
+    pub head: Vec<u8>,
+}
+
+/// Signed log head returned next to a process_data result.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AuditHeadSection {
+    pub seq: u64,
+    /// Hex head after the result's entry.
+    pub head: String,
+    /// Hex signature over IntentMessage<AuditHead> with the result's timestamp.
+    pub signature: String,
+}
+
+#[derive(Default)]
+struct AuditLog {
+    seq: u64,
+    head: [u8; 32],
+    entries: VecDeque<AuditEntry>,
+}
+
+fn chain(prev_head: &[u8; 32], record: &AuditRecord) -> [u8; 32] {
+    let mut hasher = Sha256::default();
+    hasher.update(prev_head);
+    hasher.update(bcs::to_bytes(record).expect("should not fail"));
+    hasher.finalize().digest
+}
+
+impl AuditLog {
+    fn append(&mut self, record: AuditRecord, capacity: usize) -> AuditHead {
+        self.head = chain(&self.head, &record);
+        self.seq = record.seq + 1;
+        if capacity > 0 {
+            while self.entries.len() >= capacity {
+                self.entries.pop_front();
+            }
+            self.entries.push_back(AuditEntry {
+                seq: record.seq,
+                feed_id: record.feed_id,
+                intent: record.intent,
+                timestamp_ms: record.timestamp_ms,
+                digest: Hex::encode(record.digest),
+                signer: Hex::encode(&record.signer),
+                head: Hex::encode(self.head),
+            });
+        }
+        AuditHead {
+            seq: self.seq,
+            head: self.head.to_vec(),
+        }
+    }
+}
+
+/// Append the intent message signed by `signer` to the log and return the
+/// new head.
+pub async fn record<T: Serialize + Debug>(
+    feed_id: Option<Address>,
+    signer: &[u8],
+    intent_msg: &IntentMessage<T>,
+) -> AuditHead {
+    let digest = Sha256::digest(bcs::to_bytes(intent_msg).expect("should not fail")).digest;
+    let mut log = AUDIT_LOG.write().await;
+    let record = AuditRecord {
+        seq: log.seq,
+        feed_id,
+        intent: intent_msg.intent as u8,
+        timestamp_ms: intent_msg.timestamp_ms,
+        digest,
+        signer: signer.to_vec(),
+    };
+    log.append(record, capacity(&COEUS_CONFIG.audit))
+}
+
+/// Approximate bytes held by the retained entries.
+pub async fn retained_bytes() -> u64 {
+    AUDIT_LOG
+        .read()
+        .await
+        .entries
+        .iter()
+        .map(|entry| {
+            size_of::<AuditEntry>() + entry.digest.len() + entry.signer.len() + entry.head.len()
+        })
+        .sum::<usize>() as u64
+}
+
+/// Admin feature flag turning off `audit.sign_head`.
+pub const SIGN_HEAD_FLAG: &str = "audit_sign_head";
+
+/// Whether process_data responses carry the signed log head.
+pub fn sign_head() -> bool {
+    admin::flag_enabled(SIGN_HEAD_FLAG, COEUS_CONFIG.audit.sign_head)
+}
+
+/// Response for GET /audit/head.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditHeadResponse {
+    pub seq: u64,
+    pub head: String,
+    /// Oldest entry still served by GET /audit/log.
+    pub first_retained_seq: Option<u64>,
+}
+
+/// Query parameters for GET /audit/log.
+#[derive(Debug, Deserialize)]
+pub struct AuditLogQuery {
+    /// First entry to return.
+    #[serde(default)]
+    pub from: u64,
+    /// Entries to return, at most and by default MAX_PAGE.
+    pub limit: Option<usize>,
+}
+
+/// Endpoint returning the current head of the audit log.
+pub async fn get_audit_head() -> Json<AuditHeadResponse> {
+    let log = AUDIT_LOG.read().await;
+    Json(AuditHeadResponse {
+        seq: log.seq,
+        head: Hex::encode(log.head),
+        first_retained_seq: log.entries.front().map(|entry| entry.seq),
+    })
+}
+
+/// Endpoint returning retained entries from `from` on, oldest first.
+pub async fn get_audit_log(
+    Query(query): Query<AuditLogQuery>,
+) -> Result<Json<Vec<AuditEntry>>, EnclaveError> {
+    let log = AUDIT_LOG.read().await;
+    let first_retained = log.entries.front().map(|entry| entry.seq);
+    if let Some(first) = first_retained.filter(|&first| query.from < first) {
+        return Err(EnclaveError::GenericError(format!(
+            "Entries before {} are no longer retained",
+            first
+        )));
+    }
+    Ok(Json(
+        log.entries
+            .iter()
+            .filter(|entry| entry.seq >= query.from)
+            .take(query.limit.unwrap_or(MAX_PAGE).min(MAX_PAGE))
+            .cloned()
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(seq: u64, digest: u8) -> AuditRecord {
+        AuditRecord {
+            seq,
+            feed_id: None,
+            intent: 0,
+            timestamp_ms: 1744038900000 + seq,
+            digest: [digest; 32],
+            signer: vec![1; 32],
+        }
+    }
+
+    #[test]
+    fn test_hash_chain() {
+        let mut log = AuditLog::default();
+        let mut head = [0u8; 32];
+        for seq in 0..5 {
+            let appended = log.append(record(seq, seq as u8), 3);
+            head = chain(&head, &record(seq, seq as u8));
+            assert_eq!(appended.head, head.to_vec());
+            assert_eq!(appended.seq, seq + 1);
+        }
+        // Only the last entries are retained, the head covers all of them
+        assert_eq!(log.entries.len(), 3);
+        assert_eq!(log.entries.front().unwrap().seq, 2);
+        assert_eq!(log.entries.back().unwrap().head, Hex::encode(head));
+
+        // A different entry at the same position forks the chain
+        let mut forked = AuditLog::default();
+        for seq in 0..4 {
+            forked.append(record(seq, seq as u8), 3);
+        }
+        assert_ne!(forked.append(record(4, 42), 3).head, head.to_vec());
+
+        // So does the same message signed with another key
+        let mut other_signer = AuditLog::default();
+        for seq in 0..4 {
+            other_signer.append(record(seq, seq as u8), 3);
+        }
+        let resigned = AuditRecord {
+            signer: vec![2; 32],
+            ..record(4, 4)
+        };
+        assert_ne!(other_signer.append(resigned, 3).head, head.to_vec());
+
+        let config = AuditConfig {
+            capacity: usize::MAX,
+            sign_head: false,
+        };
+        assert_eq!(capacity(&config), MAX_CAPACITY);
+    }
+}
@@ -0,0 +1,470 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Runs of several feeds at once.
+//!
+//! `/process_batch` takes up to `max_feeds` process_data requests and runs
+//! them as tasks, `max_concurrent` at a time, each for up to
+//! `feed_timeout_ms` once it has started. An entry that fails, times out or
+//! panics only fails itself: the response lists every entry in request order
+//! with either its result or its error, so one dead upstream does not cost
+//! the caller the other feeds. With `Accept: application/x-ndjson` entries
+//! are instead streamed as they complete, one per line with their index in
+//! the batch (see ndjson.rs), and a run only starts while the caller is still
+//! reading.
+//!
+//! A batch answered as a whole must complete within the `/process_batch`
+//! route timeout (see `[timeouts]` in nautilus.toml). Its runs take up to
+//! ceil(requests / max_concurrent) * feed_timeout_ms, a batch that could
+//! take longer is refused up front rather than cut off with its results
+//! lost. Streamed batches answer at once and are not bound by it.
+//!
+//! Background runs of the scheduler and of on-chain update requests share
+//! `max_concurrent` slots of their own and the same per-feed timeout, so feeds
+//! scheduled on the same interval do not all start at once and a stuck run
+//! does not hold its feed forever. Scheduled runs wait for a slot, update
+//! requests are dropped when none is free. Scripts still take an execution
+//! slot (see concurrency.rs) on top of these bounds.
+
+use super::config::COEUS_CONFIG;
+use super::encryption::ResultOutput;
+use super::{UpdateOracleRequest, ndjson, openapi, process_request};
+use crate::extract::AppJson;
+use crate::{AppState, EnclaveError, auth, catch_panic, request_id};
+use axum::Json;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, mpsc};
+use tokio::task::JoinHandle;
+use tokio_stream::StreamExt;
+use tokio_stream::wrappers::ReceiverStream;
+use utoipa::ToSchema;
+
+lazy_static::lazy_static! {
+    /// Slots of background feed runs.
+    static ref BACKGROUND_RUNS: Arc<Semaphore> =
+        Arc::new(Semaphore::new(COEUS_CONFIG.batch.max_concurrent.max(1)));
+}
+
+/// Batch section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchConfig {
+    /// Feeds of a batch, or background runs, running at once.
+    #[serde(default = "default_max_concurrent")]
+    pub max_concurrent: usize,
+    /// Longest run of a single feed.
+    #[serde(default = "default_feed_timeout_ms")]
+    pub feed_timeout_ms: u64,
+    /// Most requests in one batch.
+    #[serde(default = "default_max_feeds")]
+    pub max_feeds: usize,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: default_max_concurrent(),
+            feed_timeout_ms: default_feed_timeout_ms(),
+            max_feeds: default_max_feeds(),
+        }
+    }
+}
+
+fn default_max_concurrent() -> usize {
+    4
+}
+
+fn default_feed_timeout_ms() -> u64 {
+    30_000
+}
+
+fn default_max_feeds() -> usize {
+    32
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BatchRequest {
+    pub requests: Vec<UpdateOracleRequest>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BatchResponse {
+    /// One entry per request, in request order.
+    pub results: Vec<BatchEntry>,
+    pub succeeded: usize,
+    pub failed: usize,
+}
+
+/// Outcome of one request of a batch, a result or an error.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BatchEntry {
+    /// Position of the request in the batch.
+    pub index: usize,
+    /// Feed id of the request, or its registry id.
+    pub feed_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<ResultOutput>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<BatchError>,
+}
+
+/// Error of an entry that was not signed, as it would have been answered by
+/// process_data: status 200 for a value the update policy skipped.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BatchError {
+    pub status: u16,
+    /// Stable error code, see ProblemDetails.
+    pub code: String,
+    pub detail: String,
+}
+
+impl From<EnclaveError> for BatchError {
+    fn from(error: EnclaveError) -> Self {
+        Self {
+            status: error.status().as_u16(),
+            code: error.code().to_string(),
+            detail: error.to_string(),
+        }
+    }
+}
+
+/// Longest `requests` runs can take, going `max_concurrent` at a time for up
+/// to `feed_timeout_ms` each.
+fn longest_batch(config: &BatchConfig, requests: usize) -> Duration {
+    let waves = requests.div_ceil(config.max_concurrent.max(1)) as u64;
+    Duration::from_millis(waves.saturating_mul(config.feed_timeout_ms))
+}
+
+/// `run`, failing with a timeout if it takes longer than `timeout`.
+async fn within<T>(
+    timeout: Duration,
+    run: impl Future<Output = Result<T, EnclaveError>>,
+) -> Result<T, EnclaveError> {
+    tokio::time::timeout(timeout, run)
+        .await
+        .unwrap_or_else(|_| {
+            Err(EnclaveError::RequestTimeout {
+                timeout_ms: timeout.as_millis() as u64,
+            })
+        })
+}
+
+/// Spawn `future` as the request `id`, tasks do not inherit it.
+fn spawn_as<F>(id: Option<String>, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match id {
+        Some(id) => tokio::spawn(request_id::scope(id, future)),
+        None => tokio::spawn(future),
+    }
+}
+
+/// Run `runs` as tasks, at most `max_concurrent` at once and each for up to
+/// `timeout`. The outcome of each run is sent with its index as it completes.
+/// Runs not started once the receiver is dropped are skipped.
+pub fn spawn_bounded<T, F>(
+    runs: Vec<F>,
+    max_concurrent: usize,
+    timeout: Duration,
+) -> mpsc::Receiver<(usize, Result<T, EnclaveError>)>
+where
+    F: Future<Output = Result<T, EnclaveError>> + Send + 'static,
+    T: Send + 'static,
+{
+    let slots = Arc::new(Semaphore::new(max_concurrent.max(1)));
+    let request_id = request_id::current();
+    // Outcomes wait for the receiver, so a slow reader holds back the runs
+    // instead of piling up results
+    let (sender, receiver) = mpsc::channel(max_concurrent.max(1));
+    for (index, run) in runs.into_iter().enumerate() {
+        let slots = slots.clone();
+        let sender = sender.clone();
+        let id = request_id.clone();
+        spawn_as(request_id.clone(), async move {
+            let slot = slots
+                .acquire_owned()
+                .await
+                .expect("Batch slots are never closed");
+            if sender.is_closed() {
+                return;
+            }
+            // On its own task so a panic fails this run, not the batch
+            let run = spawn_as(id, within(timeout, run));
+            let result = run.await.unwrap_or_else(|e| match e.try_into_panic() {
+                Ok(panic) => Err(catch_panic::record("batch", panic.as_ref())),
+                Err(e) => Err(EnclaveError::Internal(e.to_string())),
+            });
+            drop(slot);
+            let _ = sender.send((index, result)).await;
+        });
+    }
+    receiver
+}
+
+/// Run `runs` as with [`spawn_bounded`], returning their results in the order
+/// of `runs`.
+pub async fn run_bounded<T, F>(
+    runs: Vec<F>,
+    max_concurrent: usize,
+    timeout: Duration,
+) -> Vec<Result<T, EnclaveError>>
+where
+    F: Future<Output = Result<T, EnclaveError>> + Send + 'static,
+    T: Send + 'static,
+{
+    let mut results: Vec<Option<Result<T, EnclaveError>>> = runs.iter().map(|_| None).collect();
+    let mut outcomes = spawn_bounded(runs, max_concurrent, timeout);
+    while let Some((index, result)) = outcomes.recv().await {
+        results[index] = Some(result);
+    }
+    results
+        .into_iter()
+        .map(|result| {
+            result.unwrap_or_else(|| {
+                Err(EnclaveError::Internal(
+                    "Batch entry did not complete".to_string(),
+                ))
+            })
+        })
+        .collect()
+}
+
+/// A background slot, free again when dropped.
+pub type BackgroundSlot = OwnedSemaphorePermit;
+
+/// Wait for a background slot.
+pub async fn background_slot() -> BackgroundSlot {
+    BACKGROUND_RUNS
+        .clone()
+        .acquire_owned()
+        .await
+        .expect("Background slots are never closed")
+}
+
+/// A background slot if one is free, None when all are busy.
+pub fn try_background_slot() -> Option<BackgroundSlot> {
+    BACKGROUND_RUNS.clone().try_acquire_owned().ok()
+}
+
+/// Run a background feed run in `slot`, for up to the per-feed timeout.
+pub async fn background<T>(
+    slot: BackgroundSlot,
+    run: impl Future<Output = Result<T, EnclaveError>>,
+) -> Result<T, EnclaveError> {
+    let _slot = slot;
+    within(
+        Duration::from_millis(COEUS_CONFIG.batch.feed_timeout_ms),
+        run,
+    )
+    .await
+}
+
+/// Run several feeds and sign their results, reporting each one's outcome.
+#[utoipa::path(
+    post,
+    path = "/process_batch",
+    request_body = BatchRequest,
+    responses(
+        (status = 200, description = "Result or error of every request, in request order. With Accept: application/x-ndjson, one BatchEntry per line in completion order", body = BatchResponse),
+        (status = 400, description = "Empty batch, too many requests, a batch that could outlast the route timeout or malformed body", body = openapi::ProblemDetails),
+        (status = 413, description = "Body over the route's body limit", body = openapi::ProblemDetails),
+    )
+)]
+pub async fn process_batch(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    AppJson(batch): AppJson<BatchRequest>,
+) -> Result<Response, EnclaveError> {
+    let config = &COEUS_CONFIG.batch;
+    if batch.requests.is_empty() || batch.requests.len() > config.max_feeds {
+        return Err(EnclaveError::InvalidRequest(format!(
+            "A batch takes 1 to {} requests, got {}",
+            config.max_feeds,
+            batch.requests.len()
+        )));
+    }
+    let streamed = ndjson::accepts_ndjson(&headers);
+    let route_timeout = state.config.timeouts.timeout("/process_batch");
+    let longest = longest_batch(config, batch.requests.len());
+    if !streamed && longest > route_timeout {
+        return Err(EnclaveError::InvalidRequest(format!(
+            "A batch of {} requests may take {}ms, over the {}ms timeout of /process_batch. \
+             Send at most {} requests or stream the results with Accept: application/x-ndjson",
+            batch.requests.len(),
+            longest.as_millis(),
+            route_timeout.as_millis(),
+            max_requests(config, route_timeout),
+        )));
+    }
+    let requester = auth::identify(&state.config.auth, &headers).map(|key| key.name.clone());
+    let feed_ids: Vec<String> = batch
+        .requests
+        .iter()
+        .map(|request| {
+            Some(request.feed_id.clone())
+                .filter(|feed_id| !feed_id.is_empty())
+                .or_else(|| request.registry_id.clone())
+                .unwrap_or_default()
+        })
+        .collect();
+    let runs = batch
+        .requests
+        .into_iter()
+        .map(|request| {
+            let state = state.clone();
+            let requester = requester.clone();
+            async move { process_request("process_batch", &state, requester, &request).await }
+        })
+        .collect();
+    let timeout = Duration::from_millis(config.feed_timeout_ms);
+    if streamed {
+        let outcomes = spawn_bounded(runs, config.max_concurrent, timeout);
+        let entries = ReceiverStream::new(outcomes)
+            .map(move |(index, result)| entry(index, feed_ids[index].clone(), result));
+        return Ok(ndjson::stream(entries));
+    }
+    let results = run_bounded(runs, config.max_concurrent, timeout).await;
+    Ok(Json(to_response(feed_ids, results)).into_response())
+}
+
+/// Most requests of a batch that completes within `route_timeout`.
+fn max_requests(config: &BatchConfig, route_timeout: Duration) -> usize {
+    let waves = route_timeout.as_millis() as u64 / config.feed_timeout_ms.max(1);
+    (waves as usize).saturating_mul(config.max_concurrent.max(1))
+}
+
+fn entry(index: usize, feed_id: String, result: Result<ResultOutput, EnclaveError>) -> BatchEntry {
+    match result {
+        Ok(output) => BatchEntry {
+            index,
+            feed_id,
+            result: Some(output),
+            error: None,
+        },
+        Err(e) => BatchEntry {
+            index,
+            feed_id,
+            result: None,
+            error: Some(e.into()),
+        },
+    }
+}
+
+fn to_response(
+    feed_ids: Vec<String>,
+    results: Vec<Result<ResultOutput, EnclaveError>>,
+) -> BatchResponse {
+    let results: Vec<BatchEntry> = feed_ids
+        .into_iter()
+        .zip(results)
+        .enumerate()
+        .map(|(index, (feed_id, result))| entry(index, feed_id, result))
+        .collect();
+    let succeeded = results
+        .iter()
+        .filter(|entry| entry.result.is_some())
+        .count();
+    BatchResponse {
+        failed: results.len() - succeeded,
+        succeeded,
+        results,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_run_bounded() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let runs: Vec<_> = (0..6u64)
+            .map(|i| {
+                let running = running.clone();
+                let peak = peak.clone();
+                async move {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    let delay = if i == 4 { 10_000 } else { 20 };
+                    tokio::time::sleep(Duration::from_millis(delay)).await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    match i {
+                        2 => Err(EnclaveError::InvalidRequest("bad feed".to_string())),
+                        3 => panic!("script blew up"),
+                        _ => Ok(i),
+                    }
+                }
+            })
+            .collect();
+        let results = run_bounded(runs, 2, Duration::from_millis(500)).await;
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+
+        // Partial successes are kept, in request order
+        assert_eq!(results.len(), 6);
+        assert_eq!(results[0].as_ref().unwrap(), &0);
+        assert_eq!(results[1].as_ref().unwrap(), &1);
+        assert_eq!(results[5].as_ref().unwrap(), &5);
+        assert!(matches!(results[2], Err(EnclaveError::InvalidRequest(_))));
+        assert!(matches!(results[3], Err(EnclaveError::Internal(_))));
+        assert!(matches!(
+            results[4],
+            Err(EnclaveError::RequestTimeout { timeout_ms: 500 })
+        ));
+
+        // Streamed outcomes come in completion order, with their index
+        let runs: Vec<_> = [50u64, 0]
+            .into_iter()
+            .map(|delay| async move {
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                Ok(delay)
+            })
+            .collect();
+        let mut outcomes = spawn_bounded(runs, 2, Duration::from_millis(500));
+        assert_eq!(outcomes.recv().await.unwrap().0, 1);
+        assert_eq!(outcomes.recv().await.unwrap().0, 0);
+        assert!(outcomes.recv().await.is_none());
+
+        let response = to_response(
+            vec!["0x1".to_string(), "0x2".to_string()],
+            vec![
+                Err(EnclaveError::InvalidRequest("bad feed".to_string())),
+                Err(EnclaveError::RequestTimeout { timeout_ms: 500 }),
+            ],
+        );
+        assert_eq!((response.succeeded, response.failed), (0, 2));
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["results"][1]["index"], 1);
+        assert_eq!(json["results"][1]["feed_id"], "0x2");
+        assert_eq!(json["results"][1]["error"]["status"], 504);
+        assert!(json["results"][1].get("result").is_none());
+    }
+
+    #[test]
+    fn test_longest_batch() {
+        let config = BatchConfig {
+            max_concurrent: 4,
+            feed_timeout_ms: 30_000,
+            max_feeds: 32,
+        };
+        assert_eq!(longest_batch(&config, 1), Duration::from_millis(30_000));
+        assert_eq!(longest_batch(&config, 4), Duration::from_millis(30_000));
+        assert_eq!(longest_batch(&config, 5), Duration::from_millis(60_000));
+        assert_eq!(longest_batch(&config, 32), Duration::from_millis(240_000));
+        assert_eq!(max_requests(&config, Duration::from_millis(120_000)), 16);
+        assert!(longest_batch(&config, 16) <= Duration::from_millis(120_000));
+        assert!(longest_batch(&config, 17) > Duration::from_millis(120_000));
+        // The default route timeout fits the largest batch
+        let timeouts = crate::config::TimeoutsConfig::default();
+        let route_timeout = timeouts.timeout("/process_batch");
+        assert!(longest_batch(&BatchConfig::default(), default_max_feeds()) < route_timeout);
+    }
+}
@@ -0,0 +1,169 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::config::COEUS_CONFIG;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+lazy_static::lazy_static! {
+    /// Downloaded Walrus blobs keyed by blob id.
+    pub static ref BLOB_CACHE: Arc<Mutex<LruCache<String, CachedBlob>>> =
+        Arc::new(Mutex::new(LruCache::new(COEUS_CONFIG.blob_cache.capacity)));
+}
+
+/// Blob cache section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlobCacheConfig {
+    /// Maximum number of blobs kept, 0 disables the cache.
+    #[serde(default = "default_capacity")]
+    pub capacity: usize,
+    /// Age after which a cached blob is revalidated with a conditional request.
+    #[serde(default = "default_max_age_ms")]
+    pub max_age_ms: u64,
+    /// Compiled scripts kept by each script worker, 0 disables the cache.
+    #[serde(default = "default_ast_capacity")]
+    pub ast_capacity: usize,
+    /// Age after which a script is compiled again.
+    #[serde(default = "default_ast_max_age_ms")]
+    pub ast_max_age_ms: u64,
+}
+
+impl Default for BlobCacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: default_capacity(),
+            max_age_ms: default_max_age_ms(),
+            ast_capacity: default_ast_capacity(),
+            ast_max_age_ms: default_ast_max_age_ms(),
+        }
+    }
+}
+
+fn default_capacity() -> usize {
+    64
+}
+
+fn default_max_age_ms() -> u64 {
+    300_000
+}
+
+fn default_ast_capacity() -> usize {
+    32
+}
+
+fn default_ast_max_age_ms() -> u64 {
+    3_600_000
+}
+
+#[derive(Debug, Clone)]
+pub struct CachedBlob {
+    pub data: Vec<u8>,
+    /// ETag returned by the aggregator, sent back as If-None-Match.
+    pub etag: Option<String>,
+    pub fetched_at_ms: u64,
+}
+
+impl CachedBlob {
+    pub fn is_fresh(&self, now_ms: u64) -> bool {
+        now_ms.saturating_sub(self.fetched_at_ms) < COEUS_CONFIG.blob_cache.max_age_ms
+    }
+}
+
+/// Minimal least-recently-used map with a fixed number of entries.
+#[derive(Debug)]
+pub struct LruCache<K, V> {
+    capacity: usize,
+    clock: u64,
+    entries: HashMap<K, (u64, V)>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            clock: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Get a copy of the value and mark it as recently used.
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.entries.get_mut(key).map(|(used, value)| {
+            *used = clock;
+            value.clone()
+        })
+    }
+
+    /// Insert a value, evicting the least recently used entry when full.
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            self.pop_lru();
+        }
+        self.clock += 1;
+        self.entries.insert(key, (self.clock, value));
+    }
+
+    /// Remove and return the least recently used entry.
+    pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        let oldest = self
+            .entries
+            .iter()
+            .min_by_key(|(_, (used, _))| *used)
+            .map(|(k, _)| k.clone())?;
+        self.entries
+            .remove(&oldest)
+            .map(|(_, value)| (oldest, value))
+    }
+
+    /// Cached values, in no particular order.
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.values().map(|(_, value)| value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lru_eviction() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        // Touch "a" so "b" becomes the least recently used
+        assert_eq!(cache.get(&"a"), Some(1));
+        cache.insert("c", 3);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert_eq!(cache.get(&"c"), Some(3));
+
+        // Updating an existing key does not evict
+        cache.insert("c", 4);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&"c"), Some(4));
+
+        assert_eq!(cache.pop_lru(), Some(("a", 1)));
+        assert_eq!(cache.values().collect::<Vec<_>>(), vec![&4]);
+
+        let mut disabled = LruCache::new(0);
+        disabled.insert("a", 1);
+        assert!(disabled.is_empty());
+    }
+}
@@ -0,0 +1,180 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! BLS12-381 (min_sig) signatures over the same intent message as the Ed25519
+//! signature. Signatures from several enclave instances over one payload can
+//! be aggregated into a single 48 byte signature, verified on-chain with one
+//! `bls12381_min_sig_verify` against the sum of the signers' public keys.
+//!
+//! The key is generated at startup like the ephemeral Ed25519 key and is bound
+//! to the enclave by its own attestation, served at /bls_attestation from the
+//! attestation cache (see attestation.rs).
+
+use super::attestation::{AttestedKey, cached_document};
+use super::conditional::Validators;
+use super::config::COEUS_CONFIG;
+use crate::EnclaveError;
+use crate::admin;
+use crate::common::{IntentMessage, attestation_for, enclave_rng};
+use crate::extract::AppJson;
+use axum::Json;
+use axum::http::HeaderMap;
+use axum::response::Response;
+use fastcrypto::bls12381::min_sig::{
+    BLS12381AggregateSignature, BLS12381KeyPair, BLS12381PublicKey, BLS12381Signature,
+};
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{AggregateAuthenticator, KeyPair, Signer, ToFromBytes};
+use serde::{Deserialize, Serialize};
+
+lazy_static::lazy_static! {
+    /// BLS key of this enclave instance, generated once per process.
+    pub static ref BLS_KP: BLS12381KeyPair = BLS12381KeyPair::generate(&mut enclave_rng());
+}
+
+/// BLS section of coeus_config.yaml.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BlsConfig {
+    /// Add a BLS signature to every process_data response.
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// Response for GET /bls_attestation.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlsAttestationResponse {
+    /// Hex BLS public key (96 bytes, G2).
+    pub public_key: String,
+    /// Hex attestation document committing to the BLS public key.
+    pub attestation: String,
+    /// When the document was generated.
+    pub created_at_ms: u64,
+}
+
+/// Request for POST /bls/aggregate.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AggregateRequest {
+    /// Hex BLS signatures over the same message.
+    pub signatures: Vec<String>,
+}
+
+/// Response for POST /bls/aggregate.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AggregateResponse {
+    /// Hex aggregated signature (48 bytes, G1).
+    pub signature: String,
+}
+
+/// Hex BLS signature over the BCS bytes of an intent message.
+pub fn sign_intent<T: Serialize>(intent_msg: &IntentMessage<T>) -> String {
+    let signing_payload = bcs::to_bytes(intent_msg).expect("should not fail");
+    Hex::encode(BLS_KP.sign(&signing_payload))
+}
+
+/// Aggregate hex signatures into one hex signature. Signatures are only
+/// decoded and added, callers verify the aggregate against the signers' keys.
+pub fn aggregate(signatures: &[String]) -> Result<String, EnclaveError> {
+    if signatures.is_empty() {
+        return Err(EnclaveError::GenericError(
+            "No signatures to aggregate".to_string(),
+        ));
+    }
+    let signatures = signatures
+        .iter()
+        .map(|sig| {
+            Hex::decode(sig)
+                .ok()
+                .and_then(|bytes| BLS12381Signature::from_bytes(&bytes).ok())
+                .ok_or_else(|| EnclaveError::GenericError(format!("Invalid BLS signature {}", sig)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let aggregated = BLS12381AggregateSignature::aggregate(&signatures)
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to aggregate: {}", e)))?;
+    Ok(Hex::encode(aggregated.as_bytes()))
+}
+
+/// Verify an aggregated hex signature over `message` by all of `public_keys`.
+pub fn verify_aggregate(
+    signature: &str,
+    public_keys: &[BLS12381PublicKey],
+    message: &[u8],
+) -> Result<(), EnclaveError> {
+    let signature = Hex::decode(signature)
+        .ok()
+        .and_then(|bytes| BLS12381AggregateSignature::from_bytes(&bytes).ok())
+        .ok_or_else(|| EnclaveError::GenericError("Invalid aggregated signature".to_string()))?;
+    signature
+        .verify(public_keys, message)
+        .map_err(|e| EnclaveError::GenericError(format!("Invalid aggregated signature: {}", e)))
+}
+
+/// Admin feature flag turning off `bls.enabled`.
+pub const FLAG: &str = "bls";
+
+/// Whether process_data responses carry a BLS signature.
+pub fn enabled() -> bool {
+    admin::flag_enabled(FLAG, COEUS_CONFIG.bls.enabled)
+}
+
+/// Endpoint returning the BLS public key and an attestation committed to it,
+/// used to register the instance as an attestor on-chain.
+pub async fn get_bls_attestation(headers: HeaderMap) -> Result<Response, EnclaveError> {
+    let public_key = BLS_KP.public().as_bytes().to_vec();
+    let attestation = cached_document(AttestedKey::Bls, public_key, false, attestation_for).await?;
+    let validators = Validators::new(attestation.created_at_ms, Some(attestation.created_at_ms));
+    if let Some(not_modified) = validators.not_modified(&headers) {
+        return Ok(not_modified);
+    }
+    Ok(validators.respond(Json(BlsAttestationResponse {
+        public_key: Hex::encode(&attestation.public_key),
+        attestation: Hex::encode(&attestation.document),
+        created_at_ms: attestation.created_at_ms,
+    })))
+}
+
+/// Endpoint aggregating signatures collected from several instances.
+pub async fn aggregate_bls_signatures(
+    AppJson(request): AppJson<AggregateRequest>,
+) -> Result<Json<AggregateResponse>, EnclaveError> {
+    Ok(Json(AggregateResponse {
+        signature: aggregate(&request.signatures)?,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::app::APP_ID;
+    use crate::common::IntentScope;
+
+    #[test]
+    fn test_aggregate() {
+        let message = IntentMessage::new(42u64, 1744038900000, IntentScope::ProcessData, APP_ID);
+        let signing_payload = bcs::to_bytes(&message).unwrap();
+        let signers: Vec<BLS12381KeyPair> = (0..3)
+            .map(|_| BLS12381KeyPair::generate(&mut rand::thread_rng()))
+            .collect();
+        let signatures: Vec<String> = signers
+            .iter()
+            .map(|kp| Hex::encode(kp.sign(&signing_payload)))
+            .collect();
+        let public_keys: Vec<BLS12381PublicKey> =
+            signers.iter().map(|kp| kp.public().clone()).collect();
+
+        let aggregated = aggregate(&signatures).unwrap();
+        assert!(verify_aggregate(&aggregated, &public_keys, &signing_payload).is_ok());
+        assert!(verify_aggregate(&aggregated, &public_keys[..2], &signing_payload).is_err());
+        assert!(verify_aggregate(&aggregated, &public_keys, b"other").is_err());
+
+        // This instance's signature aggregates with the others
+        let mut signatures = signatures;
+        signatures.push(sign_intent(&message));
+        let mut public_keys = public_keys;
+        public_keys.push(BLS_KP.public().clone());
+        let aggregated = aggregate(&signatures).unwrap();
+        assert!(verify_aggregate(&aggregated, &public_keys, &signing_payload).is_ok());
+
+        assert!(aggregate(&[]).is_err());
+        assert!(aggregate(&["00".to_string()]).is_err());
+    }
+}
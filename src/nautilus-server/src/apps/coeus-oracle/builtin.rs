@@ -0,0 +1,108 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Scripts compiled into the server, referenced by a `blob_id` of the form
+//! `builtin://<name>`. They need no external storage and are covered by the
+//! enclave PCRs like the rest of the image.
+
+use crate::EnclaveError;
+
+/// Blob id prefix selecting a built-in script.
+pub const SCHEME: &str = "builtin://";
+
+/// Built-in scripts by name, sources in builtin/.
+pub const BUILTIN_SCRIPTS: &[(&str, &str)] = &[
+    (
+        "chainlink_style_price",
+        include_str!("builtin/chainlink_style_price.rhai"),
+    ),
+    ("sui_usd_price", include_str!("builtin/sui_usd_price.rhai")),
+    (
+        "sui_usd_bucket",
+        include_str!("builtin/sui_usd_bucket.rhai"),
+    ),
+];
+
+/// Source of a built-in script.
+pub fn get(name: &str) -> Result<Vec<u8>, EnclaveError> {
+    BUILTIN_SCRIPTS
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, code)| code.as_bytes().to_vec())
+        .ok_or_else(|| EnclaveError::GenericError(format!("Unknown built-in script {}", name)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{ResultValue, ReturnType, execute_rhai_code_with_meta, mock_http};
+    use super::*;
+    use serde_json::json;
+
+    const COINGECKO: &str =
+        "https://api.coingecko.com/api/v3/simple/price?ids=sui&vs_currencies=usd";
+    const BINANCE: &str = "https://api.binance.com/api/v3/ticker/price?symbol=SUIUSDT";
+    const COINBASE: &str = "https://api.coinbase.com/v2/prices/SUI-USD/spot";
+
+    fn run(name: &str) -> Result<(Option<ResultValue>, Option<u64>, Option<u64>), EnclaveError> {
+        let code = String::from_utf8(get(name)?).unwrap();
+        let output = execute_rhai_code_with_meta(&code, &ReturnType::NUMBER, false)?;
+        Ok((output.result, output.meta.source_count, output.meta.spread))
+    }
+
+    #[test]
+    fn test_builtin_scripts() {
+        let http = mock_http::MockHttp::shared();
+        http.respond_json(COINGECKO, &json!({ "sui": { "usd": 1.75 } }));
+        http.respond_json(BINANCE, &json!({ "symbol": "SUIUSDT", "price": "2.00" }));
+        http.respond_json(
+            COINBASE,
+            &json!({ "data": { "base": "SUI", "currency": "USD", "amount": "1.50" } }),
+        );
+
+        assert_eq!(
+            run("sui_usd_price").unwrap(),
+            (Some(ResultValue::NUMBER(1_750_000)), Some(1), None)
+        );
+        assert_eq!(
+            run("sui_usd_bucket").unwrap().0,
+            Some(ResultValue::NUMBER(1))
+        );
+        // Median of the three sources, spread between the highest and lowest
+        assert_eq!(
+            run("chainlink_style_price").unwrap(),
+            (
+                Some(ResultValue::NUMBER(175_000_000)),
+                Some(3),
+                Some(50_000_000)
+            )
+        );
+
+        // Two sources are enough, their mean is the median
+        http.fail(COINBASE, "HTTP error: status 503 Service Unavailable");
+        assert_eq!(
+            run("chainlink_style_price").unwrap(),
+            (
+                Some(ResultValue::NUMBER(187_500_000)),
+                Some(2),
+                Some(25_000_000)
+            )
+        );
+        http.respond_json(BINANCE, &json!({ "code": -1121, "msg": "Invalid symbol." }));
+        assert!(run("chainlink_style_price").is_err());
+
+        http.respond_json(COINGECKO, &json!({ "sui": { "usd": 1.25 } }));
+        assert_eq!(
+            run("sui_usd_bucket").unwrap().0,
+            Some(ResultValue::NUMBER(0))
+        );
+        http.respond_json(COINGECKO, &json!({ "sui": { "usd": 2.5 } }));
+        assert_eq!(
+            run("sui_usd_bucket").unwrap().0,
+            Some(ResultValue::NUMBER(2))
+        );
+        http.respond_json(COINGECKO, &json!({ "error": "rate limited" }));
+        assert!(run("sui_usd_price").is_err());
+
+        assert!(get("missing").is_err());
+    }
+}
@@ -0,0 +1,159 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Multi-file Rhai projects published as a single blob:
+//!
+//! ```json
+//! {
+//!   "coeus_bundle": 1,
+//!   "main": "main.rhai",
+//!   "files": {
+//!     "main.rhai": "import \"lib/stats\" as stats; stats::median(bundle_json(\"data/prices.json\"))",
+//!     "lib/stats.rhai": "fn median(values) { ... }",
+//!     "data/prices.json": "[1, 2, 3]"
+//!   }
+//! }
+//! ```
+//!
+//! `.rhai` files other than `main` are modules, imported by path with or
+//! without the extension. Every file can be read with `bundle_data(path)` or,
+//! parsed as JSON, with `bundle_json(path)`.
+
+use super::json_value_to_dynamic;
+use rhai::{Dynamic, Engine, EvalAltResult, Module, ModuleResolver, Position, Scope, SharedModule};
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Bundle format version understood by this enclave.
+pub const BUNDLE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bundle {
+    pub coeus_bundle: u32,
+    pub main: String,
+    pub files: BTreeMap<String, String>,
+}
+
+/// Resolves `import` statements against the bundle files.
+struct BundleResolver {
+    files: Arc<BTreeMap<String, String>>,
+}
+
+impl ModuleResolver for BundleResolver {
+    fn resolve(
+        &self,
+        engine: &Engine,
+        _source: Option<&str>,
+        path: &str,
+        pos: Position,
+    ) -> Result<SharedModule, Box<EvalAltResult>> {
+        let code = self
+            .files
+            .get(path)
+            .or_else(|| self.files.get(&format!("{}.rhai", path)))
+            .ok_or_else(|| EvalAltResult::ErrorModuleNotFound(path.to_string(), pos))?;
+        let in_module = |e: Box<EvalAltResult>| {
+            Box::new(EvalAltResult::ErrorInModule(path.to_string(), e, pos))
+        };
+        let mut ast = engine.compile(code).map_err(|e| in_module(e.into()))?;
+        ast.set_source(path);
+        let module = Module::eval_ast_as_new(Scope::new(), &ast, engine).map_err(in_module)?;
+        Ok(module.into())
+    }
+}
+
+/// Parse a blob as a bundle, None if it is a plain script.
+pub fn parse(code: &str) -> Result<Option<Bundle>, String> {
+    if !code.trim_start().starts_with('{') {
+        return Ok(None);
+    }
+    let Ok(value) = serde_json::from_str::<JsonValue>(code) else {
+        return Ok(None);
+    };
+    if value.get("coeus_bundle").is_none() {
+        return Ok(None);
+    }
+    let bundle: Bundle =
+        serde_json::from_value(value).map_err(|e| format!("Invalid bundle: {}", e))?;
+    if bundle.coeus_bundle != BUNDLE_VERSION {
+        return Err(format!(
+            "Unsupported bundle version {}",
+            bundle.coeus_bundle
+        ));
+    }
+    if !bundle.files.contains_key(&bundle.main) {
+        return Err(format!("Bundle main file {} not found", bundle.main));
+    }
+    Ok(Some(bundle))
+}
+
+/// If `code` is a bundle, install its module resolver and data functions on
+/// the engine and return the main script, otherwise return `code` unchanged.
+pub fn prepare(engine: &mut Engine, code: &str) -> Result<String, String> {
+    let Some(bundle) = parse(code)? else {
+        return Ok(code.to_string());
+    };
+    let main = bundle.files[&bundle.main].clone();
+    let files = Arc::new(bundle.files);
+
+    engine.set_module_resolver(BundleResolver {
+        files: files.clone(),
+    });
+    let data = files.clone();
+    engine.register_fn(
+        "bundle_data",
+        move |path: &str| -> Result<String, Box<EvalAltResult>> {
+            data.get(path)
+                .cloned()
+                .ok_or_else(|| format!("Bundle file {} not found", path).into())
+        },
+    );
+    engine.register_fn(
+        "bundle_json",
+        move |path: &str| -> Result<Dynamic, Box<EvalAltResult>> {
+            let text = files
+                .get(path)
+                .ok_or_else(|| format!("Bundle file {} not found", path))?;
+            let value: JsonValue = serde_json::from_str(text)
+                .map_err(|e| format!("Bundle file {} is not JSON: {}", path, e))?;
+            Ok(json_value_to_dynamic(&value))
+        },
+    );
+    Ok(main)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::app::{ResultValue, ReturnType, execute_rhai_code_with_meta};
+    use serde_json::json;
+
+    #[test]
+    fn test_bundle() {
+        let bundle = json!({
+            "coeus_bundle": 1,
+            "main": "main.rhai",
+            "files": {
+                "main.rhai": "import \"lib/stats\" as stats;\nlet prices = bundle_json(\"data/prices.json\");\nstats::median(prices) * 100",
+                "lib/stats.rhai": "fn median(values) {\n    values.sort();\n    values[values.len() / 2]\n}",
+                "data/prices.json": "[3, 1, 2]"
+            }
+        })
+        .to_string();
+        let output = execute_rhai_code_with_meta(&bundle, &ReturnType::NUMBER, true).unwrap();
+        assert_eq!(output.result, Some(ResultValue::NUMBER(200)));
+
+        // Plain scripts and JSON that is not a bundle are left alone
+        assert!(parse("40 + 2").unwrap().is_none());
+        assert!(parse("{ 40 + 2 }").unwrap().is_none());
+        assert!(parse("{\"a\": 1}").unwrap().is_none());
+
+        assert!(
+            parse("{\"coeus_bundle\": 2, \"main\": \"m\", \"files\": {\"m\": \"1\"}}").is_err()
+        );
+        assert!(parse("{\"coeus_bundle\": 1, \"main\": \"m\", \"files\": {}}").is_err());
+    }
+}
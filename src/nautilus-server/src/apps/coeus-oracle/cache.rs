@@ -0,0 +1,79 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::UpdateOracleHttpResponse;
+use super::config::COEUS_CONFIG;
+use super::encryption;
+use crate::metrics;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use sui_sdk_types::Address;
+use tokio::sync::RwLock;
+
+lazy_static::lazy_static! {
+    /// Last signed process_data response per (feed id, sign_meta).
+    pub static ref RESULT_CACHE: Arc<RwLock<HashMap<(Address, bool), UpdateOracleHttpResponse>>> =
+        Arc::new(RwLock::new(HashMap::new()));
+}
+
+/// Cache section of coeus_config.yaml.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheConfig {
+    /// How long a signed result is reused for repeated process_data calls, 0 disables caching.
+    #[serde(default)]
+    pub ttl_ms: u64,
+}
+
+/// True if a response signed at `signed_at_ms` can still be served at `now_ms`.
+fn is_fresh(signed_at_ms: u64, now_ms: u64, ttl_ms: u64) -> bool {
+    ttl_ms > 0 && now_ms.saturating_sub(signed_at_ms) < ttl_ms
+}
+
+/// Return the cached response for the feed if it was signed within the TTL.
+pub async fn get_cached(
+    feed_id: Address,
+    sign_meta: bool,
+    now_ms: u64,
+) -> Option<UpdateOracleHttpResponse> {
+    let ttl_ms = COEUS_CONFIG.cache.ttl_ms;
+    if ttl_ms == 0 {
+        return None;
+    }
+    let cached = RESULT_CACHE
+        .read()
+        .await
+        .get(&(feed_id, sign_meta))
+        .filter(|cached| is_fresh(cached.signed.response.timestamp_ms, now_ms, ttl_ms))
+        .cloned();
+    metrics::record_cache_lookup("result", cached.is_some());
+    cached
+}
+
+/// Store a freshly signed response. Results of confidential feeds are not
+/// kept in plaintext, every request runs the feed.
+pub async fn store(feed_id: Address, sign_meta: bool, response: &UpdateOracleHttpResponse) {
+    let ttl_ms = COEUS_CONFIG.cache.ttl_ms;
+    if ttl_ms == 0 || encryption::is_confidential(feed_id) {
+        return;
+    }
+    let now_ms = response.signed.response.timestamp_ms;
+    let mut cache = RESULT_CACHE.write().await;
+    // Drop expired entries so the cache only holds recently requested feeds
+    cache.retain(|_, cached| is_fresh(cached.signed.response.timestamp_ms, now_ms, ttl_ms));
+    cache.insert((feed_id, sign_meta), response.clone());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_fresh() {
+        assert!(is_fresh(1000, 1000, 500));
+        assert!(is_fresh(1000, 1499, 500));
+        assert!(!is_fresh(1000, 1500, 500));
+        // Disabled cache is never fresh
+        assert!(!is_fresh(1000, 1000, 0));
+    }
+}
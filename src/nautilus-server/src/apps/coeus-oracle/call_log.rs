@@ -0,0 +1,282 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Opt-in log of process_data and execute_code calls, one JSON line per
+//! call, written apart from the tracing output so it can be shipped and
+//! retained on its own. Unlike the hash-chained log in audit.rs it also
+//! covers failed calls, and says who made them.
+//!
+//! A line holds the requester (API key name), feed id, SHA-256 of the script
+//! blob (of the code for execute_code), SHA-256 of the signed intent message
+//! as in the audit log, duration and outcome. Result values, script code,
+//! upstream responses and API keys are never written. The requester, feed id
+//! and error message are each kept, hashed or dropped as configured; error
+//! messages are dropped by default since they may quote upstream responses.
+
+use super::config::COEUS_CONFIG;
+use crate::EnclaveError;
+use crate::common::IntentMessage;
+use crate::request_id;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hash::{HashFunction, Sha256};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::warn;
+
+lazy_static::lazy_static! {
+    static ref SINK: Mutex<Option<Box<dyn Write + Send>>> =
+        Mutex::new(open_sink(&COEUS_CONFIG.call_log));
+}
+
+tokio::task_local! {
+    static NOTED: RefCell<Noted>;
+}
+
+/// Call log section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CallLogConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// "stdout", "stderr", or a file lines are appended to.
+    #[serde(default = "default_sink")]
+    pub sink: String,
+    #[serde(default)]
+    pub redact: RedactionConfig,
+}
+
+impl Default for CallLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sink: default_sink(),
+            redact: RedactionConfig::default(),
+        }
+    }
+}
+
+fn default_sink() -> String {
+    "stdout".to_string()
+}
+
+/// How each identifying field is written.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedactionConfig {
+    #[serde(default)]
+    pub requester: Redaction,
+    #[serde(default)]
+    pub feed_id: Redaction,
+    #[serde(default = "default_error_redaction")]
+    pub error: Redaction,
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self {
+            requester: Redaction::Keep,
+            feed_id: Redaction::Keep,
+            error: default_error_redaction(),
+        }
+    }
+}
+
+fn default_error_redaction() -> Redaction {
+    Redaction::Drop
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Redaction {
+    #[default]
+    Keep,
+    /// Hex SHA-256 of the value, so calls can still be correlated.
+    Hash,
+    Drop,
+}
+
+impl Redaction {
+    fn apply(self, value: Option<String>) -> Option<String> {
+        match self {
+            Redaction::Keep => value,
+            Redaction::Hash => value.map(|value| Hex::encode(Sha256::digest(value).digest)),
+            Redaction::Drop => None,
+        }
+    }
+}
+
+/// One line of the log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CallRecord {
+    pub timestamp_ms: u64,
+    pub request_id: Option<String>,
+    /// process_data or execute_code.
+    pub call: String,
+    pub requester: Option<String>,
+    pub feed_id: Option<String>,
+    /// Hex SHA-256 of the script blob, unset for results served from cache.
+    pub blob_hash: Option<String>,
+    /// Hex SHA-256 of the BCS signed intent message.
+    pub result_digest: Option<String>,
+    pub duration_ms: u64,
+    /// "ok", or the error code.
+    pub outcome: String,
+    pub error: Option<String>,
+}
+
+/// Hashes noted while the call runs.
+#[derive(Default)]
+struct Noted {
+    blob_hash: Option<String>,
+    result_digest: Option<String>,
+}
+
+fn open_sink(config: &CallLogConfig) -> Option<Box<dyn Write + Send>> {
+    if !config.enabled {
+        return None;
+    }
+    match config.sink.as_str() {
+        "stdout" => Some(Box::new(std::io::stdout())),
+        "stderr" => Some(Box::new(std::io::stderr())),
+        path => match std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+        {
+            Ok(file) => Some(Box::new(file)),
+            Err(e) => {
+                warn!("Call log disabled, cannot open {}: {}", path, e);
+                None
+            }
+        },
+    }
+}
+
+/// Note the SHA-256 of the script the call runs. No-op outside [`logged`].
+pub fn note_blob_hash(hash: &[u8]) {
+    let _ = NOTED.try_with(|noted| noted.borrow_mut().blob_hash = Some(Hex::encode(hash)));
+}
+
+/// Note the intent message the call signed. No-op outside [`logged`].
+pub fn note_result<T: Serialize + Debug>(intent_msg: &IntentMessage<T>) {
+    let _ = NOTED.try_with(|noted| {
+        let digest = Sha256::digest(bcs::to_bytes(intent_msg).expect("should not fail")).digest;
+        noted.borrow_mut().result_digest = Some(Hex::encode(digest));
+    });
+}
+
+fn to_record(
+    redact: &RedactionConfig,
+    call: &str,
+    requester: Option<String>,
+    feed_id: Option<String>,
+    noted: Noted,
+    duration_ms: u64,
+    error: Option<&EnclaveError>,
+) -> CallRecord {
+    let timestamp_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default();
+    CallRecord {
+        timestamp_ms,
+        request_id: request_id::current(),
+        call: call.to_string(),
+        requester: redact.requester.apply(requester),
+        feed_id: redact.feed_id.apply(feed_id),
+        blob_hash: noted.blob_hash,
+        result_digest: noted.result_digest,
+        duration_ms,
+        outcome: error.map_or("ok", EnclaveError::code).to_string(),
+        error: redact.error.apply(error.map(EnclaveError::to_string)),
+    }
+}
+
+/// Run a call and log it when the call log is enabled.
+pub async fn logged<T, F>(
+    call: &str,
+    requester: Option<String>,
+    feed_id: Option<String>,
+    future: F,
+) -> Result<T, EnclaveError>
+where
+    F: Future<Output = Result<T, EnclaveError>>,
+{
+    if !COEUS_CONFIG.call_log.enabled {
+        return future.await;
+    }
+    let started = Instant::now();
+    let (result, noted) = NOTED
+        .scope(RefCell::new(Noted::default()), async {
+            let result = future.await;
+            (result, NOTED.with(|noted| noted.take()))
+        })
+        .await;
+    let record = to_record(
+        &COEUS_CONFIG.call_log.redact,
+        call,
+        requester,
+        feed_id,
+        noted,
+        started.elapsed().as_millis() as u64,
+        result.as_ref().err(),
+    );
+    write(&record);
+    result
+}
+
+fn write(record: &CallRecord) {
+    let Ok(line) = serde_json::to_string(record) else {
+        return;
+    };
+    let mut sink = SINK.lock().expect("call log sink lock poisoned");
+    let Some(sink) = sink.as_mut() else {
+        return;
+    };
+    if let Err(e) = writeln!(sink, "{}", line).and_then(|_| sink.flush()) {
+        warn!("Failed to write the call log: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_to_record() {
+        let noted = NOTED
+            .scope(RefCell::new(Noted::default()), async {
+                note_blob_hash(&[1, 2]);
+                NOTED.with(|noted| noted.take())
+            })
+            .await;
+        let redact = RedactionConfig {
+            requester: Redaction::Hash,
+            ..RedactionConfig::default()
+        };
+        let error = EnclaveError::ScriptError {
+            line: None,
+            message: "upstream said: secret".to_string(),
+        };
+        let record = to_record(
+            &redact,
+            "process_data",
+            Some("relayer".to_string()),
+            Some("0x1".to_string()),
+            noted,
+            5,
+            Some(&error),
+        );
+        assert_eq!(record.requester.unwrap().len(), 64);
+        assert_eq!(record.feed_id.as_deref(), Some("0x1"));
+        assert_eq!(record.blob_hash.as_deref(), Some("0102"));
+        assert_eq!(record.outcome, "script_error");
+        // Error messages are dropped by default
+        assert!(record.error.is_none());
+
+        // Outside a logged call nothing is noted, and nothing fails
+        note_blob_hash(&[1]);
+    }
+}
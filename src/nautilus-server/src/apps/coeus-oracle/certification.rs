@@ -0,0 +1,350 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! On-chain check that a feed's Walrus blob is certified and not expired
+//! before trusting what the aggregators serve for it.
+//!
+//! The feed names its `walrus::blob::Blob` object in `blob_object_id`. The
+//! object must be a `blob::Blob` of the Walrus package (`walrus.package_id`,
+//! or the package of the System object), so an object of another package
+//! with the same layout is not trusted. It must hold the feed's blob id, be
+//! certified, and its storage must end after the current Walrus epoch, read
+//! from the Walrus System object.
+//!
+//! The Blob object must also be owned by the feed's creator or one of
+//! `walrus.blob_owners`, tying the stored code to whoever set up the feed
+//! rather than to any certified blob holding the same bytes. The creator is
+//! one of the feed settings (see feed_layout.rs), so feeds created without
+//! settings are only checked when blob_owners is set.
+
+use super::arweave::decode_base64url;
+use super::config::COEUS_CONFIG;
+use super::onchain::{dynamic_field_id, get_object, move_contents, typed_contents};
+use crate::EnclaveError;
+use crate::chain_reader::ChainReader;
+use serde::Deserialize;
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use std::fmt;
+use std::marker::PhantomData;
+use sui_sdk_types::{Address, Owner, StructTag, TypeTag};
+
+/// BCS layout of walrus::storage_resource::Storage
+#[derive(Debug, Clone, Deserialize)]
+pub struct WalrusStorage {
+    pub id: Address,
+    pub start_epoch: u32,
+    pub end_epoch: u32,
+    pub storage_size: u64,
+}
+
+/// BCS layout of walrus::blob::Blob
+#[derive(Debug, Clone, Deserialize)]
+pub struct WalrusBlob {
+    pub id: Address,
+    pub registered_epoch: u32,
+    /// u256 blob id, whose little-endian bytes are the base64url blob id string
+    pub blob_id: [u8; 32],
+    pub size: u64,
+    pub encoding_type: u8,
+    pub certified_epoch: Option<u32>,
+    pub storage: WalrusStorage,
+    pub deletable: bool,
+}
+
+/// BCS layout of walrus::system::System
+#[derive(Debug, Deserialize)]
+struct WalrusSystem {
+    #[allow(dead_code)]
+    id: Address,
+    version: u64,
+}
+
+/// BCS layout of sui::group_ops::Element<G1>
+#[derive(Debug, Deserialize)]
+#[cfg_attr(test, derive(serde::Serialize))]
+#[allow(dead_code)]
+struct Element {
+    bytes: Vec<u8>,
+}
+
+/// BCS layout of walrus::bls_aggregate::BlsCommitteeMember
+#[derive(Debug, Deserialize)]
+#[cfg_attr(test, derive(serde::Serialize))]
+#[allow(dead_code)]
+struct BlsCommitteeMember {
+    public_key: Element,
+    weight: u16,
+    node_id: Address,
+}
+
+/// Leading fields of walrus::bls_aggregate::BlsCommittee
+#[derive(Debug, Deserialize)]
+#[cfg_attr(test, derive(serde::Serialize))]
+struct BlsCommittee {
+    #[allow(dead_code)]
+    members: Vec<BlsCommitteeMember>,
+    #[allow(dead_code)]
+    n_shards: u16,
+    epoch: u32,
+}
+
+/// Leading fields of `Field<u64, SystemStateInnerV1>`, whose value starts
+/// with the committee.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(test, derive(serde::Serialize))]
+struct SystemStateField {
+    #[allow(dead_code)]
+    id: Address,
+    #[allow(dead_code)]
+    name: u64,
+    committee: BlsCommittee,
+}
+
+/// A `T` followed by fields it does not describe, which are skipped.
+struct Prefix<T>(T);
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Prefix<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PrefixVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for PrefixVisitor<T> {
+            type Value = Prefix<T>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a struct followed by more fields")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Prefix<T>, A::Error> {
+                let value = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::custom("Truncated Walrus system state"))?;
+                while matches!(seq.next_element::<u8>(), Ok(Some(_))) {}
+                Ok(Prefix(value))
+            }
+        }
+
+        // BCS structs are their fields back to back, read them as a tuple of
+        // unknown length
+        deserializer.deserialize_tuple(usize::MAX, PrefixVisitor(PhantomData))
+    }
+}
+
+/// Current epoch from the contents of the system state dynamic field.
+fn epoch_from_system_state(contents: &[u8]) -> Result<u32, String> {
+    let Prefix(field): Prefix<SystemStateField> = bcs::from_bytes(contents)
+        .map_err(|e| format!("Failed to deserialize Walrus system state: {}", e))?;
+    Ok(field.committee.epoch)
+}
+
+/// Check a decoded Blob against the feed's blob id and the current epoch.
+pub fn check_blob(blob: &WalrusBlob, blob_id: &str, current_epoch: u32) -> Result<(), String> {
+    let expected = decode_base64url(blob_id)?;
+    if expected.as_slice() != blob.blob_id.as_slice() {
+        return Err(format!("Blob object does not hold blob {}", blob_id));
+    }
+    if blob.certified_epoch.is_none() {
+        return Err(format!("Blob {} is not certified", blob_id));
+    }
+    if blob.storage.end_epoch <= current_epoch {
+        return Err(format!(
+            "Blob {} expired at epoch {}, current epoch is {}",
+            blob_id, blob.storage.end_epoch, current_epoch
+        ));
+    }
+    Ok(())
+}
+
+/// Check that the Blob object is owned by the feed creator or one of
+/// `allowed`. Nothing is checked when neither is known.
+pub fn check_owner(
+    owner: &Owner,
+    creator: Option<&Address>,
+    allowed: &[Address],
+) -> Result<(), String> {
+    if creator.is_none() && allowed.is_empty() {
+        return Ok(());
+    }
+    match owner {
+        Owner::Address(address) if creator == Some(address) || allowed.contains(address) => Ok(()),
+        Owner::Address(address) => Err(format!(
+            "Blob object is owned by {}, not the feed creator or an allowed owner",
+            address
+        )),
+        other => Err(format!(
+            "Blob object must be owned by an address, found {:?}",
+            other
+        )),
+    }
+}
+
+fn blob_owners() -> Result<Vec<Address>, EnclaveError> {
+    COEUS_CONFIG
+        .walrus
+        .blob_owners
+        .iter()
+        .map(|owner| {
+            Address::from_hex(owner).map_err(|e| {
+                EnclaveError::GenericError(format!("Invalid Walrus blob owner {}: {}", owner, e))
+            })
+        })
+        .collect()
+}
+
+/// Type of Blob objects of the Walrus package `package_id`, or of the
+/// package defining `system_type` when it is empty.
+fn blob_type(package_id: &str, system_type: &StructTag) -> Result<StructTag, EnclaveError> {
+    let object_type = if package_id.is_empty() {
+        format!("{}::blob::Blob", system_type.address())
+    } else {
+        format!("{}::blob::Blob", package_id)
+    };
+    object_type
+        .parse()
+        .map_err(|e| EnclaveError::GenericError(format!("Invalid type {}: {:?}", object_type, e)))
+}
+
+/// Type of the system state dynamic field of the Walrus System object.
+fn system_state_type(package: &Address) -> Result<StructTag, EnclaveError> {
+    let object_type = format!(
+        "0x2::dynamic_field::Field<u64, {}::system_state_inner::SystemStateInnerV1>",
+        package
+    );
+    object_type
+        .parse()
+        .map_err(|e| EnclaveError::GenericError(format!("Invalid type {}: {:?}", object_type, e)))
+}
+
+/// Current Walrus epoch and the type of Blob objects.
+async fn read_system(chain: &dyn ChainReader) -> Result<(u32, StructTag), EnclaveError> {
+    let system_id = Address::from_hex(&COEUS_CONFIG.walrus.system_object_id).map_err(|e| {
+        EnclaveError::GenericError(format!("Invalid Walrus system object id: {}", e))
+    })?;
+    let object = get_object(chain, &system_id).await?;
+    let system_type = object
+        .as_struct()
+        .map(|move_object| move_object.object_type().clone())
+        .ok_or_else(|| {
+            EnclaveError::GenericError("Walrus System is not a Move object".to_string())
+        })?;
+    let system: WalrusSystem =
+        bcs::from_bytes(&move_contents(&system_id, &object)?).map_err(|e| {
+            EnclaveError::GenericError(format!("Failed to deserialize Walrus System: {}", e))
+        })?;
+    let inner_id = dynamic_field_id(&system_id, &TypeTag::U64, &system.version.to_le_bytes())?;
+    let inner = get_object(chain, &inner_id).await?;
+    let inner_type = system_state_type(system_type.address())?;
+    let epoch = epoch_from_system_state(&typed_contents(&inner_id, &inner, &inner_type)?)
+        .map_err(EnclaveError::GenericError)?;
+    Ok((
+        epoch,
+        blob_type(&COEUS_CONFIG.walrus.package_id, &system_type)?,
+    ))
+}
+
+/// Refuse Walrus blobs that are not certified, have expired, or are not owned
+/// by the feed `creator` or an allowed owner. Feeds without a blob object are
+/// only accepted when certification is not required.
+pub async fn verify_certified(
+    chain: &dyn ChainReader,
+    blob_id: &str,
+    blob_object_id: Option<&Address>,
+    creator: Option<&Address>,
+) -> Result<(), EnclaveError> {
+    let Some(blob_object_id) = blob_object_id else {
+        if COEUS_CONFIG.walrus.require_certification {
+            return Err(EnclaveError::GenericError(
+                "Feed has no Walrus blob object to verify certification".to_string(),
+            ));
+        }
+        return Ok(());
+    };
+    let object = get_object(chain, blob_object_id).await?;
+    check_owner(object.owner(), creator, &blob_owners()?).map_err(EnclaveError::GenericError)?;
+    let (epoch, blob_type) = read_system(chain).await?;
+    let contents = typed_contents(blob_object_id, &object, &blob_type)?;
+    let blob: WalrusBlob = bcs::from_bytes(&contents).map_err(|e| {
+        EnclaveError::GenericError(format!("Failed to deserialize Walrus Blob: {}", e))
+    })?;
+    check_blob(&blob, blob_id, epoch).map_err(EnclaveError::GenericError)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn blob(certified_epoch: Option<u32>, end_epoch: u32) -> WalrusBlob {
+        WalrusBlob {
+            id: Address::ZERO,
+            registered_epoch: 1,
+            blob_id: [7; 32],
+            size: 10,
+            encoding_type: 1,
+            certified_epoch,
+            storage: WalrusStorage {
+                id: Address::ZERO,
+                start_epoch: 1,
+                end_epoch,
+                storage_size: 100,
+            },
+            deletable: false,
+        }
+    }
+
+    #[test]
+    fn test_check_blob() {
+        // base64url of [7; 32]
+        let blob_id = "BwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwc";
+        assert!(check_blob(&blob(Some(2), 10), blob_id, 5).is_ok());
+        assert!(check_blob(&blob(None, 10), blob_id, 5).is_err());
+        assert!(check_blob(&blob(Some(2), 10), blob_id, 10).is_err());
+        assert!(check_blob(&blob(Some(2), 10), "AAAA", 5).is_err());
+    }
+
+    #[test]
+    fn test_check_owner() {
+        let creator = Address::from_hex("0x1").unwrap();
+        let publisher = Address::from_hex("0x2").unwrap();
+        let other = Address::from_hex("0x3").unwrap();
+        assert!(check_owner(&Owner::Address(creator), Some(&creator), &[]).is_ok());
+        assert!(check_owner(&Owner::Address(publisher), Some(&creator), &[publisher]).is_ok());
+        assert!(check_owner(&Owner::Address(other), Some(&creator), &[publisher]).is_err());
+        assert!(check_owner(&Owner::Shared(1), Some(&creator), &[]).is_err());
+        // Feeds without settings have no creator
+        assert!(check_owner(&Owner::Address(other), None, &[]).is_ok());
+        assert!(check_owner(&Owner::Address(other), None, &[publisher]).is_err());
+    }
+
+    #[test]
+    fn test_blob_type() {
+        let system_type: StructTag = "0x5::system::System".parse().unwrap();
+        let expected: StructTag = "0x5::blob::Blob".parse().unwrap();
+        assert_eq!(blob_type("", &system_type).unwrap(), expected);
+        let expected: StructTag = "0x6::blob::Blob".parse().unwrap();
+        assert_eq!(blob_type("0x6", &system_type).unwrap(), expected);
+        assert!(blob_type("not hex", &system_type).is_err());
+    }
+
+    #[test]
+    fn test_epoch_from_system_state() {
+        let member = |key: Vec<u8>| BlsCommitteeMember {
+            public_key: Element { bytes: key },
+            weight: 1,
+            node_id: Address::ZERO,
+        };
+        let field = SystemStateField {
+            id: Address::ZERO,
+            name: 1,
+            committee: BlsCommittee {
+                members: vec![member(vec![1, 2, 3]), member(vec![4; 96])],
+                n_shards: 1000,
+                epoch: 42,
+            },
+        };
+        let mut contents = bcs::to_bytes(&field).unwrap();
+        // Fields after the committee
+        contents.extend_from_slice(&[9; 16]);
+        assert_eq!(epoch_from_system_state(&contents).unwrap(), 42);
+        assert!(epoch_from_system_state(&contents[..50]).is_err());
+        assert!(system_state_type(&Address::ZERO).is_ok());
+    }
+}
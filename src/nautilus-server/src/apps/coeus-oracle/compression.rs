@@ -0,0 +1,101 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Transparent decompression of gzip and zstd blobs, detected by their magic
+//! bytes. The decompressed size is capped so a small blob cannot expand into
+//! an unbounded amount of enclave memory.
+
+use super::config::COEUS_CONFIG;
+use crate::EnclaveError;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompressionConfig {
+    /// Largest decompressed blob accepted.
+    #[serde(default = "default_max_decompressed_bytes")]
+    pub max_decompressed_bytes: u64,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            max_decompressed_bytes: default_max_decompressed_bytes(),
+        }
+    }
+}
+
+fn default_max_decompressed_bytes() -> u64 {
+    4 * 1024 * 1024
+}
+
+/// Read a decoder to the end, failing once more than `max_bytes` are produced.
+fn read_capped(decoder: impl Read, format: &str, max_bytes: u64) -> Result<Vec<u8>, EnclaveError> {
+    let mut data = Vec::new();
+    decoder
+        .take(max_bytes + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| EnclaveError::GenericError(format!("Invalid {} blob: {}", format, e)))?;
+    if data.len() as u64 > max_bytes {
+        return Err(EnclaveError::GenericError(format!(
+            "Decompressed {} blob exceeds {} bytes",
+            format, max_bytes
+        )));
+    }
+    Ok(data)
+}
+
+/// Decompress a gzip or zstd blob, other blobs are returned unchanged.
+pub fn decompress_with_limit(blob: Vec<u8>, max_bytes: u64) -> Result<Vec<u8>, EnclaveError> {
+    if blob.starts_with(GZIP_MAGIC) {
+        read_capped(
+            flate2::read::GzDecoder::new(blob.as_slice()),
+            "gzip",
+            max_bytes,
+        )
+    } else if blob.starts_with(ZSTD_MAGIC) {
+        let decoder = zstd::stream::read::Decoder::new(blob.as_slice())
+            .map_err(|e| EnclaveError::GenericError(format!("Invalid zstd blob: {}", e)))?;
+        read_capped(decoder, "zstd", max_bytes)
+    } else {
+        Ok(blob)
+    }
+}
+
+/// `decompress_with_limit` with the configured limit.
+pub fn decompress(blob: Vec<u8>) -> Result<Vec<u8>, EnclaveError> {
+    decompress_with_limit(blob, COEUS_CONFIG.compression.max_decompressed_bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_decompress() {
+        let script = b"let x = 40;\nx + 2".to_vec();
+        assert_eq!(decompress_with_limit(script.clone(), 100).unwrap(), script);
+        assert_eq!(decompress_with_limit(gzip(&script), 100).unwrap(), script);
+        let zstd = zstd::encode_all(script.as_slice(), 3).unwrap();
+        assert_eq!(decompress_with_limit(zstd, 100).unwrap(), script);
+
+        // A small blob expanding past the cap is rejected
+        let bomb = gzip(&vec![b' '; 10_000]);
+        assert!(bomb.len() < 100);
+        assert!(decompress_with_limit(bomb.clone(), 9_999).is_err());
+        assert!(decompress_with_limit(bomb, 10_000).is_ok());
+
+        assert!(decompress_with_limit(vec![0x1f, 0x8b, 0, 0], 100).is_err());
+    }
+}
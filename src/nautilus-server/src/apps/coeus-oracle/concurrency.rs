@@ -0,0 +1,333 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Bound on concurrent script executions.
+//!
+//! Rhai and Starlark scripts run off the async runtime because their host
+//! functions wait for HTTP and Sui calls. They run on a fixed pool of
+//! `max_concurrent` worker threads, started once, so a burst of requests
+//! never creates more threads. Every execution first takes one of
+//! `max_concurrent` slots and holds it until its script returns, so a dropped
+//! request does not free the slot while its script is still running, and a
+//! slot always has an idle worker. At most `max_queued` executions wait for a
+//! slot, for up to `queue_timeout_ms`; beyond that requests are shed with 503
+//! and a Retry-After hint.
+//!
+//! A script is aborted once it has run `max_operations` operations or for
+//! `max_duration_ms`, so a runaway script fails with a script error and frees
+//! its worker and slot instead of holding them forever. A script waiting for
+//! a host function's HTTP or Sui call stops waiting at the deadline too; the
+//! call itself finishes on its own runtime and its reply is dropped.
+
+use super::config::COEUS_CONFIG;
+use crate::EnclaveError;
+use crate::metrics;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+lazy_static::lazy_static! {
+    static ref EXECUTION_LIMITER: ExecutionLimiter =
+        ExecutionLimiter::new(&COEUS_CONFIG.execution);
+    static ref SCRIPT_WORKERS: WorkerPool =
+        WorkerPool::new(COEUS_CONFIG.execution.max_concurrent.max(1));
+}
+
+thread_local! {
+    /// Deadline of the script running on this worker.
+    static SCRIPT_DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Execution section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionConfig {
+    /// Scripts running at once.
+    #[serde(default = "default_max_concurrent")]
+    pub max_concurrent: usize,
+    /// Executions waiting for a slot before new ones are shed.
+    #[serde(default = "default_max_queued")]
+    pub max_queued: usize,
+    /// Longest wait for a slot before the execution is shed.
+    #[serde(default = "default_queue_timeout_ms")]
+    pub queue_timeout_ms: u64,
+    /// Retry-After sent with shed requests.
+    #[serde(default = "default_retry_after_ms")]
+    pub retry_after_ms: u64,
+    /// Operations (Rhai) or statements (Starlark) a script may run, 0 for no
+    /// limit.
+    #[serde(default = "default_max_operations")]
+    pub max_operations: u64,
+    /// Longest a script may run, 0 for no limit.
+    #[serde(default = "default_max_duration_ms")]
+    pub max_duration_ms: u64,
+}
+
+impl Default for ExecutionConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: default_max_concurrent(),
+            max_queued: default_max_queued(),
+            queue_timeout_ms: default_queue_timeout_ms(),
+            retry_after_ms: default_retry_after_ms(),
+            max_operations: default_max_operations(),
+            max_duration_ms: default_max_duration_ms(),
+        }
+    }
+}
+
+fn default_max_concurrent() -> usize {
+    8
+}
+
+fn default_max_queued() -> usize {
+    32
+}
+
+fn default_queue_timeout_ms() -> u64 {
+    5_000
+}
+
+fn default_retry_after_ms() -> u64 {
+    1_000
+}
+
+fn default_max_operations() -> u64 {
+    10_000_000
+}
+
+fn default_max_duration_ms() -> u64 {
+    30_000
+}
+
+/// Deadline of a script starting now, None without a duration limit.
+pub fn script_deadline(config: &ExecutionConfig) -> Option<Instant> {
+    (config.max_duration_ms > 0)
+        .then(|| Instant::now() + Duration::from_millis(config.max_duration_ms))
+}
+
+/// Execution slots and the queue waiting for them.
+pub struct ExecutionLimiter {
+    slots: Arc<Semaphore>,
+    queued: AtomicUsize,
+    config: ExecutionConfig,
+}
+
+impl ExecutionLimiter {
+    pub fn new(config: &ExecutionConfig) -> Self {
+        Self {
+            slots: Arc::new(Semaphore::new(config.max_concurrent.max(1))),
+            queued: AtomicUsize::new(0),
+            config: config.clone(),
+        }
+    }
+
+    fn overloaded(&self) -> EnclaveError {
+        metrics::SCRIPT_EXECUTIONS_SHED.inc();
+        EnclaveError::Overloaded {
+            retry_after_ms: self.config.retry_after_ms,
+        }
+    }
+
+    /// Take a slot, waiting in the queue if none is free. The slot is
+    /// released when the returned permit is dropped.
+    pub async fn acquire(&self) -> Result<OwnedSemaphorePermit, EnclaveError> {
+        if let Ok(permit) = self.slots.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+        if self.queued.fetch_add(1, Ordering::SeqCst) >= self.config.max_queued {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            return Err(self.overloaded());
+        }
+        let waited = tokio::time::timeout(
+            Duration::from_millis(self.config.queue_timeout_ms),
+            self.slots.clone().acquire_owned(),
+        )
+        .await;
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        match waited {
+            Ok(Ok(permit)) => Ok(permit),
+            Ok(Err(_)) => Err(EnclaveError::GenericError(
+                "Execution slots closed".to_string(),
+            )),
+            Err(_) => Err(self.overloaded()),
+        }
+    }
+}
+
+/// Fixed set of threads running the jobs sent to it, in order.
+pub struct WorkerPool {
+    jobs: Mutex<Sender<Job>>,
+}
+
+impl WorkerPool {
+    pub fn new(workers: usize) -> Self {
+        let (jobs, receiver) = std::sync::mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for index in 0..workers {
+            let receiver = receiver.clone();
+            std::thread::Builder::new()
+                .name(format!("script-worker-{}", index))
+                .spawn(move || work(&receiver))
+                .expect("Failed to start script worker");
+        }
+        Self {
+            jobs: Mutex::new(jobs),
+        }
+    }
+
+    pub fn execute(&self, job: impl FnOnce() + Send + 'static) {
+        self.jobs
+            .lock()
+            .expect("script worker queue poisoned")
+            .send(Box::new(job))
+            .expect("script workers stopped");
+    }
+}
+
+fn work(receiver: &Mutex<Receiver<Job>>) {
+    loop {
+        let job = match receiver
+            .lock()
+            .expect("script worker queue poisoned")
+            .recv()
+        {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        // A panicking script drops its result sender, the worker keeps serving
+        let _ = std::panic::catch_unwind(AssertUnwindSafe(job));
+    }
+}
+
+/// Script executions holding a slot.
+pub fn executions_in_flight() -> usize {
+    COEUS_CONFIG
+        .execution
+        .max_concurrent
+        .max(1)
+        .saturating_sub(EXECUTION_LIMITER.slots.available_permits())
+}
+
+/// Take a script execution slot, to be moved into the executing job.
+pub async fn execution_slot() -> Result<OwnedSemaphorePermit, EnclaveError> {
+    EXECUTION_LIMITER.acquire().await
+}
+
+/// Run a script on a worker, holding `slot` until it returns.
+pub fn spawn_script(slot: OwnedSemaphorePermit, script: impl FnOnce() + Send + 'static) {
+    SCRIPT_WORKERS.execute(move || {
+        let _slot = slot;
+        SCRIPT_DEADLINE.with(|deadline| deadline.set(script_deadline(&COEUS_CONFIG.execution)));
+        script();
+        SCRIPT_DEADLINE.with(|deadline| deadline.set(None));
+    });
+}
+
+/// Wait for the reply to a host function's `call`, giving up at `deadline`.
+fn wait_until<T>(
+    receiver: &Receiver<T>,
+    deadline: Option<Instant>,
+    call: &str,
+) -> Result<T, String> {
+    let Some(deadline) = deadline else {
+        return receiver
+            .recv()
+            .map_err(|_| format!("{} was cancelled", call));
+    };
+    receiver
+        .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        .map_err(|e| match e {
+            RecvTimeoutError::Timeout => {
+                format!(
+                    "Script exceeded its execution deadline waiting for {}",
+                    call
+                )
+            }
+            RecvTimeoutError::Disconnected => format!("{} was cancelled", call),
+        })
+}
+
+/// Wait for the reply to a host function's `call`, giving up at the deadline
+/// of the script running on this thread. Waits indefinitely off the workers.
+pub fn wait_for_host<T>(receiver: &Receiver<T>, call: &str) -> Result<T, String> {
+    wait_until(receiver, SCRIPT_DEADLINE.with(Cell::get), call)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_execution_limiter() {
+        let limiter = Arc::new(ExecutionLimiter::new(&ExecutionConfig {
+            max_concurrent: 1,
+            max_queued: 1,
+            queue_timeout_ms: 50,
+            retry_after_ms: 250,
+            ..ExecutionConfig::default()
+        }));
+
+        let running = limiter.acquire().await.unwrap();
+        // One execution may wait, it times out while the slot is held
+        let queued = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire().await.map(|_| ()) }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        // The queue is full, the next one is shed at once
+        assert!(matches!(
+            limiter.acquire().await,
+            Err(EnclaveError::Overloaded {
+                retry_after_ms: 250
+            })
+        ));
+        assert!(matches!(
+            queued.await.unwrap(),
+            Err(EnclaveError::Overloaded { .. })
+        ));
+
+        drop(running);
+        assert!(limiter.acquire().await.is_ok());
+    }
+
+    #[test]
+    fn test_wait_until() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        sender.send(1).unwrap();
+        let deadline = Instant::now() + Duration::from_millis(20);
+        assert_eq!(wait_until(&receiver, Some(deadline), "Sui call"), Ok(1));
+        // The reply never comes, the script stops waiting at its deadline
+        assert!(
+            wait_until(&receiver, Some(deadline), "Sui call")
+                .unwrap_err()
+                .contains("deadline")
+        );
+        drop(sender);
+        assert_eq!(
+            wait_until(&receiver, None, "Sui call"),
+            Err("Sui call was cancelled".to_string())
+        );
+    }
+
+    #[test]
+    fn test_worker_pool() {
+        let pool = WorkerPool::new(2);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        pool.execute(|| panic!("script panicked"));
+        for value in 0..4 {
+            let sender = sender.clone();
+            pool.execute(move || sender.send(value).unwrap());
+        }
+        // Both workers survive the panic and run every job
+        let mut values: Vec<i32> = receiver.iter().take(4).collect();
+        values.sort();
+        assert_eq!(values, vec![0, 1, 2, 3]);
+    }
+}
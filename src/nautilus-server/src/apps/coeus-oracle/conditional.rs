@@ -0,0 +1,166 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Conditional GET for the read endpoints relayers poll (/feeds/{id}/status,
+//! /feeds/{id}/history, /get_attestation, /bls_attestation).
+//!
+//! Responses carry an `ETag` and a `Last-Modified`. A request whose
+//! `If-None-Match` names the current tag, or without one whose
+//! `If-Modified-Since` is not older than the last change, is answered with an
+//! empty 304 before anything is copied out or serialized. Tags come from
+//! counters and timestamps the endpoints already keep, prefixed with a nonce
+//! drawn at boot so a restarted enclave never matches a tag of its previous
+//! run. They are weak, the compression layer may re-encode the body.
+//!
+//! `Last-Modified` has a resolution of a second: a client relying on
+//! `If-Modified-Since` alone can miss a change made in the same second as its
+//! last fetch, `If-None-Match` has no such gap.
+
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use std::fmt::Display;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+lazy_static::lazy_static! {
+    static ref BOOT_NONCE: u32 = rand::random();
+}
+
+/// Validators of the current representation of a resource.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Validators {
+    etag: String,
+    last_modified: Option<SystemTime>,
+}
+
+impl Validators {
+    /// Validators of the representation at `version`, last changed at
+    /// `last_modified_ms` if known.
+    pub fn new(version: impl Display, last_modified_ms: Option<u64>) -> Self {
+        Self {
+            etag: format!("W/\"{:08x}-{}\"", *BOOT_NONCE, version),
+            last_modified: last_modified_ms.map(|ms| UNIX_EPOCH + Duration::from_millis(ms)),
+        }
+    }
+
+    /// Whether the caller already has this representation.
+    fn matches(&self, headers: &HeaderMap) -> bool {
+        let if_none_match: Vec<&str> = headers
+            .get_all(header::IF_NONE_MATCH)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+        // If-Modified-Since is ignored when If-None-Match is sent (RFC 9110 13.1.3)
+        if !if_none_match.is_empty() {
+            let etag = opaque_tag(&self.etag);
+            return if_none_match
+                .into_iter()
+                .any(|tag| tag == "*" || opaque_tag(tag) == etag);
+        }
+        let since = headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| httpdate::parse_http_date(value).ok());
+        match (since, self.last_modified) {
+            (Some(since), Some(last_modified)) => truncate_to_secs(last_modified) <= since,
+            _ => false,
+        }
+    }
+
+    /// An empty 304 if the caller already has this representation.
+    pub fn not_modified(&self, headers: &HeaderMap) -> Option<Response> {
+        if !self.matches(headers) {
+            return None;
+        }
+        let mut response = StatusCode::NOT_MODIFIED.into_response();
+        self.insert_headers(response.headers_mut());
+        Some(response)
+    }
+
+    /// `response` with the validators attached.
+    pub fn respond(&self, response: impl IntoResponse) -> Response {
+        let mut response = response.into_response();
+        self.insert_headers(response.headers_mut());
+        response
+    }
+
+    fn insert_headers(&self, headers: &mut HeaderMap) {
+        insert(headers, header::ETAG, &self.etag);
+        if let Some(last_modified) = self.last_modified {
+            insert(
+                headers,
+                header::LAST_MODIFIED,
+                &httpdate::fmt_http_date(last_modified),
+            );
+        }
+        // Shared caches may keep the response but must revalidate it
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    }
+}
+
+fn insert(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+    if let Ok(value) = HeaderValue::from_str(value) {
+        headers.insert(name, value);
+    }
+}
+
+/// Entity tag without its weakness indicator, for weak comparison.
+fn opaque_tag(tag: &str) -> &str {
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
+
+fn truncate_to_secs(time: SystemTime) -> SystemTime {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn headers(name: HeaderName, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_validators() {
+        let validators = Validators::new(3, Some(1744038900500));
+        let response = validators.respond("body");
+        let etag = response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(etag.starts_with("W/\""));
+        assert_eq!(
+            response.headers()[header::LAST_MODIFIED],
+            "Mon, 07 Apr 2025 15:15:00 GMT"
+        );
+
+        assert!(validators.not_modified(&HeaderMap::new()).is_none());
+        let not_modified = validators
+            .not_modified(&headers(header::IF_NONE_MATCH, &etag))
+            .unwrap();
+        assert_eq!(not_modified.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(not_modified.headers()[header::ETAG], etag.as_str());
+        // Weak comparison, and any tag of a list
+        let strong = format!("\"other\", {}", opaque_tag(&etag));
+        assert!(validators.matches(&headers(header::IF_NONE_MATCH, &strong)));
+        assert!(validators.matches(&headers(header::IF_NONE_MATCH, "*")));
+        assert!(!Validators::new(4, None).matches(&headers(header::IF_NONE_MATCH, &etag)));
+
+        let same_second = headers(header::IF_MODIFIED_SINCE, "Mon, 07 Apr 2025 15:15:00 GMT");
+        assert!(validators.matches(&same_second));
+        let before = headers(header::IF_MODIFIED_SINCE, "Mon, 07 Apr 2025 15:14:59 GMT");
+        assert!(!validators.matches(&before));
+        // If-None-Match wins over If-Modified-Since
+        let mut both = same_second.clone();
+        both.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"other\""));
+        assert!(!validators.matches(&both));
+    }
+}
@@ -0,0 +1,143 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::arweave::ArweaveConfig;
+use super::attestation::AttestationConfig;
+use super::audit::AuditConfig;
+use super::batch::BatchConfig;
+use super::blob_cache::BlobCacheConfig;
+use super::bls::BlsConfig;
+use super::cache::CacheConfig;
+use super::call_log::CallLogConfig;
+use super::compression::CompressionConfig;
+use super::concurrency::ExecutionConfig;
+use super::dry_run::DryRunConfig;
+use super::encryption::EncryptionConfig;
+use super::events::EventsConfig;
+use super::evm::EvmConfig;
+use super::feed_allowlist::FeedAllowlistConfig;
+use super::feed_keys::FeedKeyConfig;
+use super::feed_object::FeedObjectConfig;
+use super::gas_station::GasConfig;
+use super::history::HistoryConfig;
+use super::http_proxy::HttpConfig;
+use super::ipfs::IpfsConfig;
+use super::memory::MemoryConfig;
+use super::openapi::DocsConfig;
+use super::policy::UpdatePolicy;
+use super::publisher::PublisherConfig;
+use super::replay::ReplayConfig;
+use super::rotation::RotationConfig;
+use super::scheduler::SchedulerConfig;
+use super::sign_digest::SignDigestConfig;
+use super::submit::PushConfig;
+use super::threshold::ThresholdConfig;
+use super::walrus::WalrusConfig;
+use super::webhook::WebhookConfig;
+use crate::seal_store::SealConfig;
+use fastcrypto::hash::{HashFunction, Sha256};
+use serde::{Deserialize, Serialize};
+
+/// Raw coeus_config.yaml, compiled into the image.
+pub const COEUS_CONFIG_YAML: &str = include_str!("coeus_config.yaml");
+
+lazy_static::lazy_static! {
+    /// Coeus oracle configuration. It is compiled into the enclave image
+    /// so it is covered by the attested PCRs.
+    pub static ref COEUS_CONFIG: CoeusConfig = {
+        serde_yaml::from_str(COEUS_CONFIG_YAML)
+            .expect("Failed to parse coeus_config.yaml")
+    };
+}
+
+/// SHA-256 of coeus_config.yaml, committed to in attestations so verifiers
+/// can tell which configuration the enclave runs with.
+pub fn config_hash() -> [u8; 32] {
+    Sha256::digest(COEUS_CONFIG_YAML.as_bytes()).digest
+}
+
+/// Top level layout of coeus_config.yaml
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CoeusConfig {
+    #[serde(default)]
+    pub scheduler: SchedulerConfig,
+    #[serde(default)]
+    pub push: PushConfig,
+    #[serde(default)]
+    pub gas: GasConfig,
+    #[serde(default)]
+    pub events: EventsConfig,
+    #[serde(default)]
+    pub feed_object: FeedObjectConfig,
+    #[serde(default)]
+    pub feed_allowlist: FeedAllowlistConfig,
+    #[serde(default)]
+    pub dry_run: DryRunConfig,
+    #[serde(default)]
+    pub evm: EvmConfig,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    #[serde(default)]
+    pub update_policies: Vec<UpdatePolicy>,
+    #[serde(default)]
+    pub history: HistoryConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub walrus: WalrusConfig,
+    #[serde(default)]
+    pub blob_cache: BlobCacheConfig,
+    #[serde(default)]
+    pub ipfs: IpfsConfig,
+    #[serde(default)]
+    pub arweave: ArweaveConfig,
+    #[serde(default)]
+    pub publisher: PublisherConfig,
+    #[serde(default)]
+    pub compression: CompressionConfig,
+    #[serde(default)]
+    pub bls: BlsConfig,
+    #[serde(default)]
+    pub rotation: RotationConfig,
+    #[serde(default)]
+    pub attestation: AttestationConfig,
+    #[serde(default)]
+    pub threshold: ThresholdConfig,
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub sign_digest: SignDigestConfig,
+    #[serde(default)]
+    pub replay: ReplayConfig,
+    #[serde(default)]
+    pub feed_keys: Vec<FeedKeyConfig>,
+    /// Seal configuration the feed key master seed is sealed with, required
+    /// when feed_keys is set.
+    #[serde(default)]
+    pub feed_key_seal: Option<SealConfig>,
+    #[serde(default)]
+    pub execution: ExecutionConfig,
+    #[serde(default)]
+    pub call_log: CallLogConfig,
+    #[serde(default)]
+    pub batch: BatchConfig,
+    #[serde(default)]
+    pub memory: MemoryConfig,
+    #[serde(default)]
+    pub docs: DocsConfig,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        // The bundled config must always parse, otherwise the enclave panics on first use
+        let _ = &*COEUS_CONFIG;
+    }
+}
@@ -0,0 +1,329 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Executor for `CodeExtension::JSON_CONFIG` feeds. The blob is a declarative
+//! spec instead of a script:
+//!
+//! ```json
+//! {
+//!   "sources": [
+//!     { "url": "https://api.coingecko.com/api/v3/simple/price?ids=sui&vs_currencies=usd",
+//!       "path": "$.sui.usd" },
+//!     { "url": "https://example.com/price", "headers": { "Accept": "application/json" },
+//!       "path": "$.data[0].price" }
+//!   ],
+//!   "aggregation": "median",
+//!   "scale": 1000000,
+//!   "min_sources": 2
+//! }
+//! ```
+
+use super::config::COEUS_CONFIG;
+use super::http_body::read_json_capped;
+use super::http_proxy;
+use super::{ResultMeta, ReturnType, RhaiOutput, convert_rhai_result, json_value_to_dynamic};
+use crate::EnclaveError;
+use crate::admin;
+use rhai::Dynamic;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::warn;
+
+/// A declarative feed specification.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeedSpec {
+    pub sources: Vec<SourceSpec>,
+    #[serde(default)]
+    pub aggregation: Aggregation,
+    /// Multiplier applied to numeric values before aggregation, e.g. 1e6 for 6 decimals.
+    #[serde(default)]
+    pub scale: Option<f64>,
+    /// Minimum number of sources that must succeed, defaults to all of them.
+    #[serde(default)]
+    pub min_sources: Option<usize>,
+}
+
+/// One upstream HTTP source and the path of the value in its JSON response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SourceSpec {
+    pub url: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// JSONPath subset: `$.field.nested[0].value`
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregation {
+    /// Value of the first successful source, the only mode for non-numeric values.
+    #[default]
+    First,
+    Median,
+    Mean,
+    Min,
+    Max,
+}
+
+/// Parse a path like `$.data[0].price` into segments.
+fn parse_path(path: &str) -> Result<Vec<PathSegment>, String> {
+    let rest = path.strip_prefix('$').unwrap_or(path);
+    let mut segments = Vec::new();
+    for part in rest.split('.').filter(|p| !p.is_empty()) {
+        let (key, mut indices) = match part.find('[') {
+            Some(i) => (&part[..i], &part[i..]),
+            None => (part, ""),
+        };
+        if !key.is_empty() {
+            segments.push(PathSegment::Key(key.to_string()));
+        }
+        while let Some(stripped) = indices.strip_prefix('[') {
+            let end = stripped
+                .find(']')
+                .ok_or_else(|| format!("Unclosed '[' in path {}", path))?;
+            let index = stripped[..end]
+                .parse::<usize>()
+                .map_err(|e| format!("Invalid index in path {}: {}", path, e))?;
+            segments.push(PathSegment::Index(index));
+            indices = &stripped[end + 1..];
+        }
+        if !indices.is_empty() {
+            return Err(format!("Invalid path segment '{}' in {}", part, path));
+        }
+    }
+    Ok(segments)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// Resolve a JSONPath subset against a value.
+pub fn select<'a>(value: &'a JsonValue, path: &str) -> Result<&'a JsonValue, String> {
+    let mut current = value;
+    for segment in parse_path(path)? {
+        current = match &segment {
+            PathSegment::Key(key) => current.get(key),
+            PathSegment::Index(index) => current.get(index),
+        }
+        .ok_or_else(|| format!("Path {} not found at {:?}", path, segment))?;
+    }
+    Ok(current)
+}
+
+/// Numeric view of a JSON value, accepting numeric strings as many APIs quote prices.
+fn as_number(value: &JsonValue) -> Option<f64> {
+    match value {
+        JsonValue::Number(n) => n.as_f64(),
+        JsonValue::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn aggregate(values: &mut [f64], aggregation: &Aggregation) -> f64 {
+    let first = values[0];
+    values.sort_by(|a, b| a.total_cmp(b));
+    match aggregation {
+        Aggregation::First => first,
+        Aggregation::Min => values[0],
+        Aggregation::Max => values[values.len() - 1],
+        Aggregation::Mean => values.iter().sum::<f64>() / values.len() as f64,
+        Aggregation::Median => {
+            let mid = values.len() / 2;
+            if values.len() % 2 == 0 {
+                (values[mid - 1] + values[mid]) / 2.0
+            } else {
+                values[mid]
+            }
+        }
+    }
+}
+
+/// Timeout of a source request.
+const SOURCE_TIMEOUT: Duration = Duration::from_secs(10);
+
+async fn fetch_source(client: &reqwest::Client, source: &SourceSpec) -> Result<JsonValue, String> {
+    if !admin::host_allowed(&source.url) {
+        return Err(format!("Host of {} is not in the allowlist", source.url));
+    }
+    let mut request = client.get(&source.url).timeout(SOURCE_TIMEOUT);
+    for (name, value) in &source.headers {
+        request = request.header(name, value);
+    }
+    let response = request
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .map_err(|e| format!("{}: {}", source.url, e))?;
+    let json: JsonValue = read_json_capped(response, COEUS_CONFIG.http.max_response_bytes)
+        .await
+        .map_err(|e| format!("{}: {}", source.url, e))?;
+    select(&json, &source.path)
+        .cloned()
+        .map_err(|e| format!("{}: {}", source.url, e))
+}
+
+/// Combine the values extracted from each source into a single Rhai-compatible value.
+fn combine(spec: &FeedSpec, values: Vec<JsonValue>) -> Result<(Dynamic, ResultMeta), String> {
+    let mut meta = ResultMeta {
+        source_count: Some(values.len() as u64),
+        ..Default::default()
+    };
+    if spec.aggregation == Aggregation::First && spec.scale.is_none() {
+        return Ok((json_value_to_dynamic(&values[0]), meta));
+    }
+
+    let scale = spec.scale.unwrap_or(1.0);
+    let mut numbers = values
+        .iter()
+        .map(|v| as_number(v).map(|n| n * scale))
+        .collect::<Option<Vec<f64>>>()
+        .ok_or_else(|| "Aggregation requires numeric values".to_string())?;
+    let value = aggregate(&mut numbers, &spec.aggregation);
+    if numbers.len() > 1 && numbers[0] >= 0.0 {
+        meta.spread = Some((numbers[numbers.len() - 1] - numbers[0]).round() as u64);
+    }
+    Ok((Dynamic::from(value), meta))
+}
+
+/// Execute a declarative spec and convert the result like a Rhai script result.
+pub async fn execute_spec(
+    spec_json: &str,
+    expected_type: &ReturnType,
+    strict_types: bool,
+) -> Result<RhaiOutput, EnclaveError> {
+    let spec: FeedSpec =
+        serde_json::from_str(spec_json).map_err(|e| EnclaveError::ScriptError {
+            line: Some(e.line()),
+            message: format!("Invalid feed spec: {}", e),
+        })?;
+    if spec.sources.is_empty() {
+        return Err(EnclaveError::ScriptError {
+            line: None,
+            message: "Feed spec has no sources".to_string(),
+        });
+    }
+    for source in &spec.sources {
+        parse_path(&source.path).map_err(|message| EnclaveError::ScriptError {
+            line: None,
+            message,
+        })?;
+    }
+
+    // Sources are requested together, with the pooled client of host functions
+    let fetches: Vec<_> = spec
+        .sources
+        .iter()
+        .cloned()
+        .map(|source| {
+            http_proxy::spawn_request(
+                move |client| async move { fetch_source(client, &source).await },
+            )
+        })
+        .collect();
+    let mut values = Vec::new();
+    let mut errors = Vec::new();
+    for fetch in fetches {
+        match fetch.await {
+            Ok(value) => values.push(value),
+            Err(e) => {
+                warn!("Feed spec source failed: {}", e);
+                errors.push(e);
+            }
+        }
+    }
+
+    let min_sources = spec.min_sources.unwrap_or(spec.sources.len()).max(1);
+    if values.len() < min_sources {
+        return Err(EnclaveError::ScriptError {
+            line: None,
+            message: format!(
+                "Only {} of {} required sources succeeded: {}",
+                values.len(),
+                min_sources,
+                errors.join("; ")
+            ),
+        });
+    }
+
+    let (dynamic, meta) = combine(&spec, values).map_err(|message| EnclaveError::ScriptError {
+        line: None,
+        message,
+    })?;
+    Ok(RhaiOutput {
+        result: convert_rhai_result(dynamic, expected_type, strict_types)?,
+        meta,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::app::ResultValue;
+    use serde_json::json;
+
+    #[test]
+    fn test_select() {
+        let value = json!({"sui": {"usd": 1.23}, "data": [{"price": "4.5"}, {"price": 6}]});
+        assert_eq!(select(&value, "$.sui.usd").unwrap(), &json!(1.23));
+        assert_eq!(select(&value, "sui.usd").unwrap(), &json!(1.23));
+        assert_eq!(select(&value, "$.data[1].price").unwrap(), &json!(6));
+        assert!(select(&value, "$.data[2].price").is_err());
+        assert!(select(&value, "$.missing").is_err());
+        assert!(select(&value, "$.data[x]").is_err());
+    }
+
+    #[test]
+    fn test_combine() {
+        let spec = |aggregation: Aggregation, scale: Option<f64>| FeedSpec {
+            sources: vec![],
+            aggregation,
+            scale,
+            min_sources: None,
+        };
+        let values = vec![json!(1.0), json!("3"), json!(2)];
+
+        let (value, meta) =
+            combine(&spec(Aggregation::Median, Some(100.0)), values.clone()).unwrap();
+        assert_eq!(
+            convert_rhai_result(value, &ReturnType::NUMBER, false).unwrap(),
+            Some(ResultValue::NUMBER(200))
+        );
+        assert_eq!(meta.source_count, Some(3));
+        assert_eq!(meta.spread, Some(200));
+
+        let (value, _) = combine(&spec(Aggregation::Mean, None), values.clone()).unwrap();
+        assert_eq!(value.as_float().unwrap(), 2.0);
+        let (value, _) = combine(&spec(Aggregation::Max, None), values).unwrap();
+        assert_eq!(value.as_float().unwrap(), 3.0);
+
+        // First without scaling keeps the raw value
+        let (value, _) = combine(&spec(Aggregation::First, None), vec![json!("abc")]).unwrap();
+        assert_eq!(
+            convert_rhai_result(value, &ReturnType::STRING, true).unwrap(),
+            Some(ResultValue::STRING("abc".to_string()))
+        );
+
+        assert!(combine(&spec(Aggregation::Mean, None), vec![json!(true)]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_spec() {
+        assert!(
+            execute_spec("{\"sources\": []}", &ReturnType::NUMBER, false)
+                .await
+                .is_err()
+        );
+        assert!(
+            execute_spec("{\"url\": \"x\"}", &ReturnType::NUMBER, false)
+                .await
+                .is_err()
+        );
+    }
+}
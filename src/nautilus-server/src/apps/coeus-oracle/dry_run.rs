@@ -0,0 +1,463 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! `sui_dry_run` host function: feed scripts simulate a Move call on the
+//! fullnode and read its return values, e.g. a DEX quote function, so a feed
+//! can publish values only the chain computes.
+//!
+//! ```rhai
+//! let out = sui_dry_run("0x...::pool::quote", ["0x2::sui::SUI"],
+//!     [#{object: "0x..."}, #{u64: 1000000000}]);
+//! bcs_u64(out[0])
+//! ```
+//!
+//! Arguments are maps with a single key: `object` (an object id, resolved to
+//! its current version), `u8`, `u64`, `bool`, `address`, `string`, `bytes`
+//! (vector<u8>) or `pure` (raw BCS bytes). Each return value comes back as its
+//! BCS bytes; `bcs_u64` and `bcs_bool` decode the common cases.
+//!
+//! The call is simulated with transaction checks disabled, so no gas coin or
+//! signature is needed, against the fullnode's latest state. Only packages in
+//! `dry_run.packages` may be called when it is set, and a script makes at most
+//! `max_calls` dry runs. The values end up in the feed result, signed like any
+//! other. Rhai only.
+
+use super::concurrency;
+use super::config::COEUS_CONFIG;
+use super::submit::{identifier, parse_type_tag};
+use crate::sui_pool::SuiPool;
+use crate::{AppState, EnclaveError, metrics};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, RwLock};
+use sui_rpc::field::{FieldMask, FieldMaskUtil};
+use sui_rpc::proto::sui::rpc::v2::owner::OwnerKind;
+use sui_rpc::proto::sui::rpc::v2::simulate_transaction_request::TransactionChecks;
+use sui_rpc::proto::sui::rpc::v2::{GetObjectRequest, SimulateTransactionRequest};
+use sui_sdk_types::{
+    Address, Argument, Command, Digest, GasPayment, Input, MoveCall, ObjectReference,
+    ProgrammableTransaction, Transaction, TransactionExpiration, TransactionKind,
+};
+use tokio::runtime::Handle;
+
+/// Gas price of simulated transactions, not checked with checks disabled.
+const GAS_PRICE: u64 = 1000;
+
+lazy_static::lazy_static! {
+    /// Server state and runtime host functions reach Sui through, set at
+    /// startup.
+    static ref SERVER: RwLock<Option<(Arc<AppState>, Handle)>> = RwLock::new(None);
+}
+
+/// Dry run section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DryRunConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Packages scripts may call, any package when empty.
+    #[serde(default)]
+    pub packages: Vec<String>,
+    /// Dry runs per script execution.
+    #[serde(default = "default_max_calls")]
+    pub max_calls: u32,
+    /// `sui_object` reads per script execution.
+    #[serde(default = "default_max_object_reads")]
+    pub max_object_reads: u32,
+    #[serde(default = "default_gas_budget")]
+    pub gas_budget: u64,
+}
+
+impl Default for DryRunConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            packages: Vec::new(),
+            max_calls: default_max_calls(),
+            max_object_reads: default_max_object_reads(),
+            gas_budget: default_gas_budget(),
+        }
+    }
+}
+
+fn default_max_calls() -> u32 {
+    8
+}
+
+fn default_max_object_reads() -> u32 {
+    32
+}
+
+fn default_gas_budget() -> u64 {
+    50_000_000
+}
+
+/// Let script host functions read and simulate through the server's Sui
+/// backends. Called from the runtime, which then runs the calls scripts make.
+pub fn init_script_runtime(state: &Arc<AppState>) {
+    *SERVER.write().expect("script runtime lock poisoned") =
+        Some((state.clone(), Handle::current()));
+}
+
+/// Run `call` on the server runtime and wait for it on the script's thread.
+pub fn on_server<T, F, Fut>(call: F) -> Result<T, String>
+where
+    F: FnOnce(Arc<AppState>) -> Fut,
+    Fut: Future<Output = Result<T, EnclaveError>> + Send + 'static,
+    T: Send + 'static,
+{
+    let Some((state, runtime)) = SERVER.read().expect("script runtime lock poisoned").clone()
+    else {
+        return Err("Sui host functions are not initialized".to_string());
+    };
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let call = call(state);
+    runtime.spawn(async move {
+        let _ = sender.send(call.await);
+    });
+    concurrency::wait_for_host(&receiver, "Sui call")?.map_err(|e| e.to_string())
+}
+
+/// Split `0x<package>::<module>::<function>`.
+fn parse_target(target: &str) -> Result<(Address, &str, &str), String> {
+    let mut parts = target.splitn(3, "::");
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(package), Some(module), Some(function)) => {
+            let package = Address::from_hex(package)
+                .map_err(|e| format!("Invalid package {}: {}", package, e))?;
+            Ok((package, module, function))
+        }
+        _ => Err(format!(
+            "Invalid target {}, expected <package>::<module>::<function>",
+            target
+        )),
+    }
+}
+
+fn allowed(config: &DryRunConfig, package: &Address) -> bool {
+    config.packages.is_empty()
+        || config
+            .packages
+            .iter()
+            .filter_map(|allowed| Address::from_hex(allowed).ok())
+            .any(|allowed| &allowed == package)
+}
+
+/// An argument before object ids are resolved.
+#[derive(Debug, PartialEq)]
+enum Arg {
+    Object(Address),
+    Pure(Vec<u8>),
+}
+
+fn bytes(value: Dynamic) -> Result<Vec<u8>, String> {
+    value
+        .try_cast::<Array>()
+        .ok_or("expected an array of bytes")?
+        .into_iter()
+        .map(|byte| {
+            byte.as_int()
+                .ok()
+                .and_then(|byte| u8::try_from(byte).ok())
+                .ok_or_else(|| "expected an array of bytes".to_string())
+        })
+        .collect()
+}
+
+fn parse_arg(arg: Dynamic) -> Result<Arg, String> {
+    let map = arg
+        .try_cast::<Map>()
+        .ok_or("arguments are maps, e.g. #{u64: 1}")?;
+    let mut entries = map.into_iter();
+    let (Some((kind, value)), None) = (entries.next(), entries.next()) else {
+        return Err("an argument map has a single key".to_string());
+    };
+    let int = |value: &Dynamic| {
+        value
+            .as_int()
+            .map_err(|_| format!("{} expects an integer", kind))
+    };
+    let string = |value: Dynamic| {
+        value
+            .into_string()
+            .map_err(|_| format!("{} expects a string", kind))
+    };
+    let encoded = match kind.as_str() {
+        "object" => {
+            let id = string(value)?;
+            let id = Address::from_hex(&id).map_err(|e| format!("Invalid object {}: {}", id, e))?;
+            return Ok(Arg::Object(id));
+        }
+        "pure" => return bytes(value).map(Arg::Pure),
+        "u8" => {
+            let value = u8::try_from(int(&value)?).map_err(|e| e.to_string())?;
+            bcs::to_bytes(&value)
+        }
+        "u64" => {
+            let value = u64::try_from(int(&value)?).map_err(|e| e.to_string())?;
+            bcs::to_bytes(&value)
+        }
+        "bool" => bcs::to_bytes(&value.as_bool().map_err(|_| "bool expects a bool")?),
+        "address" => {
+            let address = string(value)?;
+            let address = Address::from_hex(&address)
+                .map_err(|e| format!("Invalid address {}: {}", address, e))?;
+            bcs::to_bytes(&address)
+        }
+        "string" => bcs::to_bytes(&string(value)?),
+        "bytes" => bcs::to_bytes(&bytes(value)?),
+        other => return Err(format!("Unknown argument kind {}", other)),
+    };
+    encoded
+        .map(Arg::Pure)
+        .map_err(|e| format!("Failed to encode {}: {}", kind, e))
+}
+
+/// Input for object `id` at its current version.
+async fn object_input(sui: &SuiPool, id: Address) -> Result<Input, EnclaveError> {
+    let request = GetObjectRequest::new(&id)
+        .with_read_mask(FieldMask::from_paths(["owner", "version", "digest"]));
+    let object = sui
+        .call("get_object", |mut client| {
+            let request = request.clone();
+            async move { client.ledger_client().get_object(request).await }
+        })
+        .await
+        .map_err(|e| {
+            EnclaveError::RpcError(format!("Failed to get object {}: {}", id, e.message()))
+        })?
+        .into_inner()
+        .object
+        .ok_or_else(|| EnclaveError::RpcError(format!("No object {} returned", id)))?;
+    if let Some(owner) = object
+        .owner
+        .filter(|owner| owner.kind() == OwnerKind::Shared)
+    {
+        let initial_shared_version = owner.version.ok_or_else(|| {
+            EnclaveError::RpcError(format!("Shared object {} has no initial version", id))
+        })?;
+        return Ok(Input::Shared {
+            object_id: id,
+            initial_shared_version,
+            mutable: false,
+        });
+    }
+    let version = object
+        .version
+        .ok_or_else(|| EnclaveError::RpcError(format!("Object {} has no version", id)))?;
+    let digest = object
+        .digest
+        .as_deref()
+        .and_then(|digest| Digest::from_base58(digest).ok())
+        .ok_or_else(|| EnclaveError::RpcError(format!("Object {} has no digest", id)))?;
+    Ok(Input::ImmutableOrOwned(ObjectReference::new(
+        id, version, digest,
+    )))
+}
+
+/// Simulate `call` on `args` and return the BCS of its return values.
+async fn simulate(
+    sui: &SuiPool,
+    mut call: MoveCall,
+    args: Vec<Arg>,
+    gas_budget: u64,
+) -> Result<Vec<Vec<u8>>, EnclaveError> {
+    let mut inputs = Vec::with_capacity(args.len());
+    for arg in args {
+        inputs.push(match arg {
+            Arg::Object(id) => object_input(sui, id).await?,
+            Arg::Pure(value) => Input::Pure { value },
+        });
+    }
+    call.arguments = (0..inputs.len() as u16).map(Argument::Input).collect();
+    let transaction = Transaction {
+        kind: TransactionKind::ProgrammableTransaction(ProgrammableTransaction {
+            inputs,
+            commands: vec![Command::MoveCall(call)],
+        }),
+        sender: Address::ZERO,
+        gas_payment: GasPayment {
+            objects: vec![],
+            owner: Address::ZERO,
+            price: GAS_PRICE,
+            budget: gas_budget,
+        },
+        expiration: TransactionExpiration::None,
+    };
+
+    let mut request = SimulateTransactionRequest::default();
+    request.transaction = Some(transaction.into());
+    request.set_checks(TransactionChecks::Disabled);
+    request.read_mask = Some(FieldMask::from_paths([
+        "transaction.effects.status",
+        "outputs.return_values.value",
+    ]));
+    let response = sui
+        .call("simulate_transaction", |mut client| {
+            let request = request.clone();
+            async move {
+                client
+                    .execution_client()
+                    .simulate_transaction(request)
+                    .await
+            }
+        })
+        .await
+        .map_err(|e| EnclaveError::RpcError(format!("Dry run failed: {}", e.message())))?
+        .into_inner();
+    let status = response
+        .transaction
+        .and_then(|transaction| transaction.effects)
+        .and_then(|effects| effects.status);
+    if let Some(status) = status.filter(|status| status.success != Some(true)) {
+        return Err(EnclaveError::GenericError(format!(
+            "Dry run aborted: {:?}",
+            status.error
+        )));
+    }
+    let outputs =
+        response.outputs.into_iter().next_back().ok_or_else(|| {
+            EnclaveError::RpcError("Dry run returned no command output".to_string())
+        })?;
+    outputs
+        .return_values
+        .into_iter()
+        .map(|output| {
+            output
+                .value
+                .and_then(|bcs| bcs.value)
+                .map(|value| value.to_vec())
+                .ok_or_else(|| EnclaveError::RpcError("Dry run returned no value".to_string()))
+        })
+        .collect()
+}
+
+/// Body of `sui_dry_run`, run on the script's thread.
+fn dry_run(
+    calls: &AtomicU32,
+    target: &str,
+    type_args: Array,
+    args: Array,
+) -> Result<Vec<Vec<u8>>, String> {
+    let config = &COEUS_CONFIG.dry_run;
+    if !config.enabled {
+        return Err("sui_dry_run is disabled, see dry_run in coeus_config.yaml".to_string());
+    }
+    if calls.fetch_add(1, Ordering::Relaxed) >= config.max_calls {
+        return Err(format!(
+            "A script may make at most {} dry runs",
+            config.max_calls
+        ));
+    }
+    let (package, module, function) = parse_target(target)?;
+    if !allowed(config, &package) {
+        return Err(format!("Package {} may not be called", package));
+    }
+    let call = MoveCall {
+        package,
+        module: identifier(module).map_err(|e| e.to_string())?,
+        function: identifier(function).map_err(|e| e.to_string())?,
+        type_arguments: type_args
+            .into_iter()
+            .map(|type_arg| parse_type_tag(&type_arg.to_string()).map_err(|e| e.to_string()))
+            .collect::<Result<_, _>>()?,
+        arguments: vec![],
+    };
+    let args = args
+        .into_iter()
+        .map(parse_arg)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let gas_budget = config.gas_budget;
+    let outcome =
+        on_server(|state| async move { simulate(&state.sui, call, args, gas_budget).await });
+    let label = if outcome.is_ok() { "ok" } else { "error" };
+    metrics::HOST_DRY_RUNS.with_label_values(&[label]).inc();
+    outcome
+}
+
+fn to_array(bytes: Vec<u8>) -> Dynamic {
+    Dynamic::from_array(
+        bytes
+            .into_iter()
+            .map(|byte| Dynamic::from_int(byte.into()))
+            .collect(),
+    )
+}
+
+/// Register `sui_dry_run` and the BCS decoders. Dry runs are counted per
+/// engine, so per script execution.
+pub fn register_dry_run_fns(engine: &mut Engine) {
+    let calls = Arc::new(AtomicU32::new(0));
+    engine.register_fn(
+        "sui_dry_run",
+        move |target: &str, type_args: Array, args: Array| -> Result<Array, Box<EvalAltResult>> {
+            let values = dry_run(&calls, target, type_args, args)?;
+            Ok(values.into_iter().map(to_array).collect())
+        },
+    );
+    engine.register_fn(
+        "bcs_u64",
+        |value: Array| -> Result<i64, Box<EvalAltResult>> {
+            let value: u64 = bcs::from_bytes(&bytes(Dynamic::from_array(value))?)
+                .map_err(|e| format!("Not a BCS u64: {}", e))?;
+            i64::try_from(value).map_err(|_| format!("{} does not fit in an integer", value).into())
+        },
+    );
+    engine.register_fn(
+        "bcs_bool",
+        |value: Array| -> Result<bool, Box<EvalAltResult>> {
+            bcs::from_bytes(&bytes(Dynamic::from_array(value))?)
+                .map_err(|e| format!("Not a BCS bool: {}", e).into())
+        },
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_args() {
+        let (package, module, function) = parse_target("0x2::pool::quote").unwrap();
+        assert_eq!(package, Address::from_hex("0x2").unwrap());
+        assert_eq!((module, function), ("pool", "quote"));
+        assert!(parse_target("0x2::pool").is_err());
+
+        let mut config = DryRunConfig::default();
+        assert!(allowed(&config, &package));
+        config.packages = vec!["0x3".to_string()];
+        assert!(!allowed(&config, &package));
+
+        let mut engine = Engine::new();
+        register_dry_run_fns(&mut engine);
+        let args: Array = engine
+            .eval(r#"[#{u64: 5}, #{bool: true}, #{object: "0x6"}, #{bytes: [1, 2]}]"#)
+            .unwrap();
+        let args = args
+            .into_iter()
+            .map(parse_arg)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            args,
+            vec![
+                Arg::Pure(5u64.to_le_bytes().to_vec()),
+                Arg::Pure(vec![1]),
+                Arg::Object(Address::from_hex("0x6").unwrap()),
+                Arg::Pure(vec![2, 1, 2]),
+            ]
+        );
+        let bad: Array = engine.eval(r#"[#{u64: -1}, #{u64: 1, u8: 1}, 5]"#).unwrap();
+        assert!(bad.into_iter().all(|arg| parse_arg(arg).is_err()));
+
+        // Disabled in the default config
+        let calls = AtomicU32::new(0);
+        assert!(dry_run(&calls, "0x2::pool::quote", vec![], vec![]).is_err());
+
+        let value: i64 = engine.eval("bcs_u64([7, 0, 0, 0, 0, 0, 0, 0])").unwrap();
+        assert_eq!(value, 7);
+        assert!(engine.eval::<i64>("bcs_u64([7])").is_err());
+    }
+}
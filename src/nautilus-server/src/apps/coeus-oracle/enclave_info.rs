@@ -0,0 +1,85 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! GET /enclave_info: what the running enclave reports about itself, so
+//! integrators can cross-check it against the PCRs registered on-chain
+//! (EnclaveConfig) and the key of the registered Enclave object.
+
+use super::config::config_hash;
+use super::rotation;
+use crate::common::{KeyDerivation, describe_pcr, key_derivation};
+use crate::{AppState, EnclaveError};
+use axum::Json;
+use axum::extract::State;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{KeyPair, ToFromBytes};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Build commit, passed by the Makefile as a docker build arg.
+pub const GIT_REVISION: &str = match option_env!("GIT_REVISION") {
+    Some(revision) => revision,
+    None => "unknown",
+};
+
+/// PCRs reported by /enclave_info: 0-2 measure the image, 3 the IAM role,
+/// 4 the instance id and 8 the signing certificate.
+const REPORTED_PCRS: [u16; 6] = [0, 1, 2, 3, 4, 8];
+
+/// Image measurements, the values registered in the on-chain EnclaveConfig.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Measurements {
+    /// PCR0, hash of the enclave image file.
+    pub image: String,
+    /// PCR1, hash of the kernel and bootstrap.
+    pub kernel: String,
+    /// PCR2, hash of the application.
+    pub application: String,
+}
+
+/// Response for GET /enclave_info.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EnclaveInfoResponse {
+    /// Hex PCR values by index.
+    pub pcrs: BTreeMap<u16, String>,
+    pub measurements: Measurements,
+    /// Git commit the image was built from.
+    pub git_revision: String,
+    /// Crate version.
+    pub version: String,
+    /// Hex public key results are currently signed with.
+    pub public_key: String,
+    /// Hex SHA-256 of coeus_config.yaml.
+    pub config_hash: String,
+    /// Hex hash of the server configuration (nautilus.toml and overrides).
+    pub app_config_hash: String,
+    /// Entropy source the signing keys were generated from.
+    pub key_derivation: KeyDerivation,
+}
+
+/// Endpoint reporting the PCRs, build and signing key of the running enclave.
+pub async fn get_enclave_info(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<EnclaveInfoResponse>, EnclaveError> {
+    let pcrs = REPORTED_PCRS
+        .iter()
+        .map(|&index| describe_pcr(index).map(|data| (index, Hex::encode(data))))
+        .collect::<Result<BTreeMap<_, _>, _>>()?;
+    let measurements = Measurements {
+        image: pcrs[&0].clone(),
+        kernel: pcrs[&1].clone(),
+        application: pcrs[&2].clone(),
+    };
+    let (signing_kp, _) = rotation::signing_keys(&state).await;
+    Ok(Json(EnclaveInfoResponse {
+        pcrs,
+        measurements,
+        git_revision: GIT_REVISION.to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        public_key: Hex::encode(signing_kp.public().as_bytes()),
+        config_hash: Hex::encode(config_hash()),
+        app_config_hash: Hex::encode(state.config.hash()),
+        key_derivation: key_derivation(),
+    }))
+}
@@ -0,0 +1,246 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Encryption of signed results to a consumer's X25519 public key, so a
+//! relayer forwarding the response cannot read the result.
+//!
+//! The whole signed response (intent message, signatures, metadata) is
+//! serialized as JSON and sealed with ECIES:
+//! - an ephemeral X25519 key agrees a shared secret with the recipient key,
+//! - HKDF-SHA3-256(shared, salt = ephemeral pk || recipient pk, info =
+//!   "coeus-oracle-result") derives an AES-256-GCM key,
+//! - the JSON is encrypted under a random 12 byte nonce with the app id as
+//!   associated data.
+//!
+//! The recipient decrypts, then verifies and submits the signed result as
+//! usual. Results of feeds configured with a recipient are only ever sealed to
+//! that recipient, a request naming another one is refused, and they are kept
+//! out of the result cache, the scheduled results, the history and webhooks.
+
+use super::config::COEUS_CONFIG;
+use super::{APP_ID, UpdateOracleHttpResponse};
+use crate::EnclaveError;
+use aes_gcm::Aes256Gcm;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hmac::{HkdfIkm, hkdf_sha3_256};
+use fastcrypto::traits::ToFromBytes;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sui_sdk_types::Address;
+use utoipa::ToSchema;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+const HKDF_INFO: &[u8] = b"coeus-oracle-result";
+const NONCE_LENGTH: usize = 12;
+
+/// Encryption section of coeus_config.yaml.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EncryptionConfig {
+    /// Feeds whose results are always encrypted.
+    #[serde(default)]
+    pub feeds: Vec<EncryptedFeedConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedFeedConfig {
+    pub feed_id: String,
+    /// Hex X25519 public key of the consumer.
+    pub recipient_public_key: String,
+}
+
+/// A signed response encrypted to a recipient, all fields Hex.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct EncryptedResult {
+    pub recipient_public_key: String,
+    pub ephemeral_public_key: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// process_data output: the signed response, or the signed response
+/// encrypted to a recipient.
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+#[serde(untagged)]
+pub enum ResultOutput {
+    Encrypted { encrypted: EncryptedResult },
+    Plain(UpdateOracleHttpResponse),
+}
+
+/// Parse a hex X25519 public key.
+pub fn parse_recipient(hex: &str) -> Result<PublicKey, EnclaveError> {
+    Hex::decode(hex)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .map(PublicKey::from)
+        .ok_or_else(|| EnclaveError::GenericError(format!("Invalid X25519 public key {}", hex)))
+}
+
+/// Recipient configured for a feed in coeus_config.yaml.
+pub fn feed_recipient(feed_id: Address) -> Option<&'static str> {
+    COEUS_CONFIG
+        .encryption
+        .feeds
+        .iter()
+        .find(|feed| Address::from_hex(&feed.feed_id).ok() == Some(feed_id))
+        .map(|feed| feed.recipient_public_key.as_str())
+}
+
+/// Whether results of a feed must not leave the enclave in plaintext.
+pub fn is_confidential(feed_id: Address) -> bool {
+    feed_recipient(feed_id).is_some()
+}
+
+fn derive_key(
+    shared_secret: &[u8; 32],
+    ephemeral: &PublicKey,
+    recipient: &PublicKey,
+) -> Result<Vec<u8>, EnclaveError> {
+    let ikm = HkdfIkm::from_bytes(shared_secret)
+        .map_err(|e| EnclaveError::GenericError(format!("Invalid shared secret: {}", e)))?;
+    let salt = [ephemeral.as_bytes().as_slice(), recipient.as_bytes()].concat();
+    hkdf_sha3_256(&ikm, &salt, HKDF_INFO, 32)
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to derive key: {}", e)))
+}
+
+/// Encrypt `plaintext` to `recipient`.
+pub fn encrypt(recipient: &PublicKey, plaintext: &[u8]) -> Result<EncryptedResult, EnclaveError> {
+    let ephemeral_secret = EphemeralSecret::random_from_rng(rand::thread_rng());
+    let ephemeral = PublicKey::from(&ephemeral_secret);
+    let shared = ephemeral_secret.diffie_hellman(recipient);
+    if !shared.was_contributory() {
+        return Err(EnclaveError::GenericError(
+            "Recipient public key is a low order point".to_string(),
+        ));
+    }
+    let key = derive_key(shared.as_bytes(), &ephemeral, recipient)?;
+
+    let mut nonce = [0u8; NONCE_LENGTH];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let ciphertext = Aes256Gcm::new_from_slice(&key)
+        .expect("key is 32 bytes")
+        .encrypt(
+            (&nonce).into(),
+            Payload {
+                msg: plaintext,
+                aad: APP_ID.as_bytes(),
+            },
+        )
+        .map_err(|_| EnclaveError::GenericError("Failed to encrypt result".to_string()))?;
+
+    Ok(EncryptedResult {
+        recipient_public_key: Hex::encode(recipient.as_bytes()),
+        ephemeral_public_key: Hex::encode(ephemeral.as_bytes()),
+        nonce: Hex::encode(nonce),
+        ciphertext: Hex::encode(ciphertext),
+    })
+}
+
+/// Recipient to seal a result of `feed_id` to: the feed's `configured` one,
+/// which a request may only repeat, or else the requested one.
+fn output_recipient(
+    feed_id: Address,
+    configured: Option<&str>,
+    requested_recipient: Option<&str>,
+) -> Result<Option<PublicKey>, EnclaveError> {
+    let requested = requested_recipient.map(parse_recipient).transpose()?;
+    let Some(configured) = configured else {
+        return Ok(requested);
+    };
+    let configured = parse_recipient(configured)?;
+    match requested {
+        Some(requested) if requested != configured => Err(EnclaveError::InvalidRequest(format!(
+            "Results of feed {} can only be encrypted to its configured recipient",
+            feed_id
+        ))),
+        _ => Ok(Some(configured)),
+    }
+}
+
+/// Return `response` as is, or encrypted to the feed's configured recipient
+/// or, for other feeds, to the requested one.
+pub fn seal_output(
+    feed_id: Address,
+    response: UpdateOracleHttpResponse,
+    requested_recipient: Option<&str>,
+) -> Result<ResultOutput, EnclaveError> {
+    let configured = feed_recipient(feed_id);
+    let Some(recipient) = output_recipient(feed_id, configured, requested_recipient)? else {
+        return Ok(ResultOutput::Plain(response));
+    };
+    let plaintext = serde_json::to_vec(&response)
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to serialize result: {}", e)))?;
+    Ok(ResultOutput::Encrypted {
+        encrypted: encrypt(&recipient, &plaintext)?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use x25519_dalek::StaticSecret;
+
+    /// Reference decryption, as done by the consumer.
+    fn decrypt(
+        secret: &StaticSecret,
+        encrypted: &EncryptedResult,
+    ) -> Result<Vec<u8>, EnclaveError> {
+        let recipient = PublicKey::from(secret);
+        let ephemeral = parse_recipient(&encrypted.ephemeral_public_key)?;
+        let shared = secret.diffie_hellman(&ephemeral);
+        let key = derive_key(shared.as_bytes(), &ephemeral, &recipient)?;
+        let nonce: [u8; NONCE_LENGTH] = Hex::decode(&encrypted.nonce).unwrap().try_into().unwrap();
+        Aes256Gcm::new_from_slice(&key)
+            .unwrap()
+            .decrypt(
+                (&nonce).into(),
+                Payload {
+                    msg: &Hex::decode(&encrypted.ciphertext).unwrap(),
+                    aad: APP_ID.as_bytes(),
+                },
+            )
+            .map_err(|_| EnclaveError::GenericError("Failed to decrypt".to_string()))
+    }
+
+    #[test]
+    fn test_encrypt_roundtrip() {
+        let secret = StaticSecret::random_from_rng(rand::thread_rng());
+        let recipient = PublicKey::from(&secret);
+        let encrypted = encrypt(&recipient, b"auction result").unwrap();
+        assert_eq!(decrypt(&secret, &encrypted).unwrap(), b"auction result");
+
+        // Another key can't decrypt
+        let other = StaticSecret::random_from_rng(rand::thread_rng());
+        assert!(decrypt(&other, &encrypted).is_err());
+
+        // Low order points are rejected
+        assert!(encrypt(&PublicKey::from([0u8; 32]), b"x").is_err());
+        assert!(parse_recipient("00").is_err());
+    }
+
+    #[test]
+    fn test_output_recipient() {
+        let feed_id = Address::ZERO;
+        let key = |secret: &StaticSecret| Hex::encode(PublicKey::from(secret).as_bytes());
+        let configured = key(&StaticSecret::random_from_rng(rand::thread_rng()));
+        let requested = key(&StaticSecret::random_from_rng(rand::thread_rng()));
+
+        assert_eq!(output_recipient(feed_id, None, None).unwrap(), None);
+        let sealed_to = |configured, requested| {
+            output_recipient(feed_id, configured, requested)
+                .unwrap()
+                .map(|key| Hex::encode(key.as_bytes()))
+        };
+        assert_eq!(sealed_to(None, Some(&requested)), Some(requested.clone()));
+        // The configured recipient wins, a request may only repeat it
+        assert_eq!(sealed_to(Some(&configured), None), Some(configured.clone()));
+        assert_eq!(
+            sealed_to(Some(&configured), Some(&configured.to_uppercase())),
+            Some(configured.clone())
+        );
+        assert!(matches!(
+            output_recipient(feed_id, Some(&configured), Some(&requested)),
+            Err(EnclaveError::InvalidRequest(_))
+        ));
+    }
+}
@@ -0,0 +1,217 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Feed runs requested on-chain.
+//!
+//! `feed::request_update` emits `UpdateRequested { feed_id }`. When events are
+//! enabled, the enclave polls the checkpoints after the one current at startup
+//! for events of that type from the feed_object package, and runs each
+//! requested feed as the scheduler does: the result is submitted in push mode,
+//! stored for /scheduled_results and published to subscribers.
+//!
+//! Anyone can emit the event, so only the feeds listed in `feeds` are run and
+//! the list must not be empty. Requested feeds count against the feed rate
+//! limit like process_data calls, so emitting events in a loop does not run a
+//! feed more often than a client could, and a request is dropped when every
+//! background slot (see batch.rs) is busy rather than queued.
+
+use super::batch;
+use super::config::COEUS_CONFIG;
+use super::parse_feed_id;
+use super::scheduler::refresh;
+use crate::AppState;
+use crate::EnclaveError;
+use crate::rate_limit;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use sui_sdk_types::{Address, StructTag};
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info, warn};
+
+/// Module and struct of the request event in the oracle package.
+const EVENT_TYPE: &str = "feed::UpdateRequested";
+
+/// Events section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+    /// Checkpoints read per poll, so a lagging enclave catches up gradually.
+    #[serde(default = "default_max_checkpoints_per_poll")]
+    pub max_checkpoints_per_poll: u64,
+    /// Also sign the result metadata, see UpdateOracleRequest.
+    #[serde(default)]
+    pub sign_meta: bool,
+    /// Feeds that may be requested, required when enabled.
+    #[serde(default)]
+    pub feeds: Vec<String>,
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            poll_interval_ms: default_poll_interval_ms(),
+            max_checkpoints_per_poll: default_max_checkpoints_per_poll(),
+            sign_meta: false,
+            feeds: Vec::new(),
+        }
+    }
+}
+
+fn default_poll_interval_ms() -> u64 {
+    1000
+}
+
+fn default_max_checkpoints_per_poll() -> u64 {
+    50
+}
+
+/// Which events request which feeds.
+struct Filter {
+    event_type: StructTag,
+    feeds: Vec<Address>,
+}
+
+impl Filter {
+    fn new(config: &EventsConfig) -> Result<Self, EnclaveError> {
+        let package_id = &COEUS_CONFIG.feed_object.package_id;
+        let event_type = format!("{}::{}", package_id, EVENT_TYPE);
+        let event_type = event_type.parse().map_err(|e| {
+            EnclaveError::GenericError(format!("Invalid event type {}: {:?}", event_type, e))
+        })?;
+        let feeds = config
+            .feeds
+            .iter()
+            .map(String::as_str)
+            .map(parse_feed_id)
+            .collect::<Result<_, _>>()?;
+        Ok(Self { event_type, feeds })
+    }
+
+    /// Feed requested by the contents of an UpdateRequested event, if it may
+    /// be requested.
+    fn requested_feed(&self, contents: &[u8]) -> Option<Address> {
+        // UpdateRequested is the feed ID alone
+        let feed_id = Address::new(contents.try_into().ok()?);
+        self.feeds.contains(&feed_id).then_some(feed_id)
+    }
+}
+
+/// Start watching for update requests. Does nothing unless events are enabled
+/// in coeus_config.yaml.
+pub fn spawn_event_watch(state: Arc<AppState>) -> Result<(), EnclaveError> {
+    let config = &COEUS_CONFIG.events;
+    if !config.enabled {
+        return Ok(());
+    }
+    if config.poll_interval_ms == 0 || config.max_checkpoints_per_poll == 0 {
+        return Err(EnclaveError::GenericError(
+            "events.poll_interval_ms and events.max_checkpoints_per_poll must be positive"
+                .to_string(),
+        ));
+    }
+    if config.feeds.is_empty() {
+        return Err(EnclaveError::GenericError(
+            "events.feeds must list the feeds that may be requested".to_string(),
+        ));
+    }
+    let filter = Filter::new(config)?;
+    info!("Running feeds on {} events", filter.event_type);
+    tokio::spawn(watch(state, filter));
+    Ok(())
+}
+
+/// Poll loop. A checkpoint that cannot be read is retried on the next poll,
+/// so requests are not lost while the fullnodes are unavailable.
+async fn watch(state: Arc<AppState>, filter: Filter) {
+    let config = &COEUS_CONFIG.events;
+    let mut ticker = tokio::time::interval(Duration::from_millis(config.poll_interval_ms));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // Next checkpoint to read, the first one after startup
+    let mut next = None;
+    loop {
+        ticker.tick().await;
+        let latest = match state.chain.latest_checkpoint().await {
+            Ok(latest) => latest,
+            Err(e) => {
+                warn!("Failed to read the latest checkpoint: {}", e.message());
+                continue;
+            }
+        };
+        let from = *next.get_or_insert(latest + 1);
+        let to = latest.min(from + config.max_checkpoints_per_poll - 1);
+        for sequence_number in from..=to {
+            let events = state
+                .chain
+                .checkpoint_events(sequence_number, &filter.event_type)
+                .await;
+            let events = match events {
+                Ok(events) => events,
+                Err(e) => {
+                    warn!(
+                        "Failed to read events of checkpoint {}: {}",
+                        sequence_number,
+                        e.message()
+                    );
+                    break;
+                }
+            };
+            for feed_id in events
+                .iter()
+                .filter_map(|contents| filter.requested_feed(contents))
+            {
+                request_run(&state, feed_id, config.sign_meta);
+            }
+            next = Some(sequence_number + 1);
+        }
+    }
+}
+
+fn request_run(state: &Arc<AppState>, feed_id: Address, sign_meta: bool) {
+    let Some(slot) = batch::try_background_slot() else {
+        warn!(
+            "Update request for feed {} dropped, all background slots are busy",
+            feed_id
+        );
+        return;
+    };
+    if let Err(e) = rate_limit::check_feed(&state.config.rate_limit, &feed_id.to_string()) {
+        debug!("Update request for feed {} dropped: {}", feed_id, e);
+        return;
+    }
+    info!("Update requested on-chain for feed {}", feed_id);
+    let state = state.clone();
+    tokio::spawn(async move { refresh(&state, slot, feed_id, sign_meta, "Requested").await });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_requested_feed() {
+        let feed_id = Address::from_hex("0x5").unwrap();
+        let other_id = Address::from_hex("0x6").unwrap();
+        let mut filter = Filter {
+            event_type: "0x1::feed::UpdateRequested".parse().unwrap(),
+            feeds: vec![feed_id],
+        };
+        assert_eq!(filter.requested_feed(feed_id.as_ref()), Some(feed_id));
+        // Another layout
+        assert_eq!(filter.requested_feed(&[5; 8]), None);
+
+        filter.feeds = vec![other_id];
+        assert_eq!(filter.requested_feed(feed_id.as_ref()), None);
+        // No feed may be requested without a list
+        filter.feeds = Vec::new();
+        assert_eq!(filter.requested_feed(other_id.as_ref()), None);
+
+        let config: EventsConfig = serde_yaml::from_str("{}").unwrap();
+        assert!(!config.enabled);
+        assert_eq!(config.poll_interval_ms, 1000);
+    }
+}
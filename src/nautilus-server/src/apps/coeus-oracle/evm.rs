@@ -0,0 +1,283 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! EVM JSON-RPC host functions, so a feed can attest to the state of an EVM
+//! chain (an L1 price, a bridge balance) from the same enclave:
+//!
+//! ```rhai
+//! // balanceOf(0x...) on an ERC-20
+//! let out = eth_call("https://eth.example.com", "0x<token>",
+//!     "0x70a08231000000000000000000000000<holder>");
+//! evm_uint(out, 0)
+//! ```
+//!
+//! `eth_call(rpc_url, to, data)` returns the hex return data of the call and
+//! `eth_get_storage_at(rpc_url, address, slot)` the hex 32-byte word of a
+//! storage slot. Both read at the `evm.block` tag of coeus_config.yaml
+//! (`finalized` by default, so a reorg cannot change an attested value), or at
+//! the block tag or hex number given as a last argument. The RPC host must be
+//! listed in allowed_endpoints.yaml, like any host a script calls, and
+//! requests go through the shared client of http_proxy.rs.
+//!
+//! `evm_word(hex, index)` returns the index-th 32-byte word of ABI encoded
+//! data, and `evm_uint(hex, index)` decodes it as an integer, failing if it
+//! does not fit in 63 bits. Available to Rhai and Starlark scripts.
+
+use super::config::COEUS_CONFIG;
+use super::http_proxy;
+use crate::{admin, metrics};
+use rhai::{EvalAltResult, FuncRegistration, Module};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value as JsonValue, json};
+use std::time::Duration;
+
+/// Bytes of an ABI word and of a storage slot.
+const WORD: usize = 32;
+
+/// Bytes of an EVM address.
+const ADDRESS: usize = 20;
+
+/// EVM section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvmConfig {
+    /// Block tag or hex number reads are made at by default.
+    #[serde(default = "default_block")]
+    pub block: String,
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl Default for EvmConfig {
+    fn default() -> Self {
+        Self {
+            block: default_block(),
+            timeout_ms: default_timeout_ms(),
+        }
+    }
+}
+
+fn default_block() -> String {
+    "finalized".to_string()
+}
+
+fn default_timeout_ms() -> u64 {
+    10_000
+}
+
+/// Bytes of `0x` prefixed hex `value`, checking their count if `length` is set.
+fn decode_hex(name: &str, value: &str, length: Option<usize>) -> Result<Vec<u8>, String> {
+    let digits = value
+        .strip_prefix("0x")
+        .ok_or_else(|| format!("{} must be 0x prefixed hex, got {}", name, value))?;
+    let bytes = hex::decode(digits).map_err(|e| format!("Invalid {} {}: {}", name, value, e))?;
+    match length {
+        Some(length) if bytes.len() != length => Err(format!(
+            "{} must be {} bytes, got {}",
+            name,
+            length,
+            bytes.len()
+        )),
+        _ => Ok(bytes),
+    }
+}
+
+/// Block tag, or hex block number.
+fn check_block(block: &str) -> Result<(), String> {
+    match block {
+        "latest" | "safe" | "finalized" | "earliest" => Ok(()),
+        other => other
+            .strip_prefix("0x")
+            .and_then(|number| u64::from_str_radix(number, 16).ok())
+            .map(|_| ())
+            .ok_or_else(|| format!("Invalid block {}", other)),
+    }
+}
+
+/// 32-byte storage slot, left padded from a shorter hex number.
+fn storage_slot(slot: &str) -> Result<String, String> {
+    let digits = slot
+        .strip_prefix("0x")
+        .ok_or_else(|| format!("slot must be 0x prefixed hex, got {}", slot))?;
+    if digits.is_empty() || digits.len() > 2 * WORD {
+        return Err(format!("slot must be 1 to 32 bytes, got {}", slot));
+    }
+    let padded = format!("{:0>64}", digits);
+    decode_hex("slot", &format!("0x{}", padded), Some(WORD))?;
+    Ok(format!("0x{}", padded))
+}
+
+fn call_params(to: &str, data: &str, block: &str) -> Result<JsonValue, String> {
+    decode_hex("to", to, Some(ADDRESS))?;
+    decode_hex("data", data, None)?;
+    check_block(block)?;
+    Ok(json!([{ "to": to, "data": data }, block]))
+}
+
+fn storage_params(address: &str, slot: &str, block: &str) -> Result<JsonValue, String> {
+    decode_hex("address", address, Some(ADDRESS))?;
+    check_block(block)?;
+    Ok(json!([address, storage_slot(slot)?, block]))
+}
+
+/// Result of a JSON-RPC response, failing on its error.
+fn rpc_result(method: &str, response: JsonValue) -> Result<String, String> {
+    if let Some(error) = response.get("error").filter(|error| !error.is_null()) {
+        return Err(format!("{} failed: {}", method, error));
+    }
+    let result = response
+        .get("result")
+        .and_then(JsonValue::as_str)
+        .ok_or_else(|| format!("{} returned no result", method))?;
+    decode_hex("result", result, None)?;
+    Ok(result.to_string())
+}
+
+fn rpc(rpc_url: &str, method: &str, params: JsonValue) -> Result<String, String> {
+    if !admin::host_allowed(rpc_url) {
+        return Err(format!("Host of {} is not in the allowlist", rpc_url));
+    }
+    let body = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    let timeout = Duration::from_millis(COEUS_CONFIG.evm.timeout_ms);
+    let response = http_proxy::post_json(rpc_url, &body, timeout)
+        .map_err(|e| format!("{} to {} failed: {}", method, rpc_url, e))?;
+    rpc_result(method, response)
+}
+
+fn observed(method: &str, result: Result<String, String>) -> Result<String, String> {
+    let outcome = if result.is_ok() { "ok" } else { "error" };
+    metrics::HOST_EVM_CALLS
+        .with_label_values(&[method, outcome])
+        .inc();
+    result
+}
+
+/// Hex return data of calling `to` with `data` at `block`.
+pub fn eth_call(rpc_url: &str, to: &str, data: &str, block: &str) -> Result<String, String> {
+    let result = call_params(to, data, block).and_then(|params| rpc(rpc_url, "eth_call", params));
+    observed("eth_call", result)
+}
+
+/// Hex word stored at `slot` of `address` at `block`.
+pub fn eth_get_storage_at(
+    rpc_url: &str,
+    address: &str,
+    slot: &str,
+    block: &str,
+) -> Result<String, String> {
+    let result = storage_params(address, slot, block)
+        .and_then(|params| rpc(rpc_url, "eth_getStorageAt", params));
+    observed("eth_getStorageAt", result)
+}
+
+/// The `index`-th 32-byte word of ABI encoded `data`, as hex.
+pub fn evm_word(data: &str, index: i64) -> Result<String, String> {
+    let bytes = decode_hex("data", data, None)?;
+    let start = usize::try_from(index)
+        .ok()
+        .and_then(|index| index.checked_mul(WORD))
+        .filter(|start| start.checked_add(WORD).is_some())
+        .ok_or_else(|| format!("Invalid word index {}", index))?;
+    let word = bytes.get(start..start + WORD).ok_or_else(|| {
+        format!(
+            "Word {} is past the end of {} bytes of data",
+            index,
+            bytes.len()
+        )
+    })?;
+    Ok(format!("0x{}", hex::encode(word)))
+}
+
+/// The `index`-th word of `data` as an unsigned integer.
+pub fn evm_uint(data: &str, index: i64) -> Result<i64, String> {
+    let word = decode_hex("word", &evm_word(data, index)?, Some(WORD))?;
+    let (high, low) = word.split_at(WORD - 8);
+    let value = u64::from_be_bytes(low.try_into().expect("8 bytes"));
+    if high.iter().any(|byte| *byte != 0) || value > i64::MAX as u64 {
+        return Err(format!("Word {} does not fit in an integer", index));
+    }
+    Ok(value as i64)
+}
+
+/// Register the EVM host functions into a Rhai module. They fail the script on
+/// error.
+pub fn register_evm_fns(module: &mut Module) {
+    FuncRegistration::new("eth_call").set_into_module(
+        module,
+        |rpc_url: &str, to: &str, data: &str| -> Result<String, Box<EvalAltResult>> {
+            Ok(eth_call(rpc_url, to, data, &COEUS_CONFIG.evm.block)?)
+        },
+    );
+    FuncRegistration::new("eth_call").set_into_module(
+        module,
+        |rpc_url: &str, to: &str, data: &str, block: &str| -> Result<String, Box<EvalAltResult>> {
+            Ok(eth_call(rpc_url, to, data, block)?)
+        },
+    );
+    FuncRegistration::new("eth_get_storage_at").set_into_module(
+        module,
+        |rpc_url: &str, address: &str, slot: &str| -> Result<String, Box<EvalAltResult>> {
+            Ok(eth_get_storage_at(
+                rpc_url,
+                address,
+                slot,
+                &COEUS_CONFIG.evm.block,
+            )?)
+        },
+    );
+    FuncRegistration::new("eth_get_storage_at").set_into_module(
+        module,
+        |rpc_url: &str,
+         address: &str,
+         slot: &str,
+         block: &str|
+         -> Result<String, Box<EvalAltResult>> {
+            Ok(eth_get_storage_at(rpc_url, address, slot, block)?)
+        },
+    );
+    FuncRegistration::new("evm_word").set_into_module(
+        module,
+        |data: &str, index: i64| -> Result<String, Box<EvalAltResult>> {
+            Ok(evm_word(data, index)?)
+        },
+    );
+    FuncRegistration::new("evm_uint").set_into_module(
+        module,
+        |data: &str, index: i64| -> Result<i64, Box<EvalAltResult>> { Ok(evm_uint(data, index)?) },
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_evm_params() {
+        let token = "0x6b175474e89094c44da98b954eedeac495271d0f";
+        let params = call_params(token, "0x70a08231", "finalized").unwrap();
+        assert_eq!(params[0]["to"], token);
+        assert_eq!(params[1], "finalized");
+        assert!(call_params("0x6b17", "0x70a08231", "latest").is_err());
+        assert!(call_params(token, "70a08231", "latest").is_err());
+        assert!(call_params(token, "0x70a08231", "pending").is_err());
+        assert!(call_params(token, "0x70a08231", "0x12ab").is_ok());
+
+        let params = storage_params(token, "0x2", "latest").unwrap();
+        assert_eq!(params[1], format!("0x{:0>64}", "2"));
+        assert!(storage_params(token, &format!("0x{}", "0".repeat(66)), "latest").is_err());
+
+        let response = json!({ "jsonrpc": "2.0", "id": 1, "result": "0x01" });
+        assert_eq!(rpc_result("eth_call", response).unwrap(), "0x01");
+        let response = json!({ "jsonrpc": "2.0", "id": 1, "error": { "code": 3, "message": "execution reverted" } });
+        assert!(rpc_result("eth_call", response).is_err());
+    }
+
+    #[test]
+    fn test_evm_decode() {
+        let data = format!("0x{:0>64}{:0>64}", "2a", "f".repeat(64));
+        assert_eq!(evm_uint(&data, 0).unwrap(), 42);
+        assert_eq!(evm_word(&data, 1).unwrap(), format!("0x{}", "f".repeat(64)));
+        assert!(evm_uint(&data, 1).is_err());
+        assert!(evm_word(&data, 2).is_err());
+        assert!(evm_word(&data, -1).is_err());
+    }
+}
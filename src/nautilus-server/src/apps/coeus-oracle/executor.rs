@@ -0,0 +1,163 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Runtimes for feed code, keyed by `CodeExtension`. Adding a runtime means
+//! implementing `ScriptExecutor` and registering it in `ExecutorRegistry::new`,
+//! `run_feed` does not need to change.
+
+use super::{
+    CodeExtension, ReturnType, RhaiOutput, declarative, execute_rhai_code_async, starlark_exec,
+};
+use crate::EnclaveError;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+lazy_static::lazy_static! {
+    /// Executors used by run_feed.
+    pub static ref EXECUTORS: ExecutorRegistry = ExecutorRegistry::new();
+}
+
+/// Feed settings an executor needs besides the code itself.
+#[derive(Debug, Clone)]
+pub struct ExecutionContext {
+    pub return_type: ReturnType,
+    pub strict_types: bool,
+}
+
+pub type ExecutionFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<RhaiOutput, EnclaveError>> + Send + 'a>>;
+
+/// A runtime able to execute feed code and convert its result to the
+/// feed's return type.
+pub trait ScriptExecutor: Send + Sync {
+    fn execute<'a>(&'a self, code: &'a str, ctx: &'a ExecutionContext) -> ExecutionFuture<'a>;
+}
+
+pub struct RhaiExecutor;
+
+impl ScriptExecutor for RhaiExecutor {
+    fn execute<'a>(&'a self, code: &'a str, ctx: &'a ExecutionContext) -> ExecutionFuture<'a> {
+        Box::pin(execute_rhai_code_async(
+            code,
+            &ctx.return_type,
+            ctx.strict_types,
+        ))
+    }
+}
+
+pub struct DeclarativeExecutor;
+
+impl ScriptExecutor for DeclarativeExecutor {
+    fn execute<'a>(&'a self, code: &'a str, ctx: &'a ExecutionContext) -> ExecutionFuture<'a> {
+        Box::pin(declarative::execute_spec(
+            code,
+            &ctx.return_type,
+            ctx.strict_types,
+        ))
+    }
+}
+
+pub struct StarlarkExecutor;
+
+impl ScriptExecutor for StarlarkExecutor {
+    fn execute<'a>(&'a self, code: &'a str, ctx: &'a ExecutionContext) -> ExecutionFuture<'a> {
+        Box::pin(starlark_exec::execute_starlark_code(
+            code,
+            &ctx.return_type,
+            ctx.strict_types,
+        ))
+    }
+}
+
+/// Maps each code extension to its executor.
+pub struct ExecutorRegistry {
+    executors: HashMap<CodeExtension, Arc<dyn ScriptExecutor>>,
+}
+
+impl ExecutorRegistry {
+    /// Registry with the built-in runtimes.
+    pub fn new() -> Self {
+        Self::empty()
+            .with(CodeExtension::RHAI, Arc::new(RhaiExecutor))
+            .with(CodeExtension::JSON_CONFIG, Arc::new(DeclarativeExecutor))
+            .with(CodeExtension::STARLARK, Arc::new(StarlarkExecutor))
+    }
+
+    pub fn empty() -> Self {
+        Self {
+            executors: HashMap::new(),
+        }
+    }
+
+    /// Register (or replace) the executor for an extension.
+    pub fn with(mut self, extension: CodeExtension, executor: Arc<dyn ScriptExecutor>) -> Self {
+        self.executors.insert(extension, executor);
+        self
+    }
+
+    /// Execute `code` with the executor registered for `extension`.
+    pub async fn execute(
+        &self,
+        extension: &CodeExtension,
+        code: &str,
+        ctx: &ExecutionContext,
+    ) -> Result<RhaiOutput, EnclaveError> {
+        let executor = self.executors.get(extension).ok_or_else(|| {
+            EnclaveError::InvalidRequest(format!("No executor for extension {:?}", extension))
+        })?;
+        executor.execute(code, ctx).await
+    }
+}
+
+impl Default for ExecutorRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::app::ResultValue;
+
+    struct MockExecutor;
+
+    impl ScriptExecutor for MockExecutor {
+        fn execute<'a>(&'a self, code: &'a str, _ctx: &'a ExecutionContext) -> ExecutionFuture<'a> {
+            Box::pin(async move {
+                Ok(RhaiOutput {
+                    result: Some(ResultValue::STRING(code.to_uppercase())),
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry() {
+        let ctx = ExecutionContext {
+            return_type: ReturnType::STRING,
+            strict_types: false,
+        };
+        let registry = ExecutorRegistry::empty().with(CodeExtension::RHAI, Arc::new(MockExecutor));
+        let output = registry
+            .execute(&CodeExtension::RHAI, "abc", &ctx)
+            .await
+            .unwrap();
+        assert_eq!(output.result, Some(ResultValue::STRING("ABC".to_string())));
+        assert!(
+            registry
+                .execute(&CodeExtension::STARLARK, "abc", &ctx)
+                .await
+                .is_err()
+        );
+
+        let output = ExecutorRegistry::new()
+            .execute(&CodeExtension::RHAI, "\"abc\"", &ctx)
+            .await
+            .unwrap();
+        assert_eq!(output.result, Some(ResultValue::STRING("abc".to_string())));
+    }
+}
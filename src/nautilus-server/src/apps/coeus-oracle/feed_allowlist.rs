@@ -0,0 +1,157 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Feeds this enclave is authorized to serve, listed on-chain in an
+//! `oracle::allowlist::FeedAllowlist` object.
+//!
+//! When `feed_allowlist.object_id` is set, a feed missing from that object is
+//! refused before its code is fetched, whether it is requested through
+//! process_data, gRPC, the scheduler or an on-chain update request. The
+//! object id is compiled into the image, so the host cannot point the check
+//! elsewhere. The list is cached for `refresh_ms`; a feed removed on-chain may
+//! thus still run for that long, and a result signed before its removal is
+//! served from the result cache until it expires. If the list cannot be read,
+//! feeds are refused.
+
+use super::config::COEUS_CONFIG;
+use super::onchain::get_object;
+use crate::EnclaveError;
+use crate::chain_reader::ChainReader;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use sui_sdk_types::{Address, StructTag};
+use tokio::sync::RwLock;
+
+/// Module and struct of the allowlist type in the oracle package.
+const ALLOWLIST_TYPE: &str = "allowlist::FeedAllowlist";
+
+lazy_static::lazy_static! {
+    /// Feeds of the allowlist with the time they were read.
+    static ref ALLOWED_FEEDS: RwLock<Option<(Instant, HashSet<Address>)>> = RwLock::new(None);
+}
+
+/// Feed allowlist section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedAllowlistConfig {
+    /// FeedAllowlist object, any feed may run when unset.
+    #[serde(default)]
+    pub object_id: Option<String>,
+    #[serde(default = "default_refresh_ms")]
+    pub refresh_ms: u64,
+}
+
+impl Default for FeedAllowlistConfig {
+    fn default() -> Self {
+        Self {
+            object_id: None,
+            refresh_ms: default_refresh_ms(),
+        }
+    }
+}
+
+fn default_refresh_ms() -> u64 {
+    30_000
+}
+
+/// BCS layout of oracle::allowlist::FeedAllowlist
+#[derive(Debug, Serialize, Deserialize)]
+struct FeedAllowlist {
+    id: Address,
+    feeds: Vec<Address>,
+}
+
+/// Decode the allowlist object's contents, once its type is checked.
+fn decode(
+    object_id: &Address,
+    object_type: &StructTag,
+    contents: &[u8],
+) -> Result<HashSet<Address>, EnclaveError> {
+    let package_id = &COEUS_CONFIG.feed_object.package_id;
+    let expected = format!("{}::{}", package_id, ALLOWLIST_TYPE);
+    let expected: StructTag = expected.parse().map_err(|e| {
+        EnclaveError::GenericError(format!("Invalid allowlist type {}: {:?}", expected, e))
+    })?;
+    if object_type != &expected {
+        return Err(EnclaveError::GenericError(format!(
+            "Object {} is a {}, not a {}",
+            object_id, object_type, expected
+        )));
+    }
+    let allowlist: FeedAllowlist = bcs::from_bytes(contents).map_err(|e| {
+        EnclaveError::GenericError(format!("Failed to decode allowlist {}: {}", object_id, e))
+    })?;
+    Ok(allowlist.feeds.into_iter().collect())
+}
+
+async fn read_allowlist(
+    chain: &dyn ChainReader,
+    object_id: &Address,
+) -> Result<HashSet<Address>, EnclaveError> {
+    let object = get_object(chain, object_id).await?;
+    let move_object = object.as_struct().ok_or_else(|| {
+        EnclaveError::GenericError(format!("Allowlist {} is not a Move object", object_id))
+    })?;
+    decode(object_id, move_object.object_type(), move_object.contents())
+}
+
+/// Refuse feeds missing from the configured allowlist.
+pub async fn check_feed(chain: &dyn ChainReader, feed_id: &Address) -> Result<(), EnclaveError> {
+    let config = &COEUS_CONFIG.feed_allowlist;
+    let Some(object_id) = &config.object_id else {
+        return Ok(());
+    };
+    let max_age = Duration::from_millis(config.refresh_ms);
+    let cached = ALLOWED_FEEDS
+        .read()
+        .await
+        .as_ref()
+        .filter(|(read_at, _)| read_at.elapsed() < max_age)
+        .map(|(_, feeds)| feeds.contains(feed_id));
+    let allowed = match cached {
+        Some(allowed) => allowed,
+        None => {
+            let object_id = Address::from_hex(object_id).map_err(|e| {
+                EnclaveError::GenericError(format!("Invalid allowlist id {}: {}", object_id, e))
+            })?;
+            let feeds = read_allowlist(chain, &object_id).await?;
+            let allowed = feeds.contains(feed_id);
+            *ALLOWED_FEEDS.write().await = Some((Instant::now(), feeds));
+            allowed
+        }
+    };
+    if !allowed {
+        return Err(EnclaveError::Forbidden(format!(
+            "Feed {} is not in the allowlist of this enclave",
+            feed_id
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let object_id = Address::from_hex("0x7").unwrap();
+        let feed_id = Address::from_hex("0x1").unwrap();
+        let contents = bcs::to_bytes(&FeedAllowlist {
+            id: object_id,
+            feeds: vec![feed_id],
+        })
+        .unwrap();
+        let package_id = &COEUS_CONFIG.feed_object.package_id;
+        let object_type: StructTag = format!("{}::allowlist::FeedAllowlist", package_id)
+            .parse()
+            .unwrap();
+        let feeds = decode(&object_id, &object_type, &contents).unwrap();
+        assert!(feeds.contains(&feed_id));
+        assert!(!feeds.contains(&Address::ZERO));
+
+        // Same layout from another package
+        let other_type: StructTag = "0x2::allowlist::FeedAllowlist".parse().unwrap();
+        assert!(decode(&object_id, &other_type, &contents).is_err());
+    }
+}
@@ -0,0 +1,464 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-feed signing keys.
+//!
+//! Feeds listed under a named key in coeus_config.yaml are signed with that
+//! key instead of the enclave key, so each can be registered and revoked
+//! on-chain on its own. The keys are derived from a master seed generated in
+//! the enclave:
+//!
+//! `HKDF-SHA3-256(master, salt = "coeus-oracle/feed-key", info = bcs(name, generation))`
+//!
+//! Bumping a key's `generation` replaces that key only, every other feed keeps
+//! signing with the same key. Feeds without a named key use the enclave key
+//! and its rotation schedule.
+//!
+//! The seed is sealed with Seal under `feed_key_seal`, so the keys survive a
+//! restart. On first boot the host calls POST /feed_keys/seal/generate: the
+//! enclave generates the seed, encrypts it and returns the encrypted object
+//! for the host to store. After a restart the host fetches the Seal key shares
+//! with POST /feed_keys/seal/request and loads the seed back with POST
+//! /feed_keys/seal/unseal. Until then feeds with a named key are refused.
+//!
+//! Seal encryption is public-key, so the host could seal a seed it knows. An
+//! unsealed seed is only loaded if the key it derives for the named key is
+//! the pk of an `Enclave<CONFIG>` object, which only an attested key can be
+//! registered as, so the seed is one generated in an enclave. Its other keys,
+//! e.g. after a generation bump, can then be attested like the keys of a seed
+//! generated in this boot.
+
+use super::config::COEUS_CONFIG;
+use super::rotation;
+use crate::common::enclave_rng;
+use crate::seal_store::{
+    SealConfig, deserialize_encrypted_object, deserialize_hex, deserialize_hex_vec,
+    deserialize_object_id, deserialize_seal_responses, enclave_type, fetch_key_request, seal,
+    unseal, verify_registered,
+};
+use crate::{AppState, EnclaveError};
+use axum::Json;
+use axum::extract::State;
+use fastcrypto::ed25519::{Ed25519KeyPair, Ed25519PrivateKey};
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hmac::{HkdfIkm, hkdf_sha3_256};
+use fastcrypto::traits::{KeyPair, ToFromBytes};
+use rand::RngCore;
+use seal_sdk::EncryptedObject;
+use seal_sdk::types::{FetchKeyResponse, KeyId};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use sui_sdk_types::{Address, ObjectId as ObjectID};
+use tracing::info;
+
+const HKDF_SALT: &[u8] = b"coeus-oracle/feed-key";
+
+/// Witness of the oracle package, the feed keys are registered as
+/// `Enclave<CONFIG>` objects.
+const ENCLAVE_WITNESS: &str = "config::CONFIG";
+
+lazy_static::lazy_static! {
+    /// Keys derived for the configured names, by name. Set once the master
+    /// seed is generated or unsealed.
+    static ref FEED_KEYS: RwLock<Option<HashMap<String, Arc<Ed25519KeyPair>>>> =
+        RwLock::new(None);
+}
+
+/// A named signing key in the feed_keys section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedKeyConfig {
+    pub name: String,
+    /// Incremented to replace the key, e.g. after a compromise.
+    #[serde(default)]
+    pub generation: u32,
+    /// Feed ids signed with this key.
+    #[serde(default)]
+    pub feeds: Vec<String>,
+}
+
+impl FeedKeyConfig {
+    fn signs(&self, feed_id: Address) -> bool {
+        self.feeds
+            .iter()
+            .any(|feed| Address::from_hex(feed).is_ok_and(|id| id == feed_id))
+    }
+}
+
+/// Derive the keypair for a key name and generation.
+fn derive_key(
+    master_seed: &[u8; 32],
+    name: &str,
+    generation: u32,
+) -> Result<Ed25519KeyPair, EnclaveError> {
+    let ikm = HkdfIkm::from_bytes(master_seed)
+        .map_err(|e| EnclaveError::GenericError(format!("Invalid master seed: {}", e)))?;
+    let info = bcs::to_bytes(&(name, generation))
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to encode key info: {}", e)))?;
+    let private_key = hkdf_sha3_256(&ikm, HKDF_SALT, &info, 32)
+        .ok()
+        .and_then(|bytes| Ed25519PrivateKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| EnclaveError::GenericError("Failed to derive feed key".to_string()))?;
+    Ok(Ed25519KeyPair::from(private_key))
+}
+
+/// Derive the keys of every configured name.
+fn derive_keys(
+    master_seed: &[u8; 32],
+    keys: &[FeedKeyConfig],
+) -> Result<HashMap<String, Arc<Ed25519KeyPair>>, EnclaveError> {
+    keys.iter()
+        .map(|key| {
+            let kp = derive_key(master_seed, &key.name, key.generation)?;
+            Ok((key.name.clone(), Arc::new(kp)))
+        })
+        .collect()
+}
+
+/// Check key names are unique and non-empty, feed ids valid and every feed
+/// assigned to at most one key.
+pub fn validate_feed_keys(keys: &[FeedKeyConfig]) -> Result<(), EnclaveError> {
+    let mut names = HashSet::new();
+    let mut feeds = HashSet::new();
+    for key in keys {
+        if key.name.is_empty() || !names.insert(key.name.as_str()) {
+            return Err(EnclaveError::GenericError(format!(
+                "Feed key names must be unique and non-empty, got {:?}",
+                key.name
+            )));
+        }
+        for feed in &key.feeds {
+            let feed_id = Address::from_hex(feed).map_err(|e| {
+                EnclaveError::GenericError(format!("Invalid feed id {}: {}", feed, e))
+            })?;
+            if !feeds.insert(feed_id) {
+                return Err(EnclaveError::GenericError(format!(
+                    "Feed {} is assigned to more than one key",
+                    feed
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Validate the feed_keys section. Called at startup so a bad configuration
+/// fails the boot instead of signing with the wrong key. The keys themselves
+/// are derived once the host generates or unseals the master seed.
+pub fn init_feed_keys() -> Result<(), EnclaveError> {
+    validate_feed_keys(&COEUS_CONFIG.feed_keys)?;
+    if COEUS_CONFIG.feed_keys.is_empty() {
+        return Ok(());
+    }
+    if COEUS_CONFIG.feed_key_seal.is_none() {
+        return Err(EnclaveError::GenericError(
+            "feed_keys requires feed_key_seal to seal the master seed".to_string(),
+        ));
+    }
+    info!(
+        "{} feed keys configured, waiting for the master seed to be generated or unsealed",
+        COEUS_CONFIG.feed_keys.len()
+    );
+    Ok(())
+}
+
+/// Seal configuration of the master seed, if feed keys are configured.
+fn seal_config() -> Result<&'static SealConfig, EnclaveError> {
+    if COEUS_CONFIG.feed_keys.is_empty() {
+        return Err(EnclaveError::GenericError(
+            "No feed keys configured".to_string(),
+        ));
+    }
+    COEUS_CONFIG
+        .feed_key_seal
+        .as_ref()
+        .ok_or_else(|| EnclaveError::GenericError("feed_key_seal is not configured".to_string()))
+}
+
+fn loaded_keys() -> Option<HashMap<String, Arc<Ed25519KeyPair>>> {
+    FEED_KEYS.read().expect("feed keys lock poisoned").clone()
+}
+
+/// Store the keys derived from the master seed, refused once keys are loaded.
+fn load_keys(keys: HashMap<String, Arc<Ed25519KeyPair>>) -> Result<(), EnclaveError> {
+    let mut guard = FEED_KEYS.write().expect("feed keys lock poisoned");
+    if guard.is_some() {
+        return Err(EnclaveError::GenericError(
+            "Feed key master seed already loaded".to_string(),
+        ));
+    }
+    for key in &COEUS_CONFIG.feed_keys {
+        if let Some(kp) = keys.get(&key.name) {
+            info!(
+                "Feed key {} (generation {}) signs {} feeds with {}",
+                key.name,
+                key.generation,
+                key.feeds.len(),
+                Hex::encode(kp.public().as_bytes())
+            );
+        }
+    }
+    *guard = Some(keys);
+    Ok(())
+}
+
+/// Named key by name, for GET /get_attestation?key=<name>.
+pub fn key_by_name(name: &str) -> Result<Arc<Ed25519KeyPair>, EnclaveError> {
+    if !COEUS_CONFIG.feed_keys.iter().any(|key| key.name == name) {
+        return Err(EnclaveError::GenericError(format!(
+            "Unknown feed key {}",
+            name
+        )));
+    }
+    loaded_keys()
+        .and_then(|keys| keys.get(name).cloned())
+        .ok_or_else(|| not_loaded(name))
+}
+
+fn not_loaded(name: &str) -> EnclaveError {
+    EnclaveError::Unregistered(format!(
+        "Feed key {} is not loaded until its master seed is generated or unsealed",
+        name
+    ))
+}
+
+/// Keys to sign a feed's results with: its named key, or the enclave key
+/// together with the previous key during a rotation grace window. A feed with
+/// a named key is refused until the key is loaded.
+pub async fn signing_keys_for(
+    state: &AppState,
+    feed_id: Address,
+) -> Result<(Arc<Ed25519KeyPair>, Option<Arc<Ed25519KeyPair>>), EnclaveError> {
+    match COEUS_CONFIG.feed_keys.iter().find(|key| key.signs(feed_id)) {
+        Some(key) => Ok((key_by_name(&key.name)?, None)),
+        None => Ok(rotation::signing_keys(state).await),
+    }
+}
+
+/// Public view of a named key in GET /feed_keys.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FeedKeyInfo {
+    pub name: String,
+    pub generation: u32,
+    pub feeds: Vec<String>,
+    /// Hex public key to register for the feeds, once the master seed is
+    /// loaded.
+    pub public_key: Option<String>,
+}
+
+fn key_infos() -> Vec<FeedKeyInfo> {
+    let keys = loaded_keys();
+    COEUS_CONFIG
+        .feed_keys
+        .iter()
+        .map(|key| FeedKeyInfo {
+            name: key.name.clone(),
+            generation: key.generation,
+            feeds: key
+                .feeds
+                .iter()
+                .filter_map(|feed| Address::from_hex(feed).ok())
+                .map(|feed| feed.to_string())
+                .collect(),
+            public_key: keys
+                .as_ref()
+                .and_then(|keys| keys.get(&key.name))
+                .map(|kp| Hex::encode(kp.public().as_bytes())),
+        })
+        .collect()
+}
+
+/// Endpoint listing the named keys, the feeds they sign and their public
+/// keys. Attestations are served by GET /get_attestation?key=<name>.
+pub async fn get_feed_keys() -> Json<Vec<FeedKeyInfo>> {
+    Json(key_infos())
+}
+
+/// Request for POST /feed_keys/seal/request
+#[derive(Serialize, Deserialize)]
+pub struct SeedKeyRequest {
+    /// Enclave object the enclave key is registered as, passed to seal_approve.
+    #[serde(deserialize_with = "deserialize_object_id")]
+    pub enclave_object_id: ObjectID,
+    pub initial_shared_version: u64,
+    /// Seal identities of the encrypted seed (hex).
+    #[serde(deserialize_with = "deserialize_hex_vec")]
+    pub ids: Vec<KeyId>,
+}
+
+/// Response for POST /feed_keys/seal/request
+#[derive(Serialize, Deserialize)]
+pub struct SeedKeyResponse {
+    /// Hex encoded BCS FetchKeyRequest to send to the key servers.
+    pub encoded_request: String,
+}
+
+/// Request for POST /feed_keys/seal/generate
+#[derive(Serialize, Deserialize)]
+pub struct GenerateSeedRequest {
+    /// Seal identity the master seed is encrypted to (hex).
+    #[serde(deserialize_with = "deserialize_hex")]
+    pub id: KeyId,
+}
+
+/// Response for POST /feed_keys/seal/generate
+#[derive(Serialize, Deserialize)]
+pub struct GenerateSeedResponse {
+    /// Hex encoded BCS serialized EncryptedObject holding the master seed.
+    /// The host stores it and passes it to /feed_keys/seal/unseal on restart.
+    pub encrypted_object: String,
+    pub keys: Vec<FeedKeyInfo>,
+}
+
+/// Request for POST /feed_keys/seal/unseal
+#[derive(Serialize, Deserialize)]
+pub struct UnsealSeedRequest {
+    #[serde(deserialize_with = "deserialize_encrypted_object")]
+    pub encrypted_object: EncryptedObject,
+    #[serde(deserialize_with = "deserialize_seal_responses")]
+    pub seal_responses: Vec<(ObjectID, FetchKeyResponse)>,
+    /// Name of a key already registered on-chain, and the Enclave object it
+    /// is registered as. The seed is refused unless it derives that key.
+    pub key: String,
+    #[serde(deserialize_with = "deserialize_object_id")]
+    pub enclave_object_id: ObjectID,
+}
+
+/// Endpoint returning the FetchKeyRequest for the key servers to release the
+/// master seed's key shares, signed with the registered enclave key. Admin
+/// only.
+pub async fn request_seed_keys(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<SeedKeyRequest>,
+) -> Result<Json<SeedKeyResponse>, EnclaveError> {
+    let (kp, _) = rotation::signing_keys(&state).await;
+    let encoded_request = fetch_key_request(
+        &kp,
+        seal_config()?,
+        request.enclave_object_id,
+        request.initial_shared_version,
+        request.ids,
+    )?;
+    Ok(Json(SeedKeyResponse { encoded_request }))
+}
+
+/// First boot: generate the master seed, seal it under `id` and derive the
+/// feed keys. Admin only.
+pub async fn generate_seed(
+    Json(request): Json<GenerateSeedRequest>,
+) -> Result<Json<GenerateSeedResponse>, EnclaveError> {
+    let config = seal_config()?;
+    if loaded_keys().is_some() {
+        return Err(EnclaveError::GenericError(
+            "Feed key master seed already loaded".to_string(),
+        ));
+    }
+    let mut seed = [0u8; 32];
+    enclave_rng().fill_bytes(&mut seed);
+    let encrypted_object = seal(config, request.id, seed.to_vec())?;
+
+    // Only keep the keys once the sealed seed exists, so they can always be recovered
+    load_keys(derive_keys(&seed, &COEUS_CONFIG.feed_keys)?)?;
+    Ok(Json(GenerateSeedResponse {
+        encrypted_object: Hex::encode(bcs::to_bytes(&encrypted_object).expect("should not fail")),
+        keys: key_infos(),
+    }))
+}
+
+/// Restart: decrypt the sealed master seed with the fetched Seal responses,
+/// and load its keys only if the one named `key` is registered as
+/// `enclave_object_id`. Admin only.
+pub async fn unseal_seed(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<UnsealSeedRequest>,
+) -> Result<Json<Vec<FeedKeyInfo>>, EnclaveError> {
+    let config = seal_config()?;
+    let decrypted = unseal(config, &request.seal_responses, &[request.encrypted_object])?;
+    let seed: [u8; 32] = decrypted
+        .first()
+        .and_then(|bytes| bytes.as_slice().try_into().ok())
+        .ok_or_else(|| EnclaveError::GenericError("Invalid sealed master seed".to_string()))?;
+
+    let keys = derive_keys(&seed, &COEUS_CONFIG.feed_keys)?;
+    let kp = keys
+        .get(&request.key)
+        .ok_or_else(|| EnclaveError::GenericError(format!("Unknown feed key {}", request.key)))?;
+    verify_registered(
+        state.chain.as_ref(),
+        &enclave_type(config, ENCLAVE_WITNESS)?,
+        request.enclave_object_id,
+        kp.public().as_bytes(),
+    )
+    .await?;
+
+    load_keys(keys)?;
+    Ok(Json(key_infos()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(name: &str, generation: u32, feeds: &[&str]) -> FeedKeyConfig {
+        FeedKeyConfig {
+            name: name.to_string(),
+            generation,
+            feeds: feeds.iter().map(|feed| feed.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_derive_key() {
+        let seed = [3u8; 32];
+        let key = |name, generation| {
+            derive_key(&seed, name, generation)
+                .unwrap()
+                .public()
+                .clone()
+        };
+
+        // Deterministic per name and generation, independent otherwise
+        assert_eq!(key("prices", 0), key("prices", 0));
+        assert_ne!(key("prices", 0), key("prices", 1));
+        assert_ne!(key("prices", 0), key("weather", 0));
+        assert_ne!(
+            key("prices", 0),
+            derive_key(&[4u8; 32], "prices", 0)
+                .unwrap()
+                .public()
+                .clone()
+        );
+    }
+
+    #[test]
+    fn test_derive_keys() {
+        let keys = [
+            config("prices", 0, &["0x1"]),
+            config("weather", 2, &["0x2"]),
+        ];
+        let first = derive_keys(&[3u8; 32], &keys).unwrap();
+        let second = derive_keys(&[3u8; 32], &keys).unwrap();
+
+        // The same seed gives the same keys, e.g. after unsealing it on a restart
+        assert_eq!(first.len(), 2);
+        for (name, kp) in &first {
+            assert_eq!(kp.public(), second[name].public());
+        }
+        assert_eq!(
+            first["weather"].public(),
+            derive_key(&[3u8; 32], "weather", 2).unwrap().public()
+        );
+        assert!(keys[0].signs(Address::from_hex("0x1").unwrap()));
+        assert!(!keys[0].signs(Address::from_hex("0x2").unwrap()));
+    }
+
+    #[test]
+    fn test_validate_feed_keys() {
+        assert!(validate_feed_keys(&[config("a", 0, &["0x1"]), config("b", 0, &["0x2"])]).is_ok());
+        // Duplicate name, feed assigned twice, invalid feed id
+        assert!(validate_feed_keys(&[config("a", 0, &[]), config("a", 1, &[])]).is_err());
+        assert!(validate_feed_keys(&[config("a", 0, &["0x1"]), config("b", 0, &["0x1"])]).is_err());
+        assert!(validate_feed_keys(&[config("a", 0, &["not a feed"])]).is_err());
+        assert!(validate_feed_keys(&[config("", 0, &[])]).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Decoding of OracleFeed objects and their settings across upgrades of the
+//! oracle package.
+//!
+//! A package upgrade cannot add fields to OracleFeed, so it keeps the layout
+//! it was first published with. Settings added since (strict_types,
+//! blob_hash, publisher_keys, blob_object_id, creator) are a dynamic field of
+//! the feed under `feed::SettingsKey`, holding a versioned settings struct
+//! set by `feed::new_with_settings`. Later settings come as a new version
+//! under the same key: the field's type says which version it holds, and a
+//! version this enclave does not know is refused rather than ignored, since
+//! dropping settings such as publisher_keys would weaken the checks.
+//!
+//! Feeds created by `feed::new`, or before settings existed, have no settings
+//! field and keep the defaults: their blob is not hashed, as there is no hash
+//! to check it against, and they are only run when publisher signatures are
+//! not required. A feed with settings always has a 32-byte blob_hash, which
+//! is checked on every run.
+//!
+//! Settings are never changed once set, so the latest version of the field is
+//! read even for a run pinned to an older version of the feed.
+
+use super::OracleFeed;
+use super::config::COEUS_CONFIG;
+use super::onchain::dynamic_field_id;
+use crate::EnclaveError;
+use crate::chain_reader::ChainReader;
+use serde::{Deserialize, Serialize};
+use sui_sdk_types::{Address, Object, StructTag, TypeTag};
+
+/// BCS of `feed::SettingsKey {}`, whose only field is the dummy bool Move
+/// adds to empty structs.
+const SETTINGS_KEY: [u8; 1] = [0];
+
+/// BCS layout of oracle::feed::FeedSettingsV1
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeedSettingsV1 {
+    pub strict_types: bool,
+    pub blob_hash: Vec<u8>,
+    pub publisher_keys: Vec<Vec<u8>>,
+    pub blob_object_id: Option<Address>,
+    pub creator: Address,
+}
+
+/// BCS layout of sui::dynamic_field::Field<SettingsKey, T>
+#[derive(Debug, Deserialize)]
+struct SettingsField<T> {
+    #[allow(dead_code)]
+    id: Address,
+    #[allow(dead_code)]
+    name: bool,
+    value: T,
+}
+
+impl FeedSettingsV1 {
+    fn apply(self, feed: &mut OracleFeed) {
+        feed.strict_types = self.strict_types;
+        feed.blob_hash = self.blob_hash;
+        feed.publisher_keys = self.publisher_keys;
+        feed.blob_object_id = self.blob_object_id;
+        feed.creator = Some(self.creator);
+    }
+}
+
+/// Decode the contents of an OracleFeed object, without its settings.
+pub fn decode(feed_id: &Address, contents: &[u8]) -> Result<OracleFeed, EnclaveError> {
+    bcs::from_bytes(contents).map_err(|e| {
+        EnclaveError::InvalidRequest(format!("Object {} is not an OracleFeed: {}", feed_id, e))
+    })
+}
+
+/// Object id of the settings field of the feed with UID `feed_id`.
+pub fn settings_field_id(feed_id: &Address) -> Result<Address, EnclaveError> {
+    let key_type = TypeTag::Struct(Box::new(COEUS_CONFIG.feed_object.settings_key_type()?));
+    dynamic_field_id(feed_id, &key_type, &SETTINGS_KEY)
+}
+
+/// Set the settings of `feed` from its settings field object.
+pub fn apply_settings(feed: &mut OracleFeed, field: &Object) -> Result<(), EnclaveError> {
+    let move_object = field.as_struct().ok_or_else(|| {
+        EnclaveError::InvalidRequest(format!(
+            "Settings of feed {} are not a Move object",
+            feed.id
+        ))
+    })?;
+    let field_type: &StructTag = move_object.object_type();
+    if field_type != &COEUS_CONFIG.feed_object.settings_v1_type()? {
+        return Err(EnclaveError::InvalidRequest(format!(
+            "Settings of feed {} are a {}, which this enclave does not know",
+            feed.id, field_type
+        )));
+    }
+    let field: SettingsField<FeedSettingsV1> =
+        bcs::from_bytes(move_object.contents()).map_err(|e| {
+            EnclaveError::InvalidRequest(format!(
+                "Failed to deserialize settings of feed {}: {}",
+                feed.id, e
+            ))
+        })?;
+    field.value.apply(feed);
+    Ok(())
+}
+
+/// Read the settings field of `feed`, if it has one, into it.
+pub async fn read_settings(
+    chain: &dyn ChainReader,
+    feed: &mut OracleFeed,
+) -> Result<(), EnclaveError> {
+    let field_id = settings_field_id(&feed.id)?;
+    let read = match chain.get_object(field_id, None).await {
+        Ok(read) => read,
+        // Created by feed::new, keep the defaults
+        Err(status) if status.code() == tonic::Code::NotFound => return Ok(()),
+        Err(status) => {
+            return Err(EnclaveError::RpcError(format!(
+                "Failed to get settings of feed {}: {}",
+                feed.id,
+                status.message()
+            )));
+        }
+    };
+    let field: Object = bcs::from_bytes(&read.bcs).map_err(|e| {
+        EnclaveError::GenericError(format!("Failed to deserialize object {}: {}", field_id, e))
+    })?;
+    apply_settings(feed, &field)
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{CodeExtension, ResultValue, ReturnType};
+    use super::*;
+    use sui_sdk_types::{Digest, MoveStruct, ObjectData, Owner};
+
+    fn feed() -> OracleFeed {
+        OracleFeed {
+            id: Address::from_hex("0x1").unwrap(),
+            blob_id: "blob".to_string(),
+            extension: CodeExtension::RHAI,
+            result: Some(ResultValue::NUMBER(42)),
+            return_type: ReturnType::NUMBER,
+            allow_update_timestamp_ms: 1744038900000,
+            strict_types: false,
+            blob_hash: vec![],
+            publisher_keys: vec![],
+            blob_object_id: None,
+            creator: None,
+        }
+    }
+
+    fn field(field_type: StructTag) -> Object {
+        let settings = FeedSettingsV1 {
+            strict_types: true,
+            blob_hash: vec![7; 32],
+            publisher_keys: vec![vec![1; 32]],
+            blob_object_id: Some(Address::from_hex("0x2").unwrap()),
+            creator: Address::from_hex("0x3").unwrap(),
+        };
+        let contents =
+            bcs::to_bytes(&(Address::from_hex("0x5").unwrap(), false, settings)).unwrap();
+        let move_struct = MoveStruct::new(field_type, false, 3, contents).unwrap();
+        Object::new(
+            ObjectData::Struct(move_struct),
+            Owner::Object(Address::from_hex("0x1").unwrap()),
+            Digest::ZERO,
+            0,
+        )
+    }
+
+    #[test]
+    fn test_decode() {
+        let feed_id = Address::ZERO;
+        let feed = feed();
+        let contents = bcs::to_bytes(&feed).unwrap();
+        assert_eq!(decode(&feed_id, &contents).unwrap(), feed);
+        // Settings are not part of the object
+        let with_settings = OracleFeed {
+            strict_types: true,
+            blob_hash: vec![7; 32],
+            ..feed.clone()
+        };
+        assert_eq!(bcs::to_bytes(&with_settings).unwrap(), contents);
+
+        assert!(decode(&feed_id, &contents[..contents.len() - 8]).is_err());
+        let mut trailing = contents;
+        trailing.push(0);
+        assert!(decode(&feed_id, &trailing).is_err());
+    }
+
+    #[test]
+    fn test_apply_settings() {
+        let mut feed = feed();
+        let settings_type = COEUS_CONFIG.feed_object.settings_v1_type().unwrap();
+        apply_settings(&mut feed, &field(settings_type)).unwrap();
+        assert!(feed.strict_types);
+        assert_eq!(feed.blob_hash, vec![7; 32]);
+        assert_eq!(feed.publisher_keys, vec![vec![1; 32]]);
+        assert_eq!(feed.blob_object_id, Some(Address::from_hex("0x2").unwrap()));
+        assert_eq!(feed.creator, Some(Address::from_hex("0x3").unwrap()));
+
+        // A settings version this enclave does not know
+        let package = &COEUS_CONFIG.feed_object.package_id;
+        let newer: StructTag = format!(
+            "0x2::dynamic_field::Field<{0}::feed::SettingsKey, {0}::feed::FeedSettingsV2>",
+            package
+        )
+        .parse()
+        .unwrap();
+        assert!(apply_settings(&mut self::feed(), &field(newer)).is_err());
+
+        let feed_id = Address::from_hex("0x1").unwrap();
+        assert_ne!(settings_field_id(&feed_id).unwrap(), feed_id);
+    }
+}
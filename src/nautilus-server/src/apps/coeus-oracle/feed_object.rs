@@ -0,0 +1,311 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Checks on the object behind a feed id before its contents are trusted.
+//!
+//! The object must be a `<package_id>::feed::OracleFeed` and, unless
+//! `require_shared` is off, shared as `feed::new` leaves it. Otherwise any
+//! object whose BCS happens to decode as an OracleFeed, such as one created
+//! by another package with the same layout, would have its code executed and
+//! its result signed.
+//!
+//! Feeds in a registry (see registry.rs) are instead the value of a
+//! `0x2::dynamic_field::Field<K, OracleFeed>` owned by a configured registry.
+//!
+//! Only the type, owner and contents of the object are needed, so its BCS is
+//! read into an [`ObjectView`] borrowing the contents from the response bytes
+//! rather than into a `sui_sdk_types::Object`, which copies them before the
+//! feed is decoded from them.
+
+use super::config::COEUS_CONFIG;
+use super::registry::{self, RegistryConfig};
+use crate::EnclaveError;
+use serde::{Deserialize, Serialize};
+use sui_sdk_types::{Address, Digest, Owner, StructTag, TypeTag};
+
+/// Module and struct of the feed type in the oracle package.
+const FEED_TYPE: &str = "feed::OracleFeed";
+
+/// Module and struct of the on-chain code type in the oracle package.
+const SCRIPT_TYPE: &str = "script::Script";
+
+/// Key and value of the feed settings dynamic field, see feed_layout.rs.
+const SETTINGS_KEY_TYPE: &str = "feed::SettingsKey";
+const SETTINGS_V1_TYPE: &str = "feed::FeedSettingsV1";
+
+/// Feed object section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedObjectConfig {
+    /// Package that first published the `feed` module. Types keep this id
+    /// across upgrades, so it differs from push.package_id once upgraded.
+    #[serde(default)]
+    pub package_id: String,
+    /// Package that first published the `script` module, package_id when
+    /// empty. Differs from package_id if the module came with an upgrade.
+    #[serde(default)]
+    pub script_package_id: String,
+    #[serde(default = "default_require_shared")]
+    pub require_shared: bool,
+    /// Registries feeds may be dynamic fields of, none by default.
+    #[serde(default)]
+    pub registries: Vec<RegistryConfig>,
+}
+
+impl Default for FeedObjectConfig {
+    fn default() -> Self {
+        Self {
+            package_id: String::new(),
+            script_package_id: String::new(),
+            require_shared: default_require_shared(),
+            registries: Vec::new(),
+        }
+    }
+}
+
+fn default_require_shared() -> bool {
+    true
+}
+
+impl FeedObjectConfig {
+    /// Move type feed objects must have.
+    pub fn feed_type(&self) -> Result<StructTag, EnclaveError> {
+        package_type(&self.package_id, FEED_TYPE)
+    }
+
+    /// Move type Script objects holding feed code must have.
+    pub fn script_type(&self) -> Result<StructTag, EnclaveError> {
+        if self.script_package_id.is_empty() {
+            return package_type(&self.package_id, SCRIPT_TYPE);
+        }
+        package_type(&self.script_package_id, SCRIPT_TYPE)
+    }
+
+    /// Key type of the settings dynamic field of feeds.
+    pub fn settings_key_type(&self) -> Result<StructTag, EnclaveError> {
+        package_type(&self.package_id, SETTINGS_KEY_TYPE)
+    }
+
+    /// Type of the settings dynamic field holding a FeedSettingsV1.
+    pub fn settings_v1_type(&self) -> Result<StructTag, EnclaveError> {
+        let field_type = format!(
+            "0x2::dynamic_field::Field<{}, {}>",
+            self.settings_key_type()?,
+            package_type(&self.package_id, SETTINGS_V1_TYPE)?
+        );
+        field_type.parse().map_err(|e| {
+            EnclaveError::GenericError(format!("Invalid field type {}: {:?}", field_type, e))
+        })
+    }
+}
+
+/// Type `name` of the oracle package `package_id`.
+fn package_type(package_id: &str, name: &str) -> Result<StructTag, EnclaveError> {
+    if package_id.is_empty() {
+        return Err(EnclaveError::GenericError(
+            "feed_object.package_id is not set in coeus_config.yaml".to_string(),
+        ));
+    }
+    let object_type = format!("{}::{}", package_id, name);
+    object_type
+        .parse()
+        .map_err(|e| EnclaveError::GenericError(format!("Invalid type {}: {:?}", object_type, e)))
+}
+
+/// Type of a Move object as encoded in BCS, `MoveObjectType` in Sui.
+#[derive(Debug, Deserialize)]
+enum ObjectType {
+    Other(StructTag),
+    GasCoin,
+    StakedSui,
+    Coin(TypeTag),
+}
+
+impl ObjectType {
+    fn struct_tag(self) -> Result<StructTag, String> {
+        match self {
+            ObjectType::Other(tag) => Ok(tag),
+            ObjectType::GasCoin => Err("0x2::coin::Coin<0x2::sui::SUI>".to_string()),
+            ObjectType::StakedSui => Err("0x3::staking_pool::StakedSui".to_string()),
+            ObjectType::Coin(coin) => Err(format!("0x2::coin::Coin<{}>", coin)),
+        }
+    }
+}
+
+/// Move struct of an object, as encoded in BCS.
+#[derive(Debug, Deserialize)]
+struct MoveObjectView<'a> {
+    object_type: ObjectType,
+    _has_public_transfer: bool,
+    _version: u64,
+    #[serde(borrow)]
+    contents: &'a [u8],
+}
+
+/// Data of an object. Packages are not read, their variant fails to decode.
+#[derive(Debug, Deserialize)]
+enum ObjectDataView<'a> {
+    Struct(#[serde(borrow)] MoveObjectView<'a>),
+}
+
+/// BCS of a `sui_sdk_types::Object` holding a Move struct, with the contents
+/// borrowed from the encoded bytes.
+#[derive(Debug, Deserialize)]
+pub struct ObjectView<'a> {
+    #[serde(borrow)]
+    data: ObjectDataView<'a>,
+    owner: Owner,
+    _previous_transaction: Digest,
+    _storage_rebate: u64,
+}
+
+/// Variant of ObjectData encoding a package.
+const PACKAGE_VARIANT: u8 = 1;
+
+impl<'a> ObjectView<'a> {
+    /// Read the object encoded in `bcs_bytes`.
+    pub fn decode(id: &Address, bcs_bytes: &'a [u8]) -> Result<Self, EnclaveError> {
+        if bcs_bytes.first() == Some(&PACKAGE_VARIANT) {
+            return Err(EnclaveError::InvalidRequest(format!(
+                "Object {} is not a Move object",
+                id
+            )));
+        }
+        bcs::from_bytes(bcs_bytes).map_err(|e| {
+            EnclaveError::InvalidRequest(format!("Failed to deserialize object {}: {}", id, e))
+        })
+    }
+}
+
+fn check(
+    config: &FeedObjectConfig,
+    feed_id: &Address,
+    object_type: &StructTag,
+    owner: &Owner,
+) -> Result<(), EnclaveError> {
+    let feed_type = config.feed_type()?;
+    if object_type != &feed_type {
+        return Err(EnclaveError::InvalidRequest(format!(
+            "Object {} is a {}, not a {}",
+            feed_id, object_type, feed_type
+        )));
+    }
+    if config.require_shared && !matches!(owner, Owner::Shared(_)) {
+        return Err(EnclaveError::InvalidRequest(format!(
+            "Feed {} is not a shared object, its owner is {:?}",
+            feed_id, owner
+        )));
+    }
+    Ok(())
+}
+
+/// Contents of the OracleFeed in the object encoded in `bcs_bytes`, borrowed
+/// from them, refusing objects that are not feeds of the configured oracle
+/// package or fields of a configured registry.
+pub fn feed_contents<'a>(feed_id: &Address, bcs_bytes: &'a [u8]) -> Result<&'a [u8], EnclaveError> {
+    let ObjectView {
+        data: ObjectDataView::Struct(move_object),
+        owner,
+        ..
+    } = ObjectView::decode(feed_id, bcs_bytes)?;
+    let config = &COEUS_CONFIG.feed_object;
+    let object_type = move_object
+        .object_type
+        .struct_tag()
+        .map_err(|object_type| {
+            EnclaveError::InvalidRequest(format!(
+                "Object {} is a {}, not an OracleFeed",
+                feed_id, object_type
+            ))
+        })?;
+    if let Owner::Object(registry_id) = &owner {
+        return registry::field_value(
+            registry_id,
+            feed_id,
+            &object_type,
+            &config.feed_type()?,
+            move_object.contents,
+        );
+    }
+    check(config, feed_id, &object_type, &owner)?;
+    Ok(move_object.contents)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let package = "0x1432eec6bbbaa52dbebac2e7678d52ad03e551e5427aa6120f87ec3d8223b71d";
+        let config = FeedObjectConfig {
+            package_id: package.to_string(),
+            require_shared: true,
+            ..FeedObjectConfig::default()
+        };
+        let feed_id = Address::from_hex("0x1").unwrap();
+        let feed_type: StructTag = format!("{}::feed::OracleFeed", package).parse().unwrap();
+        let shared = Owner::Shared(1);
+        assert!(check(&config, &feed_id, &feed_type, &shared).is_ok());
+
+        // Same layout from another package
+        let other_type: StructTag = "0x2::feed::OracleFeed".parse().unwrap();
+        assert!(check(&config, &feed_id, &other_type, &shared).is_err());
+
+        let owned = Owner::Address(Address::ZERO);
+        assert!(check(&config, &feed_id, &feed_type, &owned).is_err());
+        let config = FeedObjectConfig {
+            require_shared: false,
+            ..config
+        };
+        assert!(check(&config, &feed_id, &feed_type, &owned).is_ok());
+
+        assert!(FeedObjectConfig::default().feed_type().is_err());
+
+        let script_type: StructTag = format!("{}::script::Script", package).parse().unwrap();
+        assert_eq!(config.script_type().unwrap(), script_type);
+        let config = FeedObjectConfig {
+            script_package_id: "0x5".to_string(),
+            ..config
+        };
+        assert_eq!(
+            config.script_type().unwrap(),
+            "0x5::script::Script".parse().unwrap()
+        );
+
+        let settings_type: StructTag = format!(
+            "0x2::dynamic_field::Field<{0}::feed::SettingsKey, {0}::feed::FeedSettingsV1>",
+            package
+        )
+        .parse()
+        .unwrap();
+        assert_eq!(config.settings_v1_type().unwrap(), settings_type);
+    }
+
+    #[test]
+    fn test_object_view() {
+        use sui_sdk_types::{MoveStruct, Object, ObjectData};
+
+        let feed_id = Address::from_hex("0xf1").unwrap();
+        let feed_type = COEUS_CONFIG.feed_object.feed_type().unwrap();
+        let contents = [feed_id.as_bytes().as_slice(), &[7; 100]].concat();
+        let encode = |object_type: StructTag, owner: Owner| {
+            let move_struct = MoveStruct::new(object_type, true, 3, contents.clone()).unwrap();
+            let object = Object::new(ObjectData::Struct(move_struct), owner, Digest::ZERO, 9);
+            bcs::to_bytes(&object).unwrap()
+        };
+
+        // Same contents as decoding the whole object, without copying them
+        let bytes = encode(feed_type.clone(), Owner::Shared(1));
+        let view = ObjectView::decode(&feed_id, &bytes).unwrap();
+        assert_eq!(view.owner, Owner::Shared(1));
+        let read = feed_contents(&feed_id, &bytes).unwrap();
+        assert_eq!(read, contents.as_slice());
+        assert!(bytes.as_ptr_range().contains(&read.as_ptr()));
+
+        let other_type: StructTag = "0x2::feed::OracleFeed".parse().unwrap();
+        let bytes = encode(other_type, Owner::Shared(1));
+        assert!(feed_contents(&feed_id, &bytes).is_err());
+        assert!(feed_contents(&feed_id, &[PACKAGE_VARIANT, 0]).is_err());
+        assert!(feed_contents(&feed_id, &bytes[..bytes.len() - 1]).is_err());
+    }
+}
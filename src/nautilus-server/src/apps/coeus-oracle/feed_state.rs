@@ -0,0 +1,291 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Reads of the OracleFeed object pinned to an object version or a
+//! checkpoint, and the signed record of the feed state a result was computed
+//! from.
+//!
+//! process_data reads the latest version of the feed, unless the request sets
+//! `version` (that object version) or `checkpoint` (the version current as of
+//! that checkpoint). Every result carries a `feed_state` section with the
+//! version read, the checkpoint it is current as of and the configuration the
+//! feed had at that version (blob_id, blob_hash, return_type), signed under
+//! IntentScope::FeedState with the result's timestamp. The ProcessData payload
+//! itself is unchanged so it still verifies on-chain.
+//!
+//! A disputed result can thus be reproduced by running the feed pinned to the
+//! version in its feed_state: the new feed_state then signs that the same
+//! code and return type were used. A pinned result is signed under
+//! IntentScope::PinnedResult instead of ProcessData, so the Move module refuses
+//! it and an old feed state can never be submitted as a new update. Pinned runs
+//! also leave the update policy, the history and the webhooks alone.
+//!
+//! A checkpoint is resolved by walking back from the latest version through
+//! the transactions that wrote each version, so only recent checkpoints can be
+//! pinned, on fullnodes that still serve the older versions.
+
+use super::audit;
+use super::feed_rpc_error;
+use super::{APP_ID, OracleFeed, ReturnType};
+use crate::EnclaveError;
+use crate::chain_reader::{ChainReader, ObjectRead};
+use crate::common::{IntentScope, to_signed_response};
+use fastcrypto::ed25519::Ed25519KeyPair;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{KeyPair, ToFromBytes};
+use serde::{Deserialize, Serialize};
+use sui_sdk_types::Address;
+use utoipa::ToSchema;
+
+/// Versions walked back before giving up on a checkpoint.
+const MAX_VERSIONS_BACK: usize = 32;
+
+/// Feed state requested by process_data, the latest one by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FeedPin {
+    pub version: Option<u64>,
+    pub checkpoint: Option<u64>,
+}
+
+impl FeedPin {
+    pub fn new(version: Option<u64>, checkpoint: Option<u64>) -> Result<Self, EnclaveError> {
+        if version.is_some() && checkpoint.is_some() {
+            return Err(EnclaveError::InvalidRequest(
+                "Set either version or checkpoint, not both".to_string(),
+            ));
+        }
+        Ok(Self {
+            version,
+            checkpoint,
+        })
+    }
+
+    /// Whether an older state than the latest may be read.
+    pub fn is_pinned(&self) -> bool {
+        self.version.is_some() || self.checkpoint.is_some()
+    }
+}
+
+/// Inner type T for IntentMessage<T> signed under IntentScope::FeedState.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeedState {
+    pub feed_id: Address,
+    /// Version of the feed object read.
+    pub version: u64,
+    /// Checkpoint the version is current as of, unknown when an explicit
+    /// version was requested or the fullnode did not report its checkpoint.
+    pub checkpoint: Option<u64>,
+    /// Configuration of the feed at that version.
+    pub blob_id: String,
+    pub blob_hash: Vec<u8>,
+    pub return_type: ReturnType,
+}
+
+impl FeedState {
+    pub fn new(read: &FeedRead, feed: &OracleFeed) -> Self {
+        Self {
+            feed_id: read.feed_id,
+            version: read.version,
+            checkpoint: read.checkpoint,
+            blob_id: feed.blob_id.clone(),
+            blob_hash: feed.blob_hash.clone(),
+            return_type: feed.return_type.clone(),
+        }
+    }
+}
+
+/// Signed feed state returned next to a process_data result.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FeedStateSection {
+    /// Hex feed object id.
+    pub feed_id: String,
+    pub version: u64,
+    pub checkpoint: Option<u64>,
+    pub blob_id: String,
+    /// Hex SHA-256 of the script blob.
+    pub blob_hash: String,
+    pub return_type: ReturnType,
+    /// Hex signature over IntentMessage<FeedState> with the result's timestamp.
+    pub signature: String,
+}
+
+impl FeedStateSection {
+    /// Sign `state` and append the signature to the audit log.
+    pub async fn sign(kp: &Ed25519KeyPair, state: FeedState, timestamp_ms: u64) -> Self {
+        let signed = to_signed_response(
+            kp,
+            state.clone(),
+            timestamp_ms,
+            IntentScope::FeedState,
+            APP_ID,
+        );
+        audit::record(
+            Some(state.feed_id),
+            kp.public().as_bytes(),
+            &signed.response,
+        )
+        .await;
+        Self {
+            feed_id: state.feed_id.to_string(),
+            version: state.version,
+            checkpoint: state.checkpoint,
+            blob_id: state.blob_id,
+            blob_hash: Hex::encode(state.blob_hash),
+            return_type: state.return_type,
+            signature: signed.signature,
+        }
+    }
+}
+
+/// BCS of the feed object, with the version read and the checkpoint it is
+/// current as of.
+pub struct FeedRead {
+    pub bcs: Vec<u8>,
+    pub feed_id: Address,
+    pub version: u64,
+    pub checkpoint: Option<u64>,
+}
+
+fn into_read(object: ObjectRead, feed_id: Address, checkpoint: Option<u64>) -> FeedRead {
+    FeedRead {
+        bcs: object.bcs,
+        feed_id,
+        version: object.version,
+        checkpoint,
+    }
+}
+
+/// Checkpoint and input version of the feed in the transaction that wrote a
+/// version, the input version is None if it created the feed.
+struct Write {
+    checkpoint: u64,
+    input_version: Option<u64>,
+}
+
+/// Read a version of the feed, the latest by default, with the backend's
+/// latest checkpoint.
+async fn get_object(
+    chain: &dyn ChainReader,
+    feed_id: Address,
+    version: Option<u64>,
+) -> Result<ObjectRead, EnclaveError> {
+    chain
+        .get_object(feed_id, version)
+        .await
+        .map_err(|status| match version {
+            Some(version) if status.code() == tonic::Code::NotFound => {
+                EnclaveError::InvalidRequest(format!(
+                    "Version {} of feed {} is not available on the fullnode",
+                    version, feed_id
+                ))
+            }
+            _ => feed_rpc_error(&feed_id, "get_object", status),
+        })
+}
+
+/// Where the transaction `digest` wrote the feed.
+async fn written_by(
+    chain: &dyn ChainReader,
+    digest: &str,
+    feed_id: Address,
+) -> Result<Write, EnclaveError> {
+    let transaction = chain.get_transaction(digest).await.map_err(|e| {
+        EnclaveError::RpcError(format!(
+            "get_transaction {} failed: {}",
+            digest,
+            e.message()
+        ))
+    })?;
+    let checkpoint = transaction.checkpoint.ok_or_else(|| {
+        EnclaveError::RpcError(format!("Transaction {} is not in a checkpoint yet", digest))
+    })?;
+    let input_version = transaction
+        .input_versions
+        .into_iter()
+        .find(|(id, _)| *id == feed_id)
+        .map(|(_, version)| version);
+    Ok(Write {
+        checkpoint,
+        input_version,
+    })
+}
+
+/// Read the feed object in the state requested by `pin`.
+pub async fn read_feed(
+    chain: &dyn ChainReader,
+    feed_id: Address,
+    pin: FeedPin,
+) -> Result<FeedRead, EnclaveError> {
+    let mut object = get_object(chain, feed_id, pin.version).await?;
+    let height = object.checkpoint;
+    let Some(checkpoint) = pin.checkpoint else {
+        // The latest version is current as of the fullnode's checkpoint
+        let checkpoint = height.filter(|_| pin.version.is_none());
+        return Ok(into_read(object, feed_id, checkpoint));
+    };
+    if let Some(height) = height.filter(|height| *height < checkpoint) {
+        return Err(EnclaveError::RpcError(format!(
+            "The fullnode is at checkpoint {}, it has not reached {} yet",
+            height, checkpoint
+        )));
+    }
+    for _ in 0..MAX_VERSIONS_BACK {
+        let Some(digest) = object.previous_transaction.take() else {
+            return Err(EnclaveError::RpcError(format!(
+                "get_object returned no previous transaction for version {} of feed {}",
+                object.version, feed_id
+            )));
+        };
+        let write = written_by(chain, &digest, feed_id).await?;
+        if write.checkpoint <= checkpoint {
+            return Ok(into_read(object, feed_id, Some(checkpoint)));
+        }
+        let Some(input_version) = write.input_version else {
+            return Err(EnclaveError::InvalidRequest(format!(
+                "Feed {} did not exist at checkpoint {}",
+                feed_id, checkpoint
+            )));
+        };
+        object = get_object(chain, feed_id, Some(input_version)).await?;
+    }
+    Err(EnclaveError::InvalidRequest(format!(
+        "Feed {} changed more than {} times since checkpoint {}",
+        feed_id, MAX_VERSIONS_BACK, checkpoint
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::IntentMessage;
+    use fastcrypto::ed25519::Ed25519Signature;
+    use fastcrypto::traits::VerifyingKey;
+
+    #[tokio::test]
+    async fn test_feed_state() {
+        assert!(!FeedPin::new(None, None).unwrap().is_pinned());
+        assert!(FeedPin::new(Some(3), None).unwrap().is_pinned());
+        assert!(FeedPin::new(Some(3), Some(10)).is_err());
+
+        let kp = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let state = FeedState {
+            feed_id: Address::from_hex("0x1").unwrap(),
+            version: 7,
+            checkpoint: Some(1234),
+            blob_id: "blob".to_string(),
+            blob_hash: vec![7; 32],
+            return_type: ReturnType::NUMBER,
+        };
+        let section = FeedStateSection::sign(&kp, state.clone(), 1744038900000).await;
+        assert_eq!(section.version, 7);
+        assert_eq!(section.blob_hash, Hex::encode([7; 32]));
+        let message = IntentMessage::new(state, 1744038900000, IntentScope::FeedState, APP_ID);
+        let signature =
+            Ed25519Signature::from_bytes(&Hex::decode(&section.signature).unwrap()).unwrap();
+        assert!(
+            kp.public()
+                .verify(&bcs::to_bytes(&message).unwrap(), &signature)
+                .is_ok()
+        );
+    }
+}
@@ -0,0 +1,380 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Gas coins paying for push-mode submissions, owned by the enclave address.
+//!
+//! Each submission pays with the largest coin that covers its budget. A coin
+//! whose submission failed on a lock or stale version is set aside for
+//! `suspend_ms`, so the retry goes out with another coin instead of waiting
+//! for the fullnode to release it.
+//!
+//! While push mode is enabled the coins are also checked every `refresh_ms`:
+//! coins below `min_coin_balance` are merged into the largest one, which is
+//! then split until `target_coins` coins can each pay a submission. The total
+//! balance and coin count are exported as metrics, and a warning is logged
+//! when the balance drops below `low_balance_mist`. When submissions are
+//! sponsored, the coins are those of the sponsor, watched but never merged or
+//! split since the enclave cannot sign for them.
+
+use super::config::COEUS_CONFIG;
+use super::submit::{SUBMIT_LOCK, execute, gas_owner, reference_gas_price, sign, sui_signing_key};
+use crate::{AppState, EnclaveError, metrics};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use sui_rpc::field::{FieldMask, FieldMaskUtil};
+use sui_rpc::proto::sui::rpc::v2::ListOwnedObjectsRequest;
+use sui_sdk_types::{
+    Address, Argument, Command, Digest, GasPayment, Input, MergeCoins, ObjectReference,
+    ProgrammableTransaction, SplitCoins, Transaction, TransactionExpiration, TransactionKind,
+    TransferObjects,
+};
+use tracing::{info, warn};
+
+const SUI_COIN_TYPE: &str = "0x2::coin::Coin<0x2::sui::SUI>";
+
+/// Coins merged by one transaction, below the PTB input limit.
+const MAX_MERGED_COINS: usize = 256;
+
+lazy_static::lazy_static! {
+    /// Coins set aside after a failed submission, with the time they are
+    /// usable again.
+    static ref SUSPENDED: Mutex<HashMap<Address, Instant>> = Mutex::new(HashMap::new());
+}
+
+/// Gas section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GasConfig {
+    /// Coins kept that can each pay a submission, 1 disables splitting.
+    #[serde(default = "default_target_coins")]
+    pub target_coins: usize,
+    /// Coins with less are merged into the largest one.
+    #[serde(default = "default_min_coin_balance")]
+    pub min_coin_balance: u64,
+    /// Total balance below which a warning is logged.
+    #[serde(default = "default_low_balance_mist")]
+    pub low_balance_mist: u64,
+    #[serde(default = "default_refresh_ms")]
+    pub refresh_ms: u64,
+    /// How long a coin is skipped after a failed submission.
+    #[serde(default = "default_suspend_ms")]
+    pub suspend_ms: u64,
+}
+
+impl Default for GasConfig {
+    fn default() -> Self {
+        Self {
+            target_coins: default_target_coins(),
+            min_coin_balance: default_min_coin_balance(),
+            low_balance_mist: default_low_balance_mist(),
+            refresh_ms: default_refresh_ms(),
+            suspend_ms: default_suspend_ms(),
+        }
+    }
+}
+
+fn default_target_coins() -> usize {
+    4
+}
+
+fn default_min_coin_balance() -> u64 {
+    100_000_000
+}
+
+fn default_low_balance_mist() -> u64 {
+    1_000_000_000
+}
+
+fn default_refresh_ms() -> u64 {
+    60_000
+}
+
+fn default_suspend_ms() -> u64 {
+    30_000
+}
+
+/// A SUI coin of the enclave address.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GasCoin {
+    pub reference: ObjectReference,
+    pub balance: u64,
+}
+
+/// Merges and splits bringing the coins back to the configured shape.
+#[derive(Debug, PartialEq)]
+struct Rebalance {
+    /// Coin paying for the transaction, the others are merged into it.
+    gas: ObjectReference,
+    merge: Vec<ObjectReference>,
+    /// Amounts of the new coins split off the gas coin.
+    split: Vec<u64>,
+}
+
+/// SUI coins owned by `owner`.
+async fn list_coins(state: &AppState, owner: Address) -> Result<Vec<GasCoin>, EnclaveError> {
+    let mut request = ListOwnedObjectsRequest::default();
+    request.owner = Some(owner.to_string());
+    request.object_type = Some(SUI_COIN_TYPE.to_string());
+    request.read_mask = Some(FieldMask::from_paths([
+        "object_id",
+        "version",
+        "digest",
+        "balance",
+    ]));
+
+    let objects = state
+        .sui
+        .call("list_owned_objects", |mut client| {
+            let request = request.clone();
+            async move { client.state_client().list_owned_objects(request).await }
+        })
+        .await
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to list gas coins: {}", e)))?
+        .into_inner()
+        .objects;
+
+    objects
+        .into_iter()
+        .map(|coin| {
+            let object_id = coin.object_id.as_deref().unwrap_or_default();
+            let object_id = Address::from_hex(object_id).map_err(|e| {
+                EnclaveError::GenericError(format!("Invalid gas coin {}: {}", object_id, e))
+            })?;
+            let digest = coin
+                .digest
+                .as_deref()
+                .and_then(|d| Digest::from_base58(d).ok())
+                .ok_or_else(|| EnclaveError::GenericError("Gas coin has no digest".to_string()))?;
+            let version = coin
+                .version
+                .ok_or_else(|| EnclaveError::GenericError("Gas coin has no version".to_string()))?;
+            Ok(GasCoin {
+                reference: ObjectReference::new(object_id, version, digest),
+                balance: coin.balance.unwrap_or(0),
+            })
+        })
+        .collect()
+}
+
+/// Largest coin covering `budget` that is not suspended.
+fn select(coins: Vec<GasCoin>, budget: u64, now: Instant) -> Option<GasCoin> {
+    let suspended = SUSPENDED.lock().expect("gas lock poisoned");
+    coins
+        .into_iter()
+        .filter(|coin| coin.balance >= budget)
+        .filter(|coin| {
+            suspended
+                .get(coin.reference.object_id())
+                .is_none_or(|until| *until <= now)
+        })
+        .max_by_key(|coin| coin.balance)
+}
+
+/// Gas coin for a submission with `budget` from `owner`.
+pub async fn pick(
+    state: &AppState,
+    owner: Address,
+    budget: u64,
+) -> Result<ObjectReference, EnclaveError> {
+    let coins = list_coins(state, owner).await?;
+    let count = coins.len();
+    select(coins, budget, Instant::now())
+        .map(|coin| coin.reference)
+        .ok_or_else(|| {
+            EnclaveError::GenericError(format!(
+                "No usable gas coin of at least {} MIST among the {} owned by {}",
+                budget, count, owner
+            ))
+        })
+}
+
+/// Skip coin `id` for a while, after a submission with it failed.
+pub fn suspend(id: Address) {
+    let until = Instant::now() + Duration::from_millis(COEUS_CONFIG.gas.suspend_ms);
+    let mut suspended = SUSPENDED.lock().expect("gas lock poisoned");
+    suspended.retain(|_, until| *until > Instant::now());
+    suspended.insert(id, until);
+}
+
+/// Plan the merges and splits of `coins`, None when they are fine. The gas
+/// coin keeps `budget` to pay for the transaction.
+fn plan(mut coins: Vec<GasCoin>, config: &GasConfig, budget: u64) -> Option<Rebalance> {
+    coins.sort_by_key(|coin| std::cmp::Reverse(coin.balance));
+    let mut coins = coins.into_iter();
+    let gas = coins.next()?;
+    let (usable, dust): (Vec<_>, Vec<_>) =
+        coins.partition(|coin| coin.balance >= config.min_coin_balance);
+    let merge: Vec<_> = dust.into_iter().take(MAX_MERGED_COINS).collect();
+
+    // Split the gas coin, with its merged dust, into equal shares
+    let merged: u64 = merge.iter().map(|coin| coin.balance).sum();
+    let available = (gas.balance + merged).saturating_sub(budget);
+    let missing = config.target_coins.saturating_sub(usable.len() + 1);
+    let mut split = Vec::new();
+    if missing > 0 {
+        let share = available / (missing as u64 + 1);
+        if share >= config.min_coin_balance {
+            split = vec![share; missing];
+        }
+    }
+    if merge.is_empty() && split.is_empty() {
+        return None;
+    }
+    Some(Rebalance {
+        gas: gas.reference,
+        merge: merge.into_iter().map(|coin| coin.reference).collect(),
+        split,
+    })
+}
+
+fn rebalance_ptb(
+    rebalance: &Rebalance,
+    owner: Address,
+) -> Result<ProgrammableTransaction, EnclaveError> {
+    let mut inputs: Vec<Input> = rebalance
+        .merge
+        .iter()
+        .cloned()
+        .map(Input::ImmutableOrOwned)
+        .collect();
+    let mut commands = Vec::new();
+    if !rebalance.merge.is_empty() {
+        commands.push(Command::MergeCoins(MergeCoins {
+            coin: Argument::Gas,
+            coins_to_merge: (0..inputs.len() as u16).map(Argument::Input).collect(),
+        }));
+    }
+    if !rebalance.split.is_empty() {
+        let first_amount = inputs.len() as u16;
+        for amount in &rebalance.split {
+            let value = bcs::to_bytes(amount).map_err(|e| {
+                EnclaveError::GenericError(format!("Failed to encode input: {}", e))
+            })?;
+            inputs.push(Input::Pure { value });
+        }
+        let address = bcs::to_bytes(&owner)
+            .map_err(|e| EnclaveError::GenericError(format!("Failed to encode input: {}", e)))?;
+        inputs.push(Input::Pure { value: address });
+        let split = commands.len() as u16;
+        commands.push(Command::SplitCoins(SplitCoins {
+            coin: Argument::Gas,
+            amounts: (first_amount..first_amount + rebalance.split.len() as u16)
+                .map(Argument::Input)
+                .collect(),
+        }));
+        commands.push(Command::TransferObjects(TransferObjects {
+            objects: (0..rebalance.split.len() as u16)
+                .map(|coin| Argument::NestedResult(split, coin))
+                .collect(),
+            address: Argument::Input(inputs.len() as u16 - 1),
+        }));
+    }
+    Ok(ProgrammableTransaction { inputs, commands })
+}
+
+/// Check the coins, export their balance and merge or split them if needed.
+async fn refresh(state: &AppState) -> Result<(), EnclaveError> {
+    let config = &COEUS_CONFIG.gas;
+    let owner = gas_owner(state)?;
+    let coins = list_coins(state, owner).await?;
+    let balance: u64 = coins.iter().map(|coin| coin.balance).sum();
+    metrics::GAS_BALANCE.set(balance.try_into().unwrap_or(i64::MAX));
+    metrics::GAS_COINS.set(coins.len() as i64);
+    if balance < config.low_balance_mist {
+        warn!(
+            "Gas balance of {} is low: {} MIST, push submissions fail once it runs out",
+            owner, balance
+        );
+    }
+
+    if COEUS_CONFIG.push.sponsor.is_some() {
+        return Ok(());
+    }
+    let budget = COEUS_CONFIG.push.gas_budget;
+    let Some(rebalance) = plan(coins, config, budget) else {
+        return Ok(());
+    };
+    let transaction = Transaction {
+        kind: TransactionKind::ProgrammableTransaction(rebalance_ptb(&rebalance, owner)?),
+        sender: owner,
+        gas_payment: GasPayment {
+            objects: vec![rebalance.gas.clone()],
+            owner,
+            price: reference_gas_price(state).await?,
+            budget,
+        },
+        expiration: TransactionExpiration::None,
+    };
+    // Submissions would race for the same coins
+    let _guard = SUBMIT_LOCK.lock().await;
+    let signature = sign(&sui_signing_key(state)?, &transaction)?;
+    let digest = execute(state, transaction, vec![signature]).await?;
+    info!(
+        "Merged {} and split {} gas coins: {}",
+        rebalance.merge.len(),
+        rebalance.split.len(),
+        digest
+    );
+    Ok(())
+}
+
+/// Watch the gas coins while push mode is enabled.
+pub fn spawn_gas_station(state: Arc<AppState>) {
+    if !COEUS_CONFIG.push.enabled || COEUS_CONFIG.gas.refresh_ms == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let interval = Duration::from_millis(COEUS_CONFIG.gas.refresh_ms);
+        loop {
+            if let Err(e) = refresh(&state).await {
+                warn!("Gas coin refresh failed: {}", e);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn coin(id: &str, balance: u64) -> GasCoin {
+        GasCoin {
+            reference: ObjectReference::new(Address::from_hex(id).unwrap(), 1, Digest::ZERO),
+            balance,
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let config = GasConfig::default();
+        let budget = 50_000_000;
+
+        // Merge the dust, split the rest into 4 coins
+        let coins = vec![coin("0x1", 1_000), coin("0x2", 10_050_000_000)];
+        let rebalance = plan(coins, &config, budget).unwrap();
+        assert_eq!(rebalance.gas, coin("0x2", 0).reference);
+        assert_eq!(rebalance.merge, vec![coin("0x1", 0).reference]);
+        assert_eq!(rebalance.split, vec![2_500_000_250; 3]);
+        let ptb = rebalance_ptb(&rebalance, Address::ZERO).unwrap();
+        assert_eq!(ptb.inputs.len(), 5);
+        assert_eq!(ptb.commands.len(), 3);
+
+        // Already in shape, or too little to split
+        let coins = (1..=4)
+            .map(|i| coin(&format!("0x{}", i), 1_000_000_000))
+            .collect();
+        assert_eq!(plan(coins, &config, budget), None);
+        assert_eq!(plan(vec![coin("0x1", 200_000_000)], &config, budget), None);
+        assert_eq!(plan(vec![], &config, budget), None);
+
+        // Suspended coins are skipped
+        let now = Instant::now();
+        let coins = vec![coin("0x7", 1_000_000_000), coin("0x8", 2_000_000_000)];
+        assert_eq!(select(coins.clone(), budget, now), Some(coins[1].clone()));
+        suspend(Address::from_hex("0x8").unwrap());
+        assert_eq!(select(coins.clone(), budget, now), Some(coins[0].clone()));
+        assert_eq!(select(coins, 1_500_000_000, now), None);
+    }
+}
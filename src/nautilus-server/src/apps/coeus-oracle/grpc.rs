@@ -0,0 +1,497 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! gRPC service offering process_data, execute_code and get_attestation to
+//! relayers that prefer typed or streaming RPC over JSON. Defined in
+//! proto/coeus/v1/oracle.proto and served on its own port when `[grpc]` is
+//! enabled in nautilus.toml, over TLS with the attested certificate when
+//! built with the ra-tls feature.
+//!
+//! Every call goes through the REST handler, so it gets the same validation,
+//! limits and signatures. API keys and per-client rate limits are read from
+//! the call metadata as from REST headers. Errors become statuses whose
+//! message is the problem detail, with the stable error code in the
+//! `error-code` metadata.
+
+pub mod proto {
+    tonic::include_proto!("coeus.v1");
+}
+
+use super::attestation::{AttestationQuery, AttestationResponse, current_attestation};
+use super::encryption::ResultOutput;
+use super::{
+    ExecuteCodeRequest, ExecuteCodeResponse, ResultValue, ReturnType, UpdateOracleRequest,
+    UpdateOracleResponse,
+};
+use crate::auth::{self, Scope};
+use crate::common::{IntentMessage, ProcessedDataResponse};
+use crate::extract::AppJson;
+use crate::request_id::{self, REQUEST_ID_HEADER};
+use crate::{AppState, EnclaveError, rate_limit};
+use axum::Json;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use fastcrypto::encoding::{Encoding, Hex};
+use proto::oracle_server::{Oracle, OracleServer};
+use proto::process_data_response::Output;
+use proto::process_data_stream_response::Outcome;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::{Semaphore, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::metadata::MetadataValue;
+use tonic::transport::Server;
+use tonic::{Code, Request, Response, Status, Streaming};
+use tracing::{Instrument, info};
+
+/// Metadata carrying the stable error code of a failed call.
+pub const ERROR_CODE_METADATA: &str = "error-code";
+
+/// Results buffered on a ProcessDataStream before runs wait for the client.
+const STREAM_BUFFER: usize = 32;
+
+/// gRPC status code for the HTTP status of an error.
+fn grpc_code(status: StatusCode) -> Code {
+    match status {
+        StatusCode::BAD_REQUEST => Code::InvalidArgument,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::NOT_FOUND => Code::NotFound,
+        StatusCode::CONFLICT | StatusCode::UNPROCESSABLE_ENTITY => Code::FailedPrecondition,
+        StatusCode::PAYLOAD_TOO_LARGE | StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+        StatusCode::GATEWAY_TIMEOUT => Code::DeadlineExceeded,
+        _ => Code::Internal,
+    }
+}
+
+/// Status for a failed call, with the error code and any retry hint as
+/// metadata.
+pub fn to_status(error: EnclaveError) -> Status {
+    let code = grpc_code(error.status());
+    let mut status = Status::new(code, error.to_string());
+    let metadata = status.metadata_mut();
+    metadata.insert(
+        ERROR_CODE_METADATA,
+        MetadataValue::from_static(error.code()),
+    );
+    if let EnclaveError::RateLimited { retry_after_ms }
+    | EnclaveError::Overloaded { retry_after_ms } = error
+    {
+        metadata.insert("retry-after-ms", retry_after_ms.into());
+    }
+    status
+}
+
+fn decode_hex(field: &str, hex: &str) -> Result<Vec<u8>, EnclaveError> {
+    Hex::decode(hex)
+        .map_err(|e| EnclaveError::GenericError(format!("Invalid hex {}: {}", field, e)))
+}
+
+fn result_value(value: ResultValue) -> proto::ResultValue {
+    use proto::result_value::Value;
+    let value = match value {
+        ResultValue::STRING(value) => Value::StringValue(value),
+        ResultValue::BOOLEAN(value) => Value::BooleanValue(value),
+        ResultValue::NUMBER(value) => Value::NumberValue(value),
+        ResultValue::VECTOR(value) => Value::VectorValue(value),
+    };
+    proto::ResultValue { value: Some(value) }
+}
+
+fn return_type(return_type: i32) -> Result<ReturnType, EnclaveError> {
+    match proto::ReturnType::try_from(return_type) {
+        Ok(proto::ReturnType::String) => Ok(ReturnType::STRING),
+        Ok(proto::ReturnType::Boolean) => Ok(ReturnType::BOOLEAN),
+        Ok(proto::ReturnType::Number) => Ok(ReturnType::NUMBER),
+        Ok(proto::ReturnType::Vector) => Ok(ReturnType::VECTOR),
+        Err(_) => Err(EnclaveError::InvalidRequest(format!(
+            "Unknown return_type {}",
+            return_type
+        ))),
+    }
+}
+
+/// Typed form of a signed result. `json` is the REST body it came from.
+fn signed_result(
+    signed: &ProcessedDataResponse<IntentMessage<UpdateOracleResponse>>,
+    bls_signature: Option<&str>,
+    previous_key_signature: Option<&str>,
+    json: String,
+) -> Result<proto::SignedResult, EnclaveError> {
+    let intent_message = bcs::to_bytes(&signed.response).map_err(|e| {
+        EnclaveError::GenericError(format!("Failed to encode intent message: {}", e))
+    })?;
+    Ok(proto::SignedResult {
+        intent_message,
+        signature: decode_hex("signature", &signed.signature)?,
+        timestamp_ms: signed.response.timestamp_ms,
+        result: signed.response.data.result.clone().map(result_value),
+        bls_signature: bls_signature
+            .map(|signature| decode_hex("bls_signature", signature))
+            .transpose()?,
+        previous_key_signature: previous_key_signature
+            .map(|signature| decode_hex("previous_key_signature", signature))
+            .transpose()?,
+        json,
+    })
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<String, EnclaveError> {
+    serde_json::to_string(value)
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to serialize response: {}", e)))
+}
+
+fn update_request(request: proto::ProcessDataRequest) -> UpdateOracleRequest {
+    UpdateOracleRequest {
+        feed_id: request.feed_id,
+        registry_id: request.registry_id,
+        key: request.key,
+        sign_meta: request.sign_meta,
+        round_timestamp_ms: request.round_timestamp_ms,
+        recipient_public_key: request.recipient_public_key,
+        version: request.version,
+        checkpoint: request.checkpoint,
+    }
+}
+
+fn process_data_response(output: ResultOutput) -> Result<proto::ProcessDataResponse, EnclaveError> {
+    let output = match output {
+        ResultOutput::Encrypted { encrypted } => Output::Encrypted(proto::EncryptedResult {
+            recipient_public_key: decode_hex(
+                "recipient_public_key",
+                &encrypted.recipient_public_key,
+            )?,
+            ephemeral_public_key: decode_hex(
+                "ephemeral_public_key",
+                &encrypted.ephemeral_public_key,
+            )?,
+            nonce: decode_hex("nonce", &encrypted.nonce)?,
+            ciphertext: decode_hex("ciphertext", &encrypted.ciphertext)?,
+        }),
+        ResultOutput::Plain(response) => Output::Signed(signed_result(
+            &response.signed,
+            response.bls_signature.as_deref(),
+            response.previous_key_signature.as_deref(),
+            to_json(&response)?,
+        )?),
+    };
+    Ok(proto::ProcessDataResponse {
+        output: Some(output),
+    })
+}
+
+/// Run a feed as POST /process_data does.
+async fn run_process_data(
+    state: Arc<AppState>,
+    headers: HeaderMap,
+    request: proto::ProcessDataRequest,
+) -> Result<proto::ProcessDataResponse, EnclaveError> {
+    let request = AppJson(update_request(request));
+    let Json(output) = super::process_data(State(state), headers, request).await?;
+    process_data_response(output)
+}
+
+fn execute_code_response(
+    response: ExecuteCodeResponse,
+) -> Result<proto::ExecuteCodeResponse, EnclaveError> {
+    let json = to_json(&response)?;
+    Ok(proto::ExecuteCodeResponse {
+        success: response.success,
+        result: response.success.then(|| result_value(response.result)),
+        error: response.error,
+        signed: response
+            .signed
+            .map(|signed| signed_result(&signed, None, None, json))
+            .transpose()?,
+    })
+}
+
+fn attestation_response(
+    response: AttestationResponse,
+) -> Result<proto::GetAttestationResponse, EnclaveError> {
+    Ok(proto::GetAttestationResponse {
+        attestation: decode_hex("attestation", &response.attestation)?,
+        public_key: decode_hex("public_key", &response.public_key)?,
+        config_hash: decode_hex("config_hash", &response.config_hash)?,
+        app_config_hash: decode_hex("app_config_hash", &response.app_config_hash)?,
+        created_at_ms: response.created_at_ms,
+    })
+}
+
+pub struct OracleService {
+    state: Arc<AppState>,
+}
+
+impl OracleService {
+    /// Run a unary call under a request id, after the API key and client
+    /// rate limit checks of the REST routes. The id is returned in the
+    /// x-request-id metadata.
+    async fn call<T, R, F, Fut>(
+        &self,
+        request: Request<T>,
+        scope: Option<Scope>,
+        handler: F,
+    ) -> Result<Response<R>, Status>
+    where
+        F: FnOnce(Arc<AppState>, HeaderMap, T) -> Fut,
+        Fut: Future<Output = Result<R, EnclaveError>>,
+    {
+        let headers = request.metadata().clone().into_headers();
+        let peer = request.remote_addr();
+        let id = request_id::assign(&headers);
+        let span = tracing::info_span!("grpc", request_id = %id);
+        let state = self.state.clone();
+        let outcome = request_id::scope(id.clone(), async move {
+            if let Some(scope) = scope {
+                auth::authorize(&state.config.auth, &headers, scope)?;
+            }
+            rate_limit::check_client(&state, &headers, peer)?;
+            handler(state, headers, request.into_inner()).await
+        })
+        .instrument(span)
+        .await;
+
+        let id = MetadataValue::try_from(id.as_str()).ok();
+        match outcome {
+            Ok(response) => {
+                let mut response = Response::new(response);
+                if let Some(id) = id {
+                    response.metadata_mut().insert(REQUEST_ID_HEADER, id);
+                }
+                Ok(response)
+            }
+            Err(error) => {
+                let mut status = to_status(error);
+                if let Some(id) = id {
+                    status.metadata_mut().insert(REQUEST_ID_HEADER, id);
+                }
+                Err(status)
+            }
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Oracle for OracleService {
+    type ProcessDataStreamStream = ReceiverStream<Result<proto::ProcessDataStreamResponse, Status>>;
+
+    async fn process_data(
+        &self,
+        request: Request<proto::ProcessDataRequest>,
+    ) -> Result<Response<proto::ProcessDataResponse>, Status> {
+        self.call(request, Some(Scope::ProcessData), run_process_data)
+            .await
+    }
+
+    async fn process_data_stream(
+        &self,
+        request: Request<Streaming<proto::ProcessDataRequest>>,
+    ) -> Result<Response<Self::ProcessDataStreamStream>, Status> {
+        // The key is checked once for the stream, the rate limit per request.
+        // At most max_in_flight_per_stream requests run at once, the stream is
+        // not read further until one completes.
+        let headers: HeaderMap = request.metadata().clone().into_headers();
+        let peer = request.remote_addr();
+        auth::authorize(&self.state.config.auth, &headers, Scope::ProcessData)
+            .map_err(to_status)?;
+        let id = request_id::assign(&headers);
+        let mut requests = request.into_inner();
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        let state = self.state.clone();
+        let in_flight = Arc::new(Semaphore::new(
+            state.config.grpc.max_in_flight_per_stream.max(1),
+        ));
+        tokio::spawn(async move {
+            loop {
+                let Ok(permit) = in_flight.clone().acquire_owned().await else {
+                    break;
+                };
+                let request = match requests.message().await {
+                    Ok(Some(request)) => request,
+                    Ok(None) => break,
+                    Err(status) => {
+                        let _ = tx.send(Err(status)).await;
+                        break;
+                    }
+                };
+                let (state, headers, tx) = (state.clone(), headers.clone(), tx.clone());
+                let span = tracing::info_span!("grpc", request_id = %id);
+                let run = async move {
+                    let _permit = permit;
+                    let feed_id = request.feed_id.clone();
+                    let outcome = match rate_limit::check_client(&state, &headers, peer) {
+                        Ok(()) => run_process_data(state, headers, request).await,
+                        Err(e) => Err(e),
+                    };
+                    let outcome = match outcome {
+                        Ok(response) => Outcome::Response(response),
+                        Err(e) => Outcome::Error(proto::Error {
+                            code: e.code().to_string(),
+                            message: e.to_string(),
+                        }),
+                    };
+                    // The client may have gone away, the run is complete anyway
+                    let _ = tx
+                        .send(Ok(proto::ProcessDataStreamResponse {
+                            feed_id,
+                            outcome: Some(outcome),
+                        }))
+                        .await;
+                };
+                tokio::spawn(request_id::scope(id.clone(), run).instrument(span));
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn execute_code(
+        &self,
+        request: Request<proto::ExecuteCodeRequest>,
+    ) -> Result<Response<proto::ExecuteCodeResponse>, Status> {
+        self.call(
+            request,
+            Some(Scope::ExecuteCode),
+            |state, headers, request: proto::ExecuteCodeRequest| async move {
+                let request = ExecuteCodeRequest {
+                    code: request.code,
+                    return_type: return_type(request.return_type)?,
+                    strict_types: request.strict_types,
+                };
+                let Json(response) =
+                    super::execute_code(State(state), headers, AppJson(request)).await?;
+                execute_code_response(response)
+            },
+        )
+        .await
+    }
+
+    async fn get_attestation(
+        &self,
+        request: Request<proto::GetAttestationRequest>,
+    ) -> Result<Response<proto::GetAttestationResponse>, Status> {
+        self.call(
+            request,
+            None,
+            |state, _, request: proto::GetAttestationRequest| async move {
+                let query = AttestationQuery {
+                    fresh: request.fresh,
+                    key: request.key,
+                };
+                let response = current_attestation(state, query).await?;
+                attestation_response(response)
+            },
+        )
+        .await
+    }
+}
+
+/// Serve the gRPC service on `bind_address` in plaintext until it fails.
+pub async fn serve_grpc(state: Arc<AppState>, bind_address: String) -> Result<(), EnclaveError> {
+    serve(state, bind_address, Server::builder()).await
+}
+
+/// Serve the gRPC service on `bind_address` over TLS with the attested
+/// certificate of the HTTP server until it fails.
+#[cfg(feature = "ra-tls")]
+pub async fn serve_grpc_ra_tls(
+    state: Arc<AppState>,
+    bind_address: String,
+    identity: &crate::ratls::AttestedIdentity,
+) -> Result<(), EnclaveError> {
+    let tls_config = tonic::transport::ServerTlsConfig::new().identity(identity.tonic_identity());
+    let builder = Server::builder().tls_config(tls_config).map_err(|e| {
+        EnclaveError::GenericError(format!("Invalid gRPC TLS configuration: {}", e))
+    })?;
+    serve(state, bind_address, builder).await
+}
+
+/// Messages are limited to `limits.max_body_bytes`, like REST bodies.
+async fn serve(
+    state: Arc<AppState>,
+    bind_address: String,
+    mut builder: Server,
+) -> Result<(), EnclaveError> {
+    let addr: SocketAddr = bind_address.parse().map_err(|e| {
+        EnclaveError::GenericError(format!("Invalid gRPC bind address {}: {}", bind_address, e))
+    })?;
+    let max_message_bytes = state.config.limits.max_body_bytes;
+    let service =
+        OracleServer::new(OracleService { state }).max_decoding_message_size(max_message_bytes);
+    info!("gRPC listening on {}", addr);
+    builder
+        .add_service(service)
+        .serve(addr)
+        .await
+        .map_err(|e| EnclaveError::GenericError(format!("gRPC server error: {}", e)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_to_status() {
+        let status = to_status(EnclaveError::FeedNotFound {
+            feed_id: "0x1".to_string(),
+        });
+        assert_eq!(status.code(), Code::NotFound);
+        assert_eq!(status.message(), "Feed 0x1 not found");
+        assert_eq!(
+            status.metadata().get(ERROR_CODE_METADATA).unwrap(),
+            "feed_not_found"
+        );
+
+        let status = to_status(EnclaveError::Overloaded {
+            retry_after_ms: 1000,
+        });
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(status.metadata().get("retry-after-ms").unwrap(), "1000");
+
+        assert_eq!(
+            to_status(EnclaveError::ScriptError {
+                line: None,
+                message: "boom".to_string(),
+            })
+            .code(),
+            Code::FailedPrecondition
+        );
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert!(matches!(
+            return_type(proto::ReturnType::Number as i32),
+            Ok(ReturnType::NUMBER)
+        ));
+        assert!(return_type(42).is_err());
+        assert_eq!(
+            result_value(ResultValue::VECTOR(vec![1, 2])).value,
+            Some(proto::result_value::Value::VectorValue(vec![1, 2]))
+        );
+
+        let signed = ProcessedDataResponse {
+            response: IntentMessage {
+                intent: crate::common::IntentScope::ProcessData,
+                app_id: super::super::APP_ID.to_string(),
+                timestamp_ms: 1744038900000,
+                data: UpdateOracleResponse {
+                    result: Some(ResultValue::NUMBER(42)),
+                },
+            },
+            signature: "00ff".to_string(),
+        };
+        let typed = signed_result(&signed, Some("01"), None, "{}".to_string()).unwrap();
+        assert_eq!(
+            typed.intent_message,
+            bcs::to_bytes(&signed.response).unwrap()
+        );
+        assert_eq!(typed.signature, vec![0x00, 0xff]);
+        assert_eq!(typed.bls_signature, Some(vec![0x01]));
+        assert_eq!(
+            typed.result.and_then(|result| result.value),
+            Some(proto::result_value::Value::NumberValue(42))
+        );
+    }
+}
@@ -0,0 +1,252 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::conditional::Validators;
+use super::config::COEUS_CONFIG;
+use super::{UpdateOracleHttpResponse, ndjson, parse_feed_id};
+use crate::EnclaveError;
+use axum::Json;
+use axum::extract::{Path, Query};
+use axum::http::{HeaderMap, HeaderValue, header};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use sui_sdk_types::Address;
+use tokio::sync::RwLock;
+use tokio_stream::wrappers::ReceiverStream;
+
+/// Results an NDJSON history response reads ahead of the client.
+const STREAM_BUFFER: usize = 16;
+
+lazy_static::lazy_static! {
+    /// Recent signed results per feed.
+    pub static ref RESULT_HISTORY: Arc<RwLock<HashMap<Address, FeedHistory>>> =
+        Arc::new(RwLock::new(HashMap::new()));
+}
+
+/// Signed results of a feed.
+#[derive(Debug, Default)]
+pub struct FeedHistory {
+    /// Results ever recorded, the version of the history.
+    pub recorded: u64,
+    /// Most recent results, oldest first.
+    pub results: VecDeque<UpdateOracleHttpResponse>,
+}
+
+impl FeedHistory {
+    /// Result number `seq`, counting from 0 for the first ever recorded, None
+    /// if it was evicted or not recorded yet.
+    fn get(&self, seq: u64) -> Option<&UpdateOracleHttpResponse> {
+        let first = self.recorded - self.results.len() as u64;
+        seq.checked_sub(first)
+            .and_then(|index| self.results.get(index as usize))
+    }
+}
+
+/// History section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryConfig {
+    /// Signed results kept per feed, 0 disables history.
+    #[serde(default = "default_capacity")]
+    pub capacity: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            capacity: default_capacity(),
+        }
+    }
+}
+
+fn default_capacity() -> usize {
+    100
+}
+
+/// Query parameters for GET /feeds/{id}/history.
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    pub limit: Option<usize>,
+}
+
+/// Append a signed result, evicting the oldest one once the feed is at capacity.
+pub async fn record_result(feed_id: Address, response: &UpdateOracleHttpResponse) {
+    let mut histories = RESULT_HISTORY.write().await;
+    let history = histories.entry(feed_id).or_default();
+    history.recorded += 1;
+    push_bounded(
+        &mut history.results,
+        response.clone(),
+        COEUS_CONFIG.history.capacity,
+    );
+}
+
+fn push_bounded<T>(buffer: &mut VecDeque<T>, item: T, capacity: usize) {
+    if capacity == 0 {
+        return;
+    }
+    while buffer.len() >= capacity {
+        buffer.pop_front();
+    }
+    buffer.push_back(item);
+}
+
+/// Stream results `seqs` of a feed, in order. Each result is copied out
+/// under the lock when it is its turn, so memory stays bounded by the stream
+/// buffer whatever the length of the history. Results never change once
+/// recorded, so the stream is the history as of the request; it ends early if
+/// results it has yet to send get evicted.
+fn stream_history(
+    feed_id: Address,
+    seqs: impl Iterator<Item = u64> + Send + 'static,
+) -> ReceiverStream<UpdateOracleHttpResponse> {
+    let (sender, receiver) = tokio::sync::mpsc::channel(STREAM_BUFFER);
+    tokio::spawn(async move {
+        for seq in seqs {
+            let result = RESULT_HISTORY
+                .read()
+                .await
+                .get(&feed_id)
+                .and_then(|history| history.get(seq))
+                .cloned();
+            let Some(result) = result else {
+                break;
+            };
+            if sender.send(result).await.is_err() {
+                // The client went away
+                break;
+            }
+        }
+    });
+    ReceiverStream::new(receiver)
+}
+
+/// Endpoint returning up to `limit` most recent signed results of a feed, newest first.
+/// Streamed one result per line with `Accept: application/x-ndjson`.
+pub async fn get_feed_history(
+    Path(feed_id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+    headers: HeaderMap,
+) -> Result<Response, EnclaveError> {
+    let feed_id = parse_feed_id(&feed_id)?;
+    let ndjson = ndjson::accepts_ndjson(&headers);
+    let histories = RESULT_HISTORY.read().await;
+    let history = histories.get(&feed_id);
+    // Both formats share the URL, each has its own tag
+    let version = format!(
+        "{}-{}",
+        history.map_or(0, |history| history.recorded),
+        if ndjson { "ndjson" } else { "json" }
+    );
+    let last_modified_ms = history
+        .and_then(|history| history.results.back())
+        .map(|newest| newest.signed.response.timestamp_ms);
+    let validators = Validators::new(version, last_modified_ms);
+    if let Some(not_modified) = validators.not_modified(&headers) {
+        return Ok(vary_on_accept(not_modified));
+    }
+    let limit = query.limit.unwrap_or(usize::MAX);
+    let response = if ndjson {
+        let (recorded, len) =
+            history.map_or((0, 0), |history| (history.recorded, history.results.len()));
+        let newest_first = (recorded - len.min(limit) as u64..recorded).rev();
+        drop(histories);
+        ndjson::stream(stream_history(feed_id, newest_first))
+    } else {
+        // Copied out so the lock is not held while the response is written
+        let results: Vec<UpdateOracleHttpResponse> = history
+            .map(|history| history.results.iter().rev().take(limit).cloned().collect())
+            .unwrap_or_default();
+        drop(histories);
+        Json(results).into_response()
+    };
+    Ok(vary_on_accept(validators.respond(response)))
+}
+
+fn vary_on_accept(mut response: Response) -> Response {
+    response
+        .headers_mut()
+        .insert(header::VARY, HeaderValue::from_static("accept"));
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{APP_ID, ResultValue, UpdateOracleResponse};
+    use super::*;
+    use crate::common::{IntentScope, to_signed_response};
+    use fastcrypto::ed25519::Ed25519KeyPair;
+    use fastcrypto::traits::KeyPair;
+    use tokio_stream::StreamExt;
+
+    #[test]
+    fn test_push_bounded() {
+        let mut buffer = VecDeque::new();
+        for i in 0..5 {
+            push_bounded(&mut buffer, i, 3);
+        }
+        assert_eq!(buffer, VecDeque::from(vec![2, 3, 4]));
+
+        let mut buffer = VecDeque::new();
+        push_bounded(&mut buffer, 1, 0);
+        assert!(buffer.is_empty());
+    }
+
+    fn signed(timestamp_ms: u64) -> UpdateOracleHttpResponse {
+        let kp = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        UpdateOracleHttpResponse {
+            signed: to_signed_response(
+                &kp,
+                UpdateOracleResponse {
+                    result: Some(ResultValue::NUMBER(42)),
+                },
+                timestamp_ms,
+                IntentScope::ProcessData,
+                APP_ID,
+            ),
+            result_meta: None,
+            bls_signature: None,
+            previous_key_signature: None,
+            audit: None,
+            feed_state: None,
+        }
+    }
+
+    fn timestamp(result: Option<&UpdateOracleHttpResponse>) -> Option<u64> {
+        result.map(|result| result.signed.response.timestamp_ms)
+    }
+
+    #[test]
+    fn test_feed_history_get() {
+        let mut history = FeedHistory::default();
+        for timestamp_ms in 0..5 {
+            history.recorded += 1;
+            push_bounded(&mut history.results, signed(timestamp_ms), 3);
+        }
+        // Results 0 and 1 were evicted
+        assert_eq!(timestamp(history.get(1)), None);
+        assert_eq!(timestamp(history.get(2)), Some(2));
+        assert_eq!(timestamp(history.get(4)), Some(4));
+        assert_eq!(timestamp(history.get(5)), None);
+    }
+
+    #[tokio::test]
+    async fn test_stream_history() {
+        let feed_id = Address::from_hex("0x1423").unwrap();
+        for timestamp_ms in 0..4 {
+            record_result(feed_id, &signed(timestamp_ms)).await;
+        }
+        let streamed: Vec<u64> = stream_history(feed_id, (1..4).rev())
+            .map(|result| result.signed.response.timestamp_ms)
+            .collect()
+            .await;
+        assert_eq!(streamed, vec![3, 2, 1]);
+        // Results not recorded end the stream
+        let streamed: Vec<u64> = stream_history(feed_id, 3..6)
+            .map(|result| result.signed.response.timestamp_ms)
+            .collect()
+            .await;
+        assert_eq!(streamed, vec![3]);
+    }
+}
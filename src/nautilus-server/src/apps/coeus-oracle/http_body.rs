@@ -0,0 +1,46 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Size-capped reading of upstream response bodies. Every fetch of upstream
+//! content (feed code, gateway headers, script and declarative sources, the
+//! gas sponsor) reads its body through these, so an oversized or endless
+//! response is aborted before it is buffered.
+
+use serde::de::DeserializeOwned;
+
+/// Read a response body chunk by chunk, aborting as soon as it grows past
+/// `max_bytes` instead of buffering the whole body first.
+pub async fn read_body_capped(
+    mut response: reqwest::Response,
+    max_bytes: u64,
+) -> Result<Vec<u8>, String> {
+    if let Some(len) = response.content_length().filter(|len| *len > max_bytes) {
+        return Err(format!("Body of {} bytes exceeds {} bytes", len, max_bytes));
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        if (body.len() + chunk.len()) as u64 > max_bytes {
+            return Err(format!("Body exceeds {} bytes", max_bytes));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Read a response body of at most `max_bytes` as UTF-8 text.
+pub async fn read_text_capped(
+    response: reqwest::Response,
+    max_bytes: u64,
+) -> Result<String, String> {
+    let body = read_body_capped(response, max_bytes).await?;
+    String::from_utf8(body).map_err(|e| format!("Body is not UTF-8: {}", e))
+}
+
+/// Read a response body of at most `max_bytes` as JSON.
+pub async fn read_json_capped<T: DeserializeOwned>(
+    response: reqwest::Response,
+    max_bytes: u64,
+) -> Result<T, String> {
+    let body = read_body_capped(response, max_bytes).await?;
+    serde_json::from_slice(&body).map_err(|e| format!("Invalid JSON body: {}", e))
+}
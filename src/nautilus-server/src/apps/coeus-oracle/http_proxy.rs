@@ -0,0 +1,392 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! HTTP calls of script host functions (http_get, fetch_json, eth_call, ...).
+//!
+//! Host functions are synchronous, so they cannot await a request. Rather than
+//! a blocking client per call, each request is sent to a small runtime of its
+//! own and made with one shared async `reqwest::Client`, whose pool keeps
+//! connections (and HTTP/2 sessions) to the hosts scripts call alive between
+//! executions. The calling script thread waits on a channel for the response.
+//! The runtime being separate, waiting never blocks the threads the request
+//! runs on, whatever thread the script runs on.
+//!
+//! Every `warm_interval_ms` the hosts of `warm_upstreams` are resolved again
+//! and each URL is requested (HEAD), so a connection to the host, TLS
+//! handshake done, is already in the pool when a time-sensitive script calls
+//! it, and a new connection uses the addresses of the latest round rather than
+//! waiting for a lookup. Addresses follow the host's DNS within one interval.
+//! The interval must stay below both `pool_idle_timeout_ms` and the keep-alive
+//! timeout of the upstream.
+//!
+//! Async callers (declarative feed sources) use the same client through
+//! `spawn_request`.
+//!
+//! Requests go through an `HttpTransport`, always the client one in the
+//! server. In tests another can be set with `set_transport`, e.g. a MockHttp
+//! (see mock_http.rs) so scripts calling upstreams run against canned
+//! responses.
+
+use super::concurrency;
+use super::config::COEUS_CONFIG;
+use super::http_body::{read_json_capped, read_text_capped};
+use crate::admin;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info, warn};
+
+lazy_static::lazy_static! {
+    static ref HTTP_RUNTIME: Runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(COEUS_CONFIG.http.worker_threads.max(1))
+        .thread_name("script-http")
+        .enable_all()
+        .build()
+        .expect("Failed to create script HTTP runtime");
+    static ref HTTP_CLIENT: reqwest::Client = build_client(&COEUS_CONFIG.http);
+    static ref WARM_RESOLVER: Arc<WarmResolver> = Arc::new(WarmResolver::default());
+}
+
+#[cfg(test)]
+lazy_static::lazy_static! {
+    static ref TRANSPORT: RwLock<Arc<dyn HttpTransport>> = RwLock::new(Arc::new(ClientTransport));
+}
+
+/// Where the requests of host functions are sent.
+pub trait HttpTransport: Send + Sync {
+    /// Body of a GET of `url`, failing on a non-success status.
+    fn get_text(&self, url: &str) -> Result<String, String>;
+
+    /// JSON response to POSTing `body` to `url`, failing on a non-success
+    /// status.
+    fn post_json(
+        &self,
+        url: &str,
+        body: &JsonValue,
+        timeout: Duration,
+    ) -> Result<JsonValue, String>;
+}
+
+/// Requests made with the shared client on the HTTP runtime.
+pub struct ClientTransport;
+
+/// HTTP section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpConfig {
+    /// Timeout of a request, including reading the body.
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// How long an unused connection is kept open.
+    #[serde(default = "default_pool_idle_timeout_ms")]
+    pub pool_idle_timeout_ms: u64,
+    #[serde(default = "default_pool_max_idle_per_host")]
+    pub pool_max_idle_per_host: usize,
+    /// Threads of the runtime requests are made on.
+    #[serde(default = "default_worker_threads")]
+    pub worker_threads: usize,
+    /// URLs kept warm, ideally a cheap endpoint (e.g. a ping route) of each
+    /// API scripts call on a hot path.
+    #[serde(default)]
+    pub warm_upstreams: Vec<String>,
+    /// How often warm_upstreams are requested, 0 turns warming off.
+    #[serde(default = "default_warm_interval_ms")]
+    pub warm_interval_ms: u64,
+    /// Largest response body read, larger ones fail the request.
+    #[serde(default = "default_max_response_bytes")]
+    pub max_response_bytes: u64,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            timeout_ms: default_timeout_ms(),
+            pool_idle_timeout_ms: default_pool_idle_timeout_ms(),
+            pool_max_idle_per_host: default_pool_max_idle_per_host(),
+            worker_threads: default_worker_threads(),
+            warm_upstreams: Vec::new(),
+            warm_interval_ms: default_warm_interval_ms(),
+            max_response_bytes: default_max_response_bytes(),
+        }
+    }
+}
+
+fn default_timeout_ms() -> u64 {
+    30_000
+}
+
+fn default_pool_idle_timeout_ms() -> u64 {
+    90_000
+}
+
+fn default_pool_max_idle_per_host() -> usize {
+    16
+}
+
+fn default_worker_threads() -> usize {
+    2
+}
+
+fn default_warm_interval_ms() -> u64 {
+    20_000
+}
+
+fn default_max_response_bytes() -> u64 {
+    4 * 1024 * 1024
+}
+
+/// Host and port of `url`.
+fn host_port(url: &str) -> Result<(String, u16), String> {
+    let url = reqwest::Url::parse(url).map_err(|e| format!("Invalid URL {}: {}", url, e))?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| format!("URL {} has no host", url))?;
+    Ok((host.to_string(), url.port_or_known_default().unwrap_or(443)))
+}
+
+/// Resolver answering for warm hosts with the addresses of the latest warming
+/// round, and looking up any other host.
+#[derive(Default)]
+struct WarmResolver {
+    addrs: RwLock<HashMap<String, Vec<SocketAddr>>>,
+}
+
+impl WarmResolver {
+    fn cached(&self, host: &str) -> Option<Vec<SocketAddr>> {
+        self.addrs
+            .read()
+            .expect("warm resolver lock poisoned")
+            .get(host)
+            .cloned()
+    }
+
+    /// Resolve `url`'s host again, keeping its previous addresses if the
+    /// lookup fails.
+    async fn refresh(&self, url: &str) -> Result<(), String> {
+        let (host, port) = host_port(url)?;
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), port))
+            .await
+            .map_err(|e| format!("Failed to resolve {}: {}", host, e))?
+            .collect();
+        if addrs.is_empty() {
+            return Err(format!("No addresses for {}", host));
+        }
+        self.addrs
+            .write()
+            .expect("warm resolver lock poisoned")
+            .insert(host, addrs);
+        Ok(())
+    }
+}
+
+impl Resolve for WarmResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let cached = self.cached(name.as_str());
+        Box::pin(async move {
+            // The connector replaces the port with the one of the URL
+            let addrs: Vec<SocketAddr> = match cached {
+                Some(addrs) => addrs,
+                None => tokio::net::lookup_host((name.as_str(), 0)).await?.collect(),
+            };
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+fn build_client(config: &HttpConfig) -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(Duration::from_millis(config.timeout_ms))
+        .pool_idle_timeout(Duration::from_millis(config.pool_idle_timeout_ms))
+        .pool_max_idle_per_host(config.pool_max_idle_per_host)
+        .tcp_keepalive(Duration::from_secs(60))
+        .dns_resolver(WARM_RESOLVER.clone())
+        .build()
+        .expect("Failed to create script HTTP client")
+}
+
+/// Start keeping connections to `http.warm_upstreams` open. Does nothing when
+/// none are configured.
+pub fn spawn_upstream_warmup() {
+    let config = &COEUS_CONFIG.http;
+    if config.warm_upstreams.is_empty() || config.warm_interval_ms == 0 {
+        return;
+    }
+    info!(
+        "Keeping {} upstreams warm every {}ms",
+        config.warm_upstreams.len(),
+        config.warm_interval_ms
+    );
+    HTTP_RUNTIME.spawn(async move {
+        let client = &*HTTP_CLIENT;
+        let mut ticker = tokio::time::interval(Duration::from_millis(config.warm_interval_ms));
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            for url in &config.warm_upstreams {
+                // Checked on every round, the allowlist can change at runtime
+                if !admin::host_allowed(url) {
+                    continue;
+                }
+                if let Err(e) = WARM_RESOLVER.refresh(url).await {
+                    warn!("Not re-resolving upstream: {}", e);
+                }
+                let request = client.head(url).send();
+                tokio::spawn(async move {
+                    // Any answer leaves the connection in the pool, whatever its status
+                    if let Err(e) = request.await {
+                        debug!("Warming {} failed: {}", url, e);
+                    }
+                });
+            }
+        }
+    });
+}
+
+/// Run `request` with the shared client on the HTTP runtime and wait for it.
+fn proxy<T, F, Fut>(request: F) -> Result<T, String>
+where
+    F: FnOnce(&'static reqwest::Client) -> Fut,
+    Fut: Future<Output = Result<T, String>> + Send + 'static,
+    T: Send + 'static,
+{
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let request = request(&HTTP_CLIENT);
+    HTTP_RUNTIME.spawn(async move {
+        let _ = sender.send(request.await);
+    });
+    concurrency::wait_for_host(&receiver, "HTTP request")?
+}
+
+/// Make a request with the shared client from async code. The request starts
+/// on the HTTP runtime at once, the returned future waits for its outcome.
+pub fn spawn_request<T, F, Fut>(request: F) -> impl Future<Output = Result<T, String>>
+where
+    F: FnOnce(&'static reqwest::Client) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<T, String>> + Send + 'static,
+    T: Send + 'static,
+{
+    let handle = HTTP_RUNTIME.spawn(async move { request(&HTTP_CLIENT).await });
+    async move {
+        handle
+            .await
+            .map_err(|e| format!("HTTP request failed: {}", e))?
+    }
+}
+
+impl HttpTransport for ClientTransport {
+    fn get_text(&self, url: &str) -> Result<String, String> {
+        let url = url.to_string();
+        proxy(|client| async move {
+            let response = client
+                .get(&url)
+                .send()
+                .await
+                .map_err(|e| format!("Request error: {}", e))?;
+            let status = response.status();
+            if !status.is_success() {
+                return Err(format!("HTTP error: status {}", status));
+            }
+            read_text_capped(response, COEUS_CONFIG.http.max_response_bytes)
+                .await
+                .map_err(|e| format!("Read error: {}", e))
+        })
+    }
+
+    fn post_json(
+        &self,
+        url: &str,
+        body: &JsonValue,
+        timeout: Duration,
+    ) -> Result<JsonValue, String> {
+        let url = url.to_string();
+        let body = body.clone();
+        proxy(move |client| async move {
+            let response = client
+                .post(&url)
+                .json(&body)
+                .timeout(timeout)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| e.to_string())?;
+            read_json_capped(response, COEUS_CONFIG.http.max_response_bytes).await
+        })
+    }
+}
+
+/// Send the requests of host functions through `transport` from now on.
+#[cfg(test)]
+pub fn set_transport(transport: Arc<dyn HttpTransport>) {
+    *TRANSPORT.write().expect("HTTP transport lock poisoned") = transport;
+}
+
+#[cfg(test)]
+fn transport() -> Arc<dyn HttpTransport> {
+    TRANSPORT
+        .read()
+        .expect("HTTP transport lock poisoned")
+        .clone()
+}
+
+#[cfg(not(test))]
+fn transport() -> ClientTransport {
+    ClientTransport
+}
+
+/// Body of a GET of `url`, failing on a non-success status.
+pub fn get_text(url: &str) -> Result<String, String> {
+    transport().get_text(url)
+}
+
+/// JSON response to POSTing `body` to `url`, failing on a non-success status.
+pub fn post_json(url: &str, body: &JsonValue, timeout: Duration) -> Result<JsonValue, String> {
+    transport().post_json(url, body, timeout)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_proxy_from_runtime() {
+        // Waiting on a runtime thread must not stall the request it waits for
+        let result = proxy(|_| async { Ok::<_, String>(42) });
+        assert_eq!(result.unwrap(), 42);
+        let result =
+            tokio::task::spawn_blocking(|| ClientTransport.get_text("http://127.0.0.1:1/"))
+                .await
+                .unwrap();
+        assert!(result.unwrap_err().starts_with("Request error"));
+    }
+
+    #[tokio::test]
+    async fn test_warm_resolver() {
+        assert_eq!(
+            host_port("http://127.0.0.1:8080/ping").unwrap(),
+            ("127.0.0.1".to_string(), 8080)
+        );
+        assert_eq!(host_port("https://127.0.0.1/").unwrap().1, 443);
+        assert!(host_port("not a url").is_err());
+
+        let resolver = WarmResolver::default();
+        assert!(resolver.cached("127.0.0.1").is_none());
+        resolver
+            .refresh("http://127.0.0.1:8080/ping")
+            .await
+            .unwrap();
+        assert_eq!(
+            resolver.cached("127.0.0.1"),
+            Some(vec!["127.0.0.1:8080".parse::<SocketAddr>().unwrap()])
+        );
+        // A failed lookup keeps the previous addresses
+        assert!(resolver.refresh("https://upstream.invalid/").await.is_err());
+        assert!(resolver.cached("upstream.invalid").is_none());
+        assert!(resolver.cached("127.0.0.1").is_some());
+    }
+}
@@ -0,0 +1,285 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Feed code stored on IPFS, referenced by a `blob_id` of the form `ipfs://<CID>`.
+//!
+//! Gateways are not trusted: the raw block is requested (`?format=raw`) and its
+//! SHA-256 is checked against the CID before the content is extracted. Only
+//! single-block content is supported, i.e. `raw` CIDs or dag-pb files smaller
+//! than the IPFS chunk size, which covers any reasonable feed script.
+
+use super::blob_cache::{BLOB_CACHE, CachedBlob};
+use super::config::COEUS_CONFIG;
+use super::http_body::read_body_capped;
+use crate::{EnclaveError, metrics};
+use fastcrypto::encoding::{Base58, Encoding};
+use fastcrypto::hash::{HashFunction, Sha256};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::warn;
+
+/// Blob id prefix selecting IPFS as the code source.
+pub const SCHEME: &str = "ipfs://";
+
+const CODEC_RAW: u64 = 0x55;
+const CODEC_DAG_PB: u64 = 0x70;
+const MULTIHASH_SHA2_256: u64 = 0x12;
+
+/// IPFS section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpfsConfig {
+    /// Gateway base URLs in priority order. Hosts must be in allowed_endpoints.yaml.
+    #[serde(default = "default_gateways")]
+    pub gateways: Vec<String>,
+    /// Per-gateway request timeout.
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl Default for IpfsConfig {
+    fn default() -> Self {
+        Self {
+            gateways: default_gateways(),
+            timeout_ms: default_timeout_ms(),
+        }
+    }
+}
+
+fn default_gateways() -> Vec<String> {
+    vec!["https://ipfs.io".to_string()]
+}
+
+fn default_timeout_ms() -> u64 {
+    10_000
+}
+
+/// The parts of a CID needed to verify a block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cid {
+    pub codec: u64,
+    pub digest: Vec<u8>,
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> Result<u64, String> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(*pos).ok_or("Truncated varint")?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err("Varint too long".to_string())
+}
+
+/// RFC 4648 base32, lowercase without padding, as used by CIDv1 multibase `b`.
+fn decode_base32(s: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(s.len() * 5 / 8);
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for c in s.bytes() {
+        let value = match c {
+            b'a'..=b'z' => c - b'a',
+            b'2'..=b'7' => c - b'2' + 26,
+            _ => return Err(format!("Invalid base32 character '{}'", c as char)),
+        };
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Ok(out)
+}
+
+fn parse_multihash(bytes: &[u8], pos: &mut usize) -> Result<Vec<u8>, String> {
+    let code = read_varint(bytes, pos)?;
+    if code != MULTIHASH_SHA2_256 {
+        return Err(format!("Unsupported multihash 0x{:x}, only sha2-256", code));
+    }
+    let len = read_varint(bytes, pos)? as usize;
+    if len != 32 || bytes.len() != *pos + len {
+        return Err("Invalid sha2-256 multihash length".to_string());
+    }
+    Ok(bytes[*pos..].to_vec())
+}
+
+/// Parse a CIDv0 (`Qm...`) or a base32 CIDv1 (`b...`).
+pub fn parse_cid(cid: &str) -> Result<Cid, String> {
+    let mut pos = 0;
+    if cid.starts_with("Qm") {
+        let bytes = Base58::decode(cid).map_err(|e| format!("Invalid CIDv0 {}: {}", cid, e))?;
+        return Ok(Cid {
+            codec: CODEC_DAG_PB,
+            digest: parse_multihash(&bytes, &mut pos)?,
+        });
+    }
+    let encoded = cid
+        .strip_prefix('b')
+        .ok_or_else(|| format!("Unsupported CID multibase in {}, expected base32", cid))?;
+    let bytes = decode_base32(encoded)?;
+    let version = read_varint(&bytes, &mut pos)?;
+    if version != 1 {
+        return Err(format!("Unsupported CID version {}", version));
+    }
+    let codec = read_varint(&bytes, &mut pos)?;
+    if codec != CODEC_RAW && codec != CODEC_DAG_PB {
+        return Err(format!("Unsupported CID codec 0x{:x}", codec));
+    }
+    Ok(Cid {
+        codec,
+        digest: parse_multihash(&bytes, &mut pos)?,
+    })
+}
+
+/// Length-delimited fields (wire type 2) of a protobuf message as (field number, bytes).
+fn protobuf_bytes_fields(message: &[u8]) -> Result<Vec<(u64, &[u8])>, String> {
+    let mut fields = Vec::new();
+    let mut pos = 0;
+    while pos < message.len() {
+        let key = read_varint(message, &mut pos)?;
+        match key & 0x7 {
+            0 => {
+                read_varint(message, &mut pos)?;
+            }
+            2 => {
+                let len = read_varint(message, &mut pos)? as usize;
+                let value = pos
+                    .checked_add(len)
+                    .and_then(|end| message.get(pos..end))
+                    .ok_or("Truncated protobuf field")?;
+                fields.push((key >> 3, value));
+                pos += len;
+            }
+            wire => return Err(format!("Unsupported protobuf wire type {}", wire)),
+        }
+    }
+    Ok(fields)
+}
+
+/// File content of a single-block dag-pb node (PBNode.Data -> UnixFS.Data).
+fn unixfs_file_content(block: &[u8]) -> Result<Vec<u8>, String> {
+    let mut data: &[u8] = &[];
+    for (field, value) in protobuf_bytes_fields(block)? {
+        match field {
+            1 => data = value,
+            2 => return Err("Multi-block IPFS files are not supported".to_string()),
+            _ => {}
+        }
+    }
+    Ok(protobuf_bytes_fields(data)?
+        .into_iter()
+        .find(|(field, _)| *field == 2)
+        .map(|(_, value)| value.to_vec())
+        .unwrap_or_default())
+}
+
+/// Verify a block against its CID and extract the content.
+pub fn verify_block(cid: &Cid, block: &[u8]) -> Result<Vec<u8>, String> {
+    if Sha256::digest(block).digest.as_slice() != cid.digest.as_slice() {
+        return Err("Block does not match its CID".to_string());
+    }
+    match cid.codec {
+        CODEC_RAW => Ok(block.to_vec()),
+        _ => unixfs_file_content(block),
+    }
+}
+
+async fn fetch_from(
+    client: &reqwest::Client,
+    gateway: &str,
+    cid_str: &str,
+    cid: &Cid,
+) -> Result<Vec<u8>, String> {
+    let url = format!(
+        "{}/ipfs/{}?format=raw",
+        gateway.trim_end_matches('/'),
+        cid_str
+    );
+    let response = client
+        .get(&url)
+        .header(reqwest::header::ACCEPT, "application/vnd.ipld.raw")
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .map_err(|e| format!("{}: {}", gateway, e))?;
+    let block = read_body_capped(response, COEUS_CONFIG.walrus.max_blob_bytes)
+        .await
+        .map_err(|e| format!("{}: {}", gateway, e))?;
+    verify_block(cid, &block).map_err(|e| format!("{}: {}", gateway, e))
+}
+
+/// Download and verify the content of a CID, trying gateways in order.
+/// Content is immutable, so a cached copy is always reused.
+pub async fn fetch(cid_str: &str) -> Result<Vec<u8>, EnclaveError> {
+    let cache_key = format!("{}{}", SCHEME, cid_str);
+    let cached = BLOB_CACHE.lock().await.get(&cache_key);
+    metrics::record_cache_lookup("blob", cached.is_some());
+    if let Some(cached) = cached {
+        return Ok(cached.data);
+    }
+
+    let cid = parse_cid(cid_str).map_err(EnclaveError::InvalidRequest)?;
+    let config = &COEUS_CONFIG.ipfs;
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_millis(config.timeout_ms))
+        .build()
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to create HTTP client: {}", e)))?;
+
+    let mut errors = Vec::new();
+    for gateway in &config.gateways {
+        match fetch_from(&client, gateway, cid_str, &cid).await {
+            Ok(data) => {
+                BLOB_CACHE.lock().await.insert(
+                    cache_key,
+                    CachedBlob {
+                        data: data.clone(),
+                        etag: None,
+                        fetched_at_ms: 0,
+                    },
+                );
+                return Ok(data);
+            }
+            Err(e) => {
+                warn!("Failed to fetch {} from IPFS: {}", cid_str, e);
+                errors.push(e);
+            }
+        }
+    }
+    Err(EnclaveError::BlobFetchFailed {
+        blob_id: cache_key,
+        message: format!("all IPFS gateways failed: {}", errors.join("; ")),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_cid() {
+        let raw = parse_cid("bafkreidti5olicswr2g2ricfz3irae36cwpyscwe3kedw2yx3rsrwouaje").unwrap();
+        assert_eq!(raw.codec, CODEC_RAW);
+        assert_eq!(raw.digest, Sha256::digest(b"42").digest.to_vec());
+
+        let v0 = parse_cid("QmV1eXCyp7yJ9JwqH6JMPsu6wWQHi1YArhCEeKrmuRLPvB").unwrap();
+        assert_eq!(v0.codec, CODEC_DAG_PB);
+
+        assert!(parse_cid("zQm").is_err());
+        assert!(parse_cid("bafkrei").is_err());
+    }
+
+    #[test]
+    fn test_verify_block() {
+        let raw = parse_cid("bafkreidti5olicswr2g2ricfz3irae36cwpyscwe3kedw2yx3rsrwouaje").unwrap();
+        assert_eq!(verify_block(&raw, b"42").unwrap(), b"42");
+        assert!(verify_block(&raw, b"43").is_err());
+
+        // dag-pb node of a UnixFS file containing "42"
+        let block = hex::decode("0a080802120234321802").unwrap();
+        let v0 = parse_cid("QmV1eXCyp7yJ9JwqH6JMPsu6wWQHi1YArhCEeKrmuRLPvB").unwrap();
+        assert_eq!(verify_block(&v0, &block).unwrap(), b"42");
+    }
+}
@@ -0,0 +1,295 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Memory accounting.
+//!
+//! An enclave has a fixed amount of memory and no swap, and running out of it
+//! gets the attested process killed. Every `check_interval_ms` the memory held
+//! by the blob cache, the result cache, the result history, the audit log and
+//! the in-flight script executions is estimated and exported as `memory_accounted_bytes`,
+//! with its high-water mark and, where /proc is readable, the resident set
+//! size for comparison. Sizes are approximate: payloads are counted, not the
+//! allocator's overhead, and an execution is counted as `execution_bytes`.
+//!
+//! Once the total passes `shed_at_bps` of `ceiling_bytes`, entries are shed
+//! until it is back under that mark: least recently used blobs first, then the
+//! oldest signed results of the result cache. Both are refilled on demand. The
+//! result history and the audit log are counted but never shed, they are
+//! bounded by their capacity.
+//! The compiled script caches live on the script workers and are bounded by
+//! `blob_cache.ast_capacity`, they are not counted.
+
+use super::audit;
+use super::blob_cache::{BLOB_CACHE, CachedBlob};
+use super::cache::RESULT_CACHE;
+use super::concurrency::executions_in_flight;
+use super::config::COEUS_CONFIG;
+use super::history::RESULT_HISTORY;
+use super::{ResultValue, UpdateOracleHttpResponse};
+use crate::metrics;
+use serde::{Deserialize, Serialize};
+use std::mem::size_of;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+/// Memory section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryConfig {
+    /// Accounted memory the caches are shed to stay under, 0 only accounts.
+    /// Leave room below the enclave's memory for what is not accounted.
+    #[serde(default = "default_ceiling_bytes")]
+    pub ceiling_bytes: u64,
+    /// Share of the ceiling, in basis points, at which shedding starts.
+    #[serde(default = "default_shed_at_bps")]
+    pub shed_at_bps: u64,
+    /// Memory counted for each script execution in flight.
+    #[serde(default = "default_execution_bytes")]
+    pub execution_bytes: u64,
+    #[serde(default = "default_check_interval_ms")]
+    pub check_interval_ms: u64,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self {
+            ceiling_bytes: default_ceiling_bytes(),
+            shed_at_bps: default_shed_at_bps(),
+            execution_bytes: default_execution_bytes(),
+            check_interval_ms: default_check_interval_ms(),
+        }
+    }
+}
+
+fn default_ceiling_bytes() -> u64 {
+    256 * 1024 * 1024
+}
+
+fn default_shed_at_bps() -> u64 {
+    9_000
+}
+
+fn default_execution_bytes() -> u64 {
+    8 * 1024 * 1024
+}
+
+fn default_check_interval_ms() -> u64 {
+    1_000
+}
+
+/// Approximate memory held, per component.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MemoryUsage {
+    pub blob_cache: u64,
+    pub result_cache: u64,
+    pub result_history: u64,
+    pub audit_log: u64,
+    pub executions: u64,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> u64 {
+        self.blob_cache + self.result_cache + self.result_history + self.audit_log + self.executions
+    }
+}
+
+/// Approximate bytes held by a cached blob.
+pub fn blob_bytes(blob: &CachedBlob) -> u64 {
+    (size_of::<CachedBlob>() + blob.data.len() + blob.etag.as_ref().map_or(0, String::len)) as u64
+}
+
+/// Approximate bytes held by a signed response: its result and signatures.
+pub fn response_bytes(response: &UpdateOracleHttpResponse) -> u64 {
+    let result = match &response.signed.response.data.result {
+        Some(ResultValue::STRING(value)) => value.len(),
+        Some(ResultValue::VECTOR(value)) => value.len(),
+        _ => 0,
+    };
+    let signatures: usize = [
+        Some(&response.signed.signature),
+        response.bls_signature.as_ref(),
+        response.previous_key_signature.as_ref(),
+        response
+            .result_meta
+            .as_ref()
+            .and_then(|section| section.signature.as_ref()),
+    ]
+    .into_iter()
+    .flatten()
+    .map(String::len)
+    .sum();
+    (size_of::<UpdateOracleHttpResponse>() + result + signatures) as u64
+}
+
+/// Estimate the memory currently held.
+pub async fn measure() -> MemoryUsage {
+    MemoryUsage {
+        blob_cache: BLOB_CACHE.lock().await.values().map(blob_bytes).sum(),
+        result_cache: RESULT_CACHE.read().await.values().map(response_bytes).sum(),
+        result_history: RESULT_HISTORY
+            .read()
+            .await
+            .values()
+            .flat_map(|history| history.results.iter())
+            .map(response_bytes)
+            .sum(),
+        audit_log: audit::retained_bytes().await,
+        executions: executions_in_flight() as u64 * COEUS_CONFIG.memory.execution_bytes,
+    }
+}
+
+/// Accounted memory at which shedding starts, None without a ceiling.
+pub fn shed_mark(config: &MemoryConfig) -> Option<u64> {
+    (config.ceiling_bytes > 0)
+        .then(|| (config.ceiling_bytes as u128 * config.shed_at_bps as u128 / 10_000) as u64)
+}
+
+/// Shed cache entries until at least `excess` bytes are freed or the caches
+/// are empty. Returns the bytes freed.
+pub async fn shed(excess: u64) -> u64 {
+    let mut freed = 0;
+    {
+        let mut blobs = BLOB_CACHE.lock().await;
+        while freed < excess {
+            let Some((_, blob)) = blobs.pop_lru() else {
+                break;
+            };
+            freed += blob_bytes(&blob);
+            metrics::CACHE_ENTRIES_SHED
+                .with_label_values(&["blob"])
+                .inc();
+        }
+    }
+    if freed < excess {
+        let mut results = RESULT_CACHE.write().await;
+        let mut oldest_first: Vec<_> = results
+            .iter()
+            .map(|(key, response)| (response.signed.response.timestamp_ms, *key))
+            .collect();
+        oldest_first.sort_unstable_by_key(|(timestamp_ms, _)| *timestamp_ms);
+        for (_, key) in oldest_first {
+            if freed >= excess {
+                break;
+            }
+            if let Some(response) = results.remove(&key) {
+                freed += response_bytes(&response);
+                metrics::CACHE_ENTRIES_SHED
+                    .with_label_values(&["result"])
+                    .inc();
+            }
+        }
+    }
+    freed
+}
+
+/// Resident and peak resident set size from /proc/self/status, in bytes.
+fn resident_bytes() -> Option<(u64, u64)> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let field = |name: &str| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|value| value.trim().strip_suffix("kB"))
+            .and_then(|kb| kb.trim().parse::<u64>().ok())
+            .map(|kb| kb * 1024)
+    };
+    Some((field("VmRSS:")?, field("VmHWM:")?))
+}
+
+/// Measure, export and shed once.
+async fn check(config: &MemoryConfig) {
+    let usage = measure().await;
+    for (component, bytes) in [
+        ("blob_cache", usage.blob_cache),
+        ("result_cache", usage.result_cache),
+        ("result_history", usage.result_history),
+        ("audit_log", usage.audit_log),
+        ("executions", usage.executions),
+    ] {
+        metrics::MEMORY_ACCOUNTED
+            .with_label_values(&[component])
+            .set(bytes as i64);
+    }
+    let total = usage.total() as i64;
+    if total > metrics::MEMORY_ACCOUNTED_HIGH_WATER.get() {
+        metrics::MEMORY_ACCOUNTED_HIGH_WATER.set(total);
+    }
+    if let Some((resident, peak)) = resident_bytes() {
+        metrics::MEMORY_RESIDENT.set(resident as i64);
+        metrics::MEMORY_RESIDENT_HIGH_WATER.set(peak as i64);
+    }
+
+    let Some(mark) = shed_mark(config) else {
+        return;
+    };
+    if usage.total() <= mark {
+        return;
+    }
+    let excess = usage.total() - mark;
+    let freed = shed(excess).await;
+    if freed < excess {
+        warn!(
+            "Accounted memory {} bytes is over the shedding mark {} with the caches empty",
+            usage.total(),
+            mark
+        );
+    } else {
+        info!(
+            "Shed {} bytes of cache entries, accounted memory was {} bytes",
+            freed,
+            usage.total()
+        );
+    }
+}
+
+/// Start the background memory accounting.
+pub fn spawn_memory_monitor() {
+    let config = &COEUS_CONFIG.memory;
+    if config.check_interval_ms == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_millis(config.check_interval_ms));
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            check(config).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sizes() {
+        let blob = CachedBlob {
+            data: vec![0; 1000],
+            etag: Some("abcd".to_string()),
+            fetched_at_ms: 0,
+        };
+        assert_eq!(blob_bytes(&blob), (size_of::<CachedBlob>() + 1004) as u64);
+
+        let config = MemoryConfig {
+            ceiling_bytes: 1000,
+            shed_at_bps: 9_000,
+            ..MemoryConfig::default()
+        };
+        assert_eq!(shed_mark(&config), Some(900));
+        let unbounded = MemoryConfig {
+            ceiling_bytes: 0,
+            ..MemoryConfig::default()
+        };
+        assert_eq!(shed_mark(&unbounded), None);
+
+        let usage = MemoryUsage {
+            blob_cache: 1,
+            result_cache: 2,
+            result_history: 3,
+            audit_log: 4,
+            executions: 5,
+        };
+        assert_eq!(usage.total(), 15);
+    }
+}
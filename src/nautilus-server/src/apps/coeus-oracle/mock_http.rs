@@ -0,0 +1,167 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! In-memory HttpTransport, so tests of scripts calling upstreams (http_get,
+//! fetch_json, eth_call, ...) run without a network. Only built for tests,
+//! the server always uses the client. Responses are registered per URL; a URL
+//! without one is sent to the fallback transport, or fails as an unreachable
+//! host would without one.
+//!
+//! Host functions use a single transport per process. Tests running in
+//! parallel share `MockHttp::shared()`, which falls back to the client, so
+//! each test registers URLs of its own.
+
+use super::http_proxy::{ClientTransport, HttpTransport, set_transport};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+lazy_static::lazy_static! {
+    static ref SHARED: Arc<MockHttp> = {
+        let mock = Arc::new(MockHttp::with_fallback(Arc::new(ClientTransport)));
+        set_transport(mock.clone());
+        mock
+    };
+}
+
+#[derive(Default)]
+struct Canned {
+    /// Body, or error, returned for each URL.
+    responses: HashMap<String, Result<String, String>>,
+    /// Requests made to each URL, answered or not.
+    requests: HashMap<String, usize>,
+}
+
+#[derive(Default)]
+pub struct MockHttp {
+    canned: Mutex<Canned>,
+    fallback: Option<Arc<dyn HttpTransport>>,
+}
+
+impl MockHttp {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mock sending URLs without a response to `fallback`.
+    pub fn with_fallback(fallback: Arc<dyn HttpTransport>) -> Self {
+        Self {
+            canned: Mutex::default(),
+            fallback: Some(fallback),
+        }
+    }
+
+    /// The mock of the process, set as the transport of host functions on
+    /// first use.
+    pub fn shared() -> Arc<Self> {
+        SHARED.clone()
+    }
+
+    fn canned(&self) -> MutexGuard<'_, Canned> {
+        self.canned.lock().expect("mock HTTP lock poisoned")
+    }
+
+    /// Answer requests to `url` with `body`.
+    pub fn respond(&self, url: &str, body: impl Into<String>) {
+        self.canned()
+            .responses
+            .insert(url.to_string(), Ok(body.into()));
+    }
+
+    /// Answer requests to `url` with `value` as JSON.
+    pub fn respond_json(&self, url: &str, value: &JsonValue) {
+        self.respond(url, value.to_string());
+    }
+
+    /// Fail requests to `url` with `error`, as the client would on a
+    /// network error or an error status.
+    pub fn fail(&self, url: &str, error: impl Into<String>) {
+        self.canned()
+            .responses
+            .insert(url.to_string(), Err(error.into()));
+    }
+
+    /// Requests made to `url` so far.
+    pub fn request_count(&self, url: &str) -> usize {
+        self.canned().requests.get(url).copied().unwrap_or_default()
+    }
+
+    /// Canned response of `url`, None if it has none.
+    fn answer(&self, url: &str) -> Option<Result<String, String>> {
+        let mut canned = self.canned();
+        *canned.requests.entry(url.to_string()).or_default() += 1;
+        canned.responses.get(url).cloned()
+    }
+
+    fn fallback(&self, url: &str) -> Result<&dyn HttpTransport, String> {
+        self.fallback
+            .as_deref()
+            .ok_or_else(|| format!("Request error: no mock response for {}", url))
+    }
+}
+
+impl HttpTransport for MockHttp {
+    fn get_text(&self, url: &str) -> Result<String, String> {
+        match self.answer(url) {
+            Some(answer) => answer,
+            None => self.fallback(url)?.get_text(url),
+        }
+    }
+
+    fn post_json(
+        &self,
+        url: &str,
+        body: &JsonValue,
+        timeout: Duration,
+    ) -> Result<JsonValue, String> {
+        match self.answer(url) {
+            Some(answer) => {
+                answer.and_then(|text| serde_json::from_str(&text).map_err(|e| e.to_string()))
+            }
+            None => self.fallback(url)?.post_json(url, body, timeout),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_mock_http() {
+        let http = MockHttp::new();
+        let price = "https://api.example.com/price";
+        http.respond_json(price, &json!({ "price": 42 }));
+        assert_eq!(http.get_text(price).unwrap(), r#"{"price":42}"#);
+        let timeout = Duration::from_secs(1);
+        assert_eq!(
+            http.post_json(price, &json!({}), timeout).unwrap(),
+            json!({ "price": 42 })
+        );
+        assert_eq!(http.request_count(price), 2);
+
+        let down = "https://api.example.com/down";
+        http.fail(down, "HTTP error: status 503");
+        assert_eq!(http.get_text(down).unwrap_err(), "HTTP error: status 503");
+        let missing = "https://api.example.com/missing";
+        assert!(
+            http.get_text(missing)
+                .unwrap_err()
+                .starts_with("Request error")
+        );
+        assert_eq!(http.request_count(missing), 1);
+
+        // Not JSON where JSON is expected
+        let html = "https://api.example.com/html";
+        http.respond(html, "<html></html>");
+        assert!(http.post_json(html, &json!({}), timeout).is_err());
+
+        // URLs without a response go to the fallback
+        let layered = MockHttp::with_fallback(Arc::new(http));
+        assert_eq!(layered.get_text(price).unwrap(), r#"{"price":42}"#);
+        layered.respond(price, "7");
+        assert_eq!(layered.get_text(price).unwrap(), "7");
+    }
+}
@@ -1,55 +1,287 @@
 // Copyright (c), Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+pub mod arweave;
+pub mod attestation;
+pub mod audit;
+pub mod batch;
+pub mod blob_cache;
+pub mod bls;
+pub mod builtin;
+pub mod bundle;
+pub mod cache;
+pub mod call_log;
+pub mod certification;
+pub mod compression;
+pub mod concurrency;
+pub mod conditional;
+pub mod config;
+pub mod declarative;
+pub mod dry_run;
+pub mod enclave_info;
+pub mod encryption;
+pub mod events;
+pub mod evm;
+pub mod executor;
+pub mod feed_allowlist;
+pub mod feed_keys;
+pub mod feed_layout;
+pub mod feed_object;
+pub mod feed_state;
+pub mod gas_station;
+pub mod grpc;
+pub mod history;
+pub mod http_body;
+pub mod http_proxy;
+pub mod ipfs;
+pub mod memory;
+#[cfg(test)]
+pub mod mock_http;
+pub mod move_value;
+pub mod ndjson;
+pub mod onchain;
+pub mod openapi;
+pub mod payload_codec;
+pub mod policy;
+pub mod publisher;
+pub mod ready;
+pub mod registration;
+pub mod registry;
+pub mod replay;
+pub mod rotation;
+pub mod scheduler;
+pub mod script_engine;
+pub mod sign_digest;
+pub mod starlark_exec;
+pub mod status;
+pub mod submit;
+pub mod subscribe;
+pub mod threshold;
+pub mod verify;
+pub mod version;
+pub mod walrus;
+pub mod webhook;
+
+pub use attestation::get_attestation;
+pub use audit::{get_audit_head, get_audit_log};
+pub use batch::process_batch;
+pub use bls::{aggregate_bls_signatures, get_bls_attestation};
+pub use dry_run::init_script_runtime;
+pub use enclave_info::get_enclave_info;
+pub use events::spawn_event_watch;
+pub use feed_keys::{generate_seed, get_feed_keys, init_feed_keys, request_seed_keys, unseal_seed};
+pub use gas_station::spawn_gas_station;
+pub use grpc::serve_grpc;
+#[cfg(feature = "ra-tls")]
+pub use grpc::serve_grpc_ra_tls;
+pub use history::get_feed_history;
+pub use http_proxy::spawn_upstream_warmup;
+pub use memory::spawn_memory_monitor;
+pub use openapi::{get_docs, get_openapi};
+pub use payload_codec::{Payload, encode_payload};
+pub use ready::get_ready;
+pub use registration::spawn_registration_check;
+pub use replay::get_replay_policy;
+pub use rotation::{get_keys, health_check, spawn_key_rotation};
+pub use scheduler::{get_scheduled_result, spawn_scheduler};
+pub use sign_digest::sign_digest;
+pub use status::{get_feed_status, list_feeds};
+pub use subscribe::subscribe_feed;
+pub use threshold::aggregate_threshold;
+pub use version::get_version;
+
 use crate::AppState;
 use crate::EnclaveError;
+use crate::auth::{self, Scope, require_scope};
 use crate::common::IntentMessage;
 use crate::common::{IntentScope, ProcessedDataResponse, to_signed_response};
-use axum::Json;
-use axum::extract::State;
+use crate::extract::AppJson;
+use crate::{admin, metrics, rate_limit, request_id};
+use audit::AuditHeadSection;
+use axum::extract::{DefaultBodyLimit, State};
+use axum::http::HeaderMap;
+use axum::middleware::from_fn_with_state;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use config::COEUS_CONFIG;
+use encryption::ResultOutput;
 use fastcrypto::encoding::{Encoding, Hex};
-use reqwest::Url;
-use rhai::packages::Package;
-use rhai::{Dynamic, Engine, EvalAltResult, Scope};
+use fastcrypto::hash::{HashFunction, Sha256};
+use fastcrypto::traits::{KeyPair, ToFromBytes};
+use feed_state::{FeedPin, FeedState, FeedStateSection};
+use rhai::{Dynamic, Engine, EvalAltResult, FuncRegistration, Module, Scope};
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
-use std::sync::Arc;
-use sui_rpc::field::{FieldMask, FieldMaskUtil};
-use sui_rpc::proto::sui::rpc::v2::GetObjectRequest;
+use status::FeedRunStats;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use sui_sdk_types::Address;
+use utoipa::ToSchema;
+
+/// App identifier signed into every intent message, must match APP_ID in
+/// move/oracle/sources/feed.move.
+pub const APP_ID: &str = "coeus-oracle";
 
 /// ====
 /// Core Nautilus server logic, replace it with your own
 /// relavant structs and process_data endpoint.
 /// ====
 /// Inner type T for IntentMessage<T>
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
 pub struct UpdateOracleResponse {
     pub result: Option<ResultValue>,
 }
 
 /// Inner type T for ProcessDataRequest<T>
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct UpdateOracleRequest {
+    #[serde(default)]
     feed_id: String,
+    /// Registry the feed is a dynamic field of, instead of feed_id (see
+    /// registry.rs).
+    #[serde(default)]
+    registry_id: Option<String>,
+    /// Key of the feed in the registry.
+    #[serde(default)]
+    key: Option<String>,
+    /// Also sign the `result_meta` section (under IntentScope::ResultMeta).
+    #[serde(default)]
+    sign_meta: bool,
+    /// Timestamp shared by the replicas of a threshold round, signed instead
+    /// of the enclave clock (see threshold.rs).
+    #[serde(default)]
+    round_timestamp_ms: Option<u64>,
+    /// Hex X25519 key to encrypt the signed response to (see encryption.rs).
+    #[serde(default)]
+    recipient_public_key: Option<String>,
+    /// Read this version of the feed object instead of the latest one, the
+    /// result is then signed under IntentScope::PinnedResult (see
+    /// feed_state.rs).
+    #[serde(default)]
+    version: Option<u64>,
+    /// Read the feed object as of this checkpoint instead of the latest one.
+    #[serde(default)]
+    checkpoint: Option<u64>,
+}
+
+/// Optional metadata a script can attach to its result so consumers can
+/// weigh the value instead of treating it as absolute.
+/// All fields are integers so the struct stays BCS-serializable for signing,
+/// spread and the confidence interval as fixed point with `decimals` places.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct ResultMeta {
+    /// Number of upstream sources the result was aggregated from.
+    pub source_count: Option<u64>,
+    /// Decimal places of spread, confidence_low and confidence_high: each
+    /// holds its value times 10^decimals, rounded.
+    #[serde(default)]
+    pub decimals: u8,
+    /// Spread between the highest and lowest upstream value.
+    pub spread: Option<u64>,
+    /// Lower bound of the confidence interval.
+    pub confidence_low: Option<u64>,
+    /// Upper bound of the confidence interval.
+    pub confidence_high: Option<u64>,
+    /// Timestamp (ms) of the freshest upstream data point.
+    pub upstream_timestamp_ms: Option<u64>,
+    /// Age of the upstream data at execution time, derived from upstream_timestamp_ms.
+    pub staleness_ms: Option<u64>,
+}
+
+impl ResultMeta {
+    /// True if the script did not set any metadata.
+    pub fn is_empty(&self) -> bool {
+        self == &ResultMeta::default()
+    }
+
+    /// Fill in staleness_ms relative to the execution timestamp.
+    pub fn with_staleness(mut self, timestamp_ms: u64) -> Self {
+        if let Some(upstream) = self.upstream_timestamp_ms {
+            self.staleness_ms = Some(timestamp_ms.saturating_sub(upstream));
+        }
+        self
+    }
+}
+
+/// What the result_meta signature covers: the metadata bound to its feed and
+/// to the signed result, so it cannot be replayed onto another of either.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ResultMetaPayload {
+    pub feed_id: Address,
+    /// SHA-256 of the BCS of the signed result's IntentMessage.
+    pub result_digest: [u8; 32],
+    pub meta: ResultMeta,
+}
+
+/// Metadata section returned next to the signed result.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct ResultMetaSection {
+    pub meta: ResultMeta,
+    /// Hex signature over IntentMessage<ResultMetaPayload> with the same
+    /// timestamp as the result, present only when the request set `sign_meta`.
+    pub signature: Option<String>,
+}
+
+/// Response for process_data: the signed result plus optional metadata.
+/// The signed part is unchanged so it still verifies on-chain.
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub struct UpdateOracleHttpResponse {
+    #[serde(flatten)]
+    #[schema(value_type = openapi::SignedUpdateOracleResponse)]
+    pub signed: ProcessedDataResponse<IntentMessage<UpdateOracleResponse>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result_meta: Option<ResultMetaSection>,
+    /// Hex BLS signature over the same intent message, present when enabled
+    /// in coeus_config.yaml. Aggregatable across enclave instances.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bls_signature: Option<String>,
+    /// Hex signature of the same intent message by the previous key, present
+    /// during the grace window after a key rotation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_key_signature: Option<String>,
+    /// Signed head of the audit log after this result, present when enabled
+    /// in coeus_config.yaml.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit: Option<AuditHeadSection>,
+    /// Version of the feed object the result was computed from, signed with
+    /// the result's timestamp.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub feed_state: Option<FeedStateSection>,
+}
+
+/// Output of a script execution: converted result and attached metadata.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RhaiOutput {
+    pub result: Option<ResultValue>,
+    pub meta: ResultMeta,
 }
 
 /// Request for execute_code endpoint
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ExecuteCodeRequest {
     pub code: String,
     pub return_type: ReturnType,
+    /// Reject implicit coercions when converting the result (see OracleFeed::strict_types)
+    #[serde(default)]
+    pub strict_types: bool,
 }
 
 /// Response for execute_code endpoint
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ExecuteCodeResponse {
     pub result: ResultValue,
     pub success: bool,
     pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result_meta: Option<ResultMeta>,
+    /// Result signed under IntentScope::ExecuteCode, which feeds never accept,
+    /// so test runs can be attributed to the enclave but not submitted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<openapi::SignedUpdateOracleResponse>)]
+    pub signed: Option<ProcessedDataResponse<IntentMessage<UpdateOracleResponse>>>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, ToSchema)]
 pub enum ResultValue {
     STRING(String),
     BOOLEAN(bool),
@@ -57,19 +289,28 @@ pub enum ResultValue {
     VECTOR(Vec<u8>),
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
-pub struct Payload {
-    pub intent_scope: u8,
-    pub timestamp_ms: u64,
-    pub result: ResultValue,
-}
-
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[allow(non_camel_case_types)]
 pub enum CodeExtension {
     RHAI,
+    /// Declarative HTTP + JSONPath spec, see declarative.rs
+    JSON_CONFIG,
+    /// Non-Turing-complete Starlark script, see starlark_exec.rs
+    STARLARK,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+impl CodeExtension {
+    /// Metric label, one per variant so label values stay bounded.
+    pub fn label(&self) -> &'static str {
+        match self {
+            CodeExtension::RHAI => "rhai",
+            CodeExtension::JSON_CONFIG => "json_config",
+            CodeExtension::STARLARK => "starlark",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, ToSchema)]
 pub enum ReturnType {
     STRING,
     BOOLEAN,
@@ -85,25 +326,47 @@ pub struct OracleFeed {
     pub result: Option<ResultValue>,
     pub return_type: ReturnType,
     pub allow_update_timestamp_ms: u64,
+    // The fields below are the feed's settings dynamic field rather than
+    // part of the object, and keep their default for feeds without one (see
+    // feed_layout.rs)
+    /// Reject implicit coercions (e.g. "123" -> NUMBER) when converting the result
+    #[serde(skip)]
+    pub strict_types: bool,
+    /// SHA-256 of the script blob, verified before execution
+    #[serde(skip)]
+    pub blob_hash: Vec<u8>,
+    /// Ed25519 keys allowed to sign the script, see publisher.rs
+    #[serde(skip)]
+    pub publisher_keys: Vec<Vec<u8>>,
+    /// walrus::blob::Blob object of blob_id, checked for certification and expiry
+    #[serde(skip)]
+    pub blob_object_id: Option<Address>,
+    /// Sender that created the feed, allowed to own its Blob object
+    #[serde(skip)]
+    pub creator: Option<Address>,
+}
+
+impl OracleFeed {
+    /// Whether the feed has a settings field, creator being set by every
+    /// settings version.
+    pub fn has_settings(&self) -> bool {
+        self.creator.is_some()
+    }
 }
 
 // Host function: HTTP GET request (returns Result for backward compatibility)
 fn http_get_string(url: &str) -> Result<String, String> {
-    match reqwest::blocking::get(url) {
-        Ok(resp) => {
-            // Check HTTP status code
-            let status = resp.status();
-            if !status.is_success() {
-                return Err(format!("HTTP error: status {}", status));
-            }
+    let result = http_get_string_inner(url);
+    let outcome = if result.is_ok() { "ok" } else { "error" };
+    metrics::HOST_HTTP_CALLS.with_label_values(&[outcome]).inc();
+    result
+}
 
-            match resp.text() {
-                Ok(text) => Ok(text),
-                Err(e) => Err(format!("Read error: {}", e)),
-            }
-        }
-        Err(e) => Err(format!("Request error: {}", e)),
+fn http_get_string_inner(url: &str) -> Result<String, String> {
+    if !admin::host_allowed(url) {
+        return Err(format!("Host of {} is not in the allowlist", url));
     }
+    http_proxy::get_text(url)
 }
 
 // HTTP GET that validates JSON response
@@ -292,120 +555,261 @@ fn fetch_json(url: &str) -> Dynamic {
     }
 }
 
-/// Setup Rhai engine with all required functions and packages
-fn setup_rhai_engine() -> Engine {
-    let mut engine = Engine::new();
+/// Most decimal places of spread and the confidence interval.
+const MAX_META_DECIMALS: i64 = 18;
+
+/// A number passed to a metadata setter.
+#[derive(Clone, Copy)]
+enum MetaNumber {
+    Int(i64),
+    Float(f64),
+}
 
-    // Load the Rhai Standard Package (provides basic string, array, map functions)
-    engine.register_global_module(rhai::packages::StandardPackage::new().as_shared_module());
+impl MetaNumber {
+    /// The value times 10^decimals, rounded, rejecting negatives and values
+    /// that do not fit a u64.
+    fn scaled(self, name: &str, decimals: u8) -> Result<u64, Box<EvalAltResult>> {
+        let factor = 10u64.pow(decimals as u32);
+        let scaled = match self {
+            MetaNumber::Int(v) => u64::try_from(v).ok().and_then(|v| v.checked_mul(factor)),
+            MetaNumber::Float(v) => {
+                let scaled = (v * factor as f64).round();
+                (v >= 0.0 && scaled.is_finite() && scaled < u64::MAX as f64)
+                    .then_some(scaled as u64)
+            }
+        };
+        scaled.ok_or_else(|| {
+            let value = match self {
+                MetaNumber::Int(v) => v.to_string(),
+                MetaNumber::Float(v) => v.to_string(),
+            };
+            format!(
+                "{} must be a non-negative number that fits a u64 with {} decimals, got {}",
+                name, decimals, value
+            )
+            .into()
+        })
+    }
+}
 
-    // Load Basic String Package (provides additional string functions)
-    engine.register_global_module(rhai::packages::BasicStringPackage::new().as_shared_module());
+/// Register a metadata setter under `name` for both integer and float arguments.
+fn register_meta_setter(
+    engine: &mut Engine,
+    name: &'static str,
+    meta: &Arc<Mutex<ResultMeta>>,
+    apply: fn(&mut ResultMeta, &str, MetaNumber) -> Result<(), Box<EvalAltResult>>,
+) {
+    let m = meta.clone();
+    engine.register_fn(name, move |v: i64| {
+        apply(&mut m.lock().unwrap(), name, MetaNumber::Int(v))
+    });
+    let m = meta.clone();
+    engine.register_fn(name, move |v: f64| {
+        apply(&mut m.lock().unwrap(), name, MetaNumber::Float(v))
+    });
+}
 
-    // Register join() manually for arrays (not included in standard packages)
-    engine.register_fn("join", |arr: rhai::Array, sep: &str| -> String {
-        arr.into_iter()
-            .map(|x| x.to_string())
-            .collect::<Vec<_>>()
-            .join(sep)
+/// Register the result metadata setters. Values are collected into `meta`
+/// and returned alongside the script result.
+fn register_result_meta_fns(engine: &mut Engine, meta: Arc<Mutex<ResultMeta>>) {
+    register_meta_setter(engine, "set_source_count", &meta, |m, name, v| {
+        m.source_count = Some(v.scaled(name, 0)?);
+        Ok(())
+    });
+    register_meta_setter(engine, "set_spread", &meta, |m, name, v| {
+        m.spread = Some(v.scaled(name, m.decimals)?);
+        Ok(())
+    });
+    register_meta_setter(engine, "set_upstream_timestamp", &meta, |m, name, v| {
+        m.upstream_timestamp_ms = Some(v.scaled(name, 0)?);
+        Ok(())
     });
 
-    // Register contains_key manually for Map (not included in any standard package)
-    engine.register_fn("contains_key", |map: &mut rhai::Map, key: &str| -> bool {
-        map.contains_key(key)
+    // Fixed point values already set would change meaning, so the decimals
+    // come first
+    let m = meta.clone();
+    engine.register_fn(
+        "set_meta_decimals",
+        move |decimals: i64| -> Result<(), Box<EvalAltResult>> {
+            if !(0..=MAX_META_DECIMALS).contains(&decimals) {
+                return Err(format!(
+                    "meta decimals must be 0 to {}, got {}",
+                    MAX_META_DECIMALS, decimals
+                )
+                .into());
+            }
+            let mut guard = m.lock().unwrap();
+            if guard.spread.is_some() || guard.confidence_low.is_some() {
+                return Err(
+                    "set_meta_decimals must be called before set_spread and set_confidence_interval"
+                        .into(),
+                );
+            }
+            guard.decimals = decimals as u8;
+            Ok(())
+        },
+    );
+
+    // set_confidence_interval(low, high) takes two numbers of the same kind
+    fn set_interval(
+        meta: &Mutex<ResultMeta>,
+        low: MetaNumber,
+        high: MetaNumber,
+    ) -> Result<(), Box<EvalAltResult>> {
+        let mut guard = meta.lock().unwrap();
+        let low = low.scaled("confidence_low", guard.decimals)?;
+        let high = high.scaled("confidence_high", guard.decimals)?;
+        if low > high {
+            return Err(format!("confidence interval low {} > high {}", low, high).into());
+        }
+        guard.confidence_low = Some(low);
+        guard.confidence_high = Some(high);
+        Ok(())
+    }
+    let m = meta.clone();
+    engine.register_fn("set_confidence_interval", move |low: f64, high: f64| {
+        set_interval(&m, MetaNumber::Float(low), MetaNumber::Float(high))
+    });
+    engine.register_fn("set_confidence_interval", move |low: i64, high: i64| {
+        set_interval(&meta, MetaNumber::Int(low), MetaNumber::Int(high))
     });
+}
+
+/// Register the host functions that keep no per-run state, into the module
+/// engines share (see script_engine.rs)
+fn register_host_fns(module: &mut Module) {
+    // Register join() manually for arrays
+    FuncRegistration::new("join").set_into_module(
+        module,
+        |arr: rhai::Array, sep: &str| -> String {
+            arr.into_iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(sep)
+        },
+    );
+
+    // Register contains_key manually for Map
+    FuncRegistration::new("contains_key")
+        .set_into_module(module, |map: &mut rhai::Map, key: &str| -> bool {
+            map.contains_key(key)
+        });
 
     // Register host functions
     // http_get_string returns Result<String, String> (for advanced usage)
-    engine.register_fn("http_get_string", http_get_string);
+    FuncRegistration::new("http_get_string").set_into_module(module, http_get_string);
     // http_get returns String directly, or "Error: ..." if failed (easier to use)
-    engine.register_fn("http_get", http_get);
+    FuncRegistration::new("http_get").set_into_module(module, http_get);
     // http_get_json validates JSON response and returns JSON string or error string
-    engine.register_fn("http_get_json", http_get_json);
+    FuncRegistration::new("http_get_json").set_into_module(module, http_get_json);
     // Register both versions of parse_json: one for &str, one for Dynamic
-    engine.register_fn("parse_json", parse_json);
-    engine.register_fn("parse_json", parse_json_dynamic);
+    FuncRegistration::new("parse_json").set_into_module(module, parse_json);
+    FuncRegistration::new("parse_json").set_into_module(module, parse_json_dynamic);
     // fetch_json: Convenience function that fetches and parses JSON in one step (RECOMMENDED)
-    engine.register_fn("fetch_json", fetch_json);
+    FuncRegistration::new("fetch_json").set_into_module(module, fetch_json);
     // Helper function to convert Dynamic to String (useful for unwrap() results)
-    engine.register_fn("to_string", |value: &mut Dynamic| -> String {
+    FuncRegistration::new("to_string").set_into_module(module, |value: &mut Dynamic| -> String {
         if let Ok(s) = value.clone().into_string() {
             s
         } else {
             value.to_string()
         }
     });
-    engine.register_fn("error", |msg: &str| -> () {
+    FuncRegistration::new("error").set_into_module(module, |msg: &str| -> () {
         eprintln!("Script error: {}", msg);
     });
-
+    // Debug function to inspect Result type representation
+    FuncRegistration::new("debug_result").set_into_module(
+        module,
+        |result: &mut Dynamic| -> String {
+            let result_str = result.to_string();
+            let type_name = result.type_name();
+            format!("Result type: {}, string: {}", type_name, result_str)
+        },
+    );
+    // Debug function to print response (for debugging HTTP calls)
+    FuncRegistration::new("debug_print").set_into_module(module, |msg: &str| -> () {
+        eprintln!("[Rhai Debug] {}", msg);
+    });
     // Register Result helper functions for Rhai
     // These allow Rhai scripts to work with Result<String, String> from http_get_string
-    engine.register_fn("is_err", |result: &mut Dynamic| -> bool {
-        println!("result: {}", result);
+    // Note: Rhai represents Result as a special type, we need to check its string representation
+    FuncRegistration::new("is_err").set_into_module(module, |result: &mut Dynamic| -> bool {
+        // Check if result is an error by examining its string representation
+        // Result<String, String> when converted to string shows "Err(...)" for errors
         let result_str = result.to_string();
         result_str.starts_with("Err(") || result_str.starts_with("Error:")
     });
-    engine.register_fn("is_ok", |result: &mut Dynamic| -> bool {
+    FuncRegistration::new("is_ok").set_into_module(module, |result: &mut Dynamic| -> bool {
         let result_str = result.to_string();
         !result_str.starts_with("Err(") && !result_str.starts_with("Error:")
     });
-    engine.register_fn("unwrap", |result: &mut Dynamic| -> Dynamic {
+    FuncRegistration::new("unwrap").set_into_module(module, |result: &mut Dynamic| -> Dynamic {
         let result_str = result.to_string();
         if result_str.starts_with("Err(") {
+            // Extract error message from "Err(...)"
             let err_msg = result_str
                 .trim_start_matches("Err(")
                 .trim_end_matches(")")
                 .to_string();
+            // Throw error by returning error string
             Dynamic::from(format!("Error: {}", err_msg))
         } else if result_str.starts_with("Ok(") {
+            // Extract value from "Ok(...)"
             let value = result_str
                 .trim_start_matches("Ok(")
                 .trim_end_matches(")")
                 .to_string();
             Dynamic::from(value)
         } else {
+            // Not a Result type, return as-is
             result.clone()
         }
     });
     // unwrap_string returns String directly (useful for parse_json)
     // Try to extract the actual value from Result<String, String>
-    engine.register_fn("unwrap_string", |result: &mut Dynamic| -> String {
-        let result_str = result.to_string();
+    FuncRegistration::new("unwrap_string").set_into_module(
+        module,
+        |result: &mut Dynamic| -> String {
+            // First, try to get the string representation
+            let result_str = result.to_string();
 
-        // Check if it's an error
-        if result_str.starts_with("Err(") || result_str.starts_with("Error:") {
-            let err_msg = if result_str.starts_with("Err(") {
-                result_str
-                    .trim_start_matches("Err(")
-                    .trim_end_matches(")")
-                    .to_string()
-            } else {
-                result_str
-            };
-            return format!("Error: {}", err_msg);
-        }
+            // Check if it's an error
+            if result_str.starts_with("Err(") || result_str.starts_with("Error:") {
+                let err_msg = if result_str.starts_with("Err(") {
+                    result_str
+                        .trim_start_matches("Err(")
+                        .trim_end_matches(")")
+                        .to_string()
+                } else {
+                    result_str
+                };
+                return format!("Error: {}", err_msg);
+            }
 
-        // Try to extract from "Ok(...)" format
-        if result_str.starts_with("Ok(") {
-            let value = result_str
-                .trim_start_matches("Ok(")
-                .trim_end_matches(")")
-                .to_string();
-            // Remove quotes if present
-            let value = value.trim_matches('"').to_string();
-            return value;
-        }
+            // Try to extract from "Ok(...)" format
+            if result_str.starts_with("Ok(") {
+                // Remove "Ok(" prefix and ")" suffix
+                let value = result_str
+                    .trim_start_matches("Ok(")
+                    .trim_end_matches(")")
+                    .to_string();
+                // Remove quotes if present
+                let value = value.trim_matches('"').to_string();
+                return value;
+            }
 
-        // If it doesn't match Ok/Err pattern, try to extract string directly
-        if let Ok(s) = result.clone().into_string() {
-            return s;
-        }
+            // If it doesn't match Ok/Err pattern, try to extract string directly
+            // Result<String, String> might be represented differently
+            if let Ok(s) = result.clone().into_string() {
+                return s;
+            }
 
-        // Last resort: return as string
-        result_str
-    });
-    engine.register_fn("err", |result: &mut Dynamic| -> Dynamic {
+            // Last resort: return as string
+            result_str
+        },
+    );
+    FuncRegistration::new("err").set_into_module(module, |result: &mut Dynamic| -> Dynamic {
         let result_str = result.to_string();
         if result_str.starts_with("Err(") {
             let err_msg = result_str
@@ -417,17 +821,38 @@ fn setup_rhai_engine() -> Engine {
             Dynamic::UNIT
         }
     });
+}
 
-    engine
+/// Error for a Rhai value that cannot be converted to the expected ReturnType
+fn type_mismatch(expected: &ReturnType, dynamic: &Dynamic, reason: &str) -> EnclaveError {
+    EnclaveError::ConversionError(format!(
+        "Cannot convert to {:?}: got Rhai type '{}' ({:?}): {}",
+        expected,
+        dynamic.type_name(),
+        dynamic,
+        reason
+    ))
 }
 
 /// Convert Rhai Dynamic result to ResultValue based on expected type
-fn convert_rhai_result(
+/// In strict mode only values already of the expected Rhai type are accepted
+/// (string -> STRING, integer -> NUMBER, bool -> BOOLEAN, array of u8 -> VECTOR),
+/// floats are accepted as NUMBER only when they have no fractional part.
+/// In lenient mode strings are parsed and any value can coerce to STRING.
+pub fn convert_rhai_result(
     dynamic: Dynamic,
     expected_type: &ReturnType,
+    strict: bool,
 ) -> Result<Option<ResultValue>, EnclaveError> {
     match expected_type {
         ReturnType::STRING => {
+            if strict && !dynamic.is_string() {
+                return Err(type_mismatch(
+                    expected_type,
+                    &dynamic,
+                    "strict mode requires a string",
+                ));
+            }
             let s = dynamic.to_string();
             Ok(Some(ResultValue::STRING(s.trim().to_string())))
         }
@@ -437,36 +862,49 @@ fn convert_rhai_result(
                 if num >= 0 {
                     Ok(Some(ResultValue::NUMBER(num as u64)))
                 } else {
-                    Err(EnclaveError::GenericError(format!(
+                    Err(EnclaveError::ConversionError(format!(
                         "Negative number not supported: {}",
                         num
                     )))
                 }
             } else if let Ok(num) = dynamic.as_float() {
-                if num >= 0.0 {
-                    Ok(Some(ResultValue::NUMBER(num as u64)))
-                } else {
-                    Err(EnclaveError::GenericError(format!(
+                if num < 0.0 {
+                    Err(EnclaveError::ConversionError(format!(
                         "Negative number not supported: {}",
                         num
                     )))
+                } else if strict && num.fract() != 0.0 {
+                    Err(type_mismatch(
+                        expected_type,
+                        &dynamic,
+                        "strict mode does not truncate floats",
+                    ))
+                } else {
+                    Ok(Some(ResultValue::NUMBER(num as u64)))
                 }
+            } else if strict {
+                Err(type_mismatch(
+                    expected_type,
+                    &dynamic,
+                    "strict mode requires an integer",
+                ))
             } else {
                 // Try parsing as string
                 let s = dynamic.to_string().trim().to_string();
                 if s.starts_with("Error:") {
-                    Err(EnclaveError::GenericError(format!(
-                        "Rhai code execution failed: {}",
-                        s
-                    )))
+                    Err(EnclaveError::ScriptError {
+                        line: None,
+                        message: format!("Rhai code execution failed: {}", s),
+                    })
                 } else {
                     s.parse::<u64>()
                         .map(|n| Some(ResultValue::NUMBER(n)))
                         .map_err(|e| {
-                            EnclaveError::GenericError(format!(
-                                "Cannot convert to NUMBER: string '{}' is not a valid number: {}",
-                                s, e
-                            ))
+                            type_mismatch(
+                                expected_type,
+                                &dynamic,
+                                &format!("'{}' is not a valid number: {}", s, e),
+                            )
                         })
                 }
             }
@@ -475,14 +913,22 @@ fn convert_rhai_result(
             // Try as boolean first
             if let Ok(b) = dynamic.as_bool() {
                 Ok(Some(ResultValue::BOOLEAN(b)))
+            } else if strict {
+                Err(type_mismatch(
+                    expected_type,
+                    &dynamic,
+                    "strict mode requires a bool",
+                ))
             } else {
                 // Try parsing as string
                 let s = dynamic.to_string().trim().to_lowercase();
                 match s.as_str() {
                     "true" | "1" => Ok(Some(ResultValue::BOOLEAN(true))),
                     "false" | "0" => Ok(Some(ResultValue::BOOLEAN(false))),
-                    _ => Err(EnclaveError::GenericError(
-                        "Cannot convert to BOOLEAN".to_string(),
+                    _ => Err(type_mismatch(
+                        expected_type,
+                        &dynamic,
+                        "expected true/false/1/0",
                     )),
                 }
             }
@@ -496,24 +942,31 @@ fn convert_rhai_result(
                     let item = item.clone();
                     // Try as integer first
                     if let Ok(num) = item.as_int() {
-                        if num >= 0 && num <= 255 {
+                        if (0..=255).contains(&num) {
                             u8_vec.push(num as u8);
                         } else {
-                            return Err(EnclaveError::GenericError(format!(
+                            return Err(EnclaveError::ConversionError(format!(
                                 "Value {} out of u8 range",
                                 num
                             )));
                         }
-                    } else if let Ok(s) = item.clone().into_string() {
+                    } else if !strict && item.is_string() {
                         // If it's a string, convert to bytes
-                        u8_vec.extend_from_slice(s.as_bytes());
+                        u8_vec.extend_from_slice(item.to_string().as_bytes());
                     } else {
-                        return Err(EnclaveError::GenericError(
-                            "Unsupported array element type".to_string(),
-                        ));
+                        return Err(EnclaveError::ConversionError(format!(
+                            "Unsupported array element type '{}' for VECTOR",
+                            item.type_name()
+                        )));
                     }
                 }
                 Ok(Some(ResultValue::VECTOR(u8_vec)))
+            } else if strict {
+                Err(type_mismatch(
+                    expected_type,
+                    &dynamic,
+                    "strict mode requires an array of integers",
+                ))
             } else {
                 // Try as string and convert to bytes
                 let s = dynamic.to_string();
@@ -524,227 +977,62 @@ fn convert_rhai_result(
 }
 
 /// Execute Rhai script and convert to expected return type (async version)
-/// This function wraps Rhai execution in spawn_blocking to avoid blocking the async runtime
-/// Returns ResultValue converted to the type specified in the oracle feed
+/// This function runs Rhai on a script worker (see concurrency.rs) to avoid blocking the async runtime
+/// Returns ResultValue converted to the type specified in the oracle feed,
+/// together with any metadata the script attached
 pub async fn execute_rhai_code_async(
     code: &str,
     expected_type: &ReturnType,
-) -> Result<Option<ResultValue>, EnclaveError> {
+    strict_types: bool,
+) -> Result<RhaiOutput, EnclaveError> {
     let code = code.to_string();
     let expected_type = expected_type.clone();
 
-    // Execute Rhai in a separate thread to avoid blocking the async runtime
-    // This is critical because http_get_string uses reqwest::blocking::get()
-    // We use std::thread and convert Dynamic to a Send-safe type before sending
+    // Execute Rhai on a script worker to avoid blocking the async runtime, as
+    // host functions wait for their HTTP and Sui calls (see http_proxy.rs)
     let (tx, rx) = tokio::sync::oneshot::channel();
 
-    std::thread::spawn(move || {
-        // Create engine inside the blocking thread
-        let mut engine = Engine::new();
-
-        // Load the Rhai Standard Package
-        engine.register_global_module(rhai::packages::StandardPackage::new().as_shared_module());
-
-        // Load Basic String Package
-        engine.register_global_module(rhai::packages::BasicStringPackage::new().as_shared_module());
-
-        // Register join() manually for arrays
-        engine.register_fn("join", |arr: rhai::Array, sep: &str| -> String {
-            arr.into_iter()
-                .map(|x| x.to_string())
-                .collect::<Vec<_>>()
-                .join(sep)
-        });
-
-        // Register contains_key manually for Map
-        engine.register_fn("contains_key", |map: &mut rhai::Map, key: &str| -> bool {
-            map.contains_key(key)
-        });
-
-        // Register host functions
-        // http_get_string returns Result<String, String> (for advanced usage)
-        engine.register_fn("http_get_string", http_get_string);
-        // http_get returns String directly, or "Error: ..." if failed (easier to use)
-        engine.register_fn("http_get", http_get);
-        // http_get_json validates JSON response and returns JSON string or error string
-        engine.register_fn("http_get_json", http_get_json);
-        // Register both versions of parse_json: one for &str, one for Dynamic
-        engine.register_fn("parse_json", parse_json);
-        engine.register_fn("parse_json", parse_json_dynamic);
-        // fetch_json: Convenience function that fetches and parses JSON in one step (RECOMMENDED)
-        engine.register_fn("fetch_json", fetch_json);
-        // Helper function to convert Dynamic to String (useful for unwrap() results)
-        engine.register_fn("to_string", |value: &mut Dynamic| -> String {
-            if let Ok(s) = value.clone().into_string() {
-                s
-            } else {
-                value.to_string()
-            }
-        });
-        engine.register_fn("error", |msg: &str| -> () {
-            eprintln!("Script error: {}", msg);
-        });
-        // Debug function to inspect Result type representation
-        engine.register_fn("debug_result", |result: &mut Dynamic| -> String {
-            let result_str = result.to_string();
-            let type_name = result.type_name();
-            format!("Result type: {}, string: {}", type_name, result_str)
-        });
-        // Debug function to print response (for debugging HTTP calls)
-        engine.register_fn("debug_print", |msg: &str| -> () {
-            eprintln!("[Rhai Debug] {}", msg);
-        });
-
-        // Register Result helper functions for Rhai
-        // These allow Rhai scripts to work with Result<String, String> from http_get_string
-        // Note: Rhai represents Result as a special type, we need to check its string representation
-        engine.register_fn("is_err", |result: &mut Dynamic| -> bool {
-            // Check if result is an error by examining its string representation
-            // Result<String, String> when converted to string shows "Err(...)" for errors
-            let result_str = result.to_string();
-            result_str.starts_with("Err(") || result_str.starts_with("Error:")
-        });
-        engine.register_fn("is_ok", |result: &mut Dynamic| -> bool {
-            let result_str = result.to_string();
-            !result_str.starts_with("Err(") && !result_str.starts_with("Error:")
-        });
-        engine.register_fn("unwrap", |result: &mut Dynamic| -> Dynamic {
-            let result_str = result.to_string();
-            if result_str.starts_with("Err(") {
-                // Extract error message from "Err(...)"
-                let err_msg = result_str
-                    .trim_start_matches("Err(")
-                    .trim_end_matches(")")
-                    .to_string();
-                // Throw error by returning error string
-                Dynamic::from(format!("Error: {}", err_msg))
-            } else if result_str.starts_with("Ok(") {
-                // Extract value from "Ok(...)"
-                let value = result_str
-                    .trim_start_matches("Ok(")
-                    .trim_end_matches(")")
-                    .to_string();
-                Dynamic::from(value)
-            } else {
-                // Not a Result type, return as-is
-                result.clone()
+    // Bounds the scripts running, released when the script returns
+    let slot = concurrency::execution_slot().await?;
+    concurrency::spawn_script(slot, move || {
+        // Result metadata setters (set_source_count, set_spread, ...)
+        let meta = Arc::new(Mutex::new(ResultMeta::default()));
+        let mut engine = script_engine::script_engine(meta.clone());
+
+        // Bundles register their modules and data files, then run the main script
+        let code = match bundle::prepare(&mut engine, &code) {
+            Ok(code) => code,
+            Err(message) => {
+                let _ = tx.send((
+                    Err(EnclaveError::ScriptError {
+                        line: None,
+                        message,
+                    }),
+                    ResultMeta::default(),
+                ));
+                return;
             }
-        });
-        // unwrap_string returns String directly (useful for parse_json)
-        // Try to extract the actual value from Result<String, String>
-        engine.register_fn("unwrap_string", |result: &mut Dynamic| -> String {
-            // First, try to get the string representation
-            let result_str = result.to_string();
-
-            // Check if it's an error
-            if result_str.starts_with("Err(") || result_str.starts_with("Error:") {
-                let err_msg = if result_str.starts_with("Err(") {
-                    result_str
-                        .trim_start_matches("Err(")
-                        .trim_end_matches(")")
-                        .to_string()
-                } else {
-                    result_str
-                };
-                return format!("Error: {}", err_msg);
-            }
-
-            // Try to extract from "Ok(...)" format
-            if result_str.starts_with("Ok(") {
-                // Remove "Ok(" prefix and ")" suffix
-                let value = result_str
-                    .trim_start_matches("Ok(")
-                    .trim_end_matches(")")
-                    .to_string();
-                // Remove quotes if present
-                let value = value.trim_matches('"').to_string();
-                return value;
-            }
-
-            // If it doesn't match Ok/Err pattern, try to extract string directly
-            // Result<String, String> might be represented differently
-            if let Ok(s) = result.clone().into_string() {
-                return s;
-            }
-
-            // Last resort: return as string
-            result_str
-        });
-        engine.register_fn("err", |result: &mut Dynamic| -> Dynamic {
-            let result_str = result.to_string();
-            if result_str.starts_with("Err(") {
-                let err_msg = result_str
-                    .trim_start_matches("Err(")
-                    .trim_end_matches(")")
-                    .to_string();
-                Dynamic::from(err_msg)
-            } else {
-                Dynamic::UNIT
-            }
-        });
-
-        let mut scope = Scope::new();
-        let result: Result<Dynamic, Box<EvalAltResult>> = engine.eval_with_scope(&mut scope, &code);
-
-        // Convert Dynamic to a Send-safe representation (JSON string)
-        // We'll parse it back on the async side
-        let sendable_result: Result<String, String> = match result {
-            Ok(dynamic) => {
-                // Convert Dynamic to JSON string for safe thread communication
-                let json_value = match dynamic.type_name() {
-                    "()" => JsonValue::Null,
-                    "bool" => JsonValue::Bool(dynamic.as_bool().unwrap_or(false)),
-                    "i64" => JsonValue::Number(dynamic.as_int().unwrap_or(0).into()),
-                    "f64" => {
-                        let f = dynamic.as_float().unwrap_or(0.0);
-                        serde_json::Number::from_f64(f)
-                            .map(JsonValue::Number)
-                            .unwrap_or(JsonValue::Null)
-                    }
-                    "string" => JsonValue::String(dynamic.into_string().unwrap_or_default()),
-                    _ => {
-                        // For other types, convert to string
-                        JsonValue::String(dynamic.to_string())
-                    }
-                };
-                match serde_json::to_string(&json_value) {
-                    Ok(s) => Ok(s),
-                    Err(e) => Err(format!("JSON serialization error: {}", e)),
-                }
-            }
-            Err(e) => Err(format!("{}", e)),
         };
 
-        let _ = tx.send(sendable_result);
+        let mut scope = Scope::new();
+        let result: Result<Dynamic, Box<EvalAltResult>> = script_engine::compile(&engine, &code)
+            .and_then(|ast| engine.eval_ast_with_scope(&mut scope, &ast));
+
+        // Convert in the worker, Dynamic is not Send but ResultValue is
+        let output = result
+            .map_err(|e| rhai_script_error(&e))
+            .and_then(|dynamic| convert_rhai_result(dynamic, &expected_type, strict_types));
+        let meta = meta.lock().unwrap().clone();
+        let _ = tx.send((output, meta));
     });
 
-    // Receive the result and convert back to Dynamic
-    let json_str = match rx.await {
-        Ok(Ok(json_str)) => json_str,
-        Ok(Err(e)) => {
-            return Err(EnclaveError::GenericError(format!(
-                "Rhai execution error: {}",
-                e
-            )));
-        }
-        Err(e) => {
-            return Err(EnclaveError::GenericError(format!(
-                "Thread communication error: {}",
-                e
-            )));
-        }
-    };
-
-    // Parse JSON back to Dynamic
-    let json_value: JsonValue = serde_json::from_str(&json_str)
-        .map_err(|e| EnclaveError::GenericError(format!("Failed to parse result JSON: {}", e)))?;
-
-    let result: Result<Dynamic, Box<EvalAltResult>> = Ok(json_value_to_dynamic(&json_value));
-
-    match result {
-        Ok(dynamic) => convert_rhai_result(dynamic, &expected_type),
+    match rx.await {
+        Ok((result, meta)) => Ok(RhaiOutput {
+            result: result?,
+            meta,
+        }),
         Err(e) => Err(EnclaveError::GenericError(format!(
-            "Rhai execution error: {}",
+            "Thread communication error: {}",
             e
         ))),
     }
@@ -756,124 +1044,715 @@ fn execute_rhai_code(
     code: &str,
     expected_type: &ReturnType,
 ) -> Result<Option<ResultValue>, EnclaveError> {
-    let engine = setup_rhai_engine();
+    execute_rhai_code_with_meta(code, expected_type, false).map(|output| output.result)
+}
+
+/// Sync version of execute_rhai_code that also returns the script metadata
+fn execute_rhai_code_with_meta(
+    code: &str,
+    expected_type: &ReturnType,
+    strict_types: bool,
+) -> Result<RhaiOutput, EnclaveError> {
+    let meta = Arc::new(Mutex::new(ResultMeta::default()));
+    let mut engine = script_engine::script_engine(meta.clone());
+    let code = bundle::prepare(&mut engine, code).map_err(|message| EnclaveError::ScriptError {
+        line: None,
+        message,
+    })?;
     let mut scope = Scope::new();
 
     // Execute the script
-    let result: Result<Dynamic, Box<EvalAltResult>> = engine.eval_with_scope(&mut scope, code);
+    let result: Result<Dynamic, Box<EvalAltResult>> = script_engine::compile(&engine, &code)
+        .and_then(|ast| engine.eval_ast_with_scope(&mut scope, &ast));
 
     match result {
-        Ok(dynamic) => convert_rhai_result(dynamic, expected_type),
-        Err(e) => Err(EnclaveError::GenericError(format!(
-            "Rhai execution error: {}",
-            e
-        ))),
+        Ok(dynamic) => Ok(RhaiOutput {
+            result: convert_rhai_result(dynamic, expected_type, strict_types)?,
+            meta: meta.lock().unwrap().clone(),
+        }),
+        Err(e) => Err(rhai_script_error(&e)),
     }
 }
 
+/// Error for a failed Rhai evaluation, with the script line it failed at
+fn rhai_script_error(e: &EvalAltResult) -> EnclaveError {
+    EnclaveError::ScriptError {
+        line: e.position().line(),
+        message: format!("Rhai execution error: {}", e),
+    }
+}
+
+/// Check the downloaded blob against the SHA-256 stored in the feed
+fn verify_blob_hash(blob_id: &str, expected: &[u8], blob: &[u8]) -> Result<(), EnclaveError> {
+    let actual = Sha256::digest(blob).digest;
+    if actual.as_slice() != expected {
+        return Err(EnclaveError::BlobFetchFailed {
+            blob_id: blob_id.to_string(),
+            message: format!(
+                "Blob hash mismatch: expected {}, got {}",
+                Hex::encode(expected),
+                Hex::encode(actual)
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Check the blob of `feed` against its blob_hash. Feeds created by
+/// `feed::new`, or before settings existed, have no hash and are not checked.
+fn verify_feed_blob(feed: &OracleFeed, blob: &[u8]) -> Result<(), EnclaveError> {
+    if !feed.has_settings() {
+        return Ok(());
+    }
+    verify_blob_hash(&feed.blob_id, &feed.blob_hash, blob)
+}
+
+/// Refuse to update a feed before its allowed update time
+fn check_update_allowed(feed: &OracleFeed, timestamp_ms: u64) -> Result<(), EnclaveError> {
+    if timestamp_ms < feed.allow_update_timestamp_ms {
+        return Err(EnclaveError::UpdateTooEarly {
+            earliest_allowed_timestamp_ms: feed.allow_update_timestamp_ms,
+            current_timestamp_ms: timestamp_ms,
+        });
+    }
+    Ok(())
+}
+
+/// Code sources other than Walrus, selected by the blob id prefix.
+const CODE_SCHEMES: [&str; 4] = [
+    builtin::SCHEME,
+    onchain::SCHEME,
+    ipfs::SCHEME,
+    arweave::SCHEME,
+];
+
+/// Whether the code of `blob_id` is fetched from Walrus aggregators. Unknown
+/// schemes are, so they have to pass the certification check like any other
+/// Walrus blob id.
+fn is_walrus_blob(blob_id: &str) -> bool {
+    !CODE_SCHEMES
+        .iter()
+        .any(|scheme| blob_id.starts_with(scheme))
+}
+
+/// Download the feed code from the storage selected by the blob id scheme:
+/// `ipfs://<CID>` for IPFS, `ar://<tx id>` for Arweave, `sui://<object id>`
+/// for a Script object, `builtin://<name>` for a script compiled into the
+/// server, anything else is a Walrus blob id.
+async fn fetch_feed_code(
+    chain: &dyn crate::chain_reader::ChainReader,
+    blob_id: &str,
+) -> Result<Vec<u8>, EnclaveError> {
+    if let Some(name) = blob_id.strip_prefix(builtin::SCHEME) {
+        builtin::get(name)
+    } else if let Some(script_id) = blob_id.strip_prefix(onchain::SCHEME) {
+        onchain::fetch(chain, script_id).await
+    } else if let Some(cid) = blob_id.strip_prefix(ipfs::SCHEME) {
+        ipfs::fetch(cid).await
+    } else if let Some(tx_id) = blob_id.strip_prefix(arweave::SCHEME) {
+        arweave::fetch(tx_id).await
+    } else {
+        walrus::fetch_blob(blob_id).await
+    }
+}
+
+impl UpdateOracleRequest {
+    /// Feed object id, given as is or resolved from the registry key.
+    fn resolve_feed_id(&self) -> Result<Address, EnclaveError> {
+        match (&self.registry_id, &self.key) {
+            (None, None) => parse_feed_id(&self.feed_id),
+            (Some(registry_id), Some(key)) if self.feed_id.is_empty() => {
+                registry::resolve(registry_id, key)
+            }
+            _ => Err(EnclaveError::InvalidRequest(
+                "Set either feed_id, or registry_id and key".to_string(),
+            )),
+        }
+    }
+}
+
+/// Parse a hex feed object id, rejecting anything but 1 to 64 hex digits
+/// (optionally 0x prefixed) before it reaches an RPC call
+pub fn parse_feed_id(feed_id: &str) -> Result<Address, EnclaveError> {
+    let digits = feed_id.strip_prefix("0x").unwrap_or(feed_id);
+    if digits.is_empty() || digits.len() > 64 || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(EnclaveError::InvalidRequest(format!(
+            "feed_id must be a hex object id of 1 to 64 digits, got {:?}",
+            feed_id.chars().take(80).collect::<String>()
+        )));
+    }
+    Address::from_hex(feed_id)
+        .map_err(|e| EnclaveError::InvalidRequest(format!("Invalid feed_id format: {}", e)))
+}
+
+/// Error for a failed Sui RPC call made for `feed_id`
+fn feed_rpc_error(feed_id: &Address, method: &str, status: tonic::Status) -> EnclaveError {
+    match status.code() {
+        tonic::Code::NotFound => EnclaveError::FeedNotFound {
+            feed_id: feed_id.to_string(),
+        },
+        tonic::Code::DeadlineExceeded => EnclaveError::UpstreamTimeout {
+            upstream: format!("Sui fullnode ({})", method),
+        },
+        _ => EnclaveError::RpcError(format!(
+            "{} for feed {} failed: {}",
+            method,
+            feed_id,
+            status.message()
+        )),
+    }
+}
+
+/// Decode the BCS of the feed object, or of the registry field holding it,
+/// into an OracleFeed, once its type and owner are checked. The feed is read
+/// from the bytes of the response, see feed_object.rs.
+pub fn decode_oracle_feed(feed_id: &Address, bcs_bytes: &[u8]) -> Result<OracleFeed, EnclaveError> {
+    // Without its settings, see feed_layout::read_settings
+    feed_layout::decode(feed_id, feed_object::feed_contents(feed_id, bcs_bytes)?)
+}
+
+/// Run a feed's script and return the signed result.
+#[utoipa::path(
+    post,
+    path = "/process_data",
+    request_body = UpdateOracleRequest,
+    responses(
+        (status = 200, description = "Signed result, encrypted when a recipient is set", body = ResultOutput),
+        (status = 400, description = "Invalid feed id or malformed body", body = openapi::ProblemDetails),
+        (status = 413, description = "Body over the route's body limit", body = openapi::ProblemDetails),
+        (status = 409, description = "Feed not yet open for updates, a value the feed's update policy finds unchanged (code update_not_required), or a different result was already signed", body = openapi::ProblemDetails),
+        (status = 429, description = "Rate limited", body = openapi::ProblemDetails),
+        (status = 502, description = "Feed code or Sui RPC unavailable", body = openapi::ProblemDetails),
+        (status = 503, description = "Feed paused, or too many scripts running", body = openapi::ProblemDetails),
+        (status = 504, description = "Upstream timed out", body = openapi::ProblemDetails),
+    )
+)]
 pub async fn process_data(
     State(state): State<Arc<AppState>>,
-    Json(request): Json<UpdateOracleRequest>,
-) -> Result<Json<ProcessedDataResponse<IntentMessage<UpdateOracleResponse>>>, EnclaveError> {
-    // Clone the client to get mutable access (Client implements Clone)
-    let mut sui_client = state.sui_client.clone();
-    let feed_id = Address::from_hex(&request.feed_id)
-        .map_err(|e| EnclaveError::GenericError(format!("Invalid feed_id format: {}", e)))?;
-    println!("feed id: {:?}", feed_id);
-
-    // Use batch_get_objects as get_object may not be available on testnet nodes
-    // Create a single-object batch request
-    let response = sui_client
-        .ledger_client()
-        .get_object(GetObjectRequest::new(&feed_id).with_read_mask(FieldMask::from_str("bcs")))
+    headers: HeaderMap,
+    AppJson(request): AppJson<UpdateOracleRequest>,
+) -> Result<Json<ResultOutput>, EnclaveError> {
+    let requester = auth::identify(&state.config.auth, &headers).map(|key| key.name.clone());
+    process_request("process_data", &state, requester, &request)
         .await
-        .unwrap()
-        .into_inner();
-
-    let bcs_bytes = response
-        .object
-        .and_then(|obj| obj.bcs)
-        .and_then(|bcs| bcs.value)
-        .map(|bytes| bytes.to_vec())
-        .ok_or_else(|| EnclaveError::GenericError("No BCS data in Committee object".to_string()))
-        .unwrap();
-
-    let obj: sui_sdk_types::Object = bcs::from_bytes(&bcs_bytes)
-        .map_err(|e| EnclaveError::GenericError(format!("Failed to deserialize object: {}", e)))?;
-    let move_object = obj
-        .as_struct()
-        .ok_or_else(|| EnclaveError::GenericError("Object is not a Move object".to_string()))?;
-    let oracle_feed: OracleFeed = bcs::from_bytes(move_object.contents()).map_err(|e| {
-        EnclaveError::GenericError(format!("Failed to deserialize OracleFeed: {}", e))
-    })?;
+        .map(Json)
+}
+
+/// Sign the result of a request and seal it for its recipient, logged as
+/// `call`. Shared by process_data and process_batch.
+async fn process_request(
+    call: &str,
+    state: &AppState,
+    requester: Option<String>,
+    request: &UpdateOracleRequest,
+) -> Result<ResultOutput, EnclaveError> {
+    let logged_feed_id = Some(request.feed_id.clone())
+        .filter(|feed_id| !feed_id.is_empty())
+        .or_else(|| request.registry_id.clone());
+    call_log::logged(call, requester, logged_feed_id, async {
+        let feed_id = request.resolve_feed_id()?;
+        let response = signed_result(state, feed_id, request).await?;
+        call_log::note_result(&response.signed.response);
+        // Only the recipient can read the result if one is requested or configured for the feed
+        encryption::seal_output(feed_id, response, request.recipient_public_key.as_deref())
+    })
+    .await
+}
+
+async fn signed_result(
+    state: &AppState,
+    feed_id: Address,
+    request: &UpdateOracleRequest,
+) -> Result<UpdateOracleHttpResponse, EnclaveError> {
+    // Serve a recently signed result instead of re-fetching and re-running the script
+    let now_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to get current timestamp: {}", e)))?
+        .as_millis() as u64;
+    // Round results are signed under the round timestamp and pinned ones may
+    // read an older feed state, so they bypass the cache
+    let pin = FeedPin::new(request.version, request.checkpoint)?;
+    let cached = match request.round_timestamp_ms {
+        None if !pin.is_pinned() => cache::get_cached(feed_id, request.sign_meta, now_ms).await,
+        _ => None,
+    };
+    if let Some(cached) = cached {
+        return Ok(cached);
+    }
+
+    // Only runs count against the feed's rate limit, they hit the upstream APIs
+    rate_limit::check_feed(&state.config.rate_limit, &feed_id.to_string())?;
+    if request.round_timestamp_ms.is_some() || pin.is_pinned() {
+        let round_timestamp_ms = request.round_timestamp_ms;
+        return run_feed(state, feed_id, request.sign_meta, round_timestamp_ms, pin).await;
+    }
+
+    let response = run_feed(state, feed_id, request.sign_meta, None, pin).await?;
+    cache::store(feed_id, request.sign_meta, &response).await;
+    Ok(response)
+}
+
+/// Register the feature flags the admin API may flip, starting at their
+/// values in coeus_config.yaml.
+pub fn register_feature_flags() {
+    admin::register_flag(bls::FLAG, COEUS_CONFIG.bls.enabled);
+    admin::register_flag(audit::SIGN_HEAD_FLAG, COEUS_CONFIG.audit.sign_head);
+    admin::register_flag(sign_digest::FLAG, COEUS_CONFIG.sign_digest.enabled);
+}
+
+/// Routes of the oracle, mounted under /apps/coeus-oracle and, when it is the
+/// root app, at the root.
+pub fn router(state: Arc<AppState>) -> Router {
+    // Route specific limits replace the global one, see [limits.routes]
+    let limits = state.config.limits.clone();
+    let route_limit = |route: &str| DefaultBodyLimit::max(limits.body_limit(route));
+    Router::new()
+        .route("/get_attestation", get(get_attestation))
+        .route("/bls_attestation", get(get_bls_attestation))
+        .route("/keys", get(get_keys))
+        .route("/feed_keys", get(get_feed_keys))
+        .route("/replay_policy", get(get_replay_policy))
+        .route("/audit/head", get(get_audit_head))
+        .route("/audit/log", get(get_audit_log))
+        .route("/enclave_info", get(get_enclave_info))
+        .route("/version", get(get_version))
+        .route(
+            "/bls/aggregate",
+            post(aggregate_bls_signatures)
+                .layer(route_limit("/bls/aggregate"))
+                .route_layer(from_fn_with_state(
+                    (state.clone(), Scope::ProcessData),
+                    require_scope,
+                )),
+        )
+        .route(
+            "/threshold/aggregate",
+            post(aggregate_threshold)
+                .layer(route_limit("/threshold/aggregate"))
+                .route_layer(from_fn_with_state(
+                    (state.clone(), Scope::ProcessData),
+                    require_scope,
+                )),
+        )
+        .route(
+            "/process_data",
+            post(process_data)
+                .layer(route_limit("/process_data"))
+                .route_layer(from_fn_with_state(
+                    (state.clone(), Scope::ProcessData),
+                    require_scope,
+                )),
+        )
+        .route(
+            "/process_batch",
+            post(process_batch)
+                .layer(route_limit("/process_batch"))
+                .route_layer(from_fn_with_state(
+                    (state.clone(), Scope::ProcessData),
+                    require_scope,
+                )),
+        )
+        .route(
+            "/execute_code",
+            post(execute_code)
+                .layer(route_limit("/execute_code"))
+                .route_layer(from_fn_with_state(
+                    (state.clone(), Scope::ExecuteCode),
+                    require_scope,
+                )),
+        )
+        .route(
+            "/feed_keys/seal/request",
+            post(request_seed_keys)
+                .route_layer(from_fn_with_state(state.clone(), admin::require_admin)),
+        )
+        .route(
+            "/feed_keys/seal/generate",
+            post(generate_seed)
+                .route_layer(from_fn_with_state(state.clone(), admin::require_admin)),
+        )
+        .route(
+            "/feed_keys/seal/unseal",
+            post(unseal_seed).route_layer(from_fn_with_state(state.clone(), admin::require_admin)),
+        )
+        .route(
+            "/sign_digest",
+            post(sign_digest)
+                .layer(route_limit("/sign_digest"))
+                .route_layer(from_fn_with_state(state.clone(), admin::require_admin)),
+        )
+        .route("/scheduled_results/:feed_id", get(get_scheduled_result))
+        .route("/subscribe/:feed_id", get(subscribe_feed))
+        .route("/feeds", get(list_feeds))
+        .route("/feeds/:feed_id/status", get(get_feed_status))
+        .route("/feeds/:feed_id/history", get(get_feed_history))
+        .route("/ready", get(get_ready))
+        .route("/openapi.json", get(get_openapi))
+        .route("/docs", get(get_docs))
+        // Flags responses while unregistered in mark mode, see registration.rs
+        .layer(from_fn_with_state(
+            state.clone(),
+            registration::mark_unregistered,
+        ))
+        .with_state(state)
+}
+
+/// Fetch the feed object, execute its script and sign the result.
+/// Shared by process_data and the scheduler. The outcome is recorded in the feed status.
+/// `round_timestamp_ms` signs under a threshold round's timestamp instead of the clock,
+/// `pin` reads an older state of the feed object.
+pub async fn run_feed(
+    state: &AppState,
+    feed_id: Address,
+    sign_meta: bool,
+    round_timestamp_ms: Option<u64>,
+    pin: FeedPin,
+) -> Result<UpdateOracleHttpResponse, EnclaveError> {
+    registration::require(&state.config.registration)?;
+    let mut stats = FeedRunStats {
+        request_id: request_id::current(),
+        ..Default::default()
+    };
+    let outcome = run_feed_inner(
+        state,
+        feed_id,
+        sign_meta,
+        round_timestamp_ms,
+        pin,
+        &mut stats,
+    )
+    .await;
+    status::record_run(feed_id, &outcome, stats).await;
+    outcome
+}
+
+async fn run_feed_inner(
+    state: &AppState,
+    feed_id: Address,
+    sign_meta: bool,
+    round_timestamp_ms: Option<u64>,
+    pin: FeedPin,
+    stats: &mut FeedRunStats,
+) -> Result<UpdateOracleHttpResponse, EnclaveError> {
+    if admin::is_paused(&feed_id) {
+        return Err(EnclaveError::FeedPaused {
+            feed_id: feed_id.to_string(),
+        });
+    }
+    println!("feed id: {:?}", feed_id);
+    feed_allowlist::check_feed(state.chain.as_ref(), &feed_id).await?;
+
+    let rpc_started = Instant::now();
+    let read = feed_state::read_feed(state.chain.as_ref(), feed_id, pin).await;
+    stats.rpc_latency_ms = Some(rpc_started.elapsed().as_millis() as u64);
+    let read = read?;
+    let mut oracle_feed = decode_oracle_feed(&feed_id, &read.bcs)?;
+    feed_layout::read_settings(state.chain.as_ref(), &mut oracle_feed).await?;
+    call_log::note_blob_hash(&oracle_feed.blob_hash);
     // Get current timestamp
-    let timestamp_ms = std::time::SystemTime::now()
+    let now_ms = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .map_err(|e| EnclaveError::GenericError(format!("Failed to get current timestamp: {}", e)))?
         .as_millis() as u64;
+    let timestamp_ms = threshold::round_timestamp(round_timestamp_ms, now_ms)?;
+
+    // The Move module rejects results submitted before this time, so don't execute or sign
+    check_update_allowed(&oracle_feed, timestamp_ms)?;
+
+    // Only trust aggregators for blobs that are certified on-chain
+    if is_walrus_blob(&oracle_feed.blob_id) {
+        certification::verify_certified(
+            state.chain.as_ref(),
+            &oracle_feed.blob_id,
+            oracle_feed.blob_object_id.as_ref(),
+            oracle_feed.creator.as_ref(),
+        )
+        .await?;
+    }
 
-    let url = Url::parse(&format!(
-        "https://aggregator.walrus-testnet.walrus.space/v1/blobs/{}",
-        oracle_feed.blob_id
-    ))
-    .unwrap();
-    let response = reqwest::get(url).await.unwrap();
-    let body = response.text().await.unwrap();
+    let fetch_started = Instant::now();
+    let blob = fetch_feed_code(state.chain.as_ref(), &oracle_feed.blob_id).await?;
+    stats.blob_fetch_latency_ms = Some(fetch_started.elapsed().as_millis() as u64);
+
+    // Reject tampered or truncated blobs served by the storage
+    verify_feed_blob(&oracle_feed, &blob)?;
+    // blob_hash covers the stored bytes, compressed or not
+    let blob = compression::decompress(blob)?;
+    // Only run code signed by one of the feed's publishers, if it has any
+    let code = publisher::verify_feed_code(&oracle_feed, &blob)?;
+    let body = String::from_utf8(code.to_vec()).map_err(|e| EnclaveError::ScriptError {
+        line: None,
+        message: format!("Feed code is not valid UTF-8: {}", e),
+    })?;
     println!("body: {:?}", body);
 
-    // Execute Rhai script if the extension is RHAI
+    // Execute the feed code according to its extension
     // If error when execute/run code/pull api -> result is None
     // If have result in correct format -> Option::Some(result)
-    let result = if oracle_feed.extension == CodeExtension::RHAI {
-        // Use async Rhai execution (wrapped in spawn_blocking to avoid blocking async runtime)
-        // Convert errors to None, keep Ok(Some(result)) or Ok(None) as is
-        execute_rhai_code_async(&body, &oracle_feed.return_type)
-            .await
-            .unwrap_or(None)
-    } else {
-        return Err(EnclaveError::GenericError(
-            "Unsupported code extension".to_string(),
-        ));
+    // Convert errors to None (kept in the feed status), keep Ok(Some(result)) or Ok(None) as is
+    let execution_started = Instant::now();
+    let ctx = executor::ExecutionContext {
+        return_type: oracle_feed.return_type.clone(),
+        strict_types: oracle_feed.strict_types,
     };
+    let executed = executor::EXECUTORS
+        .execute(&oracle_feed.extension, &body, &ctx)
+        .await;
+    metrics::SCRIPT_EXECUTION_SECONDS
+        .with_label_values(&[
+            oracle_feed.extension.label(),
+            if executed.is_ok() { "ok" } else { "error" },
+        ])
+        .observe(execution_started.elapsed().as_secs_f64());
+    // A shed execution never ran, fail the request instead of signing no result
+    if let Err(e @ EnclaveError::Overloaded { .. }) = executed {
+        return Err(e);
+    }
+    let output = executed.unwrap_or_else(|e| {
+        stats.script_error = Some(e.to_string());
+        RhaiOutput::default()
+    });
+    stats.execution_ms = Some(execution_started.elapsed().as_millis() as u64);
+
+    // Sign with the feed's own key if it has one, otherwise with the current enclave
+    // key, and during a rotation grace window the previous one too. A feed
+    // whose named key is not loaded yet fails before anything is recorded
+    let (signing_kp, previous_kp) = feed_keys::signing_keys_for(state, feed_id).await?;
+
+    // Skip signing if the feed's deviation/heartbeat policy says the value is
+    // unchanged. Reproductions of an older state neither check nor move it, a
+    // result is only recorded for the policy once it is signed below.
+    let policy_result = output.result.clone().filter(|_| !pin.is_pinned());
+    if let Some(result) = policy_result.as_ref() {
+        policy::check_policy(feed_id, result, timestamp_ms).await?;
+    }
 
     // Pass Option<ResultValue> directly into to_signed_response
-    let update_oracle_response = UpdateOracleResponse { result };
+    let update_oracle_response = UpdateOracleResponse {
+        result: output.result,
+    };
 
-    Ok(Json(to_signed_response(
-        &state.eph_kp,
+    // A result of a pinned, possibly older, feed state is signed under a scope
+    // of its own so it can never be submitted on-chain as an update
+    let intent = if pin.is_pinned() {
+        IntentScope::PinnedResult
+    } else {
+        IntentScope::ProcessData
+    };
+
+    // Never sign two different results for the same feed and timestamp
+    let payload_digest = Sha256::digest(encode_payload(
+        intent,
+        timestamp_ms,
+        &update_oracle_response,
+    ))
+    .digest;
+    replay::reserve(feed_id, timestamp_ms, payload_digest, now_ms).await?;
+
+    // Metadata is returned next to the signed result, signed separately on
+    // request together with the feed and the digest of the signed result
+    let meta = output.meta.with_staleness(timestamp_ms);
+    let result_meta = if meta.is_empty() {
+        None
+    } else {
+        let signature = if sign_meta {
+            let payload = ResultMetaPayload {
+                feed_id,
+                result_digest: payload_digest,
+                meta: meta.clone(),
+            };
+            let signed = to_signed_response(
+                &signing_kp,
+                payload,
+                timestamp_ms,
+                IntentScope::ResultMeta,
+                APP_ID,
+            );
+            audit::record(
+                Some(feed_id),
+                signing_kp.public().as_bytes(),
+                &signed.response,
+            )
+            .await;
+            Some(signed.signature)
+        } else {
+            None
+        };
+        Some(ResultMetaSection { meta, signature })
+    };
+    let feed_state = FeedState::new(&read, &oracle_feed);
+    let feed_state = FeedStateSection::sign(&signing_kp, feed_state, timestamp_ms).await;
+
+    let previous_key_signature = match previous_kp {
+        Some(kp) => {
+            let signed = to_signed_response(
+                &kp,
+                update_oracle_response.clone(),
+                timestamp_ms,
+                intent,
+                APP_ID,
+            );
+            audit::record(Some(feed_id), kp.public().as_bytes(), &signed.response).await;
+            Some(signed.signature)
+        }
+        None => None,
+    };
+    let signed = to_signed_response(
+        &signing_kp,
         update_oracle_response,
         timestamp_ms,
-        IntentScope::ProcessData,
-    )))
+        intent,
+        APP_ID,
+    );
+    if let Some(result) = policy_result.as_ref() {
+        policy::record(feed_id, result, timestamp_ms).await;
+    }
+    let bls_signature = if bls::enabled() {
+        let signature = bls::sign_intent(&signed.response);
+        audit::record(
+            Some(feed_id),
+            bls::BLS_KP.public().as_bytes(),
+            &signed.response,
+        )
+        .await;
+        Some(signature)
+    } else {
+        None
+    };
+
+    // Every signature is appended to the audit log, the result's last so the
+    // signed head covers all of them
+    let audit_head = audit::record(
+        Some(feed_id),
+        signing_kp.public().as_bytes(),
+        &signed.response,
+    )
+    .await;
+    let audit = audit::sign_head().then(|| AuditHeadSection {
+        seq: audit_head.seq,
+        head: Hex::encode(&audit_head.head),
+        signature: to_signed_response(
+            &signing_kp,
+            audit_head,
+            timestamp_ms,
+            IntentScope::AuditLog,
+            APP_ID,
+        )
+        .signature,
+    });
+    let response = UpdateOracleHttpResponse {
+        signed,
+        result_meta,
+        bls_signature,
+        previous_key_signature,
+        audit,
+        feed_state: Some(feed_state),
+    };
+    // Results of confidential feeds only leave the enclave encrypted, pinned
+    // ones are not updates
+    if !encryption::is_confidential(feed_id) && !pin.is_pinned() {
+        history::record_result(feed_id, &response).await;
+        webhook::notify(feed_id, &response);
+    }
+    Ok(response)
+}
+
+/// Reject empty scripts and scripts over `max_code_bytes` before spawning a thread for them
+fn validate_code(code: &str, max_code_bytes: usize) -> Result<(), EnclaveError> {
+    if code.trim().is_empty() {
+        return Err(EnclaveError::InvalidRequest("code is empty".to_string()));
+    }
+    if code.len() > max_code_bytes {
+        return Err(EnclaveError::InvalidRequest(format!(
+            "code is {} bytes, the limit is {} bytes",
+            code.len(),
+            max_code_bytes
+        )));
+    }
+    Ok(())
 }
 
 /// Execute Rhai code directly without fetching from a blob
 /// This endpoint is useful for testing Rhai scripts before deploying them
+#[utoipa::path(
+    post,
+    path = "/execute_code",
+    request_body = ExecuteCodeRequest,
+    responses(
+        (status = 200, description = "Script result, signed under IntentScope::ExecuteCode", body = ExecuteCodeResponse),
+        (status = 400, description = "Invalid request or code over limits.max_code_bytes", body = openapi::ProblemDetails),
+        (status = 413, description = "Body over the route's body limit", body = openapi::ProblemDetails),
+    )
+)]
 pub async fn execute_code(
-    Json(request): Json<ExecuteCodeRequest>,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    AppJson(request): AppJson<ExecuteCodeRequest>,
 ) -> Result<Json<ExecuteCodeResponse>, EnclaveError> {
-    println!("Executing code with return_type: {:?}", request.return_type);
-    println!("Code: {}", request.code);
+    let requester = auth::identify(&state.config.auth, &headers).map(|key| key.name.clone());
+    call_log::logged("execute_code", requester, None, run_code(&state, request)).await
+}
 
-    // Execute the Rhai code (wrapped in spawn_blocking to avoid blocking async runtime)
-    match execute_rhai_code_async(&request.code, &request.return_type).await {
-        Ok(Some(result)) => Ok(Json(ExecuteCodeResponse {
-            result,
-            success: true,
-            error: None,
-        })),
-        Ok(None) => {
+async fn run_code(
+    state: &AppState,
+    request: ExecuteCodeRequest,
+) -> Result<Json<ExecuteCodeResponse>, EnclaveError> {
+    registration::require(&state.config.registration)?;
+    validate_code(&request.code, state.config.limits.max_code_bytes)?;
+    call_log::note_blob_hash(&Sha256::digest(request.code.as_bytes()).digest);
+    println!("Executing code with return_type: {:?}", request.return_type);
+    // The code itself is not logged, it may embed API keys
+    println!("Code: {} bytes", request.code.len());
+
+    // Execute the Rhai code (on a script worker to avoid blocking the async runtime)
+    match execute_rhai_code_async(&request.code, &request.return_type, request.strict_types).await {
+        Ok(RhaiOutput {
+            result: Some(result),
+            meta,
+        }) => {
+            let timestamp_ms = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_err(|e| {
+                    EnclaveError::GenericError(format!("Failed to get current timestamp: {}", e))
+                })?
+                .as_millis() as u64;
+            let (signing_kp, _) = rotation::signing_keys(state).await;
+            let signed = to_signed_response(
+                &signing_kp,
+                UpdateOracleResponse {
+                    result: Some(result.clone()),
+                },
+                timestamp_ms,
+                IntentScope::ExecuteCode,
+                APP_ID,
+            );
+            audit::record(None, signing_kp.public().as_bytes(), &signed.response).await;
+            call_log::note_result(&signed.response);
+            Ok(Json(ExecuteCodeResponse {
+                result,
+                success: true,
+                error: None,
+                result_meta: (!meta.is_empty()).then_some(meta),
+                signed: Some(signed),
+            }))
+        }
+        Ok(RhaiOutput { result: None, .. }) => {
             Ok(Json(ExecuteCodeResponse {
                 result: ResultValue::STRING("".to_string()), // Default empty result
                 success: false,
                 error: Some("Rhai code execution returned no result".to_string()),
+                result_meta: None,
+                signed: None,
             }))
         }
+        Err(e @ EnclaveError::Overloaded { .. }) => Err(e),
         Err(e) => {
             Ok(Json(ExecuteCodeResponse {
                 result: ResultValue::STRING("".to_string()), // Default empty result
                 success: false,
                 error: Some(e.to_string()),
+                result_meta: None,
+                signed: None,
             }))
         }
     }
@@ -1020,21 +1899,30 @@ mod test {
 
     #[test]
     fn test_execute_rhai_with_http_get() {
-        // Test HTTP GET function (using a simple test URL)
-        // Note: This test requires network access and may fail if the URL is unavailable
+        let http = mock_http::MockHttp::shared();
+        let url = "https://api.example.com/rhai/get";
+        http.respond_json(url, &serde_json::json!({ "price": 42 }));
         let code = r#"
-            let url = "https://httpbin.org/get";
-            let resp = http_get_string(url);
-            if resp.is_err() {
-                "Error: " + resp.err()
+            let body = http_get("https://api.example.com/rhai/get");
+            if body.starts_with("Error") {
+                body
             } else {
-                "Success"
+                parse_json(body).price.to_string()
             }
         "#;
-        let result = execute_rhai_code(code, &ReturnType::STRING);
-        // Should either succeed with "Success" or fail with an error
-        // We just check it doesn't panic
-        assert!(result.is_ok() || result.is_err());
+        let result = execute_rhai_code(code, &ReturnType::STRING).unwrap();
+        assert_eq!(result, Some(ResultValue::STRING("42".to_string())));
+        assert_eq!(http.request_count(url), 1);
+
+        // Upstream errors reach the script as errors
+        http.fail(url, "HTTP error: status 503 Service Unavailable");
+        let result = execute_rhai_code(code, &ReturnType::STRING).unwrap();
+        assert_eq!(
+            result,
+            Some(ResultValue::STRING(
+                "Error: HTTP error: status 503 Service Unavailable".to_string()
+            ))
+        );
     }
 
     #[test]
@@ -1109,13 +1997,10 @@ mod test {
         // Test syntax error
         let code = "invalid syntax {";
         let result = execute_rhai_code(code, &ReturnType::STRING);
-        assert!(result.is_err());
-        assert!(
-            result
-                .unwrap_err()
-                .to_string()
-                .contains("Rhai execution error")
-        );
+        assert!(matches!(
+            result,
+            Err(EnclaveError::ScriptError { line: Some(1), .. })
+        ));
 
         // Test invalid number string
         let code = r#""not a number""#;
@@ -1157,29 +2042,338 @@ mod test {
         assert_eq!(result, Some(ResultValue::STRING("greater".to_string())));
     }
 
+    #[test]
+    fn test_execute_rhai_strict_types() {
+        let strict = |code: &str, return_type: ReturnType| {
+            execute_rhai_code_with_meta(code, &return_type, true).map(|output| output.result)
+        };
+
+        // Values of the expected type pass
+        assert_eq!(
+            strict(r#""abc""#, ReturnType::STRING).unwrap(),
+            Some(ResultValue::STRING("abc".to_string()))
+        );
+        assert_eq!(
+            strict("42", ReturnType::NUMBER).unwrap(),
+            Some(ResultValue::NUMBER(42))
+        );
+        assert_eq!(
+            strict("42.0", ReturnType::NUMBER).unwrap(),
+            Some(ResultValue::NUMBER(42))
+        );
+        assert_eq!(
+            strict("true", ReturnType::BOOLEAN).unwrap(),
+            Some(ResultValue::BOOLEAN(true))
+        );
+        assert_eq!(
+            strict("[1, 2]", ReturnType::VECTOR).unwrap(),
+            Some(ResultValue::VECTOR(vec![1, 2]))
+        );
+
+        // Implicit coercions are rejected and report the actual Rhai type
+        let err = strict(r#""123""#, ReturnType::NUMBER)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("NUMBER") && err.contains("string"), "{}", err);
+        let err = strict("1.5", ReturnType::NUMBER).unwrap_err().to_string();
+        assert!(err.contains("f64"), "{}", err);
+        let err = strict("42", ReturnType::STRING).unwrap_err().to_string();
+        assert!(err.contains("STRING") && err.contains("i64"), "{}", err);
+        assert!(strict(r#""true""#, ReturnType::BOOLEAN).is_err());
+        assert!(strict(r#""test""#, ReturnType::VECTOR).is_err());
+        assert!(strict(r#"["a"]"#, ReturnType::VECTOR).is_err());
+
+        // Lenient mode still reports the type on failure
+        let err = execute_rhai_code("[1]", &ReturnType::BOOLEAN)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("array"), "{}", err);
+    }
+
+    #[test]
+    fn test_execute_rhai_result_meta() {
+        let code = r#"
+            set_source_count(3);
+            set_spread(12.5);
+            set_confidence_interval(95, 105);
+            set_upstream_timestamp(1744038890000);
+            100
+        "#;
+        let output = execute_rhai_code_with_meta(code, &ReturnType::NUMBER, false).unwrap();
+        assert_eq!(output.result, Some(ResultValue::NUMBER(100)));
+        let meta = output.meta.with_staleness(1744038900000);
+        assert_eq!(meta.source_count, Some(3));
+        assert_eq!(meta.decimals, 0);
+        assert_eq!(meta.spread, Some(13));
+        assert_eq!(meta.confidence_low, Some(95));
+        assert_eq!(meta.confidence_high, Some(105));
+        assert_eq!(meta.staleness_ms, Some(10000));
+
+        // Fractions are kept with decimals
+        let code = r#"
+            set_meta_decimals(3);
+            set_spread(0.0125);
+            set_confidence_interval(99.5, 100.25);
+            100
+        "#;
+        let meta = execute_rhai_code_with_meta(code, &ReturnType::NUMBER, false)
+            .unwrap()
+            .meta;
+        assert_eq!(meta.decimals, 3);
+        assert_eq!(meta.spread, Some(13));
+        assert_eq!(meta.confidence_low, Some(99_500));
+        assert_eq!(meta.confidence_high, Some(100_250));
+        let result = execute_rhai_code(
+            "set_spread(1); set_meta_decimals(2); 1",
+            &ReturnType::NUMBER,
+        );
+        assert!(result.is_err());
+        let result = execute_rhai_code("set_meta_decimals(19); 1", &ReturnType::NUMBER);
+        assert!(result.is_err());
+
+        // No setters called -> empty metadata
+        let output = execute_rhai_code_with_meta("1", &ReturnType::NUMBER, false).unwrap();
+        assert!(output.meta.is_empty());
+
+        // Invalid values are rejected
+        let result = execute_rhai_code("set_source_count(-1); 1", &ReturnType::NUMBER);
+        assert!(result.is_err());
+        let result = execute_rhai_code("set_confidence_interval(10, 5); 1", &ReturnType::NUMBER);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_check_update_allowed() {
+        let feed = OracleFeed {
+            id: Address::ZERO,
+            blob_id: "blob".to_string(),
+            extension: CodeExtension::RHAI,
+            result: None,
+            return_type: ReturnType::NUMBER,
+            allow_update_timestamp_ms: 1744038900000,
+            strict_types: false,
+            blob_hash: vec![],
+            publisher_keys: vec![],
+            blob_object_id: None,
+            creator: None,
+        };
+        assert!(check_update_allowed(&feed, 1744038900000).is_ok());
+        assert!(check_update_allowed(&feed, 1744038900001).is_ok());
+        match check_update_allowed(&feed, 1744038899999) {
+            Err(EnclaveError::UpdateTooEarly {
+                earliest_allowed_timestamp_ms,
+                current_timestamp_ms,
+            }) => {
+                assert_eq!(earliest_allowed_timestamp_ms, 1744038900000);
+                assert_eq!(current_timestamp_ms, 1744038899999);
+            }
+            other => panic!("expected UpdateTooEarly, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_verify_blob_hash() {
+        let blob = b"42";
+        let hash = Hex::decode("73475cb40a568e8da8a045ced110137e159f890ac4da883b6b17dc651b3a8049")
+            .unwrap();
+        assert!(verify_blob_hash("blob", &hash, blob).is_ok());
+        let err = verify_blob_hash("blob", &hash, b"43").unwrap_err();
+        assert_eq!(err.code(), "blob_fetch_failed");
+        assert!(err.to_string().contains("Blob hash mismatch"));
+        assert!(verify_blob_hash("blob", &[], blob).is_err());
+
+        // A feed without settings has no hash to check
+        let mut feed = OracleFeed {
+            id: Address::ZERO,
+            blob_id: "blob".to_string(),
+            extension: CodeExtension::RHAI,
+            result: None,
+            return_type: ReturnType::NUMBER,
+            allow_update_timestamp_ms: 0,
+            strict_types: false,
+            blob_hash: vec![],
+            publisher_keys: vec![],
+            blob_object_id: None,
+            creator: None,
+        };
+        assert!(verify_feed_blob(&feed, blob).is_ok());
+        // With settings its hash is always checked
+        feed.creator = Some(Address::ZERO);
+        assert!(verify_feed_blob(&feed, blob).is_err());
+        feed.blob_hash = hash;
+        assert!(verify_feed_blob(&feed, blob).is_ok());
+        assert!(verify_feed_blob(&feed, b"43").is_err());
+    }
+
+    #[test]
+    fn test_is_walrus_blob() {
+        assert!(is_walrus_blob(
+            "0ubhOsk6VdDAuOfWjcrdq5A8rrczLUrNzDL4FxTtJ8Q"
+        ));
+        assert!(!is_walrus_blob("sui://0x5c"));
+        assert!(!is_walrus_blob("builtin://coingecko_price"));
+        // Unknown schemes are not exempt from certification
+        assert!(is_walrus_blob("https://example.com/feed.rhai"));
+        assert!(is_walrus_blob("x://sui://0x5c"));
+    }
+
+    #[test]
+    fn test_parse_feed_id_invalid() {
+        for feed_id in [
+            "",
+            "0x",
+            "not hex",
+            "0xzz",
+            &format!("0x{}", "1".repeat(65)),
+        ] {
+            assert!(matches!(
+                parse_feed_id(feed_id),
+                Err(EnclaveError::InvalidRequest(_))
+            ));
+        }
+        assert!(parse_feed_id(&Address::ZERO.to_string()).is_ok());
+        assert!(parse_feed_id("0x2").is_ok());
+    }
+
+    #[test]
+    fn test_validate_code() {
+        assert!(validate_code("40 + 2", 16).is_ok());
+        assert!(validate_code(" \n", 16).is_err());
+        let err = validate_code(&"1".repeat(17), 16).unwrap_err();
+        assert!(err.to_string().contains("17 bytes, the limit is 16 bytes"));
+    }
+
+    #[test]
+    fn test_decode_oracle_feed_invalid() {
+        let feed_id = Address::ZERO;
+        for bytes in [&[][..], b"not an object"] {
+            let err = decode_oracle_feed(&feed_id, bytes).unwrap_err();
+            assert_eq!(err.code(), "invalid_request");
+            assert!(err.to_string().contains("Failed to deserialize object"));
+        }
+    }
+
+    #[test]
+    fn test_feed_rpc_error() {
+        let feed_id = Address::ZERO;
+        assert!(matches!(
+            feed_rpc_error(&feed_id, "get_object", tonic::Status::not_found("gone")),
+            EnclaveError::FeedNotFound { .. }
+        ));
+        assert!(matches!(
+            feed_rpc_error(
+                &feed_id,
+                "get_object",
+                tonic::Status::deadline_exceeded("slow")
+            ),
+            EnclaveError::UpstreamTimeout { .. }
+        ));
+        let err = feed_rpc_error(&feed_id, "get_object", tonic::Status::unavailable("down"));
+        assert_eq!(err.code(), "rpc_error");
+        assert!(err.to_string().contains("get_object for feed"));
+        assert!(err.to_string().contains("down"));
+    }
+
     #[test]
     fn test_signing_payload() {
         let payload = Option::Some(ResultValue::NUMBER(1));
         let timestamp = 1744038900000;
-        let intent_msg = IntentMessage::new(payload, timestamp, IntentScope::ProcessData);
+        let intent_msg = IntentMessage::new(payload, timestamp, IntentScope::ProcessData, APP_ID);
         let signing_payload = bcs::to_bytes(&intent_msg).expect("should not fail");
         println!("signing_payload: {:?}", Hex::encode(&signing_payload));
-        assert!(Hex::encode(&signing_payload) == "0020b1d11096010000020100000000000000");
+        assert!(
+            Hex::encode(&signing_payload)
+                == "000c636f6575732d6f7261636c6520b1d1109601000001020100000000000000"
+        );
     }
 
     #[test]
     fn test_signature() {
         let payload = Option::Some(ResultValue::NUMBER(1));
         let timestamp = 1744038900000;
-        let intent_msg = IntentMessage::new(payload.clone(), timestamp, IntentScope::ProcessData);
+        let intent_msg =
+            IntentMessage::new(payload.clone(), timestamp, IntentScope::ProcessData, APP_ID);
         let signing_payload = bcs::to_bytes(&intent_msg).expect("should not fail");
         println!("signing_payload: {:?}", Hex::encode(&signing_payload));
-        assert!(Hex::encode(&signing_payload) == "0020b1d1109601000001020100000000000000");
+        assert!(
+            Hex::encode(&signing_payload)
+                == "000c636f6575732d6f7261636c6520b1d1109601000001020100000000000000"
+        );
+
+        // The same result signed by execute_code differs in scope
+        let test_run =
+            IntentMessage::new(payload.clone(), timestamp, IntentScope::ExecuteCode, APP_ID);
+        assert_ne!(bcs::to_bytes(&test_run).unwrap(), signing_payload);
 
-        use fastcrypto::traits::KeyPair;
         let eph_kp = Ed25519KeyPair::generate(&mut rand::thread_rng());
-        let signed_response =
-            to_signed_response(&eph_kp, payload, timestamp, IntentScope::ProcessData);
+        let signed_response = to_signed_response(
+            &eph_kp,
+            payload,
+            timestamp,
+            IntentScope::ProcessData,
+            APP_ID,
+        );
         println!("signature: {:?}", signed_response.signature);
     }
+
+    #[tokio::test]
+    async fn test_execute_rhai_code_async_types() {
+        // Arrays reach the conversion as arrays, not as their string form
+        let output = execute_rhai_code_async("[1, 2, 3]", &ReturnType::VECTOR, true)
+            .await
+            .unwrap();
+        assert_eq!(output.result, Some(ResultValue::VECTOR(vec![1, 2, 3])));
+        let output = execute_rhai_code_async("2.0 * 21", &ReturnType::NUMBER, true)
+            .await
+            .unwrap();
+        assert_eq!(output.result, Some(ResultValue::NUMBER(42)));
+        assert!(
+            execute_rhai_code_async("\"42\"", &ReturnType::NUMBER, true)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_process_data_offline() {
+        use crate::config::{AppConfig, SuiConfig};
+        use crate::mock_chain::MockChain;
+        use crate::sui_pool::SuiPool;
+
+        // Feed running `40 + 2` from an on-chain Script, see fixtures/feed.json
+        let chain = MockChain::from_fixtures(include_str!("fixtures/feed.json")).unwrap();
+        let state = Arc::new(AppState {
+            eph_kp: Ed25519KeyPair::generate(&mut rand::thread_rng()),
+            sui: Arc::new(SuiPool::new(&SuiConfig::default()).unwrap()),
+            chain: Arc::new(chain),
+            config: AppConfig::default(),
+        });
+        let feed_id = "0x00000000000000000000000000000000000000000000000000000000000000f1";
+        let request: UpdateOracleRequest =
+            serde_json::from_value(serde_json::json!({ "feed_id": feed_id })).unwrap();
+        let Json(output) = process_data(State(state.clone()), HeaderMap::new(), AppJson(request))
+            .await
+            .unwrap();
+        let ResultOutput::Plain(response) = output else {
+            panic!("Result encrypted without a recipient");
+        };
+        assert_eq!(
+            response.signed.response.data.result,
+            Some(ResultValue::NUMBER(42))
+        );
+        let feed_state = response.feed_state.unwrap();
+        assert_eq!((feed_state.version, feed_state.checkpoint), (7, Some(1000)));
+
+        // Feeds missing from the chain are not found
+        let request: UpdateOracleRequest =
+            serde_json::from_value(serde_json::json!({ "feed_id": "0xf2" })).unwrap();
+        let error = process_data(State(state), HeaderMap::new(), AppJson(request))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, EnclaveError::FeedNotFound { .. }),
+            "{:?}",
+            error
+        );
+    }
 }
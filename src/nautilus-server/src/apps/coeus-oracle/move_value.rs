@@ -0,0 +1,497 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Move objects decoded into Rhai values, so scripts read fields by name
+//! instead of parsing BCS:
+//!
+//! ```rhai
+//! let pool = sui_object("0x...");
+//! pool.fields.reserve_x * 1000000 / pool.fields.reserve_y
+//! ```
+//!
+//! `sui_object(id)` returns `#{id, version, type, fields}` for the latest
+//! version of a Move object. The layout of its type is read from the package
+//! that defines it (GetDatatype) and cached, packages being immutable. A script
+//! makes at most `dry_run.max_object_reads` calls.
+//!
+//! Integers up to i64::MAX are Rhai integers, larger u64, u128 and u256 are
+//! decimal strings. Addresses, UIDs and IDs are 0x strings, Strings are
+//! strings, an Option is its value or `()`, a Balance is its value, a struct is
+//! a map of its fields and an enum a map of its fields plus `variant`.
+
+use super::blob_cache::LruCache;
+use super::config::COEUS_CONFIG;
+use super::dry_run::on_server;
+use super::onchain;
+use crate::{AppState, EnclaveError};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use sui_rpc::proto::sui::rpc::v2::datatype_descriptor::DatatypeKind;
+use sui_rpc::proto::sui::rpc::v2::open_signature_body::Type as BodyType;
+use sui_rpc::proto::sui::rpc::v2::{
+    DatatypeDescriptor, FieldDescriptor, GetDatatypeRequest, OpenSignatureBody,
+};
+use sui_sdk_types::{Address, StructTag, TypeTag};
+
+/// Nesting of types resolved for one object, deeper layouts are refused.
+const MAX_DEPTH: usize = 16;
+
+/// Datatypes kept in the cache, the least recently used are evicted.
+const DATATYPE_CAPACITY: usize = 1024;
+
+lazy_static::lazy_static! {
+    /// Datatypes read from their package, by `<package>::<module>::<name>`.
+    static ref DATATYPES: Mutex<LruCache<String, Arc<DatatypeDescriptor>>> =
+        Mutex::new(LruCache::new(DATATYPE_CAPACITY));
+}
+
+/// How a Move value is laid out in BCS.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MoveLayout {
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    U256,
+    Address,
+    Vector(Box<MoveLayout>),
+    /// 0x1::string::String and 0x1::ascii::String.
+    String,
+    /// 0x1::option::Option<T>.
+    Option(Box<MoveLayout>),
+    Struct(Vec<(String, MoveLayout)>),
+    /// Variants with their fields, in declaration order.
+    Enum(Vec<(String, Vec<(String, MoveLayout)>)>),
+}
+
+type LayoutFuture<'a> = Pin<Box<dyn Future<Output = Result<MoveLayout, EnclaveError>> + Send + 'a>>;
+
+fn layout_error(message: impl Into<String>) -> EnclaveError {
+    EnclaveError::GenericError(message.into())
+}
+
+/// Datatype `tag` (without its type arguments), from the cache or its package.
+async fn datatype(
+    state: &AppState,
+    tag: &StructTag,
+) -> Result<Arc<DatatypeDescriptor>, EnclaveError> {
+    let key = format!("{}::{}::{}", tag.address(), tag.module(), tag.name());
+    if let Some(datatype) = DATATYPES.lock().expect("datatype cache poisoned").get(&key) {
+        return Ok(datatype);
+    }
+    let mut request = GetDatatypeRequest::default();
+    request.package_id = Some(tag.address().to_string());
+    request.module_name = Some(tag.module().to_string());
+    request.name = Some(tag.name().to_string());
+    let datatype = state
+        .sui
+        .call("get_datatype", |mut client| {
+            let request = request.clone();
+            async move { client.package_client().get_datatype(request).await }
+        })
+        .await
+        .map_err(|e| {
+            EnclaveError::RpcError(format!("Failed to get type {}: {}", key, e.message()))
+        })?
+        .into_inner()
+        .datatype
+        .ok_or_else(|| EnclaveError::RpcError(format!("No datatype {} returned", key)))?;
+    let datatype = Arc::new(datatype);
+    DATATYPES
+        .lock()
+        .expect("datatype cache poisoned")
+        .insert(key, datatype.clone());
+    Ok(datatype)
+}
+
+/// Concrete type of a field, its type parameters replaced by `type_args`.
+fn instantiate(body: &OpenSignatureBody, type_args: &[TypeTag]) -> Result<TypeTag, EnclaveError> {
+    let tag = match body.r#type() {
+        BodyType::Address => TypeTag::Address,
+        BodyType::Bool => TypeTag::Bool,
+        BodyType::U8 => TypeTag::U8,
+        BodyType::U16 => TypeTag::U16,
+        BodyType::U32 => TypeTag::U32,
+        BodyType::U64 => TypeTag::U64,
+        BodyType::U128 => TypeTag::U128,
+        BodyType::U256 => TypeTag::U256,
+        BodyType::Vector => {
+            let element = body
+                .type_parameter_instantiation
+                .first()
+                .ok_or_else(|| layout_error("Vector without an element type"))?;
+            TypeTag::Vector(Box::new(instantiate(element, type_args)?))
+        }
+        BodyType::TypeParameter => {
+            let index = body.type_parameter.unwrap_or_default() as usize;
+            type_args
+                .get(index)
+                .cloned()
+                .ok_or_else(|| layout_error(format!("No type argument {}", index)))?
+        }
+        BodyType::Datatype => {
+            let name = body
+                .type_name
+                .as_deref()
+                .ok_or_else(|| layout_error("Datatype without a name"))?;
+            let params = body
+                .type_parameter_instantiation
+                .iter()
+                .map(|param| instantiate(param, type_args).map(|tag| tag.to_string()))
+                .collect::<Result<Vec<_>, _>>()?;
+            let tag = if params.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}<{}>", name, params.join(", "))
+            };
+            let tag: StructTag = tag
+                .parse()
+                .map_err(|e| layout_error(format!("Invalid type {}: {:?}", tag, e)))?;
+            TypeTag::Struct(Box::new(tag))
+        }
+        other => return Err(layout_error(format!("Unsupported field type {:?}", other))),
+    };
+    Ok(tag)
+}
+
+fn is_type(tag: &StructTag, address: &str, module: &str, name: &str) -> bool {
+    Address::from_hex(address).is_ok_and(|address| *tag.address() == address)
+        && tag.module().as_str() == module
+        && tag.name().as_str() == name
+}
+
+fn fields_layout<'a>(
+    state: &'a AppState,
+    fields: &'a [FieldDescriptor],
+    type_args: &'a [TypeTag],
+    depth: usize,
+) -> Pin<Box<dyn Future<Output = Result<Vec<(String, MoveLayout)>, EnclaveError>> + Send + 'a>> {
+    Box::pin(async move {
+        let mut fields: Vec<_> = fields.iter().collect();
+        fields.sort_by_key(|field| field.position.unwrap_or_default());
+        let mut layouts = Vec::with_capacity(fields.len());
+        for field in fields {
+            let body = field
+                .r#type
+                .as_ref()
+                .ok_or_else(|| layout_error("Field without a type"))?;
+            let tag = instantiate(body, type_args)?;
+            let name = field.name.clone().unwrap_or_default();
+            layouts.push((name, layout(state, &tag, depth + 1).await?));
+        }
+        Ok(layouts)
+    })
+}
+
+/// Layout of values of type `tag`.
+pub fn layout<'a>(state: &'a AppState, tag: &'a TypeTag, depth: usize) -> LayoutFuture<'a> {
+    Box::pin(async move {
+        if depth > MAX_DEPTH {
+            return Err(layout_error(format!("Type {} is nested too deep", tag)));
+        }
+        let tag = match tag {
+            TypeTag::Bool => return Ok(MoveLayout::Bool),
+            TypeTag::U8 => return Ok(MoveLayout::U8),
+            TypeTag::U16 => return Ok(MoveLayout::U16),
+            TypeTag::U32 => return Ok(MoveLayout::U32),
+            TypeTag::U64 => return Ok(MoveLayout::U64),
+            TypeTag::U128 => return Ok(MoveLayout::U128),
+            TypeTag::U256 => return Ok(MoveLayout::U256),
+            TypeTag::Address | TypeTag::Signer => return Ok(MoveLayout::Address),
+            TypeTag::Vector(element) => {
+                let element = layout(state, element, depth + 1).await?;
+                return Ok(MoveLayout::Vector(Box::new(element)));
+            }
+            TypeTag::Struct(tag) => tag,
+        };
+        if is_type(tag, "0x1", "string", "String") || is_type(tag, "0x1", "ascii", "String") {
+            return Ok(MoveLayout::String);
+        }
+        if is_type(tag, "0x2", "object", "UID") || is_type(tag, "0x2", "object", "ID") {
+            return Ok(MoveLayout::Address);
+        }
+        if is_type(tag, "0x2", "balance", "Balance") {
+            return Ok(MoveLayout::U64);
+        }
+        if is_type(tag, "0x1", "option", "Option") {
+            let value = tag
+                .type_params()
+                .first()
+                .ok_or_else(|| layout_error("Option without a type argument"))?;
+            let value = layout(state, value, depth + 1).await?;
+            return Ok(MoveLayout::Option(Box::new(value)));
+        }
+
+        let datatype = datatype(state, tag).await?;
+        let type_args = tag.type_params();
+        match datatype.kind() {
+            DatatypeKind::Enum => {
+                let mut variants: Vec<_> = datatype.variants.iter().collect();
+                variants.sort_by_key(|variant| variant.position.unwrap_or_default());
+                let mut layouts = Vec::with_capacity(variants.len());
+                for variant in variants {
+                    let fields = fields_layout(state, &variant.fields, type_args, depth).await?;
+                    layouts.push((variant.name.clone().unwrap_or_default(), fields));
+                }
+                Ok(MoveLayout::Enum(layouts))
+            }
+            _ => {
+                let fields = fields_layout(state, &datatype.fields, type_args, depth).await?;
+                Ok(MoveLayout::Struct(fields))
+            }
+        }
+    })
+}
+
+/// BCS bytes being decoded.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < count {
+            return Err("Move value ends early".to_string());
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn uleb128(&mut self) -> Result<usize, String> {
+        let mut value: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return usize::try_from(value).map_err(|e| e.to_string());
+            }
+        }
+        Err("Invalid length in Move value".to_string())
+    }
+
+    fn fixed<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().expect("N bytes taken"))
+    }
+}
+
+/// Decimal digits of a little-endian unsigned integer.
+fn decimal(mut le_bytes: Vec<u8>) -> String {
+    let mut digits = Vec::new();
+    while le_bytes.iter().any(|byte| *byte != 0) {
+        let mut remainder = 0u32;
+        for byte in le_bytes.iter_mut().rev() {
+            let value = (remainder << 8) | u32::from(*byte);
+            *byte = (value / 10) as u8;
+            remainder = value % 10;
+        }
+        digits.push(b'0' + remainder as u8);
+    }
+    if digits.is_empty() {
+        return "0".to_string();
+    }
+    digits.reverse();
+    String::from_utf8(digits).expect("ASCII digits")
+}
+
+fn decode_fields(fields: &[(String, MoveLayout)], reader: &mut Reader) -> Result<Map, String> {
+    let mut map = Map::new();
+    for (name, layout) in fields {
+        map.insert(name.as_str().into(), decode_value(layout, reader)?);
+    }
+    Ok(map)
+}
+
+fn decode_value(layout: &MoveLayout, reader: &mut Reader) -> Result<Dynamic, String> {
+    let value = match layout {
+        MoveLayout::Bool => match reader.take(1)?[0] {
+            0 => Dynamic::FALSE,
+            1 => Dynamic::TRUE,
+            other => return Err(format!("Invalid bool {}", other)),
+        },
+        MoveLayout::U8 => Dynamic::from_int(reader.take(1)?[0].into()),
+        MoveLayout::U16 => Dynamic::from_int(u16::from_le_bytes(reader.fixed()?).into()),
+        MoveLayout::U32 => Dynamic::from_int(u32::from_le_bytes(reader.fixed()?).into()),
+        MoveLayout::U64 => {
+            let value = u64::from_le_bytes(reader.fixed()?);
+            match i64::try_from(value) {
+                Ok(value) => Dynamic::from_int(value),
+                Err(_) => Dynamic::from(value.to_string()),
+            }
+        }
+        MoveLayout::U128 => Dynamic::from(decimal(reader.take(16)?.to_vec())),
+        MoveLayout::U256 => Dynamic::from(decimal(reader.take(32)?.to_vec())),
+        MoveLayout::Address => {
+            let address: [u8; Address::LENGTH] = reader.fixed()?;
+            Dynamic::from(Address::new(address).to_string())
+        }
+        MoveLayout::String => {
+            let length = reader.uleb128()?;
+            let bytes = reader.take(length)?;
+            let string =
+                std::str::from_utf8(bytes).map_err(|e| format!("Invalid string: {}", e))?;
+            Dynamic::from(string.to_string())
+        }
+        MoveLayout::Vector(element) => {
+            let length = reader.uleb128()?;
+            let mut values = Array::new();
+            for _ in 0..length {
+                values.push(decode_value(element, reader)?);
+            }
+            Dynamic::from_array(values)
+        }
+        MoveLayout::Option(value) => match reader.uleb128()? {
+            0 => Dynamic::UNIT,
+            1 => decode_value(value, reader)?,
+            other => return Err(format!("Invalid Option of {} values", other)),
+        },
+        MoveLayout::Struct(fields) => Dynamic::from_map(decode_fields(fields, reader)?),
+        MoveLayout::Enum(variants) => {
+            let index = reader.uleb128()?;
+            let (name, fields) = variants
+                .get(index)
+                .ok_or_else(|| format!("Invalid enum variant {}", index))?;
+            let mut map = decode_fields(fields, reader)?;
+            map.insert("variant".into(), Dynamic::from(name.clone()));
+            Dynamic::from_map(map)
+        }
+    };
+    Ok(value)
+}
+
+/// Decode the BCS of a Move value, all of it.
+pub fn decode(layout: &MoveLayout, bytes: &[u8]) -> Result<Dynamic, String> {
+    let mut reader = Reader { bytes };
+    let value = decode_value(layout, &mut reader)?;
+    if !reader.bytes.is_empty() {
+        return Err(format!(
+            "{} bytes left after the Move value",
+            reader.bytes.len()
+        ));
+    }
+    Ok(value)
+}
+
+/// Layout of the latest version of Move object `id`, with its contents.
+async fn read_object(
+    state: Arc<AppState>,
+    id: Address,
+) -> Result<(Map, MoveLayout, Vec<u8>), EnclaveError> {
+    let object = onchain::get_object(state.chain.as_ref(), &id).await?;
+    let move_object = object
+        .as_struct()
+        .ok_or_else(|| layout_error(format!("Object {} is not a Move object", id)))?;
+    let object_type = TypeTag::Struct(Box::new(move_object.object_type().clone()));
+    let layout = layout(&state, &object_type, 0).await?;
+    let mut map = Map::new();
+    map.insert("id".into(), Dynamic::from(id.to_string()));
+    map.insert(
+        "version".into(),
+        Dynamic::from(object.version().to_string()),
+    );
+    map.insert("type".into(), Dynamic::from(object_type.to_string()));
+    Ok((map, layout, move_object.contents().to_vec()))
+}
+
+/// `sui_object`: latest version of a Move object with its fields decoded.
+fn sui_object(reads: &AtomicU32, id: &str) -> Result<Map, String> {
+    let max_reads = COEUS_CONFIG.dry_run.max_object_reads;
+    if reads.fetch_add(1, Ordering::Relaxed) >= max_reads {
+        return Err(format!(
+            "A script may read at most {} objects with sui_object",
+            max_reads
+        ));
+    }
+    let id = Address::from_hex(id).map_err(|e| format!("Invalid object id {}: {}", id, e))?;
+    let (mut map, layout, contents) = on_server(move |state| read_object(state, id))?;
+    map.insert("fields".into(), decode(&layout, &contents)?);
+    Ok(map)
+}
+
+/// Register `sui_object`. Reads are counted per engine, so per script
+/// execution.
+pub fn register_move_value_fns(engine: &mut Engine) {
+    let reads = Arc::new(AtomicU32::new(0));
+    engine.register_fn(
+        "sui_object",
+        move |id: &str| -> Result<Map, Box<EvalAltResult>> { Ok(sui_object(&reads, id)?) },
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        // struct Pool { id: UID, reserve_x: u64, fee: Option<u128>, name: String, side: Side }
+        // enum Side { Bid, Ask { size: u8 } }
+        let layout = MoveLayout::Struct(vec![
+            ("id".to_string(), MoveLayout::Address),
+            ("reserve_x".to_string(), MoveLayout::U64),
+            (
+                "fee".to_string(),
+                MoveLayout::Option(Box::new(MoveLayout::U128)),
+            ),
+            ("name".to_string(), MoveLayout::String),
+            (
+                "side".to_string(),
+                MoveLayout::Enum(vec![
+                    ("Bid".to_string(), vec![]),
+                    (
+                        "Ask".to_string(),
+                        vec![("size".to_string(), MoveLayout::U8)],
+                    ),
+                ]),
+            ),
+        ]);
+        let id = Address::from_hex("0x5").unwrap();
+        let mut bytes = bcs::to_bytes(&(id, u64::MAX, Some(1_000u128), "SUI/USDC")).unwrap();
+        bytes.extend([1, 7]);
+
+        let pool = decode(&layout, &bytes).unwrap().cast::<Map>();
+        assert_eq!(pool["id"].clone().cast::<String>(), id.to_string());
+        // Past i64::MAX as a decimal string
+        assert_eq!(
+            pool["reserve_x"].clone().cast::<String>(),
+            u64::MAX.to_string()
+        );
+        assert_eq!(pool["fee"].clone().cast::<String>(), "1000");
+        assert_eq!(pool["name"].clone().cast::<String>(), "SUI/USDC");
+        let side = pool["side"].clone().cast::<Map>();
+        assert_eq!(side["variant"].clone().cast::<String>(), "Ask");
+        assert_eq!(side["size"].as_int().unwrap(), 7);
+
+        // Truncated or trailing bytes
+        assert!(decode(&layout, &bytes[..bytes.len() - 1]).is_err());
+        bytes.push(0);
+        assert!(decode(&layout, &bytes).is_err());
+
+        assert_eq!(decimal(vec![0; 32]), "0");
+        assert_eq!(
+            decimal(u128::MAX.to_le_bytes().to_vec()),
+            u128::MAX.to_string()
+        );
+    }
+
+    #[test]
+    fn test_object_reads() {
+        let reads = AtomicU32::new(COEUS_CONFIG.dry_run.max_object_reads);
+        let error = sui_object(&reads, "0x5").unwrap_err();
+        assert!(error.contains("at most"), "{}", error);
+
+        let mut cache = DATATYPES.lock().unwrap();
+        for i in 0..=DATATYPE_CAPACITY {
+            cache.insert(
+                format!("0x{:x}::m::T", i),
+                Arc::new(DatatypeDescriptor::default()),
+            );
+        }
+        assert_eq!(cache.len(), DATATYPE_CAPACITY);
+    }
+}
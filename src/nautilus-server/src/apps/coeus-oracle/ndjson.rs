@@ -0,0 +1,91 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Newline delimited JSON responses, for endpoints returning many items
+//! (/process_batch, /feeds/{id}/history).
+//!
+//! A caller sending `Accept: application/x-ndjson` gets one JSON document per
+//! line, written to a chunked body as the items are produced, instead of a
+//! JSON array serialized in full before the first byte is sent. Without it the
+//! endpoints answer with the array, as before. NDJSON responses are never
+//! compressed, the encoder would hold lines back until its buffer fills.
+
+use axum::body::{Body, Bytes};
+use axum::http::{HeaderMap, HeaderValue, header};
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use std::convert::Infallible;
+use tokio_stream::{Stream, StreamExt};
+use tracing::warn;
+
+pub const NDJSON: &str = "application/x-ndjson";
+
+/// Whether the caller asked for an NDJSON response.
+pub fn accepts_ndjson(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_type| {
+            media_type
+                .split(';')
+                .next()
+                .is_some_and(|media_type| media_type.trim().eq_ignore_ascii_case(NDJSON))
+        })
+}
+
+/// One line of the response: `item` as JSON followed by a newline.
+fn line<T: Serialize>(item: &T) -> Option<Bytes> {
+    match serde_json::to_vec(item) {
+        Ok(mut line) => {
+            line.push(b'\n');
+            Some(line.into())
+        }
+        Err(e) => {
+            // Only reachable with a non-string map key, skipped rather than cutting the stream
+            warn!("Failed to serialize NDJSON line: {}", e);
+            None
+        }
+    }
+}
+
+/// Response streaming each item of `items` as a line.
+pub fn stream<T, S>(items: S) -> Response
+where
+    T: Serialize,
+    S: Stream<Item = T> + Send + 'static,
+{
+    let lines = items.filter_map(|item| line(&item).map(Ok::<_, Infallible>));
+    let mut response = Body::from_stream(lines).into_response();
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(NDJSON));
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ndjson() {
+        let mut headers = HeaderMap::new();
+        assert!(!accepts_ndjson(&headers));
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("application/json, application/x-ndjson;q=0.9"),
+        );
+        assert!(accepts_ndjson(&headers));
+
+        let response = stream(tokio_stream::iter(vec![
+            serde_json::json!({ "a": 1 }),
+            serde_json::json!("b"),
+        ]));
+        assert_eq!(response.headers()[header::CONTENT_TYPE], NDJSON);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"{\"a\":1}\n\"b\"\n");
+    }
+}
@@ -0,0 +1,218 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Feed code stored in an `oracle::script::Script` object, referenced by a
+//! `blob_id` of the form `sui://<object id>`. The code is the object's inline
+//! `code` followed by its dynamic field chunks, read through the chain reader.
+//! The chunks are fetched together in one batch, so a script costs two round
+//! trips whatever its size.
+//!
+//! The object must be a `script::Script` of the oracle package (see
+//! `feed_object.script_type`), so an object of another package with the same
+//! layout is not run as feed code, and each chunk a
+//! `dynamic_field::Field<u64, vector<u8>>`.
+
+use super::config::COEUS_CONFIG;
+use crate::EnclaveError;
+use crate::chain_reader::ChainReader;
+use fastcrypto::hash::{Blake2b256, HashFunction};
+use serde::Deserialize;
+use sui_sdk_types::{Address, Object, StructTag, TypeTag};
+
+/// Blob id prefix selecting an on-chain Script object as the code source.
+pub const SCHEME: &str = "sui://";
+
+/// Hashing intent of dynamic field object ids.
+const CHILD_OBJECT_ID_SCOPE: u8 = 0xf0;
+
+/// BCS layout of oracle::script::Script
+#[derive(Debug, Deserialize)]
+struct Script {
+    #[allow(dead_code)]
+    id: Address,
+    code: Vec<u8>,
+    chunk_count: u64,
+}
+
+/// Type of the dynamic fields holding the chunks of a Script.
+const CHUNK_FIELD_TYPE: &str = "0x2::dynamic_field::Field<u64, vector<u8>>";
+
+/// BCS layout of sui::dynamic_field::Field<u64, vector<u8>>
+#[derive(Debug, Deserialize)]
+struct ChunkField {
+    #[allow(dead_code)]
+    id: Address,
+    name: u64,
+    value: Vec<u8>,
+}
+
+/// Object id of the dynamic field `key` of type `key_type` under `parent`.
+pub fn dynamic_field_id(
+    parent: &Address,
+    key_type: &TypeTag,
+    key: &[u8],
+) -> Result<Address, EnclaveError> {
+    let type_bytes = bcs::to_bytes(key_type)
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to encode type tag: {}", e)))?;
+    let mut hasher = Blake2b256::default();
+    hasher.update([CHILD_OBJECT_ID_SCOPE]);
+    hasher.update(parent.as_ref());
+    hasher.update((key.len() as u64).to_le_bytes());
+    hasher.update(key);
+    hasher.update(type_bytes);
+    Ok(Address::new(hasher.finalize().digest))
+}
+
+fn deserialize(id: &Address, bcs_bytes: &[u8]) -> Result<Object, EnclaveError> {
+    bcs::from_bytes(bcs_bytes).map_err(|e| {
+        EnclaveError::GenericError(format!("Failed to deserialize object {}: {}", id, e))
+    })
+}
+
+/// Object `id` at its latest version.
+pub async fn get_object(chain: &dyn ChainReader, id: &Address) -> Result<Object, EnclaveError> {
+    let read = chain.get_object(*id, None).await.map_err(|e| {
+        EnclaveError::RpcError(format!("Failed to get object {}: {}", id, e.message()))
+    })?;
+    deserialize(id, &read.bcs)
+}
+
+/// Objects `ids` at their latest version, in the same order, read in batches
+/// rather than one round trip each.
+pub async fn get_objects(
+    chain: &dyn ChainReader,
+    ids: &[Address],
+) -> Result<Vec<Object>, EnclaveError> {
+    let objects = chain.get_objects(ids).await.map_err(|e| {
+        EnclaveError::RpcError(format!(
+            "Failed to get {} objects: {}",
+            ids.len(),
+            e.message()
+        ))
+    })?;
+    ids.iter()
+        .zip(&objects)
+        .map(|(id, bcs_bytes)| deserialize(id, bcs_bytes))
+        .collect()
+}
+
+/// Contents of a Move object already read.
+pub fn move_contents(id: &Address, object: &Object) -> Result<Vec<u8>, EnclaveError> {
+    object
+        .as_struct()
+        .map(|move_object| move_object.contents().to_vec())
+        .ok_or_else(|| EnclaveError::GenericError(format!("Object {} is not a Move object", id)))
+}
+
+/// Contents of a Move object of type `expected`.
+pub fn typed_contents(
+    id: &Address,
+    object: &Object,
+    expected: &StructTag,
+) -> Result<Vec<u8>, EnclaveError> {
+    let move_object = object
+        .as_struct()
+        .ok_or_else(|| EnclaveError::GenericError(format!("Object {} is not a Move object", id)))?;
+    if move_object.object_type() != expected {
+        return Err(EnclaveError::GenericError(format!(
+            "Object {} is a {}, not a {}",
+            id,
+            move_object.object_type(),
+            expected
+        )));
+    }
+    Ok(move_object.contents().to_vec())
+}
+
+/// Contents of a Move object.
+pub async fn object_contents(
+    chain: &dyn ChainReader,
+    id: &Address,
+) -> Result<Vec<u8>, EnclaveError> {
+    move_contents(id, &get_object(chain, id).await?)
+}
+
+fn chunk_field_type() -> StructTag {
+    CHUNK_FIELD_TYPE
+        .parse()
+        .expect("chunk field type is a valid struct tag")
+}
+
+/// Read and reassemble the code of a Script object.
+pub async fn fetch(chain: &dyn ChainReader, script_id: &str) -> Result<Vec<u8>, EnclaveError> {
+    let script_id = Address::from_hex(script_id).map_err(|e| {
+        EnclaveError::GenericError(format!("Invalid script object id {}: {}", script_id, e))
+    })?;
+    let script_type = COEUS_CONFIG.feed_object.script_type()?;
+    let object = get_object(chain, &script_id).await?;
+    let script: Script = bcs::from_bytes(&typed_contents(&script_id, &object, &script_type)?)
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to deserialize Script: {}", e)))?;
+
+    let field_ids = (0..script.chunk_count)
+        .map(|index| dynamic_field_id(&script_id, &TypeTag::U64, &index.to_le_bytes()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let fields = get_objects(chain, &field_ids).await?;
+    let chunk_type = chunk_field_type();
+
+    let mut code = script.code;
+    for ((index, field_id), field) in (0..script.chunk_count).zip(&field_ids).zip(&fields) {
+        let contents = typed_contents(field_id, field, &chunk_type)?;
+        let chunk: ChunkField = bcs::from_bytes(&contents).map_err(|e| {
+            EnclaveError::GenericError(format!("Failed to deserialize chunk {}: {}", index, e))
+        })?;
+        if chunk.name != index {
+            return Err(EnclaveError::GenericError(format!(
+                "Chunk {} of script {} has index {}",
+                index, script_id, chunk.name
+            )));
+        }
+        code.extend_from_slice(&chunk.value);
+    }
+    Ok(code)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dynamic_field_id() {
+        let parent = Address::from_hex("0x2").unwrap();
+        let first = dynamic_field_id(&parent, &TypeTag::U64, &0u64.to_le_bytes()).unwrap();
+        let second = dynamic_field_id(&parent, &TypeTag::U64, &1u64.to_le_bytes()).unwrap();
+        assert_ne!(first, second);
+        // The key type is part of the derivation
+        let other_type = dynamic_field_id(&parent, &TypeTag::U8, &[0]).unwrap();
+        assert_ne!(first, other_type);
+    }
+
+    #[test]
+    fn test_typed_contents() {
+        use sui_sdk_types::{Digest, MoveStruct, ObjectData, Owner};
+
+        let id = Address::from_hex("0x5c").unwrap();
+        let script_type: StructTag = "0x1::script::Script".parse().unwrap();
+        let object = |object_type: &StructTag| {
+            let move_struct = MoveStruct::new(object_type.clone(), true, 3, vec![7; 40]).unwrap();
+            Object::new(
+                ObjectData::Struct(move_struct),
+                Owner::Immutable,
+                Digest::ZERO,
+                0,
+            )
+        };
+        assert_eq!(
+            typed_contents(&id, &object(&script_type), &script_type).unwrap(),
+            vec![7; 40]
+        );
+        // Same layout from another package
+        let other_type: StructTag = "0x2::script::Script".parse().unwrap();
+        assert!(typed_contents(&id, &object(&other_type), &script_type).is_err());
+
+        // Chunks must hold bytes
+        let chunk_type = chunk_field_type();
+        assert!(typed_contents(&id, &object(&chunk_type), &chunk_type).is_ok());
+        let other_chunk: StructTag = "0x2::dynamic_field::Field<u64, u64>".parse().unwrap();
+        assert!(typed_contents(&id, &object(&other_chunk), &chunk_type).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! OpenAPI description of the oracle endpoints, served as GET /openapi.json,
+//! and a Swagger UI page at GET /docs.
+//!
+//! The UI assets are loaded by the browser from a CDN rather than compiled
+//! into the server, so the enclave build does not download them. Their URLs
+//! are pinned to an exact version in the `docs` section of coeus_config.yaml
+//! together with their Subresource Integrity hashes, so the browser refuses
+//! assets the CDN changed. Without both hashes /docs only links to
+//! /openapi.json.
+
+use super::audit::AuditHeadSection;
+use super::batch::{BatchEntry, BatchError, BatchRequest, BatchResponse};
+use super::config::COEUS_CONFIG;
+use super::encryption::{EncryptedResult, ResultOutput};
+use super::feed_state::FeedStateSection;
+use super::{
+    ExecuteCodeRequest, ExecuteCodeResponse, ResultMeta, ResultMetaSection, ResultValue,
+    ReturnType, UpdateOracleHttpResponse, UpdateOracleRequest, UpdateOracleResponse,
+};
+use axum::Json;
+use axum::response::Html;
+use serde::{Deserialize, Serialize};
+use utoipa::{OpenApi, ToSchema};
+
+/// IntentMessage<UpdateOracleResponse>, the BCS encoded message that is
+/// signed (see payload_codec.rs).
+#[derive(Serialize, ToSchema)]
+pub struct SignedIntentMessage {
+    /// IntentScope: 0 ProcessData, 3 ExecuteCode.
+    pub intent: u8,
+    pub app_id: String,
+    pub timestamp_ms: u64,
+    pub data: UpdateOracleResponse,
+}
+
+/// Intent message and the hex Ed25519 signature over it.
+#[derive(Serialize, ToSchema)]
+pub struct SignedUpdateOracleResponse {
+    pub response: SignedIntentMessage,
+    pub signature: String,
+}
+
+/// Body of every error response, RFC 7807 problem details served as
+/// application/problem+json. Variants add their fields as extra members,
+/// e.g. `retry_after_ms` or `feed_id`.
+#[derive(Serialize, ToSchema)]
+pub struct ProblemDetails {
+    /// `urn:nautilus:error:<code>`.
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    /// Stable error code, e.g. feed_not_found, script_error, rpc_error.
+    pub code: String,
+    /// Id of the failed request, also sent as X-Request-Id.
+    pub request_id: Option<String>,
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Coeus oracle enclave API"),
+    paths(super::process_data, super::batch::process_batch, super::execute_code),
+    components(schemas(
+        UpdateOracleRequest,
+        UpdateOracleResponse,
+        UpdateOracleHttpResponse,
+        SignedUpdateOracleResponse,
+        SignedIntentMessage,
+        ResultOutput,
+        EncryptedResult,
+        ResultValue,
+        ResultMeta,
+        ResultMetaSection,
+        AuditHeadSection,
+        FeedStateSection,
+        BatchRequest,
+        BatchResponse,
+        BatchEntry,
+        BatchError,
+        ExecuteCodeRequest,
+        ExecuteCodeResponse,
+        ReturnType,
+        ProblemDetails,
+    ))
+)]
+pub struct ApiDoc;
+
+/// Endpoint serving the OpenAPI document.
+pub async fn get_openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Docs section of coeus_config.yaml: the Swagger UI assets of /docs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocsConfig {
+    /// Stylesheet URL, pinned to an exact version.
+    #[serde(default = "default_css_url")]
+    pub css_url: String,
+    /// Subresource Integrity hash of the stylesheet, e.g. "sha384-...".
+    #[serde(default)]
+    pub css_integrity: String,
+    /// Script URL, pinned to the same version.
+    #[serde(default = "default_js_url")]
+    pub js_url: String,
+    /// Subresource Integrity hash of the script.
+    #[serde(default)]
+    pub js_integrity: String,
+}
+
+impl Default for DocsConfig {
+    fn default() -> Self {
+        Self {
+            css_url: default_css_url(),
+            css_integrity: String::new(),
+            js_url: default_js_url(),
+            js_integrity: String::new(),
+        }
+    }
+}
+
+fn default_css_url() -> String {
+    "https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui.css".to_string()
+}
+
+fn default_js_url() -> String {
+    "https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui-bundle.js".to_string()
+}
+
+/// Page shown while the assets have no integrity hashes.
+const NO_UI: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+  <title>Coeus oracle API</title>
+</head>
+<body>
+  <p>The API description is served at <a href="/openapi.json">/openapi.json</a>.</p>
+</body>
+</html>
+"##;
+
+/// Swagger UI page loading the configured assets, None unless both have an
+/// integrity hash.
+fn swagger_ui(config: &DocsConfig) -> Option<String> {
+    if config.css_integrity.is_empty() || config.js_integrity.is_empty() {
+        return None;
+    }
+    Some(format!(
+        r##"<!DOCTYPE html>
+<html>
+<head>
+  <title>Coeus oracle API</title>
+  <link rel="stylesheet" href="{}" integrity="{}" crossorigin="anonymous" />
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="{}" integrity="{}" crossorigin="anonymous"></script>
+  <script>SwaggerUIBundle({{ url: "/openapi.json", dom_id: "#swagger-ui" }});</script>
+</body>
+</html>
+"##,
+        config.css_url, config.css_integrity, config.js_url, config.js_integrity
+    ))
+}
+
+/// Endpoint serving a Swagger UI page for /openapi.json.
+pub async fn get_docs() -> Html<String> {
+    Html(swagger_ui(&COEUS_CONFIG.docs).unwrap_or_else(|| NO_UI.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_openapi() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        assert!(doc["paths"]["/process_data"]["post"].is_object());
+        assert!(doc["paths"]["/process_batch"]["post"].is_object());
+        assert!(doc["paths"]["/execute_code"]["post"].is_object());
+        assert!(doc["components"]["schemas"]["UpdateOracleRequest"].is_object());
+    }
+
+    #[test]
+    fn test_swagger_ui() {
+        let mut config = DocsConfig::default();
+        // Assets are not loaded unchecked
+        assert!(swagger_ui(&config).is_none());
+        config.css_integrity = "sha384-css".to_string();
+        assert!(swagger_ui(&config).is_none());
+
+        config.js_integrity = "sha384-js".to_string();
+        let page = swagger_ui(&config).unwrap();
+        assert!(page.contains(r#"integrity="sha384-css" crossorigin="anonymous""#));
+        assert!(page.contains(r#"integrity="sha384-js" crossorigin="anonymous""#));
+        assert!(page.contains(&config.js_url));
+    }
+}
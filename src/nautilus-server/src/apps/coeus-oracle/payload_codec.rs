@@ -0,0 +1,214 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Canonical encoding of the signed oracle payload.
+//!
+//! The enclave signs the BCS bytes of `IntentMessage<UpdateOracleResponse>`,
+//! which `oracle::feed` rebuilds as its `Payload` struct. The layout is:
+//!
+//! | field          | encoding                                           |
+//! |----------------|----------------------------------------------------|
+//! | intent_scope   | u8 (IntentScope, 0 for process_data)               |
+//! | app_id         | ULEB128 length + UTF-8 bytes (`coeus-oracle`)      |
+//! | timestamp_ms   | u64 little endian                                  |
+//! | result         | Option tag: 0x00 none, 0x01 some, then ResultValue |
+//!
+//! ResultValue is a ULEB128 variant index followed by the value:
+//! 0 STRING (ULEB128 length + UTF-8), 1 BOOLEAN (u8 0/1),
+//! 2 NUMBER (u64 little endian), 3 VECTOR (ULEB128 length + bytes).
+//!
+//! `encode_payload` writes this layout by hand rather than through serde, and
+//! `GOLDEN_VECTORS` pins it; the tests check both against `bcs::to_bytes` and
+//! `oracle::feed::test_payload_vectors` checks the same bytes on the Move side.
+
+use super::{APP_ID, ResultValue, UpdateOracleResponse};
+use crate::common::IntentScope;
+use serde::{Deserialize, Serialize};
+
+/// Mirror of `oracle::feed::Payload`, field for field. Its BCS bytes equal
+/// those of the signed `IntentMessage<UpdateOracleResponse>`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Payload {
+    pub intent_scope: u8,
+    pub app_id: Vec<u8>,
+    pub timestamp_ms: u64,
+    pub result: UpdateOracleResponse,
+}
+
+/// A named input and its expected hex encoding.
+pub struct GoldenVector {
+    pub name: &'static str,
+    pub intent: IntentScope,
+    pub timestamp_ms: u64,
+    pub result: fn() -> Option<ResultValue>,
+    pub hex: &'static str,
+}
+
+/// Reference encodings for SDKs and verifiers, all with app id
+/// `coeus-oracle` and timestamp 1744038900000.
+pub const GOLDEN_VECTORS: &[GoldenVector] = &[
+    GoldenVector {
+        name: "none",
+        intent: IntentScope::ProcessData,
+        timestamp_ms: 1744038900000,
+        result: || None,
+        hex: "000c636f6575732d6f7261636c6520b1d1109601000000",
+    },
+    GoldenVector {
+        name: "string",
+        intent: IntentScope::ProcessData,
+        timestamp_ms: 1744038900000,
+        result: || Some(ResultValue::STRING("BTC/USD".to_string())),
+        hex: "000c636f6575732d6f7261636c6520b1d110960100000100074254432f555344",
+    },
+    GoldenVector {
+        name: "boolean",
+        intent: IntentScope::ProcessData,
+        timestamp_ms: 1744038900000,
+        result: || Some(ResultValue::BOOLEAN(true)),
+        hex: "000c636f6575732d6f7261636c6520b1d11096010000010101",
+    },
+    GoldenVector {
+        name: "number",
+        intent: IntentScope::ProcessData,
+        timestamp_ms: 1744038900000,
+        result: || Some(ResultValue::NUMBER(1)),
+        hex: "000c636f6575732d6f7261636c6520b1d1109601000001020100000000000000",
+    },
+    GoldenVector {
+        name: "number_max",
+        intent: IntentScope::ProcessData,
+        timestamp_ms: 1744038900000,
+        result: || Some(ResultValue::NUMBER(u64::MAX)),
+        hex: "000c636f6575732d6f7261636c6520b1d110960100000102ffffffffffffffff",
+    },
+    GoldenVector {
+        name: "vector",
+        intent: IntentScope::ProcessData,
+        timestamp_ms: 1744038900000,
+        result: || Some(ResultValue::VECTOR(vec![1, 2, 3])),
+        hex: "000c636f6575732d6f7261636c6520b1d11096010000010303010203",
+    },
+    GoldenVector {
+        name: "execute_code",
+        intent: IntentScope::ExecuteCode,
+        timestamp_ms: 1744038900000,
+        result: || Some(ResultValue::NUMBER(1)),
+        hex: "030c636f6575732d6f7261636c6520b1d1109601000001020100000000000000",
+    },
+];
+
+fn write_uleb128(out: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_uleb128(out, bytes.len());
+    out.extend_from_slice(bytes);
+}
+
+/// Encode a ResultValue.
+pub fn encode_result(out: &mut Vec<u8>, result: &ResultValue) {
+    match result {
+        ResultValue::STRING(value) => {
+            write_uleb128(out, 0);
+            write_bytes(out, value.as_bytes());
+        }
+        ResultValue::BOOLEAN(value) => {
+            write_uleb128(out, 1);
+            out.push(*value as u8);
+        }
+        ResultValue::NUMBER(value) => {
+            write_uleb128(out, 2);
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        ResultValue::VECTOR(value) => {
+            write_uleb128(out, 3);
+            write_bytes(out, value);
+        }
+    }
+}
+
+/// The exact bytes the enclave signs for a result, and `oracle::feed`
+/// verifies against.
+pub fn encode_payload(
+    intent: IntentScope,
+    timestamp_ms: u64,
+    response: &UpdateOracleResponse,
+) -> Vec<u8> {
+    let mut out = vec![intent as u8];
+    write_bytes(&mut out, APP_ID.as_bytes());
+    out.extend_from_slice(&timestamp_ms.to_le_bytes());
+    match &response.result {
+        None => out.push(0),
+        Some(result) => {
+            out.push(1);
+            encode_result(&mut out, result);
+        }
+    }
+    out
+}
+
+impl Payload {
+    pub fn new(intent: IntentScope, timestamp_ms: u64, result: UpdateOracleResponse) -> Self {
+        Self {
+            intent_scope: intent as u8,
+            app_id: APP_ID.as_bytes().to_vec(),
+            timestamp_ms,
+            result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::IntentMessage;
+    use fastcrypto::encoding::{Encoding, Hex};
+
+    #[test]
+    fn test_golden_vectors() {
+        for vector in GOLDEN_VECTORS {
+            let response = UpdateOracleResponse {
+                result: (vector.result)(),
+            };
+            let encoded = encode_payload(vector.intent, vector.timestamp_ms, &response);
+            assert_eq!(Hex::encode(&encoded), vector.hex, "{}", vector.name);
+
+            // serde/BCS of the signed message and of the Move mirror agree
+            let intent_msg =
+                IntentMessage::new(response.clone(), vector.timestamp_ms, vector.intent, APP_ID);
+            assert_eq!(
+                bcs::to_bytes(&intent_msg).unwrap(),
+                encoded,
+                "{}",
+                vector.name
+            );
+            let payload = Payload::new(vector.intent, vector.timestamp_ms, response);
+            assert_eq!(bcs::to_bytes(&payload).unwrap(), encoded, "{}", vector.name);
+        }
+    }
+
+    #[test]
+    fn test_uleb128() {
+        let mut out = vec![];
+        write_uleb128(&mut out, 127);
+        write_uleb128(&mut out, 128);
+        write_uleb128(&mut out, 300);
+        assert_eq!(out, vec![0x7f, 0x80, 0x01, 0xac, 0x02]);
+
+        // Long strings use a multi-byte length prefix, like BCS
+        let long = ResultValue::STRING("a".repeat(200));
+        let mut encoded = vec![];
+        encode_result(&mut encoded, &long);
+        assert_eq!(encoded, bcs::to_bytes(&long).unwrap());
+    }
+}
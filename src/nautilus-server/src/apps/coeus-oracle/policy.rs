@@ -0,0 +1,171 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::ResultValue;
+use super::config::COEUS_CONFIG;
+use crate::EnclaveError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use sui_sdk_types::Address;
+use tokio::sync::RwLock;
+
+lazy_static::lazy_static! {
+    /// Last signed result per feed, used to evaluate update policies.
+    pub static ref LAST_SIGNED: Arc<RwLock<HashMap<Address, LastSigned>>> =
+        Arc::new(RwLock::new(HashMap::new()));
+}
+
+/// Per-feed update policy. A new value is only signed if it deviates from the
+/// last signed value by more than `max_deviation_bps`, or if `heartbeat_ms`
+/// elapsed since the last signed update.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdatePolicy {
+    pub feed_id: String,
+    /// Deviation threshold in basis points (1/100 of a percent). For non-NUMBER
+    /// results any change counts as a deviation.
+    pub max_deviation_bps: u64,
+    /// Always sign once this much time passed since the last signed update.
+    pub heartbeat_ms: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct LastSigned {
+    pub result: ResultValue,
+    pub timestamp_ms: u64,
+}
+
+fn policy_for(feed_id: Address) -> Option<&'static UpdatePolicy> {
+    COEUS_CONFIG
+        .update_policies
+        .iter()
+        .find(|policy| Address::from_hex(&policy.feed_id).ok() == Some(feed_id))
+}
+
+/// Deviation between two results in basis points, u64::MAX if the value
+/// changed but cannot be measured (different type, non-numeric or zero base).
+pub fn deviation_bps(last: &ResultValue, new: &ResultValue) -> u64 {
+    match (last, new) {
+        (ResultValue::NUMBER(last), ResultValue::NUMBER(new)) => {
+            if last == new {
+                0
+            } else if *last == 0 {
+                u64::MAX
+            } else {
+                let diff = last.abs_diff(*new) as u128;
+                (diff * 10_000 / *last as u128).min(u64::MAX as u128) as u64
+            }
+        }
+        (last, new) if last == new => 0,
+        _ => u64::MAX,
+    }
+}
+
+/// Check a result against `policy` and the last signed result of the feed.
+fn check(
+    policy: &UpdatePolicy,
+    last: Option<&LastSigned>,
+    result: &ResultValue,
+    timestamp_ms: u64,
+) -> Result<(), EnclaveError> {
+    let Some(last) = last else {
+        return Ok(());
+    };
+    let heartbeat_due = timestamp_ms.saturating_sub(last.timestamp_ms) >= policy.heartbeat_ms;
+    let deviation = deviation_bps(&last.result, result);
+    if heartbeat_due || deviation > policy.max_deviation_bps {
+        return Ok(());
+    }
+    Err(EnclaveError::UpdateNotRequired {
+        deviation_bps: deviation,
+        max_deviation_bps: policy.max_deviation_bps,
+        next_heartbeat_timestamp_ms: last.timestamp_ms.saturating_add(policy.heartbeat_ms),
+    })
+}
+
+/// Check the feed's update policy for a freshly computed result. Returns
+/// `EnclaveError::UpdateNotRequired` if the result should not be signed.
+pub async fn check_policy(
+    feed_id: Address,
+    result: &ResultValue,
+    timestamp_ms: u64,
+) -> Result<(), EnclaveError> {
+    let Some(policy) = policy_for(feed_id) else {
+        return Ok(());
+    };
+    check(
+        policy,
+        LAST_SIGNED.read().await.get(&feed_id),
+        result,
+        timestamp_ms,
+    )
+}
+
+/// Remember a result once it is signed, for future policy checks. A result
+/// refused or failing before it is signed never moves the policy. Concurrent
+/// runs of a feed may both pass the check, the later timestamp is kept.
+pub async fn record(feed_id: Address, result: &ResultValue, timestamp_ms: u64) {
+    if policy_for(feed_id).is_none() {
+        return;
+    }
+    let mut last_signed = LAST_SIGNED.write().await;
+    if last_signed
+        .get(&feed_id)
+        .is_some_and(|last| last.timestamp_ms > timestamp_ms)
+    {
+        return;
+    }
+    last_signed.insert(
+        feed_id,
+        LastSigned {
+            result: result.clone(),
+            timestamp_ms,
+        },
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_deviation_bps() {
+        let n = ResultValue::NUMBER;
+        assert_eq!(deviation_bps(&n(100), &n(100)), 0);
+        assert_eq!(deviation_bps(&n(100), &n(101)), 100);
+        assert_eq!(deviation_bps(&n(100), &n(99)), 100);
+        assert_eq!(deviation_bps(&n(10_000), &n(10_005)), 5);
+        assert_eq!(deviation_bps(&n(0), &n(1)), u64::MAX);
+        assert_eq!(deviation_bps(&n(0), &n(0)), 0);
+
+        let s = |v: &str| ResultValue::STRING(v.to_string());
+        assert_eq!(deviation_bps(&s("a"), &s("a")), 0);
+        assert_eq!(deviation_bps(&s("a"), &s("b")), u64::MAX);
+        assert_eq!(deviation_bps(&n(1), &s("1")), u64::MAX);
+    }
+
+    #[test]
+    fn test_check() {
+        let policy = UpdatePolicy {
+            feed_id: "0x1".to_string(),
+            max_deviation_bps: 50,
+            heartbeat_ms: 60_000,
+        };
+        let last = LastSigned {
+            result: ResultValue::NUMBER(10_000),
+            timestamp_ms: 1744038900000,
+        };
+        let n = ResultValue::NUMBER;
+        assert!(check(&policy, None, &n(10_000), 1744038900000).is_ok());
+        assert!(matches!(
+            check(&policy, Some(&last), &n(10_050), 1744038901000),
+            Err(EnclaveError::UpdateNotRequired {
+                deviation_bps: 50,
+                next_heartbeat_timestamp_ms: 1744038960000,
+                ..
+            })
+        ));
+        assert!(check(&policy, Some(&last), &n(10_051), 1744038901000).is_ok());
+        assert!(check(&policy, Some(&last), &n(10_000), 1744038960000).is_ok());
+    }
+}
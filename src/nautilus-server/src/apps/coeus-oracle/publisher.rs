@@ -0,0 +1,173 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Publisher signatures over feed code. A signed blob starts with an envelope:
+//!
+//! ```text
+//! "COEUSIG1" (8 bytes) | ed25519 public key (32) | signature (64) | code
+//! ```
+//!
+//! The signature covers `"COEUSIG1" || code`. When the feed registers
+//! publisher keys, or the config requires signatures globally, the enclave
+//! only executes code signed by one of the feed's keys, so substituting the
+//! code in storage is not enough to change what a feed runs.
+
+use super::OracleFeed;
+use super::config::COEUS_CONFIG;
+use crate::EnclaveError;
+use fastcrypto::ed25519::{Ed25519PublicKey, Ed25519Signature};
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{ToFromBytes, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+/// Magic prefix of a signed blob.
+pub const ENVELOPE_MAGIC: &[u8; 8] = b"COEUSIG1";
+
+const PUBLIC_KEY_LENGTH: usize = 32;
+const SIGNATURE_LENGTH: usize = 64;
+const HEADER_LENGTH: usize = ENVELOPE_MAGIC.len() + PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH;
+
+/// Publisher section of coeus_config.yaml.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PublisherConfig {
+    /// Refuse to execute any feed whose code is not signed by a publisher key
+    /// registered in the feed object.
+    #[serde(default)]
+    pub require_signature: bool,
+}
+
+/// A parsed signature envelope.
+pub struct Envelope<'a> {
+    pub public_key: &'a [u8],
+    pub signature: &'a [u8],
+    pub code: &'a [u8],
+}
+
+/// Split a blob into its envelope, None if the blob is not signed.
+pub fn parse_envelope(blob: &[u8]) -> Option<Envelope<'_>> {
+    if blob.len() < HEADER_LENGTH || !blob.starts_with(ENVELOPE_MAGIC) {
+        return None;
+    }
+    let (public_key, rest) = blob[ENVELOPE_MAGIC.len()..].split_at(PUBLIC_KEY_LENGTH);
+    let (signature, code) = rest.split_at(SIGNATURE_LENGTH);
+    Some(Envelope {
+        public_key,
+        signature,
+        code,
+    })
+}
+
+fn verify_signature(envelope: &Envelope) -> Result<(), EnclaveError> {
+    let invalid =
+        |e: String| EnclaveError::GenericError(format!("Invalid script signature: {}", e));
+    let public_key =
+        Ed25519PublicKey::from_bytes(envelope.public_key).map_err(|e| invalid(e.to_string()))?;
+    let signature =
+        Ed25519Signature::from_bytes(envelope.signature).map_err(|e| invalid(e.to_string()))?;
+    let message = [ENVELOPE_MAGIC.as_slice(), envelope.code].concat();
+    public_key
+        .verify(&message, &signature)
+        .map_err(|e| invalid(e.to_string()))
+}
+
+/// Check the publisher signature of a blob and return the code to execute.
+/// Unsigned blobs are returned as is unless a signature is required.
+pub fn verify_publisher<'a>(
+    feed: &OracleFeed,
+    blob: &'a [u8],
+    require_signature: bool,
+) -> Result<&'a [u8], EnclaveError> {
+    let required = require_signature || !feed.publisher_keys.is_empty();
+    let Some(envelope) = parse_envelope(blob) else {
+        if required {
+            return Err(EnclaveError::GenericError(
+                "Feed requires a signed script but the blob has no signature envelope".to_string(),
+            ));
+        }
+        return Ok(blob);
+    };
+
+    verify_signature(&envelope)?;
+    if required
+        && !feed
+            .publisher_keys
+            .iter()
+            .any(|key| key.as_slice() == envelope.public_key)
+    {
+        return Err(EnclaveError::GenericError(format!(
+            "Script signed by {} which is not a publisher of this feed",
+            Hex::encode(envelope.public_key)
+        )));
+    }
+    Ok(envelope.code)
+}
+
+/// `verify_publisher` with the globally configured requirement.
+pub fn verify_feed_code<'a>(feed: &OracleFeed, blob: &'a [u8]) -> Result<&'a [u8], EnclaveError> {
+    verify_publisher(feed, blob, COEUS_CONFIG.publisher.require_signature)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::app::{CodeExtension, ReturnType};
+    use fastcrypto::ed25519::Ed25519KeyPair;
+    use fastcrypto::traits::{KeyPair, Signer};
+    use sui_sdk_types::Address;
+
+    fn feed(publisher_keys: Vec<Vec<u8>>) -> OracleFeed {
+        OracleFeed {
+            id: Address::ZERO,
+            blob_id: String::new(),
+            extension: CodeExtension::RHAI,
+            result: None,
+            return_type: ReturnType::NUMBER,
+            allow_update_timestamp_ms: 0,
+            strict_types: false,
+            blob_hash: vec![],
+            publisher_keys,
+            blob_object_id: None,
+            creator: None,
+        }
+    }
+
+    fn sign(kp: &Ed25519KeyPair, code: &[u8]) -> Vec<u8> {
+        let message = [ENVELOPE_MAGIC.as_slice(), code].concat();
+        let signature: Ed25519Signature = kp.sign(&message);
+        [
+            ENVELOPE_MAGIC.as_slice(),
+            kp.public().as_bytes(),
+            signature.as_bytes(),
+            code,
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_verify_publisher() {
+        let publisher = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let other = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let keys = vec![publisher.public().as_bytes().to_vec()];
+        let signed = sign(&publisher, b"40 + 2");
+
+        // Unsigned code runs when nothing is required
+        assert_eq!(
+            verify_publisher(&feed(vec![]), b"42", false).unwrap(),
+            b"42"
+        );
+        assert!(verify_publisher(&feed(vec![]), b"42", true).is_err());
+        assert!(verify_publisher(&feed(keys.clone()), b"42", false).is_err());
+
+        assert_eq!(
+            verify_publisher(&feed(keys.clone()), &signed, true).unwrap(),
+            b"40 + 2"
+        );
+        // Signed by a key that is not registered
+        assert!(verify_publisher(&feed(keys.clone()), &sign(&other, b"40 + 2"), false).is_err());
+
+        // Tampered code
+        let mut tampered = signed.clone();
+        *tampered.last_mut().unwrap() = b'3';
+        assert!(verify_publisher(&feed(keys), &tampered, false).is_err());
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! GET /ready: actively probes what the oracle needs to serve results, so a
+//! load balancer can route away from a degraded enclave.
+//!
+//! - `sui_rpc`: a read of the latest checkpoint from the `sui.backend` reads
+//!   go to.
+//! - `walrus`: whether an aggregator answers, trying them in priority order
+//!   until one does. Since blob reads fail over between aggregators only one
+//!   of them needs to be up. The outcome is cached for WALRUS_TTL, so load
+//!   balancer probes do not turn into a stream of aggregator requests.
+//! - `signing_key`: with key rotation enabled, that rotation started and the
+//!   current key is not overdue for rotation.
+//! - `registration`: the outcome of the latest on-chain registration check,
+//!   unless `registration.mode` is off.
+//!
+//! Responds 200 when ready and 503 otherwise, with the status and latency of
+//! every probe in both cases. /health_check still only reports reachability
+//! of allowed_endpoints.yaml.
+
+use super::config::COEUS_CONFIG;
+use super::registration;
+use super::rotation;
+use super::walrus;
+use crate::AppState;
+use crate::config::RegistrationMode;
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+lazy_static::lazy_static! {
+    /// Latest Walrus probe and when it finished.
+    static ref WALRUS_HEALTH: Mutex<Option<(Instant, DependencyStatus)>> = Mutex::new(None);
+}
+
+/// Time allowed for each probe.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a Walrus probe outcome is reused.
+const WALRUS_TTL: Duration = Duration::from_secs(30);
+
+const SUI_RPC: &str = "sui_rpc";
+const SIGNING_KEY: &str = "signing_key";
+const REGISTRATION: &str = "registration";
+const WALRUS: &str = "walrus";
+
+/// Outcome of one probe.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DependencyStatus {
+    pub healthy: bool,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Response for GET /ready.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadyResponse {
+    pub ready: bool,
+    /// Probe outcomes by dependency name.
+    pub dependencies: BTreeMap<String, DependencyStatus>,
+}
+
+/// Run `probe` with the probe timeout and time it.
+async fn timed<F>(probe: F) -> DependencyStatus
+where
+    F: Future<Output = Result<(), String>>,
+{
+    let started = Instant::now();
+    let outcome = match tokio::time::timeout(PROBE_TIMEOUT, probe).await {
+        Ok(outcome) => outcome,
+        Err(_) => Err(format!("timed out after {}ms", PROBE_TIMEOUT.as_millis())),
+    };
+    DependencyStatus {
+        healthy: outcome.is_ok(),
+        latency_ms: started.elapsed().as_millis() as u64,
+        error: outcome.err(),
+    }
+}
+
+async fn probe_sui_rpc(state: &AppState) -> Result<(), String> {
+    state
+        .chain
+        .latest_checkpoint()
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Outcome of the latest Walrus probe, probing again once it is older than
+/// WALRUS_TTL. Concurrent callers wait for the same probe.
+async fn walrus_health() -> DependencyStatus {
+    let mut cached = WALRUS_HEALTH.lock().await;
+    if let Some((_, status)) = cached
+        .as_ref()
+        .filter(|(checked_at, _)| checked_at.elapsed() < WALRUS_TTL)
+    {
+        return status.clone();
+    }
+    let status = timed(walrus::probe_aggregators()).await;
+    *cached = Some((Instant::now(), status.clone()));
+    status
+}
+
+/// Ready when the fullnode and Walrus are reachable and every optional check
+/// that ran passed.
+fn is_ready(dependencies: &BTreeMap<String, DependencyStatus>) -> bool {
+    let healthy = |name: &str| dependencies.get(name).is_some_and(|status| status.healthy);
+    healthy(SUI_RPC) && healthy(WALRUS) && dependencies.values().all(|status| status.healthy)
+}
+
+/// Endpoint probing the oracle's dependencies.
+pub async fn get_ready(State(state): State<Arc<AppState>>) -> (StatusCode, Json<ReadyResponse>) {
+    let (sui_rpc, walrus) = tokio::join!(timed(probe_sui_rpc(&state)), walrus_health());
+
+    let mut dependencies = BTreeMap::new();
+    dependencies.insert(SUI_RPC.to_string(), sui_rpc);
+    dependencies.insert(WALRUS.to_string(), walrus);
+    if COEUS_CONFIG.rotation.enabled {
+        dependencies.insert(
+            SIGNING_KEY.to_string(),
+            timed(rotation::check_rotation()).await,
+        );
+    }
+    if state.config.registration.mode != RegistrationMode::Off {
+        let registration = registration::status();
+        dependencies.insert(
+            REGISTRATION.to_string(),
+            DependencyStatus {
+                healthy: registration.registered,
+                latency_ms: 0,
+                error: registration.error,
+            },
+        );
+    }
+
+    let ready = is_ready(&dependencies);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(ReadyResponse {
+            ready,
+            dependencies,
+        }),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn status(healthy: bool) -> DependencyStatus {
+        DependencyStatus {
+            healthy,
+            latency_ms: 1,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_is_ready() {
+        let mut dependencies = BTreeMap::new();
+        dependencies.insert(SUI_RPC.to_string(), status(true));
+        assert!(!is_ready(&dependencies));
+        dependencies.insert(WALRUS.to_string(), status(false));
+        assert!(!is_ready(&dependencies));
+        dependencies.insert(WALRUS.to_string(), status(true));
+        assert!(is_ready(&dependencies));
+
+        dependencies.insert(SIGNING_KEY.to_string(), status(false));
+        assert!(!is_ready(&dependencies));
+        dependencies.insert(SIGNING_KEY.to_string(), status(true));
+        dependencies.insert(REGISTRATION.to_string(), status(false));
+        assert!(!is_ready(&dependencies));
+        dependencies.insert(REGISTRATION.to_string(), status(true));
+        assert!(is_ready(&dependencies));
+        dependencies.remove(SUI_RPC);
+        assert!(!is_ready(&dependencies));
+    }
+}
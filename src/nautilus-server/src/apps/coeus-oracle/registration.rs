@@ -0,0 +1,319 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Check that the running enclave is the one registered on Sui, see
+//! `[registration]` in nautilus.toml.
+//!
+//! The Enclave object must hold the current signing key (or the previous one
+//! during a rotation grace period), point to the current version of the
+//! EnclaveConfig, and that config must hold the PCRs of this image. The check
+//! runs at startup and then every `check_interval_ms`, so a registration made
+//! after boot, or a PCR update that retires this build, is picked up without a
+//! restart. Until it passes, `enforce` refuses to sign results and `mark`
+//! flags every response with `X-Enclave-Registration: unregistered`. This
+//! guards against serving from a build nobody registered, not against a host
+//! that points the check at objects it controls.
+
+use super::onchain::get_objects;
+use super::rotation;
+use crate::common::describe_pcr;
+use crate::config::{RegistrationConfig, RegistrationMode};
+use crate::{AppState, EnclaveError, admin};
+use axum::extract::{Request, State};
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::Response;
+use fastcrypto::traits::{KeyPair, ToFromBytes};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use sui_sdk_types::{Address, Object};
+use tracing::{info, warn};
+
+/// Header set on responses while unregistered in mark mode.
+pub const REGISTRATION_HEADER: &str = "x-enclave-registration";
+
+lazy_static::lazy_static! {
+    static ref STATUS: RwLock<RegistrationStatus> = RwLock::new(RegistrationStatus {
+        registered: false,
+        enclave_object_id: None,
+        error: Some("not checked yet".to_string()),
+    });
+}
+
+/// Outcome of the latest check.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegistrationStatus {
+    pub registered: bool,
+    /// Enclave object checked, when one is set.
+    pub enclave_object_id: Option<String>,
+    /// Why the check failed.
+    pub error: Option<String>,
+}
+
+/// BCS layout of enclave::Enclave<T>
+#[derive(Debug, Deserialize)]
+struct EnclaveObject {
+    #[allow(dead_code)]
+    id: Address,
+    pk: Vec<u8>,
+    config_version: u64,
+    #[allow(dead_code)]
+    owner: Address,
+}
+
+/// BCS layout of enclave::EnclaveConfig<T>
+#[derive(Debug, Deserialize)]
+struct EnclaveConfigObject {
+    #[allow(dead_code)]
+    id: Address,
+    #[allow(dead_code)]
+    name: String,
+    pcrs: (Vec<u8>, Vec<u8>, Vec<u8>),
+    #[allow(dead_code)]
+    capability_id: Address,
+    version: u64,
+}
+
+pub fn status() -> RegistrationStatus {
+    STATUS.read().expect("registration lock poisoned").clone()
+}
+
+fn is_registered() -> bool {
+    STATUS
+        .read()
+        .expect("registration lock poisoned")
+        .registered
+}
+
+/// Refuse to sign in enforce mode until the registration is verified.
+pub fn require(config: &RegistrationConfig) -> Result<(), EnclaveError> {
+    if config.mode != RegistrationMode::Enforce {
+        return Ok(());
+    }
+    let status = status();
+    if status.registered {
+        return Ok(());
+    }
+    Err(EnclaveError::Unregistered(status.error.unwrap_or_default()))
+}
+
+/// Middleware flagging responses while unregistered in mark mode.
+pub async fn mark_unregistered(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    if state.config.registration.mode == RegistrationMode::Mark && !is_registered() {
+        response.headers_mut().insert(
+            REGISTRATION_HEADER,
+            HeaderValue::from_static("unregistered"),
+        );
+    }
+    response
+}
+
+fn parse_id(name: &str, id: &str) -> Result<Address, String> {
+    Address::from_hex(id).map_err(|e| format!("Invalid {} {}: {}", name, id, e))
+}
+
+fn decode<T: serde::de::DeserializeOwned>(name: &str, object: &Object) -> Result<T, String> {
+    let contents = object
+        .as_struct()
+        .ok_or_else(|| format!("{} is not a Move object", name))?
+        .contents();
+    bcs::from_bytes(contents).map_err(|e| format!("Failed to decode {}: {}", name, e))
+}
+
+/// Whether `enclave` registers one of `public_keys` with `pcrs`, against
+/// `config` of the same enclave type.
+fn verify(
+    enclave_type: &str,
+    config_type: &str,
+    enclave: &EnclaveObject,
+    config: &EnclaveConfigObject,
+    public_keys: &[Vec<u8>],
+    pcrs: &(Vec<u8>, Vec<u8>, Vec<u8>),
+) -> Result<(), String> {
+    let Some(expected_config_type) = enclave_type
+        .contains("::enclave::Enclave<")
+        .then(|| enclave_type.replacen("::enclave::Enclave<", "::enclave::EnclaveConfig<", 1))
+    else {
+        return Err(format!("Enclave object is a {}", enclave_type));
+    };
+    if config_type != expected_config_type {
+        return Err(format!(
+            "EnclaveConfig is a {}, not a {}",
+            config_type, expected_config_type
+        ));
+    }
+    if !public_keys.contains(&enclave.pk) {
+        return Err("Enclave object holds another public key".to_string());
+    }
+    if enclave.config_version != config.version {
+        return Err(format!(
+            "Enclave object was registered against EnclaveConfig version {}, it is now at {}",
+            enclave.config_version, config.version
+        ));
+    }
+    if config.pcrs != *pcrs {
+        return Err("EnclaveConfig holds the PCRs of another build".to_string());
+    }
+    Ok(())
+}
+
+async fn check(state: &AppState, enclave_object_id: Option<&str>) -> Result<(), String> {
+    let config = &state.config.registration;
+    let enclave_object_id = parse_id(
+        "Enclave object id",
+        enclave_object_id.ok_or("No Enclave object set, see PUT /admin/registration")?,
+    )?;
+    let config_id = parse_id(
+        "EnclaveConfig id",
+        config
+            .enclave_config_id
+            .as_deref()
+            .ok_or("registration.enclave_config_id is not set")?,
+    )?;
+    let [enclave_object, config_object]: [Object; 2] =
+        get_objects(state.chain.as_ref(), &[enclave_object_id, config_id])
+            .await
+            .map_err(|e| e.to_string())?
+            .try_into()
+            .map_err(|_| "Expected two objects".to_string())?;
+    let type_of = |object: &Object| {
+        object
+            .as_struct()
+            .map(|move_object| move_object.object_type().to_string())
+            .unwrap_or_default()
+    };
+
+    let (current, previous) = rotation::signing_keys(state).await;
+    let public_keys = std::iter::once(current)
+        .chain(previous)
+        .map(|kp| kp.public().as_bytes().to_vec())
+        .collect::<Vec<_>>();
+    let pcrs = (
+        describe_pcr(0).map_err(|e| e.to_string())?,
+        describe_pcr(1).map_err(|e| e.to_string())?,
+        describe_pcr(2).map_err(|e| e.to_string())?,
+    );
+    verify(
+        &type_of(&enclave_object),
+        &type_of(&config_object),
+        &decode("Enclave object", &enclave_object)?,
+        &decode("EnclaveConfig", &config_object)?,
+        &public_keys,
+        &pcrs,
+    )
+}
+
+/// Start checking the registration. Does nothing unless `registration.mode`
+/// is set in nautilus.toml.
+pub fn spawn_registration_check(state: Arc<AppState>) -> Result<(), EnclaveError> {
+    let config = &state.config.registration;
+    if config.mode == RegistrationMode::Off {
+        return Ok(());
+    }
+    if config.enclave_config_id.is_none() || config.check_interval_ms == 0 {
+        return Err(EnclaveError::GenericError(
+            "registration needs enclave_config_id and a positive check_interval_ms".to_string(),
+        ));
+    }
+    tokio::spawn(async move {
+        let interval = Duration::from_millis(state.config.registration.check_interval_ms);
+        loop {
+            let enclave_object_id = admin::enclave_object_id()
+                .or_else(|| state.config.registration.enclave_object_id.clone());
+            let outcome = check(&state, enclave_object_id.as_deref()).await;
+            let was_registered = is_registered();
+            match &outcome {
+                Ok(()) if !was_registered => info!("Enclave registration verified"),
+                Err(e) if was_registered => warn!("Enclave registration lost: {}", e),
+                Err(e) => info!("Enclave not registered yet: {}", e),
+                Ok(()) => {}
+            }
+            *STATUS.write().expect("registration lock poisoned") = RegistrationStatus {
+                registered: outcome.is_ok(),
+                enclave_object_id,
+                error: outcome.err(),
+            };
+            tokio::time::sleep(interval).await;
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let pcrs = (vec![0; 48], vec![1; 48], vec![2; 48]);
+        let enclave = EnclaveObject {
+            id: Address::ZERO,
+            pk: vec![7; 32],
+            config_version: 2,
+            owner: Address::ZERO,
+        };
+        let config = EnclaveConfigObject {
+            id: Address::ZERO,
+            name: "coeus".to_string(),
+            pcrs: pcrs.clone(),
+            capability_id: Address::ZERO,
+            version: 2,
+        };
+        let enclave_type = "0x2a::enclave::Enclave<0x3b::oracle::ORACLE>";
+        let config_type = "0x2a::enclave::EnclaveConfig<0x3b::oracle::ORACLE>";
+        let keys = vec![vec![7; 32]];
+        assert!(verify(enclave_type, config_type, &enclave, &config, &keys, &pcrs).is_ok());
+
+        // Another app's config, another key, another build
+        let other_type = "0x2a::enclave::EnclaveConfig<0x3b::other::OTHER>";
+        assert!(verify(enclave_type, other_type, &enclave, &config, &keys, &pcrs).is_err());
+        assert!(verify(config_type, config_type, &enclave, &config, &keys, &pcrs).is_err());
+        let other_keys = vec![vec![8; 32]];
+        assert!(
+            verify(
+                enclave_type,
+                config_type,
+                &enclave,
+                &config,
+                &other_keys,
+                &pcrs
+            )
+            .is_err()
+        );
+        let other_pcrs = (vec![0; 48], vec![1; 48], vec![3; 48]);
+        assert!(
+            verify(
+                enclave_type,
+                config_type,
+                &enclave,
+                &config,
+                &keys,
+                &other_pcrs
+            )
+            .is_err()
+        );
+
+        // PCRs updated since the registration
+        let config = EnclaveConfigObject {
+            version: 3,
+            ..config
+        };
+        assert!(verify(enclave_type, config_type, &enclave, &config, &keys, &pcrs).is_err());
+
+        assert!(require(&RegistrationConfig::default()).is_ok());
+        let enforce = RegistrationConfig {
+            mode: RegistrationMode::Enforce,
+            ..Default::default()
+        };
+        assert!(matches!(
+            require(&enforce),
+            Err(EnclaveError::Unregistered(_))
+        ));
+    }
+}
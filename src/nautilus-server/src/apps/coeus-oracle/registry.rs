@@ -0,0 +1,203 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Feeds held as dynamic fields of a registry object, such as the values of a
+//! `Table<K, OracleFeed>` or fields added with `dynamic_field::add`.
+//!
+//! process_data takes `registry_id` and `key` instead of `feed_id`. The feed
+//! id is then the id of the field object, derived from the registry and the
+//! key, and the request proceeds as for a standalone feed: the field object
+//! must be a `0x2::dynamic_field::Field<K, OracleFeed>` owned by the registry,
+//! and its value is decoded as the feed. Registries are listed under
+//! feed_object.registries in coeus_config.yaml with their key type. For a
+//! Table, registry_id is the id of the Table, not of the object holding it.
+
+use super::config::COEUS_CONFIG;
+use super::onchain::dynamic_field_id;
+use crate::EnclaveError;
+use serde::{Deserialize, Serialize};
+use sui_sdk_types::{Address, StructTag, TypeTag};
+
+/// Length of the UID the contents of a field object start with.
+const UID_LENGTH: usize = 32;
+
+/// A registry in feed_object.registries.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryConfig {
+    /// Object, or Table, the feed fields are on.
+    pub registry_id: String,
+    pub key_type: KeyType,
+}
+
+/// Move type of the registry keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyType {
+    /// 0x1::string::String
+    String,
+    U64,
+    Address,
+    /// 0x2::object::ID
+    Id,
+}
+
+impl KeyType {
+    fn type_name(self) -> &'static str {
+        match self {
+            KeyType::String => "0x1::string::String",
+            KeyType::U64 => "u64",
+            KeyType::Address => "address",
+            KeyType::Id => "0x2::object::ID",
+        }
+    }
+
+    fn type_tag(self) -> Result<TypeTag, EnclaveError> {
+        self.type_name().parse().map_err(|e| {
+            EnclaveError::GenericError(format!("Invalid key type {}: {:?}", self.type_name(), e))
+        })
+    }
+
+    /// BCS of a key given as text: as is for String, decimal for u64, hex for
+    /// address and ID.
+    fn encode(self, key: &str) -> Result<Vec<u8>, EnclaveError> {
+        let invalid = |e: String| {
+            EnclaveError::InvalidRequest(format!(
+                "Invalid {} registry key {}: {}",
+                self.type_name(),
+                key,
+                e
+            ))
+        };
+        match self {
+            KeyType::String => bcs::to_bytes(key).map_err(|e| invalid(e.to_string())),
+            KeyType::U64 => key
+                .parse::<u64>()
+                .map(|key| key.to_le_bytes().to_vec())
+                .map_err(|e| invalid(e.to_string())),
+            KeyType::Address | KeyType::Id => Address::from_hex(key)
+                .map(|key| key.as_ref().to_vec())
+                .map_err(|e| invalid(e.to_string())),
+        }
+    }
+
+    /// Length of the BCS key `bytes` start with.
+    fn encoded_len(self, bytes: &[u8]) -> Option<usize> {
+        let len = match self {
+            KeyType::U64 => 8,
+            KeyType::Address | KeyType::Id => 32,
+            KeyType::String => {
+                let (len, prefix) = read_uleb128(bytes)?;
+                prefix.checked_add(len)?
+            }
+        };
+        (len <= bytes.len()).then_some(len)
+    }
+}
+
+/// Value and length of the ULEB128 length prefix `bytes` start with.
+fn read_uleb128(bytes: &[u8]) -> Option<(usize, usize)> {
+    let mut value: u64 = 0;
+    for (index, byte) in bytes.iter().take(5).enumerate() {
+        value |= u64::from(byte & 0x7f) << (7 * index);
+        if byte & 0x80 == 0 {
+            return Some((usize::try_from(value).ok()?, index + 1));
+        }
+    }
+    None
+}
+
+fn registry(registry_id: &Address) -> Option<&'static RegistryConfig> {
+    COEUS_CONFIG
+        .feed_object
+        .registries
+        .iter()
+        .find(|registry| Address::from_hex(&registry.registry_id).ok() == Some(*registry_id))
+}
+
+/// Feed id of the field holding `key` in a configured registry.
+pub fn resolve(registry_id: &str, key: &str) -> Result<Address, EnclaveError> {
+    let registry_id = Address::from_hex(registry_id).map_err(|e| {
+        EnclaveError::InvalidRequest(format!("Invalid registry id {}: {}", registry_id, e))
+    })?;
+    let config = registry(&registry_id).ok_or_else(|| {
+        EnclaveError::InvalidRequest(format!(
+            "Registry {} is not in feed_object.registries",
+            registry_id
+        ))
+    })?;
+    let key_type = config.key_type;
+    dynamic_field_id(&registry_id, &key_type.type_tag()?, &key_type.encode(key)?)
+}
+
+/// The value of a field object: its contents after the UID and the key.
+fn split_field(key_type: KeyType, contents: &[u8]) -> Option<&[u8]> {
+    let rest = contents.get(UID_LENGTH..)?;
+    let key_len = key_type.encoded_len(rest)?;
+    Some(&rest[key_len..])
+}
+
+/// Contents of the OracleFeed held by a field object of `registry_id`, once
+/// the field type is checked.
+pub fn field_value<'a>(
+    registry_id: &Address,
+    feed_id: &Address,
+    object_type: &StructTag,
+    feed_type: &StructTag,
+    contents: &'a [u8],
+) -> Result<&'a [u8], EnclaveError> {
+    let config = registry(registry_id).ok_or_else(|| {
+        EnclaveError::InvalidRequest(format!(
+            "Feed {} is owned by {}, which is not in feed_object.registries",
+            feed_id, registry_id
+        ))
+    })?;
+    let field_type = format!(
+        "0x2::dynamic_field::Field<{}, {}>",
+        config.key_type.type_name(),
+        feed_type
+    );
+    let field_type: StructTag = field_type.parse().map_err(|e| {
+        EnclaveError::GenericError(format!("Invalid field type {}: {:?}", field_type, e))
+    })?;
+    if object_type != &field_type {
+        return Err(EnclaveError::InvalidRequest(format!(
+            "Object {} is a {}, not a {}",
+            feed_id, object_type, field_type
+        )));
+    }
+    split_field(config.key_type, contents).ok_or_else(|| {
+        EnclaveError::InvalidRequest(format!("Field {} has a truncated key", feed_id))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_keys() {
+        assert_eq!(
+            KeyType::String.encode("BTC/USD").unwrap(),
+            bcs::to_bytes("BTC/USD").unwrap()
+        );
+        assert_eq!(KeyType::U64.encode("7").unwrap(), 7u64.to_le_bytes());
+        assert!(KeyType::U64.encode("BTC").is_err());
+        assert_eq!(KeyType::Id.encode("0x1").unwrap().len(), 32);
+        assert!(KeyType::Address.encode("0xzz").is_err());
+
+        for (key_type, key) in [
+            (KeyType::String, "BTC/USD"),
+            (KeyType::U64, "7"),
+            (KeyType::Address, "0x1"),
+        ] {
+            let key = key_type.encode(key).unwrap();
+            let mut contents = vec![9; UID_LENGTH];
+            contents.extend_from_slice(&key);
+            contents.extend_from_slice(b"feed");
+            assert_eq!(split_field(key_type, &contents), Some(&b"feed"[..]));
+            assert!(key_type.type_tag().is_ok());
+        }
+        assert_eq!(split_field(KeyType::U64, &[0; 36]), None);
+        assert_eq!(read_uleb128(&[0x80, 0x01]), Some((128, 2)));
+    }
+}
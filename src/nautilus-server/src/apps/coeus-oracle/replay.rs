@@ -0,0 +1,215 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Uniqueness of signed results per (feed id, timestamp).
+//!
+//! The enclave remembers the payload digest it signed for every (feed id,
+//! timestamp_ms) pair for `window_ms` and refuses to sign a different result
+//! for a pair it already used, so an operator cannot obtain two conflicting
+//! signed values for the same feed and instant (e.g. by replaying a threshold
+//! round). Signing the same result again is allowed, it produces the same
+//! message. On-chain, `oracle::feed` accepts a single result per feed and at
+//! most `max_update_time_ms` old, so a window at least that long covers every
+//! response that is still submittable.
+//!
+//! Requests authorized by a signature over a timestamp, such as POST
+//! /sign_digest, are also only served once: their nonce is remembered until
+//! the request expires, so a captured request cannot be replayed while it is
+//! still fresh.
+
+use super::config::COEUS_CONFIG;
+use crate::EnclaveError;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use sui_sdk_types::Address;
+use tokio::sync::Mutex;
+
+lazy_static::lazy_static! {
+    static ref ISSUED: Mutex<IssuedResults> = Mutex::new(IssuedResults::default());
+    static ref SEEN_NONCES: Mutex<SeenNonces> = Mutex::new(SeenNonces::default());
+}
+
+/// Replay section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayConfig {
+    /// How long a (feed id, timestamp) pair stays reserved for the result it
+    /// was first signed with.
+    #[serde(default = "default_window_ms")]
+    pub window_ms: u64,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            window_ms: default_window_ms(),
+        }
+    }
+}
+
+fn default_window_ms() -> u64 {
+    10 * 60 * 1000
+}
+
+/// Payload digest signed per (feed id, timestamp_ms).
+#[derive(Default)]
+struct IssuedResults {
+    digests: HashMap<(Address, u64), [u8; 32]>,
+}
+
+impl IssuedResults {
+    /// Reserve the pair for `digest`, failing if it holds another digest.
+    /// Pairs older than the window are dropped first.
+    fn reserve(
+        &mut self,
+        feed_id: Address,
+        timestamp_ms: u64,
+        digest: [u8; 32],
+        now_ms: u64,
+        window_ms: u64,
+    ) -> Result<(), EnclaveError> {
+        self.digests
+            .retain(|(_, issued_at), _| now_ms.saturating_sub(*issued_at) < window_ms);
+        match self.digests.get(&(feed_id, timestamp_ms)) {
+            Some(issued) if issued != &digest => {
+                Err(EnclaveError::ConflictingResult { timestamp_ms })
+            }
+            Some(_) => Ok(()),
+            None => {
+                self.digests.insert((feed_id, timestamp_ms), digest);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Reserve (feed id, timestamp) for the payload with `digest` before signing
+/// it. Returns `EnclaveError::ConflictingResult` if a different payload was
+/// signed for the pair within the window.
+pub async fn reserve(
+    feed_id: Address,
+    timestamp_ms: u64,
+    digest: [u8; 32],
+    now_ms: u64,
+) -> Result<(), EnclaveError> {
+    ISSUED.lock().await.reserve(
+        feed_id,
+        timestamp_ms,
+        digest,
+        now_ms,
+        COEUS_CONFIG.replay.window_ms,
+    )
+}
+
+/// Nonces of signed requests, with the time each request expires.
+#[derive(Default)]
+struct SeenNonces {
+    expires_at_ms: HashMap<[u8; 32], u64>,
+}
+
+impl SeenNonces {
+    /// Remember `nonce` until `expires_at_ms`, failing if it was already
+    /// used. Expired nonces are dropped first.
+    fn use_nonce(
+        &mut self,
+        nonce: [u8; 32],
+        expires_at_ms: u64,
+        now_ms: u64,
+    ) -> Result<(), EnclaveError> {
+        self.expires_at_ms
+            .retain(|_, expires_at_ms| *expires_at_ms >= now_ms);
+        if self.expires_at_ms.contains_key(&nonce) {
+            return Err(EnclaveError::GenericError(
+                "Request was already used".to_string(),
+            ));
+        }
+        self.expires_at_ms.insert(nonce, expires_at_ms);
+        Ok(())
+    }
+}
+
+/// Accept a signed request with `nonce` once. The request must be refused by
+/// its own checks once `expires_at_ms` has passed, the nonce is forgotten then.
+pub async fn use_nonce(
+    nonce: [u8; 32],
+    expires_at_ms: u64,
+    now_ms: u64,
+) -> Result<(), EnclaveError> {
+    SEEN_NONCES
+        .lock()
+        .await
+        .use_nonce(nonce, expires_at_ms, now_ms)
+}
+
+/// Response for GET /replay_policy.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReplayPolicyResponse {
+    /// Fields that identify a signed result.
+    pub unique_key: Vec<String>,
+    pub window_ms: u64,
+    /// What happens when a pair is signed again.
+    pub semantics: String,
+}
+
+/// Endpoint describing the uniqueness policy, so contracts and relayers can
+/// check it matches what they enforce.
+pub async fn get_replay_policy() -> Json<ReplayPolicyResponse> {
+    Json(ReplayPolicyResponse {
+        unique_key: vec!["feed_id".to_string(), "timestamp_ms".to_string()],
+        window_ms: COEUS_CONFIG.replay.window_ms,
+        semantics: "at most one distinct signed payload per key within window_ms, \
+                    identical payloads may be re-signed"
+            .to_string(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reserve() {
+        let mut issued = IssuedResults::default();
+        let feed = Address::ZERO;
+        let now = 1744038900000;
+
+        assert!(issued.reserve(feed, now, [1; 32], now, 1_000).is_ok());
+        // Same result again is fine, a different one is refused
+        assert!(issued.reserve(feed, now, [1; 32], now + 10, 1_000).is_ok());
+        assert!(matches!(
+            issued.reserve(feed, now, [2; 32], now + 10, 1_000),
+            Err(EnclaveError::ConflictingResult { .. })
+        ));
+        // Other timestamps and feeds are independent
+        assert!(
+            issued
+                .reserve(feed, now + 1, [2; 32], now + 10, 1_000)
+                .is_ok()
+        );
+        let other_feed = Address::from_hex("0x1").unwrap();
+        assert!(
+            issued
+                .reserve(other_feed, now, [2; 32], now + 10, 1_000)
+                .is_ok()
+        );
+
+        // Outside the window the pair is forgotten
+        assert!(
+            issued
+                .reserve(feed, now, [2; 32], now + 1_000, 1_000)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_use_nonce() {
+        let mut seen = SeenNonces::default();
+        let now = 1744038900000;
+
+        assert!(seen.use_nonce([1; 32], now + 1_000, now).is_ok());
+        assert!(seen.use_nonce([1; 32], now + 1_000, now + 1_000).is_err());
+        assert!(seen.use_nonce([2; 32], now + 1_000, now).is_ok());
+        // Forgotten once the request expired
+        assert!(seen.use_nonce([1; 32], now + 3_000, now + 1_001).is_ok());
+    }
+}
@@ -0,0 +1,320 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Periodic rotation of the ephemeral signing key.
+//!
+//! Every `interval_ms` a new keypair is generated together with an attestation
+//! committed to it, and the outgoing key signs the new public key under
+//! IntentScope::KeyRotation. For `grace_ms` after a rotation, results are
+//! signed by both keys so relayers can keep submitting against the previous
+//! on-chain registration until the new attestation is registered.
+
+use super::APP_ID;
+use super::audit;
+use super::config::COEUS_CONFIG;
+use crate::common::{
+    HealthCheckResponse, IntentMessage, IntentScope, attestation_for, enclave_rng,
+    health_check_for, to_signed_response,
+};
+use crate::{AppState, EnclaveError};
+use axum::Json;
+use axum::extract::State;
+use fastcrypto::ed25519::Ed25519KeyPair;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{KeyPair, ToFromBytes};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+lazy_static::lazy_static! {
+    /// Current and previous signing keys, None until rotation starts.
+    static ref KEY_RING: RwLock<Option<KeyRing>> = RwLock::new(None);
+}
+
+/// Key rotation section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RotationConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Time between rotations.
+    #[serde(default = "default_interval_ms")]
+    pub interval_ms: u64,
+    /// Time after a rotation during which the previous key still co-signs.
+    #[serde(default = "default_grace_ms")]
+    pub grace_ms: u64,
+}
+
+impl Default for RotationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_ms: default_interval_ms(),
+            grace_ms: default_grace_ms(),
+        }
+    }
+}
+
+fn default_interval_ms() -> u64 {
+    24 * 60 * 60 * 1000
+}
+
+fn default_grace_ms() -> u64 {
+    60 * 60 * 1000
+}
+
+pub struct RotatedKey {
+    pub kp: Arc<Ed25519KeyPair>,
+    pub activated_at_ms: u64,
+    /// Hex attestation document committed to the public key.
+    pub attestation: Option<String>,
+    /// Hex signature of the previous key over the public key
+    /// (IntentScope::KeyRotation), linking the two registrations.
+    pub handoff_signature: Option<String>,
+}
+
+pub struct KeyRing {
+    pub current: RotatedKey,
+    pub previous: Option<RotatedKey>,
+}
+
+impl KeyRing {
+    /// Make `next` the current key, keeping the outgoing one as previous.
+    pub fn rotate(&mut self, next: RotatedKey) {
+        self.previous = Some(std::mem::replace(&mut self.current, next));
+    }
+
+    /// The previous key, if the current one was activated less than
+    /// `grace_ms` ago.
+    pub fn previous_in_grace(&self, now_ms: u64, grace_ms: u64) -> Option<&RotatedKey> {
+        self.previous
+            .as_ref()
+            .filter(|_| now_ms < self.current.activated_at_ms.saturating_add(grace_ms))
+    }
+
+    /// Whether the current key has outlived its interval by more than the
+    /// grace window, i.e. rotations keep failing.
+    pub fn is_overdue(&self, now_ms: u64, interval_ms: u64, grace_ms: u64) -> bool {
+        now_ms
+            > self
+                .current
+                .activated_at_ms
+                .saturating_add(interval_ms)
+                .saturating_add(grace_ms)
+    }
+}
+
+/// Public view of a key in GET /keys.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeyInfo {
+    pub public_key: String,
+    pub activated_at_ms: u64,
+    pub attestation: Option<String>,
+    pub handoff_signature: Option<String>,
+}
+
+/// Response for GET /keys.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeysResponse {
+    pub current: KeyInfo,
+    /// Previous key, present while it still co-signs results.
+    pub previous: Option<KeyInfo>,
+    /// Until when the previous key co-signs.
+    pub previous_valid_until_ms: Option<u64>,
+    /// When the next rotation happens, None when rotation is disabled.
+    pub next_rotation_ms: Option<u64>,
+}
+
+impl From<&RotatedKey> for KeyInfo {
+    fn from(key: &RotatedKey) -> Self {
+        Self {
+            public_key: Hex::encode(key.kp.public().as_bytes()),
+            activated_at_ms: key.activated_at_ms,
+            attestation: key.attestation.clone(),
+            handoff_signature: key.handoff_signature.clone(),
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Keys to sign results with: the current key and, during the grace window,
+/// the previous one. Without rotation this is the process' ephemeral key.
+pub async fn signing_keys(state: &AppState) -> (Arc<Ed25519KeyPair>, Option<Arc<Ed25519KeyPair>>) {
+    match KEY_RING.read().await.as_ref() {
+        Some(ring) => (
+            ring.current.kp.clone(),
+            ring.previous_in_grace(now_ms(), COEUS_CONFIG.rotation.grace_ms)
+                .map(|key| key.kp.clone()),
+        ),
+        None => (Arc::new(state.eph_kp.copy()), None),
+    }
+}
+
+/// Check that the signing key is rotated on schedule. Fails until rotation
+/// started, or once the current key is overdue for rotation.
+pub async fn check_rotation() -> Result<(), String> {
+    let config = &COEUS_CONFIG.rotation;
+    match KEY_RING.read().await.as_ref() {
+        None => Err("key rotation has not started".to_string()),
+        Some(ring) if ring.is_overdue(now_ms(), config.interval_ms, config.grace_ms) => {
+            Err(format!(
+                "signing key active since {} is overdue for rotation",
+                ring.current.activated_at_ms
+            ))
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+/// Generate the next key, attest it and sign the handoff with `outgoing`.
+/// Returns the key and the signed handoff message.
+fn next_key(
+    outgoing: &Ed25519KeyPair,
+) -> Result<(RotatedKey, IntentMessage<Vec<u8>>), EnclaveError> {
+    let kp = Ed25519KeyPair::generate(&mut enclave_rng());
+    let public_key = kp.public().as_bytes().to_vec();
+    let attestation = attestation_for(&public_key)?;
+    let activated_at_ms = now_ms();
+    let handoff = to_signed_response(
+        outgoing,
+        public_key,
+        activated_at_ms,
+        IntentScope::KeyRotation,
+        APP_ID,
+    );
+    let key = RotatedKey {
+        kp: Arc::new(kp),
+        activated_at_ms,
+        attestation: Some(Hex::encode(attestation)),
+        handoff_signature: Some(handoff.signature),
+    };
+    Ok((key, handoff.response))
+}
+
+/// Start rotating the signing key. Does nothing unless rotation is enabled
+/// in coeus_config.yaml.
+pub fn spawn_key_rotation(state: Arc<AppState>) -> Result<(), EnclaveError> {
+    let config = &COEUS_CONFIG.rotation;
+    if !config.enabled {
+        return Ok(());
+    }
+    if config.interval_ms == 0 || config.grace_ms >= config.interval_ms {
+        return Err(EnclaveError::GenericError(
+            "Key rotation needs a non-zero interval longer than the grace window".to_string(),
+        ));
+    }
+    tokio::spawn(async move {
+        *KEY_RING.write().await = Some(KeyRing {
+            current: RotatedKey {
+                kp: Arc::new(state.eph_kp.copy()),
+                activated_at_ms: now_ms(),
+                attestation: None,
+                handoff_signature: None,
+            },
+            previous: None,
+        });
+        let interval = Duration::from_millis(COEUS_CONFIG.rotation.interval_ms);
+        loop {
+            tokio::time::sleep(interval).await;
+            let outgoing = KEY_RING
+                .read()
+                .await
+                .as_ref()
+                .map(|ring| ring.current.kp.clone())
+                .expect("key ring is initialized");
+            match next_key(&outgoing) {
+                Ok((next, handoff)) => {
+                    audit::record(None, outgoing.public().as_bytes(), &handoff).await;
+                    info!(
+                        "Rotated signing key to {}",
+                        Hex::encode(next.kp.public().as_bytes())
+                    );
+                    if let Some(ring) = KEY_RING.write().await.as_mut() {
+                        ring.rotate(next);
+                    }
+                }
+                // Keep signing with the current key rather than with an unattested one
+                Err(e) => warn!("Key rotation failed, keeping the current key: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// GET /health_check, reporting the current signing key rather than the
+/// one the enclave booted with.
+pub async fn health_check(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<HealthCheckResponse>, EnclaveError> {
+    let (current, _) = signing_keys(&state).await;
+    health_check_for(current.public()).await
+}
+
+/// Endpoint exposing the rotation schedule and the current and previous keys.
+pub async fn get_keys(State(state): State<Arc<AppState>>) -> Json<KeysResponse> {
+    let config = &COEUS_CONFIG.rotation;
+    let ring = KEY_RING.read().await;
+    let Some(ring) = ring.as_ref() else {
+        return Json(KeysResponse {
+            current: KeyInfo {
+                public_key: Hex::encode(state.eph_kp.public().as_bytes()),
+                activated_at_ms: 0,
+                attestation: None,
+                handoff_signature: None,
+            },
+            previous: None,
+            previous_valid_until_ms: None,
+            next_rotation_ms: None,
+        });
+    };
+    let previous = ring.previous_in_grace(now_ms(), config.grace_ms);
+    Json(KeysResponse {
+        current: (&ring.current).into(),
+        previous: previous.map(KeyInfo::from),
+        previous_valid_until_ms: previous.map(|_| ring.current.activated_at_ms + config.grace_ms),
+        next_rotation_ms: Some(ring.current.activated_at_ms + config.interval_ms),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(activated_at_ms: u64) -> RotatedKey {
+        RotatedKey {
+            kp: Arc::new(Ed25519KeyPair::generate(&mut rand::thread_rng())),
+            activated_at_ms,
+            attestation: None,
+            handoff_signature: None,
+        }
+    }
+
+    #[test]
+    fn test_rotate() {
+        let mut ring = KeyRing {
+            current: key(0),
+            previous: None,
+        };
+        assert!(ring.previous_in_grace(10, 100).is_none());
+        assert!(!ring.is_overdue(1_100, 1_000, 100));
+        assert!(ring.is_overdue(1_101, 1_000, 100));
+
+        let first = ring.current.kp.public().clone();
+        ring.rotate(key(1_000));
+        assert_eq!(ring.previous.as_ref().unwrap().kp.public(), &first);
+        assert!(ring.previous_in_grace(1_050, 100).is_some());
+        assert!(ring.previous_in_grace(1_100, 100).is_none());
+
+        // Only one previous key is kept
+        let second = ring.current.kp.public().clone();
+        ring.rotate(key(2_000));
+        assert_eq!(ring.previous.as_ref().unwrap().kp.public(), &second);
+    }
+}
@@ -0,0 +1,390 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Background refresh of the feeds listed in coeus_config.yaml.
+//!
+//! Feeds sharing an interval would otherwise all run at the same instant. With
+//! `stagger` the first run of each feed is offset by its share of its interval
+//! (the i-th of n feeds starts i/n of an interval in), and every later run is
+//! delayed by up to `jitter_bps` of the interval so the feeds do not drift
+//! back into step. Runs are only ever delayed, never brought forward, so a
+//! feed is not run more often than its interval. With `adaptive` the interval
+//! of a feed whose runs are slow is stretched, so that on average its runs
+//! take no more than `max_busy_bps` of it, up to `max_stretch` times the
+//! configured interval, and shrinks back as the runs speed up again. Both are
+//! off by default, runs then start an interval apart.
+
+use super::batch;
+use super::config::COEUS_CONFIG;
+use super::encryption::{self, ResultOutput};
+use super::feed_state::FeedPin;
+use super::submit::{enclave_address, submit_update};
+use super::subscribe;
+use super::{UpdateOracleHttpResponse, parse_feed_id, run_feed};
+use crate::AppState;
+use crate::EnclaveError;
+use crate::catch_panic;
+use axum::Json;
+use axum::extract::Path;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use sui_sdk_types::Address;
+use tokio::sync::RwLock;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+lazy_static::lazy_static! {
+    /// Latest signed payload produced by the scheduler, keyed by feed id.
+    pub static ref SCHEDULED_RESULTS: Arc<RwLock<HashMap<Address, UpdateOracleHttpResponse>>> =
+        Arc::new(RwLock::new(HashMap::new()));
+}
+
+/// A feed the scheduler refreshes on a fixed interval.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledFeed {
+    pub feed_id: String,
+    pub interval_ms: u64,
+    /// Also sign the result metadata, see UpdateOracleRequest.
+    #[serde(default)]
+    pub sign_meta: bool,
+}
+
+/// Scheduler section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchedulerConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub feeds: Vec<ScheduledFeed>,
+    /// Spread the first runs of the feeds over their interval.
+    #[serde(default = "default_stagger")]
+    pub stagger: bool,
+    /// Largest delay of a run with stagger, in basis points of the interval.
+    #[serde(default = "default_jitter_bps")]
+    pub jitter_bps: u64,
+    /// Stretch the interval of feeds whose runs are slow.
+    #[serde(default = "default_adaptive")]
+    pub adaptive: bool,
+    /// Share of its interval a feed may spend running, in basis points.
+    #[serde(default = "default_max_busy_bps")]
+    pub max_busy_bps: u64,
+    /// Longest stretched interval, as a multiple of the configured one.
+    #[serde(default = "default_max_stretch")]
+    pub max_stretch: u32,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            feeds: Vec::new(),
+            stagger: default_stagger(),
+            jitter_bps: default_jitter_bps(),
+            adaptive: default_adaptive(),
+            max_busy_bps: default_max_busy_bps(),
+            max_stretch: default_max_stretch(),
+        }
+    }
+}
+
+fn default_stagger() -> bool {
+    false
+}
+
+fn default_jitter_bps() -> u64 {
+    1000
+}
+
+fn default_adaptive() -> bool {
+    false
+}
+
+fn default_max_busy_bps() -> u64 {
+    2500
+}
+
+fn default_max_stretch() -> u32 {
+    4
+}
+
+/// Weight of the latest run in the average run duration.
+const DURATION_WEIGHT: f64 = 0.3;
+
+/// Offset of the first run of the `index`-th of `count` feeds.
+fn phase(interval: Duration, index: usize, count: usize) -> Duration {
+    interval.mul_f64(index as f64 / count.max(1) as f64)
+}
+
+/// `interval` lengthened by a random delay of up to `jitter_bps` of it.
+fn jittered(interval: Duration, jitter_bps: u64, rng: &mut impl Rng) -> Duration {
+    let max_delay_ms =
+        (interval.as_millis() as u64).saturating_mul(jitter_bps.min(10_000)) / 10_000;
+    if max_delay_ms == 0 {
+        return interval;
+    }
+    interval + Duration::from_millis(rng.gen_range(0..=max_delay_ms))
+}
+
+/// Recent run durations of a feed, and the interval they call for.
+#[derive(Debug, Default)]
+struct Pacing {
+    /// Moving average of the run durations.
+    average_ms: Option<f64>,
+}
+
+impl Pacing {
+    fn record(&mut self, duration: Duration) {
+        let duration_ms = duration.as_secs_f64() * 1000.0;
+        self.average_ms = Some(match self.average_ms {
+            Some(average) => average + DURATION_WEIGHT * (duration_ms - average),
+            None => duration_ms,
+        });
+    }
+
+    /// `base` stretched so that runs take at most `max_busy_bps` of it.
+    fn interval(&self, config: &SchedulerConfig, base: Duration) -> Duration {
+        let Some(average_ms) = self.average_ms.filter(|_| config.adaptive) else {
+            return base;
+        };
+        let busy_share = config.max_busy_bps.clamp(1, 10_000) as f64 / 10_000.0;
+        let needed = Duration::from_secs_f64(average_ms / busy_share / 1000.0);
+        needed.clamp(base, base * config.max_stretch.max(1))
+    }
+}
+
+/// Spawn one refresh task per configured feed. Does nothing unless the
+/// scheduler is enabled in coeus_config.yaml.
+pub fn spawn_scheduler(state: Arc<AppState>) -> Result<(), EnclaveError> {
+    let config = &COEUS_CONFIG.scheduler;
+    if !config.enabled {
+        return Ok(());
+    }
+    if COEUS_CONFIG.push.enabled {
+        info!(
+            "Push mode enabled, gas is paid by enclave address {}",
+            enclave_address(&state)?
+        );
+    }
+
+    for (index, feed) in config.feeds.iter().enumerate() {
+        let feed_id = parse_feed_id(&feed.feed_id)?;
+        if feed.interval_ms == 0 {
+            return Err(EnclaveError::GenericError(format!(
+                "Scheduled feed {} has a zero interval",
+                feed.feed_id
+            )));
+        }
+        let interval = Duration::from_millis(feed.interval_ms);
+        let offset = if config.stagger {
+            phase(interval, index, config.feeds.len())
+        } else {
+            Duration::ZERO
+        };
+        info!(
+            "Scheduling feed {} every {}ms, first run in {}ms",
+            feed_id,
+            feed.interval_ms,
+            offset.as_millis()
+        );
+        tokio::spawn(run_schedule(
+            state.clone(),
+            feed_id,
+            interval,
+            offset,
+            feed.sign_meta,
+        ));
+    }
+    Ok(())
+}
+
+/// Refresh loop for a single feed. Failures are logged and the previous
+/// result is kept until the next successful run. Runs start an interval
+/// apart, or right after the previous one if it took longer. Pacing only
+/// counts the time the feed ran, not the wait for a background slot.
+async fn run_schedule(
+    state: Arc<AppState>,
+    feed_id: Address,
+    interval: Duration,
+    offset: Duration,
+    sign_meta: bool,
+) {
+    let config = &COEUS_CONFIG.scheduler;
+    let mut pacing = Pacing::default();
+    let mut next_run = Instant::now() + offset;
+    loop {
+        tokio::time::sleep_until(next_run).await;
+        let started = Instant::now();
+        let slot = batch::background_slot().await;
+        let ran = refresh(&state, slot, feed_id, sign_meta, "Scheduled").await;
+        pacing.record(ran);
+
+        let paced = pacing.interval(config, interval);
+        if paced > interval {
+            debug!(
+                "Feed {} runs slowly, next run in {}ms",
+                feed_id,
+                paced.as_millis()
+            );
+        }
+        let delay = if config.stagger {
+            jittered(paced, config.jitter_bps, &mut rand::thread_rng())
+        } else {
+            paced
+        };
+        next_run = started + delay;
+    }
+}
+
+/// Run a feed in the background, submit its result in push mode and publish
+/// it to subscribers. Shared by the scheduler and on-chain update requests
+/// (see events.rs), `trigger` starts the log lines. Runs hold one of the
+/// background slots, `slot`, and time out, see batch.rs. Returns how long the
+/// feed ran, without the submission.
+pub async fn refresh(
+    state: &Arc<AppState>,
+    slot: batch::BackgroundSlot,
+    feed_id: Address,
+    sign_meta: bool,
+    trigger: &str,
+) -> Duration {
+    let started = Instant::now();
+    // Run on its own task so a panic fails this run, not the schedule
+    let run = tokio::spawn({
+        let state = state.clone();
+        async move {
+            let run = run_feed(&state, feed_id, sign_meta, None, FeedPin::default());
+            batch::background(slot, run).await
+        }
+    });
+    let result = run.await.unwrap_or_else(|e| match e.try_into_panic() {
+        Ok(panic) => Err(catch_panic::record("scheduler", panic.as_ref())),
+        Err(e) => Err(EnclaveError::Internal(e.to_string())),
+    });
+    let ran = started.elapsed();
+    match result {
+        Ok(response) => {
+            // In push mode the enclave also lands the update on-chain. Outside
+            // the run's timeout, so a submission is never dropped half way
+            if let Err(e) = submit_update(state, feed_id, &response).await {
+                warn!("Failed to submit update for feed {}: {}", feed_id, e);
+            }
+            // Stored before it is published, see subscribe::subscribe_feed.
+            // Results of confidential feeds are only published, sealed.
+            if !encryption::is_confidential(feed_id) {
+                SCHEDULED_RESULTS
+                    .write()
+                    .await
+                    .insert(feed_id, response.clone());
+            }
+            subscribe::publish(feed_id, response);
+        }
+        Err(EnclaveError::FeedPaused { .. }) => {
+            info!("{} feed {} is paused", trigger, feed_id);
+        }
+        Err(EnclaveError::UpdateTooEarly { .. }) => {
+            info!("{} feed {} not yet open for updates", trigger, feed_id);
+        }
+        Err(EnclaveError::UpdateNotRequired { deviation_bps, .. }) => {
+            info!(
+                "{} feed {} unchanged ({}bps), keeping last result",
+                trigger, feed_id, deviation_bps
+            );
+        }
+        Err(e) => warn!("{} run of feed {} failed: {}", trigger, feed_id, e),
+    }
+    ran
+}
+
+/// Endpoint returning the latest signed payload the scheduler produced for a
+/// feed. Confidential feeds have none, their results are only streamed to
+/// subscribers, sealed to the configured recipient.
+pub async fn get_scheduled_result(
+    Path(feed_id): Path<String>,
+) -> Result<Json<ResultOutput>, EnclaveError> {
+    let feed_id = parse_feed_id(&feed_id)?;
+    let response = SCHEDULED_RESULTS
+        .read()
+        .await
+        .get(&feed_id)
+        .cloned()
+        .ok_or_else(|| {
+            EnclaveError::GenericError(format!("No scheduled result for feed {}", feed_id))
+        })?;
+    Ok(Json(encryption::seal_output(feed_id, response, None)?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_scheduler_config() {
+        let yaml = r#"
+enabled: true
+feeds:
+  - feed_id: "0x1"
+    interval_ms: 30000
+  - feed_id: "0x2"
+    interval_ms: 60000
+    sign_meta: true
+"#;
+        let config: SchedulerConfig = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.enabled);
+        assert_eq!(config.feeds.len(), 2);
+        assert_eq!(config.feeds[0].interval_ms, 30000);
+        assert!(!config.feeds[0].sign_meta);
+        assert!(config.feeds[1].sign_meta);
+
+        // Missing section means disabled
+        let config: SchedulerConfig = serde_yaml::from_str("{}").unwrap();
+        assert!(!config.enabled);
+        assert!(config.feeds.is_empty());
+        assert!(!config.stagger && !config.adaptive);
+        assert_eq!(config.jitter_bps, 1000);
+    }
+
+    #[test]
+    fn test_pacing() {
+        let interval = Duration::from_secs(60);
+        assert_eq!(phase(interval, 0, 4), Duration::ZERO);
+        assert_eq!(phase(interval, 3, 4), Duration::from_secs(45));
+
+        let mut rng = rand::thread_rng();
+        // Runs are only delayed
+        for _ in 0..100 {
+            let shifted = jittered(interval, 1000, &mut rng);
+            assert!(shifted >= interval && shifted <= Duration::from_secs(66));
+        }
+        assert_eq!(jittered(interval, 0, &mut rng), interval);
+
+        // Fast runs keep the interval, slow ones stretch it up to max_stretch
+        let config = SchedulerConfig {
+            adaptive: true,
+            ..SchedulerConfig::default()
+        };
+        let mut pacing = Pacing::default();
+        assert_eq!(pacing.interval(&config, interval), interval);
+        pacing.record(Duration::from_secs(1));
+        assert_eq!(pacing.interval(&config, interval), interval);
+        let mut pacing = Pacing::default();
+        pacing.record(Duration::from_secs(20));
+        assert_eq!(pacing.interval(&config, interval), Duration::from_secs(80));
+        pacing.record(Duration::from_secs(600));
+        assert_eq!(pacing.interval(&config, interval), Duration::from_secs(240));
+
+        // And shrink back as they speed up
+        for _ in 0..50 {
+            pacing.record(Duration::from_secs(1));
+        }
+        assert_eq!(pacing.interval(&config, interval), interval);
+
+        let fixed = SchedulerConfig {
+            adaptive: false,
+            ..SchedulerConfig::default()
+        };
+        assert_eq!(pacing.interval(&fixed, interval), interval);
+    }
+}
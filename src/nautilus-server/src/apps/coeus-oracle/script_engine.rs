@@ -0,0 +1,180 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Rhai engines of script executions.
+//!
+//! Creating the standard packages and registering every host function made up
+//! most of the cost of a short script. They are now built into modules once
+//! per thread and shared read-only by the engines of later runs, each of which
+//! only adds what belongs to its run: its result metadata, its dry-run and
+//! object-read budgets, its execution budget and its bundle, with a fresh
+//! Scope. Rhai modules are not Send, so the modules are kept per thread rather
+//! than per process; scripts run on a fixed pool of workers (see
+//! concurrency.rs), so they are built a bounded number of times.
+//!
+//! For the same reason each worker keeps its own LRU cache of compiled
+//! scripts, keyed by the SHA-256 of the code, the second level below the blob
+//! cache: a hot feed is neither downloaded nor parsed again.
+
+use super::blob_cache::LruCache;
+use super::concurrency::{ExecutionConfig, script_deadline};
+use super::config::COEUS_CONFIG;
+use super::{ResultMeta, dry_run, evm, move_value, register_host_fns, register_result_meta_fns};
+use crate::metrics;
+use fastcrypto::hash::{HashFunction, Sha256};
+use rhai::packages::{BasicStringPackage, Package, StandardPackage};
+use rhai::{AST, Dynamic, Engine, EvalAltResult, Module, Shared};
+use std::cell::{OnceCell, RefCell};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+thread_local! {
+    static MODULES: OnceCell<Vec<Shared<Module>>> = const { OnceCell::new() };
+    /// Compiled scripts by code hash, with when they were compiled.
+    static ASTS: RefCell<LruCache<[u8; 32], (Instant, AST)>> =
+        RefCell::new(LruCache::new(COEUS_CONFIG.blob_cache.ast_capacity));
+}
+
+/// Packages and stateless host functions, built from scratch.
+pub fn build_modules() -> Vec<Shared<Module>> {
+    let mut host = Module::new();
+    register_host_fns(&mut host);
+    evm::register_evm_fns(&mut host);
+    host.build_index();
+    vec![
+        StandardPackage::new().as_shared_module(),
+        BasicStringPackage::new().as_shared_module(),
+        host.into(),
+    ]
+}
+
+/// Engine with the packages and the host functions that keep no state.
+pub fn new_engine() -> Engine {
+    let modules = MODULES.with(|modules| modules.get_or_init(build_modules).clone());
+    let mut engine = Engine::new_raw();
+    engine.on_print(|text| println!("{}", text));
+    engine.on_debug(|text, source, position| match source {
+        Some(source) => println!("{} @ {:?} | {}", source, position, text),
+        None => println!("{:?} | {}", position, text),
+    });
+    for module in modules {
+        engine.register_global_module(module);
+    }
+    engine
+}
+
+/// Engine running one script, whose result metadata is collected into `meta`.
+pub fn script_engine(meta: Arc<Mutex<ResultMeta>>) -> Engine {
+    let mut engine = new_engine();
+    register_result_meta_fns(&mut engine, meta);
+    dry_run::register_dry_run_fns(&mut engine);
+    move_value::register_move_value_fns(&mut engine);
+    limit_execution(&mut engine, &COEUS_CONFIG.execution);
+    engine
+}
+
+/// Abort the script of `engine` past the execution budget, which starts now.
+pub fn limit_execution(engine: &mut Engine, config: &ExecutionConfig) {
+    engine.set_max_operations(config.max_operations);
+    if let Some(deadline) = script_deadline(config) {
+        // Reading the clock on every operation would slow scripts down
+        engine.on_progress(move |operations| {
+            (operations % 1024 == 0 && Instant::now() >= deadline)
+                .then(|| Dynamic::from("Script exceeded its execution deadline"))
+        });
+    }
+}
+
+/// Compile `code`, reusing this thread's compiled copy while it is fresh.
+pub fn compile(engine: &Engine, code: &str) -> Result<AST, Box<EvalAltResult>> {
+    let key = Sha256::digest(code.as_bytes()).digest;
+    let max_age = Duration::from_millis(COEUS_CONFIG.blob_cache.ast_max_age_ms);
+    let cached = ASTS
+        .with(|asts| asts.borrow_mut().get(&key))
+        .filter(|(compiled_at, _)| compiled_at.elapsed() < max_age);
+    metrics::record_cache_lookup("ast", cached.is_some());
+    if let Some((_, ast)) = cached {
+        return Ok(ast);
+    }
+    let ast = engine.compile(code)?;
+    ASTS.with(|asts| asts.borrow_mut().insert(key, (Instant::now(), ast.clone())));
+    Ok(ast)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_script_engine() {
+        let meta = Arc::new(Mutex::new(ResultMeta::default()));
+        let engine = script_engine(meta.clone());
+        let joined: String = engine
+            .eval(r#"set_source_count(3); ["a", "b"].join("/")"#)
+            .unwrap();
+        assert_eq!(joined, "a/b");
+        assert_eq!(meta.lock().unwrap().source_count, Some(3));
+
+        // Engines of later runs share the modules but not the run state
+        let other = Arc::new(Mutex::new(ResultMeta::default()));
+        let engine = script_engine(other.clone());
+        let _: () = engine.eval("set_source_count(5)").unwrap();
+        assert_eq!(other.lock().unwrap().source_count, Some(5));
+        assert_eq!(meta.lock().unwrap().source_count, Some(3));
+    }
+
+    #[test]
+    fn test_execution_budget() {
+        let mut engine = new_engine();
+        limit_execution(
+            &mut engine,
+            &ExecutionConfig {
+                max_operations: 10_000,
+                max_duration_ms: 0,
+                ..ExecutionConfig::default()
+            },
+        );
+        assert_eq!(
+            engine
+                .eval::<i64>("let x = 0; for i in 0..10 { x += i } x")
+                .unwrap(),
+            45
+        );
+        let err = engine
+            .eval::<i64>("let x = 0; loop { x += 1 }")
+            .unwrap_err();
+        assert!(matches!(*err, EvalAltResult::ErrorTooManyOperations(_)));
+
+        let mut engine = new_engine();
+        limit_execution(
+            &mut engine,
+            &ExecutionConfig {
+                max_operations: 0,
+                max_duration_ms: 50,
+                ..ExecutionConfig::default()
+            },
+        );
+        let err = engine
+            .eval::<i64>("let x = 0; loop { x += 1 }")
+            .unwrap_err();
+        assert!(matches!(*err, EvalAltResult::ErrorTerminated(..)));
+    }
+
+    #[test]
+    fn test_compile_cache() {
+        let engine = new_engine();
+        let code = "let x = 40; x + 2";
+        let hits = || {
+            metrics::CACHE_LOOKUPS
+                .with_label_values(&["ast", "hit"])
+                .get()
+        };
+        let before = hits();
+        let ast = compile(&engine, code).unwrap();
+        let cached = compile(&engine, code).unwrap();
+        assert!(hits() > before);
+        assert_eq!(engine.eval_ast::<i64>(&ast).unwrap(), 42);
+        assert_eq!(engine.eval_ast::<i64>(&cached).unwrap(), 42);
+        assert!(compile(&engine, "let = ;").is_err());
+    }
+}
@@ -0,0 +1,218 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! POST /sign_digest: co-sign a caller supplied 32 byte digest, for
+//! integrations such as cross-chain relays that need the enclave key to
+//! endorse data computed elsewhere.
+//!
+//! The digest is signed under IntentScope::SignDigest, which no oracle
+//! verifier accepts, so this endpoint cannot be used to forge feed results.
+//! It is disabled by default and only serves requests signed by one of the
+//! configured operator Ed25519 keys. The configuration is part of the
+//! attested image, so operator keys, not shared secrets, gate the endpoint.
+
+use super::APP_ID;
+use super::audit;
+use super::config::COEUS_CONFIG;
+use super::registration;
+use super::replay;
+use super::rotation;
+use crate::common::{IntentMessage, IntentScope, ProcessedDataResponse, to_signed_response};
+use crate::extract::AppJson;
+use crate::{AppState, EnclaveError, admin};
+use axum::Json;
+use axum::extract::State;
+use fastcrypto::ed25519::{Ed25519PublicKey, Ed25519Signature};
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hash::{HashFunction, Sha256};
+use fastcrypto::traits::{KeyPair, ToFromBytes, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Domain of the operator's request signature.
+const REQUEST_DOMAIN: &[u8] = b"coeus-oracle/sign_digest";
+
+/// Admin feature flag turning off `sign_digest.enabled`.
+pub const FLAG: &str = "sign_digest";
+
+/// Sign digest section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignDigestConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Hex Ed25519 public keys allowed to request signatures.
+    #[serde(default)]
+    pub operator_public_keys: Vec<String>,
+    /// Maximum age of a request. Each request is served once, its nonce is
+    /// kept until it is this old.
+    #[serde(default = "default_max_request_age_ms")]
+    pub max_request_age_ms: u64,
+}
+
+impl Default for SignDigestConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            operator_public_keys: vec![],
+            max_request_age_ms: default_max_request_age_ms(),
+        }
+    }
+}
+
+fn default_max_request_age_ms() -> u64 {
+    30_000
+}
+
+/// Request for POST /sign_digest.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignDigestRequest {
+    /// Hex 32 byte digest to sign.
+    pub digest: String,
+    /// When the operator signed the request.
+    pub timestamp_ms: u64,
+    /// Hex Ed25519 public key of the operator.
+    pub operator_public_key: String,
+    /// Hex operator signature over
+    /// `"coeus-oracle/sign_digest" || digest || timestamp_ms (u64 LE)`.
+    pub operator_signature: String,
+}
+
+/// Bytes the operator signs to authorize a request.
+pub fn request_message(digest: &[u8], timestamp_ms: u64) -> Vec<u8> {
+    [REQUEST_DOMAIN, digest, &timestamp_ms.to_le_bytes()].concat()
+}
+
+fn decode_digest(digest: &str) -> Result<Vec<u8>, EnclaveError> {
+    Hex::decode(digest)
+        .ok()
+        .filter(|bytes| bytes.len() == 32)
+        .ok_or_else(|| {
+            EnclaveError::GenericError("Digest must be 32 hex encoded bytes".to_string())
+        })
+}
+
+/// Check the request is recent and signed by an allowed operator. Returns the
+/// request's nonce. A missing, expired or invalid signature is Unauthorized,
+/// a valid key that is not an operator's is Forbidden.
+fn authorize(
+    request: &SignDigestRequest,
+    digest: &[u8],
+    config: &SignDigestConfig,
+    now_ms: u64,
+) -> Result<[u8; 32], EnclaveError> {
+    if now_ms.abs_diff(request.timestamp_ms) > config.max_request_age_ms {
+        return Err(EnclaveError::Unauthorized(
+            "Sign digest request expired".to_string(),
+        ));
+    }
+    let allowed = config
+        .operator_public_keys
+        .iter()
+        .any(|key| key.eq_ignore_ascii_case(&request.operator_public_key));
+    if !allowed {
+        return Err(EnclaveError::Forbidden("Unknown operator key".to_string()));
+    }
+    let public_key = Hex::decode(&request.operator_public_key)
+        .ok()
+        .and_then(|bytes| Ed25519PublicKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| EnclaveError::Unauthorized("Invalid operator key".to_string()))?;
+    let signature = Hex::decode(&request.operator_signature)
+        .ok()
+        .and_then(|bytes| Ed25519Signature::from_bytes(&bytes).ok())
+        .ok_or_else(|| EnclaveError::Unauthorized("Invalid operator signature".to_string()))?;
+    let message = request_message(digest, request.timestamp_ms);
+    public_key
+        .verify(&message, &signature)
+        .map_err(|_| EnclaveError::Unauthorized("Invalid operator signature".to_string()))?;
+    Ok(Sha256::digest([public_key.as_bytes(), &message].concat()).digest)
+}
+
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Endpoint signing an operator approved digest under IntentScope::SignDigest.
+pub async fn sign_digest(
+    State(state): State<Arc<AppState>>,
+    AppJson(request): AppJson<SignDigestRequest>,
+) -> Result<Json<ProcessedDataResponse<IntentMessage<Vec<u8>>>>, EnclaveError> {
+    let config = &COEUS_CONFIG.sign_digest;
+    if !admin::flag_enabled(FLAG, config.enabled) {
+        return Err(EnclaveError::GenericError(
+            "sign_digest is disabled".to_string(),
+        ));
+    }
+    let digest = decode_digest(&request.digest)?;
+    let now = now_ms();
+    let nonce = authorize(&request, &digest, config, now)?;
+    registration::require(&state.config.registration)?;
+    // A captured request is refused until it expires rather than re-signed
+    let expires_at_ms = request
+        .timestamp_ms
+        .saturating_add(config.max_request_age_ms);
+    replay::use_nonce(nonce, expires_at_ms, now).await?;
+
+    let (signing_kp, _) = rotation::signing_keys(&state).await;
+    let signed = to_signed_response(&signing_kp, digest, now, IntentScope::SignDigest, APP_ID);
+    audit::record(None, signing_kp.public().as_bytes(), &signed.response).await;
+    Ok(Json(signed))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fastcrypto::ed25519::Ed25519KeyPair;
+    use fastcrypto::traits::Signer;
+
+    fn request(kp: &Ed25519KeyPair, digest: &[u8], timestamp_ms: u64) -> SignDigestRequest {
+        SignDigestRequest {
+            digest: Hex::encode(digest),
+            timestamp_ms,
+            operator_public_key: Hex::encode(kp.public().as_bytes()),
+            operator_signature: Hex::encode(kp.sign(&request_message(digest, timestamp_ms))),
+        }
+    }
+
+    #[test]
+    fn test_authorize() {
+        let operator = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let config = SignDigestConfig {
+            enabled: true,
+            operator_public_keys: vec![Hex::encode(operator.public().as_bytes())],
+            max_request_age_ms: 1_000,
+        };
+        let digest = [7u8; 32];
+        let now = 1744038900000;
+
+        let nonce = authorize(&request(&operator, &digest, now), &digest, &config, now).unwrap();
+        // Another request by the same operator has another nonce
+        let next = authorize(&request(&operator, &digest, now + 1), &digest, &config, now).unwrap();
+        assert_ne!(nonce, next);
+        // Expired
+        assert!(matches!(
+            authorize(
+                &request(&operator, &digest, now - 2_000),
+                &digest,
+                &config,
+                now
+            ),
+            Err(EnclaveError::Unauthorized(_))
+        ));
+        // Signed for another digest
+        assert!(matches!(
+            authorize(&request(&operator, &[8u8; 32], now), &digest, &config, now),
+            Err(EnclaveError::Unauthorized(_))
+        ));
+        // Not an operator
+        let other = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        assert!(matches!(
+            authorize(&request(&other, &digest, now), &digest, &config, now),
+            Err(EnclaveError::Forbidden(_))
+        ));
+
+        assert!(decode_digest(&Hex::encode([0u8; 31])).is_err());
+    }
+}
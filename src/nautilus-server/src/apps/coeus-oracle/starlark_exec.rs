@@ -0,0 +1,289 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Executor for `CodeExtension::STARLARK` feeds. Starlark has no `while`
+//! loops and no recursion, so a script only loops over the values it iterates,
+//! which makes feed logic easy to audit compared to a general purpose Rhai
+//! script:
+//!
+//! ```python
+//! def median(values):
+//!     values = sorted(values)
+//!     return values[len(values) // 2]
+//!
+//! prices = [
+//!     float(fetch_json("https://api.coingecko.com/api/v3/simple/price?ids=sui&vs_currencies=usd")["sui"]["usd"]),
+//!     float(fetch_json("https://api.binance.com/api/v3/ticker/price?symbol=SUIUSDT")["price"]),
+//! ]
+//! int(median(prices) * 1000000)
+//! ```
+//!
+//! The value of the last statement is the feed result.
+//!
+//! Iterating a large enough range still takes forever, so scripts run under
+//! the execution budget of concurrency.rs: `max_operations` counts statements
+//! and both it and `max_duration_ms` are checked before each statement. A
+//! single expression, such as a comprehension, runs to its end before the next
+//! check.
+
+use super::concurrency::{self, ExecutionConfig, script_deadline};
+use super::config::COEUS_CONFIG;
+use super::evm;
+use super::{
+    ResultMeta, ReturnType, RhaiOutput, convert_rhai_result, http_get_string, json_value_to_dynamic,
+};
+use crate::EnclaveError;
+use serde_json::Value as JsonValue;
+use starlark::codemap::FileSpanRef;
+use starlark::environment::{Globals, GlobalsBuilder, LibraryExtension, Module};
+use starlark::eval::{BeforeStmtFuncDyn, Evaluator};
+use starlark::starlark_module;
+use starlark::syntax::{AstModule, Dialect};
+use starlark::values::{Heap, Value};
+use std::time::Instant;
+
+/// Maximum call depth of a script.
+const MAX_CALLSTACK_SIZE: usize = 64;
+
+#[starlark_module]
+fn oracle_functions(builder: &mut GlobalsBuilder) {
+    /// HTTP GET returning the response body, fails the script on error.
+    fn http_get(#[starlark(require = pos)] url: &str) -> anyhow::Result<String> {
+        http_get_string(url).map_err(anyhow::Error::msg)
+    }
+
+    /// HTTP GET parsing the response body as JSON, fails the script on error.
+    fn fetch_json<'v>(
+        #[starlark(require = pos)] url: &str,
+        heap: &'v Heap,
+    ) -> anyhow::Result<Value<'v>> {
+        let text = http_get_string(url).map_err(anyhow::Error::msg)?;
+        Ok(heap.alloc(serde_json::from_str::<JsonValue>(&text)?))
+    }
+
+    /// EVM eth_call returning the hex return data, see evm.rs.
+    fn eth_call(
+        #[starlark(require = pos)] rpc_url: &str,
+        #[starlark(require = pos)] to: &str,
+        #[starlark(require = pos)] data: &str,
+        block: Option<&str>,
+    ) -> anyhow::Result<String> {
+        let block = block.unwrap_or(&COEUS_CONFIG.evm.block);
+        evm::eth_call(rpc_url, to, data, block).map_err(anyhow::Error::msg)
+    }
+
+    /// EVM storage slot as a hex word, see evm.rs.
+    fn eth_get_storage_at(
+        #[starlark(require = pos)] rpc_url: &str,
+        #[starlark(require = pos)] address: &str,
+        #[starlark(require = pos)] slot: &str,
+        block: Option<&str>,
+    ) -> anyhow::Result<String> {
+        let block = block.unwrap_or(&COEUS_CONFIG.evm.block);
+        evm::eth_get_storage_at(rpc_url, address, slot, block).map_err(anyhow::Error::msg)
+    }
+
+    /// The index-th 32-byte word of ABI encoded data, as hex.
+    fn evm_word(
+        #[starlark(require = pos)] data: &str,
+        #[starlark(require = pos)] index: i32,
+    ) -> anyhow::Result<String> {
+        evm::evm_word(data, index.into()).map_err(anyhow::Error::msg)
+    }
+
+    /// The index-th 32-byte word of ABI encoded data as an integer.
+    fn evm_uint(
+        #[starlark(require = pos)] data: &str,
+        #[starlark(require = pos)] index: i32,
+    ) -> anyhow::Result<i64> {
+        evm::evm_uint(data, index.into()).map_err(anyhow::Error::msg)
+    }
+}
+
+/// Standard Starlark plus top level statements, without `load`.
+fn dialect() -> Dialect {
+    Dialect {
+        enable_load: false,
+        enable_top_level_stmt: true,
+        ..Dialect::Standard
+    }
+}
+
+fn globals() -> Globals {
+    GlobalsBuilder::extended_by(&[
+        LibraryExtension::Json,
+        LibraryExtension::Map,
+        LibraryExtension::Filter,
+    ])
+    .with(oracle_functions)
+    .build()
+}
+
+/// Statements and time left to a script, checked before each statement.
+struct ExecutionBudget {
+    statements_left: Option<u64>,
+    deadline: Option<Instant>,
+}
+
+impl ExecutionBudget {
+    /// Budget of a script starting now.
+    fn new(config: &ExecutionConfig) -> Self {
+        Self {
+            statements_left: (config.max_operations > 0).then_some(config.max_operations),
+            deadline: script_deadline(config),
+        }
+    }
+}
+
+impl<'a, 'e: 'a> BeforeStmtFuncDyn<'a, 'e> for ExecutionBudget {
+    fn call<'v>(
+        &mut self,
+        _span: FileSpanRef,
+        _eval: &mut Evaluator<'v, 'a, 'e>,
+    ) -> starlark::Result<()> {
+        if let Some(left) = &mut self.statements_left {
+            if *left == 0 {
+                return Err(starlark::Error::new_other(anyhow::anyhow!(
+                    "Script exceeded its operation limit"
+                )));
+            }
+            *left -= 1;
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(starlark::Error::new_other(anyhow::anyhow!(
+                "Script exceeded its execution deadline"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Evaluate a script and return the value of its last statement as JSON.
+fn eval_to_json(code: String, config: &ExecutionConfig) -> Result<JsonValue, String> {
+    let ast = AstModule::parse("feed.star", code, &dialect()).map_err(|e| e.to_string())?;
+    let globals = globals();
+    let module = Module::new();
+    let mut eval = Evaluator::new(&module);
+    eval.set_max_callstack_size(MAX_CALLSTACK_SIZE)
+        .map_err(|e| e.to_string())?;
+    let budget: Box<dyn BeforeStmtFuncDyn> = Box::new(ExecutionBudget::new(config));
+    eval.before_stmt_for_dap(budget.into());
+    let value = eval.eval_module(ast, &globals).map_err(|e| e.to_string())?;
+    value.to_json_value().map_err(|e| e.to_string())
+}
+
+/// Execute a Starlark script and convert the result like a Rhai script result.
+/// Runs on a script worker because the host functions wait for their HTTP
+/// calls.
+pub async fn execute_starlark_code(
+    code: &str,
+    expected_type: &ReturnType,
+    strict_types: bool,
+) -> Result<RhaiOutput, EnclaveError> {
+    let code = code.to_string();
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    // Shares the execution slots with Rhai, released when the script returns
+    let slot = concurrency::execution_slot().await?;
+    concurrency::spawn_script(slot, move || {
+        let _ = tx.send(eval_to_json(code, &COEUS_CONFIG.execution));
+    });
+
+    let value = rx
+        .await
+        .map_err(|e| EnclaveError::GenericError(format!("Starlark thread failed: {}", e)))?
+        .map_err(|e| EnclaveError::ScriptError {
+            line: None,
+            message: format!("Starlark execution failed: {}", e),
+        })?;
+    Ok(RhaiOutput {
+        result: convert_rhai_result(json_value_to_dynamic(&value), expected_type, strict_types)?,
+        meta: ResultMeta::default(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::app::ResultValue;
+
+    #[tokio::test]
+    async fn test_execute_starlark_code() {
+        let code = r#"
+def median(values):
+    values = sorted(values)
+    return values[len(values) // 2]
+
+prices = [json.decode('{"p": 3}')["p"], 1, 2]
+median(prices) * 100
+"#;
+        let output = execute_starlark_code(code, &ReturnType::NUMBER, true)
+            .await
+            .unwrap();
+        assert_eq!(output.result, Some(ResultValue::NUMBER(200)));
+
+        let output = execute_starlark_code("'sui' + '/usd'", &ReturnType::STRING, true)
+            .await
+            .unwrap();
+        assert_eq!(
+            output.result,
+            Some(ResultValue::STRING("sui/usd".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_starlark_rejects_unbounded_constructs() {
+        // `while` is not part of the language
+        let code = "x = 0\nwhile True:\n    x += 1\nx";
+        assert!(
+            execute_starlark_code(code, &ReturnType::NUMBER, false)
+                .await
+                .is_err()
+        );
+        // Recursion is a runtime error
+        let code = "def f(n):\n    return f(n + 1)\nf(0)";
+        assert!(
+            execute_starlark_code(code, &ReturnType::NUMBER, false)
+                .await
+                .is_err()
+        );
+        // No load statements
+        assert!(
+            execute_starlark_code("load('x.star', 'y')\n1", &ReturnType::NUMBER, false)
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_starlark_execution_budget() {
+        let code = "x = 0\nfor i in range(1000000000):\n    x += i\nx";
+        let config = ExecutionConfig {
+            max_operations: 1_000,
+            max_duration_ms: 0,
+            ..ExecutionConfig::default()
+        };
+        let err = eval_to_json(code.to_string(), &config).unwrap_err();
+        assert!(err.contains("operation limit"), "{}", err);
+
+        let config = ExecutionConfig {
+            max_operations: 0,
+            max_duration_ms: 50,
+            ..ExecutionConfig::default()
+        };
+        let err = eval_to_json(code.to_string(), &config).unwrap_err();
+        assert!(err.contains("execution deadline"), "{}", err);
+
+        let code = "x = 0\nfor i in range(10):\n    x += i\nx";
+        let config = ExecutionConfig {
+            max_operations: 1_000,
+            ..ExecutionConfig::default()
+        };
+        assert_eq!(
+            eval_to_json(code.to_string(), &config).unwrap(),
+            JsonValue::from(45)
+        );
+    }
+}
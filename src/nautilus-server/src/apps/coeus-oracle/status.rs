@@ -0,0 +1,222 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::conditional::Validators;
+use super::config::COEUS_CONFIG;
+use super::{ResultValue, UpdateOracleHttpResponse, parse_feed_id};
+use crate::EnclaveError;
+use axum::Json;
+use axum::extract::Path;
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use sui_sdk_types::Address;
+use tokio::sync::RwLock;
+
+lazy_static::lazy_static! {
+    /// Execution status per feed, updated on every run_feed call.
+    pub static ref FEED_STATUS: Arc<RwLock<HashMap<Address, FeedStatus>>> =
+        Arc::new(RwLock::new(HashMap::new()));
+}
+
+/// Timings and script errors collected while running a feed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FeedRunStats {
+    /// Time spent reading the feed object from Sui.
+    pub rpc_latency_ms: Option<u64>,
+    /// Time spent downloading the script blob.
+    pub blob_fetch_latency_ms: Option<u64>,
+    /// Time spent executing the script, including its upstream HTTP calls.
+    pub execution_ms: Option<u64>,
+    /// Script failure that was converted into an empty result.
+    pub script_error: Option<String>,
+    /// Id of the request that ran the feed, None for scheduled runs.
+    pub request_id: Option<String>,
+}
+
+/// Health of a single feed as seen by this enclave.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FeedStatus {
+    pub feed_id: String,
+    pub last_execution_timestamp_ms: Option<u64>,
+    pub last_success_timestamp_ms: Option<u64>,
+    pub last_result: Option<ResultValue>,
+    pub last_error: Option<String>,
+    pub last_run: FeedRunStats,
+    pub run_count: u64,
+    pub error_count: u64,
+    /// Runs that signed nothing because the feed was not yet open for
+    /// updates or its value had not moved enough. They are not errors.
+    #[serde(default)]
+    pub skip_count: u64,
+    #[serde(default)]
+    pub last_skip_reason: Option<String>,
+}
+
+/// Entry of the GET /feeds listing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedSummary {
+    pub feed_id: String,
+    /// Refresh interval if the feed is run by the scheduler.
+    pub interval_ms: Option<u64>,
+    pub last_execution_timestamp_ms: Option<u64>,
+    pub healthy: bool,
+}
+
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Record the outcome of a feed run. Runs refused by the update policy are
+/// recorded as skipped rather than failed.
+pub async fn record_run(
+    feed_id: Address,
+    outcome: &Result<UpdateOracleHttpResponse, EnclaveError>,
+    stats: FeedRunStats,
+) {
+    let now = now_ms();
+    let mut statuses = FEED_STATUS.write().await;
+    let status = statuses.entry(feed_id).or_insert_with(|| FeedStatus {
+        feed_id: feed_id.to_string(),
+        ..Default::default()
+    });
+    status.last_execution_timestamp_ms = Some(now);
+    status.run_count += 1;
+    match outcome {
+        Ok(response) => {
+            status.last_success_timestamp_ms = Some(now);
+            status.last_result = response.signed.response.data.result.clone();
+            status.last_error = stats.script_error.clone();
+        }
+        Err(e @ (EnclaveError::UpdateTooEarly { .. } | EnclaveError::UpdateNotRequired { .. })) => {
+            status.skip_count += 1;
+            status.last_skip_reason = Some(e.to_string());
+            status.last_error = None;
+        }
+        Err(e) => status.last_error = Some(e.to_string()),
+    }
+    if status.last_error.is_some() {
+        status.error_count += 1;
+    }
+    status.last_run = stats;
+}
+
+/// Endpoint listing every feed known to this enclave: scheduled feeds from
+/// coeus_config.yaml plus any feed that has been executed.
+pub async fn list_feeds() -> Json<Vec<FeedSummary>> {
+    let statuses = FEED_STATUS.read().await;
+    let mut feeds: BTreeMap<String, FeedSummary> = BTreeMap::new();
+
+    for scheduled in &COEUS_CONFIG.scheduler.feeds {
+        let Ok(feed_id) = Address::from_hex(&scheduled.feed_id) else {
+            continue;
+        };
+        let status = statuses.get(&feed_id);
+        feeds.insert(
+            feed_id.to_string(),
+            FeedSummary {
+                feed_id: feed_id.to_string(),
+                interval_ms: Some(scheduled.interval_ms),
+                last_execution_timestamp_ms: status.and_then(|s| s.last_execution_timestamp_ms),
+                healthy: status.is_none_or(|s| s.last_error.is_none()),
+            },
+        );
+    }
+    for (feed_id, status) in statuses.iter() {
+        feeds
+            .entry(feed_id.to_string())
+            .or_insert_with(|| FeedSummary {
+                feed_id: feed_id.to_string(),
+                interval_ms: None,
+                last_execution_timestamp_ms: status.last_execution_timestamp_ms,
+                healthy: status.last_error.is_none(),
+            });
+    }
+    Json(feeds.into_values().collect())
+}
+
+/// Endpoint returning the execution status of a single feed. The status
+/// changes with every run, so its run count is its ETag.
+pub async fn get_feed_status(
+    Path(feed_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, EnclaveError> {
+    let feed_id = parse_feed_id(&feed_id)?;
+    let statuses = FEED_STATUS.read().await;
+    let status = statuses
+        .get(&feed_id)
+        .ok_or_else(|| EnclaveError::FeedNotFound {
+            feed_id: feed_id.to_string(),
+        })?;
+    let validators = Validators::new(status.run_count, status.last_execution_timestamp_ms);
+    if let Some(not_modified) = validators.not_modified(&headers) {
+        return Ok(not_modified);
+    }
+    let status = status.clone();
+    drop(statuses);
+    Ok(validators.respond(Json(status)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::http::{StatusCode, header};
+
+    #[tokio::test]
+    async fn test_record_run() {
+        let feed_id = Address::from_hex("0x1234").unwrap();
+        let err = Err(EnclaveError::GenericError("boom".to_string()));
+        record_run(feed_id, &err, FeedRunStats::default()).await;
+
+        let response = get_feed_status(Path(feed_id.to_string()), HeaderMap::new())
+            .await
+            .unwrap();
+        let etag = response.headers()[header::ETAG].clone();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let status: FeedStatus = serde_json::from_slice(&body).unwrap();
+        assert_eq!(status.run_count, 1);
+        assert_eq!(status.error_count, 1);
+        assert_eq!(status.last_error.as_deref(), Some("boom"));
+        assert!(status.last_success_timestamp_ms.is_none());
+
+        let Json(feeds) = list_feeds().await;
+        assert!(
+            feeds
+                .iter()
+                .any(|f| f.feed_id == feed_id.to_string() && !f.healthy)
+        );
+
+        // Unchanged until the next run
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, etag);
+        let response = get_feed_status(Path(feed_id.to_string()), headers.clone())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        record_run(feed_id, &err, FeedRunStats::default()).await;
+        let response = get_feed_status(Path(feed_id.to_string()), headers)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Policy refusals are skips, not errors
+        let skipped = Err(EnclaveError::UpdateTooEarly {
+            earliest_allowed_timestamp_ms: 1744038900000,
+            current_timestamp_ms: 1744038800000,
+        });
+        record_run(feed_id, &skipped, FeedRunStats::default()).await;
+        let statuses = FEED_STATUS.read().await;
+        let status = &statuses[&feed_id];
+        assert_eq!((status.run_count, status.error_count), (3, 2));
+        assert_eq!(status.skip_count, 1);
+        assert!(status.last_error.is_none());
+        assert!(status.last_skip_reason.is_some());
+    }
+}
@@ -0,0 +1,526 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::config::COEUS_CONFIG;
+use super::gas_station;
+use super::http_body::read_json_capped;
+use super::{ResultValue, UpdateOracleHttpResponse};
+use crate::AppState;
+use crate::EnclaveError;
+use fastcrypto::encoding::{Base64, Encoding, Hex};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use sui_crypto::SuiSigner;
+use sui_crypto::ed25519::Ed25519PrivateKey;
+use sui_rpc::field::{FieldMask, FieldMaskUtil};
+use sui_rpc::proto::sui::rpc::v2::{ExecuteTransactionRequest, GetEpochRequest, GetObjectRequest};
+use sui_sdk_types::{
+    Address, Argument, Command, GasPayment, Identifier, Input, MoveCall, ObjectReference,
+    ProgrammableTransaction, Transaction, TransactionExpiration, TransactionKind, TypeTag,
+    UserSignature,
+};
+use tokio::sync::Mutex;
+use tonic::{Code, Status};
+use tracing::{info, warn};
+
+lazy_static::lazy_static! {
+    /// Serializes submissions so the enclave never signs two transactions
+    /// using the same gas coin version (which would equivocate and lock it).
+    /// Also held while the gas station merges and splits coins.
+    pub static ref SUBMIT_LOCK: Mutex<()> = Mutex::new(());
+
+    static ref SPONSOR_CLIENT: reqwest::Client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .expect("Failed to create sponsor HTTP client");
+}
+
+const CLOCK_OBJECT_ID: &str = "0x6";
+
+/// Push section of coeus_config.yaml. When enabled the enclave submits
+/// `feed::submit_result` itself, paying gas from its own address unless a
+/// sponsor is configured.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PushConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Published oracle package containing the `feed` module.
+    pub package_id: String,
+    /// Shared oracle::config::Config object.
+    pub config_object_id: String,
+    /// Shared Enclave<T> object the enclave key is registered in.
+    pub enclave_object_id: String,
+    /// Type argument T of the Enclave object, e.g. "0x...::oracle::ORACLE".
+    pub enclave_type: String,
+    #[serde(default = "default_gas_budget")]
+    pub gas_budget: u64,
+    /// Attempts per update before giving up, retried when the fullnode
+    /// rejects the transaction without executing it (see is_retryable).
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// Sponsor paying the gas of submissions instead of the enclave address.
+    #[serde(default)]
+    pub sponsor: Option<SponsorConfig>,
+}
+
+/// Sponsor of push-mode transactions. The enclave builds each transaction
+/// with gas coins of `address` and asks the sponsor service at `url` to
+/// co-sign it. The url host must be listed in allowed_endpoints.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SponsorConfig {
+    /// Treasury address owning the gas coins.
+    pub address: String,
+    pub url: String,
+    /// Environment variable holding a bearer token for the sponsor service.
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+}
+
+/// Body POSTed to the sponsor service.
+#[derive(Debug, Serialize)]
+pub struct SponsorRequest {
+    /// Base64 BCS of the transaction data to co-sign.
+    pub tx_bytes: String,
+    pub sender: String,
+}
+
+/// Sponsor service answer.
+#[derive(Debug, Deserialize)]
+pub struct SponsorResponse {
+    /// Base64 serialized signature of the gas owner.
+    pub signature: String,
+}
+
+fn default_gas_budget() -> u64 {
+    50_000_000
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+/// Why a submission attempt failed.
+#[derive(Debug)]
+pub enum SubmitError {
+    /// The fullnode refused the transaction without executing it, so no gas
+    /// was charged.
+    Rejected(Status),
+    /// The transaction executed and failed, e.g. a Move abort, charging gas.
+    Failed(EnclaveError),
+    /// Anything else, e.g. building or signing the transaction.
+    Other(EnclaveError),
+}
+
+impl From<EnclaveError> for SubmitError {
+    fn from(e: EnclaveError) -> Self {
+        SubmitError::Other(e)
+    }
+}
+
+impl From<SubmitError> for EnclaveError {
+    fn from(e: SubmitError) -> Self {
+        match e {
+            SubmitError::Rejected(status) => {
+                EnclaveError::GenericError(format!("Failed to execute transaction: {}", status))
+            }
+            SubmitError::Failed(e) | SubmitError::Other(e) => e,
+        }
+    }
+}
+
+/// Convert the enclave's fastcrypto keypair to a sui-crypto signing key.
+pub fn sui_signing_key(state: &AppState) -> Result<Ed25519PrivateKey, EnclaveError> {
+    let key_bytes: [u8; 32] = state.eph_kp.as_ref().try_into().map_err(|_| {
+        EnclaveError::GenericError(format!(
+            "Invalid private key length {}, expected 32",
+            state.eph_kp.as_ref().len()
+        ))
+    })?;
+    Ok(Ed25519PrivateKey::new(key_bytes))
+}
+
+/// Sui address that pays for and sends push-mode transactions.
+pub fn enclave_address(state: &AppState) -> Result<Address, EnclaveError> {
+    Ok(sui_signing_key(state)?.public_key().to_address())
+}
+
+/// Address paying for push-mode transactions: the sponsor if any, else the
+/// enclave address.
+pub fn gas_owner(state: &AppState) -> Result<Address, EnclaveError> {
+    match &COEUS_CONFIG.push.sponsor {
+        Some(sponsor) => parse_address(&sponsor.address),
+        None => enclave_address(state),
+    }
+}
+
+/// Submit a signed feed result on-chain. Returns the transaction digest.
+/// Does nothing (returns Ok(None)) when push mode is disabled or the result is empty.
+pub async fn submit_update(
+    state: &AppState,
+    feed_id: Address,
+    response: &UpdateOracleHttpResponse,
+) -> Result<Option<String>, EnclaveError> {
+    let config = &COEUS_CONFIG.push;
+    if !config.enabled {
+        return Ok(None);
+    }
+    // feed::submit_result aborts on empty results, don't waste gas on them
+    let Some(result) = response.signed.response.data.result.clone() else {
+        return Ok(None);
+    };
+
+    let _guard = SUBMIT_LOCK.lock().await;
+    let mut last_error = None;
+    for attempt in 0..config.max_retries.max(1) {
+        if attempt > 0 {
+            tokio::time::sleep(Duration::from_millis(500 * 2u64.pow(attempt - 1))).await;
+        }
+        let owner = gas_owner(state)?;
+        let gas = gas_station::pick(state, owner, config.gas_budget).await?;
+        match try_submit(state, config, feed_id, &result, response, gas.clone()).await {
+            Ok(digest) => {
+                info!("Submitted update for feed {}: {}", feed_id, digest);
+                return Ok(Some(digest));
+            }
+            Err(e) if is_retryable(&e) => {
+                let e = EnclaveError::from(e);
+                warn!(
+                    "Submission for feed {} failed (attempt {}): {}",
+                    feed_id,
+                    attempt + 1,
+                    e
+                );
+                // Retry with another coin, this one may be locked or stale
+                gas_station::suspend(*gas.object_id());
+                last_error = Some(e);
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Err(last_error
+        .unwrap_or_else(|| EnclaveError::GenericError("Transaction submission failed".to_string())))
+}
+
+/// Rejections another attempt with a fresh gas coin can get past: the coin
+/// was locked by another transaction or its version was stale (Aborted,
+/// FailedPrecondition), or no fullnode took the transaction (Unavailable).
+/// Transactions that executed are never retried, they failed on-chain and
+/// would burn gas again, and neither are timeouts, after which the
+/// transaction may have executed.
+fn is_retryable(e: &SubmitError) -> bool {
+    match e {
+        SubmitError::Rejected(status) => matches!(
+            status.code(),
+            Code::Aborted | Code::FailedPrecondition | Code::Unavailable
+        ),
+        SubmitError::Failed(_) | SubmitError::Other(_) => false,
+    }
+}
+
+async fn try_submit(
+    state: &AppState,
+    config: &PushConfig,
+    feed_id: Address,
+    result: &ResultValue,
+    response: &UpdateOracleHttpResponse,
+    gas: ObjectReference,
+) -> Result<String, SubmitError> {
+    let signing_key = sui_signing_key(state)?;
+    let sender = signing_key.public_key().to_address();
+
+    let ptb = build_submit_ptb(state, config, feed_id, result, response).await?;
+    let price = reference_gas_price(state).await?;
+
+    let transaction = Transaction {
+        kind: TransactionKind::ProgrammableTransaction(ptb),
+        sender,
+        gas_payment: GasPayment {
+            objects: vec![gas],
+            owner: gas_owner(state)?,
+            price,
+            budget: config.gas_budget,
+        },
+        expiration: TransactionExpiration::None,
+    };
+    let mut signatures = vec![sign(&signing_key, &transaction)?];
+    if let Some(sponsor) = &config.sponsor {
+        signatures.push(sponsor_signature(sponsor, &transaction).await?);
+    }
+    execute_transaction(state, transaction, signatures).await
+}
+
+/// Signature of the enclave key over `transaction`.
+pub fn sign(
+    signing_key: &Ed25519PrivateKey,
+    transaction: &Transaction,
+) -> Result<UserSignature, EnclaveError> {
+    signing_key
+        .sign_transaction(transaction)
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to sign transaction: {}", e)))
+}
+
+/// Have the sponsor service co-sign `transaction` as its gas owner.
+async fn sponsor_signature(
+    sponsor: &SponsorConfig,
+    transaction: &Transaction,
+) -> Result<UserSignature, EnclaveError> {
+    let tx_bytes = bcs::to_bytes(transaction).map_err(|e| {
+        EnclaveError::GenericError(format!("Failed to serialize transaction: {}", e))
+    })?;
+    let mut request = SPONSOR_CLIENT.post(&sponsor.url).json(&SponsorRequest {
+        tx_bytes: Base64::encode(tx_bytes),
+        sender: transaction.sender.to_string(),
+    });
+    if let Some(env) = &sponsor.api_key_env {
+        let api_key = std::env::var(env)
+            .map_err(|_| EnclaveError::GenericError(format!("Sponsor API key {} not set", env)))?;
+        request = request.bearer_auth(api_key);
+    }
+    let response = request
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| EnclaveError::GenericError(format!("Sponsor request failed: {}", e)))?;
+    let response: SponsorResponse =
+        read_json_capped(response, COEUS_CONFIG.http.max_response_bytes)
+            .await
+            .map_err(|e| EnclaveError::GenericError(format!("Invalid sponsor response: {}", e)))?;
+    decode_signature(&response.signature)
+}
+
+fn decode_signature(signature: &str) -> Result<UserSignature, EnclaveError> {
+    let bytes = Base64::decode(signature)
+        .map_err(|e| EnclaveError::GenericError(format!("Invalid sponsor signature: {}", e)))?;
+    UserSignature::from_bytes(&bytes)
+        .map_err(|e| EnclaveError::GenericError(format!("Invalid sponsor signature: {}", e)))
+}
+
+/// Execute `transaction` with its signatures, returning its digest once it
+/// succeeded.
+pub async fn execute(
+    state: &AppState,
+    transaction: Transaction,
+    signatures: Vec<UserSignature>,
+) -> Result<String, EnclaveError> {
+    Ok(execute_transaction(state, transaction, signatures).await?)
+}
+
+async fn execute_transaction(
+    state: &AppState,
+    transaction: Transaction,
+    signatures: Vec<UserSignature>,
+) -> Result<String, SubmitError> {
+    let digest = transaction.digest().to_string();
+
+    let mut request = ExecuteTransactionRequest::default();
+    request.transaction = Some(transaction.into());
+    request.signatures = signatures.into_iter().map(Into::into).collect();
+    request.read_mask = Some(FieldMask::from_str("effects.status"));
+
+    // Executing the same signed transaction again is idempotent, so it can be
+    // retried on another fullnode
+    let executed = state
+        .sui
+        .call("execute_transaction", |mut client| {
+            let request = request.clone();
+            async move { client.execution_client().execute_transaction(request).await }
+        })
+        .await
+        .map_err(SubmitError::Rejected)?
+        .into_inner();
+
+    let status = executed
+        .transaction
+        .and_then(|tx| tx.effects)
+        .and_then(|effects| effects.status);
+    match status {
+        Some(status) if status.success == Some(true) => Ok(digest),
+        Some(status) => Err(SubmitError::Failed(EnclaveError::GenericError(format!(
+            "Transaction {} failed: {:?}",
+            digest, status.error
+        )))),
+        // Executed, but the outcome is unknown
+        None => Err(SubmitError::Failed(EnclaveError::GenericError(format!(
+            "Transaction {} returned no status",
+            digest
+        )))),
+    }
+}
+
+/// Build the PTB: construct the Result enum, wrap it in an Option and call
+/// feed::submit_result<T>(config, enclave, result, intent_scope, timestamp_ms, signature, feed, clock).
+async fn build_submit_ptb(
+    state: &AppState,
+    config: &PushConfig,
+    feed_id: Address,
+    result: &ResultValue,
+    response: &UpdateOracleHttpResponse,
+) -> Result<ProgrammableTransaction, EnclaveError> {
+    let package = parse_address(&config.package_id)?;
+    let intent = &response.signed.response;
+    let signature = Hex::decode(&response.signed.signature)
+        .map_err(|e| EnclaveError::GenericError(format!("Invalid signature hex: {}", e)))?;
+
+    let (constructor, value) = match result {
+        ResultValue::STRING(s) => ("construct_string_result", bcs::to_bytes(s)),
+        ResultValue::BOOLEAN(b) => ("construct_boolean_result", bcs::to_bytes(b)),
+        ResultValue::NUMBER(n) => ("construct_number_result", bcs::to_bytes(n)),
+        ResultValue::VECTOR(v) => ("construct_vector_result", bcs::to_bytes(v)),
+    };
+    let pure = |bytes: Result<Vec<u8>, bcs::Error>| -> Result<Input, EnclaveError> {
+        bytes
+            .map(|value| Input::Pure { value })
+            .map_err(|e| EnclaveError::GenericError(format!("Failed to encode input: {}", e)))
+    };
+
+    let inputs = vec![
+        pure(value)?,
+        shared_input(state, &config.config_object_id, false).await?,
+        shared_input(state, &config.enclave_object_id, false).await?,
+        pure(bcs::to_bytes(&(intent.intent as u8)))?,
+        pure(bcs::to_bytes(&intent.timestamp_ms))?,
+        pure(bcs::to_bytes(&signature))?,
+        shared_input(state, &feed_id.to_string(), true).await?,
+        shared_input(state, CLOCK_OBJECT_ID, false).await?,
+    ];
+
+    let result_type = parse_type_tag(&format!("{}::feed::Result", package))?;
+    let enclave_type = parse_type_tag(&config.enclave_type)?;
+    let commands = vec![
+        Command::MoveCall(MoveCall {
+            package,
+            module: identifier("feed")?,
+            function: identifier(constructor)?,
+            type_arguments: vec![],
+            arguments: vec![Argument::Input(0)],
+        }),
+        Command::MoveCall(MoveCall {
+            package: parse_address("0x1")?,
+            module: identifier("option")?,
+            function: identifier("some")?,
+            type_arguments: vec![result_type],
+            arguments: vec![Argument::Result(0)],
+        }),
+        Command::MoveCall(MoveCall {
+            package,
+            module: identifier("feed")?,
+            function: identifier("submit_result")?,
+            type_arguments: vec![enclave_type],
+            arguments: vec![
+                Argument::Input(1),
+                Argument::Input(2),
+                Argument::Result(1),
+                Argument::Input(3),
+                Argument::Input(4),
+                Argument::Input(5),
+                Argument::Input(6),
+                Argument::Input(7),
+            ],
+        }),
+    ];
+    Ok(ProgrammableTransaction { inputs, commands })
+}
+
+/// Resolve a shared object's initial version for use as a PTB input.
+async fn shared_input(
+    state: &AppState,
+    object_id: &str,
+    mutable: bool,
+) -> Result<Input, EnclaveError> {
+    let object_id = parse_address(object_id)?;
+    let request = GetObjectRequest::new(&object_id).with_read_mask(FieldMask::from_str("owner"));
+    let object = state
+        .sui
+        .call("get_object", |mut client| {
+            let request = request.clone();
+            async move { client.ledger_client().get_object(request).await }
+        })
+        .await
+        .map_err(|e| {
+            EnclaveError::GenericError(format!("Failed to fetch object {}: {}", object_id, e))
+        })?
+        .into_inner()
+        .object;
+    let initial_shared_version = object
+        .and_then(|obj| obj.owner)
+        .and_then(|owner| owner.version)
+        .ok_or_else(|| EnclaveError::GenericError(format!("Object {} is not shared", object_id)))?;
+    Ok(Input::Shared {
+        object_id,
+        initial_shared_version,
+        mutable,
+    })
+}
+
+pub async fn reference_gas_price(state: &AppState) -> Result<u64, EnclaveError> {
+    let request =
+        GetEpochRequest::latest().with_read_mask(FieldMask::from_str("reference_gas_price"));
+    state
+        .sui
+        .call("get_epoch", |mut client| {
+            let request = request.clone();
+            async move { client.ledger_client().get_epoch(request).await }
+        })
+        .await
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to fetch gas price: {}", e)))?
+        .into_inner()
+        .epoch
+        .and_then(|epoch| epoch.reference_gas_price)
+        .ok_or_else(|| EnclaveError::GenericError("No reference gas price".to_string()))
+}
+
+fn parse_address(s: &str) -> Result<Address, EnclaveError> {
+    Address::from_hex(s)
+        .map_err(|e| EnclaveError::GenericError(format!("Invalid address {}: {}", s, e)))
+}
+
+pub fn parse_type_tag(s: &str) -> Result<TypeTag, EnclaveError> {
+    s.parse()
+        .map_err(|e| EnclaveError::GenericError(format!("Invalid type {}: {:?}", s, e)))
+}
+
+pub fn identifier(s: &str) -> Result<Identifier, EnclaveError> {
+    Identifier::new(s)
+        .map_err(|e| EnclaveError::GenericError(format!("Invalid identifier {}: {:?}", s, e)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sui_sdk_types::PersonalMessage;
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(is_retryable(&SubmitError::Rejected(Status::aborted(
+            "Object 0x1 is locked by another transaction"
+        ))));
+        assert!(is_retryable(&SubmitError::Rejected(
+            Status::failed_precondition("ObjectVersionUnavailableForConsumption")
+        )));
+        assert!(is_retryable(&SubmitError::Rejected(Status::unavailable(
+            "No Sui fullnode is enabled"
+        ))));
+        // May have executed
+        assert!(!is_retryable(&SubmitError::Rejected(
+            Status::deadline_exceeded("timeout")
+        )));
+        // Executed and charged gas, whatever the message says
+        assert!(!is_retryable(&SubmitError::Failed(
+            EnclaveError::GenericError("Object 0x1 is locked, version unavailable".to_string())
+        )));
+        assert!(!is_retryable(&SubmitError::Other(
+            EnclaveError::GenericError("Invalid address".to_string())
+        )));
+    }
+
+    #[test]
+    fn test_decode_signature() {
+        let key = Ed25519PrivateKey::new([7; 32]);
+        let message = PersonalMessage(b"sponsored".as_slice().into());
+        let signature = key.sign_personal_message(&message).unwrap();
+        let encoded = Base64::encode(signature.to_bytes());
+        assert_eq!(decode_signature(&encoded).unwrap(), signature);
+        assert!(decode_signature("not base64!").is_err());
+        assert!(decode_signature(&Base64::encode([1, 2, 3])).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! GET /subscribe/{feed_id}: Server-Sent Events stream of the payloads the
+//! scheduler signs for a feed, so relayers can submit them without polling
+//! /scheduled_results.
+//!
+//! The latest scheduled result, if any, is sent first. Every event is an
+//! `update` whose data is the same JSON as /scheduled_results (encrypted for
+//! feeds with a recipient) and whose id is the signed timestamp_ms. A
+//! subscriber too slow to keep up gets a `lagged` event with the number of
+//! skipped updates and continues from the newest ones.
+
+use super::config::COEUS_CONFIG;
+use super::encryption;
+use super::scheduler::{SCHEDULED_RESULTS, SchedulerConfig};
+use super::{UpdateOracleHttpResponse, parse_feed_id};
+use crate::EnclaveError;
+use axum::extract::Path;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use std::convert::Infallible;
+use std::time::Duration;
+use sui_sdk_types::Address;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::{Stream, StreamExt};
+
+/// Updates kept for subscribers that fall behind, across all feeds.
+const UPDATE_BUFFER: usize = 64;
+
+/// Interval of the keep-alive comments that hold idle connections open.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+lazy_static::lazy_static! {
+    static ref UPDATES: broadcast::Sender<(Address, UpdateOracleHttpResponse)> =
+        broadcast::channel(UPDATE_BUFFER).0;
+}
+
+/// What a subscriber receives for its feed.
+enum FeedUpdate {
+    Signed(UpdateOracleHttpResponse),
+    /// Updates skipped because the subscriber fell behind.
+    Lagged(u64),
+}
+
+/// Send a payload signed by the scheduler to the feed's subscribers.
+pub fn publish(feed_id: Address, response: UpdateOracleHttpResponse) {
+    // Fails only when nobody is subscribed
+    let _ = UPDATES.send((feed_id, response));
+}
+
+fn is_scheduled(config: &SchedulerConfig, feed_id: Address) -> bool {
+    config.enabled
+        && config
+            .feeds
+            .iter()
+            .any(|feed| parse_feed_id(&feed.feed_id).ok() == Some(feed_id))
+}
+
+/// `latest` followed by the updates of `feed_id` received on `receiver`.
+fn feed_updates(
+    feed_id: Address,
+    latest: Option<UpdateOracleHttpResponse>,
+    receiver: broadcast::Receiver<(Address, UpdateOracleHttpResponse)>,
+) -> impl Stream<Item = FeedUpdate> {
+    let updates = BroadcastStream::new(receiver);
+    tokio_stream::iter(latest.map(|response| Ok((feed_id, response))))
+        .chain(updates)
+        .filter_map(move |update| match update {
+            Ok((id, response)) if id == feed_id => Some(FeedUpdate::Signed(response)),
+            Ok(_) => None,
+            Err(BroadcastStreamRecvError::Lagged(skipped)) => Some(FeedUpdate::Lagged(skipped)),
+        })
+}
+
+fn to_event(feed_id: Address, update: FeedUpdate) -> Event {
+    let response = match update {
+        FeedUpdate::Signed(response) => response,
+        FeedUpdate::Lagged(skipped) => {
+            return Event::default().event("lagged").data(skipped.to_string());
+        }
+    };
+    let id = response.signed.response.timestamp_ms.to_string();
+    let json = encryption::seal_output(feed_id, response, None).and_then(|output| {
+        serde_json::to_string(&output)
+            .map_err(|e| EnclaveError::GenericError(format!("Failed to serialize result: {}", e)))
+    });
+    match json {
+        Ok(json) => Event::default().event("update").id(id).data(json),
+        Err(e) => Event::default().event("error").data(e.to_string()),
+    }
+}
+
+/// Endpoint streaming the payloads the scheduler signs for a feed.
+pub async fn subscribe_feed(
+    Path(feed_id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, EnclaveError> {
+    let feed_id = parse_feed_id(&feed_id)?;
+    if !is_scheduled(&COEUS_CONFIG.scheduler, feed_id) {
+        return Err(EnclaveError::InvalidRequest(format!(
+            "Feed {} is not scheduled, it has no updates to subscribe to",
+            feed_id
+        )));
+    }
+    // Subscribe before reading the latest result so no update is missed. One
+    // landing in between is sent twice, with the same id.
+    let receiver = UPDATES.subscribe();
+    let latest = SCHEDULED_RESULTS.read().await.get(&feed_id).cloned();
+    let events =
+        feed_updates(feed_id, latest, receiver).map(move |update| Ok(to_event(feed_id, update)));
+    Ok(Sse::new(events).keep_alive(KeepAlive::new().interval(KEEP_ALIVE)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::scheduler::ScheduledFeed;
+    use super::super::{APP_ID, ResultValue, UpdateOracleResponse};
+    use super::*;
+    use crate::common::{IntentScope, to_signed_response};
+    use fastcrypto::ed25519::Ed25519KeyPair;
+    use fastcrypto::traits::KeyPair;
+
+    fn signed(timestamp_ms: u64) -> UpdateOracleHttpResponse {
+        let kp = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        UpdateOracleHttpResponse {
+            signed: to_signed_response(
+                &kp,
+                UpdateOracleResponse {
+                    result: Some(ResultValue::NUMBER(42)),
+                },
+                timestamp_ms,
+                IntentScope::ProcessData,
+                APP_ID,
+            ),
+            result_meta: None,
+            bls_signature: None,
+            previous_key_signature: None,
+            audit: None,
+            feed_state: None,
+        }
+    }
+
+    fn timestamp(update: Option<FeedUpdate>) -> u64 {
+        match update {
+            Some(FeedUpdate::Signed(response)) => response.signed.response.timestamp_ms,
+            Some(FeedUpdate::Lagged(skipped)) => panic!("lagged by {}", skipped),
+            None => panic!("stream ended"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_feed_updates() {
+        let feed_id = Address::from_hex("0x1").unwrap();
+        let other_feed_id = Address::from_hex("0x2").unwrap();
+
+        let mut updates = Box::pin(feed_updates(feed_id, Some(signed(1)), UPDATES.subscribe()));
+        publish(other_feed_id, signed(2));
+        publish(feed_id, signed(3));
+        assert_eq!(timestamp(updates.next().await), 1);
+        // Other feeds are filtered out
+        assert_eq!(timestamp(updates.next().await), 3);
+
+        let config = SchedulerConfig {
+            enabled: true,
+            feeds: vec![ScheduledFeed {
+                feed_id: "0x1".to_string(),
+                interval_ms: 1000,
+                sign_meta: false,
+            }],
+            ..SchedulerConfig::default()
+        };
+        assert!(is_scheduled(&config, feed_id));
+        assert!(!is_scheduled(&config, other_feed_id));
+    }
+}
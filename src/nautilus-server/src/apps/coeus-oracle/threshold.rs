@@ -0,0 +1,318 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! K-of-N threshold signing across enclave replicas.
+//!
+//! A coordinator sends the same process_data request with a shared
+//! `round_timestamp_ms` to N instances. Each instance fetches and executes the
+//! feed independently and, if the round timestamp is at most
+//! `max_clock_skew_ms` behind its own clock, signs under it, so instances
+//! that compute the same value sign the same intent message. A round
+//! timestamp ahead of the clock is refused, so a coordinator cannot get
+//! results signed for a time that has not come yet. POST /threshold/aggregate collects the partial BLS
+//! signatures and only returns an aggregated proof when at least `threshold`
+//! registered attestors agree on the value. The proof is submitted with
+//! `oracle::feed::submit_aggregated_result`.
+
+use super::UpdateOracleResponse;
+use super::bls;
+use super::config::COEUS_CONFIG;
+use crate::EnclaveError;
+use crate::common::IntentMessage;
+use crate::extract::AppJson;
+use axum::Json;
+use fastcrypto::bls12381::min_sig::{BLS12381PublicKey, BLS12381Signature};
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{ToFromBytes, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Threshold section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThresholdConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Number of agreeing signers required (K).
+    #[serde(default = "default_threshold")]
+    pub threshold: usize,
+    /// Hex BLS public keys of the N replicas, in the order of the on-chain
+    /// Attestors object so the signer indices match.
+    #[serde(default)]
+    pub attestors: Vec<String>,
+    /// Maximum age of a round timestamp by the enclave clock.
+    #[serde(default = "default_max_clock_skew_ms")]
+    pub max_clock_skew_ms: u64,
+}
+
+impl Default for ThresholdConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: default_threshold(),
+            attestors: vec![],
+            max_clock_skew_ms: default_max_clock_skew_ms(),
+        }
+    }
+}
+
+fn default_threshold() -> usize {
+    1
+}
+
+fn default_max_clock_skew_ms() -> u64 {
+    5_000
+}
+
+/// A partial result of one replica, as returned by its process_data.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PartialResult {
+    pub response: IntentMessage<UpdateOracleResponse>,
+    /// Hex BLS signature of the replica over `response`.
+    pub bls_signature: String,
+    /// Hex BLS public key of the replica (GET /bls_attestation).
+    pub public_key: String,
+}
+
+/// Request for POST /threshold/aggregate.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ThresholdAggregateRequest {
+    pub partials: Vec<PartialResult>,
+}
+
+/// Response for POST /threshold/aggregate, the arguments of
+/// `submit_aggregated_result`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ThresholdAggregateResponse {
+    /// The intent message the signers agreed on.
+    pub response: IntentMessage<UpdateOracleResponse>,
+    /// Strictly increasing attestor indices of the signers.
+    pub signers: Vec<u64>,
+    /// Hex aggregated BLS signature.
+    pub signature: String,
+}
+
+/// Timestamp to sign a round under: the coordinator's round timestamp when
+/// threshold mode and BLS signing are enabled and it is within the allowed
+/// clock skew.
+pub fn round_timestamp(round_timestamp_ms: Option<u64>, now_ms: u64) -> Result<u64, EnclaveError> {
+    let Some(round) = round_timestamp_ms else {
+        return Ok(now_ms);
+    };
+    let config = &COEUS_CONFIG.threshold;
+    if !config.enabled {
+        return Err(EnclaveError::GenericError(
+            "Threshold signing is not enabled".to_string(),
+        ));
+    }
+    // Partials are BLS signatures, without them the round cannot be aggregated
+    if !bls::enabled() {
+        return Err(EnclaveError::GenericError(
+            "Threshold signing needs BLS signing, which is not enabled".to_string(),
+        ));
+    }
+    check_round(round, now_ms, config.max_clock_skew_ms)
+}
+
+/// `round` if it is not ahead of `now_ms` and at most `max_skew_ms` behind.
+fn check_round(round: u64, now_ms: u64, max_skew_ms: u64) -> Result<u64, EnclaveError> {
+    if round > now_ms {
+        return Err(EnclaveError::GenericError(format!(
+            "Round timestamp {} is ahead of the enclave clock {}",
+            round, now_ms
+        )));
+    }
+    if now_ms - round > max_skew_ms {
+        return Err(EnclaveError::GenericError(format!(
+            "Round timestamp {} is more than {}ms behind the enclave clock {}",
+            round, max_skew_ms, now_ms
+        )));
+    }
+    Ok(round)
+}
+
+fn parse_public_key(hex: &str) -> Option<BLS12381PublicKey> {
+    Hex::decode(hex)
+        .ok()
+        .and_then(|bytes| BLS12381PublicKey::from_bytes(&bytes).ok())
+}
+
+/// Verify the partials against the attestor set and aggregate the signatures
+/// of the largest group agreeing on one message, if it has `threshold`
+/// members. Partials by unknown keys, with invalid signatures or repeating a
+/// signer are ignored. Of groups of the same size, the one with the smallest
+/// signed message wins, so every aggregator picks the same one.
+pub fn aggregate_agreeing(
+    partials: Vec<PartialResult>,
+    attestors: &[BLS12381PublicKey],
+    threshold: usize,
+) -> Result<ThresholdAggregateResponse, EnclaveError> {
+    // Signed message -> (message, signer index -> signature)
+    let mut groups: BTreeMap<
+        Vec<u8>,
+        (IntentMessage<UpdateOracleResponse>, BTreeMap<u64, String>),
+    > = BTreeMap::new();
+    for partial in partials {
+        let Some(index) = parse_public_key(&partial.public_key)
+            .and_then(|pk| attestors.iter().position(|attestor| attestor == &pk))
+        else {
+            continue;
+        };
+        let message = bcs::to_bytes(&partial.response).expect("should not fail");
+        let valid = Hex::decode(&partial.bls_signature)
+            .ok()
+            .and_then(|bytes| BLS12381Signature::from_bytes(&bytes).ok())
+            .is_some_and(|sig| attestors[index].verify(&message, &sig).is_ok());
+        if !valid {
+            continue;
+        }
+        groups
+            .entry(message)
+            .or_insert_with(|| (partial.response, BTreeMap::new()))
+            .1
+            .entry(index as u64)
+            .or_insert(partial.bls_signature);
+    }
+
+    let agreed = groups
+        .into_iter()
+        .max_by(|(message_a, (_, signers_a)), (message_b, (_, signers_b))| {
+            signers_a
+                .len()
+                .cmp(&signers_b.len())
+                .then_with(|| message_b.cmp(message_a))
+        })
+        .map(|(_, group)| group);
+    let agreeing = agreed.as_ref().map_or(0, |(_, signers)| signers.len());
+    let Some((response, signers)) = agreed.filter(|_| agreeing >= threshold) else {
+        return Err(EnclaveError::GenericError(format!(
+            "No agreement: at most {} of {} attestors signed the same value, {} required",
+            agreeing,
+            attestors.len(),
+            threshold
+        )));
+    };
+    let signatures: Vec<String> = signers.values().cloned().collect();
+    Ok(ThresholdAggregateResponse {
+        response,
+        signers: signers.into_keys().collect(),
+        signature: bls::aggregate(&signatures)?,
+    })
+}
+
+/// Endpoint aggregating the partial results of a round into a K-of-N proof.
+pub async fn aggregate_threshold(
+    AppJson(request): AppJson<ThresholdAggregateRequest>,
+) -> Result<Json<ThresholdAggregateResponse>, EnclaveError> {
+    let config = &COEUS_CONFIG.threshold;
+    if !config.enabled {
+        return Err(EnclaveError::GenericError(
+            "Threshold signing is not enabled".to_string(),
+        ));
+    }
+    let attestors = config
+        .attestors
+        .iter()
+        .map(|hex| {
+            parse_public_key(hex).ok_or_else(|| {
+                EnclaveError::GenericError(format!("Invalid attestor key in config: {}", hex))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Json(aggregate_agreeing(
+        request.partials,
+        &attestors,
+        config.threshold,
+    )?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::app::{APP_ID, ResultValue};
+    use crate::common::IntentScope;
+    use fastcrypto::bls12381::min_sig::BLS12381KeyPair;
+    use fastcrypto::traits::{KeyPair, Signer};
+
+    fn partial(kp: &BLS12381KeyPair, value: u64) -> PartialResult {
+        let response = IntentMessage::new(
+            UpdateOracleResponse {
+                result: Some(ResultValue::NUMBER(value)),
+            },
+            1744038900000,
+            IntentScope::ProcessData,
+            APP_ID,
+        );
+        let message = bcs::to_bytes(&response).unwrap();
+        PartialResult {
+            response,
+            bls_signature: Hex::encode(kp.sign(&message)),
+            public_key: Hex::encode(kp.public().as_bytes()),
+        }
+    }
+
+    #[test]
+    fn test_aggregate_agreeing() {
+        let kps: Vec<BLS12381KeyPair> = (0..4)
+            .map(|_| BLS12381KeyPair::generate(&mut rand::thread_rng()))
+            .collect();
+        let attestors: Vec<BLS12381PublicKey> = kps.iter().map(|kp| kp.public().clone()).collect();
+
+        // 3 of 4 agree, the outlier and a duplicate are ignored
+        let partials = vec![
+            partial(&kps[3], 42),
+            partial(&kps[0], 42),
+            partial(&kps[1], 41),
+            partial(&kps[2], 42),
+            partial(&kps[0], 42),
+        ];
+        let aggregated = aggregate_agreeing(partials, &attestors, 3).unwrap();
+        assert_eq!(aggregated.signers, vec![0, 2, 3]);
+        let message = bcs::to_bytes(&aggregated.response).unwrap();
+        let signers: Vec<BLS12381PublicKey> = aggregated
+            .signers
+            .iter()
+            .map(|&i| attestors[i as usize].clone())
+            .collect();
+        assert!(bls::verify_aggregate(&aggregated.signature, &signers, &message).is_ok());
+
+        // Split vote
+        let partials = vec![
+            partial(&kps[0], 1),
+            partial(&kps[1], 2),
+            partial(&kps[2], 1),
+        ];
+        assert!(aggregate_agreeing(partials, &attestors, 3).is_err());
+
+        // Unknown signers don't count
+        let outsider = BLS12381KeyPair::generate(&mut rand::thread_rng());
+        let partials = vec![partial(&kps[0], 1), partial(&outsider, 1)];
+        assert!(aggregate_agreeing(partials, &attestors, 2).is_err());
+
+        // A signature over a different value doesn't count
+        let mut forged = partial(&kps[1], 2);
+        forged.response = partial(&kps[1], 1).response;
+        let partials = vec![partial(&kps[0], 1), forged];
+        assert!(aggregate_agreeing(partials, &attestors, 2).is_err());
+
+        // Ties go to the same group whatever the order of the partials
+        let tied = |order: [usize; 4]| {
+            let partials = order
+                .iter()
+                .map(|&i| partial(&kps[i], if i < 2 { 7 } else { 8 }))
+                .collect();
+            aggregate_agreeing(partials, &attestors, 2).unwrap().signers
+        };
+        assert_eq!(tied([0, 1, 2, 3]), tied([3, 2, 1, 0]));
+        assert_eq!(tied([2, 0, 3, 1]), tied([1, 3, 0, 2]));
+    }
+
+    #[test]
+    fn test_check_round() {
+        let now = 1744038900000;
+        assert_eq!(check_round(now, now, 5000).unwrap(), now);
+        assert_eq!(check_round(now - 5000, now, 5000).unwrap(), now - 5000);
+        assert!(check_round(now - 5001, now, 5000).is_err());
+        // Never ahead of the clock
+        assert!(check_round(now + 1, now, 5000).is_err());
+    }
+}
@@ -0,0 +1,212 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Off-chain verification of process_data responses, the same checks
+//! `oracle::feed::submit_result` makes, so relayers and indexers can reject a
+//! bad response before paying gas. Used by the `coeus-verify` binary.
+
+use super::payload_codec::encode_payload;
+use super::{APP_ID, UpdateOracleHttpResponse, UpdateOracleResponse};
+use crate::EnclaveError;
+use crate::common::IntentScope;
+use fastcrypto::ed25519::{Ed25519PublicKey, Ed25519Signature};
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{ToFromBytes, VerifyingKey};
+
+/// Checks beyond the signature.
+#[derive(Debug, Clone, Default)]
+pub struct VerifyOptions {
+    /// Current time. Responses signed after it are refused, as on-chain, and
+    /// the response age is checked against `max_age_ms`.
+    pub now_ms: Option<u64>,
+    /// Maximum response age, the feed's max_update_time_ms on-chain.
+    pub max_age_ms: Option<u64>,
+    /// How far a response may be signed ahead of `now_ms`, for the clock
+    /// difference between the enclave and the verifier.
+    pub max_clock_skew_ms: u64,
+    /// Accept a result without a value. The Move module rejects them.
+    pub allow_empty_result: bool,
+}
+
+/// A response that passed verification.
+#[derive(Debug)]
+pub struct VerifiedResult {
+    pub response: UpdateOracleResponse,
+    pub timestamp_ms: u64,
+    /// Only the previous key's signature verified, the public key was
+    /// registered before the last rotation.
+    pub signed_by_previous_key: bool,
+}
+
+fn invalid(message: impl Into<String>) -> EnclaveError {
+    EnclaveError::GenericError(message.into())
+}
+
+fn verify_signature(public_key: &Ed25519PublicKey, message: &[u8], signature: &str) -> bool {
+    Hex::decode(signature)
+        .ok()
+        .and_then(|bytes| Ed25519Signature::from_bytes(&bytes).ok())
+        .is_some_and(|signature| public_key.verify(message, &signature).is_ok())
+}
+
+/// Verify a process_data response against the hex public key registered
+/// on-chain. The signature is checked over the canonical payload encoding,
+/// so the message can be trusted field by field.
+pub fn verify_response(
+    response: &UpdateOracleHttpResponse,
+    public_key: &str,
+    options: &VerifyOptions,
+) -> Result<VerifiedResult, EnclaveError> {
+    let public_key = Hex::decode(public_key)
+        .ok()
+        .and_then(|bytes| Ed25519PublicKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| invalid("Invalid public key"))?;
+    let message = &response.signed.response;
+
+    if message.intent != IntentScope::ProcessData {
+        return Err(invalid(format!(
+            "Intent scope {:?} is not accepted by oracle feeds",
+            message.intent
+        )));
+    }
+    if message.app_id != APP_ID {
+        return Err(invalid(format!("Unexpected app id {}", message.app_id)));
+    }
+    if message.data.result.is_none() && !options.allow_empty_result {
+        return Err(invalid("Response has no result"));
+    }
+    let latest_ms = options
+        .now_ms
+        .map(|now_ms| now_ms.saturating_add(options.max_clock_skew_ms));
+    if latest_ms.is_some_and(|latest_ms| message.timestamp_ms > latest_ms) {
+        return Err(invalid(format!(
+            "Response signed at {} is in the future",
+            message.timestamp_ms
+        )));
+    }
+    let age_ms = options
+        .now_ms
+        .map(|now_ms| now_ms.saturating_sub(message.timestamp_ms));
+    if let Some(max_age_ms) = options
+        .max_age_ms
+        .filter(|&max| age_ms.is_some_and(|age| age > max))
+    {
+        return Err(invalid(format!(
+            "Response signed at {} is older than {}ms",
+            message.timestamp_ms, max_age_ms
+        )));
+    }
+
+    let signing_payload = encode_payload(message.intent, message.timestamp_ms, &message.data);
+    let signed_by_previous_key =
+        if verify_signature(&public_key, &signing_payload, &response.signed.signature) {
+            false
+        } else if response
+            .previous_key_signature
+            .as_deref()
+            .is_some_and(|signature| verify_signature(&public_key, &signing_payload, signature))
+        {
+            true
+        } else {
+            return Err(invalid("Signature does not verify against the public key"));
+        };
+
+    Ok(VerifiedResult {
+        response: message.data.clone(),
+        timestamp_ms: message.timestamp_ms,
+        signed_by_previous_key,
+    })
+}
+
+/// Parse a process_data response JSON and verify it.
+pub fn verify_response_json(
+    json: &str,
+    public_key: &str,
+    options: &VerifyOptions,
+) -> Result<VerifiedResult, EnclaveError> {
+    let response: UpdateOracleHttpResponse =
+        serde_json::from_str(json).map_err(|e| invalid(format!("Invalid response JSON: {}", e)))?;
+    verify_response(&response, public_key, options)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::app::ResultValue;
+    use crate::common::to_signed_response;
+    use fastcrypto::ed25519::Ed25519KeyPair;
+    use fastcrypto::traits::KeyPair;
+
+    fn signed_json(
+        kp: &Ed25519KeyPair,
+        intent: IntentScope,
+        result: Option<ResultValue>,
+    ) -> String {
+        let signed = to_signed_response(
+            kp,
+            UpdateOracleResponse { result },
+            1744038900000,
+            intent,
+            APP_ID,
+        );
+        serde_json::to_string(&UpdateOracleHttpResponse {
+            signed,
+            result_meta: None,
+            bls_signature: None,
+            previous_key_signature: None,
+            audit: None,
+            feed_state: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_verify_response() {
+        let kp = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let public_key = Hex::encode(kp.public().as_bytes());
+        let options = VerifyOptions::default();
+
+        let json = signed_json(&kp, IntentScope::ProcessData, Some(ResultValue::NUMBER(42)));
+        let verified = verify_response_json(&json, &public_key, &options).unwrap();
+        assert_eq!(verified.response.result, Some(ResultValue::NUMBER(42)));
+        assert!(!verified.signed_by_previous_key);
+
+        // Wrong key
+        let other = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let other_key = Hex::encode(other.public().as_bytes());
+        assert!(verify_response_json(&json, &other_key, &options).is_err());
+
+        // Tampered result
+        let tampered = json.replace("42", "43");
+        assert!(verify_response_json(&tampered, &public_key, &options).is_err());
+
+        // Test runs and empty results are not submittable
+        let json = signed_json(&kp, IntentScope::ExecuteCode, Some(ResultValue::NUMBER(1)));
+        assert!(verify_response_json(&json, &public_key, &options).is_err());
+        let json = signed_json(&kp, IntentScope::ProcessData, None);
+        assert!(verify_response_json(&json, &public_key, &options).is_err());
+
+        // Too old
+        let json = signed_json(&kp, IntentScope::ProcessData, Some(ResultValue::NUMBER(1)));
+        let options = VerifyOptions {
+            now_ms: Some(1744038900000 + 10_000),
+            max_age_ms: Some(5_000),
+            max_clock_skew_ms: 0,
+            allow_empty_result: false,
+        };
+        assert!(verify_response_json(&json, &public_key, &options).is_err());
+
+        // Signed in the future, beyond the tolerated skew
+        let options = VerifyOptions {
+            now_ms: Some(1744038900000 - 2_000),
+            max_clock_skew_ms: 1_000,
+            ..VerifyOptions::default()
+        };
+        assert!(verify_response_json(&json, &public_key, &options).is_err());
+        let options = VerifyOptions {
+            max_clock_skew_ms: 2_000,
+            ..options
+        };
+        assert!(verify_response_json(&json, &public_key, &options).is_ok());
+    }
+}
@@ -0,0 +1,105 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! GET /version: build of the running server and the values it accepts, so
+//! clients can feature-detect instead of relying on errors.
+
+use super::enclave_info::GIT_REVISION;
+use super::{APP_ID, CodeExtension, ReturnType};
+use crate::network::{self, ActiveNetwork};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+/// Commit time of the build in unix seconds, passed by the Makefile as
+/// SOURCE_DATE_EPOCH so the image stays reproducible.
+const BUILD_TIMESTAMP: Option<&str> = option_env!("SOURCE_DATE_EPOCH");
+
+/// Feed script languages, keep in sync with CodeExtension.
+const CODE_EXTENSIONS: [CodeExtension; 3] = [
+    CodeExtension::RHAI,
+    CodeExtension::JSON_CONFIG,
+    CodeExtension::STARLARK,
+];
+
+/// Result types, keep in sync with ReturnType.
+const RETURN_TYPES: [ReturnType; 4] = [
+    ReturnType::STRING,
+    ReturnType::BOOLEAN,
+    ReturnType::NUMBER,
+    ReturnType::VECTOR,
+];
+
+/// Cargo features of the binary that change what it serves.
+const FEATURES: &[&str] = &[
+    #[cfg(feature = "twitter-example")]
+    "twitter-example",
+    #[cfg(feature = "weather-example")]
+    "weather-example",
+    #[cfg(feature = "seal-example")]
+    "seal-example",
+    #[cfg(feature = "coeus-oracle")]
+    "coeus-oracle",
+    #[cfg(feature = "ra-tls")]
+    "ra-tls",
+];
+
+/// Response for GET /version.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VersionResponse {
+    /// Crate version.
+    pub version: String,
+    /// Git commit the image was built from.
+    pub git_revision: String,
+    /// Unix seconds of the built commit, unset outside the image build.
+    pub build_timestamp: Option<u64>,
+    /// APP_ID signed into the intent messages.
+    pub app_id: String,
+    /// Sui network feed objects are read from.
+    pub network: Option<ActiveNetwork>,
+    /// Cargo features compiled in.
+    pub features: Vec<String>,
+    /// Script languages a feed may use.
+    pub code_extensions: Vec<CodeExtension>,
+    /// Result types a feed may declare.
+    pub return_types: Vec<ReturnType>,
+}
+
+fn version() -> VersionResponse {
+    VersionResponse {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_revision: GIT_REVISION.to_string(),
+        build_timestamp: BUILD_TIMESTAMP.and_then(|timestamp| timestamp.parse().ok()),
+        app_id: APP_ID.to_string(),
+        network: network::active(),
+        features: FEATURES.iter().map(|feature| feature.to_string()).collect(),
+        code_extensions: CODE_EXTENSIONS.to_vec(),
+        return_types: RETURN_TYPES.to_vec(),
+    }
+}
+
+/// Endpoint reporting the build and supported values of the server.
+pub async fn get_version() -> Json<VersionResponse> {
+    Json(version())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_version() {
+        let version = serde_json::to_value(version()).unwrap();
+        assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+        assert!(
+            version["features"]
+                .as_array()
+                .unwrap()
+                .contains(&"coeus-oracle".into())
+        );
+        assert_eq!(
+            version["code_extensions"],
+            serde_json::json!(["RHAI", "JSON_CONFIG", "STARLARK"])
+        );
+        assert_eq!(version["return_types"][3], "VECTOR");
+    }
+}
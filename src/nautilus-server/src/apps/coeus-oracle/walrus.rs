@@ -0,0 +1,280 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::blob_cache::{BLOB_CACHE, CachedBlob};
+use super::config::COEUS_CONFIG;
+use super::http_body::read_body_capped;
+use crate::EnclaveError;
+use crate::metrics;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::warn;
+
+lazy_static::lazy_static! {
+    /// Client shared by all blob downloads, so connections to the aggregators
+    /// are reused.
+    static ref WALRUS_CLIENT: reqwest::Client = reqwest::Client::builder()
+        .timeout(Duration::from_millis(COEUS_CONFIG.walrus.timeout_ms))
+        .build()
+        .expect("Failed to create walrus HTTP client");
+}
+
+/// Walrus section of coeus_config.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalrusConfig {
+    /// Aggregator base URLs in priority order. Hosts must be in allowed_endpoints.yaml.
+    #[serde(default = "default_aggregators")]
+    pub aggregators: Vec<String>,
+    /// Per-aggregator request timeout.
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Require two aggregators to serve identical bytes before executing.
+    #[serde(default)]
+    pub quorum: bool,
+    /// Refuse feeds that do not name a certified, unexpired Blob object.
+    #[serde(default)]
+    pub require_certification: bool,
+    /// Addresses besides the feed creator allowed to own a feed's Blob
+    /// object, e.g. a publisher uploading code for others.
+    #[serde(default)]
+    pub blob_owners: Vec<String>,
+    /// Walrus System shared object, used to read the current epoch.
+    #[serde(default = "default_system_object_id")]
+    pub system_object_id: String,
+    /// Package defining `blob::Blob`, the package of the System object when
+    /// empty. Blob objects of any other type are refused.
+    #[serde(default)]
+    pub package_id: String,
+    /// Largest blob downloaded, larger downloads are aborted once the limit is hit.
+    #[serde(default = "default_max_blob_bytes")]
+    pub max_blob_bytes: u64,
+}
+
+impl Default for WalrusConfig {
+    fn default() -> Self {
+        Self {
+            aggregators: default_aggregators(),
+            timeout_ms: default_timeout_ms(),
+            quorum: false,
+            require_certification: false,
+            blob_owners: vec![],
+            system_object_id: default_system_object_id(),
+            package_id: String::new(),
+            max_blob_bytes: default_max_blob_bytes(),
+        }
+    }
+}
+
+fn default_aggregators() -> Vec<String> {
+    vec!["https://aggregator.walrus-testnet.walrus.space".to_string()]
+}
+
+fn default_system_object_id() -> String {
+    // Walrus testnet
+    "0x6c2547cbbc38025cf3adac45f63cb0a8d12ecf777cdc75a4971612bf97fdf6af".to_string()
+}
+
+fn default_timeout_ms() -> u64 {
+    10_000
+}
+
+fn default_max_blob_bytes() -> u64 {
+    4 * 1024 * 1024
+}
+
+/// Aggregators to use. Only the list in coeus_config.yaml, which the
+/// attestation covers, is used: the host environment cannot change it.
+pub fn aggregators() -> &'static [String] {
+    &COEUS_CONFIG.walrus.aggregators
+}
+
+/// Check that at least one aggregator answers, trying them in priority order
+/// and stopping at the first that does. Any non-5xx response counts, since
+/// blob reads fail over to the next aggregator.
+pub async fn probe_aggregators() -> Result<(), String> {
+    let mut errors = Vec::new();
+    for aggregator in aggregators() {
+        match WALRUS_CLIENT.get(aggregator).send().await {
+            Ok(response) if !response.status().is_server_error() => return Ok(()),
+            Ok(response) => errors.push(format!("{}: status {}", aggregator, response.status())),
+            Err(e) => errors.push(format!("{}: {}", aggregator, e)),
+        }
+    }
+    Err(format!("no aggregator reachable: {}", errors.join("; ")))
+}
+
+fn blob_url(aggregator: &str, blob_id: &str) -> Result<Url, EnclaveError> {
+    Url::parse(&format!(
+        "{}/v1/blobs/{}",
+        aggregator.trim_end_matches('/'),
+        blob_id
+    ))
+    .map_err(|e| aggregator_error(aggregator, blob_id, format!("invalid URL: {}", e)))
+}
+
+/// Error for a failed request to `aggregator`.
+fn aggregator_error(aggregator: &str, blob_id: &str, e: impl std::fmt::Display) -> EnclaveError {
+    EnclaveError::BlobFetchFailed {
+        blob_id: blob_id.to_string(),
+        message: format!("{}: {}", aggregator, e),
+    }
+}
+
+/// Download from a single aggregator. If a cached copy is given, its ETag is
+/// sent as If-None-Match and a 304 response returns the cached bytes. A 304
+/// to an unconditional request is an error.
+async fn fetch_from(
+    client: &reqwest::Client,
+    aggregator: &str,
+    blob_id: &str,
+    cached: Option<&CachedBlob>,
+) -> Result<(Vec<u8>, Option<String>), EnclaveError> {
+    let url = blob_url(aggregator, blob_id)?;
+    let mut request = client.get(url);
+    if let Some(etag) = cached.and_then(|c| c.etag.as_deref()) {
+        request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+    }
+    let response = request.send().await.map_err(|e| {
+        if e.is_timeout() {
+            EnclaveError::UpstreamTimeout {
+                upstream: aggregator.to_string(),
+            }
+        } else {
+            aggregator_error(aggregator, blob_id, e)
+        }
+    })?;
+
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        return match cached {
+            Some(cached) => Ok((cached.data.clone(), cached.etag.clone())),
+            None => Err(aggregator_error(
+                aggregator,
+                blob_id,
+                "304 Not Modified to an unconditional request",
+            )),
+        };
+    }
+    let response = response
+        .error_for_status()
+        .map_err(|e| aggregator_error(aggregator, blob_id, e))?;
+    let etag = response
+        .headers()
+        .get(reqwest::header::ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let bytes = read_body_capped(response, COEUS_CONFIG.walrus.max_blob_bytes)
+        .await
+        .map_err(|e| aggregator_error(aggregator, blob_id, e))?;
+    Ok((bytes, etag))
+}
+
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Download a blob, serving it from the blob cache while fresh and
+/// revalidating it with a conditional request once it is older than max_age_ms.
+pub async fn fetch_blob(blob_id: &str) -> Result<Vec<u8>, EnclaveError> {
+    let now = now_ms();
+    let cached = BLOB_CACHE.lock().await.get(&blob_id.to_string());
+    let fresh = cached.as_ref().filter(|c| c.is_fresh(now));
+    metrics::record_cache_lookup("blob", fresh.is_some());
+    if let Some(cached) = fresh {
+        return Ok(cached.data.clone());
+    }
+
+    let (data, etag) = fetch_uncached(blob_id, cached.as_ref()).await?;
+    BLOB_CACHE.lock().await.insert(
+        blob_id.to_string(),
+        CachedBlob {
+            data: data.clone(),
+            etag,
+            fetched_at_ms: now,
+        },
+    );
+    Ok(data)
+}
+
+/// Download a blob, trying aggregators in order until one succeeds. With
+/// quorum enabled, keeps going until two aggregators returned identical bytes.
+/// Quorum requests are never conditional: a 304 would return the cached copy,
+/// which the vote would then compare against itself.
+/// Fails with UpstreamTimeout if every aggregator timed out.
+async fn fetch_uncached(
+    blob_id: &str,
+    cached: Option<&CachedBlob>,
+) -> Result<(Vec<u8>, Option<String>), EnclaveError> {
+    let config = &COEUS_CONFIG.walrus;
+
+    let mut errors = Vec::new();
+    let mut all_timed_out = true;
+    let mut first: Option<(String, Vec<u8>)> = None;
+    let revalidate = cached.filter(|_| !config.quorum);
+    for aggregator in aggregators() {
+        match fetch_from(&WALRUS_CLIENT, aggregator, blob_id, revalidate).await {
+            Ok(fetched) if !config.quorum => return Ok(fetched),
+            Ok((blob, etag)) => match &first {
+                None => first = Some((aggregator.clone(), blob)),
+                Some((_, agreed)) if *agreed == blob => return Ok((blob, etag)),
+                Some((other, _)) => {
+                    return Err(EnclaveError::BlobFetchFailed {
+                        blob_id: blob_id.to_string(),
+                        message: format!(
+                            "Aggregators {} and {} served different content",
+                            other, aggregator
+                        ),
+                    });
+                }
+            },
+            Err(e) => {
+                warn!("Failed to fetch blob {}: {}", blob_id, e);
+                all_timed_out &= matches!(e, EnclaveError::UpstreamTimeout { .. });
+                errors.push(e.to_string());
+            }
+        }
+    }
+
+    if first.is_some() {
+        all_timed_out = false;
+        errors.push("quorum requires two aggregators to agree".to_string());
+    }
+    if all_timed_out && !errors.is_empty() {
+        return Err(EnclaveError::UpstreamTimeout {
+            upstream: "walrus aggregators".to_string(),
+        });
+    }
+    Err(EnclaveError::BlobFetchFailed {
+        blob_id: blob_id.to_string(),
+        message: format!("all aggregators failed: {}", errors.join("; ")),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_blob_url() {
+        let url = blob_url("https://aggregator.example.com/", "abc").unwrap();
+        assert_eq!(url.as_str(), "https://aggregator.example.com/v1/blobs/abc");
+        assert!(blob_url("not a url", "abc").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_from_errors() {
+        let client = reqwest::Client::new();
+        for aggregator in ["not a url", "http://127.0.0.1:1"] {
+            match fetch_from(&client, aggregator, "abc", None).await {
+                Err(EnclaveError::BlobFetchFailed { blob_id, message }) => {
+                    assert_eq!(blob_id, "abc");
+                    assert!(message.starts_with(aggregator));
+                }
+                other => panic!("expected BlobFetchFailed, got {:?}", other.map(|_| ())),
+            }
+        }
+    }
+}
@@ -0,0 +1,121 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::UpdateOracleHttpResponse;
+use super::config::COEUS_CONFIG;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hmac::{HmacKey, hmac_sha3_256};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use sui_sdk_types::Address;
+use tracing::{info, warn};
+
+/// Header carrying the hex HMAC-SHA3-256 of the request body.
+pub const SIGNATURE_HEADER: &str = "X-Coeus-Signature";
+
+lazy_static::lazy_static! {
+    static ref WEBHOOK_CLIENT: reqwest::Client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .expect("Failed to create webhook HTTP client");
+}
+
+/// Webhooks for a single feed. Hosts must also be listed in allowed_endpoints.yaml.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub feed_id: String,
+    pub urls: Vec<String>,
+    /// Environment variable holding the HMAC secret, so it is not baked into the image.
+    pub secret_env: String,
+}
+
+/// Body POSTed to each webhook URL.
+#[derive(Serialize)]
+pub struct WebhookPayload<'a> {
+    pub feed_id: String,
+    #[serde(flatten)]
+    pub response: &'a UpdateOracleHttpResponse,
+}
+
+/// Hex HMAC-SHA3-256 of `body` under `secret`.
+pub fn sign_body(secret: &[u8], body: &[u8]) -> Result<String, String> {
+    let key = HmacKey::from_bytes(secret).map_err(|e| format!("Invalid HMAC key: {}", e))?;
+    Ok(Hex::encode(hmac_sha3_256(&key, body).digest))
+}
+
+/// POST a computed result to the webhooks configured for the feed.
+/// Runs in the background so webhook latency never delays the response.
+pub fn notify(feed_id: Address, response: &UpdateOracleHttpResponse) {
+    let hooks: Vec<&WebhookConfig> = COEUS_CONFIG
+        .webhooks
+        .iter()
+        .filter(|hook| Address::from_hex(&hook.feed_id).ok() == Some(feed_id))
+        .collect();
+    if hooks.is_empty() {
+        return;
+    }
+
+    let body = match serde_json::to_vec(&WebhookPayload {
+        feed_id: feed_id.to_string(),
+        response,
+    }) {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("Failed to serialize webhook payload for {}: {}", feed_id, e);
+            return;
+        }
+    };
+
+    for hook in hooks {
+        let secret = match std::env::var(&hook.secret_env) {
+            Ok(secret) => secret,
+            Err(_) => {
+                warn!(
+                    "Webhook secret {} not set, skipping webhooks for {}",
+                    hook.secret_env, feed_id
+                );
+                continue;
+            }
+        };
+        let signature = match sign_body(secret.as_bytes(), &body) {
+            Ok(signature) => signature,
+            Err(e) => {
+                warn!("Failed to sign webhook body for {}: {}", feed_id, e);
+                continue;
+            }
+        };
+        for url in hook.urls.clone() {
+            let body = body.clone();
+            let signature = signature.clone();
+            tokio::spawn(async move {
+                let result = WEBHOOK_CLIENT
+                    .post(&url)
+                    .header("Content-Type", "application/json")
+                    .header(SIGNATURE_HEADER, signature)
+                    .body(body)
+                    .send()
+                    .await
+                    .and_then(|resp| resp.error_for_status());
+                match result {
+                    Ok(_) => info!("Webhook {} notified for feed {}", url, feed_id),
+                    Err(e) => warn!("Webhook {} failed for feed {}: {}", url, feed_id, e),
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sign_body() {
+        let sig = sign_body(b"secret", b"{\"a\":1}").unwrap();
+        assert_eq!(sig.len(), 64);
+        // Deterministic for the same key and body
+        assert_eq!(sig, sign_body(b"secret", b"{\"a\":1}").unwrap());
+        assert_ne!(sig, sign_body(b"other", b"{\"a\":1}").unwrap());
+        assert_ne!(sig, sign_body(b"secret", b"{\"a\":2}").unwrap());
+    }
+}
@@ -2,25 +2,13 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
 
 use axum::extract::State;
 use axum::Json;
-use fastcrypto::ed25519::Ed25519KeyPair;
-use fastcrypto::encoding::{Base64, Encoding, Hex};
-use fastcrypto::traits::{KeyPair, Signer};
-use rand::thread_rng;
-use seal_sdk::types::{FetchKeyRequest, KeyId};
-use seal_sdk::{
-    genkey, seal_decrypt_all_objects, signed_message, signed_request, Certificate, ElGamalSecretKey,
-};
-use sui_sdk_types::{
-    Argument, Command, Identifier, Input, MoveCall, ObjectId as ObjectID, PersonalMessage,
-    ProgrammableTransaction,
-};
 use tokio::sync::RwLock;
 
 use super::types::*;
+use crate::seal_store::{fetch_key_request, unseal};
 use crate::{AppState, EnclaveError};
 
 lazy_static::lazy_static! {
@@ -32,11 +20,6 @@ lazy_static::lazy_static! {
         serde_yaml::from_str(config_str)
             .expect("Failed to parse seal_config.yaml")
     };
-    /// Encryption secret key generated initialized on startup.
-    pub static ref ENCRYPTION_KEYS: (ElGamalSecretKey, seal_sdk::types::ElGamalPublicKey, seal_sdk::types::ElgamalVerificationKey) = {
-        genkey(&mut thread_rng())
-    };
-
     /// Secret plaintext decrypted and set in enclave here when
     /// /complete_parameter_load finishes. This is the weather
     /// API key in this example, change it for your application.
@@ -58,77 +41,14 @@ pub async fn init_parameter_load(
             "API key already set".to_string(),
         ));
     }
-    // Generate the session and create certificate.
-    let session = Ed25519KeyPair::generate(&mut thread_rng());
-    let session_vk = session.public();
-    let creation_time = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map_err(|e| EnclaveError::GenericError(format!("Time error: {}", e)))?
-        .as_millis() as u64;
-    let ttl_min = 10;
-    let message = signed_message(
-        SEAL_CONFIG.package_id.to_string(),
-        session_vk,
-        creation_time,
-        ttl_min,
-    );
-
-    // Convert fastcrypto keypair to sui-crypto for signing.
-    let sui_private_key = {
-        let priv_key_bytes = state.eph_kp.as_ref();
-        let key_bytes: [u8; 32] = priv_key_bytes
-            .try_into()
-            .expect("Invalid private key length");
-        sui_crypto::ed25519::Ed25519PrivateKey::new(key_bytes)
-    };
-
-    // Sign personal message.
-    let signature = {
-        use sui_crypto::SuiSigner;
-        sui_private_key
-            .sign_personal_message(&PersonalMessage(message.as_bytes().into()))
-            .map_err(|e| {
-                EnclaveError::GenericError(format!("Failed to sign personal message: {}", e))
-            })?
-    };
-
-    // Create certificate with enclave's ephemeral key's address and session vk.
-    let certificate = Certificate {
-        user: sui_private_key.public_key().to_address(),
-        session_vk: session_vk.clone(),
-        creation_time,
-        ttl_min,
-        signature,
-        mvr_name: None,
-    };
-
-    // Create PTB for seal_approve of package with all key IDs.
-    let ptb = create_ptb(
-        SEAL_CONFIG.package_id,
+    let encoded_request = fetch_key_request(
+        &state.eph_kp,
+        &SEAL_CONFIG,
         request.enclave_object_id,
         request.initial_shared_version,
         request.ids,
-    )
-    .await
-    .map_err(|e| EnclaveError::GenericError(format!("Failed to create PTB: {}", e)))?;
-
-    // Load the encryption public key and verification key.
-    let (_enc_secret, enc_key, enc_verification_key) = &*ENCRYPTION_KEYS;
-
-    // Create the FetchKeyRequest.
-    let request_message = signed_request(&ptb, enc_key, enc_verification_key);
-    let request_signature = session.sign(&request_message);
-    let request = FetchKeyRequest {
-        ptb: Base64::encode(bcs::to_bytes(&ptb).expect("should not fail")),
-        enc_key: enc_key.clone(),
-        enc_verification_key: enc_verification_key.clone(),
-        request_signature,
-        certificate,
-    };
-
-    Ok(Json(InitParameterLoadResponse {
-        encoded_request: Hex::encode(bcs::to_bytes(&request).expect("should not fail")),
-    }))
+    )?;
+    Ok(Json(InitParameterLoadResponse { encoded_request }))
 }
 
 /// This endpoint accepts a list of encrypted objects and encoded seal responses,
@@ -147,15 +67,12 @@ pub async fn complete_parameter_load(
         ));
     }
 
-    // Load the encryption secret key and try decrypting all encrypted objects.
-    let (enc_secret, _enc_key, _enc_verification_key) = &*ENCRYPTION_KEYS;
-    let decrypted_results = seal_decrypt_all_objects(
-        enc_secret,
+    // Try decrypting all encrypted objects with this boot's encryption key.
+    let decrypted_results = unseal(
+        &SEAL_CONFIG,
         &request.seal_responses,
         &request.encrypted_objects,
-        &SEAL_CONFIG.server_pk_map,
-    )
-    .map_err(|e| EnclaveError::GenericError(format!("Failed to decrypt objects: {}", e)))?;
+    )?;
 
     // The first secret is the weather API key, store it.
     if let Some(api_key_bytes) = decrypted_results.first() {
@@ -176,47 +93,3 @@ pub async fn complete_parameter_load(
         dummy_secrets: decrypted_results[1..].to_vec(),
     }))
 }
-
-/// Helper function that creates a PTB with multiple commands for
-/// the given IDs and the enclave shared object.
-async fn create_ptb(
-    package_id: ObjectID,
-    enclave_object_id: ObjectID,
-    initial_shared_version: u64,
-    ids: Vec<KeyId>,
-) -> Result<ProgrammableTransaction, Box<dyn std::error::Error>> {
-    let mut inputs = vec![];
-    let mut commands = vec![];
-
-    // Create inputs for all IDs.
-    for id in ids.iter() {
-        inputs.push(Input::Pure {
-            value: bcs::to_bytes(id)?,
-        });
-    }
-
-    // Add the shared enclave object as the last input.
-    let enclave_input_idx = inputs.len();
-    inputs.push(Input::Shared {
-        object_id: enclave_object_id,
-        initial_shared_version,
-        mutable: false,
-    });
-
-    // Create multiple commands with each one calling seal_approve
-    // with a different ID and the shared enclave object.
-    for (idx, _id) in ids.iter().enumerate() {
-        let move_call = MoveCall {
-            package: package_id,
-            module: Identifier::new("seal_policy")?,
-            function: Identifier::new("seal_approve")?,
-            type_arguments: vec![],
-            arguments: vec![
-                Argument::Input(idx as u16),               // ID input
-                Argument::Input(enclave_input_idx as u16), // Enclave object
-            ],
-        };
-        commands.push(Command::MoveCall(move_call));
-    }
-    Ok(ProgrammableTransaction { inputs, commands })
-}
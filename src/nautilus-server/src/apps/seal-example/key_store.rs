@@ -0,0 +1,151 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Persistent signing key, sealed with Seal so it survives enclave restarts.
+//!
+//! On first boot the host calls /seal/generate_signing_key: the enclave
+//! generates the key, encrypts it to the Seal policy package and returns the
+//! encrypted object for the host to store. On restart the host runs the usual
+//! parameter load with the key's id, and /seal/unseal_signing_key decrypts the
+//! key back into memory. The registered on-chain public key stays the same.
+//!
+//! Seal encryption is public-key, so the host could seal a key it generated
+//! itself. Only a key generated in this boot is ever attested, and an
+//! unsealed key is only loaded if it is the pk of an `Enclave<WEATHER>`
+//! object, which only an attested key can be registered as.
+//!
+//! The ephemeral key is still generated on every boot, it only authorizes
+//! seal_approve for the bootstrap and never signs results.
+
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::Json;
+use fastcrypto::ed25519::{Ed25519KeyPair, Ed25519PrivateKey};
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{KeyPair, ToFromBytes};
+use tokio::sync::RwLock;
+
+use super::endpoints::SEAL_CONFIG;
+use super::types::*;
+use crate::common::{attestation_for, enclave_rng};
+use crate::seal_store::{enclave_type, seal, unseal, verify_registered};
+use crate::{AppState, EnclaveError};
+
+lazy_static::lazy_static! {
+    /// Signing key for results, set once it is generated or unsealed.
+    pub static ref SIGNING_KEY: Arc<RwLock<Option<(Arc<Ed25519KeyPair>, KeyOrigin)>>> = Arc::new(RwLock::new(None));
+}
+
+/// How the loaded signing key got into the enclave.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyOrigin {
+    /// Generated in this boot, never seen by the host.
+    Generated,
+    /// Unsealed, and checked against its on-chain registration.
+    Unsealed,
+}
+
+/// The loaded signing key, or an error until the bootstrap completed.
+pub async fn signing_key() -> Result<Arc<Ed25519KeyPair>, EnclaveError> {
+    SIGNING_KEY
+        .read()
+        .await
+        .as_ref()
+        .map(|(kp, _)| kp.clone())
+        .ok_or_else(|| {
+            EnclaveError::GenericError(
+                "Signing key not loaded. Please generate or unseal it first.".to_string(),
+            )
+        })
+}
+
+async fn set_signing_key(kp: Ed25519KeyPair, origin: KeyOrigin) -> Result<String, EnclaveError> {
+    let mut guard = SIGNING_KEY.write().await;
+    if guard.is_some() {
+        return Err(EnclaveError::GenericError(
+            "Signing key already loaded".to_string(),
+        ));
+    }
+    let public_key = Hex::encode(kp.public().as_bytes());
+    *guard = Some((Arc::new(kp), origin));
+    Ok(public_key)
+}
+
+/// First boot: generate the signing key and seal it under `id`. Host-only.
+pub async fn generate_signing_key(
+    State(_state): State<Arc<AppState>>,
+    Json(request): Json<GenerateSigningKeyRequest>,
+) -> Result<Json<GenerateSigningKeyResponse>, EnclaveError> {
+    if SIGNING_KEY.read().await.is_some() {
+        return Err(EnclaveError::GenericError(
+            "Signing key already loaded".to_string(),
+        ));
+    }
+    let kp = Ed25519KeyPair::generate(&mut enclave_rng());
+    let encrypted_object = seal(&SEAL_CONFIG, request.id, kp.as_ref().to_vec())?;
+
+    // Only keep the key once its sealed copy exists, so it can always be recovered
+    let public_key = set_signing_key(kp, KeyOrigin::Generated).await?;
+    Ok(Json(GenerateSigningKeyResponse {
+        public_key,
+        encrypted_object: Hex::encode(bcs::to_bytes(&encrypted_object).expect("should not fail")),
+    }))
+}
+
+/// Restart: decrypt the sealed signing key with the fetched Seal responses,
+/// and load it only if it is the key registered as `enclave_object_id`.
+/// Host-only.
+pub async fn unseal_signing_key(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<UnsealSigningKeyRequest>,
+) -> Result<Json<SigningKeyResponse>, EnclaveError> {
+    let decrypted = unseal(
+        &SEAL_CONFIG,
+        &request.seal_responses,
+        &[request.encrypted_object],
+    )?;
+    let key_bytes = decrypted.first().ok_or_else(|| {
+        EnclaveError::GenericError("No signing key was decrypted".to_string())
+    })?;
+    let private_key = Ed25519PrivateKey::from_bytes(key_bytes)
+        .map_err(|e| EnclaveError::GenericError(format!("Invalid sealed signing key: {}", e)))?;
+
+    let kp = Ed25519KeyPair::from(private_key);
+    verify_registered(
+        state.chain.as_ref(),
+        &enclave_type(&SEAL_CONFIG, "weather::WEATHER")?,
+        request.enclave_object_id,
+        kp.public().as_bytes(),
+    )
+    .await?;
+
+    let public_key = set_signing_key(kp, KeyOrigin::Unsealed).await?;
+    Ok(Json(SigningKeyResponse {
+        public_key,
+        attestation: None,
+    }))
+}
+
+/// Attestation committed to the signing key, for registering it on-chain.
+/// Only a key generated in this boot is attested, an unsealed one is already
+/// registered.
+pub async fn get_signing_key_attestation() -> Result<Json<SigningKeyResponse>, EnclaveError> {
+    let (kp, origin) = SIGNING_KEY.read().await.clone().ok_or_else(|| {
+        EnclaveError::GenericError(
+            "Signing key not loaded. Please generate or unseal it first.".to_string(),
+        )
+    })?;
+    if origin != KeyOrigin::Generated {
+        return Err(EnclaveError::Forbidden(
+            "Only a signing key generated in this enclave is attested".to_string(),
+        ));
+    }
+    let public_key = kp.public().as_bytes().to_vec();
+    let document = attestation_for(&public_key)?;
+    Ok(Json(SigningKeyResponse {
+        public_key: Hex::encode(public_key),
+        attestation: Some(Hex::encode(document)),
+    }))
+}
+
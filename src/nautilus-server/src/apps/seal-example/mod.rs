@@ -2,9 +2,11 @@
 // SPDX-License-Identifier: Apache-2.0
 
 pub mod endpoints;
+pub mod key_store;
 pub mod types;
 
 pub use endpoints::{complete_parameter_load, init_parameter_load};
+pub use key_store::{generate_signing_key, get_signing_key_attestation, unseal_signing_key};
 pub use types::*;
 
 use crate::app::endpoints::SEAL_API_KEY;
@@ -18,6 +20,10 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::sync::Arc;
 use tracing::info;
+/// App identifier signed into every intent message, must match APP_ID in
+/// the Move module verifying the signatures.
+pub const APP_ID: &str = "seal-weather";
+
 /// Inner type T for IntentMessage<T>
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct WeatherResponse {
@@ -32,7 +38,7 @@ pub struct WeatherRequest {
 }
 
 pub async fn process_data(
-    State(state): State<Arc<AppState>>,
+    State(_state): State<Arc<AppState>>,
     Json(request): Json<ProcessDataRequest<WeatherRequest>>,
 ) -> Result<Json<ProcessedDataResponse<IntentMessage<WeatherResponse>>>, EnclaveError> {
     // API key loaded from what was set during bootstrap.
@@ -69,14 +75,17 @@ pub async fn process_data(
         ));
     }
 
+    // Results are signed with the persistent key, registered on-chain once
+    let signing_key = key_store::signing_key().await?;
     Ok(Json(to_signed_response(
-        &state.eph_kp,
+        &signing_key,
         WeatherResponse {
             location: location.to_string(),
             temperature,
         },
         last_updated_timestamp_ms,
         IntentScope::ProcessData,
+        APP_ID,
     )))
 }
 
@@ -87,6 +96,20 @@ use axum::{
 };
 use tokio::net::TcpListener;
 
+/// Routes of the app, mounted under /apps/{name} and, when it is the root
+/// app, at the root. Parameter loading is served on the host-only port, see
+/// spawn_host_init_server.
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/process_data", post(process_data))
+        // Attestation of the persistent signing key, for registering it on-chain
+        .route(
+            "/get_signing_key_attestation",
+            get(get_signing_key_attestation),
+        )
+        .with_state(state)
+}
+
 /// Response for the ping endpoint
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PingResponse {
@@ -110,6 +133,8 @@ pub async fn spawn_host_init_server(state: Arc<AppState>) -> Result<(), EnclaveE
             "/seal/complete_parameter_load",
             post(complete_parameter_load),
         )
+        .route("/seal/generate_signing_key", post(generate_signing_key))
+        .route("/seal/unseal_signing_key", post(unseal_signing_key))
         .with_state(state);
 
     let host_listener = TcpListener::bind("0.0.0.0:3001").await.map_err(|e| {
@@ -144,11 +169,11 @@ mod test {
             temperature: 13,
         };
         let timestamp = 1744038900000;
-        let intent_msg = IntentMessage::new(payload, timestamp, IntentScope::ProcessData);
+        let intent_msg = IntentMessage::new(payload, timestamp, IntentScope::ProcessData, APP_ID);
         let signing_payload = bcs::to_bytes(&intent_msg).expect("should not fail");
         assert!(
             signing_payload
-                == Hex::decode("0020b1d110960100000d53616e204672616e636973636f0d00000000000000")
+                == Hex::decode("000c7365616c2d7765617468657220b1d110960100000d53616e204672616e636973636f0d00000000000000")
                     .unwrap()
         );
     }
@@ -6,12 +6,19 @@ use crate::common::{to_signed_response, IntentScope, ProcessDataRequest, Process
 use crate::AppState;
 use crate::EnclaveError;
 use axum::extract::State;
+use axum::routing::post;
 use axum::Json;
+use axum::Router;
 use fastcrypto::encoding::{Encoding, Hex};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tracing::info;
+
+/// App identifier signed into every intent message, must match APP_ID in
+/// the Move module verifying the signatures.
+pub const APP_ID: &str = "twitter";
+
 /// ====
 /// Core Nautilus server logic, replace it with your own
 /// relavant structs and process_data endpoint.
@@ -29,6 +36,14 @@ pub struct UserRequest {
     pub user_url: String,
 }
 
+/// Routes of the app, mounted under /apps/{name} and, when it is the root
+/// app, at the root.
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/process_data", post(process_data))
+        .with_state(state)
+}
+
 pub async fn process_data(
     State(state): State<Arc<AppState>>,
     Json(request): Json<ProcessDataRequest<UserRequest>>,
@@ -50,6 +65,7 @@ pub async fn process_data(
         },
         current_timestamp,
         IntentScope::ProcessData,
+        APP_ID,
     )))
 }
 
@@ -201,8 +217,9 @@ mod test {
             },
             1743989326143,
             IntentScope::ProcessData,
+            APP_ID,
         );
         let signing_payload = bcs::to_bytes(&intent_msg).expect("should not fail");
-        assert!(signing_payload == Hex::decode("003f41dd0d960100000c6d797374656e696e7465726e20101ce8865558e08408b83f60ee9e78843d03d547c850cbe12cb599e17833dd3e").unwrap());
+        assert!(signing_payload == Hex::decode("0007747769747465723f41dd0d960100000c6d797374656e696e7465726e20101ce8865558e08408b83f60ee9e78843d03d547c850cbe12cb599e17833dd3e").unwrap());
     }
 }
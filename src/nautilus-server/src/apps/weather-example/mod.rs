@@ -6,10 +6,17 @@ use crate::common::{to_signed_response, IntentScope, ProcessDataRequest, Process
 use crate::AppState;
 use crate::EnclaveError;
 use axum::extract::State;
+use axum::routing::post;
 use axum::Json;
+use axum::Router;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::sync::Arc;
+
+/// App identifier signed into every intent message, must match APP_ID in
+/// the Move module verifying the signatures.
+pub const APP_ID: &str = "weather";
+
 /// ====
 /// Core Nautilus server logic, replace it with your own
 /// relavant structs and process_data endpoint.
@@ -27,6 +34,14 @@ pub struct WeatherRequest {
     pub location: String,
 }
 
+/// Routes of the app, mounted under /apps/{name} and, when it is the root
+/// app, at the root.
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/process_data", post(process_data))
+        .with_state(state)
+}
+
 pub async fn process_data(
     State(state): State<Arc<AppState>>,
     Json(request): Json<ProcessDataRequest<WeatherRequest>>,
@@ -65,6 +80,7 @@ pub async fn process_data(
         },
         last_updated_timestamp_ms,
         IntentScope::ProcessData,
+        APP_ID,
     )))
 }
 
@@ -106,11 +122,11 @@ mod test {
             temperature: 13,
         };
         let timestamp = 1744038900000;
-        let intent_msg = IntentMessage::new(payload, timestamp, IntentScope::ProcessData);
+        let intent_msg = IntentMessage::new(payload, timestamp, IntentScope::ProcessData, APP_ID);
         let signing_payload = bcs::to_bytes(&intent_msg).expect("should not fail");
         assert!(
             signing_payload
-                == Hex::decode("0020b1d110960100000d53616e204672616e636973636f0d00000000000000")
+                == Hex::decode("00077765617468657220b1d110960100000d53616e204672616e636973636f0d00000000000000")
                     .unwrap()
         );
     }
@@ -0,0 +1,151 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! API key authentication for selected routes.
+//!
+//! Keys are configured in the `[auth]` section of nautilus.toml as SHA-256
+//! hashes with the scopes they grant. A caller passes the key as
+//! `Authorization: Bearer <key>` or `X-API-Key: <key>`. Without configured
+//! keys every call is accepted, as before.
+
+use crate::config::{ApiKeyConfig, AuthConfig};
+use crate::{AppState, EnclaveError};
+use axum::extract::{Request, State};
+use axum::http::HeaderMap;
+use axum::http::header::AUTHORIZATION;
+use axum::middleware::Next;
+use axum::response::Response;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hash::{HashFunction, Sha256};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Header carrying the key when Authorization is not used.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// What a key may call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// process_data, process_batch and the aggregate routes.
+    ProcessData,
+    ExecuteCode,
+    /// sign_digest.
+    Admin,
+}
+
+/// Hex SHA-256 of an API key, the value stored in the config.
+pub fn hash_key(key: &str) -> String {
+    Hex::encode(Sha256::digest(key.as_bytes()).digest)
+}
+
+/// Key presented with the request, if any.
+fn presented_key(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| {
+            headers
+                .get(API_KEY_HEADER)
+                .and_then(|value| value.to_str().ok())
+        })
+        .map(str::trim)
+}
+
+/// Configured key presented with the request, if any.
+pub fn identify<'a>(config: &'a AuthConfig, headers: &HeaderMap) -> Option<&'a ApiKeyConfig> {
+    let hash = hash_key(presented_key(headers)?);
+    config
+        .keys
+        .iter()
+        .find(|api_key| api_key.sha256.eq_ignore_ascii_case(&hash))
+}
+
+/// Check the request carries a key granting `scope`.
+pub fn authorize<'a>(
+    config: &'a AuthConfig,
+    headers: &HeaderMap,
+    scope: Scope,
+) -> Result<Option<&'a ApiKeyConfig>, EnclaveError> {
+    if config.keys.is_empty() {
+        return Ok(None);
+    }
+    if presented_key(headers).is_none() {
+        return Err(EnclaveError::Unauthorized("Missing API key".to_string()));
+    }
+    let api_key = identify(config, headers)
+        .ok_or_else(|| EnclaveError::Unauthorized("Unknown API key".to_string()))?;
+    if !api_key.scopes.contains(&scope) {
+        return Err(EnclaveError::Forbidden(format!(
+            "API key {} does not grant {:?}",
+            api_key.name, scope
+        )));
+    }
+    Ok(Some(api_key))
+}
+
+/// Middleware rejecting requests without a key granting the scope, used as
+/// `route_layer(from_fn_with_state((state, Scope::ProcessData), require_scope))`.
+pub async fn require_scope(
+    State((state, scope)): State<(Arc<AppState>, Scope)>,
+    request: Request,
+    next: Next,
+) -> Result<Response, EnclaveError> {
+    authorize(&state.config.auth, request.headers(), scope)?;
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn headers(name: &str, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            axum::http::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+            value.parse().unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn test_authorize() {
+        let config = AuthConfig {
+            keys: vec![ApiKeyConfig {
+                name: "relayer".to_string(),
+                sha256: hash_key("secret"),
+                scopes: vec![Scope::ProcessData],
+            }],
+        };
+
+        let bearer = headers("authorization", "Bearer secret");
+        assert_eq!(
+            authorize(&config, &bearer, Scope::ProcessData)
+                .unwrap()
+                .unwrap()
+                .name,
+            "relayer"
+        );
+        assert!(authorize(&config, &headers("x-api-key", "secret"), Scope::ProcessData).is_ok());
+        assert!(matches!(
+            authorize(&config, &bearer, Scope::ExecuteCode),
+            Err(EnclaveError::Forbidden(_))
+        ));
+        assert!(matches!(
+            authorize(&config, &headers("x-api-key", "other"), Scope::ProcessData),
+            Err(EnclaveError::Unauthorized(_))
+        ));
+        assert!(matches!(
+            authorize(&config, &HeaderMap::new(), Scope::ProcessData),
+            Err(EnclaveError::Unauthorized(_))
+        ));
+
+        // No keys configured, authentication is off
+        assert!(
+            authorize(&AuthConfig::default(), &HeaderMap::new(), Scope::Admin)
+                .unwrap()
+                .is_none()
+        );
+    }
+}
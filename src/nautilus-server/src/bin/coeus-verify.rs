@@ -0,0 +1,95 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Verify a coeus-oracle process_data response before submitting it.
+//!
+//! Usage: coeus-verify --public-key <hex> [--max-age-ms <ms>] [--max-clock-skew-ms <ms>]
+//!                     [--allow-empty] [response.json]
+//!
+//! Reads the response from the file, or stdin when omitted. Responses signed
+//! in the future, beyond --max-clock-skew-ms (default 0), are refused. Prints the verified
+//! result and exits with 0, or prints the reason and exits with 1.
+
+use anyhow::{Context, Result, anyhow, bail};
+use nautilus_server::app::verify::{VerifyOptions, verify_response_json};
+use std::io::Read;
+
+const USAGE: &str = "Usage: coeus-verify --public-key <hex> [--max-age-ms <ms>] \
+                     [--max-clock-skew-ms <ms>] [--allow-empty] [response.json]";
+
+struct Args {
+    public_key: String,
+    options: VerifyOptions,
+    path: Option<String>,
+}
+
+fn parse_args() -> Result<Args> {
+    let mut public_key = None;
+    let mut options = VerifyOptions {
+        now_ms: Some(
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_millis() as u64,
+        ),
+        ..VerifyOptions::default()
+    };
+    let mut path = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--public-key" => public_key = args.next(),
+            "--max-age-ms" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--max-age-ms needs a value"))?;
+                options.max_age_ms = Some(value.parse().context("invalid --max-age-ms")?);
+            }
+            "--max-clock-skew-ms" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--max-clock-skew-ms needs a value"))?;
+                options.max_clock_skew_ms = value.parse().context("invalid --max-clock-skew-ms")?;
+            }
+            "--allow-empty" => options.allow_empty_result = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            _ if arg.starts_with("--") => bail!("unknown option {}\n{}", arg, USAGE),
+            _ => path = Some(arg),
+        }
+    }
+    Ok(Args {
+        public_key: public_key.ok_or_else(|| anyhow!("--public-key is required\n{}", USAGE))?,
+        options,
+        path,
+    })
+}
+
+fn main() -> Result<()> {
+    let args = parse_args()?;
+    let json = match &args.path {
+        Some(path) => std::fs::read_to_string(path).with_context(|| format!("reading {}", path))?,
+        None => {
+            let mut json = String::new();
+            std::io::stdin().read_to_string(&mut json)?;
+            json
+        }
+    };
+
+    match verify_response_json(&json, &args.public_key, &args.options) {
+        Ok(verified) => {
+            println!("OK");
+            println!("timestamp_ms: {}", verified.timestamp_ms);
+            println!("result: {:?}", verified.response.result);
+            if verified.signed_by_previous_key {
+                println!("signed by: previous key (rotation grace window)");
+            }
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("INVALID: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
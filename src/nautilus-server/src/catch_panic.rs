@@ -0,0 +1,85 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Panics turned into errors, so a bug reached by one request or feed does
+//! not drop the connection or stop a schedule.
+//!
+//! Handlers run under [`layer`], which answers a panic with a 500
+//! `internal_error` problem carrying the request id. The panic message is
+//! only logged, it may contain request data. Every caught panic is counted
+//! in `panics_total`.
+
+use crate::EnclaveError;
+use crate::metrics::PANICS;
+use crate::request_id;
+use axum::response::{IntoResponse, Response};
+use std::any::Any;
+use tower_http::catch_panic::{CatchPanicLayer, ResponseForPanic};
+use tracing::error;
+
+/// Message of a panic payload, for the payloads of `panic!` and `unwrap`.
+pub fn message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic payload"
+    }
+}
+
+/// Log and count a caught panic, returning the error to report in its place.
+pub fn record(source: &str, panic: &(dyn Any + Send)) -> EnclaveError {
+    error!(
+        request_id = request_id::current().as_deref(),
+        "Caught panic ({}): {}",
+        source,
+        message(panic)
+    );
+    PANICS.with_label_values(&[source]).inc();
+    EnclaveError::Internal("Unexpected panic, see the enclave logs".to_string())
+}
+
+#[derive(Clone, Copy)]
+pub struct PanicResponse;
+
+impl ResponseForPanic for PanicResponse {
+    type ResponseBody = axum::body::Body;
+
+    fn response_for_panic(&mut self, panic: Box<dyn Any + Send + 'static>) -> Response {
+        record("http", panic.as_ref()).into_response()
+    }
+}
+
+/// Layer answering handler panics with a 500. Must run inside the request id
+/// middleware for the response to carry the id.
+pub fn layer() -> CatchPanicLayer<PanicResponse> {
+    CatchPanicLayer::custom(PanicResponse)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::http::StatusCode;
+
+    #[tokio::test]
+    async fn test_catch_panic() {
+        assert_eq!(message(&"boom"), "boom");
+        assert_eq!(message(&"boom".to_string()), "boom");
+        assert_eq!(message(&1u8), "unknown panic payload");
+
+        let before = PANICS.with_label_values(&["http"]).get();
+        let response = request_id::scope("abc".to_string(), async {
+            PanicResponse.response_for_panic(Box::new("boom"))
+        })
+        .await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(PANICS.with_label_values(&["http"]).get(), before + 1);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "internal_error");
+        assert_eq!(body["request_id"], "abc");
+    }
+}
@@ -0,0 +1,327 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Sui reads, from the backend selected by `sui.backend` in nautilus.toml:
+//! the gRPC fullnodes of the SuiPool (the default), or a GraphQL RPC service
+//! (see graphql.rs) for operators whose gRPC access is limited. Apps read
+//! objects, transactions and checkpoint events through `AppState::chain` and
+//! do not depend on the backend. Transactions are still submitted through the
+//! SuiPool.
+//!
+//! Errors are tonic statuses whatever the backend, so callers keep telling a
+//! missing object (NotFound) from an unavailable backend.
+
+use crate::sui_pool::SuiPool;
+use std::future::Future;
+use std::pin::Pin;
+use sui_rpc::field::{FieldMask, FieldMaskUtil};
+use sui_rpc::proto::sui::rpc::v2::get_checkpoint_request::CheckpointId;
+use sui_rpc::proto::sui::rpc::v2::get_object_result;
+use sui_rpc::proto::sui::rpc::v2::{
+    BatchGetObjectsRequest, Event, GetCheckpointRequest, GetObjectRequest, GetObjectResult,
+    GetServiceInfoRequest, GetTransactionRequest,
+};
+use sui_sdk_types::{Address, StructTag};
+use tonic::Status;
+use tonic::metadata::MetadataMap;
+
+/// Response header carrying the fullnode's latest checkpoint.
+const CHECKPOINT_HEIGHT_HEADER: &str = "x-sui-checkpoint-height";
+
+/// Objects requested per batch_get_objects call, below the fullnode limit.
+pub const MAX_BATCH_OBJECTS: usize = 50;
+
+pub type ReadFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, Status>> + Send + 'a>>;
+
+/// An object version, with the latest checkpoint of the backend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectRead {
+    /// BCS of the whole object.
+    pub bcs: Vec<u8>,
+    pub version: u64,
+    /// Digest of the transaction that wrote this version.
+    pub previous_transaction: Option<String>,
+    /// Checkpoint the backend had reached when reading.
+    pub checkpoint: Option<u64>,
+}
+
+/// Where a transaction was included, and the versions of the objects it
+/// changed before it ran.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TransactionRead {
+    /// None until the transaction is in a checkpoint.
+    pub checkpoint: Option<u64>,
+    /// Objects changed by the transaction that existed before it.
+    pub input_versions: Vec<(Address, u64)>,
+}
+
+/// A backend Sui reads go to.
+pub trait ChainReader: Send + Sync {
+    /// Object `id` at `version`, the latest one by default.
+    fn get_object(&self, id: Address, version: Option<u64>) -> ReadFuture<'_, ObjectRead>;
+
+    /// BCS of the latest version of each of `ids`, in the same order. Fails
+    /// when any of them cannot be read.
+    fn get_objects<'a>(&'a self, ids: &'a [Address]) -> ReadFuture<'a, Vec<Vec<u8>>>;
+
+    /// Transaction `digest`.
+    fn get_transaction<'a>(&'a self, digest: &'a str) -> ReadFuture<'a, TransactionRead>;
+
+    /// Sequence number of the latest checkpoint.
+    fn latest_checkpoint(&self) -> ReadFuture<'_, u64>;
+
+    /// BCS contents of the events of `event_type` emitted in a checkpoint.
+    fn checkpoint_events<'a>(
+        &'a self,
+        sequence_number: u64,
+        event_type: &'a StructTag,
+    ) -> ReadFuture<'a, Vec<Vec<u8>>>;
+}
+
+fn checkpoint_height(metadata: &MetadataMap) -> Option<u64> {
+    metadata
+        .get(CHECKPOINT_HEIGHT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
+
+/// BCS of object `id` in a batch_get_objects result.
+fn batch_object_bcs(id: &Address, result: GetObjectResult) -> Result<Vec<u8>, Status> {
+    match result.result {
+        Some(get_object_result::Result::Object(object)) => object
+            .bcs
+            .and_then(|bcs| bcs.value)
+            .map(|value| value.to_vec())
+            .ok_or_else(|| Status::internal(format!("No BCS data for object {}", id))),
+        Some(get_object_result::Result::Error(status)) => Err(Status::not_found(format!(
+            "Failed to get object {}: {}",
+            id, status.message
+        ))),
+        _ => Err(Status::internal(format!(
+            "batch_get_objects returned nothing for object {}",
+            id
+        ))),
+    }
+}
+
+/// Contents of the events of `event_type`, other types and layouts are
+/// skipped.
+fn events_of_type(events: Vec<Event>, event_type: &StructTag) -> Vec<Vec<u8>> {
+    events
+        .into_iter()
+        .filter(|event| {
+            event
+                .event_type
+                .as_deref()
+                .and_then(|event_type| event_type.parse::<StructTag>().ok())
+                .is_some_and(|parsed| &parsed == event_type)
+        })
+        .filter_map(|event| event.contents?.value)
+        .map(|value| value.to_vec())
+        .collect()
+}
+
+impl ChainReader for SuiPool {
+    fn get_object(&self, id: Address, version: Option<u64>) -> ReadFuture<'_, ObjectRead> {
+        Box::pin(async move {
+            let mut request = GetObjectRequest::new(&id).with_read_mask(FieldMask::from_paths([
+                "bcs",
+                "version",
+                "previous_transaction",
+            ]));
+            request.version = version;
+            let response = self
+                .call("get_object", |mut client| {
+                    let request = request.clone();
+                    async move { client.ledger_client().get_object(request).await }
+                })
+                .await?;
+            let checkpoint = checkpoint_height(response.metadata());
+            let object = response
+                .into_inner()
+                .object
+                .ok_or_else(|| Status::internal(format!("No object {} returned", id)))?;
+            let bcs = object
+                .bcs
+                .and_then(|bcs| bcs.value)
+                .ok_or_else(|| Status::internal(format!("No BCS data for object {}", id)))?
+                .to_vec();
+            let version = object
+                .version
+                .ok_or_else(|| Status::internal(format!("No version for object {}", id)))?;
+            Ok(ObjectRead {
+                bcs,
+                version,
+                previous_transaction: object.previous_transaction,
+                checkpoint,
+            })
+        })
+    }
+
+    fn get_objects<'a>(&'a self, ids: &'a [Address]) -> ReadFuture<'a, Vec<Vec<u8>>> {
+        Box::pin(async move {
+            let mut objects = Vec::with_capacity(ids.len());
+            for batch in ids.chunks(MAX_BATCH_OBJECTS) {
+                let mut request = BatchGetObjectsRequest::default();
+                request.requests = batch.iter().map(GetObjectRequest::new).collect();
+                request.read_mask = Some(FieldMask::from_str("bcs"));
+                let results = self
+                    .call("batch_get_objects", |mut client| {
+                        let request = request.clone();
+                        async move { client.ledger_client().batch_get_objects(request).await }
+                    })
+                    .await?
+                    .into_inner()
+                    .objects;
+                if results.len() != batch.len() {
+                    return Err(Status::internal(format!(
+                        "batch_get_objects returned {} objects for {} ids",
+                        results.len(),
+                        batch.len()
+                    )));
+                }
+                for (id, result) in batch.iter().zip(results) {
+                    objects.push(batch_object_bcs(id, result)?);
+                }
+            }
+            Ok(objects)
+        })
+    }
+
+    fn get_transaction<'a>(&'a self, digest: &'a str) -> ReadFuture<'a, TransactionRead> {
+        Box::pin(async move {
+            let mut request = GetTransactionRequest::default();
+            request.digest = Some(digest.to_string());
+            request.read_mask = Some(FieldMask::from_paths([
+                "checkpoint",
+                "effects.changed_objects",
+            ]));
+            let transaction = self
+                .call("get_transaction", |mut client| {
+                    let request = request.clone();
+                    async move { client.ledger_client().get_transaction(request).await }
+                })
+                .await?
+                .into_inner()
+                .transaction
+                .ok_or_else(|| Status::internal(format!("No transaction {} returned", digest)))?;
+            let input_versions = transaction
+                .effects
+                .into_iter()
+                .flat_map(|effects| effects.changed_objects)
+                .filter_map(|changed| {
+                    let id = Address::from_hex(changed.object_id.as_deref()?).ok()?;
+                    Some((id, changed.input_version?))
+                })
+                .collect();
+            Ok(TransactionRead {
+                checkpoint: transaction.checkpoint,
+                input_versions,
+            })
+        })
+    }
+
+    fn latest_checkpoint(&self) -> ReadFuture<'_, u64> {
+        Box::pin(async move {
+            self.call("get_service_info", |mut client| async move {
+                client
+                    .ledger_client()
+                    .get_service_info(GetServiceInfoRequest::default())
+                    .await
+            })
+            .await?
+            .into_inner()
+            .checkpoint_height
+            .ok_or_else(|| Status::internal("get_service_info returned no checkpoint height"))
+        })
+    }
+
+    fn checkpoint_events<'a>(
+        &'a self,
+        sequence_number: u64,
+        event_type: &'a StructTag,
+    ) -> ReadFuture<'a, Vec<Vec<u8>>> {
+        Box::pin(async move {
+            let mut request = GetCheckpointRequest::default();
+            request.checkpoint_id = Some(CheckpointId::SequenceNumber(sequence_number));
+            request.read_mask = Some(FieldMask::from_paths([
+                "transactions.events.events.event_type",
+                "transactions.events.events.contents",
+            ]));
+            let checkpoint = self
+                .call("get_checkpoint", |mut client| {
+                    let request = request.clone();
+                    async move { client.ledger_client().get_checkpoint(request).await }
+                })
+                .await?
+                .into_inner()
+                .checkpoint
+                .ok_or_else(|| {
+                    Status::internal(format!("No checkpoint {} returned", sequence_number))
+                })?;
+            let events = checkpoint
+                .transactions
+                .into_iter()
+                .filter_map(|transaction| transaction.events)
+                .flat_map(|events| events.events)
+                .collect();
+            Ok(events_of_type(events, event_type))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sui_rpc::proto::sui::rpc::v2::{Bcs, Object};
+
+    fn event(event_type: &str, contents: Vec<u8>) -> Event {
+        let mut bcs = Bcs::default();
+        bcs.value = Some(contents.into());
+        let mut event = Event::default();
+        event.event_type = Some(event_type.to_string());
+        event.contents = Some(bcs);
+        event
+    }
+
+    #[test]
+    fn test_grpc_results() {
+        let id = Address::from_hex("0x2").unwrap();
+        let mut bcs = Bcs::default();
+        bcs.value = Some(vec![1, 2, 3].into());
+        let mut object = Object::default();
+        object.bcs = Some(bcs);
+        let mut result = GetObjectResult::default();
+        result.result = Some(get_object_result::Result::Object(object));
+        assert_eq!(batch_object_bcs(&id, result).unwrap(), vec![1, 2, 3]);
+
+        // Objects missing from the batch fail the whole read
+        let mut status = sui_rpc::proto::google::rpc::Status::default();
+        status.message = "not found".to_string();
+        let mut result = GetObjectResult::default();
+        result.result = Some(get_object_result::Result::Error(status));
+        assert_eq!(
+            batch_object_bcs(&id, result).unwrap_err().code(),
+            tonic::Code::NotFound
+        );
+        assert!(batch_object_bcs(&id, GetObjectResult::default()).is_err());
+
+        let mut metadata = MetadataMap::new();
+        assert_eq!(checkpoint_height(&metadata), None);
+        metadata.insert(CHECKPOINT_HEIGHT_HEADER, "1234".parse().unwrap());
+        assert_eq!(checkpoint_height(&metadata), Some(1234));
+
+        // Same event type with the address written in full, another package
+        let event_type: StructTag = "0x1::feed::UpdateRequested".parse().unwrap();
+        let full_type = format!(
+            "{}::feed::UpdateRequested",
+            Address::from_hex("0x1").unwrap()
+        );
+        let events = vec![
+            event("0x1::feed::UpdateRequested", vec![1]),
+            event(&full_type, vec![2]),
+            event("0x2::feed::UpdateRequested", vec![3]),
+        ];
+        assert_eq!(events_of_type(events, &event_type), vec![vec![1], vec![2]]);
+    }
+}
@@ -3,12 +3,16 @@
 
 use crate::AppState;
 use crate::EnclaveError;
+use crate::metrics;
 use axum::{extract::State, Json};
 use fastcrypto::traits::Signer;
 use fastcrypto::{encoding::Encoding, traits::ToFromBytes};
 use fastcrypto::{encoding::Hex, traits::KeyPair as FcKeyPair};
+use fastcrypto::hmac::{hkdf_sha3_256, HkdfIkm};
 use nsm_api::api::{Request as NsmRequest, Response as NsmResponse};
 use nsm_api::driver;
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_bytes::ByteBuf;
@@ -16,41 +20,54 @@ use serde_repr::Deserialize_repr;
 use serde_repr::Serialize_repr;
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tracing::info;
 
-use fastcrypto::ed25519::Ed25519KeyPair;
+use fastcrypto::ed25519::{Ed25519KeyPair, Ed25519PublicKey};
 /// ==== COMMON TYPES ====
-/// Intent message wrapper struct containing the intent scope and timestamp.
-/// This standardizes the serialized payload for signing.
-#[derive(Debug, Serialize, Deserialize)]
+/// Intent message wrapper struct containing the intent scope, the app
+/// identifier and timestamp. This standardizes the serialized payload for
+/// signing and domain separates it, so a signature produced by one app or
+/// endpoint is never valid where another app or endpoint is expected.
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct IntentMessage<T: Serialize> {
     pub intent: IntentScope,
+    pub app_id: String,
     pub timestamp_ms: u64,
     pub data: T,
 }
 
 /// Intent scope enum. Add new scope here if needed, each corresponds to a
 /// scope for signing. Replace in with your own intent per message type being signed by the enclave.
-#[derive(Serialize_repr, Deserialize_repr, Debug)]
+/// Each endpoint signs under its own scope, the verifier checks the scope it expects.
+#[derive(Serialize_repr, Deserialize_repr, Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum IntentScope {
     ProcessData = 0,
+    ResultMeta = 1,
+    KeyRotation = 2,
+    ExecuteCode = 3,
+    AuditLog = 4,
+    SignDigest = 5,
+    FeedState = 6,
+    PinnedResult = 7,
 }
 
 impl<T: Serialize + Debug> IntentMessage<T> {
-    pub fn new(data: T, timestamp_ms: u64, intent: IntentScope) -> Self {
+    pub fn new(data: T, timestamp_ms: u64, intent: IntentScope, app_id: &str) -> Self {
         Self {
             data,
             timestamp_ms,
             intent,
+            app_id: app_id.to_string(),
         }
     }
 }
 
 /// Wrapper struct containing the response (the intent message) and signature.
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ProcessedDataResponse<T> {
     pub response: T,
     pub signature: String,
@@ -62,21 +79,27 @@ pub struct ProcessDataRequest<T> {
     pub payload: T,
 }
 
-/// Sign the bcs bytes of the the payload with keypair.
+/// Sign the bcs bytes of the the payload with keypair, under the scope and
+/// identifier of the app.
 pub fn to_signed_response<T: Serialize + Clone>(
     kp: &Ed25519KeyPair,
     payload: T,
     timestamp_ms: u64,
     intent: IntentScope,
+    app_id: &str,
 ) -> ProcessedDataResponse<IntentMessage<T>> {
     let intent_msg = IntentMessage {
         intent,
+        app_id: app_id.to_string(),
         timestamp_ms,
         data: payload.clone(),
     };
 
     let signing_payload = bcs::to_bytes(&intent_msg).expect("should not fail");
     let sig = kp.sign(&signing_payload);
+    metrics::SIGNATURES
+        .with_label_values(&[&format!("{:?}", intent)])
+        .inc();
     ProcessedDataResponse {
         response: intent_msg,
         signature: Hex::encode(sig),
@@ -91,44 +114,134 @@ pub struct GetAttestationResponse {
     pub attestation: String,
 }
 
-/// Endpoint that returns an attestation committed
-/// to the enclave's public key.
-pub async fn get_attestation(
-    State(state): State<Arc<AppState>>,
-) -> Result<Json<GetAttestationResponse>, EnclaveError> {
-    info!("get attestation called");
+/// Request an attestation document from the NSM committing to `public_key`.
+pub fn attestation_for(public_key: &[u8]) -> Result<Vec<u8>, EnclaveError> {
+    attestation_with_user_data(public_key, None)
+}
 
-    let pk = state.eph_kp.public();
+/// Request an attestation document from the NSM committing to `public_key`
+/// and, if given, to `user_data`.
+pub fn attestation_with_user_data(
+    public_key: &[u8],
+    user_data: Option<&[u8]>,
+) -> Result<Vec<u8>, EnclaveError> {
     let fd = driver::nsm_init();
 
     // Send attestation request to NSM driver with public key set.
     let request = NsmRequest::Attestation {
-        user_data: None,
+        user_data: user_data.map(|data| ByteBuf::from(data.to_vec())),
         nonce: None,
-        public_key: Some(ByteBuf::from(pk.as_bytes().to_vec())),
+        public_key: Some(ByteBuf::from(public_key.to_vec())),
     };
 
     let response = driver::nsm_process_request(fd, request);
+    driver::nsm_exit(fd);
     match response {
-        NsmResponse::Attestation { document } => {
-            driver::nsm_exit(fd);
-            Ok(Json(GetAttestationResponse {
-                attestation: Hex::encode(document),
-            }))
-        }
-        _ => {
-            driver::nsm_exit(fd);
-            Err(EnclaveError::GenericError(
-                "unexpected response".to_string(),
-            ))
-        }
+        NsmResponse::Attestation { document } => Ok(document),
+        _ => Err(EnclaveError::GenericError(
+            "unexpected response".to_string(),
+        )),
+    }
+}
+
+/// Read PCR `index` from the NSM.
+pub fn describe_pcr(index: u16) -> Result<Vec<u8>, EnclaveError> {
+    let fd = driver::nsm_init();
+    let response = driver::nsm_process_request(fd, NsmRequest::DescribePCR { index });
+    driver::nsm_exit(fd);
+    match response {
+        NsmResponse::DescribePCR { data, .. } => Ok(data),
+        NsmResponse::Error(e) => Err(EnclaveError::GenericError(format!(
+            "failed to describe PCR{}: {:?}",
+            index, e
+        ))),
+        _ => Err(EnclaveError::GenericError(
+            "unexpected response".to_string(),
+        )),
+    }
+}
+
+/// Request random bytes from the NSM.
+pub fn nsm_random() -> Result<Vec<u8>, EnclaveError> {
+    let fd = driver::nsm_init();
+    let response = driver::nsm_process_request(fd, NsmRequest::GetRandom);
+    driver::nsm_exit(fd);
+    match response {
+        NsmResponse::GetRandom { random } if random.len() >= 32 => Ok(random),
+        NsmResponse::GetRandom { .. } => Err(EnclaveError::GenericError(
+            "NSM returned too few random bytes".to_string(),
+        )),
+        NsmResponse::Error(e) => Err(EnclaveError::GenericError(format!(
+            "failed to get NSM randomness: {:?}",
+            e
+        ))),
+        _ => Err(EnclaveError::GenericError(
+            "unexpected response".to_string(),
+        )),
+    }
+}
+
+/// Where the entropy of the enclave's keys comes from.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyDerivation {
+    /// NSM random bytes mixed with OS randomness through HKDF-SHA3-256.
+    NsmMixed,
+    /// OS randomness only, the NSM was unavailable (e.g. running locally).
+    OsOnly,
+}
+
+/// Set once any key was generated without NSM entropy.
+static NSM_ENTROPY_UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+
+/// Derivation mode of the keys generated so far.
+pub fn key_derivation() -> KeyDerivation {
+    if NSM_ENTROPY_UNAVAILABLE.load(Ordering::Relaxed) {
+        KeyDerivation::OsOnly
+    } else {
+        KeyDerivation::NsmMixed
     }
 }
 
+/// RNG to generate keys with, seeded from the NSM's hardware entropy mixed
+/// with the OS RNG, so a weak early-boot kernel pool alone can't make keys
+/// predictable. Falls back to the OS RNG alone when the NSM is unavailable,
+/// which is then reported by key_derivation().
+pub fn enclave_rng() -> StdRng {
+    let mut os_random = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut os_random);
+    let nsm_random = match nsm_random() {
+        Ok(random) => random,
+        Err(e) => {
+            info!("NSM entropy unavailable, using OS randomness only: {}", e);
+            NSM_ENTROPY_UNAVAILABLE.store(true, Ordering::Relaxed);
+            return StdRng::from_seed(os_random);
+        }
+    };
+    let ikm = HkdfIkm::from_bytes(&[nsm_random.as_slice(), &os_random].concat())
+        .expect("any length is accepted");
+    let seed = hkdf_sha3_256(&ikm, b"nautilus-enclave-rng", &[], 32).expect("should not fail");
+    StdRng::from_seed(seed.try_into().expect("32 bytes"))
+}
+
+/// Endpoint that returns an attestation committed
+/// to the enclave's public key.
+pub async fn get_attestation(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<GetAttestationResponse>, EnclaveError> {
+    info!("get attestation called");
+
+    let pk = state.eph_kp.public();
+    let document = attestation_for(pk.as_bytes())?;
+    Ok(Json(GetAttestationResponse {
+        attestation: Hex::encode(document),
+    }))
+}
+
 /// Health check response.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HealthCheckResponse {
-    /// Hex encoded public key booted on enclave.
+    /// Hex encoded public key the enclave currently signs with.
     pub pk: String,
     /// Status of endpoint connectivity checks
     pub endpoints_status: HashMap<String, bool>,
@@ -139,8 +252,14 @@ pub struct HealthCheckResponse {
 pub async fn health_check(
     State(state): State<Arc<AppState>>,
 ) -> Result<Json<HealthCheckResponse>, EnclaveError> {
-    let pk = state.eph_kp.public();
+    health_check_for(state.eph_kp.public()).await
+}
 
+/// Health check the enclave connectivity to all domains, reporting `pk`.
+/// For apps whose signing key is not the boot key.
+pub async fn health_check_for(
+    pk: &Ed25519PublicKey,
+) -> Result<Json<HealthCheckResponse>, EnclaveError> {
     // Create HTTP client with timeout
     let client = Client::builder()
         .timeout(Duration::from_secs(5))
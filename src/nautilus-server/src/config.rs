@@ -0,0 +1,918 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Server configuration shared by all apps.
+//!
+//! Read from the TOML file given by `--config` or `NAUTILUS_CONFIG` (default
+//! `nautilus.toml`, copied into the enclave image), then overridden by
+//! `NAUTILUS_*` environment variables, which the host provides at boot
+//! through run.sh, and by command line flags. Environment overrides are not covered by the PCRs, so the
+//! hash of the effective configuration is exposed for attestation binding.
+
+use crate::EnclaveError;
+use crate::auth::Scope;
+use fastcrypto::hash::{HashFunction, Sha256};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use sui_rpc::client::Client;
+
+/// Environment variable naming the config file.
+pub const CONFIG_PATH_ENV: &str = "NAUTILUS_CONFIG";
+const DEFAULT_CONFIG_PATH: &str = "nautilus.toml";
+
+/// Top level layout of nautilus.toml.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AppConfig {
+    #[serde(default)]
+    pub server: ServerConfig,
+    #[serde(default)]
+    pub sui: SuiConfig,
+    #[serde(default)]
+    pub cors: CorsConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    #[serde(default)]
+    pub timeouts: TimeoutsConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub grpc: GrpcConfig,
+    #[serde(default)]
+    pub compression: CompressionConfig,
+    #[serde(default)]
+    pub logs: LogsConfig,
+    #[serde(default)]
+    pub registration: RegistrationConfig,
+    #[serde(default)]
+    pub profiling: ProfilingConfig,
+    /// Sections of the apps mounted under /apps, by app name.
+    #[serde(default)]
+    pub apps: BTreeMap<String, AppSection>,
+}
+
+/// `[apps.<name>]`: settings of one app, replacing the top level ones in the
+/// app's state.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AppSection {
+    /// Enclave registration of the app, each app registers its own key.
+    #[serde(default)]
+    pub registration: Option<RegistrationConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    #[serde(default)]
+    pub listener: Listener,
+    /// Address the TCP listener binds. run.sh forwards vsock port 3000 to
+    /// localhost:3000, change both together.
+    #[serde(default = "default_bind_address")]
+    pub bind_address: String,
+    /// CID the vsock listener binds, VMADDR_CID_ANY by default.
+    #[serde(default = "default_vsock_cid")]
+    pub vsock_cid: u32,
+    /// Port the vsock listener binds. Remove the socat forwarder from run.sh
+    /// when it is 3000, both listen on that port.
+    #[serde(default = "default_vsock_port")]
+    pub vsock_port: u32,
+    /// Echo the request id in the X-Request-Id response header.
+    #[serde(default = "default_request_id_header")]
+    pub request_id_header: bool,
+    /// App also served at the root, besides /apps/{name}. Defaults to the
+    /// app when a single one is compiled in.
+    #[serde(default)]
+    pub root_app: Option<String>,
+}
+
+/// How the server accepts connections.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Listener {
+    /// TCP on bind_address, reached through the host's forwarder.
+    #[default]
+    Tcp,
+    /// vsock on vsock_cid:vsock_port, reached by the host directly.
+    Vsock,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listener: Listener::default(),
+            bind_address: default_bind_address(),
+            vsock_cid: default_vsock_cid(),
+            vsock_port: default_vsock_port(),
+            request_id_header: default_request_id_header(),
+            root_app: None,
+        }
+    }
+}
+
+fn default_bind_address() -> String {
+    "0.0.0.0:3000".to_string()
+}
+
+fn default_vsock_cid() -> u32 {
+    // VMADDR_CID_ANY
+    u32::MAX
+}
+
+fn default_vsock_port() -> u32 {
+    3000
+}
+
+fn default_request_id_header() -> bool {
+    true
+}
+
+/// Chain id (base58 digest of the genesis checkpoint) of Sui mainnet.
+pub const MAINNET_CHAIN_ID: &str = "4btiuiMPvEENsttpZC7CZ53DruC3MAgfznDbASZ7DR6S";
+/// Chain id of Sui testnet.
+pub const TESTNET_CHAIN_ID: &str = "69WiPg3DAQiwdxfncX6wYQ2siKwAe6L9BZthQea3JNMD";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SuiConfig {
+    #[serde(default)]
+    pub network: SuiNetwork,
+    /// Fullnode gRPC URL, the network's public fullnode by default. Its
+    /// host must be in allowed_endpoints.yaml.
+    #[serde(default)]
+    pub fullnode_url: Option<String>,
+    /// Chain id the fullnode must report at startup. Known for mainnet and
+    /// testnet, required for devnet, localnet and custom networks.
+    #[serde(default)]
+    pub chain_id: Option<String>,
+    /// Fullnodes to fail over to when fullnode_url is unavailable, in order
+    /// of preference. Their hosts must be in allowed_endpoints.yaml too.
+    #[serde(default)]
+    pub fallback_urls: Vec<String>,
+    #[serde(default)]
+    pub retry: SuiRetryConfig,
+    #[serde(default)]
+    pub keepalive: SuiKeepaliveConfig,
+    /// Where objects, transactions and events are read from.
+    #[serde(default)]
+    pub backend: SuiBackend,
+    /// GraphQL RPC URL used by the graphql backend, the network's public
+    /// service by default. Its host must be in allowed_endpoints.yaml.
+    #[serde(default)]
+    pub graphql_url: Option<String>,
+}
+
+/// Backend Sui reads go to, see chain_reader.rs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuiBackend {
+    /// The gRPC fullnodes, with failover.
+    #[default]
+    Grpc,
+    /// The GraphQL RPC service at graphql_url.
+    Graphql,
+}
+
+/// Retries of Sui RPC calls failing with a transient error (unavailable,
+/// deadline exceeded, ...). Each retry goes to the next healthy fullnode.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SuiRetryConfig {
+    /// Attempts per call, including the first one.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled after each one.
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    /// Time a fullnode is skipped after a transient error, unless no other
+    /// one is healthy.
+    #[serde(default = "default_unhealthy_ms")]
+    pub unhealthy_ms: u64,
+}
+
+impl Default for SuiRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            initial_backoff_ms: default_initial_backoff_ms(),
+            max_backoff_ms: default_max_backoff_ms(),
+            unhealthy_ms: default_unhealthy_ms(),
+        }
+    }
+}
+
+fn default_max_attempts() -> u32 {
+    4
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    100
+}
+
+fn default_max_backoff_ms() -> u64 {
+    2000
+}
+
+fn default_unhealthy_ms() -> u64 {
+    30_000
+}
+
+/// Pings of idle fullnodes, so the connection opened at startup is still up
+/// for the next call instead of being re-established on the request path.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SuiKeepaliveConfig {
+    /// Fullnodes that have not answered for this long are pinged, 0 disables.
+    #[serde(default = "default_idle_ms")]
+    pub idle_ms: u64,
+}
+
+impl Default for SuiKeepaliveConfig {
+    fn default() -> Self {
+        Self {
+            idle_ms: default_idle_ms(),
+        }
+    }
+}
+
+fn default_idle_ms() -> u64 {
+    20_000
+}
+
+/// Sui network feed objects are read from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum SuiNetwork {
+    Mainnet,
+    #[default]
+    Testnet,
+    Devnet,
+    Localnet,
+    /// Any other network, fullnode_url is required.
+    Custom,
+}
+
+impl SuiNetwork {
+    /// Public fullnode of the network.
+    pub fn fullnode_url(self) -> Option<&'static str> {
+        match self {
+            SuiNetwork::Mainnet => Some(Client::MAINNET_FULLNODE),
+            SuiNetwork::Testnet => Some(Client::TESTNET_FULLNODE),
+            SuiNetwork::Devnet => Some(Client::DEVNET_FULLNODE),
+            SuiNetwork::Localnet => Some("http://127.0.0.1:9000"),
+            SuiNetwork::Custom => None,
+        }
+    }
+
+    /// Public GraphQL RPC service of the network.
+    pub fn graphql_url(self) -> Option<&'static str> {
+        match self {
+            SuiNetwork::Mainnet => Some("https://graphql.mainnet.sui.io/graphql"),
+            SuiNetwork::Testnet => Some("https://graphql.testnet.sui.io/graphql"),
+            SuiNetwork::Devnet => Some("https://graphql.devnet.sui.io/graphql"),
+            SuiNetwork::Localnet => Some("http://127.0.0.1:9125/graphql"),
+            SuiNetwork::Custom => None,
+        }
+    }
+
+    /// Chain id of the network, for those that are never reset.
+    pub fn chain_id(self) -> Option<&'static str> {
+        match self {
+            SuiNetwork::Mainnet => Some(MAINNET_CHAIN_ID),
+            SuiNetwork::Testnet => Some(TESTNET_CHAIN_ID),
+            _ => None,
+        }
+    }
+}
+
+impl SuiConfig {
+    /// Fullnode to read from: fullnode_url, or the network's public one.
+    pub fn fullnode_url(&self) -> Result<&str, EnclaveError> {
+        self.fullnode_url
+            .as_deref()
+            .or(self.network.fullnode_url())
+            .ok_or_else(|| {
+                EnclaveError::GenericError(
+                    "sui.fullnode_url is required with the custom network".to_string(),
+                )
+            })
+    }
+
+    /// Fullnodes to read from: the primary one, then the fallbacks.
+    pub fn fullnode_urls(&self) -> Result<Vec<&str>, EnclaveError> {
+        let mut urls = vec![self.fullnode_url()?];
+        for url in &self.fallback_urls {
+            if !urls.contains(&url.as_str()) {
+                urls.push(url);
+            }
+        }
+        Ok(urls)
+    }
+
+    /// GraphQL service to read from: graphql_url, or the network's public one.
+    pub fn graphql_url(&self) -> Result<&str, EnclaveError> {
+        self.graphql_url
+            .as_deref()
+            .or(self.network.graphql_url())
+            .ok_or_else(|| {
+                EnclaveError::GenericError(
+                    "sui.graphql_url is required with the custom network".to_string(),
+                )
+            })
+    }
+
+    /// Chain id the fullnode must report: chain_id, or the network's one.
+    pub fn expected_chain_id(&self) -> Result<&str, EnclaveError> {
+        self.chain_id
+            .as_deref()
+            .or(self.network.chain_id())
+            .ok_or_else(|| {
+                EnclaveError::GenericError(format!(
+                    "sui.chain_id is required with the {:?} network",
+                    self.network
+                ))
+            })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CorsConfig {
+    /// Origins allowed to call the server from a browser. Empty sends no
+    /// Access-Control-Allow-Origin header, so only same-origin calls work.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Largest request body accepted.
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize,
+    /// Largest request body by route path, replacing max_body_bytes there.
+    #[serde(default = "default_route_body_bytes")]
+    pub routes: BTreeMap<String, usize>,
+    /// Largest script accepted by /execute_code.
+    #[serde(default = "default_max_code_bytes")]
+    pub max_code_bytes: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_body_bytes: default_max_body_bytes(),
+            routes: default_route_body_bytes(),
+            max_code_bytes: default_max_code_bytes(),
+        }
+    }
+}
+
+impl LimitsConfig {
+    /// Largest request body accepted on `route`.
+    pub fn body_limit(&self, route: &str) -> usize {
+        self.routes
+            .get(route)
+            .copied()
+            .unwrap_or(self.max_body_bytes)
+    }
+}
+
+fn default_max_body_bytes() -> usize {
+    2 * 1024 * 1024
+}
+
+fn default_route_body_bytes() -> BTreeMap<String, usize> {
+    BTreeMap::from([
+        ("/process_data".to_string(), 64 * 1024),
+        ("/process_batch".to_string(), 512 * 1024),
+        ("/execute_code".to_string(), 512 * 1024),
+    ])
+}
+
+fn default_max_code_bytes() -> usize {
+    256 * 1024
+}
+
+/// Time a request may take before it is answered with a 504. Covers the
+/// handler up to the response headers, not streamed bodies.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TimeoutsConfig {
+    #[serde(default = "default_timeout_ms")]
+    pub default_ms: u64,
+    /// Timeout by route path, replacing default_ms there. Apps mounted under
+    /// /apps/{name} use the path within the app.
+    #[serde(default = "default_route_timeouts_ms")]
+    pub routes: BTreeMap<String, u64>,
+}
+
+impl Default for TimeoutsConfig {
+    fn default() -> Self {
+        Self {
+            default_ms: default_timeout_ms(),
+            routes: default_route_timeouts_ms(),
+        }
+    }
+}
+
+impl TimeoutsConfig {
+    /// Timeout of requests to `route`.
+    pub fn timeout(&self, route: &str) -> Duration {
+        Duration::from_millis(self.routes.get(route).copied().unwrap_or(self.default_ms))
+    }
+}
+
+fn default_timeout_ms() -> u64 {
+    30_000
+}
+
+fn default_route_timeouts_ms() -> BTreeMap<String, u64> {
+    BTreeMap::from([
+        ("/health_check".to_string(), 2_000),
+        ("/ready".to_string(), 10_000),
+        ("/get_attestation".to_string(), 10_000),
+        ("/process_data".to_string(), 90_000),
+        // Room for the largest batch of coeus-oracle's default batch section
+        ("/process_batch".to_string(), 250_000),
+        ("/execute_code".to_string(), 90_000),
+    ])
+}
+
+/// API keys, read from the config file only so the host cannot add keys
+/// through the environment.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Keys accepted by authenticated routes, none turns authentication off.
+    #[serde(default)]
+    pub keys: Vec<ApiKeyConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKeyConfig {
+    /// Name used in logs and errors.
+    pub name: String,
+    /// Hex SHA-256 of the key, see auth::hash_key.
+    pub sha256: String,
+    pub scopes: Vec<Scope>,
+}
+
+/// Token bucket limits, see rate_limit.rs. A missing limit is not enforced.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Per API key, or per peer address without one.
+    #[serde(default)]
+    pub client: Option<RateLimit>,
+    /// Per feed id, charged when a feed actually runs.
+    #[serde(default)]
+    pub feed: Option<RateLimit>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    /// Requests allowed at once.
+    pub burst: u32,
+    /// Steady rate the bucket refills at.
+    pub per_second: f64,
+}
+
+/// Response compression, negotiated through Accept-Encoding. Event and NDJSON
+/// streams, gRPC and images are never compressed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CompressionConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_true")]
+    pub gzip: bool,
+    #[serde(default = "default_true")]
+    pub br: bool,
+    /// Smallest response body compressed.
+    #[serde(default = "default_compression_min_bytes")]
+    pub min_bytes: u16,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            gzip: true,
+            br: true,
+            min_bytes: default_compression_min_bytes(),
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_compression_min_bytes() -> u16 {
+    1024
+}
+
+/// Logs forwarded to the host over vsock, see log_forward.rs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogsConfig {
+    #[serde(default)]
+    pub forward: bool,
+    /// CID of the host, 3 for the parent instance of a Nitro enclave.
+    #[serde(default = "default_log_host_cid")]
+    pub host_cid: u32,
+    #[serde(default = "default_log_port")]
+    pub port: u32,
+    /// Most lines sent in one write.
+    #[serde(default = "default_log_batch_lines")]
+    pub batch_lines: usize,
+    /// Longest a line waits for its batch to fill.
+    #[serde(default = "default_log_flush_interval_ms")]
+    pub flush_interval_ms: u64,
+    /// Lines queued while the host is slow or away, later ones are dropped.
+    #[serde(default = "default_log_buffer_lines")]
+    pub buffer_lines: usize,
+}
+
+impl Default for LogsConfig {
+    fn default() -> Self {
+        Self {
+            forward: false,
+            host_cid: default_log_host_cid(),
+            port: default_log_port(),
+            batch_lines: default_log_batch_lines(),
+            flush_interval_ms: default_log_flush_interval_ms(),
+            buffer_lines: default_log_buffer_lines(),
+        }
+    }
+}
+
+fn default_log_host_cid() -> u32 {
+    3
+}
+
+fn default_log_port() -> u32 {
+    9100
+}
+
+fn default_log_batch_lines() -> usize {
+    64
+}
+
+fn default_log_flush_interval_ms() -> u64 {
+    500
+}
+
+fn default_log_buffer_lines() -> usize {
+    4096
+}
+
+/// Check that the signing key and PCRs match an Enclave registration on Sui,
+/// see registration.rs of the app.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegistrationConfig {
+    #[serde(default)]
+    pub mode: RegistrationMode,
+    /// EnclaveConfig object holding the registered PCRs.
+    #[serde(default)]
+    pub enclave_config_id: Option<String>,
+    /// Enclave object registered for this instance. It is created after the
+    /// enclave boots, so it is usually set through PUT /admin/registration.
+    #[serde(default)]
+    pub enclave_object_id: Option<String>,
+    /// Interval between checks, registered or not.
+    #[serde(default = "default_registration_check_interval_ms")]
+    pub check_interval_ms: u64,
+}
+
+impl Default for RegistrationConfig {
+    fn default() -> Self {
+        Self {
+            mode: RegistrationMode::default(),
+            enclave_config_id: None,
+            enclave_object_id: None,
+            check_interval_ms: default_registration_check_interval_ms(),
+        }
+    }
+}
+
+fn default_registration_check_interval_ms() -> u64 {
+    10_000
+}
+
+/// CPU profiles served at GET /admin/profile by builds with the `profiling`
+/// feature, see profiling.rs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfilingConfig {
+    /// Longest capture. Captures past the route timeout of /admin/profile
+    /// are answered with a 504, raise both together.
+    #[serde(default = "default_profiling_max_seconds")]
+    pub max_seconds: u64,
+    /// Default sampling frequency.
+    #[serde(default = "default_profiling_frequency_hz")]
+    pub frequency_hz: i32,
+}
+
+impl Default for ProfilingConfig {
+    fn default() -> Self {
+        Self {
+            max_seconds: default_profiling_max_seconds(),
+            frequency_hz: default_profiling_frequency_hz(),
+        }
+    }
+}
+
+fn default_profiling_max_seconds() -> u64 {
+    20
+}
+
+fn default_profiling_frequency_hz() -> i32 {
+    99
+}
+
+/// What an enclave does while its registration is not verified.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistrationMode {
+    /// No check.
+    #[default]
+    Off,
+    /// Sign anyway, with an `X-Enclave-Registration: unregistered` header.
+    Mark,
+    /// Refuse to sign.
+    Enforce,
+}
+
+/// gRPC service of the app, see proto/. It listens on TCP, also with the
+/// vsock listener, so run.sh must forward its port. With the ra-tls feature
+/// it is served over TLS with the attested certificate of the HTTP server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GrpcConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_grpc_bind_address")]
+    pub bind_address: String,
+    /// Requests of one ProcessDataStream run at once. Further requests are
+    /// not read from the stream until one completes.
+    #[serde(default = "default_grpc_max_in_flight_per_stream")]
+    pub max_in_flight_per_stream: usize,
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_address: default_grpc_bind_address(),
+            max_in_flight_per_stream: default_grpc_max_in_flight_per_stream(),
+        }
+    }
+}
+
+fn default_grpc_bind_address() -> String {
+    "0.0.0.0:3001".to_string()
+}
+
+fn default_grpc_max_in_flight_per_stream() -> usize {
+    8
+}
+
+impl AppConfig {
+    /// Load the config file and apply the environment overrides. `path`
+    /// takes precedence over `NAUTILUS_CONFIG`. A missing file is only an
+    /// error when one of them names it explicitly.
+    pub fn load(path: Option<&str>) -> Result<Self, EnclaveError> {
+        let explicit_path = path
+            .map(str::to_string)
+            .or_else(|| std::env::var(CONFIG_PATH_ENV).ok());
+        let path = explicit_path.as_deref().unwrap_or(DEFAULT_CONFIG_PATH);
+        let mut config = match std::fs::read_to_string(path) {
+            Ok(contents) => Self::from_toml(&contents)?,
+            Err(e) if explicit_path.is_some() => {
+                return Err(EnclaveError::GenericError(format!(
+                    "Failed to read config file {}: {}",
+                    path, e
+                )));
+            }
+            Err(_) => Self::default(),
+        };
+        config.apply_overrides(|name| std::env::var(name).ok())?;
+        Ok(config)
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self, EnclaveError> {
+        toml::from_str(contents)
+            .map_err(|e| EnclaveError::GenericError(format!("Invalid config file: {}", e)))
+    }
+
+    /// Apply `NAUTILUS_*` overrides read through `lookup`.
+    pub fn apply_overrides(
+        &mut self,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<(), EnclaveError> {
+        if let Some(listener) = lookup("NAUTILUS_LISTENER") {
+            self.server.listener = match listener.as_str() {
+                "tcp" => Listener::Tcp,
+                "vsock" => Listener::Vsock,
+                _ => {
+                    return Err(EnclaveError::GenericError(format!(
+                        "Invalid NAUTILUS_LISTENER {}, expected tcp or vsock",
+                        listener
+                    )));
+                }
+            };
+        }
+        if let Some(vsock_port) = lookup("NAUTILUS_VSOCK_PORT") {
+            self.server.vsock_port = vsock_port.parse().map_err(|e| {
+                EnclaveError::GenericError(format!("Invalid NAUTILUS_VSOCK_PORT: {}", e))
+            })?;
+        }
+        if let Some(bind_address) = lookup("NAUTILUS_BIND_ADDRESS") {
+            self.server.bind_address = bind_address;
+        }
+        if let Some(network) = lookup("NAUTILUS_SUI_NETWORK") {
+            self.sui.network = clap::ValueEnum::from_str(&network, true).map_err(|_| {
+                EnclaveError::GenericError(format!(
+                    "Invalid NAUTILUS_SUI_NETWORK {}, expected mainnet, testnet, devnet, localnet or custom",
+                    network
+                ))
+            })?;
+        }
+        // The chain id is only read from the config file, so the host cannot
+        // lift the check
+        if let Some(fullnode_url) = lookup("NAUTILUS_FULLNODE_URL") {
+            self.sui.fullnode_url = Some(fullnode_url);
+        }
+        if let Some(fallback_urls) = lookup("NAUTILUS_FALLBACK_URLS") {
+            self.sui.fallback_urls = fallback_urls
+                .split(',')
+                .map(|url| url.trim().to_string())
+                .filter(|url| !url.is_empty())
+                .collect();
+        }
+        if let Some(origins) = lookup("NAUTILUS_CORS_ALLOWED_ORIGINS") {
+            self.cors.allowed_origins = origins
+                .split(',')
+                .map(|origin| origin.trim().to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect();
+        }
+        if let Some(compression) = lookup("NAUTILUS_COMPRESSION") {
+            self.compression.enabled = compression.parse().map_err(|e| {
+                EnclaveError::GenericError(format!("Invalid NAUTILUS_COMPRESSION: {}", e))
+            })?;
+        }
+        if let Some(max_body_bytes) = lookup("NAUTILUS_MAX_BODY_BYTES") {
+            self.limits.max_body_bytes = max_body_bytes.parse().map_err(|e| {
+                EnclaveError::GenericError(format!("Invalid NAUTILUS_MAX_BODY_BYTES: {}", e))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Listen on `port`: the vsock port with the vsock listener, otherwise
+    /// the port of the bind address.
+    pub fn set_port(&mut self, port: u16) -> Result<(), EnclaveError> {
+        if self.server.listener == Listener::Vsock {
+            self.server.vsock_port = port.into();
+            return Ok(());
+        }
+        let mut addr: std::net::SocketAddr = self.server.bind_address.parse().map_err(|e| {
+            EnclaveError::GenericError(format!(
+                "Invalid bind address {}: {}",
+                self.server.bind_address, e
+            ))
+        })?;
+        addr.set_port(port);
+        self.server.bind_address = addr.to_string();
+        Ok(())
+    }
+
+    /// Configuration of app `name`, with its `[apps.<name>]` section applied.
+    pub fn for_app(&self, name: &str) -> AppConfig {
+        let mut config = self.clone();
+        if let Some(registration) = self
+            .apps
+            .get(name)
+            .and_then(|section| section.registration.clone())
+        {
+            config.registration = registration;
+        }
+        config
+    }
+
+    /// SHA-256 of the effective configuration serialized as JSON.
+    pub fn hash(&self) -> [u8; 32] {
+        let json = serde_json::to_vec(self).expect("config serializes");
+        Sha256::digest(json).digest
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_load_config() {
+        let mut config = AppConfig::from_toml(
+            r#"
+            [server]
+            bind_address = "127.0.0.1:4000"
+
+            [cors]
+            allowed_origins = ["https://example.com"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.server.bind_address, "127.0.0.1:4000");
+        assert_eq!(config.sui, SuiConfig::default());
+        assert_eq!(config.sui.fullnode_url().unwrap(), Client::TESTNET_FULLNODE);
+        assert_eq!(config.sui.expected_chain_id().unwrap(), TESTNET_CHAIN_ID);
+        assert_eq!(config.sui.backend, SuiBackend::Grpc);
+        assert_eq!(
+            config.sui.graphql_url().unwrap(),
+            "https://graphql.testnet.sui.io/graphql"
+        );
+        assert_eq!(config.limits.max_body_bytes, 2 * 1024 * 1024);
+        assert_eq!(config.limits.body_limit("/process_data"), 64 * 1024);
+        assert_eq!(config.limits.body_limit("/keys"), 2 * 1024 * 1024);
+        assert!(!config.grpc.enabled);
+        assert_eq!(config.server.root_app, None);
+        assert!(!config.logs.forward);
+        assert_eq!(config.registration.mode, RegistrationMode::Off);
+        assert_eq!(
+            config.timeouts.timeout("/health_check"),
+            Duration::from_secs(2)
+        );
+        assert_eq!(config.timeouts.timeout("/keys"), Duration::from_secs(30));
+        assert_eq!(config.grpc.bind_address, "0.0.0.0:3001");
+        assert_eq!(config.grpc.max_in_flight_per_stream, 8);
+        assert!(AppConfig::from_toml("[server]\nport = 1").is_err());
+
+        let hash = config.hash();
+        config
+            .apply_overrides(|name| match name {
+                "NAUTILUS_CORS_ALLOWED_ORIGINS" => Some("https://a.com, https://b.com".to_string()),
+                "NAUTILUS_MAX_BODY_BYTES" => Some("1024".to_string()),
+                "NAUTILUS_COMPRESSION" => Some("false".to_string()),
+                "NAUTILUS_SUI_NETWORK" => Some("mainnet".to_string()),
+                "NAUTILUS_FALLBACK_URLS" => Some(format!(
+                    "https://a.example.com, {}",
+                    Client::MAINNET_FULLNODE
+                )),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(
+            config.cors.allowed_origins,
+            vec!["https://a.com", "https://b.com"]
+        );
+        assert_eq!(config.limits.max_body_bytes, 1024);
+        assert_eq!(config.sui.network, SuiNetwork::Mainnet);
+        assert_eq!(config.sui.expected_chain_id().unwrap(), MAINNET_CHAIN_ID);
+        // Devnet is reset, so its chain id must be configured
+        let mut devnet = config.sui.clone();
+        devnet.network = SuiNetwork::Devnet;
+        assert!(devnet.expected_chain_id().is_err());
+        devnet.chain_id = Some("devnet-chain".to_string());
+        assert_eq!(devnet.expected_chain_id().unwrap(), "devnet-chain");
+        // The primary fullnode is not repeated
+        assert_eq!(
+            config.sui.fullnode_urls().unwrap(),
+            vec![Client::MAINNET_FULLNODE, "https://a.example.com"]
+        );
+        assert!(!config.compression.enabled);
+        assert_ne!(config.hash(), hash);
+
+        config.set_port(8080).unwrap();
+        assert_eq!(config.server.bind_address, "127.0.0.1:8080");
+
+        let mut config = AppConfig::from_toml("[server]\nlistener = \"vsock\"").unwrap();
+        assert_eq!(config.server.listener, Listener::Vsock);
+        assert_eq!(config.server.vsock_cid, u32::MAX);
+        config.set_port(5005).unwrap();
+        assert_eq!(config.server.vsock_port, 5005);
+        assert_eq!(config.server.bind_address, "0.0.0.0:3000");
+        assert!(
+            config
+                .apply_overrides(|name| (name == "NAUTILUS_LISTENER").then(|| "udp".to_string()))
+                .is_err()
+        );
+
+        assert!(
+            config
+                .apply_overrides(|_| Some("not a number".to_string()))
+                .is_err()
+        );
+    }
+}
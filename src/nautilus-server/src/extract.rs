@@ -0,0 +1,69 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! JSON body extractor whose rejections are EnclaveErrors, so malformed or
+//! oversized bodies get the same problem details as every other error instead
+//! of axum's plain text responses.
+
+use crate::EnclaveError;
+use axum::extract::FromRequest;
+use axum::extract::rejection::JsonRejection;
+use axum::http::StatusCode;
+
+/// `axum::Json` with EnclaveError rejections: 413 PayloadTooLarge over the
+/// route's body limit, 400 InvalidRequest otherwise.
+#[derive(Debug, FromRequest)]
+#[from_request(via(axum::Json), rejection(EnclaveError))]
+pub struct AppJson<T>(pub T);
+
+impl From<JsonRejection> for EnclaveError {
+    fn from(rejection: JsonRejection) -> Self {
+        if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            EnclaveError::PayloadTooLarge(rejection.body_text())
+        } else {
+            EnclaveError::InvalidRequest(rejection.body_text())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::body::Body;
+    use axum::extract::Request;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    struct FeedRequest {
+        feed_id: String,
+    }
+
+    async fn extract(body: impl Into<Body>) -> Result<AppJson<FeedRequest>, EnclaveError> {
+        let request = Request::post("/")
+            .header("content-type", "application/json")
+            .body(body.into())
+            .unwrap();
+        AppJson::<FeedRequest>::from_request(request, &()).await
+    }
+
+    #[tokio::test]
+    async fn test_rejections() {
+        let AppJson(request) = extract(r#"{"feed_id":"0x1"}"#).await.unwrap();
+        assert_eq!(request.feed_id, "0x1");
+
+        assert!(matches!(
+            extract("{").await,
+            Err(EnclaveError::InvalidRequest(_))
+        ));
+        match extract(r#"{"feed":1}"#).await {
+            Err(EnclaveError::InvalidRequest(message)) => assert!(message.contains("feed_id")),
+            other => panic!("expected InvalidRequest, got {:?}", other),
+        }
+        // Over axum's default 2MB body limit
+        let body = format!(r#"{{"feed_id":"{}"}}"#, "0".repeat(3 * 1024 * 1024));
+        assert!(matches!(
+            extract(body).await,
+            Err(EnclaveError::PayloadTooLarge(_))
+        ));
+    }
+}
@@ -0,0 +1,459 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Sui reads through the GraphQL RPC service, selected with
+//! `sui.backend = "graphql"` in nautilus.toml. Its host must be in
+//! allowed_endpoints.yaml.
+//!
+//! The service is a single endpoint without failover, transient errors are
+//! returned to the caller. Its chain identifier is checked at startup like the
+//! fullnodes' (see network.rs), and object reads are answered as of the
+//! service's latest indexed checkpoint, queried together with the object so
+//! the two are consistent.
+
+use crate::EnclaveError;
+use crate::chain_reader::{
+    ChainReader, MAX_BATCH_OBJECTS, ObjectRead, ReadFuture, TransactionRead,
+};
+use crate::config::SuiConfig;
+use crate::metrics;
+use fastcrypto::encoding::{Base64, Encoding};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use std::time::{Duration, Instant};
+use sui_sdk_types::{Address, StructTag};
+use tonic::Status;
+
+/// Time a query may take.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+const CHAIN_IDENTIFIER: &str = "query { chainIdentifier }";
+
+const LATEST_CHECKPOINT: &str = "query { checkpoint { sequenceNumber } }";
+
+const OBJECT: &str = "query ($address: SuiAddress!, $version: UInt53) {
+  checkpoint { sequenceNumber }
+  object(address: $address, version: $version) {
+    version
+    objectBcs
+    previousTransaction { digest }
+  }
+}";
+
+const MULTI_GET_OBJECTS: &str = "query ($keys: [ObjectKey!]!) {
+  multiGetObjects(keys: $keys) { objectBcs }
+}";
+
+const TRANSACTION: &str = "query ($digest: String!, $after: String) {
+  transaction(digest: $digest) {
+    effects {
+      checkpoint { sequenceNumber }
+      objectChanges(first: 50, after: $after) {
+        pageInfo { hasNextPage endCursor }
+        nodes { address inputState { version } }
+      }
+    }
+  }
+}";
+
+const EVENTS: &str = "query ($type: String!, $checkpoint: UInt53!, $after: String) {
+  events(first: 50, after: $after, filter: { type: $type, atCheckpoint: $checkpoint }) {
+    pageInfo { hasNextPage endCursor }
+    nodes { contents { bcs } }
+  }
+}";
+
+#[derive(Debug, Deserialize)]
+struct Response<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<GraphqlError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphqlError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    has_next_page: bool,
+    end_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Connection<T> {
+    page_info: PageInfo,
+    nodes: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Checkpoint {
+    sequence_number: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChainIdentifierData {
+    chain_identifier: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct LatestCheckpointData {
+    checkpoint: Option<Checkpoint>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Digest {
+    digest: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Object {
+    version: Option<u64>,
+    object_bcs: Option<String>,
+    previous_transaction: Option<Digest>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ObjectData {
+    checkpoint: Option<Checkpoint>,
+    object: Option<Object>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MultiGetObjectsData {
+    multi_get_objects: Vec<Option<Object>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InputState {
+    version: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ObjectChange {
+    address: String,
+    input_state: Option<InputState>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Effects {
+    checkpoint: Option<Checkpoint>,
+    object_changes: Connection<ObjectChange>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Transaction {
+    effects: Option<Effects>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TransactionData {
+    transaction: Option<Transaction>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MoveValue {
+    bcs: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct EventNode {
+    contents: Option<MoveValue>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EventsData {
+    events: Connection<EventNode>,
+}
+
+fn decode_base64(field: &str, value: &str) -> Result<Vec<u8>, Status> {
+    Base64::decode(value).map_err(|e| Status::internal(format!("Invalid {}: {}", field, e)))
+}
+
+/// BCS of a queried object, None when it does not exist.
+fn object_bcs(id: &Address, object: Option<Object>) -> Result<Vec<u8>, Status> {
+    let object = object.ok_or_else(|| Status::not_found(format!("Object {} not found", id)))?;
+    let bcs = object
+        .object_bcs
+        .ok_or_else(|| Status::internal(format!("No BCS data for object {}", id)))?;
+    decode_base64("objectBcs", &bcs)
+}
+
+/// Status of a response with GraphQL errors, or the data of one without.
+fn into_data<T>(response: Response<T>) -> Result<T, Status> {
+    if let Some(error) = response.errors.first() {
+        return Err(Status::internal(error.message.clone()));
+    }
+    response
+        .data
+        .ok_or_else(|| Status::internal("GraphQL response has no data"))
+}
+
+/// Reader of the GraphQL RPC service.
+pub struct GraphqlReader {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl GraphqlReader {
+    pub fn new(config: &SuiConfig) -> Result<Self, EnclaveError> {
+        let client = reqwest::Client::builder()
+            .timeout(QUERY_TIMEOUT)
+            .build()
+            .map_err(|e| {
+                EnclaveError::GenericError(format!("Failed to build GraphQL client: {}", e))
+            })?;
+        Ok(Self {
+            url: config.graphql_url()?.to_string(),
+            client,
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Run `query`, `method` labels the latency metric like fullnode calls.
+    async fn query<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        query: &str,
+        variables: Value,
+    ) -> Result<T, Status> {
+        let started = Instant::now();
+        let outcome = self
+            .client
+            .post(&self.url)
+            .json(&json!({ "query": query, "variables": variables }))
+            .send()
+            .await;
+        metrics::SUI_RPC_SECONDS
+            .with_label_values(&[method])
+            .observe(started.elapsed().as_secs_f64());
+        let response = outcome.map_err(|e| {
+            if e.is_timeout() {
+                Status::deadline_exceeded(format!("GraphQL {} timed out", method))
+            } else {
+                Status::unavailable(format!("GraphQL {} failed: {}", method, e))
+            }
+        })?;
+        let status = response.status();
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(Status::resource_exhausted(format!(
+                "GraphQL {} rate limited",
+                method
+            )));
+        }
+        if status.is_server_error() {
+            return Err(Status::unavailable(format!(
+                "GraphQL {} failed with HTTP {}",
+                method, status
+            )));
+        }
+        let response: Response<T> = response
+            .json()
+            .await
+            .map_err(|e| Status::internal(format!("Invalid GraphQL {} response: {}", method, e)))?;
+        into_data(response)
+    }
+
+    /// Chain id of the network the service indexes.
+    pub async fn chain_id(&self) -> Result<String, Status> {
+        let data: ChainIdentifierData = self
+            .query("chain_identifier", CHAIN_IDENTIFIER, json!({}))
+            .await?;
+        Ok(data.chain_identifier)
+    }
+}
+
+impl ChainReader for GraphqlReader {
+    fn get_object(&self, id: Address, version: Option<u64>) -> ReadFuture<'_, ObjectRead> {
+        Box::pin(async move {
+            let variables = json!({ "address": id.to_string(), "version": version });
+            let data: ObjectData = self.query("get_object", OBJECT, variables).await?;
+            let object = data
+                .object
+                .ok_or_else(|| Status::not_found(format!("Object {} not found", id)))?;
+            let version = object
+                .version
+                .ok_or_else(|| Status::internal(format!("No version for object {}", id)))?;
+            let previous_transaction = object
+                .previous_transaction
+                .as_ref()
+                .map(|transaction| transaction.digest.clone());
+            Ok(ObjectRead {
+                bcs: object_bcs(&id, Some(object))?,
+                version,
+                previous_transaction,
+                checkpoint: data.checkpoint.map(|checkpoint| checkpoint.sequence_number),
+            })
+        })
+    }
+
+    fn get_objects<'a>(&'a self, ids: &'a [Address]) -> ReadFuture<'a, Vec<Vec<u8>>> {
+        Box::pin(async move {
+            let mut objects = Vec::with_capacity(ids.len());
+            for batch in ids.chunks(MAX_BATCH_OBJECTS) {
+                let keys = batch
+                    .iter()
+                    .map(|id| json!({ "address": id.to_string() }))
+                    .collect::<Vec<_>>();
+                let data: MultiGetObjectsData = self
+                    .query(
+                        "batch_get_objects",
+                        MULTI_GET_OBJECTS,
+                        json!({ "keys": keys }),
+                    )
+                    .await?;
+                if data.multi_get_objects.len() != batch.len() {
+                    return Err(Status::internal(format!(
+                        "multiGetObjects returned {} objects for {} ids",
+                        data.multi_get_objects.len(),
+                        batch.len()
+                    )));
+                }
+                for (id, object) in batch.iter().zip(data.multi_get_objects) {
+                    objects.push(object_bcs(id, object)?);
+                }
+            }
+            Ok(objects)
+        })
+    }
+
+    fn get_transaction<'a>(&'a self, digest: &'a str) -> ReadFuture<'a, TransactionRead> {
+        Box::pin(async move {
+            let mut read = TransactionRead::default();
+            let mut after: Option<String> = None;
+            loop {
+                let variables = json!({ "digest": digest, "after": after });
+                let data: TransactionData = self
+                    .query("get_transaction", TRANSACTION, variables)
+                    .await?;
+                let effects = data
+                    .transaction
+                    .and_then(|transaction| transaction.effects)
+                    .ok_or_else(|| {
+                        Status::not_found(format!("Transaction {} not found", digest))
+                    })?;
+                read.checkpoint = effects
+                    .checkpoint
+                    .map(|checkpoint| checkpoint.sequence_number);
+                for change in effects.object_changes.nodes {
+                    let Some(input_state) = change.input_state else {
+                        continue;
+                    };
+                    let id = Address::from_hex(&change.address).map_err(|e| {
+                        Status::internal(format!("Invalid address {}: {}", change.address, e))
+                    })?;
+                    read.input_versions.push((id, input_state.version));
+                }
+                let page_info = effects.object_changes.page_info;
+                if !page_info.has_next_page {
+                    return Ok(read);
+                }
+                after = page_info.end_cursor;
+            }
+        })
+    }
+
+    fn latest_checkpoint(&self) -> ReadFuture<'_, u64> {
+        Box::pin(async move {
+            let data: LatestCheckpointData = self
+                .query("get_service_info", LATEST_CHECKPOINT, json!({}))
+                .await?;
+            data.checkpoint
+                .map(|checkpoint| checkpoint.sequence_number)
+                .ok_or_else(|| Status::internal("GraphQL returned no checkpoint"))
+        })
+    }
+
+    fn checkpoint_events<'a>(
+        &'a self,
+        sequence_number: u64,
+        event_type: &'a StructTag,
+    ) -> ReadFuture<'a, Vec<Vec<u8>>> {
+        Box::pin(async move {
+            let mut contents = Vec::new();
+            let mut after: Option<String> = None;
+            loop {
+                let variables = json!({
+                    "type": event_type.to_string(),
+                    "checkpoint": sequence_number,
+                    "after": after,
+                });
+                let data: EventsData = self.query("get_checkpoint", EVENTS, variables).await?;
+                for event in data.events.nodes {
+                    if let Some(value) = event.contents {
+                        contents.push(decode_base64("event bcs", &value.bcs)?);
+                    }
+                }
+                if !data.events.page_info.has_next_page {
+                    return Ok(contents);
+                }
+                after = data.events.page_info.end_cursor;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_responses() {
+        let id = Address::from_hex("0x2").unwrap();
+        let response: Response<ObjectData> = serde_json::from_value(json!({
+            "data": {
+                "checkpoint": { "sequenceNumber": 42 },
+                "object": {
+                    "version": 7,
+                    "objectBcs": Base64::encode([1, 2, 3]),
+                    "previousTransaction": { "digest": "abc" }
+                }
+            }
+        }))
+        .unwrap();
+        let data = into_data(response).unwrap();
+        assert_eq!(data.checkpoint.unwrap().sequence_number, 42);
+        let object = data.object.unwrap();
+        assert_eq!(object.previous_transaction.as_ref().unwrap().digest, "abc");
+        assert_eq!(object_bcs(&id, Some(object)).unwrap(), vec![1, 2, 3]);
+
+        // A missing object is NotFound, GraphQL errors fail the query
+        assert_eq!(
+            object_bcs(&id, None).unwrap_err().code(),
+            tonic::Code::NotFound
+        );
+        let response: Response<ObjectData> = serde_json::from_value(json!({
+            "data": null,
+            "errors": [{ "message": "Invalid SuiAddress" }]
+        }))
+        .unwrap();
+        assert_eq!(
+            into_data(response).unwrap_err().message(),
+            "Invalid SuiAddress"
+        );
+
+        let response: Response<EventsData> = serde_json::from_value(json!({
+            "data": { "events": {
+                "pageInfo": { "hasNextPage": false, "endCursor": null },
+                "nodes": [{ "contents": { "bcs": Base64::encode([5]) } }]
+            } }
+        }))
+        .unwrap();
+        let events = into_data(response).unwrap().events;
+        assert!(!events.page_info.has_next_page);
+        assert_eq!(events.nodes.len(), 1);
+    }
+}
@@ -1,6 +1,8 @@
 // Copyright (c), Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+use axum::http::header;
+use axum::http::HeaderValue;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::response::Response;
@@ -8,8 +10,8 @@ use axum::Json;
 use fastcrypto::ed25519::Ed25519KeyPair;
 use fastcrypto::ed25519::Ed25519PrivateKey;
 use serde_json::json;
-use sui_rpc::client::Client;
 use std::fmt;
+use std::sync::Arc;
 
 mod apps {
     #[cfg(feature = "twitter-example")]
@@ -43,25 +45,235 @@ pub mod app {
     pub use crate::apps::coeus_oracle::*;
 }
 
+/// Apps compiled into this binary, each selected by its cargo feature.
+pub const APPS: &[&str] = &[
+    #[cfg(feature = "twitter-example")]
+    "twitter-example",
+    #[cfg(feature = "weather-example")]
+    "weather-example",
+    #[cfg(feature = "seal-example")]
+    "seal-example",
+    #[cfg(feature = "coeus-oracle")]
+    "coeus-oracle",
+];
+
+pub mod admin;
+pub mod auth;
+pub mod catch_panic;
+pub mod chain_reader;
 pub mod common;
+pub mod config;
+pub mod extract;
+pub mod graphql;
+pub mod log_forward;
+pub mod metrics;
+#[cfg(test)]
+pub mod mock_chain;
+pub mod mount;
+pub mod network;
+pub mod rate_limit;
+pub mod request_id;
+#[cfg(any(feature = "seal-example", feature = "coeus-oracle"))]
+pub mod seal_store;
+pub mod sui_pool;
+pub mod timeout;
+
+#[cfg(feature = "profiling")]
+pub mod profiling;
+
+#[cfg(feature = "ra-tls")]
+pub mod ratls;
+
+pub mod vsock;
 
 /// App state, at minimum needs to maintain the ephemeral keypair.  
 pub struct AppState {
     pub eph_kp: Ed25519KeyPair,
 
-    pub sui_client: Client,
+    /// Fullnodes Sui RPC calls go to, with failover and retries.
+    pub sui: Arc<sui_pool::SuiPool>,
+
+    /// Backend objects, transactions and events are read from, see
+    /// `sui.backend` in nautilus.toml.
+    pub chain: Arc<dyn chain_reader::ChainReader>,
+
+    pub config: config::AppConfig,
 }
 
-/// Implement IntoResponse for EnclaveError.
+/// Implement IntoResponse for EnclaveError. Errors are returned as RFC 7807
+/// problem details (application/problem+json): `type`, `title`, `status` and
+/// `detail`, a stable machine readable `code`, and the variant's fields as
+/// extension members.
 impl IntoResponse for EnclaveError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            EnclaveError::GenericError(e) => (StatusCode::BAD_REQUEST, e),
+        let (status, code, title) = self.problem();
+        let mut body = json!({
+            "type": format!("urn:nautilus:error:{}", code),
+            "title": title,
+            "status": status.as_u16(),
+            "detail": self.to_string(),
+            "code": code,
+        });
+        let extensions = match &self {
+            EnclaveError::UpdateTooEarly {
+                earliest_allowed_timestamp_ms,
+                current_timestamp_ms,
+            } => json!({
+                "earliest_allowed_timestamp_ms": earliest_allowed_timestamp_ms,
+                "current_timestamp_ms": current_timestamp_ms,
+            }),
+            EnclaveError::UpdateNotRequired {
+                deviation_bps,
+                max_deviation_bps,
+                next_heartbeat_timestamp_ms,
+            } => json!({
+                "deviation_bps": deviation_bps,
+                "max_deviation_bps": max_deviation_bps,
+                "next_heartbeat_timestamp_ms": next_heartbeat_timestamp_ms,
+            }),
+            EnclaveError::RateLimited { retry_after_ms }
+            | EnclaveError::Overloaded { retry_after_ms } => {
+                json!({ "retry_after_ms": retry_after_ms })
+            }
+            EnclaveError::ConflictingResult { timestamp_ms } => {
+                json!({ "timestamp_ms": timestamp_ms })
+            }
+            EnclaveError::FeedNotFound { feed_id } | EnclaveError::FeedPaused { feed_id } => {
+                json!({ "feed_id": feed_id })
+            }
+            EnclaveError::BlobFetchFailed { blob_id, .. } => json!({ "blob_id": blob_id }),
+            EnclaveError::ScriptError { line, .. } => json!({ "line": line }),
+            EnclaveError::UpstreamTimeout { upstream } => json!({ "upstream": upstream }),
+            EnclaveError::RequestTimeout { timeout_ms } => json!({ "timeout_ms": timeout_ms }),
+            _ => json!({}),
         };
-        let body = Json(json!({
-            "error": error_message,
-        }));
-        (status, body).into_response()
+        if let serde_json::Value::Object(extensions) = extensions {
+            for (key, value) in extensions {
+                body[key] = value;
+            }
+        }
+        if let Some(request_id) = request_id::current() {
+            body["request_id"] = json!(request_id);
+        }
+
+        let mut response = (status, Json(body)).into_response();
+        if !status.is_success() {
+            response.headers_mut().insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/problem+json"),
+            );
+        }
+        if let EnclaveError::RateLimited { retry_after_ms }
+        | EnclaveError::Overloaded { retry_after_ms } = self
+        {
+            response.headers_mut().insert(
+                header::RETRY_AFTER,
+                HeaderValue::from(retry_after_ms.div_ceil(1000)),
+            );
+        }
+        response
+    }
+}
+
+impl EnclaveError {
+    /// Stable error code, the `code` member of the response body.
+    pub fn code(&self) -> &'static str {
+        self.problem().1
+    }
+
+    /// HTTP status of the problem type.
+    pub fn status(&self) -> StatusCode {
+        self.problem().0
+    }
+
+    /// HTTP status, error code and title of the problem type.
+    fn problem(&self) -> (StatusCode, &'static str, &'static str) {
+        match self {
+            EnclaveError::GenericError(_) => {
+                (StatusCode::BAD_REQUEST, "generic_error", "Request failed")
+            }
+            EnclaveError::Internal(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_error",
+                "Internal error",
+            ),
+            EnclaveError::InvalidRequest(_) => (
+                StatusCode::BAD_REQUEST,
+                "invalid_request",
+                "Invalid request",
+            ),
+            EnclaveError::PayloadTooLarge(_) => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "payload_too_large",
+                "Payload too large",
+            ),
+            EnclaveError::FeedNotFound { .. } => {
+                (StatusCode::NOT_FOUND, "feed_not_found", "Feed not found")
+            }
+            EnclaveError::FeedPaused { .. } => {
+                (StatusCode::SERVICE_UNAVAILABLE, "feed_paused", "Feed paused")
+            }
+            EnclaveError::BlobFetchFailed { .. } => (
+                StatusCode::BAD_GATEWAY,
+                "blob_fetch_failed",
+                "Failed to fetch feed code",
+            ),
+            EnclaveError::ScriptError { .. } => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "script_error",
+                "Feed script failed",
+            ),
+            EnclaveError::ConversionError(_) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "conversion_error",
+                "Result does not match the return type",
+            ),
+            EnclaveError::UpstreamTimeout { .. } => (
+                StatusCode::GATEWAY_TIMEOUT,
+                "upstream_timeout",
+                "Upstream timed out",
+            ),
+            EnclaveError::RequestTimeout { .. } => (
+                StatusCode::GATEWAY_TIMEOUT,
+                "request_timeout",
+                "Request timed out",
+            ),
+            EnclaveError::RpcError(_) => (StatusCode::BAD_GATEWAY, "rpc_error", "Sui RPC failed"),
+            EnclaveError::UpdateTooEarly { .. } => (
+                StatusCode::CONFLICT,
+                "update_too_early",
+                "Update too early",
+            ),
+            EnclaveError::UpdateNotRequired { .. } => (
+                StatusCode::CONFLICT,
+                "update_not_required",
+                "Update not required",
+            ),
+            EnclaveError::ConflictingResult { .. } => (
+                StatusCode::CONFLICT,
+                "conflicting_result",
+                "A different result was already signed for this feed and timestamp",
+            ),
+            EnclaveError::Unauthorized(_) => {
+                (StatusCode::UNAUTHORIZED, "unauthorized", "Unauthorized")
+            }
+            EnclaveError::Forbidden(_) => (StatusCode::FORBIDDEN, "forbidden", "Forbidden"),
+            EnclaveError::RateLimited { .. } => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limited",
+                "Rate limited",
+            ),
+            EnclaveError::Overloaded { .. } => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "overloaded",
+                "Enclave overloaded",
+            ),
+            EnclaveError::Unregistered(_) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "unregistered",
+                "Enclave not registered",
+            ),
+        }
     }
 }
 
@@ -69,14 +281,175 @@ impl IntoResponse for EnclaveError {
 #[derive(Debug)]
 pub enum EnclaveError {
     GenericError(String),
+    /// A bug in the enclave, such as a panic while handling the request.
+    Internal(String),
+    /// The request is malformed, such as an invalid feed id.
+    InvalidRequest(String),
+    /// The request body is over the route's limit.
+    PayloadTooLarge(String),
+    /// No feed object with this id.
+    FeedNotFound { feed_id: String },
+    /// Runs of the feed were paused through the admin API.
+    FeedPaused { feed_id: String },
+    /// The feed code could not be downloaded from any source.
+    BlobFetchFailed { blob_id: String, message: String },
+    /// The feed script failed, with the script line when the runtime reports it.
+    ScriptError { line: Option<usize>, message: String },
+    /// The script result cannot be converted to the feed's return type.
+    ConversionError(String),
+    /// An upstream service did not answer in time.
+    UpstreamTimeout { upstream: String },
+    /// The request took longer than its route's timeout.
+    RequestTimeout { timeout_ms: u64 },
+    /// A Sui fullnode call failed.
+    RpcError(String),
+    /// The feed does not accept updates before `earliest_allowed_timestamp_ms`.
+    UpdateTooEarly {
+        earliest_allowed_timestamp_ms: u64,
+        current_timestamp_ms: u64,
+    },
+    /// The new value is within the feed's deviation threshold and the heartbeat has not elapsed.
+    UpdateNotRequired {
+        deviation_bps: u64,
+        max_deviation_bps: u64,
+        next_heartbeat_timestamp_ms: u64,
+    },
+    /// A different result was already signed for the same feed and timestamp.
+    ConflictingResult { timestamp_ms: u64 },
+    /// The request carries no valid API key.
+    Unauthorized(String),
+    /// The API key does not grant access to the route.
+    Forbidden(String),
+    /// The caller or feed is over its rate limit.
+    RateLimited { retry_after_ms: u64 },
+    /// Every execution slot is busy and the queue is full.
+    Overloaded { retry_after_ms: u64 },
+    /// The signing key or PCRs do not match the on-chain registration.
+    Unregistered(String),
 }
 
 impl fmt::Display for EnclaveError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             EnclaveError::GenericError(e) => write!(f, "{}", e),
+            EnclaveError::Internal(e) => write!(f, "Internal error: {}", e),
+            EnclaveError::InvalidRequest(e) => write!(f, "Invalid request: {}", e),
+            EnclaveError::PayloadTooLarge(e) => write!(f, "Payload too large: {}", e),
+            EnclaveError::FeedNotFound { feed_id } => write!(f, "Feed {} not found", feed_id),
+            EnclaveError::FeedPaused { feed_id } => write!(f, "Feed {} is paused", feed_id),
+            EnclaveError::BlobFetchFailed { blob_id, message } => {
+                write!(f, "Failed to fetch blob {}: {}", blob_id, message)
+            }
+            EnclaveError::ScriptError {
+                line: Some(line),
+                message,
+            } => write!(f, "Script error at line {}: {}", line, message),
+            EnclaveError::ScriptError {
+                line: None,
+                message,
+            } => {
+                write!(f, "Script error: {}", message)
+            }
+            EnclaveError::ConversionError(e) => write!(f, "Conversion error: {}", e),
+            EnclaveError::UpstreamTimeout { upstream } => write!(f, "{} timed out", upstream),
+            EnclaveError::RequestTimeout { timeout_ms } => {
+                write!(f, "Request timed out after {}ms", timeout_ms)
+            }
+            EnclaveError::RpcError(e) => write!(f, "Sui RPC error: {}", e),
+            EnclaveError::UpdateTooEarly {
+                earliest_allowed_timestamp_ms,
+                current_timestamp_ms,
+            } => write!(
+                f,
+                "Update too early: current time {} is before allowed update time {}",
+                current_timestamp_ms, earliest_allowed_timestamp_ms
+            ),
+            EnclaveError::UpdateNotRequired {
+                deviation_bps,
+                max_deviation_bps,
+                next_heartbeat_timestamp_ms,
+            } => write!(
+                f,
+                "Update not required: deviation {}bps <= {}bps, next heartbeat at {}",
+                deviation_bps, max_deviation_bps, next_heartbeat_timestamp_ms
+            ),
+            EnclaveError::Unauthorized(e) => write!(f, "Unauthorized: {}", e),
+            EnclaveError::Forbidden(e) => write!(f, "Forbidden: {}", e),
+            EnclaveError::RateLimited { retry_after_ms } => {
+                write!(f, "Rate limited, retry after {}ms", retry_after_ms)
+            }
+            EnclaveError::Overloaded { retry_after_ms } => write!(
+                f,
+                "Too many scripts running, retry after {}ms",
+                retry_after_ms
+            ),
+            EnclaveError::ConflictingResult { timestamp_ms } => write!(
+                f,
+                "Conflicting result: a different result was already signed at {}",
+                timestamp_ms
+            ),
+            EnclaveError::Unregistered(e) => write!(f, "Enclave not registered on-chain: {}", e),
         }
     }
 }
 
 impl std::error::Error for EnclaveError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_problem_details() {
+        let response = EnclaveError::ScriptError {
+            line: Some(3),
+            message: "boom".to_string(),
+        }
+        .into_response();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/problem+json"
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["type"], "urn:nautilus:error:script_error");
+        assert_eq!(body["code"], "script_error");
+        assert_eq!(body["status"], 422);
+        assert_eq!(body["line"], 3);
+        assert_eq!(body["detail"], "Script error at line 3: boom");
+        assert!(body.get("request_id").is_none());
+
+        let response = EnclaveError::RateLimited {
+            retry_after_ms: 1500,
+        }
+        .into_response();
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+
+        let too_early = EnclaveError::UpdateTooEarly {
+            earliest_allowed_timestamp_ms: 1744038960000,
+            current_timestamp_ms: 1744038900000,
+        };
+        assert_eq!(too_early.status(), StatusCode::CONFLICT);
+
+        let response = EnclaveError::UpdateNotRequired {
+            deviation_bps: 10,
+            max_deviation_bps: 50,
+            next_heartbeat_timestamp_ms: 1744038960000,
+        }
+        .into_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/problem+json"
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "update_not_required");
+        assert_eq!(body["deviation_bps"], 10);
+    }
+}
@@ -0,0 +1,171 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Logs shipped to the host over vsock, selected by `[logs] forward` in
+//! nautilus.toml, for enclaves whose console is not readable (any enclave
+//! not started in debug mode).
+//!
+//! Every tracing event is also written as a JSON line to a bounded queue.
+//! A background task sends the queue to (host_cid, port) in batches of up to
+//! `batch_lines`, or whatever arrived within `flush_interval_ms`, and
+//! reconnects when the host goes away. Logging never waits on the host:
+//! when the queue is full lines are dropped, counted in
+//! `forwarded_log_lines_dropped_total`, and the next batch starts with a
+//! `{"dropped_lines":N}` line. Receive them on the host with e.g.
+//! `socat -u VSOCK-LISTEN:9100,fork OPEN:enclave.log,creat,append`.
+
+use crate::config::LogsConfig;
+use crate::metrics::FORWARDED_LOG_LINES_DROPPED;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tokio_vsock::{VsockAddr, VsockStream};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Lines dropped since the last batch was sent.
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Writer of the forwarded log lines, for `fmt::layer().with_writer`.
+#[derive(Clone)]
+pub struct VsockLogs {
+    sender: mpsc::Sender<Vec<u8>>,
+}
+
+/// Buffers one event and queues it when dropped.
+pub struct LineWriter {
+    sender: mpsc::Sender<Vec<u8>>,
+    buffer: Vec<u8>,
+}
+
+impl Write for LineWriter {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for LineWriter {
+    fn drop(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let line = std::mem::take(&mut self.buffer);
+        if self.sender.try_send(line).is_err() {
+            record_dropped(1);
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for VsockLogs {
+    type Writer = LineWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        LineWriter {
+            sender: self.sender.clone(),
+            buffer: Vec::new(),
+        }
+    }
+}
+
+fn record_dropped(lines: u64) {
+    DROPPED.fetch_add(lines, Ordering::Relaxed);
+    FORWARDED_LOG_LINES_DROPPED.inc_by(lines);
+}
+
+/// Start forwarding and return the writer feeding it. Must be called
+/// within the tokio runtime.
+pub fn spawn(config: &LogsConfig) -> VsockLogs {
+    let (sender, receiver) = mpsc::channel(config.buffer_lines.max(1));
+    tokio::spawn(forward(config.clone(), receiver));
+    VsockLogs { sender }
+}
+
+/// Next batch: the lines queued within the flush interval after the first
+/// one, at most `batch_lines`. None once every writer is gone.
+async fn next_batch(
+    receiver: &mut mpsc::Receiver<Vec<u8>>,
+    batch_lines: usize,
+    flush_interval: Duration,
+) -> Option<(Vec<u8>, u64)> {
+    let mut batch = receiver.recv().await?;
+    let mut lines = 1;
+    let deadline = tokio::time::sleep(flush_interval);
+    tokio::pin!(deadline);
+    while lines < batch_lines {
+        tokio::select! {
+            line = receiver.recv() => match line {
+                Some(line) => {
+                    batch.extend_from_slice(&line);
+                    lines += 1;
+                }
+                None => break,
+            },
+            _ = &mut deadline => break,
+        }
+    }
+    Some((batch, lines as u64))
+}
+
+async fn forward(config: LogsConfig, mut receiver: mpsc::Receiver<Vec<u8>>) {
+    let addr = VsockAddr::new(config.host_cid, config.port);
+    let flush_interval = Duration::from_millis(config.flush_interval_ms);
+    let mut stream: Option<VsockStream> = None;
+    while let Some((lines, count)) =
+        next_batch(&mut receiver, config.batch_lines, flush_interval).await
+    {
+        let mut batch = Vec::new();
+        let dropped = DROPPED.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            batch.extend_from_slice(format!("{{\"dropped_lines\":{}}}\n", dropped).as_bytes());
+        }
+        batch.extend_from_slice(&lines);
+
+        let sent = match stream.as_mut() {
+            Some(stream) => stream.write_all(&batch).await,
+            None => match VsockStream::connect(addr).await {
+                Ok(connected) => stream.insert(connected).write_all(&batch).await,
+                Err(e) => Err(e),
+            },
+        };
+        // Not logged through tracing, it would queue more lines for the host
+        if let Err(e) = sent {
+            if stream.take().is_some() {
+                eprintln!("Log forwarding to vsock {:?} failed: {}", addr, e);
+            }
+            // The earlier drops were already counted in the metric
+            DROPPED.fetch_add(dropped, Ordering::Relaxed);
+            record_dropped(count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_batches() {
+        let (sender, mut receiver) = mpsc::channel(2);
+        let logs = VsockLogs { sender };
+        for line in ["a\n", "b\n", "c\n"] {
+            let mut writer = logs.make_writer();
+            writer.write_all(line.as_bytes()).unwrap();
+        }
+        // The third line did not fit the queue
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 1);
+
+        let batch = next_batch(&mut receiver, 10, Duration::from_millis(10)).await;
+        assert_eq!(batch, Some((b"a\nb\n".to_vec(), 2)));
+        drop(logs);
+        assert_eq!(
+            next_batch(&mut receiver, 10, Duration::from_millis(10)).await,
+            None
+        );
+    }
+}
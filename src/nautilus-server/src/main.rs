@@ -1,53 +1,315 @@
 // Copyright (c), Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use anyhow::{Context, Result};
-use axum::{Router, routing::get, routing::post};
+use anyhow::{Context, Result, bail};
+use axum::extract::DefaultBodyLimit;
+use axum::http::HeaderValue;
+use axum::middleware::{self, from_fn_with_state};
+use axum::{Router, routing::get};
 use bech32::{Hrp, decode};
+use clap::Parser;
 use fastcrypto::ed25519::Ed25519PrivateKey;
+use fastcrypto::encoding::{Encoding, Hex};
 use fastcrypto::traits::ToFromBytes;
 use fastcrypto::{ed25519::Ed25519KeyPair, traits::KeyPair};
-use nautilus_server::AppState;
-use nautilus_server::app::{execute_code, process_data};
-use nautilus_server::common::{get_attestation, health_check};
+use nautilus_server::admin;
+use nautilus_server::app::{
+    health_check, init_feed_keys, init_script_runtime, register_feature_flags, serve_grpc,
+    spawn_event_watch, spawn_gas_station, spawn_key_rotation, spawn_memory_monitor,
+    spawn_registration_check, spawn_scheduler, spawn_upstream_warmup,
+};
+use nautilus_server::catch_panic;
+use nautilus_server::chain_reader::ChainReader;
+use nautilus_server::common::enclave_rng;
+use nautilus_server::config::{AppConfig, Listener, SuiBackend, SuiNetwork};
+use nautilus_server::graphql::GraphqlReader;
+use nautilus_server::log_forward;
+use nautilus_server::metrics::{get_metrics, track_requests};
+use nautilus_server::mount::{app_states, mount_apps};
+use nautilus_server::network;
+use nautilus_server::rate_limit::limit_clients;
+use nautilus_server::request_id::assign_request_id;
+use nautilus_server::sui_pool::{SuiPool, spawn_keepalive};
+use nautilus_server::timeout::enforce_timeout;
+use nautilus_server::{APPS, AppState, EnclaveError};
+use std::net::SocketAddr;
 use std::sync::Arc;
-use sui_rpc::client::Client;
+use tower_http::compression::CompressionLayer;
+use tower_http::compression::predicate::{
+    DefaultPredicate, NotForContentType, Predicate, SizeAbove,
+};
 use tower_http::cors::{Any, CorsLayer};
-use tracing::info;
+use tracing::{error, info, warn};
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Nautilus enclave server.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Cli {
+    /// Config file, defaults to $NAUTILUS_CONFIG or nautilus.toml.
+    #[arg(long)]
+    config: Option<String>,
+    /// Port to listen on, replaces the port of server.bind_address.
+    #[arg(long)]
+    port: Option<u16>,
+    /// Sui network to read from, replaces sui.network, sui.fullnode_url,
+    /// sui.fallback_urls and sui.chain_id.
+    #[arg(long, value_enum)]
+    network: Option<SuiNetwork>,
+    /// Chain id the fullnode must report, replaces sui.chain_id. Required
+    /// with --network devnet, localnet or custom.
+    #[arg(long)]
+    chain_id: Option<String>,
+    /// Log filter, a level such as debug or a directive such as nautilus_server=debug.
+    #[arg(long, env = "RUST_LOG", default_value = "info")]
+    log_level: String,
+    /// App to serve. Apps are compiled in by cargo feature, so this only
+    /// checks the binary serves the expected one.
+    #[arg(long)]
+    app: Option<String>,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let eph_kp = Ed25519KeyPair::generate(&mut rand::thread_rng());
+    let cli = Cli::parse();
+
+    // nautilus.toml with NAUTILUS_* environment and command line overrides, see config.rs
+    let mut config = AppConfig::load(cli.config.as_deref())?;
+    if let Some(port) = cli.port {
+        config.set_port(port)?;
+    }
+    if let Some(network) = cli.network {
+        config.sui.network = network;
+        config.sui.fullnode_url = None;
+        config.sui.chain_id = None;
+        config.sui.fallback_urls.clear();
+    }
+    if let Some(chain_id) = cli.chain_id {
+        config.sui.chain_id = Some(chain_id);
+    }
+
+    // Also shipped to the host over vsock when [logs] forward is set
+    let forwarded = config.logs.forward.then(|| {
+        tracing_subscriber::fmt::layer()
+            .json()
+            .with_writer(log_forward::spawn(&config.logs))
+    });
+    tracing_subscriber::registry()
+        .with(EnvFilter::try_new(&cli.log_level).context("Invalid --log-level")?)
+        .with(tracing_subscriber::fmt::layer())
+        .with(forwarded)
+        .init();
+    if let Some(app) = cli.app.as_deref().filter(|app| !APPS.contains(app)) {
+        bail!(
+            "App {} is not compiled into this binary, it serves {:?}",
+            app,
+            APPS
+        );
+    }
+
+    // Seeded from NSM entropy mixed with the OS RNG, see common::enclave_rng
+    let eph_kp = Ed25519KeyPair::generate(&mut enclave_rng());
 
-    // Use archive node for better support of historical data queries
-    // If you need real-time data, you can switch back to TESTNET_FULLNODE
-    let sui_client = Client::new(Client::TESTNET_FULLNODE).unwrap();
+    info!("Loaded config, hash {}", Hex::encode(config.hash()));
 
-    let state = Arc::new(AppState { eph_kp, sui_client });
+    let sui = Arc::new(SuiPool::new(&config.sui)?);
+    // Refuse to start on a fullnode of another network than configured
+    let chain: Arc<dyn ChainReader> = match config.sui.backend {
+        SuiBackend::Grpc => {
+            network::verify(&sui, &config.sui).await?;
+            sui.clone()
+        }
+        SuiBackend::Graphql => {
+            // Fullnodes are only needed to submit in push mode, but must not
+            // serve another network
+            match network::verify(&sui, &config.sui).await {
+                Err(EnclaveError::RpcError(e)) => {
+                    warn!(
+                        "No fullnode reachable, transactions cannot be submitted: {}",
+                        e
+                    )
+                }
+                outcome => {
+                    outcome?;
+                }
+            }
+            let graphql = GraphqlReader::new(&config.sui)?;
+            network::verify_graphql(&graphql, &config.sui).await?;
+            Arc::new(graphql)
+        }
+    };
+
+    // TLS key and attested certificate of the HTTP and gRPC servers, see ratls
+    #[cfg(feature = "ra-tls")]
+    let tls_identity = Arc::new(nautilus_server::ratls::AttestedIdentity::generate(
+        eph_kp.public().as_bytes(),
+    )?);
+
+    let base = AppState {
+        eph_kp,
+        sui,
+        chain,
+        config,
+    };
+    // Every app gets a key pair and configuration of its own, see mount.rs.
+    // The background tasks below run the oracle.
+    let states = app_states(&base);
+    let state = states
+        .get("coeus-oracle")
+        .cloned()
+        .context("coeus-oracle is not compiled into this binary")?;
 
     // Spawn host-only init server if seal-example feature is enabled
     #[cfg(feature = "seal-example")]
     {
-        nautilus_server::app::spawn_host_init_server(state.clone()).await?;
+        nautilus_server::app::spawn_host_init_server(states["seal-example"].clone()).await?;
+    }
+
+    // Check the per-feed signing keys configured in coeus_config.yaml, they are
+    // derived once the host generates or unseals their master seed
+    init_feed_keys()?;
+    // Runtime the Sui host functions of scripts (sui_dry_run, sui_object) run on
+    init_script_runtime(&state);
+
+    // Flags switchable at runtime through /admin/flags
+    register_feature_flags();
+
+    // Start the background feed scheduler (no-op unless enabled in coeus_config.yaml)
+    spawn_scheduler(state.clone())?;
+
+    // Merge and split the push-mode gas coins (no-op unless push is enabled)
+    spawn_gas_station(state.clone());
+
+    // Run feeds requested on-chain (no-op unless enabled in coeus_config.yaml)
+    spawn_event_watch(state.clone())?;
+
+    // Start rotating the signing key (no-op unless enabled in coeus_config.yaml)
+    spawn_key_rotation(state.clone())?;
+
+    // Check the on-chain registration (no-op unless enabled in nautilus.toml)
+    spawn_registration_check(state.clone())?;
+
+    // Keep fullnode connections up while idle, see [sui.keepalive] in nautilus.toml
+    spawn_keepalive(state.clone());
+
+    // Keep connections to script upstreams open (no-op unless listed in coeus_config.yaml)
+    spawn_upstream_warmup();
+
+    // Account cache memory and shed entries near the ceiling, see memory in coeus_config.yaml
+    spawn_memory_monitor();
+
+    // gRPC service on its own port, see [grpc] in nautilus.toml
+    if state.config.grpc.enabled {
+        let grpc_state = state.clone();
+        let bind_address = state.config.grpc.bind_address.clone();
+        #[cfg(feature = "ra-tls")]
+        let grpc_identity = tls_identity.clone();
+        tokio::spawn(async move {
+            #[cfg(feature = "ra-tls")]
+            let served =
+                nautilus_server::app::serve_grpc_ra_tls(grpc_state, bind_address, &grpc_identity)
+                    .await;
+            #[cfg(not(feature = "ra-tls"))]
+            let served = serve_grpc(grpc_state, bind_address).await;
+            if let Err(e) = served {
+                error!("{}", e);
+            }
+        });
     }
 
-    // Define your own restricted CORS policy here if needed.
-    let cors = CorsLayer::new().allow_methods(Any).allow_headers(Any);
+    // Origins are restricted through [cors] in nautilus.toml
+    let mut cors = CorsLayer::new().allow_methods(Any).allow_headers(Any);
+    if !state.config.cors.allowed_origins.is_empty() {
+        let origins = state
+            .config
+            .cors
+            .allowed_origins
+            .iter()
+            .map(|origin| origin.parse::<HeaderValue>())
+            .collect::<Result<Vec<_>, _>>()
+            .context("Invalid CORS origin")?;
+        cors = cors.allow_origin(origins);
+    }
+    let body_limit = DefaultBodyLimit::max(state.config.limits.max_body_bytes);
+    let server_config = state.config.server.clone();
 
     let app = Router::new()
         .route("/", get(ping))
-        .route("/get_attestation", get(get_attestation))
-        .route("/process_data", post(process_data))
-        .route("/execute_code", post(execute_code))
         .route("/health_check", get(health_check))
-        .with_state(state)
-        .layer(cors);
+        .route("/metrics", get(get_metrics))
+        .nest("/admin", admin::router(state.clone()));
+
+    let rate_limit = from_fn_with_state(state.clone(), limit_clients);
+    let request_id = from_fn_with_state(state.clone(), assign_request_id);
+    // Per route, see [timeouts] in nautilus.toml
+    let timeout = from_fn_with_state(state.clone(), enforce_timeout);
+    // Large batch and history responses are compressed for clients sending
+    // Accept-Encoding, see [compression]. Event and NDJSON streams are left
+    // alone, the encoder would buffer their lines.
+    let compression_config = &state.config.compression;
+    let compression = CompressionLayer::new()
+        .gzip(compression_config.enabled && compression_config.gzip)
+        .br(compression_config.enabled && compression_config.br)
+        .compress_when(
+            DefaultPredicate::new()
+                .and(NotForContentType::new("application/x-ndjson"))
+                .and(SizeAbove::new(compression_config.min_bytes)),
+        );
+    // Every app under /apps/{name}, and the root app at the root, see mount.rs
+    let app = mount_apps(app.with_state(state.clone()), &server_config, &states)?;
+    let app = app
+        .layer(rate_limit)
+        .layer(timeout)
+        // Inside track_requests and the request id, so panics are recorded as
+        // 500s that carry the id
+        .layer(catch_panic::layer())
+        .layer(middleware::from_fn(track_requests))
+        .layer(request_id)
+        .layer(cors)
+        .layer(compression)
+        .layer(body_limit);
+
+    if server_config.listener == Listener::Vsock {
+        // RA-TLS is served by axum-server, which only accepts TCP connections
+        #[cfg(feature = "ra-tls")]
+        bail!("The ra-tls feature needs the tcp listener");
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
-    info!("listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app.into_make_service())
+        #[cfg(not(feature = "ra-tls"))]
+        return nautilus_server::vsock::serve(
+            server_config.vsock_cid,
+            server_config.vsock_port,
+            app,
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("Server error: {}", e));
+    }
+    let bind_address = server_config.bind_address;
+
+    // Terminate TLS inside the enclave with an attested certificate, see ratls
+    #[cfg(feature = "ra-tls")]
+    {
+        let tls_config = tls_identity.rustls_config().await?;
+        let addr: SocketAddr = bind_address.parse().context("Invalid bind address")?;
+        info!("listening on {} (RA-TLS)", addr);
+        axum_server::bind_rustls(addr, tls_config)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+            .await
+            .map_err(|e| anyhow::anyhow!("Server error: {}", e))
+    }
+
+    #[cfg(not(feature = "ra-tls"))]
+    {
+        let listener = tokio::net::TcpListener::bind(&bind_address).await?;
+        info!("listening on {}", listener.local_addr().unwrap());
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
         .await
         .map_err(|e| anyhow::anyhow!("Server error: {}", e))
+    }
 }
 
 async fn ping() -> &'static str {
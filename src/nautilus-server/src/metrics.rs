@@ -0,0 +1,247 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Prometheus metrics, served in the text format by GET /metrics.
+
+use axum::extract::{MatchedPath, Request};
+use axum::http::Method;
+use axum::http::header::CONTENT_TYPE;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use std::time::Instant;
+
+lazy_static::lazy_static! {
+    pub static ref REGISTRY: Registry = Registry::new();
+
+    /// HTTP requests served, by route, method and status.
+    pub static ref HTTP_REQUESTS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("http_requests_total", "HTTP requests served"),
+        &["route", "method", "status"],
+    ));
+
+    /// HTTP request latency, by route and method.
+    pub static ref HTTP_REQUEST_SECONDS: HistogramVec = register(HistogramVec::new(
+        HistogramOpts::new("http_request_duration_seconds", "HTTP request latency"),
+        &["route", "method"],
+    ));
+
+    /// Messages signed by the enclave key, by intent scope.
+    pub static ref SIGNATURES: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("signatures_total", "Messages signed"),
+        &["intent"],
+    ));
+
+    /// Feed script execution time, by script extension and outcome.
+    pub static ref SCRIPT_EXECUTION_SECONDS: HistogramVec = register(HistogramVec::new(
+        HistogramOpts::new(
+            "script_execution_duration_seconds",
+            "Feed script execution time, including its HTTP calls",
+        ),
+        &["extension", "outcome"],
+    ));
+
+    /// Script executions refused because every slot was busy and the queue full.
+    pub static ref SCRIPT_EXECUTIONS_SHED: IntCounter = register(IntCounter::new(
+        "script_executions_shed_total",
+        "Script executions refused under load",
+    ));
+
+    /// HTTP calls made by feed scripts, by outcome (ok or error).
+    pub static ref HOST_HTTP_CALLS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("host_http_calls_total", "HTTP calls made by feed scripts"),
+        &["outcome"],
+    ));
+
+    /// Move calls simulated by feed scripts, by outcome.
+    pub static ref HOST_DRY_RUNS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("host_dry_runs_total", "Move calls simulated by feed scripts"),
+        &["outcome"],
+    ));
+
+    /// EVM JSON-RPC calls made by feed scripts, by method and outcome.
+    pub static ref HOST_EVM_CALLS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("host_evm_calls_total", "EVM JSON-RPC calls made by feed scripts"),
+        &["method", "outcome"],
+    ));
+
+    /// Sui RPC latency, by method.
+    pub static ref SUI_RPC_SECONDS: HistogramVec = register(HistogramVec::new(
+        HistogramOpts::new("sui_rpc_duration_seconds", "Sui RPC latency"),
+        &["method"],
+    ));
+
+    /// Sui RPC calls retried after a transient error, by method.
+    pub static ref SUI_RPC_RETRIES: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("sui_rpc_retries_total", "Sui RPC calls retried"),
+        &["method"],
+    ));
+
+    /// Whether each fullnode is used (1) or skipped after errors (0), by URL.
+    pub static ref SUI_FULLNODE_HEALTHY: IntGaugeVec = register(IntGaugeVec::new(
+        Opts::new("sui_fullnode_healthy", "Fullnode health"),
+        &["url"],
+    ));
+
+    /// Unix time of the latest answer of each fullnode, by URL.
+    pub static ref SUI_FULLNODE_LAST_ANSWER: IntGaugeVec = register(IntGaugeVec::new(
+        Opts::new(
+            "sui_fullnode_last_answer_timestamp_seconds",
+            "Latest fullnode answer"
+        ),
+        &["url"],
+    ));
+
+    /// Keepalive pings of idle fullnodes, by URL and outcome (ok or error).
+    pub static ref SUI_FULLNODE_PINGS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("sui_fullnode_pings_total", "Fullnode keepalive pings"),
+        &["url", "outcome"],
+    ));
+
+    /// SUI balance in MIST of the address paying push-mode gas, while push
+    /// mode is enabled.
+    pub static ref GAS_BALANCE: IntGauge = register(IntGauge::new(
+        "enclave_gas_balance_mist",
+        "Gas balance of the push-mode gas owner",
+    ));
+
+    /// SUI coins of the address paying push-mode gas, while push mode is
+    /// enabled.
+    pub static ref GAS_COINS: IntGauge = register(IntGauge::new(
+        "enclave_gas_coins",
+        "Gas coins of the push-mode gas owner",
+    ));
+
+    /// Panics caught instead of taking down the task, by source (http or
+    /// scheduler).
+    pub static ref PANICS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("panics_total", "Panics caught"),
+        &["source"],
+    ));
+
+    /// Log lines not forwarded to the host, see log_forward.
+    pub static ref FORWARDED_LOG_LINES_DROPPED: IntCounter = register(IntCounter::new(
+        "forwarded_log_lines_dropped_total",
+        "Log lines dropped instead of being forwarded to the host",
+    ));
+
+    /// Cache lookups, by cache and outcome (hit or miss).
+    pub static ref CACHE_LOOKUPS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("cache_lookups_total", "Cache lookups"),
+        &["cache", "outcome"],
+    ));
+
+    /// Approximate memory held by caches, result history and in-flight
+    /// executions, by component. See memory.rs of the coeus oracle.
+    pub static ref MEMORY_ACCOUNTED: IntGaugeVec = register(IntGaugeVec::new(
+        Opts::new("memory_accounted_bytes", "Approximate memory held, by component"),
+        &["component"],
+    ));
+
+    /// Highest total of memory_accounted_bytes since boot.
+    pub static ref MEMORY_ACCOUNTED_HIGH_WATER: IntGauge = register(IntGauge::new(
+        "memory_accounted_high_water_bytes",
+        "Highest approximate memory held",
+    ));
+
+    /// Resident set size of the process, where /proc is readable.
+    pub static ref MEMORY_RESIDENT: IntGauge = register(IntGauge::new(
+        "memory_resident_bytes",
+        "Resident set size",
+    ));
+
+    /// Peak resident set size of the process, where /proc is readable.
+    pub static ref MEMORY_RESIDENT_HIGH_WATER: IntGauge = register(IntGauge::new(
+        "memory_resident_high_water_bytes",
+        "Peak resident set size",
+    ));
+
+    /// Cache entries dropped to stay under the memory ceiling, by cache.
+    pub static ref CACHE_ENTRIES_SHED: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("cache_entries_shed_total", "Cache entries shed under memory pressure"),
+        &["cache"],
+    ));
+}
+
+fn register<M: prometheus::core::Collector + Clone + 'static>(metric: prometheus::Result<M>) -> M {
+    let metric = metric.expect("valid metric definition");
+    REGISTRY
+        .register(Box::new(metric.clone()))
+        .expect("metric registered once");
+    metric
+}
+
+/// Count a cache lookup.
+pub fn record_cache_lookup(cache: &str, hit: bool) {
+    CACHE_LOOKUPS
+        .with_label_values(&[cache, if hit { "hit" } else { "miss" }])
+        .inc();
+}
+
+/// Methods recorded under their own label.
+const LABELLED_METHODS: [Method; 7] = [
+    Method::GET,
+    Method::POST,
+    Method::PUT,
+    Method::DELETE,
+    Method::HEAD,
+    Method::OPTIONS,
+    Method::PATCH,
+];
+
+/// Middleware recording the count and latency of every request. Requests
+/// that match no route are recorded under "unmatched" and non-standard
+/// methods under "OTHER" to bound the labels.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = if LABELLED_METHODS.contains(request.method()) {
+        request.method().to_string()
+    } else {
+        "OTHER".to_string()
+    };
+    let started = Instant::now();
+    let response = next.run(request).await;
+    HTTP_REQUEST_SECONDS
+        .with_label_values(&[&route, &method])
+        .observe(started.elapsed().as_secs_f64());
+    HTTP_REQUESTS
+        .with_label_values(&[&route, &method, response.status().as_str()])
+        .inc();
+    response
+}
+
+/// Endpoint serving all metrics in the Prometheus text format.
+pub async fn get_metrics() -> Response {
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(e) = encoder.encode(&REGISTRY.gather(), &mut buffer) {
+        return crate::EnclaveError::GenericError(format!("Failed to encode metrics: {}", e))
+            .into_response();
+    }
+    ([(CONTENT_TYPE, encoder.format_type().to_string())], buffer).into_response()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_gather() {
+        record_cache_lookup("result", true);
+        SIGNATURES.with_label_values(&["ProcessData"]).inc();
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&REGISTRY.gather(), &mut buffer)
+            .unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        assert!(text.contains("cache_lookups_total{cache=\"result\",outcome=\"hit\"}"));
+        assert!(text.contains("signatures_total{intent=\"ProcessData\"}"));
+    }
+}
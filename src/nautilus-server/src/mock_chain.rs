@@ -0,0 +1,279 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! In-memory ChainReader, so feeds can be run without a network by tests of
+//! the process_data pipeline. Compiled only for tests. Objects are loaded from
+//! a JSON fixture of Move objects:
+//!
+//! ```json
+//! {
+//!   "checkpoint": 1000,
+//!   "objects": [
+//!     {"id": "0x..", "version": 7, "type": "0x..::feed::OracleFeed",
+//!      "owner": "shared", "contents": "<hex BCS of the Move struct>"}
+//!   ]
+//! }
+//! ```
+//!
+//! `owner` is `shared`, `immutable` or an owner address. Transactions and
+//! checkpoint events can be added in code. Anything missing is NotFound, as
+//! a fullnode would answer.
+
+use crate::EnclaveError;
+use crate::chain_reader::{ChainReader, ObjectRead, ReadFuture, TransactionRead};
+use fastcrypto::encoding::{Encoding, Hex};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, MutexGuard};
+use sui_sdk_types::{Address, Digest, MoveStruct, Object, ObjectData, Owner, StructTag};
+use tonic::Status;
+
+/// A Move object of a fixture.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FixtureObject {
+    pub id: String,
+    pub version: u64,
+    #[serde(rename = "type")]
+    pub object_type: String,
+    pub owner: String,
+    /// Hex BCS of the struct, starting with its id.
+    pub contents: String,
+}
+
+/// Chain state loaded by MockChain::from_fixtures.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fixtures {
+    #[serde(default)]
+    pub checkpoint: u64,
+    #[serde(default)]
+    pub objects: Vec<FixtureObject>,
+}
+
+#[derive(Default)]
+struct Chain {
+    checkpoint: u64,
+    /// Versions of each object.
+    objects: HashMap<Address, BTreeMap<u64, ObjectRead>>,
+    transactions: HashMap<String, TransactionRead>,
+    /// Events of each checkpoint, with their type.
+    events: HashMap<u64, Vec<(StructTag, Vec<u8>)>>,
+}
+
+#[derive(Default)]
+pub struct MockChain {
+    chain: Mutex<Chain>,
+}
+
+fn fixture_error(id: &str, message: impl std::fmt::Display) -> EnclaveError {
+    EnclaveError::GenericError(format!("Invalid fixture object {}: {}", id, message))
+}
+
+fn parse_owner(owner: &str, version: u64) -> Result<Owner, String> {
+    match owner {
+        "shared" => Ok(Owner::Shared(version)),
+        "immutable" => Ok(Owner::Immutable),
+        address => Address::from_hex(address)
+            .map(Owner::Address)
+            .map_err(|e| format!("invalid owner {}: {}", address, e)),
+    }
+}
+
+impl MockChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Chain holding the objects of a JSON fixture.
+    pub fn from_fixtures(json: &str) -> Result<Self, EnclaveError> {
+        let fixtures: Fixtures = serde_json::from_str(json)
+            .map_err(|e| EnclaveError::GenericError(format!("Invalid fixtures: {}", e)))?;
+        let chain = Self::new();
+        chain.set_checkpoint(fixtures.checkpoint);
+        for fixture in fixtures.objects {
+            let id = Address::from_hex(&fixture.id).map_err(|e| fixture_error(&fixture.id, e))?;
+            let object_type: StructTag = fixture
+                .object_type
+                .parse()
+                .map_err(|e| fixture_error(&fixture.id, format!("{:?}", e)))?;
+            let owner = parse_owner(&fixture.owner, fixture.version)
+                .map_err(|e| fixture_error(&fixture.id, e))?;
+            let contents =
+                Hex::decode(&fixture.contents).map_err(|e| fixture_error(&fixture.id, e))?;
+            chain.insert_move_object(id, fixture.version, object_type, owner, contents)?;
+        }
+        Ok(chain)
+    }
+
+    fn chain(&self) -> MutexGuard<'_, Chain> {
+        self.chain.lock().expect("mock chain lock poisoned")
+    }
+
+    pub fn set_checkpoint(&self, checkpoint: u64) {
+        self.chain().checkpoint = checkpoint;
+    }
+
+    /// Add a version of an object.
+    pub fn insert_object(&self, object: &Object) -> Result<(), EnclaveError> {
+        let bcs = bcs::to_bytes(object)
+            .map_err(|e| EnclaveError::GenericError(format!("Failed to encode object: {}", e)))?;
+        let read = ObjectRead {
+            bcs,
+            version: object.version(),
+            previous_transaction: Some(object.previous_transaction().to_string()),
+            checkpoint: None,
+        };
+        self.chain()
+            .objects
+            .entry(object.object_id())
+            .or_default()
+            .insert(object.version(), read);
+        Ok(())
+    }
+
+    /// Add a version of a Move object, `contents` starting with `id`.
+    pub fn insert_move_object(
+        &self,
+        id: Address,
+        version: u64,
+        object_type: StructTag,
+        owner: Owner,
+        contents: Vec<u8>,
+    ) -> Result<(), EnclaveError> {
+        if contents.get(..Address::LENGTH) != Some(id.as_ref()) {
+            return Err(fixture_error(
+                &id.to_string(),
+                "contents do not start with its id",
+            ));
+        }
+        let move_struct = MoveStruct::new(object_type, true, version, contents)
+            .ok_or_else(|| fixture_error(&id.to_string(), "invalid Move struct"))?;
+        let object = Object::new(ObjectData::Struct(move_struct), owner, Digest::ZERO, 0);
+        self.insert_object(&object)
+    }
+
+    pub fn insert_transaction(&self, digest: &str, transaction: TransactionRead) {
+        self.chain()
+            .transactions
+            .insert(digest.to_string(), transaction);
+    }
+
+    /// Add an event emitted in `checkpoint`.
+    pub fn insert_event(&self, checkpoint: u64, event_type: StructTag, contents: Vec<u8>) {
+        self.chain()
+            .events
+            .entry(checkpoint)
+            .or_default()
+            .push((event_type, contents));
+    }
+
+    fn object(&self, id: &Address, version: Option<u64>) -> Result<ObjectRead, Status> {
+        let chain = self.chain();
+        let versions = chain.objects.get(id);
+        let read = match version {
+            Some(version) => versions.and_then(|versions| versions.get(&version)),
+            None => versions.and_then(|versions| versions.values().next_back()),
+        };
+        let mut read = read
+            .cloned()
+            .ok_or_else(|| Status::not_found(format!("Object {} not found", id)))?;
+        read.checkpoint = Some(chain.checkpoint);
+        Ok(read)
+    }
+}
+
+impl ChainReader for MockChain {
+    fn get_object(&self, id: Address, version: Option<u64>) -> ReadFuture<'_, ObjectRead> {
+        Box::pin(async move { self.object(&id, version) })
+    }
+
+    fn get_objects<'a>(&'a self, ids: &'a [Address]) -> ReadFuture<'a, Vec<Vec<u8>>> {
+        Box::pin(async move {
+            ids.iter()
+                .map(|id| self.object(id, None).map(|read| read.bcs))
+                .collect()
+        })
+    }
+
+    fn get_transaction<'a>(&'a self, digest: &'a str) -> ReadFuture<'a, TransactionRead> {
+        Box::pin(async move {
+            self.chain()
+                .transactions
+                .get(digest)
+                .cloned()
+                .ok_or_else(|| Status::not_found(format!("Transaction {} not found", digest)))
+        })
+    }
+
+    fn latest_checkpoint(&self) -> ReadFuture<'_, u64> {
+        Box::pin(async move { Ok(self.chain().checkpoint) })
+    }
+
+    fn checkpoint_events<'a>(
+        &'a self,
+        sequence_number: u64,
+        event_type: &'a StructTag,
+    ) -> ReadFuture<'a, Vec<Vec<u8>>> {
+        Box::pin(async move {
+            let chain = self.chain();
+            if sequence_number > chain.checkpoint {
+                return Err(Status::not_found(format!(
+                    "Checkpoint {} not found",
+                    sequence_number
+                )));
+            }
+            Ok(chain
+                .events
+                .get(&sequence_number)
+                .into_iter()
+                .flatten()
+                .filter(|(emitted, _)| emitted == event_type)
+                .map(|(_, contents)| contents.clone())
+                .collect())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_chain() {
+        let id = Address::from_hex("0x7").unwrap();
+        let object_type: StructTag = "0x2::coin::Coin<0x2::sui::SUI>".parse().unwrap();
+        let chain = MockChain::new();
+        chain.set_checkpoint(10);
+        for (version, balance) in [(1u64, 5u64), (2, 6)] {
+            let contents = bcs::to_bytes(&(id, balance)).unwrap();
+            chain
+                .insert_move_object(id, version, object_type.clone(), Owner::Immutable, contents)
+                .unwrap();
+        }
+
+        let latest = chain.get_object(id, None).await.unwrap();
+        assert_eq!((latest.version, latest.checkpoint), (2, Some(10)));
+        assert_eq!(chain.get_object(id, Some(1)).await.unwrap().version, 1);
+        let missing = Address::from_hex("0x8").unwrap();
+        let error = chain.get_object(missing, None).await.unwrap_err();
+        assert_eq!(error.code(), tonic::Code::NotFound);
+        assert!(chain.get_objects(&[id, missing]).await.is_err());
+
+        chain.insert_event(10, object_type.clone(), vec![1]);
+        assert_eq!(
+            chain.checkpoint_events(10, &object_type).await.unwrap(),
+            vec![vec![1]]
+        );
+        assert!(chain.checkpoint_events(11, &object_type).await.is_err());
+
+        // Contents must start with the object id
+        let contents = bcs::to_bytes(&(missing, 1u64)).unwrap();
+        assert!(
+            chain
+                .insert_move_object(id, 3, object_type, Owner::Immutable, contents)
+                .is_err()
+        );
+        assert!(MockChain::from_fixtures(r#"{"objects": [{"id": "0x1"}]}"#).is_err());
+    }
+}
@@ -0,0 +1,257 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Several apps in one enclave image. Every app compiled in is mounted under
+//! `/apps/{name}` with its own router and state, and signs with its own
+//! APP_ID, so the signatures of one app are never accepted by the Move
+//! module of another. Each state has a key pair of its own and the app's
+//! `[apps.<name>]` section of nautilus.toml applied, so every app registers
+//! and is attested with its own key. `server.root_app` is also served at the
+//! root with the same state, the layout of single app deployments. GET /apps
+//! lists the mounted apps.
+
+use crate::common::enclave_rng;
+use crate::config::ServerConfig;
+use crate::{APPS, AppState, EnclaveError};
+use axum::{Json, Router};
+use fastcrypto::ed25519::Ed25519KeyPair;
+use fastcrypto::traits::KeyPair;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// An app compiled into this binary.
+pub struct AppMount {
+    /// Cargo feature of the app, also its path under /apps.
+    pub name: &'static str,
+    /// Signed into every intent message of the app.
+    pub app_id: &'static str,
+    /// Routes of the app, with its state applied.
+    pub router: fn(Arc<AppState>) -> Router,
+}
+
+/// Apps compiled into this binary, in the order of APPS.
+pub const APP_MOUNTS: &[AppMount] = &[
+    #[cfg(feature = "twitter-example")]
+    AppMount {
+        name: "twitter-example",
+        app_id: crate::apps::twitter_example::APP_ID,
+        router: crate::apps::twitter_example::router,
+    },
+    #[cfg(feature = "weather-example")]
+    AppMount {
+        name: "weather-example",
+        app_id: crate::apps::weather_example::APP_ID,
+        router: crate::apps::weather_example::router,
+    },
+    #[cfg(feature = "seal-example")]
+    AppMount {
+        name: "seal-example",
+        app_id: crate::apps::seal_example::APP_ID,
+        router: crate::apps::seal_example::router,
+    },
+    #[cfg(feature = "coeus-oracle")]
+    AppMount {
+        name: "coeus-oracle",
+        app_id: crate::apps::coeus_oracle::APP_ID,
+        router: crate::apps::coeus_oracle::router,
+    },
+];
+
+/// Entry of GET /apps.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MountedApp {
+    pub name: String,
+    pub app_id: String,
+    pub path: String,
+    /// Also served at the root.
+    pub root: bool,
+}
+
+/// App served at the root: `server.root_app`, or the only app compiled in.
+pub fn root_app(config: &ServerConfig) -> Result<Option<&'static AppMount>, EnclaveError> {
+    match &config.root_app {
+        Some(name) => APP_MOUNTS
+            .iter()
+            .find(|app| app.name == name)
+            .map(Some)
+            .ok_or_else(|| {
+                EnclaveError::GenericError(format!(
+                    "root_app {} is not compiled into this binary, it serves {:?}",
+                    name, APPS
+                ))
+            }),
+        None if APP_MOUNTS.len() == 1 => Ok(APP_MOUNTS.first()),
+        None => Ok(None),
+    }
+}
+
+fn mounted_apps(mounts: &[AppMount], root: Option<&AppMount>) -> Vec<MountedApp> {
+    mounts
+        .iter()
+        .map(|app| MountedApp {
+            name: app.name.to_string(),
+            app_id: app.app_id.to_string(),
+            path: format!("/apps/{}", app.name),
+            root: root.is_some_and(|root| root.name == app.name),
+        })
+        .collect()
+}
+
+/// State of app `name`: a new key pair, and `base`'s configuration with the
+/// app's section applied. Fullnode connections are shared.
+pub fn app_state(base: &AppState, name: &str) -> AppState {
+    AppState {
+        eph_kp: Ed25519KeyPair::generate(&mut enclave_rng()),
+        sui: base.sui.clone(),
+        chain: base.chain.clone(),
+        config: base.config.for_app(name),
+    }
+}
+
+/// States of the apps compiled into this binary, by name.
+pub fn app_states(base: &AppState) -> BTreeMap<String, Arc<AppState>> {
+    states_of(APP_MOUNTS, base)
+}
+
+fn states_of(mounts: &[AppMount], base: &AppState) -> BTreeMap<String, Arc<AppState>> {
+    mounts
+        .iter()
+        .map(|app| (app.name.to_string(), Arc::new(app_state(base, app.name))))
+        .collect()
+}
+
+/// Mount every app under /apps/{name} with its state from `states`, the root
+/// app at the root, and GET /apps on `router`.
+pub fn mount_apps(
+    router: Router,
+    server: &ServerConfig,
+    states: &BTreeMap<String, Arc<AppState>>,
+) -> Result<Router, EnclaveError> {
+    let root = root_app(server)?;
+    mount(router, APP_MOUNTS, root, states)
+}
+
+fn mount(
+    router: Router,
+    mounts: &[AppMount],
+    root: Option<&AppMount>,
+    states: &BTreeMap<String, Arc<AppState>>,
+) -> Result<Router, EnclaveError> {
+    let state_of = |app: &AppMount| {
+        states
+            .get(app.name)
+            .cloned()
+            .ok_or_else(|| EnclaveError::GenericError(format!("No state for app {}", app.name)))
+    };
+    let apps = mounted_apps(mounts, root);
+    let mut router = router.route("/apps", axum::routing::get(|| async { Json(apps) }));
+    for app in mounts {
+        router = router.nest(&format!("/apps/{}", app.name), (app.router)(state_of(app)?));
+    }
+    if let Some(root) = root {
+        router = router.merge((root.router)(state_of(root)?));
+    }
+    Ok(router)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_root_app() {
+        let mut config = ServerConfig::default();
+        let root = root_app(&config).unwrap();
+        assert_eq!(root.is_some(), APPS.len() == 1);
+
+        config.root_app = Some(APPS[0].to_string());
+        let root = root_app(&config).unwrap();
+        let apps = mounted_apps(APP_MOUNTS, root);
+        assert_eq!(apps.len(), APPS.len());
+        assert_eq!(apps[0].path, format!("/apps/{}", APPS[0]));
+        assert!(apps[0].root);
+
+        config.root_app = Some("unknown".to_string());
+        assert!(root_app(&config).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_app_states() {
+        use crate::config::{AppConfig, AppSection, RegistrationConfig, SuiConfig};
+        use crate::mock_chain::MockChain;
+        use crate::sui_pool::SuiPool;
+        use axum::extract::State;
+        use axum::routing::get;
+        use fastcrypto::encoding::{Encoding, Hex};
+
+        fn key_router(state: Arc<AppState>) -> Router {
+            Router::new()
+                .route(
+                    "/key",
+                    get(|State(state): State<Arc<AppState>>| async move {
+                        Hex::encode(state.eph_kp.public().as_bytes())
+                    }),
+                )
+                .with_state(state)
+        }
+        static MOUNTS: &[AppMount] = &[
+            AppMount {
+                name: "first",
+                app_id: "first",
+                router: key_router,
+            },
+            AppMount {
+                name: "second",
+                app_id: "second",
+                router: key_router,
+            },
+        ];
+
+        let mut config = AppConfig::default();
+        config.apps.insert(
+            "second".to_string(),
+            AppSection {
+                registration: Some(RegistrationConfig {
+                    enclave_object_id: Some("0x2".to_string()),
+                    ..Default::default()
+                }),
+            },
+        );
+        let base = AppState {
+            eph_kp: Ed25519KeyPair::generate(&mut rand::thread_rng()),
+            sui: Arc::new(SuiPool::new(&SuiConfig::default()).unwrap()),
+            chain: Arc::new(MockChain::new()),
+            config,
+        };
+        let states = states_of(MOUNTS, &base);
+        assert_eq!(states["first"].config.registration.enclave_object_id, None);
+        assert_eq!(
+            states["second"].config.registration.enclave_object_id,
+            Some("0x2".to_string())
+        );
+
+        // Each mounted app, and the root app, reports the key of its own state
+        let router = mount(Router::new(), MOUNTS, MOUNTS.first(), &states).unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        let key = |path: &'static str| async move {
+            reqwest::get(format!("http://{}{}", addr, path))
+                .await
+                .unwrap()
+                .text()
+                .await
+                .unwrap()
+        };
+        let first = key("/apps/first/key").await;
+        let second = key("/apps/second/key").await;
+        assert_ne!(first, second);
+        assert_ne!(first, Hex::encode(base.eph_kp.public().as_bytes()));
+        assert_eq!(
+            first,
+            Hex::encode(states["first"].eph_kp.public().as_bytes())
+        );
+        assert_eq!(key("/key").await, first);
+    }
+}
@@ -0,0 +1,185 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Startup check of the Sui network, see `[sui]` in nautilus.toml. The chain
+//! id the fullnode reports must match the configured network, so a mainnet
+//! enclave pointed at a testnet fullnode (or the reverse) refuses to start
+//! instead of signing results read from the wrong feed objects. The chain id
+//! is known for mainnet and testnet and must be configured for the others,
+//! which are reset from time to time. Fallback fullnodes must report the same
+//! chain id, those that cannot be reached at startup are not used until
+//! restart. With the graphql backend, the GraphQL service must report the
+//! expected chain id too.
+
+use crate::EnclaveError;
+use crate::config::{SuiConfig, SuiNetwork};
+use crate::graphql::GraphqlReader;
+use crate::metrics;
+use crate::sui_pool::SuiPool;
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use sui_rpc::client::Client;
+use sui_rpc::proto::sui::rpc::v2::{GetServiceInfoRequest, GetServiceInfoResponse};
+use tracing::{info, warn};
+
+/// Attempts to reach the fullnode before giving up, the host forwarders may
+/// still be starting.
+const ATTEMPTS: u32 = 5;
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+lazy_static::lazy_static! {
+    static ref ACTIVE: RwLock<Option<ActiveNetwork>> = RwLock::new(None);
+}
+
+/// Network the enclave reads from, as reported by /version.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActiveNetwork {
+    pub network: SuiNetwork,
+    /// Fullnodes in use, in order of preference.
+    pub fullnode_urls: Vec<String>,
+    /// Chain id reported by the fullnode.
+    pub chain_id: String,
+    /// Chain name reported by the fullnode, e.g. mainnet.
+    pub chain: Option<String>,
+}
+
+/// Network verified at startup, None before.
+pub fn active() -> Option<ActiveNetwork> {
+    ACTIVE.read().expect("network lock poisoned").clone()
+}
+
+fn check_chain_id(expected: &str, reported: &str) -> Result<(), EnclaveError> {
+    if expected != reported {
+        return Err(EnclaveError::GenericError(format!(
+            "The fullnode serves chain {}, expected {}. Check sui.network and sui.fullnode_url",
+            reported, expected
+        )));
+    }
+    Ok(())
+}
+
+async fn service_info(client: &Client) -> Result<GetServiceInfoResponse, EnclaveError> {
+    let mut client = client.clone();
+    let mut attempt = 1;
+    loop {
+        let started = Instant::now();
+        let outcome = client
+            .ledger_client()
+            .get_service_info(GetServiceInfoRequest::default())
+            .await;
+        metrics::SUI_RPC_SECONDS
+            .with_label_values(&["get_service_info"])
+            .observe(started.elapsed().as_secs_f64());
+        match outcome {
+            Ok(response) => return Ok(response.into_inner()),
+            Err(status) if attempt < ATTEMPTS => {
+                warn!("Fullnode not reachable (attempt {}): {}", attempt, status);
+                attempt += 1;
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+            Err(status) => {
+                return Err(EnclaveError::RpcError(format!(
+                    "get_service_info failed: {}",
+                    status
+                )));
+            }
+        }
+    }
+}
+
+/// Ask each fullnode for its chain id and check it against the config.
+/// Fullnodes that cannot be reached are disabled, startup fails when none can.
+pub async fn verify(pool: &SuiPool, config: &SuiConfig) -> Result<ActiveNetwork, EnclaveError> {
+    let expected = config.expected_chain_id()?;
+    let mut first: Option<GetServiceInfoResponse> = None;
+    let mut unreachable = None;
+    for (url, client) in pool.endpoints() {
+        let response = match service_info(client).await {
+            Ok(response) => {
+                pool.answered(url);
+                response
+            }
+            Err(e) => {
+                warn!(
+                    "Fullnode {} not used, its chain id could not be checked: {}",
+                    url, e
+                );
+                pool.disable(url);
+                unreachable = Some(e);
+                continue;
+            }
+        };
+        let chain_id = response.chain_id.as_deref().ok_or_else(|| {
+            EnclaveError::RpcError(format!("get_service_info of {} returned no chain id", url))
+        })?;
+        check_chain_id(expected, chain_id)
+            .map_err(|e| EnclaveError::GenericError(format!("Fullnode {}: {}", url, e)))?;
+        first.get_or_insert(response);
+    }
+    let Some(response) = first else {
+        return Err(unreachable.unwrap_or_else(|| {
+            EnclaveError::GenericError("No Sui fullnode configured".to_string())
+        }));
+    };
+    let chain_id = response.chain_id.unwrap_or_default();
+    activate(config, pool.enabled_urls(), chain_id, response.chain)
+}
+
+/// Check the chain id of the GraphQL service reads go to against the config.
+pub async fn verify_graphql(
+    reader: &GraphqlReader,
+    config: &SuiConfig,
+) -> Result<ActiveNetwork, EnclaveError> {
+    let expected = config.expected_chain_id()?;
+    let chain_id = reader.chain_id().await.map_err(|e| {
+        EnclaveError::RpcError(format!(
+            "chainIdentifier of {} failed: {}",
+            reader.url(),
+            e.message()
+        ))
+    })?;
+    check_chain_id(expected, &chain_id)
+        .map_err(|e| EnclaveError::GenericError(format!("GraphQL {}: {}", reader.url(), e)))?;
+    let mut urls = vec![reader.url().to_string()];
+    let mut chain = None;
+    if let Some(fullnodes) = active() {
+        urls.extend(fullnodes.fullnode_urls);
+        chain = fullnodes.chain;
+    }
+    activate(config, urls, chain_id, chain)
+}
+
+fn activate(
+    config: &SuiConfig,
+    fullnode_urls: Vec<String>,
+    chain_id: String,
+    chain: Option<String>,
+) -> Result<ActiveNetwork, EnclaveError> {
+    let active = ActiveNetwork {
+        network: config.network,
+        fullnode_urls,
+        chain_id,
+        chain,
+    };
+    info!(
+        "Reading from Sui {:?} ({}), chain id {}",
+        active.network,
+        active.fullnode_urls.join(", "),
+        active.chain_id
+    );
+    *ACTIVE.write().expect("network lock poisoned") = Some(active.clone());
+    Ok(active)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{MAINNET_CHAIN_ID, TESTNET_CHAIN_ID};
+
+    #[test]
+    fn test_check_chain_id() {
+        assert!(check_chain_id(MAINNET_CHAIN_ID, MAINNET_CHAIN_ID).is_ok());
+        assert!(check_chain_id(MAINNET_CHAIN_ID, TESTNET_CHAIN_ID).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! CPU profiles of the running enclave, `GET /admin/profile`, compiled in only
+//! with the `profiling` feature.
+//!
+//! A Nitro enclave has no perf, no ptrace and no shell, so the server samples
+//! itself: for `seconds` (up to `[profiling] max_seconds`) the stacks of all
+//! its threads are sampled `frequency` times a second, and the profile is
+//! returned as a flamegraph SVG or, with `format=pprof`, as an uncompressed
+//! pprof protobuf for `go tool pprof`. One profile is captured at a time, a
+//! request during a capture gets a 429 with the time left as Retry-After.
+//!
+//! Like every admin route it needs an API key with the admin scope. The
+//! feature changes the image and so its PCRs, a profiling build is never the
+//! one registered on-chain.
+
+use crate::{AppState, EnclaveError};
+use axum::extract::{Query, State};
+use axum::http::{HeaderValue, header};
+use axum::response::{IntoResponse, Response};
+use pprof::protos::Message;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::info;
+
+/// Unix time in ms at which the profile being captured is done, 0 when idle.
+static CAPTURE_ENDS_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Frames of the sampler and of the C runtime, left out of the stacks.
+const BLOCKLIST: &[&str] = &["libc", "libgcc", "pthread", "vdso"];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProfileFormat {
+    #[default]
+    Flamegraph,
+    Pprof,
+}
+
+/// Query parameters for GET /admin/profile.
+#[derive(Debug, Default, Deserialize)]
+pub struct ProfileQuery {
+    /// Capture duration, 10 seconds by default.
+    pub seconds: Option<u64>,
+    /// Samples per second, `[profiling] frequency_hz` by default.
+    pub frequency: Option<i32>,
+    #[serde(default)]
+    pub format: ProfileFormat,
+}
+
+/// Releases the capture when dropped, also if the request is.
+struct Capture;
+
+impl Capture {
+    /// Take the capture slot for `duration`. While another profile is
+    /// captured the request is refused with 429, retrying once it is done.
+    fn start(duration: Duration) -> Result<Self, EnclaveError> {
+        let now = now_ms();
+        let ends_ms = now.saturating_add(duration.as_millis() as u64).max(1);
+        match CAPTURE_ENDS_MS.compare_exchange(0, ends_ms, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => Ok(Capture),
+            Err(busy_until_ms) => Err(EnclaveError::RateLimited {
+                retry_after_ms: busy_until_ms.saturating_sub(now).max(1000),
+            }),
+        }
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        CAPTURE_ENDS_MS.store(0, Ordering::Release);
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn profile_error(e: impl std::fmt::Display) -> EnclaveError {
+    EnclaveError::GenericError(format!("Profiling failed: {}", e))
+}
+
+/// Sample the process for `duration` and render the profile.
+fn capture(
+    duration: Duration,
+    frequency: i32,
+    format: ProfileFormat,
+) -> Result<Vec<u8>, EnclaveError> {
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(frequency)
+        .blocklist(BLOCKLIST)
+        .build()
+        .map_err(profile_error)?;
+    std::thread::sleep(duration);
+    let report = guard.report().build().map_err(profile_error)?;
+    let mut body = Vec::new();
+    match format {
+        ProfileFormat::Flamegraph => report.flamegraph(&mut body).map_err(profile_error)?,
+        ProfileFormat::Pprof => report
+            .pprof()
+            .map_err(profile_error)?
+            .encode(&mut body)
+            .map_err(profile_error)?,
+    }
+    Ok(body)
+}
+
+/// Endpoint capturing a CPU profile of the enclave.
+pub async fn get_profile(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ProfileQuery>,
+) -> Result<Response, EnclaveError> {
+    let config = &state.config.profiling;
+    let seconds = query
+        .seconds
+        .unwrap_or(10)
+        .clamp(1, config.max_seconds.max(1));
+    let frequency = query
+        .frequency
+        .unwrap_or(config.frequency_hz)
+        .clamp(1, 1000);
+    let format = query.format;
+    let capture_slot = Capture::start(Duration::from_secs(seconds))?;
+    info!("Capturing a {}s CPU profile at {}Hz", seconds, frequency);
+    // Sampling only waits, on a blocking thread so no runtime thread is held
+    let body = tokio::task::spawn_blocking(move || {
+        let _capture_slot = capture_slot;
+        capture(Duration::from_secs(seconds), frequency, format)
+    })
+    .await
+    .map_err(|e| EnclaveError::Internal(format!("Profiling task failed: {}", e)))??;
+
+    let content_type = match format {
+        ProfileFormat::Flamegraph => "image/svg+xml",
+        ProfileFormat::Pprof => "application/octet-stream",
+    };
+    let mut response = body.into_response();
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    Ok(response)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_capture() {
+        let query: ProfileQuery = serde_json::from_value(serde_json::json!({
+            "seconds": 1,
+            "format": "pprof",
+        }))
+        .unwrap();
+        assert_eq!(query.format, ProfileFormat::Pprof);
+
+        let slot = Capture::start(Duration::from_secs(5)).unwrap();
+        let busy = Capture::start(Duration::from_secs(1)).err().unwrap();
+        assert!(matches!(
+            busy,
+            EnclaveError::RateLimited { retry_after_ms } if retry_after_ms > 3000 && retry_after_ms <= 5000
+        ));
+        drop(slot);
+        let _slot = Capture::start(Duration::from_secs(1)).unwrap();
+
+        // An idle process still gives a valid, if empty, profile
+        let profile = capture(Duration::from_millis(200), 99, ProfileFormat::Pprof).unwrap();
+        assert!(pprof::protos::Profile::decode(profile.as_slice()).is_ok());
+    }
+}
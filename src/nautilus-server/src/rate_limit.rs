@@ -0,0 +1,219 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Token bucket rate limits, configured in the `[rate_limit]` section of
+//! nautilus.toml.
+//!
+//! - Per client: every request is charged to the caller's API key, or to its
+//!   peer address without one. Inside the enclave every connection comes from
+//!   the host (the run.sh forwarder or vsock), so without API keys the client
+//!   limit acts as one global limit.
+//! - Per feed: apps charge a feed when it actually runs, see `check_feed`.
+//!
+//! A bucket holds up to `burst` tokens and refills at `per_second`. A request
+//! over the limit gets 429 with the time until a token is available. The
+//! limits can be replaced at runtime through the admin API.
+
+use crate::config::{RateLimit, RateLimitConfig};
+use crate::{AppState, EnclaveError};
+use crate::{admin, auth};
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::HeaderMap;
+use axum::middleware::Next;
+use axum::response::Response;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+/// Buckets kept before idle ones are dropped.
+const MAX_BUCKETS: usize = 10_000;
+
+lazy_static::lazy_static! {
+    static ref CLIENT_LIMITER: RateLimiter = RateLimiter::default();
+    static ref FEED_LIMITER: RateLimiter = RateLimiter::default();
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    updated_at_ms: u64,
+}
+
+impl TokenBucket {
+    fn full(limit: &RateLimit, now_ms: u64) -> Self {
+        Self {
+            tokens: limit.burst as f64,
+            updated_at_ms: now_ms,
+        }
+    }
+
+    fn refill(&mut self, limit: &RateLimit, now_ms: u64) {
+        let elapsed_s = now_ms.saturating_sub(self.updated_at_ms) as f64 / 1000.0;
+        self.tokens = (self.tokens + elapsed_s * limit.per_second).min(limit.burst as f64);
+        self.updated_at_ms = now_ms;
+    }
+
+    /// Take a token, or return how many ms until one is available.
+    fn take(&mut self, limit: &RateLimit, now_ms: u64) -> Result<(), u64> {
+        self.refill(limit, now_ms);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else if limit.per_second > 0.0 {
+            Err(((1.0 - self.tokens) / limit.per_second * 1000.0).ceil() as u64)
+        } else {
+            Err(u64::MAX)
+        }
+    }
+}
+
+/// Make room for a new bucket in a map holding `max` of them. Buckets that
+/// refilled completely carry no state and go first, then the fullest ones,
+/// whose clients lose the least by starting over with a full bucket.
+fn evict(buckets: &mut HashMap<String, TokenBucket>, limit: &RateLimit, now_ms: u64, max: usize) {
+    buckets.retain(|_, bucket| {
+        bucket.refill(limit, now_ms);
+        bucket.tokens < limit.burst as f64
+    });
+    if buckets.len() < max {
+        return;
+    }
+    let mut fullest_first: Vec<_> = buckets
+        .iter()
+        .map(|(key, bucket)| (bucket.tokens, key.clone()))
+        .collect();
+    fullest_first.sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+    let excess = buckets.len() + 1 - max;
+    for (_, key) in fullest_first.into_iter().take(excess) {
+        buckets.remove(&key);
+    }
+}
+
+/// Token buckets by key.
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl RateLimiter {
+    /// Charge one request to `key`.
+    pub fn check(&self, key: &str, limit: &RateLimit, now_ms: u64) -> Result<(), EnclaveError> {
+        let mut buckets = self.buckets.lock().expect("rate limiter lock poisoned");
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(key) {
+            evict(&mut buckets, limit, now_ms, MAX_BUCKETS);
+        }
+        buckets
+            .entry(key.to_string())
+            .or_insert_with(|| TokenBucket::full(limit, now_ms))
+            .take(limit, now_ms)
+            .map_err(|retry_after_ms| EnclaveError::RateLimited { retry_after_ms })
+    }
+}
+
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Charge a run of `feed_id` to its bucket, if per-feed limits are configured.
+pub fn check_feed(config: &RateLimitConfig, feed_id: &str) -> Result<(), EnclaveError> {
+    match &admin::rate_limits(config).feed {
+        Some(limit) => FEED_LIMITER.check(feed_id, limit, now_ms()),
+        None => Ok(()),
+    }
+}
+
+/// Charge a request to its caller, identified by API key or else by `peer`,
+/// if per-client limits are configured.
+pub fn check_client(
+    state: &AppState,
+    headers: &HeaderMap,
+    peer: Option<SocketAddr>,
+) -> Result<(), EnclaveError> {
+    if let Some(limit) = &admin::rate_limits(&state.config.rate_limit).client {
+        let client = match auth::identify(&state.config.auth, headers) {
+            Some(api_key) => format!("key:{}", api_key.name),
+            None => peer
+                .map(|addr| format!("ip:{}", addr.ip()))
+                .unwrap_or_else(|| "unknown".to_string()),
+        };
+        CLIENT_LIMITER.check(&client, limit, now_ms())?;
+    }
+    Ok(())
+}
+
+/// Middleware charging each request to the caller, see `check_client`.
+pub async fn limit_clients(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, EnclaveError> {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+    check_client(&state, request.headers(), peer)?;
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::default();
+        let limit = RateLimit {
+            burst: 2,
+            per_second: 1.0,
+        };
+        let now = 1744038900000;
+
+        assert!(limiter.check("a", &limit, now).is_ok());
+        assert!(limiter.check("a", &limit, now).is_ok());
+        assert!(matches!(
+            limiter.check("a", &limit, now),
+            Err(EnclaveError::RateLimited {
+                retry_after_ms: 1000
+            })
+        ));
+        // Other keys have their own bucket
+        assert!(limiter.check("b", &limit, now).is_ok());
+        // Refilled at the steady rate, never beyond the burst
+        assert!(matches!(
+            limiter.check("a", &limit, now + 500),
+            Err(EnclaveError::RateLimited {
+                retry_after_ms: 500
+            })
+        ));
+        assert!(limiter.check("a", &limit, now + 1_000).is_ok());
+        assert!(limiter.check("a", &limit, now + 60_000).is_ok());
+        assert!(limiter.check("a", &limit, now + 60_000).is_ok());
+        assert!(limiter.check("a", &limit, now + 60_000).is_err());
+    }
+
+    #[test]
+    fn test_evict() {
+        let limit = RateLimit {
+            burst: 4,
+            per_second: 0.0,
+        };
+        let now = 1744038900000;
+        let mut buckets = HashMap::new();
+        for (key, tokens) in [("a", 0.0), ("b", 3.0), ("c", 1.0), ("d", 2.0)] {
+            buckets.insert(
+                key.to_string(),
+                TokenBucket {
+                    tokens,
+                    updated_at_ms: now,
+                },
+            );
+        }
+        // None refilled, the fullest go until there is room for one more
+        evict(&mut buckets, &limit, now, 3);
+        assert_eq!(buckets.len(), 2);
+        assert!(buckets.contains_key("a") && buckets.contains_key("c"));
+    }
+}
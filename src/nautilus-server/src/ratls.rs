@@ -0,0 +1,133 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Attested TLS (RA-TLS) for the enclave's HTTP and gRPC servers, enabled by
+//! the `ra-tls` feature (e.g. `make ENCLAVE_APP=coeus-oracle,ra-tls`).
+//!
+//! At boot the enclave generates a TLS key that never leaves enclave memory
+//! and a self-signed certificate for it. The certificate carries the
+//! attestation document in the extension [`ATTESTATION_EXTENSION_OID`], with
+//!
+//! - `public_key` set to the enclave's Ed25519 signing key, and
+//! - `user_data` set to SHA-256 of the certificate's DER SubjectPublicKeyInfo.
+//!
+//! A client verifies the server certificate by checking the document against
+//! the expected PCRs and that its user data matches the certificate key, so
+//! the TLS session ends inside the attested enclave rather than at the host.
+
+use crate::EnclaveError;
+use crate::common::attestation_with_user_data;
+use axum_server::tls_rustls::RustlsConfig;
+use fastcrypto::hash::{HashFunction, Sha256};
+use rcgen::{Certificate, CertificateParams, CustomExtension, DistinguishedName, DnType, KeyPair};
+
+/// Project specific OID of the certificate extension holding the attestation
+/// document, an OCTET STRING with the COSE_Sign1 document returned by the NSM.
+pub const ATTESTATION_EXTENSION_OID: &[u64] = &[1, 3, 6, 1, 4, 1, 58270, 1, 1];
+
+/// DER encode `content` as an OCTET STRING.
+fn der_octet_string(content: &[u8]) -> Vec<u8> {
+    let len = content.len();
+    let mut der = vec![0x04];
+    if len < 0x80 {
+        der.push(len as u8);
+    } else {
+        let len_bytes: Vec<u8> = len
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|&b| b == 0)
+            .collect();
+        der.push(0x80 | len_bytes.len() as u8);
+        der.extend(len_bytes);
+    }
+    der.extend_from_slice(content);
+    der
+}
+
+/// Self-signed certificate binding `document` to `key_pair`.
+fn self_signed_certificate(
+    key_pair: &KeyPair,
+    document: &[u8],
+) -> Result<Certificate, EnclaveError> {
+    let mut params = CertificateParams::new(vec!["localhost".to_string()])
+        .map_err(|e| EnclaveError::GenericError(format!("Invalid certificate params: {}", e)))?;
+    let mut name = DistinguishedName::new();
+    name.push(DnType::CommonName, "nautilus-enclave");
+    params.distinguished_name = name;
+    params
+        .custom_extensions
+        .push(CustomExtension::from_oid_content(
+            ATTESTATION_EXTENSION_OID,
+            der_octet_string(document),
+        ));
+    params
+        .self_signed(key_pair)
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to sign certificate: {}", e)))
+}
+
+/// TLS key and attested certificate of the enclave, generated once at boot
+/// and served by both the HTTP and the gRPC server.
+pub struct AttestedIdentity {
+    key_pair: KeyPair,
+    certificate: Certificate,
+}
+
+impl AttestedIdentity {
+    /// Generate the TLS key and attested certificate, committing the
+    /// attestation to `signing_public_key` and the TLS key.
+    pub fn generate(signing_public_key: &[u8]) -> Result<Self, EnclaveError> {
+        let key_pair = KeyPair::generate().map_err(|e| {
+            EnclaveError::GenericError(format!("Failed to generate TLS key: {}", e))
+        })?;
+        let spki_hash = Sha256::digest(key_pair.public_key_der()).digest;
+        let document = attestation_with_user_data(signing_public_key, Some(&spki_hash))?;
+        let certificate = self_signed_certificate(&key_pair, &document)?;
+        Ok(Self {
+            key_pair,
+            certificate,
+        })
+    }
+
+    /// Configuration of the HTTP server.
+    pub async fn rustls_config(&self) -> Result<RustlsConfig, EnclaveError> {
+        // Only ring is compiled in, see Cargo.toml
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        RustlsConfig::from_der(
+            vec![self.certificate.der().to_vec()],
+            self.key_pair.serialize_der(),
+        )
+        .await
+        .map_err(|e| EnclaveError::GenericError(format!("Invalid TLS configuration: {}", e)))
+    }
+
+    /// Identity of the gRPC server.
+    pub fn tonic_identity(&self) -> tonic::transport::Identity {
+        tonic::transport::Identity::from_pem(self.certificate.pem(), self.key_pair.serialize_pem())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_der_octet_string() {
+        assert_eq!(der_octet_string(&[1, 2]), vec![0x04, 0x02, 1, 2]);
+        let long = der_octet_string(&[0u8; 300]);
+        assert_eq!(&long[..4], &[0x04, 0x82, 0x01, 0x2c]);
+        assert_eq!(long.len(), 304);
+    }
+
+    #[test]
+    fn test_self_signed_certificate() {
+        let key_pair = KeyPair::generate().unwrap();
+        let certificate = self_signed_certificate(&key_pair, b"document").unwrap();
+        // The document is embedded verbatim
+        assert!(
+            certificate
+                .der()
+                .windows(8)
+                .any(|window| window == b"document")
+        );
+    }
+}
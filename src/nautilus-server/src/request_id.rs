@@ -0,0 +1,115 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per request ids, to correlate a response (or a bad signed value) with the
+//! enclave logs.
+//!
+//! A well formed `X-Request-Id` sent by the caller is kept, otherwise a random
+//! id is generated. The id is recorded on the tracing span of the request,
+//! added to error responses as `request_id`, available to handlers through
+//! [`current`], and echoed in the `X-Request-Id` response header unless
+//! `server.request_id_header` is off.
+
+use crate::AppState;
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::sync::Arc;
+use tracing::Instrument;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest caller supplied id that is kept.
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Id of the request being handled, None outside of a request (e.g. in
+/// scheduler runs) or on threads spawned by the handler.
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Id for a request carrying `headers`: the caller's if well formed,
+/// otherwise a new one.
+pub fn assign(headers: &HeaderMap) -> String {
+    from_headers(headers).unwrap_or_else(generate)
+}
+
+/// Run `future` as the request `id`, for calls not going through
+/// [`assign_request_id`] such as gRPC.
+pub async fn scope<F: Future>(id: String, future: F) -> F::Output {
+    REQUEST_ID.scope(id, future).await
+}
+
+fn generate() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+/// Caller supplied id, if it is short and only uses [A-Za-z0-9._-].
+fn from_headers(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+        })
+        .map(str::to_string)
+}
+
+/// Middleware assigning the request id and running the request in its span.
+pub async fn assign_request_id(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let id = assign(request.headers());
+    let span = tracing::info_span!(
+        "request",
+        request_id = %id,
+        method = %request.method(),
+        path = %request.uri().path(),
+    );
+    let mut response = REQUEST_ID
+        .scope(id.clone(), next.run(request))
+        .instrument(span)
+        .await;
+    let header = HeaderValue::from_str(&id)
+        .ok()
+        .filter(|_| state.config.server.request_id_header);
+    if let Some(value) = header {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_request_id() {
+        let mut headers = HeaderMap::new();
+        assert!(from_headers(&headers).is_none());
+        headers.insert(REQUEST_ID_HEADER, "abc-123".parse().unwrap());
+        assert_eq!(from_headers(&headers).as_deref(), Some("abc-123"));
+        headers.insert(REQUEST_ID_HEADER, "a b".parse().unwrap());
+        assert!(from_headers(&headers).is_none());
+        headers.insert(REQUEST_ID_HEADER, "a".repeat(65).parse().unwrap());
+        assert!(from_headers(&headers).is_none());
+
+        assert_eq!(generate().len(), 32);
+        assert_ne!(generate(), generate());
+
+        assert!(current().is_none());
+        let id = scope("abc".to_string(), async { current() }).await;
+        assert_eq!(id.as_deref(), Some("abc"));
+    }
+}
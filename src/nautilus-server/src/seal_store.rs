@@ -0,0 +1,470 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Secrets sealed with Seal, so they survive enclave restarts.
+//!
+//! The enclave encrypts a secret to `seal_policy::seal_approve` of the app's
+//! package under an id, and hands the encrypted object to the host to store.
+//! On restart the host gets a fetch key request signed by the key of the
+//! app's Enclave object, fetches the key shares from the key servers, which
+//! encrypt them to this boot's ElGamal key, and passes them back with the
+//! encrypted object.
+//!
+//! Seal encryption is public-key, so anyone can seal a secret under the
+//! app's package and a decrypted secret says nothing of who made it. A secret
+//! that is, or derives, a signing key is only used once its public key is
+//! found registered on-chain (`verify_registered`), which only a key attested
+//! by the enclave can be.
+
+use crate::EnclaveError;
+use crate::chain_reader::ChainReader;
+use crate::common::enclave_rng;
+use fastcrypto::ed25519::Ed25519KeyPair;
+use fastcrypto::encoding::{Base64, Encoding, Hex};
+use fastcrypto::serde_helpers::ToFromByteArray;
+use fastcrypto::traits::{KeyPair, Signer};
+use seal_sdk::types::{
+    ElGamalPublicKey, ElgamalVerificationKey, FetchKeyRequest, FetchKeyResponse, KeyId,
+};
+use seal_sdk::{
+    Certificate, ElGamalSecretKey, EncryptedObject, EncryptionInput, IBEPublicKey, IBEPublicKeys,
+    genkey, seal_decrypt_all_objects, seal_encrypt, signed_message, signed_request,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use sui_sdk_types::{
+    Address, Argument, Command, Identifier, Input, MoveCall, Object, ObjectId as ObjectID,
+    PersonalMessage, ProgrammableTransaction, StructTag,
+};
+
+lazy_static::lazy_static! {
+    /// ElGamal key the key servers encrypt their shares to, new on every boot
+    /// so only this enclave can decrypt them.
+    pub static ref ENCRYPTION_KEYS: (ElGamalSecretKey, ElGamalPublicKey, ElgamalVerificationKey) =
+        genkey(&mut enclave_rng());
+}
+
+/// Custom deserializer for hex strings to Vec<u8>
+pub fn deserialize_hex_vec<'de, D>(deserializer: D) -> Result<Vec<KeyId>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let hex_strings: Vec<String> = Vec::deserialize(deserializer)?;
+    hex_strings
+        .into_iter()
+        .map(|s| Hex::decode(&s).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+/// Custom deserializer for a hex string to a single KeyId
+pub fn deserialize_hex<'de, D>(deserializer: D) -> Result<KeyId, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: String = String::deserialize(deserializer)?;
+    Hex::decode(&s).map_err(serde::de::Error::custom)
+}
+
+/// Custom deserializer for hex string to ObjectID
+pub fn deserialize_object_id<'de, D>(deserializer: D) -> Result<ObjectID, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: String = String::deserialize(deserializer)?;
+    ObjectID::from_str(&s).map_err(serde::de::Error::custom)
+}
+
+/// Custom deserializer for Vec of hex strings to Vec<ObjectID>
+fn deserialize_object_ids<'de, D>(deserializer: D) -> Result<Vec<ObjectID>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let strings: Vec<String> = Vec::deserialize(deserializer)?;
+    strings
+        .into_iter()
+        .map(|s| ObjectID::from_str(&s).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+/// Custom deserializer for Vec of hex strings to Vec<IBEPublicKey>
+fn deserialize_ibe_public_keys<'de, D>(deserializer: D) -> Result<Vec<IBEPublicKey>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pk_hexs: Vec<String> = Vec::deserialize(deserializer)?;
+    pk_hexs
+        .into_iter()
+        .map(|pk_hex| {
+            let pk_bytes = Hex::decode(&pk_hex).map_err(serde::de::Error::custom)?;
+            let pk = IBEPublicKey::from_byte_array(
+                &pk_bytes
+                    .try_into()
+                    .map_err(|_| serde::de::Error::custom("Invalid public key length"))?,
+            )
+            .map_err(serde::de::Error::custom)?;
+            Ok(pk)
+        })
+        .collect()
+}
+
+/// Custom deserializer for hex string to Vec<(ObjectID, FetchKeyResponse)>
+pub fn deserialize_seal_responses<'de, D>(
+    deserializer: D,
+) -> Result<Vec<(ObjectID, FetchKeyResponse)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let hex_string: String = String::deserialize(deserializer)?;
+    let bytes = Hex::decode(&hex_string).map_err(serde::de::Error::custom)?;
+    let responses: Vec<(ObjectID, FetchKeyResponse)> =
+        bcs::from_bytes(&bytes).map_err(serde::de::Error::custom)?;
+    Ok(responses)
+}
+
+/// Custom deserializer for hex string to Vec<EncryptedObject>
+pub fn deserialize_encrypted_objects<'de, D>(
+    deserializer: D,
+) -> Result<Vec<EncryptedObject>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let hex_string: String = String::deserialize(deserializer)?;
+    let bytes = Hex::decode(&hex_string).map_err(serde::de::Error::custom)?;
+    let responses: Vec<EncryptedObject> =
+        bcs::from_bytes(&bytes).map_err(serde::de::Error::custom)?;
+    Ok(responses)
+}
+
+/// Custom deserializer for hex string to a single EncryptedObject
+pub fn deserialize_encrypted_object<'de, D>(deserializer: D) -> Result<EncryptedObject, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let hex_string: String = String::deserialize(deserializer)?;
+    let bytes = Hex::decode(&hex_string).map_err(serde::de::Error::custom)?;
+    bcs::from_bytes(&bytes).map_err(serde::de::Error::custom)
+}
+
+/// Configuration for Seal key servers, and the packages of the policy and of
+/// the Enclave objects keys are registered as.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "SealConfigRaw")]
+pub struct SealConfig {
+    pub key_servers: Vec<ObjectID>,
+    pub public_keys: Vec<IBEPublicKey>,
+    pub package_id: ObjectID,
+    /// Package defining enclave::Enclave, whose objects register keys.
+    pub enclave_package_id: ObjectID,
+    pub server_pk_map: HashMap<ObjectID, IBEPublicKey>,
+    /// Number of key servers needed to decrypt what the enclave seals.
+    pub threshold: u8,
+}
+
+#[derive(Debug, Deserialize)]
+struct SealConfigRaw {
+    #[serde(deserialize_with = "deserialize_object_ids")]
+    key_servers: Vec<ObjectID>,
+    #[serde(deserialize_with = "deserialize_ibe_public_keys")]
+    public_keys: Vec<IBEPublicKey>,
+    #[serde(deserialize_with = "deserialize_object_id")]
+    package_id: ObjectID,
+    #[serde(deserialize_with = "deserialize_object_id")]
+    enclave_package_id: ObjectID,
+    threshold: Option<u8>,
+}
+
+impl TryFrom<SealConfigRaw> for SealConfig {
+    type Error = String;
+
+    fn try_from(raw: SealConfigRaw) -> Result<Self, Self::Error> {
+        if raw.key_servers.len() != raw.public_keys.len() {
+            return Err(format!(
+                "key_servers and public_keys length mismatch: {} vs {}",
+                raw.key_servers.len(),
+                raw.public_keys.len()
+            ));
+        }
+
+        let server_pk_map: HashMap<ObjectID, IBEPublicKey> = raw
+            .key_servers
+            .iter()
+            .zip(raw.public_keys.iter())
+            .map(|(id, pk)| (*id, *pk))
+            .collect();
+
+        let threshold = raw.threshold.unwrap_or(raw.key_servers.len() as u8);
+        if threshold == 0 || threshold as usize > raw.key_servers.len() {
+            return Err(format!(
+                "threshold must be between 1 and {}, got {}",
+                raw.key_servers.len(),
+                threshold
+            ));
+        }
+
+        Ok(SealConfig {
+            key_servers: raw.key_servers,
+            public_keys: raw.public_keys,
+            package_id: raw.package_id,
+            enclave_package_id: raw.enclave_package_id,
+            server_pk_map,
+            threshold,
+        })
+    }
+}
+
+/// BCS layout of enclave::Enclave<T>
+#[derive(Debug, Deserialize)]
+struct EnclaveObject {
+    #[allow(dead_code)]
+    id: Address,
+    pk: Vec<u8>,
+    #[allow(dead_code)]
+    config_version: u64,
+    #[allow(dead_code)]
+    owner: Address,
+}
+
+/// Encrypt `data` under `id` to the key servers of `config`.
+pub fn seal(
+    config: &SealConfig,
+    id: KeyId,
+    data: Vec<u8>,
+) -> Result<EncryptedObject, EnclaveError> {
+    let (encrypted_object, _) = seal_encrypt(
+        config.package_id,
+        id,
+        config.key_servers.clone(),
+        &IBEPublicKeys::BonehFranklinBLS12381(config.public_keys.clone()),
+        config.threshold,
+        EncryptionInput::Aes256Gcm { data, aad: None },
+    )
+    .map_err(|e| EnclaveError::GenericError(format!("Failed to seal: {}", e)))?;
+    Ok(encrypted_object)
+}
+
+/// Decrypt `objects` with the key shares of `seal_responses`.
+pub fn unseal(
+    config: &SealConfig,
+    seal_responses: &[(ObjectID, FetchKeyResponse)],
+    objects: &[EncryptedObject],
+) -> Result<Vec<Vec<u8>>, EnclaveError> {
+    let (enc_secret, _enc_key, _enc_verification_key) = &*ENCRYPTION_KEYS;
+    seal_decrypt_all_objects(enc_secret, seal_responses, objects, &config.server_pk_map)
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to decrypt objects: {}", e)))
+}
+
+/// Hex encoded BCS FetchKeyRequest for `ids`, approved by `seal_approve` of
+/// `config.package_id` for Enclave object `enclave_object_id`, which must
+/// register `kp`.
+pub fn fetch_key_request(
+    kp: &Ed25519KeyPair,
+    config: &SealConfig,
+    enclave_object_id: ObjectID,
+    initial_shared_version: u64,
+    ids: Vec<KeyId>,
+) -> Result<String, EnclaveError> {
+    // Generate the session and create certificate.
+    let session = Ed25519KeyPair::generate(&mut enclave_rng());
+    let session_vk = session.public();
+    let creation_time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| EnclaveError::GenericError(format!("Time error: {}", e)))?
+        .as_millis() as u64;
+    // Minutes the session key is valid for
+    let ttl_min = 10;
+    let message = signed_message(
+        config.package_id.to_string(),
+        session_vk,
+        creation_time,
+        ttl_min,
+    );
+
+    // Convert fastcrypto keypair to sui-crypto for signing.
+    let sui_private_key = {
+        let key_bytes: [u8; 32] = kp.as_ref().try_into().expect("Invalid private key length");
+        sui_crypto::ed25519::Ed25519PrivateKey::new(key_bytes)
+    };
+    let signature = {
+        use sui_crypto::SuiSigner;
+        sui_private_key
+            .sign_personal_message(&PersonalMessage(message.as_bytes().into()))
+            .map_err(|e| {
+                EnclaveError::GenericError(format!("Failed to sign personal message: {}", e))
+            })?
+    };
+
+    // Certificate with the address of the registered key and the session vk.
+    let certificate = Certificate {
+        user: sui_private_key.public_key().to_address(),
+        session_vk: session_vk.clone(),
+        creation_time,
+        ttl_min,
+        signature,
+        mvr_name: None,
+    };
+
+    let ptb = create_ptb(
+        config.package_id,
+        enclave_object_id,
+        initial_shared_version,
+        &ids,
+    )
+    .map_err(|e| EnclaveError::GenericError(format!("Failed to create PTB: {}", e)))?;
+
+    let (_enc_secret, enc_key, enc_verification_key) = &*ENCRYPTION_KEYS;
+    let request_message = signed_request(&ptb, enc_key, enc_verification_key);
+    let request_signature = session.sign(&request_message);
+    let request = FetchKeyRequest {
+        ptb: Base64::encode(bcs::to_bytes(&ptb).expect("should not fail")),
+        enc_key: enc_key.clone(),
+        enc_verification_key: enc_verification_key.clone(),
+        request_signature,
+        certificate,
+    };
+    Ok(Hex::encode(
+        bcs::to_bytes(&request).expect("should not fail"),
+    ))
+}
+
+/// PTB calling seal_approve once per id, with the shared enclave object.
+fn create_ptb(
+    package_id: ObjectID,
+    enclave_object_id: ObjectID,
+    initial_shared_version: u64,
+    ids: &[KeyId],
+) -> Result<ProgrammableTransaction, Box<dyn std::error::Error>> {
+    let mut inputs = vec![];
+    let mut commands = vec![];
+
+    for id in ids {
+        inputs.push(Input::Pure {
+            value: bcs::to_bytes(id)?,
+        });
+    }
+
+    // The shared enclave object is the last input.
+    let enclave_input_idx = inputs.len();
+    inputs.push(Input::Shared {
+        object_id: enclave_object_id,
+        initial_shared_version,
+        mutable: false,
+    });
+
+    for idx in 0..ids.len() {
+        let move_call = MoveCall {
+            package: package_id,
+            module: Identifier::new("seal_policy")?,
+            function: Identifier::new("seal_approve")?,
+            type_arguments: vec![],
+            arguments: vec![
+                Argument::Input(idx as u16),
+                Argument::Input(enclave_input_idx as u16),
+            ],
+        };
+        commands.push(Command::MoveCall(move_call));
+    }
+    Ok(ProgrammableTransaction { inputs, commands })
+}
+
+/// Type of the Enclave objects of an app, `Enclave<witness>` of the enclave
+/// package, `witness` being `<module>::<name>` in the app's package.
+pub fn enclave_type(config: &SealConfig, witness: &str) -> Result<StructTag, EnclaveError> {
+    format!(
+        "{}::enclave::Enclave<{}::{}>",
+        config.enclave_package_id, config.package_id, witness
+    )
+    .parse()
+    .map_err(|e| EnclaveError::GenericError(format!("Invalid enclave type: {}", e)))
+}
+
+/// Check that `object` is an Enclave object of `expected_type` registering
+/// `public_key`.
+pub fn check_registered(
+    object: &Object,
+    expected_type: &StructTag,
+    public_key: &[u8],
+) -> Result<(), EnclaveError> {
+    let move_object = object.as_struct().ok_or_else(|| {
+        EnclaveError::InvalidRequest("Enclave object is not a Move object".to_string())
+    })?;
+    if move_object.object_type() != expected_type {
+        return Err(EnclaveError::InvalidRequest(format!(
+            "Object is a {}, not a {}",
+            move_object.object_type(),
+            expected_type
+        )));
+    }
+    let enclave: EnclaveObject = bcs::from_bytes(move_object.contents()).map_err(|e| {
+        EnclaveError::GenericError(format!("Failed to decode Enclave object: {}", e))
+    })?;
+    if enclave.pk != public_key {
+        return Err(EnclaveError::Forbidden(
+            "Unsealed key is not the one registered on-chain".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Check that `public_key` is registered on-chain as Enclave object `id` of
+/// `expected_type`.
+pub async fn verify_registered(
+    chain: &dyn ChainReader,
+    expected_type: &StructTag,
+    id: Address,
+    public_key: &[u8],
+) -> Result<(), EnclaveError> {
+    let read = chain.get_object(id, None).await.map_err(|status| {
+        EnclaveError::RpcError(format!(
+            "Failed to get Enclave object {}: {}",
+            id,
+            status.message()
+        ))
+    })?;
+    let object: Object = bcs::from_bytes(&read.bcs).map_err(|e| {
+        EnclaveError::GenericError(format!("Failed to deserialize object {}: {}", id, e))
+    })?;
+    check_registered(&object, expected_type, public_key)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sui_sdk_types::{Digest, MoveStruct, ObjectData, Owner};
+
+    fn enclave_object(object_type: StructTag, pk: &[u8]) -> Object {
+        let id = Address::from_hex("0x5").unwrap();
+        let contents = bcs::to_bytes(&(id, pk.to_vec(), 1u64, Address::ZERO)).unwrap();
+        Object::new(
+            ObjectData::Struct(MoveStruct::new(object_type, false, 3, contents).unwrap()),
+            Owner::Shared(3),
+            Digest::ZERO,
+            0,
+        )
+    }
+
+    #[test]
+    fn test_check_registered() {
+        let expected_type: StructTag = "0x2a::enclave::Enclave<0x3b::config::CONFIG>"
+            .parse()
+            .unwrap();
+        let kp = Ed25519KeyPair::generate(&mut enclave_rng());
+        let pk = kp.public().as_bytes();
+        let registered = enclave_object(expected_type.clone(), pk);
+        assert!(check_registered(&registered, &expected_type, pk).is_ok());
+
+        // A key the host sealed itself is not the registered one
+        let other = Ed25519KeyPair::generate(&mut enclave_rng());
+        assert!(matches!(
+            check_registered(&registered, &expected_type, other.public().as_bytes()),
+            Err(EnclaveError::Forbidden(_))
+        ));
+
+        // An Enclave object of a package the host published
+        let forged: StructTag = "0x7::enclave::Enclave<0x3b::config::CONFIG>"
+            .parse()
+            .unwrap();
+        assert!(check_registered(&enclave_object(forged, pk), &expected_type, pk).is_err());
+    }
+}
@@ -0,0 +1,402 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Sui fullnodes RPC calls go to, see `[sui]` in nautilus.toml.
+//!
+//! Calls go to the first healthy fullnode, in the configured order. A call
+//! failing with a transient error (the fullnode is down, overloaded or too
+//! slow) marks its fullnode unhealthy for `retry.unhealthy_ms`, and is retried
+//! with exponential backoff on the next healthy one. A single fullnode outage
+//! thus only delays feed updates instead of failing them. Other errors, such
+//! as an object that does not exist, are returned at once.
+//!
+//! Each fullnode has a single client, created at startup. Calls get a clone of
+//! it, which shares its HTTP/2 channel rather than opening a connection, so
+//! concurrent calls are multiplexed over one connection per fullnode. That
+//! connection is opened by the chain id check at startup (see network.rs), and
+//! fullnodes that have not answered for `keepalive.idle_ms` are pinged so it is
+//! still up when the next call comes.
+
+use crate::config::{SuiConfig, SuiKeepaliveConfig, SuiRetryConfig};
+use crate::metrics;
+use crate::{AppState, EnclaveError};
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+use sui_rpc::client::Client;
+use sui_rpc::proto::sui::rpc::v2::GetServiceInfoRequest;
+use tokio::time::MissedTickBehavior;
+use tonic::{Code, Status};
+use tracing::{debug, info, warn};
+
+struct Endpoint {
+    url: String,
+    client: Client,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Health {
+    /// Transient errors since the last answer.
+    consecutive_failures: u32,
+    /// Skipped until then, unless no other fullnode is healthy.
+    unhealthy_until: Option<Instant>,
+    /// Never used, see [`SuiPool::disable`].
+    disabled: bool,
+    /// Latest answer, error or not.
+    last_answer: Option<Instant>,
+}
+
+impl Health {
+    fn is_healthy(&self, now: Instant) -> bool {
+        !self.disabled && self.unhealthy_until.is_none_or(|until| until <= now)
+    }
+}
+
+/// Clients of the configured fullnodes, with their health.
+pub struct SuiPool {
+    endpoints: Vec<Endpoint>,
+    health: Mutex<Vec<Health>>,
+    retry: SuiRetryConfig,
+}
+
+/// Whether a call that failed with `code` may succeed on a retry.
+pub fn is_transient(code: Code) -> bool {
+    matches!(
+        code,
+        Code::Unavailable
+            | Code::DeadlineExceeded
+            | Code::ResourceExhausted
+            | Code::Aborted
+            | Code::Unknown
+    )
+}
+
+/// Delay before retry number `retries + 1`.
+fn backoff(retry: &SuiRetryConfig, retries: u32) -> Duration {
+    let delay = retry
+        .initial_backoff_ms
+        .saturating_mul(1 << retries.min(32))
+        .min(retry.max_backoff_ms);
+    Duration::from_millis(delay)
+}
+
+/// Fullnode to call: the first healthy one, or the one back the soonest when
+/// none is. None when all are disabled.
+fn pick(health: &[Health], now: Instant) -> Option<usize> {
+    let enabled = || health.iter().enumerate().filter(|(_, h)| !h.disabled);
+    enabled()
+        .find(|(_, h)| h.is_healthy(now))
+        .or_else(|| enabled().min_by_key(|(_, h)| h.unhealthy_until))
+        .map(|(index, _)| index)
+}
+
+/// Enabled fullnodes that have not answered for `idle`.
+fn idle(health: &[Health], now: Instant, idle: Duration) -> Vec<usize> {
+    health
+        .iter()
+        .enumerate()
+        .filter(|(_, h)| !h.disabled)
+        .filter(|(_, h)| {
+            h.last_answer
+                .is_none_or(|answered| now.saturating_duration_since(answered) >= idle)
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+fn set_last_answer(url: &str) {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    metrics::SUI_FULLNODE_LAST_ANSWER
+        .with_label_values(&[url])
+        .set(now as i64);
+}
+
+impl SuiPool {
+    pub fn new(config: &SuiConfig) -> Result<Self, EnclaveError> {
+        let endpoints = config
+            .fullnode_urls()?
+            .into_iter()
+            .map(|url| {
+                let client = Client::new(url).map_err(|e| {
+                    EnclaveError::GenericError(format!("Invalid fullnode URL {}: {}", url, e))
+                })?;
+                metrics::SUI_FULLNODE_HEALTHY
+                    .with_label_values(&[url])
+                    .set(1);
+                Ok(Endpoint {
+                    url: url.to_string(),
+                    client,
+                })
+            })
+            .collect::<Result<Vec<_>, EnclaveError>>()?;
+        Ok(Self {
+            health: Mutex::new(vec![Health::default(); endpoints.len()]),
+            endpoints,
+            retry: config.retry.clone(),
+        })
+    }
+
+    fn health(&self) -> MutexGuard<'_, Vec<Health>> {
+        self.health.lock().expect("fullnode health lock poisoned")
+    }
+
+    /// Fullnode URLs with their client, in order of preference.
+    pub fn endpoints(&self) -> impl Iterator<Item = (&str, &Client)> {
+        self.endpoints
+            .iter()
+            .map(|endpoint| (endpoint.url.as_str(), &endpoint.client))
+    }
+
+    /// URLs of the fullnodes in use, in order of preference.
+    pub fn enabled_urls(&self) -> Vec<String> {
+        let health = self.health();
+        self.endpoints
+            .iter()
+            .zip(health.iter())
+            .filter(|(_, h)| !h.disabled)
+            .map(|(endpoint, _)| endpoint.url.clone())
+            .collect()
+    }
+
+    /// Stop calling a fullnode until restart, e.g. one whose chain id could
+    /// not be checked at startup.
+    pub fn disable(&self, url: &str) {
+        let mut health = self.health();
+        for (endpoint, h) in self.endpoints.iter().zip(health.iter_mut()) {
+            if endpoint.url == url {
+                h.disabled = true;
+                metrics::SUI_FULLNODE_HEALTHY
+                    .with_label_values(&[url])
+                    .set(0);
+            }
+        }
+    }
+
+    /// Note an answer of a fullnode called outside the pool, e.g. at startup.
+    pub fn answered(&self, url: &str) {
+        if let Some(index) = self.endpoints.iter().position(|e| e.url == url) {
+            self.record(index, true);
+        }
+    }
+
+    fn record(&self, index: usize, answered: bool) {
+        let url = &self.endpoints[index].url;
+        let mut health = self.health();
+        let h = &mut health[index];
+        if answered {
+            h.last_answer = Some(Instant::now());
+            set_last_answer(url);
+            if h.consecutive_failures > 0 {
+                info!(
+                    "Fullnode {} answers again after {} failures",
+                    url, h.consecutive_failures
+                );
+            }
+            h.consecutive_failures = 0;
+            h.unhealthy_until = None;
+        } else {
+            h.consecutive_failures += 1;
+            h.unhealthy_until =
+                Some(Instant::now() + Duration::from_millis(self.retry.unhealthy_ms));
+        }
+        metrics::SUI_FULLNODE_HEALTHY
+            .with_label_values(&[url])
+            .set(i64::from(answered));
+    }
+
+    /// Run `call` with the client of the preferred fullnode, retrying
+    /// transient errors on the next healthy one. `method` labels the latency
+    /// and retry metrics.
+    pub async fn call<T, F, Fut>(&self, method: &str, mut call: F) -> Result<T, Status>
+    where
+        F: FnMut(Client) -> Fut,
+        Fut: Future<Output = Result<T, Status>>,
+    {
+        let mut retries = 0;
+        loop {
+            let Some(index) = pick(&self.health(), Instant::now()) else {
+                return Err(Status::unavailable("No Sui fullnode is enabled"));
+            };
+            let endpoint = &self.endpoints[index];
+            let started = Instant::now();
+            let outcome = call(endpoint.client.clone()).await;
+            metrics::SUI_RPC_SECONDS
+                .with_label_values(&[method])
+                .observe(started.elapsed().as_secs_f64());
+            let status = match outcome {
+                Ok(value) => {
+                    self.record(index, true);
+                    return Ok(value);
+                }
+                Err(status) if !is_transient(status.code()) => {
+                    self.record(index, true);
+                    return Err(status);
+                }
+                Err(status) => status,
+            };
+            self.record(index, false);
+            if retries + 1 >= self.retry.max_attempts {
+                return Err(status);
+            }
+            warn!(
+                "Sui {} failed on {}, retrying: {}",
+                method, endpoint.url, status
+            );
+            metrics::SUI_RPC_RETRIES.with_label_values(&[method]).inc();
+            tokio::time::sleep(backoff(&self.retry, retries)).await;
+            retries += 1;
+        }
+    }
+
+    /// Ping a fullnode directly, bypassing failover, to keep its connection
+    /// up. An answer also brings an unhealthy fullnode back into use.
+    async fn ping(&self, index: usize) {
+        let endpoint = &self.endpoints[index];
+        let mut client = endpoint.client.clone();
+        let started = Instant::now();
+        let outcome = client
+            .ledger_client()
+            .get_service_info(GetServiceInfoRequest::default())
+            .await;
+        metrics::SUI_RPC_SECONDS
+            .with_label_values(&["ping"])
+            .observe(started.elapsed().as_secs_f64());
+        let answered = match &outcome {
+            Ok(_) => true,
+            Err(status) => !is_transient(status.code()),
+        };
+        if let Err(status) = &outcome {
+            debug!("Ping of fullnode {} failed: {}", endpoint.url, status);
+        }
+        metrics::SUI_FULLNODE_PINGS
+            .with_label_values(&[&endpoint.url, if answered { "ok" } else { "error" }])
+            .inc();
+        self.record(index, answered);
+    }
+
+    /// Ping the fullnodes idle for `config.idle_ms`.
+    async fn ping_idle(&self, config: &SuiKeepaliveConfig) {
+        let indexes = idle(
+            &self.health(),
+            Instant::now(),
+            Duration::from_millis(config.idle_ms),
+        );
+        for index in indexes {
+            self.ping(index).await;
+        }
+    }
+}
+
+/// Start pinging idle fullnodes. Does nothing when `sui.keepalive.idle_ms` is
+/// 0 in nautilus.toml.
+pub fn spawn_keepalive(state: Arc<AppState>) {
+    let config = state.config.sui.keepalive.clone();
+    if config.idle_ms == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        // Twice per idle period, so a fullnode is never idle much longer
+        let mut ticker = tokio::time::interval(Duration::from_millis(config.idle_ms.div_ceil(2)));
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            state.sui.ping_idle(&config).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_backoff_and_pick() {
+        let retry = SuiRetryConfig::default();
+        assert_eq!(backoff(&retry, 0), Duration::from_millis(100));
+        assert_eq!(backoff(&retry, 2), Duration::from_millis(400));
+        assert_eq!(backoff(&retry, 40), Duration::from_millis(2000));
+
+        let now = Instant::now();
+        let mut health = vec![Health::default(); 3];
+        assert_eq!(pick(&health, now), Some(0));
+        health[0].unhealthy_until = Some(now + Duration::from_secs(30));
+        assert_eq!(pick(&health, now), Some(1));
+        // None healthy: the one back the soonest
+        health[1].unhealthy_until = Some(now + Duration::from_secs(10));
+        health[2].disabled = true;
+        assert_eq!(pick(&health, now), Some(1));
+        assert_eq!(pick(&health, now + Duration::from_secs(30)), Some(0));
+        health[0].disabled = true;
+        health[1].disabled = true;
+        assert_eq!(pick(&health, now), None);
+
+        assert!(is_transient(Code::Unavailable));
+        assert!(!is_transient(Code::NotFound));
+
+        // Idle: never answered, or not for the idle period, and enabled
+        let idle_period = Duration::from_secs(20);
+        let mut health = vec![Health::default(); 3];
+        health[0].last_answer = Some(now);
+        health[1].last_answer = Some(now - Duration::from_secs(30));
+        assert_eq!(idle(&health, now, idle_period), vec![1, 2]);
+        health[2].disabled = true;
+        assert_eq!(idle(&health, now, idle_period), vec![1]);
+        assert_eq!(idle(&health, now + idle_period, idle_period), vec![0, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_failover() {
+        let config = SuiConfig {
+            fallback_urls: vec!["http://127.0.0.1:9001".to_string()],
+            retry: SuiRetryConfig {
+                initial_backoff_ms: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let pool = SuiPool::new(&config).unwrap();
+        assert_eq!(pool.enabled_urls().len(), 2);
+
+        // A transient error fails over to the fallback
+        let calls = &AtomicU32::new(0);
+        let outcome = pool
+            .call("test", move |_| async move {
+                match calls.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(Status::unavailable("down")),
+                    n => Ok(n),
+                }
+            })
+            .await;
+        assert_eq!(outcome.unwrap(), 1);
+        assert_eq!(pick(&pool.health(), Instant::now()), Some(1));
+
+        // Other errors are not retried
+        calls.store(0, Ordering::SeqCst);
+        let outcome: Result<(), _> = pool
+            .call("test", move |_| async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(Status::not_found("no object"))
+            })
+            .await;
+        assert_eq!(outcome.unwrap_err().code(), Code::NotFound);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Transient errors are retried up to max_attempts
+        calls.store(0, Ordering::SeqCst);
+        let outcome: Result<(), _> = pool
+            .call("test", move |_| async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(Status::unavailable("down"))
+            })
+            .await;
+        assert!(outcome.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+        pool.disable("http://127.0.0.1:9001");
+        assert_eq!(pool.enabled_urls().len(), 1);
+    }
+}
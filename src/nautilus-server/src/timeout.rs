@@ -0,0 +1,63 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per route request timeouts, see `[timeouts]` in nautilus.toml. A request
+//! still running when its route's timeout elapses is dropped, along with
+//! the upstream calls it awaits, and answered with a 504 `request_timeout`.
+//! Script threads already running finish in the background and release
+//! their slot.
+
+use crate::{AppState, EnclaveError};
+use axum::extract::{MatchedPath, Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::sync::Arc;
+
+/// Route path used to look up the timeout: the matched path, within its app
+/// for routes under /apps/{name}.
+fn route_path(matched: &str) -> &str {
+    matched
+        .strip_prefix("/apps/")
+        .and_then(|rest| rest.find('/').map(|end| &rest[end..]))
+        .unwrap_or(matched)
+}
+
+/// Middleware applying the timeout of the matched route.
+pub async fn enforce_timeout(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| route_path(path.as_str()).to_string())
+        .unwrap_or_default();
+    let timeout = state.config.timeouts.timeout(&route);
+    match tokio::time::timeout(timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => EnclaveError::RequestTimeout {
+            timeout_ms: timeout.as_millis() as u64,
+        }
+        .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_route_path() {
+        assert_eq!(route_path("/process_data"), "/process_data");
+        assert_eq!(
+            route_path("/apps/coeus-oracle/process_data"),
+            "/process_data"
+        );
+        assert_eq!(
+            route_path("/apps/coeus-oracle/feeds/:feed_id/status"),
+            "/feeds/:feed_id/status"
+        );
+        assert_eq!(route_path("/apps"), "/apps");
+    }
+}
@@ -0,0 +1,32 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Serving the router on a vsock port, selected by `listener = "vsock"` in
+//! nautilus.toml. The host reaches the enclave directly on (enclave CID,
+//! port), without the socat TCP forwarder in run.sh.
+
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::service::TowerToHyperService;
+use tokio_vsock::{VsockAddr, VsockListener};
+use tracing::{info, warn};
+
+/// Accept connections on `cid:port` and serve each with `app` until the
+/// listener fails.
+pub async fn serve(cid: u32, port: u32, app: Router) -> std::io::Result<()> {
+    let mut listener = VsockListener::bind(VsockAddr::new(cid, port))?;
+    info!("listening on vsock {}:{}", cid, port);
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let service = TowerToHyperService::new(app.clone());
+        tokio::spawn(async move {
+            if let Err(e) = Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                warn!("vsock connection from {:?} failed: {}", peer, e);
+            }
+        });
+    }
+}